@@ -0,0 +1,92 @@
+//! Benchmarks of the derivation stages on batcher transactions recorded from OP Sepolia
+
+use std::sync::{mpsc, Arc};
+
+use bytes::Bytes;
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+
+use magi::{
+    config::{ChainConfig, Config},
+    derive::stages::{
+        batcher_transactions::{
+            BatcherTransaction, BatcherTransactionMessage, BatcherTransactions,
+        },
+        batches::decode_batches,
+        channels::{Channel, Channels},
+    },
+};
+
+/// A batcher transaction holding a single batch channel
+const SINGLE_BATCH_TX: &str = include_str!("data/single_batch_tx.hex");
+/// A batcher transaction holding a span batch channel
+const SPAN_BATCH_TX: &str = include_str!("data/span_batch_tx.hex");
+
+fn recorded_txs() -> Vec<(&'static str, Bytes)> {
+    [("single", SINGLE_BATCH_TX), ("span", SPAN_BATCH_TX)]
+        .into_iter()
+        .map(|(name, data)| (name, Bytes::from(hex::decode(data.trim()).unwrap())))
+        .collect()
+}
+
+fn config() -> Arc<Config> {
+    Arc::new(Config {
+        chain: ChainConfig::optimism_sepolia(),
+        ..Default::default()
+    })
+}
+
+/// Pushes a batcher transaction through the batcher transactions and channels stages
+fn read_channel(data: &Bytes, config: &Arc<Config>) -> Channel {
+    let (tx, rx) = mpsc::channel();
+    let mut channels = Channels::new(BatcherTransactions::new(rx), config.clone());
+
+    tx.send(BatcherTransactionMessage {
+        txs: vec![data.clone()],
+        l1_origin: 1,
+        l1_timestamp: 0,
+    })
+    .unwrap();
+
+    channels.next().unwrap()
+}
+
+fn bench_frames(c: &mut Criterion) {
+    let mut group = c.benchmark_group("batcher_transactions");
+    for (name, data) in recorded_txs() {
+        group.throughput(Throughput::Bytes(data.len() as u64));
+        group.bench_function(name, |b| {
+            b.iter(|| BatcherTransaction::new(data.clone(), 1, 0).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn bench_channels(c: &mut Criterion) {
+    let config = config();
+    let mut group = c.benchmark_group("channels");
+    for (name, data) in recorded_txs() {
+        group.throughput(Throughput::Bytes(data.len() as u64));
+        group.bench_function(name, |b| b.iter(|| read_channel(&data, &config)));
+    }
+    group.finish();
+}
+
+fn bench_batches(c: &mut Criterion) {
+    let config = config();
+    let mut group = c.benchmark_group("batches");
+    for (name, data) in recorded_txs() {
+        let channel = read_channel(&data, &config);
+        group.throughput(Throughput::Bytes(channel.data.len() as u64));
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                || channel.clone(),
+                |channel| decode_batches(&channel, config.chain.l2_chain_id).unwrap(),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_frames, bench_channels, bench_batches);
+criterion_main!(benches);
@@ -1,30 +1,123 @@
 use std::path::PathBuf;
 use std::{env::current_dir, process};
 
-use clap::Parser;
+use alloy_primitives::Address;
+use clap::{Parser, Subcommand};
 use dirs::home_dir;
 use eyre::Result;
+use futures::future::join_all;
 
 use magi::{
-    config::{ChainConfig, CliConfig, Config, SyncMode},
-    runner::Runner,
+    config::{
+        check::check_config, devnet::DevnetGenesis, ChainConfig, CliConfig, Config, SyncMode,
+        WatchdogAction,
+    },
+    driver::{DriverError, FAILURE_EXIT_CODE},
+    runner::{shutdown_signal, Runner},
+    store::{
+        dump::{dump_blocks, DumpFormat},
+        DataDir, Snapshot,
+    },
     telemetry::{self, metrics},
 };
 use serde::Serialize;
 
+/// Counts allocations for `--profile-derivation`
+#[cfg(feature = "count-allocations")]
+#[global_allocator]
+static ALLOCATOR: magi::telemetry::allocations::CountingAllocator =
+    magi::telemetry::allocations::CountingAllocator;
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    let cli = Cli::parse();
+    let mut cli = Cli::parse();
+    let command = cli.command.take();
     let sync_mode = cli.sync_mode;
     let verbose = cli.verbose;
     let logs_dir = cli.logs_dir.clone();
     let logs_rotation = cli.logs_rotation.clone();
     let checkpoint_hash = cli.checkpoint_hash.clone();
-    let config = cli.to_config();
+    let rollups = cli.rollups.clone();
+    let devnet_genesis = cli
+        .devnet_deploy_config
+        .clone()
+        .zip(cli.devnet_addresses.clone());
+    let mut config = cli.to_config();
 
     let _guards = telemetry::init(verbose, logs_dir, logs_rotation);
+
+    if let Some((deploy_config, addresses)) = devnet_genesis {
+        let genesis = DevnetGenesis::read(&deploy_config, &addresses)?;
+        config.chain = genesis
+            .chain_config(&config.l1_rpc_url, &config.l2_rpc_url)
+            .await?;
+        tracing::info!(
+            target: "magi",
+            "derived devnet chain config starting at L1 block {}",
+            config.chain.l1_start_epoch.number
+        );
+    }
+
+    if let Some(Command::Snapshot { action }) = command {
+        return run_snapshot_command(action, &config).await;
+    }
+
+    if let Some(Command::CheckConfig) = command {
+        return run_check_config_command(&config).await;
+    }
+
+    if let Some(Command::Db { action }) = command {
+        return run_db_command(action, &config);
+    }
+
+    if let Some(Command::DumpBlocks {
+        from,
+        to,
+        out,
+        format,
+    }) = command
+    {
+        let count = dump_blocks(&config, from, to, &out, format).await?;
+        tracing::info!(target: "magi", "dumped {} blocks to {:?}", count, out);
+        return Ok(());
+    }
+
     metrics::init()?;
 
+    if let Some(rollups) = rollups {
+        let configs = config.for_rollups(&rollups)?;
+        let shutdown_recv = shutdown_signal();
+
+        let runners = configs.into_iter().map(|config| {
+            tracing::info!(
+                target: "magi",
+                "Starting Magi. sync mode={}, network={}",
+                sync_mode, config.chain.network
+            );
+
+            let network = config.chain.network.clone();
+            let runner = Runner::new(config, shutdown_recv.clone())
+                .with_sync_mode(sync_mode)
+                .with_checkpoint_hash(checkpoint_hash.clone());
+
+            // a failed rollup stops alone, while the others keep running
+            async move {
+                let result = runner.run().await;
+                if let Err(err) = &result {
+                    tracing::error!(target: "magi", "rollup {} stopped: {}", network, err);
+                }
+                result
+            }
+        });
+
+        let results = join_all(runners).await;
+        if let Some(err) = results.into_iter().find_map(Result::err) {
+            process::exit(exit_code(&err));
+        }
+
+        return Ok(());
+    }
+
     tracing::info!(
         target: "magi",
         "Starting Magi. sync mode={}, network={}",
@@ -37,14 +130,143 @@ async fn main() -> Result<()> {
 
     if let Err(err) = runner.run().await {
         tracing::error!(target: "magi", "{}", err);
+        process::exit(exit_code(&err));
+    }
+
+    Ok(())
+}
+
+/// Returns the exit code of the node stopped by the given error
+fn exit_code(err: &eyre::Report) -> i32 {
+    err.downcast_ref::<DriverError>()
+        .map(DriverError::exit_code)
+        .unwrap_or(FAILURE_EXIT_CODE)
+}
+
+async fn run_snapshot_command(action: SnapshotCommand, config: &Config) -> Result<()> {
+    match action {
+        SnapshotCommand::Export { path } => {
+            let snapshot = Snapshot::export(config).await?;
+            snapshot.write(&path)?;
+            tracing::info!(
+                target: "magi",
+                "exported snapshot at finalized block {} to {:?}",
+                snapshot.finalized_head.number, path
+            );
+        }
+        SnapshotCommand::Import { path } => {
+            let snapshot = Snapshot::read(&path, config)?;
+            snapshot.import(config)?;
+            tracing::info!(
+                target: "magi",
+                "imported snapshot at finalized block {}",
+                snapshot.finalized_head.number
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn run_db_command(action: DbCommand, config: &Config) -> Result<()> {
+    let data_dir = DataDir::open(config)?.ok_or(eyre::eyre!("a data directory is required"))?;
+
+    let databases = match action {
+        DbCommand::Info => data_dir.databases()?,
+        DbCommand::Compact => data_dir.compact()?,
+    };
+
+    let schema = data_dir.schema()?;
+    println!("data directory: {:?}", data_dir.path());
+    if let Some(schema) = schema {
+        println!(
+            "schema version: {} (written by {})",
+            schema.version, schema.written_by
+        );
+    }
+
+    for db in databases {
+        println!(
+            "{:?}: {} keys, {} bytes, {} reclaimable",
+            db.path, db.keys, db.size, db.garbage
+        );
+    }
+
+    Ok(())
+}
+
+async fn run_check_config_command(config: &Config) -> Result<()> {
+    let checks = check_config(config).await;
+
+    for check in &checks {
+        match &check.result {
+            Ok(summary) => println!("ok    {}: {}", check.name, summary),
+            Err(err) => println!("FAIL  {}: {}", check.name, err),
+        }
+    }
+
+    let failed = checks.iter().filter(|check| check.result.is_err()).count();
+    if failed > 0 {
+        println!("{} of {} checks failed", failed, checks.len());
         process::exit(1);
     }
 
+    println!("all {} checks passed", checks.len());
     Ok(())
 }
 
+#[derive(Subcommand)]
+pub enum Command {
+    /// Export or import derivation state snapshots
+    Snapshot {
+        #[clap(subcommand)]
+        action: SnapshotCommand,
+    },
+    /// Validate the configuration against the configured endpoints and exit
+    CheckConfig,
+    /// Inspect or compact the databases in the data directory. Magi must not be running.
+    Db {
+        #[clap(subcommand)]
+        action: DbCommand,
+    },
+    /// Write a range of L2 blocks from the L2 RPC as execution payload files
+    DumpBlocks {
+        /// The first block to write
+        #[clap(long)]
+        from: u64,
+        /// The last block to write, defaults to the unsafe head
+        #[clap(long)]
+        to: Option<u64>,
+        /// The directory to write the files to
+        #[clap(long)]
+        out: PathBuf,
+        /// The file format, json or ssz
+        #[clap(long, default_value = "json")]
+        format: DumpFormat,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum DbCommand {
+    /// Print the schema version and the size of each database
+    Info,
+    /// Rewrite each database with only its live records
+    Compact,
+}
+
+#[derive(Subcommand)]
+pub enum SnapshotCommand {
+    /// Export a snapshot of the synced node to a file
+    Export { path: PathBuf },
+    /// Import a snapshot file into the data directory. Magi must not be running.
+    Import { path: PathBuf },
+}
+
 #[derive(Parser, Serialize)]
 pub struct Cli {
+    #[clap(subcommand)]
+    #[serde(skip)]
+    command: Option<Command>,
     #[clap(short, long, default_value = "optimism")]
     network: String,
     #[clap(long)]
@@ -57,10 +279,14 @@ pub struct Cli {
     sync_mode: SyncMode,
     #[clap(long)]
     l2_engine_url: Option<String>,
+    /// Comma separated standby engine urls to fail over to
+    #[clap(long, value_delimiter = ',')]
+    l2_engine_standby_urls: Option<Vec<String>>,
     #[clap(long)]
     jwt_secret: Option<String>,
-    /// Path to a JWT secret to use for authenticated RPC endpoints
-    #[clap(long)]
+    /// Path to a JWT secret to use for authenticated RPC endpoints.
+    /// The file is re-read on SIGHUP or when the engine rejects the secret.
+    #[clap(long, alias = "jwt-secret-file")]
     jwt_file: Option<PathBuf>,
     #[clap(short = 'v', long)]
     verbose: bool,
@@ -78,13 +304,162 @@ pub struct Cli {
     checkpoint_sync_url: Option<String>,
     #[clap(long)]
     devnet: bool,
+    /// Path to the L1 deploy config of a local devnet, such as `devnetL1.json`, to derive
+    /// the chain config from instead of the network
+    #[clap(long, requires_all = &["devnet", "devnet-addresses"])]
+    devnet_deploy_config: Option<PathBuf>,
+    /// Path to the L1 contract addresses of a local devnet, such as `addresses.json`
+    #[clap(long, requires = "devnet-deploy-config")]
+    devnet_addresses: Option<PathBuf>,
+    /// Supervisor RPC used to validate interop executing messages
+    #[clap(long)]
+    supervisor_rpc_url: Option<String>,
+    /// Port to listen on for p2p networking
+    #[clap(long = "p2p.port")]
+    p2p_port: Option<u16>,
+    /// Request missing unsafe blocks from peers with the req/resp protocol
+    #[clap(long = "p2p.sync")]
+    p2p_sync: bool,
+    /// Path to a TOML file listing multiple rollups to run in this process
+    #[clap(long)]
+    rollups: Option<PathBuf>,
+    /// Maximum number of derived blocks per coalesced forkchoice update
+    #[clap(long)]
+    forkchoice_batch_size: Option<u64>,
+    /// Maximum delay in milliseconds before a coalesced forkchoice update is sent
+    #[clap(long)]
+    forkchoice_batch_interval_ms: Option<u64>,
+    /// Compute units per second that may be spent on L1 RPC requests
+    #[clap(long)]
+    l1_rpc_budget: Option<u32>,
+    /// Directory to persist node state in
+    #[clap(long)]
+    data_dir: Option<String>,
+    /// Number of L1 epochs before the safe epoch kept in memory
+    #[clap(long)]
+    l1_epoch_retention: Option<u64>,
+    /// Number of L2 block refs before the safe head kept in memory
+    #[clap(long)]
+    l2_block_retention: Option<u64>,
+    /// Maximum number of inbound connections on the p2p network
+    #[clap(long = "p2p.max-inbound-peers")]
+    p2p_max_inbound_peers: Option<usize>,
+    /// Maximum number of outbound connections on the p2p network
+    #[clap(long = "p2p.max-outbound-peers")]
+    p2p_max_outbound_peers: Option<usize>,
+    /// Comma separated multiaddrs of p2p peers that are always dialed and never pruned, ending with /p2p/<peer id>
+    #[clap(long = "p2p.static", value_delimiter = ',')]
+    p2p_static_peers: Option<Vec<String>>,
+    /// Comma separated node records replacing the default p2p bootnodes
+    #[clap(long = "p2p.bootnodes", value_delimiter = ',')]
+    p2p_bootnodes: Option<Vec<String>>,
+    /// Run as the sequencer, building and gossiping unsafe blocks
+    #[clap(long)]
+    sequencer: bool,
+    /// Periodically report the derivation throughput, time per stage and allocations
+    #[clap(long)]
+    profile_derivation: bool,
+    /// Hex encoded private key signing the unsafe blocks gossiped by the sequencer
+    #[clap(long = "p2p.sequencer.key")]
+    p2p_sequencer_key: Option<String>,
+    /// Path to an encrypted JSON keystore holding the sequencer key
+    #[clap(long = "p2p.sequencer.keystore")]
+    p2p_sequencer_keystore: Option<String>,
+    /// Path to a file holding the password of the sequencer keystore
+    #[clap(long = "p2p.sequencer.keystore-password-file")]
+    p2p_sequencer_keystore_password_file: Option<String>,
+    /// Sign endpoint of a remote signer holding the sequencer key
+    #[clap(long = "p2p.sequencer.signer-url")]
+    p2p_sequencer_signer_url: Option<String>,
+    /// Sign endpoint of a backup remote signer holding the same sequencer key
+    #[clap(
+        long = "p2p.sequencer.signer-backup-url",
+        requires = "p2p-sequencer-signer-url"
+    )]
+    p2p_sequencer_signer_backup_url: Option<String>,
+    /// Address of the sequencer key held by the remote signer
+    #[clap(long = "p2p.sequencer.address")]
+    p2p_sequencer_address: Option<Address>,
+    /// L2 RPC trusted to serve the unsafe head of the sequencer, followed ahead of derivation
+    #[clap(long = "unsafe-follow.url")]
+    unsafe_follow_url: Option<String>,
+    /// Pause following the trusted RPC while the safe head is more than this many blocks behind
+    #[clap(long = "unsafe-follow.max-lag")]
+    unsafe_follow_max_lag: Option<u64>,
+    #[clap(long = "readiness.max-head-lag")]
+    readiness_max_head_lag: Option<u64>,
+    #[clap(long = "readiness.max-stall")]
+    readiness_max_stall: Option<u64>,
+    /// Comma separated RPC namespaces to serve, out of optimism, opp2p, admin, debug and eth
+    #[clap(long = "rpc.api", value_delimiter = ',')]
+    rpc_api: Option<Vec<String>>,
+    /// Bearer token required to call the RPC server
+    #[clap(long = "rpc.auth-token")]
+    rpc_auth_token: Option<String>,
+    /// Hex encoded secret of the JWTs required to call the RPC server
+    #[clap(long = "rpc.jwt-secret")]
+    rpc_jwt_secret: Option<String>,
+    /// Comma separated origins allowed to call the RPC server from a browser
+    #[clap(long = "rpc.cors", value_delimiter = ',')]
+    rpc_cors_origins: Option<Vec<String>>,
+    /// Maximum RPC requests per second from a single IP address. WebSocket connections are
+    /// refused while set, as their messages would bypass the limit.
+    #[clap(long = "rpc.rate-limit")]
+    rpc_rate_limit: Option<u32>,
+    /// Maximum number of fetched L1 blocks waiting for derivation
+    #[clap(long)]
+    l1_block_buffer: Option<usize>,
+    /// Start in light-follow mode, trusting the unsafe heads and pausing L1 derivation
+    #[clap(long)]
+    light_follow: bool,
+    /// Maximum number of channels decoded ahead of derivation in the background, 0 to disable
+    #[clap(long = "derivation.lookahead")]
+    derivation_lookahead: Option<usize>,
+    /// Gas limit of the blocks built by the sequencer, until the gas limit is updated on L1
+    #[clap(long = "sequencer.gas-limit")]
+    sequencer_gas_limit: Option<u64>,
+    /// Compute and cache the output root of every new safe block
+    #[clap(long = "rpc.precompute-outputs")]
+    rpc_precompute_outputs: bool,
+    /// Seconds without progress before a driver component is reported as stalled, 0 to disable
+    #[clap(long = "watchdog.stall-timeout")]
+    watchdog_stall_timeout: Option<u64>,
+    /// What to do once a driver component stalls: log, restart or exit
+    #[clap(long = "watchdog.action")]
+    watchdog_action: Option<WatchdogAction>,
+    /// Sequencer RPC to forward eth_sendRawTransaction to, served in the eth namespace
+    #[clap(long = "rpc.sequencer-url")]
+    rpc_sequencer_url: Option<String>,
+    /// Maximum transactions forwarded to the sequencer per second
+    #[clap(long = "rpc.sequencer-rate-limit")]
+    rpc_sequencer_rate_limit: Option<u32>,
+    /// Seconds an iteration of the driver loop may take, bounding the calls it makes
+    #[clap(long = "driver.tick-timeout")]
+    driver_tick_timeout: Option<u64>,
+    /// Derive blocks without sending them to an execution client, printing a summary on exit
+    #[clap(long = "dry-run")]
+    dry_run: bool,
+    /// Stop the dry run once this L2 block is derived
+    #[clap(long = "dry-run.until")]
+    dry_run_until: Option<u64>,
+    /// Number of the latest unsafe payloads kept in the data directory, 0 to disable
+    #[clap(long = "unsafe-payload-retention")]
+    unsafe_payload_retention: Option<u64>,
+    /// Maximum number of frames pushed into channels by a derivation step, 0 for no bound
+    #[clap(long = "derivation.step-frames")]
+    derivation_step_frames: Option<usize>,
+    /// Halt derivation at the first block past a hardfork whose rules are not implemented
+    #[clap(long = "derivation.halt-on-unsupported-fork")]
+    halt_on_unsupported_fork: bool,
 }
 
 impl Cli {
     pub fn to_config(self) -> Config {
-        let chain = ChainConfig::from_network_name(&self.network);
-        let config_path = home_dir().unwrap().join(".magi/magi.toml");
+        let magi_dir = home_dir().unwrap().join(".magi");
+        let chain = ChainConfig::from_network(&self.network, &magi_dir.join("chains"));
+        let config_path = magi_dir.join("magi.toml");
         let cli_config = CliConfig::from(self);
+
         Config::new(&config_path, cli_config, chain)
     }
 
@@ -120,11 +495,61 @@ impl From<Cli> for CliConfig {
             l1_beacon_url: value.l1_beacon_url,
             l2_rpc_url: value.l2_rpc_url,
             l2_engine_url: value.l2_engine_url,
+            l2_engine_standby_urls: value.l2_engine_standby_urls,
             jwt_secret,
             checkpoint_sync_url: value.checkpoint_sync_url,
             rpc_port: value.rpc_port,
             rpc_addr: value.rpc_addr,
             devnet: value.devnet,
+            supervisor_rpc_url: value.supervisor_rpc_url,
+            p2p_port: value.p2p_port,
+            forkchoice_batch_size: value.forkchoice_batch_size,
+            forkchoice_batch_interval_ms: value.forkchoice_batch_interval_ms,
+            jwt_secret_file: value
+                .jwt_file
+                .as_ref()
+                .map(|path| path.to_string_lossy().to_string()),
+            l1_rpc_budget: value.l1_rpc_budget,
+            data_dir: value.data_dir,
+            l1_epoch_retention: value.l1_epoch_retention,
+            l2_block_retention: value.l2_block_retention,
+            p2p_sync: value.p2p_sync.then_some(true),
+            p2p_max_inbound_peers: value.p2p_max_inbound_peers,
+            p2p_max_outbound_peers: value.p2p_max_outbound_peers,
+            p2p_static_peers: value.p2p_static_peers,
+            p2p_bootnodes: value.p2p_bootnodes,
+            sequencer: value.sequencer.then_some(true),
+            p2p_sequencer_key: value.p2p_sequencer_key,
+            p2p_sequencer_keystore: value.p2p_sequencer_keystore,
+            p2p_sequencer_keystore_password_file: value.p2p_sequencer_keystore_password_file,
+            p2p_sequencer_signer_url: value.p2p_sequencer_signer_url,
+            p2p_sequencer_signer_backup_url: value.p2p_sequencer_signer_backup_url,
+            p2p_sequencer_address: value.p2p_sequencer_address,
+            profile_derivation: value.profile_derivation.then_some(true),
+            unsafe_follow_url: value.unsafe_follow_url,
+            unsafe_follow_max_lag: value.unsafe_follow_max_lag,
+            readiness_max_head_lag: value.readiness_max_head_lag,
+            readiness_max_stall: value.readiness_max_stall,
+            rpc_api: value.rpc_api,
+            rpc_auth_token: value.rpc_auth_token,
+            rpc_jwt_secret: value.rpc_jwt_secret,
+            rpc_cors_origins: value.rpc_cors_origins,
+            rpc_rate_limit: value.rpc_rate_limit,
+            l1_block_buffer: value.l1_block_buffer,
+            light_follow: value.light_follow.then_some(true),
+            derivation_lookahead: value.derivation_lookahead,
+            sequencer_gas_limit: value.sequencer_gas_limit,
+            rpc_precompute_outputs: value.rpc_precompute_outputs.then_some(true),
+            watchdog_stall_timeout: value.watchdog_stall_timeout,
+            watchdog_action: value.watchdog_action,
+            rpc_sequencer_url: value.rpc_sequencer_url,
+            rpc_sequencer_rate_limit: value.rpc_sequencer_rate_limit,
+            driver_tick_timeout: value.driver_tick_timeout,
+            dry_run: value.dry_run.then_some(true),
+            dry_run_until: value.dry_run_until,
+            unsafe_payload_retention: value.unsafe_payload_retention,
+            derivation_step_frames: value.derivation_step_frames,
+            halt_on_unsupported_fork: value.halt_on_unsupported_fork.then_some(true),
         }
     }
 }
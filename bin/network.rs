@@ -4,6 +4,7 @@ use ethers::types::Address;
 use eyre::Result;
 
 use magi::{
+    config::HardforkSchedule,
     network::{handlers::block_handler::BlockHandler, service::Service},
     telemetry,
 };
@@ -18,7 +19,8 @@ async fn main() -> Result<()> {
     let (_, recv) = watch::channel(Address::from_str(
         "0x715b7219d986641df9efd9c7ef01218d528e19ec",
     )?);
-    let (block_handler, block_recv) = BlockHandler::new(chain_id, recv);
+    let (block_handler, block_recv) =
+        BlockHandler::new(chain_id, HardforkSchedule::default(), recv);
 
     Service::new(addr, chain_id)
         .add_handler(Box::new(block_handler))
@@ -28,6 +28,10 @@ pub struct AttributesDepositedCall {
     pub blob_base_fee_scalar: Option<u32>,
     /// Blob base fee (after Ecotone)
     pub blob_base_fee: Option<U256>,
+    /// Operator fee scalar (after Isthmus)
+    pub operator_fee_scalar: Option<u32>,
+    /// Operator fee constant (after Isthmus)
+    pub operator_fee_constant: Option<u64>,
 }
 
 const L1_INFO_BEDROCK_LEN: usize = 4 + 32 * 8;
@@ -37,6 +41,9 @@ const L1_INFO_BEDROCK_SIGNATURE: &str =
 const L1_INFO_ECOTONE_LEN: usize = 4 + 32 * 5;
 const L1_INFO_ECOTONE_SIGNATURE: &str = "setL1BlockValuesEcotone()";
 
+const L1_INFO_ISTHMUS_LEN: usize = 4 + 32 * 5 + 4 + 8;
+const L1_INFO_ISTHMUS_SIGNATURE: &str = "setL1BlockValuesIsthmus()";
+
 lazy_static! {
     static ref SET_L1_BLOCK_VALUES_BEDROCK_SELECTOR: [u8; 4] = keccak256(L1_INFO_BEDROCK_SIGNATURE)
         [..4]
@@ -46,6 +53,10 @@ lazy_static! {
         [..4]
         .try_into()
         .unwrap();
+    static ref SET_L1_BLOCK_VALUES_ISTHMUS_SELECTOR: [u8; 4] = keccak256(L1_INFO_ISTHMUS_SIGNATURE)
+        [..4]
+        .try_into()
+        .unwrap();
 }
 
 impl AttributesDepositedCall {
@@ -117,6 +128,8 @@ impl AttributesDepositedCall {
             // Ecotone fields are not present in Bedrock attributes deposited calls
             blob_base_fee_scalar: None,
             blob_base_fee: None,
+            operator_fee_scalar: None,
+            operator_fee_constant: None,
         })
     }
 
@@ -137,15 +150,24 @@ impl AttributesDepositedCall {
     /// | 32      | BatcherHash              |
     /// +---------+--------------------------+
     /// ```
+    ///
+    /// The Isthmus format calls `setL1BlockValuesIsthmus` instead, and appends the 4 bytes
+    /// of the OperatorFeeScalar and the 8 bytes of the OperatorFeeConstant.
     pub fn try_from_ecotone(calldata: Bytes) -> Result<Self> {
         let mut cursor = 0;
 
-        if calldata.len() != L1_INFO_ECOTONE_LEN {
-            eyre::bail!("invalid calldata length");
-        }
+        let is_isthmus = match calldata.len() {
+            L1_INFO_ECOTONE_LEN => false,
+            L1_INFO_ISTHMUS_LEN => true,
+            _ => eyre::bail!("invalid calldata length"),
+        };
 
         let selector = &calldata[cursor..cursor + 4];
-        if selector != *SET_L1_BLOCK_VALUES_ECOTONE_SELECTOR {
+        let expected_selector = match is_isthmus {
+            true => *SET_L1_BLOCK_VALUES_ISTHMUS_SELECTOR,
+            false => *SET_L1_BLOCK_VALUES_ECOTONE_SELECTOR,
+        };
+        if selector != expected_selector {
             eyre::bail!("invalid selector");
         }
         cursor += 4;
@@ -177,6 +199,15 @@ impl AttributesDepositedCall {
         cursor += 32;
 
         let batcher_hash = H256::from_slice(&calldata[cursor..cursor + 32]);
+        cursor += 32;
+
+        let (operator_fee_scalar, operator_fee_constant) = if is_isthmus {
+            let scalar = u32::from_be_bytes(calldata[cursor..cursor + 4].try_into()?);
+            let constant = u64::from_be_bytes(calldata[cursor + 4..cursor + 12].try_into()?);
+            (Some(scalar), Some(constant))
+        } else {
+            (None, None)
+        };
 
         Ok(Self {
             number,
@@ -188,6 +219,8 @@ impl AttributesDepositedCall {
             fee_scalar,
             blob_base_fee,
             blob_base_fee_scalar,
+            operator_fee_scalar,
+            operator_fee_constant,
 
             // The pre-Ecotone L1 fee overhead value is dropped in Ecotone
             fee_overhead: U256::zero(),
@@ -1,4 +1,4 @@
-use ethers::types::{Block, Transaction};
+use ethers::types::{Block, Bytes, Transaction};
 use eyre::Result;
 use serde::{Deserialize, Serialize};
 
@@ -25,6 +25,7 @@ impl HeadInfo {
     pub fn try_from_l2_block(config: &Config, l2_block: Block<Transaction>) -> Result<Self> {
         if config
             .chain
+            .hardforks
             .is_ecotone_but_not_first_block(l2_block.timestamp.as_u64())
         {
             HeadInfo::try_from_ecotone_block(l2_block)
@@ -33,6 +34,30 @@ impl HeadInfo {
         }
     }
 
+    /// Returns the head info from the given L2 block info and the calldata of its L1 attributes
+    /// deposited transaction. Used when only the header and raw transactions of a block are known.
+    pub fn try_from_l1_info_calldata(
+        config: &Config,
+        l2_block_info: BlockInfo,
+        calldata: Bytes,
+    ) -> Result<Self> {
+        let call = if config
+            .chain
+            .hardforks
+            .is_ecotone_but_not_first_block(l2_block_info.timestamp)
+        {
+            AttributesDepositedCall::try_from_ecotone(calldata)?
+        } else {
+            AttributesDepositedCall::try_from_bedrock(calldata)?
+        };
+
+        Ok(Self {
+            l2_block_info,
+            l1_epoch: Epoch::from(&call),
+            sequence_number: call.sequence_number,
+        })
+    }
+
     /// Returns `HeadInfo` consisting of the L2 block, the L1 epoch block it belongs to, and the L2 block's position in the epoch.
     /// This function is used when the L2 block is from the Bedrock hardfork or earlier.
     fn try_from_bedrock_block(block: Block<Transaction>) -> Result<Self> {
@@ -75,7 +100,7 @@ impl HeadInfo {
 #[cfg(test)]
 mod tests {
     mod head_info_bedrock {
-        use crate::driver::HeadInfo;
+        use crate::common::HeadInfo;
         use std::str::FromStr;
 
         use ethers::{
@@ -260,7 +285,7 @@ mod tests {
     }
 
     mod head_info_ecotone {
-        use crate::driver::HeadInfo;
+        use crate::common::HeadInfo;
         use std::str::FromStr;
 
         use ethers::{
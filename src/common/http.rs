@@ -0,0 +1,170 @@
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use ethers::providers::{Http, Provider};
+use eyre::Result;
+use reqwest::Url;
+use tokio::sync::watch;
+
+/// Timeout of L1 execution RPC requests
+pub const L1_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Timeout of L1 beacon API requests, which may return every blob of a block
+pub const BEACON_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Timeout of Engine API calls, including their retries
+pub const ENGINE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Timeout of L2 execution RPC and supervisor requests
+pub const L2_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Maximum time to connect to an endpoint
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Minimum timeout of the calls made close to or past the deadline of their task, so the
+/// task can wrap up its work
+const MIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+tokio::task_local! {
+    /// The deadline of the task, applying to its calls
+    static DEADLINE: Deadline;
+}
+
+/// Returns a builder of HTTP clients whose requests time out after `timeout`
+pub fn client_builder(timeout: Duration) -> reqwest::ClientBuilder {
+    reqwest::ClientBuilder::new()
+        .timeout(timeout)
+        .connect_timeout(CONNECT_TIMEOUT.min(timeout))
+}
+
+/// Returns an HTTP client whose requests time out after `timeout`
+pub fn client(timeout: Duration) -> Result<reqwest::Client> {
+    Ok(client_builder(timeout).build()?)
+}
+
+/// Returns a provider for the JSON-RPC endpoint at `url` whose requests time out after
+/// `timeout`
+pub fn provider(url: &str, timeout: Duration) -> Result<Provider<Http>> {
+    let http = Http::new_with_client(Url::parse(url)?, client(timeout)?);
+    Ok(Provider::new(http))
+}
+
+/// The deadline of a unit of work, such as an iteration of the driver loop, propagated to
+/// the [call]s made while it runs.
+#[derive(Debug, Clone)]
+pub struct Deadline {
+    /// When the work must be done
+    at: Instant,
+    /// Signals a shutdown, cancelling the calls in flight
+    shutdown: watch::Receiver<bool>,
+}
+
+impl Deadline {
+    /// Creates a deadline `timeout` from now, cancelled by the shutdown signal
+    pub fn new(timeout: Duration, shutdown: watch::Receiver<bool>) -> Self {
+        Self {
+            at: Instant::now() + timeout,
+            shutdown,
+        }
+    }
+
+    /// Runs `fut` with the deadline applying to its calls
+    pub async fn scope<F: Future>(self, fut: F) -> F::Output {
+        DEADLINE.scope(self, fut).await
+    }
+
+    /// Returns true if the task runs past its deadline
+    pub fn passed() -> bool {
+        DEADLINE
+            .try_with(|deadline| Instant::now() >= deadline.at)
+            .unwrap_or(false)
+    }
+
+    /// Returns the timeout of a call, shortened to the time left until the deadline
+    fn timeout(&self, timeout: Duration) -> Duration {
+        let remaining = self.at.saturating_duration_since(Instant::now());
+        timeout.min(remaining.max(MIN_TIMEOUT))
+    }
+
+    /// Resolves once a shutdown is signaled
+    async fn cancelled(mut self) {
+        loop {
+            if *self.shutdown.borrow() {
+                return;
+            }
+
+            if self.shutdown.changed().await.is_err() {
+                // no shutdown can be signaled anymore
+                std::future::pending::<()>().await;
+            }
+        }
+    }
+}
+
+/// Runs an external call, failing it after `timeout` or the deadline of the task, whichever
+/// comes first, and cancelling it on shutdown
+pub async fn call<T, F>(name: &str, timeout: Duration, fut: F) -> Result<T>
+where
+    F: Future<Output = Result<T>>,
+{
+    let deadline = DEADLINE.try_with(Deadline::clone).ok();
+    let timeout = deadline
+        .as_ref()
+        .map_or(timeout, |deadline| deadline.timeout(timeout));
+
+    let cancelled = async move {
+        match deadline {
+            Some(deadline) => deadline.cancelled().await,
+            None => std::future::pending().await,
+        }
+    };
+
+    tokio::select! {
+        result = tokio::time::timeout(timeout, fut) => {
+            result.map_err(|_| eyre::eyre!("{} timed out after {:?}", name, timeout))?
+        }
+        _ = cancelled => Err(eyre::eyre!("{} cancelled by shutdown", name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_call_deadline() {
+        let (shutdown_sender, shutdown) = watch::channel(false);
+        let slow = || async {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok(())
+        };
+
+        // calls outside of a deadline only have their own timeout
+        let err = call("slow", Duration::from_millis(10), slow()).await;
+        assert!(err.unwrap_err().to_string().contains("timed out"));
+        assert!(!Deadline::passed());
+
+        // the deadline shortens the timeout of the call, down to the minimum
+        let deadline = Deadline::new(Duration::ZERO, shutdown.clone());
+        let start = Instant::now();
+        deadline
+            .scope(async {
+                assert!(Deadline::passed());
+                assert!(call("slow", Duration::from_secs(60), slow()).await.is_err());
+            })
+            .await;
+        assert!(start.elapsed() < Duration::from_secs(2));
+
+        // a shutdown cancels the calls in flight
+        let deadline = Deadline::new(Duration::from_secs(60), shutdown);
+        let result = deadline
+            .scope(async {
+                shutdown_sender.send(true).unwrap();
+                call("slow", Duration::from_secs(60), slow()).await
+            })
+            .await;
+        assert!(result.unwrap_err().to_string().contains("cancelled"));
+    }
+}
@@ -2,7 +2,10 @@ use std::fmt::Debug;
 
 use ethers::{
     types::{Block, Transaction, H256},
-    utils::rlp::{Decodable, DecoderError, Rlp},
+    utils::{
+        keccak256,
+        rlp::{Decodable, DecoderError, Rlp},
+    },
 };
 use eyre::Result;
 use figment::value::{Dict, Tag, Value};
@@ -14,6 +17,14 @@ use crate::engine::ExecutionPayload;
 pub mod attributes_deposited;
 pub use attributes_deposited::AttributesDepositedCall;
 
+/// A module to handle conversions to a [HeadInfo] struct
+pub mod head_info;
+pub use head_info::HeadInfo;
+
+/// HTTP clients, timeouts and deadlines of external calls
+#[cfg(any(feature = "l1-client", feature = "engine-client"))]
+pub mod http;
+
 /// Selected block header info
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Default, Serialize, Deserialize)]
 pub struct BlockInfo {
@@ -27,6 +38,23 @@ pub struct BlockInfo {
     pub timestamp: u64,
 }
 
+/// Computes the L2 output root.
+/// Refer to the [Optimism Spec](https://specs.optimism.io/protocol/proposals.html#l2-output-commitment-construction) for details
+pub fn compute_l2_output_root<T>(block: Block<T>, storage_root: H256) -> H256 {
+    let version: H256 = Default::default();
+    let digest = keccak256(
+        [
+            version.to_fixed_bytes(),
+            block.state_root.to_fixed_bytes(),
+            storage_root.to_fixed_bytes(),
+            block.hash.unwrap().to_fixed_bytes(),
+        ]
+        .concat(),
+    );
+
+    H256::from_slice(&digest)
+}
+
 /// A raw transaction
 #[derive(Clone, PartialEq, Eq)]
 pub struct RawTransaction(pub Vec<u8>);
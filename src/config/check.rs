@@ -0,0 +1,313 @@
+use std::{
+    future::Future,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use ethers::{
+    providers::{Http, Middleware, Provider},
+    types::BlockNumber,
+};
+use eyre::Result;
+use serde_json::Value;
+
+use crate::{
+    driver::HeadInfo,
+    engine::{EngineApi, IpcClient, JwtSecret, JwtSecretFile},
+    l1::blob_fetcher::BlobFetcher,
+};
+
+use super::Config;
+
+/// Time after which an unresponsive endpoint fails its check
+const CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Number of consecutive slots tried when looking for blobs, as a slot may be missed
+const BLOB_SLOT_ATTEMPTS: u64 = 4;
+
+/// The result of validating one part of the [Config] against its endpoint
+#[derive(Debug)]
+pub struct Check {
+    /// What was checked
+    pub name: String,
+    /// A summary of the checked value, or an error describing how to fix the config
+    pub result: Result<String>,
+}
+
+/// Validates the configuration against the live endpoints: the chain ids of the L1 and
+/// L2 RPCs, the L2 genesis block, the JWT handshake with every engine endpoint, and the
+/// availability of the beacon API and of the blobs derivation still needs.
+pub async fn check_config(config: &Config) -> Vec<Check> {
+    let mut checks = vec![
+        check("l1 chain id", check_l1_chain_id(config)).await,
+        check("l2 chain id", check_l2_chain_id(config)).await,
+        check("l2 genesis", check_l2_genesis(config)).await,
+    ];
+
+    let engine_urls = std::iter::once(&config.l2_engine_url).chain(&config.l2_engine_standby_urls);
+    for url in engine_urls {
+        let name = format!("engine {}", url);
+        checks.push(check(&name, check_engine(config, url)).await);
+    }
+
+    let beacon = BlobFetcher::new(config.l1_beacon_url.clone());
+    checks.push(check("beacon api", check_beacon(config, &beacon)).await);
+    checks.push(check("blob retention", check_blob_retention(config, &beacon)).await);
+
+    checks
+}
+
+/// Runs a check, failing it if the endpoint does not respond in time
+async fn check(name: &str, check: impl Future<Output = Result<String>>) -> Check {
+    let result = match tokio::time::timeout(CHECK_TIMEOUT, check).await {
+        Ok(result) => result,
+        Err(_) => Err(eyre::eyre!(
+            "no response within {}s",
+            CHECK_TIMEOUT.as_secs()
+        )),
+    };
+
+    Check {
+        name: name.to_string(),
+        result,
+    }
+}
+
+async fn check_l1_chain_id(config: &Config) -> Result<String> {
+    let chain_id = provider(&config.l1_rpc_url, "--l1-rpc-url")?
+        .get_chainid()
+        .await
+        .map_err(|err| eyre::eyre!("L1 RPC unreachable: {}; check --l1-rpc-url", err))?
+        .as_u64();
+
+    if chain_id != config.chain.l1_chain_id {
+        eyre::bail!(
+            "the L1 RPC serves chain {}, but {} settles on chain {}; check --l1-rpc-url and --network",
+            chain_id,
+            config.chain.network,
+            config.chain.l1_chain_id
+        );
+    }
+
+    Ok(format!("chain {}", chain_id))
+}
+
+async fn check_l2_chain_id(config: &Config) -> Result<String> {
+    let chain_id = provider(&config.l2_rpc_url, "--l2-rpc-url")?
+        .get_chainid()
+        .await
+        .map_err(|err| eyre::eyre!("L2 RPC unreachable: {}; check --l2-rpc-url", err))?
+        .as_u64();
+
+    if chain_id != config.chain.l2_chain_id {
+        eyre::bail!(
+            "the L2 RPC serves chain {}, but {} is chain {}; check --l2-rpc-url and --network",
+            chain_id,
+            config.chain.network,
+            config.chain.l2_chain_id
+        );
+    }
+
+    Ok(format!("chain {}", chain_id))
+}
+
+async fn check_l2_genesis(config: &Config) -> Result<String> {
+    let genesis = &config.chain.l2_genesis;
+    let block = provider(&config.l2_rpc_url, "--l2-rpc-url")?
+        .get_block(genesis.number)
+        .await
+        .map_err(|err| eyre::eyre!("L2 RPC unreachable: {}; check --l2-rpc-url", err))?
+        .ok_or(eyre::eyre!(
+            "the execution client has no block {}; initialize it with the genesis of {}",
+            genesis.number,
+            config.chain.network
+        ))?;
+
+    if block.hash != Some(genesis.hash) {
+        eyre::bail!(
+            "genesis block {} is {:?}, but {:?} is expected; initialize the execution client with the genesis of {}",
+            genesis.number,
+            block.hash.unwrap_or_default(),
+            genesis.hash,
+            config.chain.network
+        );
+    }
+
+    Ok(format!("block {} {:?}", genesis.number, genesis.hash))
+}
+
+async fn check_engine(config: &Config, url: &str) -> Result<String> {
+    let is_ipc = IpcClient::path_from_url(url).is_some();
+    let secret = config.jwt_secret.as_deref().unwrap_or_default();
+    if !is_ipc && JwtSecret::from_hex(secret).is_err() {
+        eyre::bail!("invalid jwt secret; check --jwt-secret or --jwt-file");
+    }
+
+    let mut engine = EngineApi::from_config(url, config)?;
+    if let Some(path) = &config.jwt_secret_file {
+        let secret_file = JwtSecretFile::load(path).map_err(|err| {
+            eyre::eyre!(
+                "could not load jwt secret file {}: {}; check --jwt-file",
+                path,
+                err
+            )
+        })?;
+
+        engine = engine.with_secret_file(Arc::new(secret_file));
+    }
+
+    let capabilities = engine.exchange_capabilities().await.map_err(|err| {
+        eyre::eyre!(
+            "handshake failed: {}; check the engine url and that the jwt secret matches the execution client",
+            err
+        )
+    })?;
+
+    Ok(format!("{} engine methods supported", capabilities.len()))
+}
+
+async fn check_beacon(config: &Config, beacon: &BlobFetcher) -> Result<String> {
+    let genesis_time = beacon
+        .fetch_beacon_genesis_timestamp()
+        .await
+        .map_err(|err| eyre::eyre!("beacon API unreachable: {}; check --l1-beacon-url", err))?;
+
+    let spec = beacon
+        .fetch_beacon_spec()
+        .await
+        .map_err(|err| eyre::eyre!("beacon spec unavailable: {}; check --l1-beacon-url", err))?;
+
+    let chain_id = spec_value(&spec, "DEPOSIT_CHAIN_ID")?;
+    if chain_id != config.chain.l1_chain_id {
+        eyre::bail!(
+            "the beacon node follows chain {}, but {} settles on chain {}; check --l1-beacon-url",
+            chain_id,
+            config.chain.network,
+            config.chain.l1_chain_id
+        );
+    }
+
+    Ok(format!("genesis time {}", genesis_time))
+}
+
+/// Checks that the beacon node still serves the blobs from the L1 origin of the finalized
+/// L2 head, less a channel timeout, which derivation may need to read again
+async fn check_blob_retention(config: &Config, beacon: &BlobFetcher) -> Result<String> {
+    let now = now();
+    if !config.chain.hardforks.is_ecotone_active(now) {
+        return Ok("blobs are not used before ecotone".to_string());
+    }
+
+    let l2 = provider(&config.l2_rpc_url, "--l2-rpc-url")?;
+    let origin = l2
+        .get_block_with_txs(BlockNumber::Finalized)
+        .await
+        .ok()
+        .flatten()
+        .and_then(|block| HeadInfo::try_from_l2_block(config, block).ok())
+        .map(|head| head.l1_epoch)
+        .unwrap_or(config.chain.l1_start_epoch);
+
+    let oldest = origin
+        .number
+        .saturating_sub(config.chain.max_channel_timeout(origin.timestamp));
+    let block = provider(&config.l1_rpc_url, "--l1-rpc-url")?
+        .get_block(oldest)
+        .await
+        .map_err(|err| eyre::eyre!("L1 RPC unreachable: {}; check --l1-rpc-url", err))?
+        .ok_or(eyre::eyre!("L1 block {} not found", oldest))?;
+
+    let timestamp = block
+        .timestamp
+        .as_u64()
+        .max(config.chain.hardforks.ecotone_time);
+
+    let spec = beacon
+        .fetch_beacon_spec()
+        .await
+        .map_err(|err| eyre::eyre!("beacon spec unavailable: {}; check --l1-beacon-url", err))?;
+
+    let retention = spec_value(&spec, "MIN_EPOCHS_FOR_BLOB_SIDECARS_REQUESTS")?
+        * spec_value(&spec, "SLOTS_PER_EPOCH")?
+        * spec_value(&spec, "SECONDS_PER_SLOT")?;
+
+    let age = now.saturating_sub(timestamp);
+    if age > retention {
+        eyre::bail!(
+            "derivation needs blobs from {}h ago, but beacon nodes only keep them for {}h; use a beacon node archiving blobs or sync from a recent checkpoint",
+            age / 3600,
+            retention / 3600
+        );
+    }
+
+    let slot = beacon.get_slot_from_time(timestamp).await?;
+    let mut result = Ok(Vec::new());
+    for attempt in slot..slot + BLOB_SLOT_ATTEMPTS {
+        result = beacon.fetch_blob_sidecars(attempt).await;
+        if result.is_ok() {
+            break;
+        }
+    }
+
+    result.map_err(|err| {
+        eyre::eyre!(
+            "the beacon node does not serve the blobs of slot {}: {}; use a beacon node keeping blobs since then",
+            slot,
+            err
+        )
+    })?;
+
+    Ok(format!("blobs available since slot {}", slot))
+}
+
+/// Creates an HTTP provider, describing the flag to fix if the url is invalid
+fn provider(url: &str, flag: &str) -> Result<Provider<Http>> {
+    Provider::<Http>::try_from(url)
+        .map_err(|err| eyre::eyre!("invalid url {}: {}; check {}", url, err, flag))
+}
+
+/// Returns a numeric value of the beacon chain spec
+fn spec_value(spec: &Value, key: &str) -> Result<u64> {
+    spec.get(key)
+        .and_then(|value| value.as_str())
+        .and_then(|value| value.parse().ok())
+        .ok_or(eyre::eyre!("beacon spec has no {}", key))
+}
+
+/// Returns the current unix timestamp
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_unreachable_endpoints() {
+        let config = Config {
+            l1_rpc_url: "http://127.0.0.1:1".to_string(),
+            l1_beacon_url: "http://127.0.0.1:1".to_string(),
+            l2_rpc_url: "not a url".to_string(),
+            l2_engine_url: "http://127.0.0.1:1".to_string(),
+            ..Default::default()
+        };
+
+        let checks = check_config(&config).await;
+        assert_eq!(checks.len(), 6);
+        assert!(checks.iter().all(|check| check.result.is_err()));
+
+        let error = |name: &str| {
+            let check = checks.iter().find(|check| check.name == name).unwrap();
+            check.result.as_ref().unwrap_err().to_string()
+        };
+
+        assert!(error("l1 chain id").contains("--l1-rpc-url"));
+        assert!(error("l2 chain id").contains("invalid url"));
+        assert!(error("engine http://127.0.0.1:1").contains("invalid jwt secret"));
+        assert!(error("beacon api").contains("--l1-beacon-url"));
+    }
+}
@@ -0,0 +1,322 @@
+use std::{fs, path::Path, str::FromStr};
+
+use alloy_primitives::{Address, U256};
+use ethers::{
+    providers::{Http, Middleware, Provider},
+    types::{BlockId, BlockNumber, H256},
+};
+use eyre::Result;
+use serde::Deserialize;
+
+use crate::common::{BlockInfo, Epoch};
+
+use super::{addr, ChainConfig, ChainOpConfig, HardforkSchedule, SystemAccounts, SystemConfig};
+
+/// The maximum byte size of all pending channels before the fjord hardfork
+const MAX_CHANNEL_SIZE: u64 = 100_000_000;
+
+/// A quantity of the deploy config, written either as a number or as a hex string
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum Quantity {
+    /// A JSON number
+    Number(u64),
+    /// A `0x` prefixed hex string
+    Hex(String),
+}
+
+impl Quantity {
+    fn value(&self) -> Result<u64> {
+        match self {
+            Quantity::Number(value) => Ok(*value),
+            Quantity::Hex(hex) => Ok(u64::from_str_radix(hex.trim_start_matches("0x"), 16)?),
+        }
+    }
+}
+
+/// The subset of the L1 deploy config of the OP Stack deployer, such as
+/// `devnetL1.json`, which determines the rollup config
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DeployConfig {
+    #[serde(rename = "l1ChainID")]
+    l1_chain_id: u64,
+    #[serde(rename = "l2ChainID")]
+    l2_chain_id: u64,
+    l2_block_time: u64,
+    max_sequencer_drift: u64,
+    sequencer_window_size: u64,
+    channel_timeout: u64,
+    p2p_sequencer_address: Address,
+    batch_inbox_address: Address,
+    batch_sender_address: Address,
+    /// The L1 block the rollup starts at, by hash, number or tag
+    l1_starting_block_tag: String,
+    l2_genesis_block_gas_limit: Quantity,
+    #[serde(default)]
+    gas_price_oracle_overhead: u64,
+    #[serde(default)]
+    gas_price_oracle_scalar: u64,
+    #[serde(default)]
+    gas_price_oracle_base_fee_scalar: u32,
+    #[serde(default)]
+    gas_price_oracle_blob_base_fee_scalar: u32,
+    eip1559_elasticity: Option<u64>,
+    eip1559_denominator: Option<u64>,
+    eip1559_denominator_canyon: Option<u64>,
+    l2_genesis_regolith_time_offset: Option<Quantity>,
+    l2_genesis_canyon_time_offset: Option<Quantity>,
+    l2_genesis_delta_time_offset: Option<Quantity>,
+    l2_genesis_ecotone_time_offset: Option<Quantity>,
+    l2_genesis_fjord_time_offset: Option<Quantity>,
+    l2_genesis_granite_time_offset: Option<Quantity>,
+    l2_genesis_holocene_time_offset: Option<Quantity>,
+    l2_genesis_isthmus_time_offset: Option<Quantity>,
+    l2_genesis_interop_time_offset: Option<Quantity>,
+}
+
+/// The L1 contract addresses written by the OP Stack deployer, such as
+/// `.devnet/addresses.json`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DeployAddresses {
+    optimism_portal_proxy: Address,
+    system_config_proxy: Address,
+    #[serde(default)]
+    protocol_versions_proxy: Address,
+}
+
+/// The deploy config and L1 contract addresses of a local devnet, from which the
+/// [ChainConfig] is derived without hand-computing genesis values
+#[derive(Debug, Clone)]
+pub struct DevnetGenesis {
+    /// The L1 deploy config
+    deploy_config: DeployConfig,
+    /// The deployed L1 contracts
+    addresses: DeployAddresses,
+}
+
+impl DevnetGenesis {
+    /// Reads the deploy config and L1 contract addresses JSON files
+    pub fn read(deploy_config: &Path, addresses: &Path) -> Result<Self> {
+        Ok(Self {
+            deploy_config: serde_json::from_slice(&fs::read(deploy_config)?)?,
+            addresses: serde_json::from_slice(&fs::read(addresses)?)?,
+        })
+    }
+
+    /// Derives the [ChainConfig], fetching the L1 starting block from the L1 RPC and the
+    /// L2 genesis block from the L2 RPC of an execution client initialized with the
+    /// genesis of the devnet
+    pub async fn chain_config(&self, l1_rpc_url: &str, l2_rpc_url: &str) -> Result<ChainConfig> {
+        let tag = &self.deploy_config.l1_starting_block_tag;
+        let l1_provider = Provider::<Http>::try_from(l1_rpc_url)?;
+        let l1_block = l1_provider
+            .get_block(block_id(tag)?)
+            .await?
+            .ok_or(eyre::eyre!("L1 starting block {} not found", tag))?;
+
+        let l2_provider = Provider::<Http>::try_from(l2_rpc_url)?;
+        let l2_block = l2_provider
+            .get_block(0)
+            .await?
+            .ok_or(eyre::eyre!("L2 genesis block not found"))?;
+
+        let l1_start_epoch = Epoch {
+            number: l1_block
+                .number
+                .ok_or(eyre::eyre!("L1 starting block without number"))?
+                .as_u64(),
+            hash: l1_block
+                .hash
+                .ok_or(eyre::eyre!("L1 starting block without hash"))?,
+            timestamp: l1_block.timestamp.as_u64(),
+        };
+
+        let l2_genesis = BlockInfo {
+            hash: l2_block
+                .hash
+                .ok_or(eyre::eyre!("L2 genesis block without hash"))?,
+            number: 0,
+            parent_hash: H256::zero(),
+            timestamp: l2_block.timestamp.as_u64(),
+        };
+
+        let chain = self.build(l1_start_epoch, l2_genesis)?;
+        chain.validate()?;
+
+        Ok(chain)
+    }
+
+    /// Builds the [ChainConfig] starting at the given L1 block and L2 genesis block
+    fn build(&self, l1_start_epoch: Epoch, l2_genesis: BlockInfo) -> Result<ChainConfig> {
+        let config = &self.deploy_config;
+        let genesis_time = l2_genesis.timestamp;
+
+        // hardforks are scheduled relative to the L2 genesis, and unscheduled without an offset
+        let time = |offset: &Option<Quantity>| -> Result<Option<u64>> {
+            offset
+                .as_ref()
+                .map(|offset| Ok(genesis_time + offset.value()?))
+                .transpose()
+        };
+
+        let hardforks = HardforkSchedule {
+            regolith_time: time(&config.l2_genesis_regolith_time_offset)?.unwrap_or(u64::MAX),
+            canyon_time: time(&config.l2_genesis_canyon_time_offset)?.unwrap_or(u64::MAX),
+            delta_time: time(&config.l2_genesis_delta_time_offset)?.unwrap_or(u64::MAX),
+            ecotone_time: time(&config.l2_genesis_ecotone_time_offset)?.unwrap_or(u64::MAX),
+            fjord_time: time(&config.l2_genesis_fjord_time_offset)?,
+            granite_time: time(&config.l2_genesis_granite_time_offset)?,
+            holocene_time: time(&config.l2_genesis_holocene_time_offset)?,
+            isthmus_time: time(&config.l2_genesis_isthmus_time_offset)?,
+            interop_time: time(&config.l2_genesis_interop_time_offset)?,
+        };
+
+        let op_config = ChainOpConfig::default();
+
+        Ok(ChainConfig {
+            network: "devnet".to_string(),
+            l1_chain_id: config.l1_chain_id,
+            l2_chain_id: config.l2_chain_id,
+            l1_start_epoch,
+            l2_genesis,
+            system_config: SystemConfig {
+                batch_sender: config.batch_sender_address,
+                gas_limit: U256::from(config.l2_genesis_block_gas_limit.value()?),
+                l1_fee_overhead: U256::from(config.gas_price_oracle_overhead),
+                l1_fee_scalar: self.fee_scalar(),
+                unsafe_block_signer: config.p2p_sequencer_address,
+                operator_fee_params: Default::default(),
+            },
+            batch_inbox: config.batch_inbox_address,
+            batch_inbox_rotations: Vec::new(),
+            deposit_contract: self.addresses.optimism_portal_proxy,
+            system_config_contract: self.addresses.system_config_proxy,
+            max_channel_size: MAX_CHANNEL_SIZE,
+            channel_timeout: config.channel_timeout,
+            seq_window_size: config.sequencer_window_size,
+            max_seq_drift: config.max_sequencer_drift,
+            hardforks,
+            interop_dependency_set: Vec::new(),
+            blocktime: config.l2_block_time,
+            l2_to_l1_message_passer: addr("0x4200000000000000000000000000000000000016"),
+            system_accounts: SystemAccounts::default(),
+            protocol_versions_contract: self.addresses.protocol_versions_proxy,
+            chain_op_config: ChainOpConfig {
+                eip1559_elasticity: config
+                    .eip1559_elasticity
+                    .unwrap_or(op_config.eip1559_elasticity),
+                eip1559_denominator: config
+                    .eip1559_denominator
+                    .unwrap_or(op_config.eip1559_denominator),
+                eip1559_denominator_canyon: config
+                    .eip1559_denominator_canyon
+                    .unwrap_or(op_config.eip1559_denominator_canyon),
+            },
+        })
+    }
+
+    /// Returns the genesis fee scalar like the deployer: the legacy scalar if set, and
+    /// otherwise the version 1 scalar packing the ecotone base fee and blob base fee scalars
+    fn fee_scalar(&self) -> U256 {
+        let config = &self.deploy_config;
+        if config.gas_price_oracle_scalar != 0 {
+            return U256::from(config.gas_price_oracle_scalar);
+        }
+
+        let mut scalar = [0u8; 32];
+        scalar[0] = 1;
+        scalar[24..28].copy_from_slice(&config.gas_price_oracle_blob_base_fee_scalar.to_be_bytes());
+        scalar[28..32].copy_from_slice(&config.gas_price_oracle_base_fee_scalar.to_be_bytes());
+        U256::from_be_bytes(scalar)
+    }
+}
+
+/// Parses the L1 starting block tag, which is a block hash, number or tag
+fn block_id(tag: &str) -> Result<BlockId> {
+    if tag.len() == 66 {
+        Ok(BlockId::Hash(H256::from_str(tag)?))
+    } else {
+        let number = BlockNumber::from_str(tag).map_err(|err| eyre::eyre!(err))?;
+        Ok(BlockId::Number(number))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_chain_config() -> Result<()> {
+        let deploy_config = serde_json::json!({
+            "l1ChainID": 900,
+            "l2ChainID": 901,
+            "l2BlockTime": 2,
+            "maxSequencerDrift": 300,
+            "sequencerWindowSize": 200,
+            "channelTimeout": 120,
+            "p2pSequencerAddress": "0x9965507d1a55bcc2695c58ba16fb37d819b0a4dc",
+            "batchInboxAddress": "0xff00000000000000000000000000000000000901",
+            "batchSenderAddress": "0x3c44cdddb6a900fa2b585dd299e03d12fa4293bc",
+            "l1StartingBlockTag": "0x3f8b3364a1e3e5ec2cf3a3b9ee8801b2cb83e8cbc6a1c2f76e4c6b4b3f0a5b6c",
+            "l2GenesisBlockGasLimit": "0x1c9c380",
+            "gasPriceOracleBaseFeeScalar": 1368,
+            "gasPriceOracleBlobBaseFeeScalar": 810949,
+            "eip1559Denominator": 50,
+            "eip1559DenominatorCanyon": 250,
+            "eip1559Elasticity": 6,
+            "l2GenesisRegolithTimeOffset": "0x0",
+            "l2GenesisCanyonTimeOffset": "0x0",
+            "l2GenesisDeltaTimeOffset": "0x0",
+            "l2GenesisEcotoneTimeOffset": "0x0",
+            "l2GenesisFjordTimeOffset": 10,
+        });
+        let addresses = serde_json::json!({
+            "OptimismPortalProxy": "0x1000000000000000000000000000000000000001",
+            "SystemConfigProxy": "0x1000000000000000000000000000000000000002",
+            "L1CrossDomainMessengerProxy": "0x1000000000000000000000000000000000000003",
+        });
+
+        let genesis = DevnetGenesis {
+            deploy_config: serde_json::from_value(deploy_config)?,
+            addresses: serde_json::from_value(addresses)?,
+        };
+        assert!(matches!(block_id("0x0")?, BlockId::Number(_)));
+        assert!(matches!(
+            block_id(&genesis.deploy_config.l1_starting_block_tag)?,
+            BlockId::Hash(_)
+        ));
+
+        let l1_start_epoch = Epoch {
+            number: 20,
+            hash: H256::repeat_byte(1),
+            timestamp: 1000,
+        };
+        let l2_genesis = BlockInfo {
+            hash: H256::repeat_byte(2),
+            number: 0,
+            parent_hash: H256::zero(),
+            timestamp: 1000,
+        };
+        let chain = genesis.build(l1_start_epoch, l2_genesis)?;
+        chain.validate()?;
+
+        assert_eq!(chain.l2_chain_id, 901);
+        assert_eq!(chain.l1_start_epoch, l1_start_epoch);
+        assert_eq!(chain.l2_genesis, l2_genesis);
+        assert_eq!(chain.system_config.gas_limit, U256::from(30_000_000));
+        assert_eq!(chain.system_config.ecotone_scalars()?, (1368, 810949));
+        assert_eq!(
+            chain.deposit_contract,
+            addr("0x1000000000000000000000000000000000000001")
+        );
+        assert!(chain.protocol_versions_contract.is_zero());
+
+        assert_eq!(chain.hardforks.ecotone_time, 1000);
+        assert_eq!(chain.hardforks.fjord_time, Some(1010));
+        assert_eq!(chain.hardforks.granite_time, None);
+
+        Ok(())
+    }
+}
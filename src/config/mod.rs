@@ -1,4 +1,10 @@
-use std::{fmt, iter, path::PathBuf, process::exit, str::FromStr};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, iter,
+    path::{Path, PathBuf},
+    process::exit,
+    str::FromStr,
+};
 
 use alloy_primitives::{Address, B256, U256};
 use figment::{
@@ -9,6 +15,34 @@ use serde::{Deserialize, Serialize};
 
 use crate::common::{BlockInfo, Epoch};
 
+/// Validation of the configuration against the live endpoints
+#[cfg(feature = "node")]
+pub mod check;
+
+/// Derivation of the chain config of a local devnet from its deployment files
+pub mod devnet;
+
+/// Chain configs from the chain definitions of the superchain registry
+pub mod registry;
+
+/// The names of the networks with a preset [ChainConfig]
+const NETWORKS: &[&str] = &["optimism", "optimism-sepolia", "base", "base-sepolia"];
+
+/// The maximum total byte size of the pending channels after the fjord hardfork
+pub const FJORD_MAX_CHANNEL_BANK_SIZE: u64 = 1_000_000_000;
+
+/// The maximum sequencer drift after the fjord hardfork
+pub const FJORD_MAX_SEQUENCER_DRIFT: u64 = 1800;
+
+/// The channel timeout in L1 blocks after the granite hardfork
+pub const GRANITE_CHANNEL_TIMEOUT: u64 = 50;
+
+/// The default maximum number of inbound p2p connections
+pub const DEFAULT_MAX_INBOUND_PEERS: usize = 30;
+
+/// The default maximum number of outbound p2p connections
+pub const DEFAULT_MAX_OUTBOUND_PEERS: usize = 20;
+
 /// Sync Mode Specifies how `magi` should sync the L2 chain
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 pub enum SyncMode {
@@ -47,6 +81,42 @@ impl fmt::Display for SyncMode {
     }
 }
 
+/// What the watchdog does once a driver component stalls
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum WatchdogAction {
+    /// Log a diagnostic dump and count the stall
+    #[default]
+    Log,
+    /// Also restart the stalled component, or exit if it cannot be restarted in place
+    Restart,
+    /// Also exit with a distinct code, for the orchestrator to restart the node
+    Exit,
+}
+
+impl FromStr for WatchdogAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "log" => Ok(Self::Log),
+            "restart" => Ok(Self::Restart),
+            "exit" => Ok(Self::Exit),
+            _ => Err("invalid watchdog action".to_string()),
+        }
+    }
+}
+
+impl fmt::Display for WatchdogAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Log => write!(f, "log"),
+            Self::Restart => write!(f, "restart"),
+            Self::Exit => write!(f, "exit"),
+        }
+    }
+}
+
 /// The global `Magi` configuration.
 #[derive(Debug, Clone, Deserialize, Default)]
 pub struct Config {
@@ -58,11 +128,16 @@ pub struct Config {
     pub l2_rpc_url: String,
     /// The L2 engine API URL
     pub l2_engine_url: String,
+    /// Standby L2 engine API URLs, failed over to if the primary engine is unhealthy
+    #[serde(default)]
+    pub l2_engine_standby_urls: Vec<String>,
     /// The L2 chain config
     pub chain: ChainConfig,
     /// Engine API JWT Secret.
-    /// This is used to authenticate with the engine API
-    pub jwt_secret: String,
+    /// This is used to authenticate with the engine API. Only needed for HTTP engines, so the
+    /// shared settings of a multi-rollup file and IPC engines go without.
+    #[serde(default)]
+    pub jwt_secret: Option<String>,
     /// A trusted L2 RPC URL to use for fast/checkpoint syncing
     pub checkpoint_sync_url: Option<String>,
     /// The port of the `Magi` RPC server
@@ -72,6 +147,105 @@ pub struct Config {
     /// The devnet mode.
     /// If devnet is enabled.
     pub devnet: bool,
+    /// The supervisor RPC URL used to validate interop executing messages
+    pub supervisor_rpc_url: Option<String>,
+    /// The port to listen on for p2p networking
+    pub p2p_port: u16,
+    /// Maximum number of safe blocks applied before a coalesced forkchoice update is sent
+    pub forkchoice_batch_size: u64,
+    /// Maximum delay in milliseconds before a coalesced forkchoice update is sent
+    pub forkchoice_batch_interval_ms: u64,
+    /// Path to the engine API JWT secret file, reloaded when the secret is rotated
+    #[serde(default)]
+    pub jwt_secret_file: Option<String>,
+    /// Compute units per second that may be spent on L1 RPC requests. Unlimited if unset.
+    pub l1_rpc_budget: Option<u32>,
+    /// Compute unit weights of L1 RPC methods, overriding the defaults
+    #[serde(default)]
+    pub l1_rpc_method_weights: HashMap<String, u32>,
+    /// Directory to persist node state in. State is only kept in memory if unset.
+    pub data_dir: Option<String>,
+    /// Number of L1 epochs before the safe epoch kept in memory. Defaults to the sequencing window size.
+    pub l1_epoch_retention: Option<u64>,
+    /// Number of L2 block refs before the safe head kept in memory. Defaults to the maximum sequencer drift in blocks.
+    pub l2_block_retention: Option<u64>,
+    /// Request missing unsafe blocks from peers with the req/resp protocol
+    pub p2p_sync: bool,
+    /// Maximum number of inbound p2p connections
+    pub p2p_max_inbound_peers: usize,
+    /// Maximum number of outbound p2p connections
+    pub p2p_max_outbound_peers: usize,
+    /// Multiaddrs of p2p peers that are always dialed and never pruned
+    #[serde(default)]
+    pub p2p_static_peers: Vec<String>,
+    /// Node records replacing the default p2p bootnodes
+    #[serde(default)]
+    pub p2p_bootnodes: Vec<String>,
+    /// Whether to run as the sequencer, building and gossiping unsafe blocks
+    pub sequencer: bool,
+    /// Hex encoded private key signing the unsafe blocks gossiped by the sequencer
+    pub p2p_sequencer_key: Option<String>,
+    /// Path to an encrypted JSON keystore holding the sequencer key
+    pub p2p_sequencer_keystore: Option<String>,
+    /// Path to a file holding the password of the sequencer keystore
+    pub p2p_sequencer_keystore_password_file: Option<String>,
+    /// Sign endpoint of a remote signer holding the sequencer key
+    pub p2p_sequencer_signer_url: Option<String>,
+    /// Sign endpoint of a backup remote signer holding the same sequencer key
+    pub p2p_sequencer_signer_backup_url: Option<String>,
+    /// Address of the sequencer key held by the remote signer
+    pub p2p_sequencer_address: Option<Address>,
+    /// Whether to periodically report the derivation throughput, time per stage and allocations
+    pub profile_derivation: bool,
+    /// An L2 RPC trusted to serve the unsafe head of the sequencer, followed ahead of derivation
+    pub unsafe_follow_url: Option<String>,
+    /// Pauses following the trusted RPC while the safe head is more than this many blocks behind the unsafe head
+    pub unsafe_follow_max_lag: Option<u64>,
+    /// Maximum seconds the unsafe head may trail the current time for the node to be ready
+    pub readiness_max_head_lag: u64,
+    /// Maximum seconds without derivation ingesting a new L1 block for the node to be healthy and ready
+    pub readiness_max_stall: u64,
+    /// The RPC namespaces to serve
+    pub rpc_api: Vec<String>,
+    /// A bearer token required in the Authorization header of RPC requests
+    pub rpc_auth_token: Option<String>,
+    /// A hex encoded secret of JWTs required in the Authorization header of RPC requests
+    pub rpc_jwt_secret: Option<String>,
+    /// Origins allowed to call the RPC server from a browser, or `*` for any
+    #[serde(default)]
+    pub rpc_cors_origins: Vec<String>,
+    /// Maximum RPC requests per second from a single IP address. WebSocket connections are refused while set.
+    pub rpc_rate_limit: Option<u32>,
+    /// Maximum number of fetched L1 blocks waiting for derivation
+    pub l1_block_buffer: usize,
+    /// Whether to start in light-follow mode, trusting the unsafe heads and pausing L1 derivation
+    pub light_follow: bool,
+    /// Maximum number of channels decoded ahead of derivation in the background. 0 disables the lookahead.
+    pub derivation_lookahead: usize,
+    /// The gas limit of the blocks built by the sequencer, until the gas limit is updated on L1
+    pub sequencer_gas_limit: Option<u64>,
+    /// Whether to compute and cache the output root of every new safe block for `optimism_outputAtBlock`
+    pub rpc_precompute_outputs: bool,
+    /// Seconds without progress before the watchdog reports a driver component as stalled, or 0 to disable it
+    pub watchdog_stall_timeout: u64,
+    /// What the watchdog does once a driver component stalls
+    pub watchdog_action: WatchdogAction,
+    /// The sequencer RPC that `eth_sendRawTransaction` is forwarded to
+    pub rpc_sequencer_url: Option<String>,
+    /// Maximum number of transactions forwarded to the sequencer per second
+    pub rpc_sequencer_rate_limit: Option<u32>,
+    /// Seconds an iteration of the driver loop may take, bounding the external calls it makes
+    pub driver_tick_timeout: u64,
+    /// Whether to validate and hash derived blocks without sending them to an execution client
+    pub dry_run: bool,
+    /// The L2 block after which a dry run stops
+    pub dry_run_until: Option<u64>,
+    /// Number of the latest unsafe payloads kept in the data directory to restore the unsafe head on restart
+    pub unsafe_payload_retention: u64,
+    /// Maximum number of frames pushed into channels by a single step of the derivation pipeline. 0 removes the bound.
+    pub derivation_step_frames: usize,
+    /// Whether to halt derivation at the first block past a hardfork whose rules are not implemented
+    pub halt_on_unsupported_fork: bool,
 }
 
 impl Config {
@@ -91,7 +265,7 @@ impl Config {
             .merge(cli_provider)
             .extract();
 
-        match config_res {
+        let config: Config = match config_res {
             Ok(config) => config,
             Err(err) => {
                 match err.kind {
@@ -106,6 +280,219 @@ impl Config {
                 }
                 exit(1);
             }
+        };
+
+        if let Err(err) = config.chain.validate() {
+            println!("\x1b[91merror\x1b[0m: invalid chain config: {err}");
+            exit(1);
+        }
+
+        config
+    }
+
+    /// Creates one [Config] per rollup listed in a multi-rollup TOML file.
+    ///
+    /// L1 settings are taken from this config and shared by every rollup, while the L2
+    /// settings of each rollup are read from the `[[rollup]]` tables of the file. Errors if
+    /// this config sets a rollup-specific setting, such as the sequencer key or the data
+    /// directory, which would otherwise be shared by every rollup.
+    pub fn for_rollups(&self, path: &Path) -> eyre::Result<Vec<Config>> {
+        let file: RollupsFile = Figment::from(Toml::file(path)).extract()?;
+
+        if file.rollup.is_empty() {
+            eyre::bail!("no rollups configured in {}", path.display());
+        }
+
+        if let Some((setting, _)) = self.rollup_settings().find(|(_, set)| *set) {
+            eyre::bail!("{} must be set per rollup in {}", setting, path.display());
+        }
+
+        let configs = file
+            .rollup
+            .into_iter()
+            .map(|rollup| rollup.into_config(self))
+            .collect::<Vec<_>>();
+
+        let mut chain_ids = HashSet::new();
+        let mut ports = HashSet::new();
+        let mut data_dirs = HashSet::new();
+
+        for config in &configs {
+            config.chain.validate()?;
+
+            if !chain_ids.insert(config.chain.l2_chain_id) {
+                eyre::bail!("rollup {} is configured twice", config.chain.l2_chain_id);
+            }
+
+            for port in [config.rpc_port, config.p2p_port] {
+                if !ports.insert(port) {
+                    eyre::bail!("port {} is used by more than one rollup", port);
+                }
+            }
+
+            if let Some(data_dir) = &config.data_dir {
+                if !data_dirs.insert(data_dir) {
+                    eyre::bail!(
+                        "data directory {} is used by more than one rollup",
+                        data_dir
+                    );
+                }
+            }
+        }
+
+        Ok(configs)
+    }
+
+    /// Returns the rollup-specific settings of a multi-rollup file, and whether this
+    /// config sets them
+    fn rollup_settings(&self) -> impl Iterator<Item = (&'static str, bool)> {
+        [
+            ("jwt_secret_file", self.jwt_secret_file.is_some()),
+            ("data_dir", self.data_dir.is_some()),
+            ("sequencer", self.sequencer),
+            ("p2p_sequencer_key", self.p2p_sequencer_key.is_some()),
+            (
+                "p2p_sequencer_keystore",
+                self.p2p_sequencer_keystore.is_some(),
+            ),
+            (
+                "p2p_sequencer_keystore_password_file",
+                self.p2p_sequencer_keystore_password_file.is_some(),
+            ),
+            (
+                "p2p_sequencer_signer_url",
+                self.p2p_sequencer_signer_url.is_some(),
+            ),
+            (
+                "p2p_sequencer_signer_backup_url",
+                self.p2p_sequencer_signer_backup_url.is_some(),
+            ),
+            (
+                "p2p_sequencer_address",
+                self.p2p_sequencer_address.is_some(),
+            ),
+            ("sequencer_gas_limit", self.sequencer_gas_limit.is_some()),
+            ("unsafe_follow_url", self.unsafe_follow_url.is_some()),
+            (
+                "unsafe_follow_max_lag",
+                self.unsafe_follow_max_lag.is_some(),
+            ),
+            ("p2p_static_peers", !self.p2p_static_peers.is_empty()),
+            ("p2p_bootnodes", !self.p2p_bootnodes.is_empty()),
+            ("rpc_sequencer_url", self.rpc_sequencer_url.is_some()),
+        ]
+        .into_iter()
+    }
+}
+
+/// The format of a multi-rollup TOML file
+#[derive(Debug, Clone, Deserialize)]
+struct RollupsFile {
+    /// The rollups to run
+    rollup: Vec<RollupConfig>,
+}
+
+/// The L2 settings of a single rollup when running multiple rollups in one process
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RollupConfig {
+    /// The network name, or the path to a `rollup.json` or superchain registry TOML file
+    pub network: String,
+    /// The L2 execution client RPC
+    pub l2_rpc_url: String,
+    /// The L2 engine RPC
+    pub l2_engine_url: String,
+    /// Standby L2 engine RPCs
+    #[serde(default)]
+    pub l2_engine_standby_urls: Vec<String>,
+    /// The JWT secret used to authenticate with the engine
+    #[serde(default)]
+    pub jwt_secret: Option<String>,
+    /// Path to the engine API JWT secret file, reloaded when the secret is rotated
+    #[serde(default)]
+    pub jwt_secret_file: Option<String>,
+    /// The port to serve the Magi RPC of this rollup on
+    pub rpc_port: u16,
+    /// The port to listen on for p2p networking
+    pub p2p_port: u16,
+    /// A trusted L2 RPC used to obtain data from when using checkpoint sync mode
+    #[serde(default)]
+    pub checkpoint_sync_url: Option<String>,
+    /// The supervisor RPC used to validate interop executing messages
+    #[serde(default)]
+    pub supervisor_rpc_url: Option<String>,
+    /// Directory to persist the state of this rollup in
+    #[serde(default)]
+    pub data_dir: Option<String>,
+    /// Multiaddrs of p2p peers that are always dialed and never pruned
+    #[serde(default)]
+    pub p2p_static_peers: Vec<String>,
+    /// Node records replacing the default p2p bootnodes
+    #[serde(default)]
+    pub p2p_bootnodes: Vec<String>,
+    /// Whether to run as the sequencer of this rollup
+    #[serde(default)]
+    pub sequencer: bool,
+    /// Hex encoded private key signing the unsafe blocks gossiped by the sequencer
+    #[serde(default)]
+    pub p2p_sequencer_key: Option<String>,
+    /// Path to an encrypted JSON keystore holding the sequencer key
+    #[serde(default)]
+    pub p2p_sequencer_keystore: Option<String>,
+    /// Path to a file holding the password of the sequencer keystore
+    #[serde(default)]
+    pub p2p_sequencer_keystore_password_file: Option<String>,
+    /// Sign endpoint of a remote signer holding the sequencer key
+    #[serde(default)]
+    pub p2p_sequencer_signer_url: Option<String>,
+    /// Sign endpoint of a backup remote signer holding the same sequencer key
+    #[serde(default)]
+    pub p2p_sequencer_signer_backup_url: Option<String>,
+    /// Address of the sequencer key held by the remote signer
+    #[serde(default)]
+    pub p2p_sequencer_address: Option<Address>,
+    /// The gas limit of the blocks built by the sequencer, until the gas limit is updated on L1
+    #[serde(default)]
+    pub sequencer_gas_limit: Option<u64>,
+    /// An L2 RPC trusted to serve the unsafe head of the sequencer, followed ahead of derivation
+    #[serde(default)]
+    pub unsafe_follow_url: Option<String>,
+    /// Pauses following the trusted RPC while the safe head is more than this many blocks behind the unsafe head
+    #[serde(default)]
+    pub unsafe_follow_max_lag: Option<u64>,
+    /// The sequencer RPC that `eth_sendRawTransaction` is forwarded to
+    #[serde(default)]
+    pub rpc_sequencer_url: Option<String>,
+}
+
+impl RollupConfig {
+    /// Builds the [Config] of this rollup on top of the shared L1 settings
+    fn into_config(self, shared: &Config) -> Config {
+        Config {
+            chain: ChainConfig::from_network_name(&self.network),
+            l2_rpc_url: self.l2_rpc_url,
+            l2_engine_url: self.l2_engine_url,
+            l2_engine_standby_urls: self.l2_engine_standby_urls,
+            jwt_secret: self.jwt_secret,
+            jwt_secret_file: self.jwt_secret_file,
+            rpc_port: self.rpc_port,
+            p2p_port: self.p2p_port,
+            checkpoint_sync_url: self.checkpoint_sync_url,
+            supervisor_rpc_url: self.supervisor_rpc_url,
+            data_dir: self.data_dir,
+            p2p_static_peers: self.p2p_static_peers,
+            p2p_bootnodes: self.p2p_bootnodes,
+            sequencer: self.sequencer,
+            p2p_sequencer_key: self.p2p_sequencer_key,
+            p2p_sequencer_keystore: self.p2p_sequencer_keystore,
+            p2p_sequencer_keystore_password_file: self.p2p_sequencer_keystore_password_file,
+            p2p_sequencer_signer_url: self.p2p_sequencer_signer_url,
+            p2p_sequencer_signer_backup_url: self.p2p_sequencer_signer_backup_url,
+            p2p_sequencer_address: self.p2p_sequencer_address,
+            sequencer_gas_limit: self.sequencer_gas_limit,
+            unsafe_follow_url: self.unsafe_follow_url,
+            unsafe_follow_max_lag: self.unsafe_follow_max_lag,
+            rpc_sequencer_url: self.rpc_sequencer_url,
+            ..shared.clone()
         }
     }
 }
@@ -125,6 +512,9 @@ pub struct CliConfig {
     /// The L2 engine RPC
     #[serde(skip_serializing_if = "Option::is_none")]
     pub l2_engine_url: Option<String>,
+    /// Standby L2 engine RPCs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub l2_engine_standby_urls: Option<Vec<String>>,
     /// The JWT secret used to authenticate with the engine
     #[serde(skip_serializing_if = "Option::is_none")]
     pub jwt_secret: Option<String>,
@@ -140,6 +530,144 @@ pub struct CliConfig {
     /// If Magi is running in devnet mode.
     #[serde(default)]
     pub devnet: bool,
+    /// The supervisor RPC used to validate interop executing messages.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub supervisor_rpc_url: Option<String>,
+    /// The port to listen on for p2p networking.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p2p_port: Option<u16>,
+    /// Maximum number of safe blocks applied before a coalesced forkchoice update is sent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub forkchoice_batch_size: Option<u64>,
+    /// Maximum delay in milliseconds before a coalesced forkchoice update is sent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub forkchoice_batch_interval_ms: Option<u64>,
+    /// Path to the engine API JWT secret file, reloaded when the secret is rotated
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jwt_secret_file: Option<String>,
+    /// Compute units per second that may be spent on L1 RPC requests. Unlimited if unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub l1_rpc_budget: Option<u32>,
+    /// Directory to persist node state in. State is only kept in memory if unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data_dir: Option<String>,
+    /// Number of L1 epochs before the safe epoch kept in memory. Defaults to the sequencing window size.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub l1_epoch_retention: Option<u64>,
+    /// Number of L2 block refs before the safe head kept in memory. Defaults to the maximum sequencer drift in blocks.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub l2_block_retention: Option<u64>,
+    /// Request missing unsafe blocks from peers with the req/resp protocol
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p2p_sync: Option<bool>,
+    /// Maximum number of inbound p2p connections
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p2p_max_inbound_peers: Option<usize>,
+    /// Maximum number of outbound p2p connections
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p2p_max_outbound_peers: Option<usize>,
+    /// Multiaddrs of p2p peers that are always dialed and never pruned
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p2p_static_peers: Option<Vec<String>>,
+    /// Node records replacing the default p2p bootnodes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p2p_bootnodes: Option<Vec<String>>,
+    /// Whether to run as the sequencer, building and gossiping unsafe blocks
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sequencer: Option<bool>,
+    /// Hex encoded private key signing the unsafe blocks gossiped by the sequencer
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p2p_sequencer_key: Option<String>,
+    /// Path to an encrypted JSON keystore holding the sequencer key
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p2p_sequencer_keystore: Option<String>,
+    /// Path to a file holding the password of the sequencer keystore
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p2p_sequencer_keystore_password_file: Option<String>,
+    /// Sign endpoint of a remote signer holding the sequencer key
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p2p_sequencer_signer_url: Option<String>,
+    /// Sign endpoint of a backup remote signer holding the same sequencer key
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p2p_sequencer_signer_backup_url: Option<String>,
+    /// Address of the sequencer key held by the remote signer
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p2p_sequencer_address: Option<Address>,
+    /// Whether to periodically report the derivation throughput, time per stage and allocations
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile_derivation: Option<bool>,
+    /// An L2 RPC trusted to serve the unsafe head of the sequencer, followed ahead of derivation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unsafe_follow_url: Option<String>,
+    /// Pauses following the trusted RPC while the safe head is more than this many blocks behind the unsafe head
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unsafe_follow_max_lag: Option<u64>,
+    /// Maximum seconds the unsafe head may trail the current time for the node to be ready
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub readiness_max_head_lag: Option<u64>,
+    /// Maximum seconds without derivation ingesting a new L1 block for the node to be healthy and ready
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub readiness_max_stall: Option<u64>,
+    /// The RPC namespaces to serve
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rpc_api: Option<Vec<String>>,
+    /// A bearer token required in the Authorization header of RPC requests
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rpc_auth_token: Option<String>,
+    /// A hex encoded secret of JWTs required in the Authorization header of RPC requests
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rpc_jwt_secret: Option<String>,
+    /// Origins allowed to call the RPC server from a browser, or `*` for any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rpc_cors_origins: Option<Vec<String>>,
+    /// Maximum RPC requests per second from a single IP address
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rpc_rate_limit: Option<u32>,
+    /// Maximum number of fetched L1 blocks waiting for derivation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub l1_block_buffer: Option<usize>,
+    /// Whether to start in light-follow mode, trusting the unsafe heads and pausing L1 derivation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub light_follow: Option<bool>,
+    /// Maximum number of channels decoded ahead of derivation in the background. 0 disables the lookahead.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub derivation_lookahead: Option<usize>,
+    /// The gas limit of the blocks built by the sequencer, until the gas limit is updated on L1
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sequencer_gas_limit: Option<u64>,
+    /// Whether to compute and cache the output root of every new safe block for `optimism_outputAtBlock`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rpc_precompute_outputs: Option<bool>,
+    /// Seconds without progress before the watchdog reports a driver component as stalled, or 0 to disable it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub watchdog_stall_timeout: Option<u64>,
+    /// What the watchdog does once a driver component stalls
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub watchdog_action: Option<WatchdogAction>,
+    /// The sequencer RPC that `eth_sendRawTransaction` is forwarded to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rpc_sequencer_url: Option<String>,
+    /// Maximum number of transactions forwarded to the sequencer per second
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rpc_sequencer_rate_limit: Option<u32>,
+    /// Seconds an iteration of the driver loop may take, bounding the external calls it makes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub driver_tick_timeout: Option<u64>,
+    /// Whether to validate and hash derived blocks without sending them to an execution client
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dry_run: Option<bool>,
+    /// The L2 block after which a dry run stops
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dry_run_until: Option<u64>,
+    /// Number of the latest unsafe payloads kept in the data directory to restore the unsafe head on restart
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unsafe_payload_retention: Option<u64>,
+    /// Maximum number of frames pushed into channels by a single step of the derivation pipeline. 0 removes the bound.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub derivation_step_frames: Option<usize>,
+    /// Whether to halt derivation at the first block past a hardfork whose rules are not implemented
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub halt_on_unsupported_fork: Option<bool>,
 }
 
 /// Configurations for a blockchain.
@@ -159,6 +687,9 @@ pub struct ChainConfig {
     pub system_config: SystemConfig,
     /// The batch inbox address
     pub batch_inbox: Address,
+    /// Batch inbox addresses replacing `batch_inbox` from their L1 block, ordered by block
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub batch_inbox_rotations: Vec<BatchInboxRotation>,
     /// The deposit contract address
     pub deposit_contract: Address,
     /// The L1 system config contract address
@@ -171,19 +702,36 @@ pub struct ChainConfig {
     pub seq_window_size: u64,
     /// Maximum timestamp drift
     pub max_seq_drift: u64,
-    /// Timestamp of the regolith hardfork
-    pub regolith_time: u64,
-    /// Timestamp of the canyon hardfork
-    pub canyon_time: u64,
-    /// Timestamp of the delta hardfork
-    pub delta_time: u64,
-    /// Timestamp of the ecotone hardfork
-    pub ecotone_time: u64,
+    /// The hardfork activation timestamps
+    #[serde(flatten)]
+    pub hardforks: HardforkSchedule,
+    /// Chain ids which can send messages to this chain after the interop hardfork
+    #[serde(default)]
+    pub interop_dependency_set: Vec<u64>,
     /// Network blocktime
     #[serde(default = "default_blocktime")]
     pub blocktime: u64,
     /// L2 To L1 Message passer address
     pub l2_to_l1_message_passer: Address,
+    /// The L2 system accounts and predeploys, overridable for chains deploying them at
+    /// nonstandard addresses
+    #[serde(default)]
+    pub system_accounts: SystemAccounts,
+    /// The L1 protocol versions contract address. Monitoring is disabled if unset.
+    #[serde(default)]
+    pub protocol_versions_contract: Address,
+    /// The EIP-1559 parameters of the L2 chain
+    #[serde(default)]
+    pub chain_op_config: ChainOpConfig,
+}
+
+/// A batch inbox address taking over from an L1 block, for chains rotating their inbox
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchInboxRotation {
+    /// The first L1 block whose batcher transactions are sent to the address
+    pub l1_block: u64,
+    /// The batch inbox address
+    pub address: Address,
 }
 
 impl Default for ChainConfig {
@@ -193,6 +741,137 @@ impl Default for ChainConfig {
     }
 }
 
+/// Activation timestamps of the L2 hardforks. Hardforks without a timestamp are not
+/// scheduled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HardforkSchedule {
+    /// Timestamp of the regolith hardfork
+    pub regolith_time: u64,
+    /// Timestamp of the canyon hardfork
+    pub canyon_time: u64,
+    /// Timestamp of the delta hardfork
+    pub delta_time: u64,
+    /// Timestamp of the ecotone hardfork
+    pub ecotone_time: u64,
+    /// Timestamp of the fjord hardfork, if scheduled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fjord_time: Option<u64>,
+    /// Timestamp of the granite hardfork, if scheduled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub granite_time: Option<u64>,
+    /// Timestamp of the holocene hardfork, if scheduled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub holocene_time: Option<u64>,
+    /// Timestamp of the isthmus hardfork, if scheduled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub isthmus_time: Option<u64>,
+    /// Timestamp of the interop hardfork, if scheduled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interop_time: Option<u64>,
+}
+
+impl HardforkSchedule {
+    /// Returns true if the regolith hardfork is active at the given timestamp
+    pub fn is_regolith_active(&self, timestamp: u64) -> bool {
+        timestamp >= self.regolith_time
+    }
+
+    /// Returns true if the canyon hardfork is active at the given timestamp
+    pub fn is_canyon_active(&self, timestamp: u64) -> bool {
+        timestamp >= self.canyon_time
+    }
+
+    /// Returns true if the delta hardfork is active at the given timestamp
+    pub fn is_delta_active(&self, timestamp: u64) -> bool {
+        timestamp >= self.delta_time
+    }
+
+    /// Returns true if the ecotone hardfork is active at the given timestamp
+    pub fn is_ecotone_active(&self, timestamp: u64) -> bool {
+        timestamp >= self.ecotone_time
+    }
+
+    /// Returns true if the fjord hardfork is active at the given timestamp
+    pub fn is_fjord_active(&self, timestamp: u64) -> bool {
+        is_active(self.fjord_time, timestamp)
+    }
+
+    /// Returns true if the granite hardfork is active at the given timestamp
+    pub fn is_granite_active(&self, timestamp: u64) -> bool {
+        is_active(self.granite_time, timestamp)
+    }
+
+    /// Returns true if the holocene hardfork is active at the given timestamp
+    pub fn is_holocene_active(&self, timestamp: u64) -> bool {
+        is_active(self.holocene_time, timestamp)
+    }
+
+    /// Returns true if the isthmus hardfork is active at the given timestamp
+    pub fn is_isthmus_active(&self, timestamp: u64) -> bool {
+        is_active(self.isthmus_time, timestamp)
+    }
+
+    /// Returns true if the interop hardfork is active at the given timestamp
+    pub fn is_interop_active(&self, timestamp: u64) -> bool {
+        is_active(self.interop_time, timestamp)
+    }
+
+    /// Returns the name of the first hardfork active at the given timestamp whose
+    /// derivation rules are not fully implemented, if any. Blocks past such a hardfork may
+    /// differ from op-node: fjord brotli channels and upgrade transactions, holocene batch
+    /// rules and EIP-1559 parameters, and the isthmus upgrade transactions are not
+    /// supported.
+    pub fn unsupported_fork(&self, timestamp: u64) -> Option<&'static str> {
+        self.unsupported_forks()
+            .find(|(_, time)| is_active(*time, timestamp))
+            .map(|(name, _)| name)
+    }
+
+    /// Returns the names of the scheduled hardforks whose derivation rules are not fully
+    /// implemented, see [HardforkSchedule::unsupported_fork]
+    pub fn scheduled_unsupported_forks(&self) -> Vec<&'static str> {
+        self.unsupported_forks()
+            .filter(|(_, time)| time.is_some())
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    /// Returns the hardforks whose derivation rules are not fully implemented, with their
+    /// timestamps
+    fn unsupported_forks(&self) -> impl Iterator<Item = (&'static str, Option<u64>)> {
+        [
+            ("fjord", self.fjord_time),
+            ("granite", self.granite_time),
+            ("holocene", self.holocene_time),
+            ("isthmus", self.isthmus_time),
+        ]
+        .into_iter()
+    }
+
+    /// Returns true if the block is the first block subject to the Ecotone hardfork
+    pub fn is_ecotone_activation_block(&self, l2_block_timestamp: u64) -> bool {
+        l2_block_timestamp == self.ecotone_time
+    }
+
+    /// Returns true if Ecotone hardfork is active but the block is not the
+    /// first block subject to the hardfork. Ecotone activation at genesis does not count.
+    pub fn is_ecotone_but_not_first_block(&self, l2_block_timestamp: u64) -> bool {
+        self.is_ecotone_active(l2_block_timestamp)
+            && !self.is_ecotone_activation_block(l2_block_timestamp)
+    }
+
+    /// Returns true if the isthmus hardfork is active but the block is not the first block
+    /// subject to the hardfork, which still carries the ecotone L1 info deposit
+    pub fn is_isthmus_but_not_first_block(&self, l2_block_timestamp: u64) -> bool {
+        self.is_isthmus_active(l2_block_timestamp) && self.isthmus_time != Some(l2_block_timestamp)
+    }
+}
+
+/// Returns true if a hardfork with the given optional activation timestamp is active
+fn is_active(activation: Option<u64>, timestamp: u64) -> bool {
+    activation.is_some_and(|time| timestamp >= time)
+}
+
 /// Optimism system config contract values
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct SystemConfig {
@@ -206,6 +885,9 @@ pub struct SystemConfig {
     pub l1_fee_scalar: U256,
     /// Sequencer's signer for unsafe blocks
     pub unsafe_block_signer: Address,
+    /// Operator fee scalar and constant, packed as in the `OPERATOR_FEE_PARAMS` update
+    #[serde(default)]
+    pub operator_fee_params: U256,
 }
 
 impl SystemConfig {
@@ -216,10 +898,42 @@ impl SystemConfig {
         batcher_hash.append(&mut batch_sender_bytes);
         B256::from_slice(&batcher_hash)
     }
+
+    /// Returns the base fee scalar and blob base fee scalar of the L1 cost function
+    /// after the ecotone hardfork. Version 1 scalars pack both, while version 0 scalars only
+    /// hold the base fee scalar in their last 4 bytes. Fails on other versions, and on
+    /// scalars with bytes set outside of the fields of their version.
+    pub fn ecotone_scalars(&self) -> eyre::Result<(u32, u32)> {
+        let scalar: [u8; 32] = self.l1_fee_scalar.to_be_bytes();
+        let word = |start: usize| u32::from_be_bytes(scalar[start..start + 4].try_into().unwrap());
+
+        let unused = match scalar[0] {
+            0 => &scalar[1..28],
+            1 => &scalar[1..24],
+            version => eyre::bail!("unknown ecotone scalars version {}", version),
+        };
+
+        if unused.iter().any(|byte| *byte != 0) {
+            eyre::bail!("invalid version {} ecotone scalars", scalar[0]);
+        }
+
+        let blob_base_fee_scalar = if scalar[0] == 1 { word(24) } else { 0 };
+        Ok((word(28), blob_base_fee_scalar))
+    }
+
+    /// Returns the operator fee scalar and constant after the isthmus hardfork, packed in
+    /// bytes 20 to 24 and 24 to 32 of the operator fee params
+    pub fn operator_fee(&self) -> (u32, u64) {
+        let params: [u8; 32] = self.operator_fee_params.to_be_bytes();
+        let scalar = u32::from_be_bytes(params[20..24].try_into().unwrap());
+        let constant = u64::from_be_bytes(params[24..32].try_into().unwrap());
+        (scalar, constant)
+    }
 }
 
-/// System accounts
-#[derive(Debug, Clone)]
+/// System accounts. Unset fields default to the standard OP Stack addresses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct SystemAccounts {
     /// The address that submits attributes deposited transactions in every L2 block
     pub attributes_depositor: Address,
@@ -229,6 +943,19 @@ pub struct SystemAccounts {
     pub fee_vault: Address,
 }
 
+/// The EIP-1559 parameters of an L2 chain. Unset fields default to the standard OP Stack
+/// values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ChainOpConfig {
+    /// The maximum gas limit of a block divided by its gas target
+    pub eip1559_elasticity: u64,
+    /// The denominator bounding the base fee change between blocks
+    pub eip1559_denominator: u64,
+    /// The base fee change denominator after the canyon hardfork
+    pub eip1559_denominator_canyon: u64,
+}
+
 /// Wrapper around a [ChainConfig]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct ChainProvider {
@@ -252,6 +979,50 @@ struct DefaultsProvider {
     /// The port to serve the Magi RPC server on
     rpc_port: u16,
     rpc_addr: String,
+    /// The port to listen on for p2p networking
+    p2p_port: u16,
+    /// Maximum number of safe blocks applied before a coalesced forkchoice update is sent
+    forkchoice_batch_size: u64,
+    /// Maximum delay in milliseconds before a coalesced forkchoice update is sent
+    forkchoice_batch_interval_ms: u64,
+    /// Request missing unsafe blocks from peers with the req/resp protocol
+    p2p_sync: bool,
+    /// Maximum number of inbound p2p connections
+    p2p_max_inbound_peers: usize,
+    /// Maximum number of outbound p2p connections
+    p2p_max_outbound_peers: usize,
+    /// Whether to run as the sequencer, building and gossiping unsafe blocks
+    sequencer: bool,
+    /// Whether to periodically report the derivation throughput, time per stage and allocations
+    profile_derivation: bool,
+    /// Maximum seconds the unsafe head may trail the current time for the node to be ready
+    readiness_max_head_lag: u64,
+    /// Maximum seconds without derivation ingesting a new L1 block for the node to be healthy and ready
+    readiness_max_stall: u64,
+    /// The RPC namespaces to serve
+    rpc_api: Vec<String>,
+    /// Maximum number of fetched L1 blocks waiting for derivation
+    l1_block_buffer: usize,
+    /// Whether to start in light-follow mode, trusting the unsafe heads and pausing L1 derivation
+    light_follow: bool,
+    /// Maximum number of channels decoded ahead of derivation in the background. 0 disables the lookahead.
+    derivation_lookahead: usize,
+    /// Whether to compute and cache the output root of every new safe block for `optimism_outputAtBlock`
+    rpc_precompute_outputs: bool,
+    /// Seconds without progress before the watchdog reports a driver component as stalled, or 0 to disable it
+    watchdog_stall_timeout: u64,
+    /// What the watchdog does once a driver component stalls
+    watchdog_action: WatchdogAction,
+    /// Seconds an iteration of the driver loop may take, bounding the external calls it makes
+    driver_tick_timeout: u64,
+    /// Whether to validate and hash derived blocks without sending them to an execution client
+    dry_run: bool,
+    /// Number of the latest unsafe payloads kept in the data directory to restore the unsafe head on restart
+    unsafe_payload_retention: u64,
+    /// Maximum number of frames pushed into channels by a single step of the derivation pipeline. 0 removes the bound.
+    derivation_step_frames: usize,
+    /// Whether to halt derivation at the first block past a hardfork whose rules are not implemented
+    halt_on_unsupported_fork: bool,
 }
 
 impl Default for DefaultsProvider {
@@ -262,6 +1033,28 @@ impl Default for DefaultsProvider {
             l2_engine_url: "http://127.0.0.1:8551".to_string(),
             rpc_port: 9545,
             rpc_addr: "127.0.0.1".to_string(),
+            p2p_port: 9876,
+            forkchoice_batch_size: 16,
+            forkchoice_batch_interval_ms: 200,
+            p2p_sync: false,
+            p2p_max_inbound_peers: DEFAULT_MAX_INBOUND_PEERS,
+            p2p_max_outbound_peers: DEFAULT_MAX_OUTBOUND_PEERS,
+            sequencer: false,
+            profile_derivation: false,
+            readiness_max_head_lag: 60,
+            readiness_max_stall: 120,
+            rpc_api: vec!["optimism".to_string(), "opp2p".to_string()],
+            l1_block_buffer: 64,
+            light_follow: false,
+            derivation_lookahead: 16,
+            rpc_precompute_outputs: false,
+            watchdog_stall_timeout: 180,
+            watchdog_action: WatchdogAction::Log,
+            driver_tick_timeout: 30,
+            dry_run: false,
+            unsafe_payload_retention: 256,
+            derivation_step_frames: 64,
+            halt_on_unsupported_fork: false,
         }
     }
 }
@@ -274,6 +1067,14 @@ impl ChainConfig {
         external.into()
     }
 
+    /// Read and parse the [ChainConfig] from a chain TOML file of the superchain registry,
+    /// with the `superchain.toml` of its superchain next to it
+    pub fn from_registry_toml(path: &str) -> Self {
+        registry::RegistryChain::read(Path::new(path))
+            .and_then(|chain| chain.chain_config())
+            .unwrap_or_else(|err| panic!("invalid registry chain {}: {}", path, err))
+    }
+
     /// Generates a [ChainConfig] instance from a given network name.
     pub fn from_network_name(network: &str) -> Self {
         match network.to_lowercase().as_str() {
@@ -282,25 +1083,123 @@ impl ChainConfig {
             "base" => Self::base(),
             "base-sepolia" => Self::base_sepolia(),
             file if file.ends_with(".json") => Self::from_json(file),
+            _ if network.ends_with(".toml") => Self::from_registry_toml(network),
             _ => panic!(
                 "Invalid network name. \\
             Please use one of the following: 'optimism', 'optimism-sepolia', 'base-sepolia', 'base'. \\
-            You can also use a JSON file path for custom configuration."
+            You can also use a JSON file path for custom configuration, or the name or path \\
+            of a superchain registry TOML file."
             ),
         }
     }
 
-    /// Returns true if the block is the first block subject to the Ecotone hardfork
-    pub fn is_ecotone_activation_block(&self, l2_block_timestamp: u64) -> bool {
-        l2_block_timestamp == self.ecotone_time
+    /// Generates a [ChainConfig] instance from a given network name, looking up names which
+    /// are neither a preset nor a file in the registry TOML files of `chains_dir`, see
+    /// [registry::find_chain]
+    pub fn from_network(network: &str, chains_dir: &Path) -> Self {
+        let is_preset = NETWORKS.contains(&network.to_lowercase().as_str());
+        if is_preset || network.ends_with(".json") || network.ends_with(".toml") {
+            return Self::from_network_name(network);
+        }
+
+        match registry::find_chain(chains_dir, network) {
+            Ok(Some(path)) => Self::from_registry_toml(&path.to_string_lossy()),
+            Ok(None) => Self::from_network_name(network),
+            Err(err) => panic!("{}", err),
+        }
     }
 
-    /// Returns true if Ecotone hardfork is active but the block is not the
-    /// first block subject to the hardfork. Ecotone activation at genesis does not count.
-    pub fn is_ecotone_but_not_first_block(&self, l2_block_timestamp: u64) -> bool {
-        let is_ecotone = l2_block_timestamp >= self.ecotone_time;
+    /// Returns the maximum total byte size of the pending channels at the given L1
+    /// timestamp, which the fjord hardfork raises to [FJORD_MAX_CHANNEL_BANK_SIZE]
+    pub fn max_channel_bank_size(&self, l1_timestamp: u64) -> u64 {
+        if self.hardforks.is_fjord_active(l1_timestamp) {
+            FJORD_MAX_CHANNEL_BANK_SIZE
+        } else {
+            self.max_channel_size
+        }
+    }
+
+    /// Returns the maximum drift of an L2 block timestamp past the timestamp of its L1
+    /// origin, which the fjord hardfork fixes to [FJORD_MAX_SEQUENCER_DRIFT]
+    pub fn max_sequencer_drift(&self, l1_origin_timestamp: u64) -> u64 {
+        if self.hardforks.is_fjord_active(l1_origin_timestamp) {
+            FJORD_MAX_SEQUENCER_DRIFT
+        } else {
+            self.max_seq_drift
+        }
+    }
+
+    /// Returns the maximum number of L1 blocks the frames of a channel may span at the
+    /// given L1 timestamp, which the granite hardfork reduces to [GRANITE_CHANNEL_TIMEOUT]
+    pub fn max_channel_timeout(&self, l1_timestamp: u64) -> u64 {
+        if self.hardforks.is_granite_active(l1_timestamp) {
+            GRANITE_CHANNEL_TIMEOUT
+        } else {
+            self.channel_timeout
+        }
+    }
+
+    /// Returns the batch inbox address in effect at the given L1 block
+    pub fn batch_inbox_at(&self, l1_block: u64) -> Address {
+        self.batch_inbox_rotations
+            .iter()
+            .rev()
+            .find(|rotation| rotation.l1_block <= l1_block)
+            .map_or(self.batch_inbox, |rotation| rotation.address)
+    }
+
+    /// Checks that every protocol address is set, as custom chains must provide them all.
+    /// The protocol versions contract is optional.
+    pub fn validate(&self) -> eyre::Result<()> {
+        let addresses = [
+            ("batch_inbox", self.batch_inbox),
+            ("deposit_contract", self.deposit_contract),
+            ("system_config_contract", self.system_config_contract),
+            ("l2_to_l1_message_passer", self.l2_to_l1_message_passer),
+            (
+                "system_accounts.attributes_depositor",
+                self.system_accounts.attributes_depositor,
+            ),
+            (
+                "system_accounts.attributes_predeploy",
+                self.system_accounts.attributes_predeploy,
+            ),
+            ("system_accounts.fee_vault", self.system_accounts.fee_vault),
+        ];
+
+        let unset = addresses
+            .iter()
+            .filter(|(_, address)| address.is_zero())
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>();
 
-        is_ecotone && !self.is_ecotone_activation_block(l2_block_timestamp)
+        if !unset.is_empty() {
+            eyre::bail!(
+                "unset addresses for network {}: {}",
+                self.network,
+                unset.join(", ")
+            );
+        }
+
+        let rotations = &self.batch_inbox_rotations;
+        if rotations.iter().any(|rotation| rotation.address.is_zero()) {
+            eyre::bail!(
+                "unset batch inbox rotation address for network {}",
+                self.network
+            );
+        }
+
+        if rotations
+            .windows(2)
+            .any(|pair| pair[0].l1_block >= pair[1].l1_block)
+        {
+            eyre::bail!(
+                "batch inbox rotations for network {} are not ordered by L1 block",
+                self.network
+            );
+        }
+
+        Ok(())
     }
 
     /// [ChainConfig] for Optimism
@@ -335,20 +1234,33 @@ impl ChainConfig {
                 l1_fee_overhead: U256::from(188),
                 l1_fee_scalar: U256::from(684000),
                 unsafe_block_signer: addr("0xAAAA45d9549EDA09E70937013520214382Ffc4A2"),
+                operator_fee_params: U256::ZERO,
             },
             batch_inbox: addr("0xff00000000000000000000000000000000000010"),
+            batch_inbox_rotations: Vec::new(),
             deposit_contract: addr("0xbEb5Fc579115071764c7423A4f12eDde41f106Ed"),
             system_config_contract: addr("0x229047fed2591dbec1eF1118d64F7aF3dB9EB290"),
             l2_to_l1_message_passer: addr("0x4200000000000000000000000000000000000016"),
+            system_accounts: SystemAccounts::default(),
+            protocol_versions_contract: addr("0x8062AbC286f5e7D9428a0Ccb9AbD71e50d93b935"),
+            chain_op_config: ChainOpConfig::default(),
             max_channel_size: 100_000_000,
             channel_timeout: 300,
             seq_window_size: 3600,
             max_seq_drift: 600,
             blocktime: 2,
-            regolith_time: 0,
-            canyon_time: 170499240,
-            delta_time: 1708560000,
-            ecotone_time: 1710781201,
+            hardforks: HardforkSchedule {
+                regolith_time: 0,
+                canyon_time: 170499240,
+                delta_time: 1708560000,
+                ecotone_time: 1710781201,
+                fjord_time: Some(1720627201),
+                granite_time: Some(1726070401),
+                holocene_time: Some(1736445601),
+                isthmus_time: Some(1746806401),
+                interop_time: None,
+            },
+            interop_dependency_set: Vec::new(),
         }
     }
 
@@ -384,19 +1296,32 @@ impl ChainConfig {
                 l1_fee_overhead: U256::from(188),
                 l1_fee_scalar: U256::from(684000),
                 unsafe_block_signer: addr("0x57CACBB0d30b01eb2462e5dC940c161aff3230D3"),
+                operator_fee_params: U256::ZERO,
             },
             system_config_contract: addr("0x034edd2a225f7f429a63e0f1d2084b9e0a93b538"),
             batch_inbox: addr("0xff00000000000000000000000000000011155420"),
+            batch_inbox_rotations: Vec::new(),
             deposit_contract: addr("0x16fc5058f25648194471939df75cf27a2fdc48bc"),
             l2_to_l1_message_passer: addr("0x4200000000000000000000000000000000000016"),
+            system_accounts: SystemAccounts::default(),
+            protocol_versions_contract: addr("0x79ADD5713B383DAa0a138d3C4780C7A1804a8090"),
+            chain_op_config: ChainOpConfig::default(),
             max_channel_size: 100_000_000,
             channel_timeout: 300,
             seq_window_size: 3600,
             max_seq_drift: 600,
-            regolith_time: 0,
-            canyon_time: 1699981200,
-            delta_time: 1703203200,
-            ecotone_time: 1708534800,
+            hardforks: HardforkSchedule {
+                regolith_time: 0,
+                canyon_time: 1699981200,
+                delta_time: 1703203200,
+                ecotone_time: 1708534800,
+                fjord_time: Some(1716998400),
+                granite_time: Some(1723478400),
+                holocene_time: Some(1732633200),
+                isthmus_time: Some(1744905600),
+                interop_time: None,
+            },
+            interop_dependency_set: Vec::new(),
             blocktime: 2,
         }
     }
@@ -430,20 +1355,33 @@ impl ChainConfig {
                 l1_fee_overhead: U256::from(188),
                 l1_fee_scalar: U256::from(684000),
                 unsafe_block_signer: addr("0xAf6E19BE0F9cE7f8afd49a1824851023A8249e8a"),
+                operator_fee_params: U256::ZERO,
             },
             batch_inbox: addr("0xff00000000000000000000000000000000008453"),
+            batch_inbox_rotations: Vec::new(),
             deposit_contract: addr("0x49048044d57e1c92a77f79988d21fa8faf74e97e"),
             system_config_contract: addr("0x73a79fab69143498ed3712e519a88a918e1f4072"),
             l2_to_l1_message_passer: addr("0x4200000000000000000000000000000000000016"),
+            system_accounts: SystemAccounts::default(),
+            protocol_versions_contract: addr("0x8062AbC286f5e7D9428a0Ccb9AbD71e50d93b935"),
+            chain_op_config: ChainOpConfig::default(),
             max_channel_size: 100_000_000,
             channel_timeout: 300,
             seq_window_size: 3600,
             max_seq_drift: 600,
             blocktime: 2,
-            regolith_time: 0,
-            canyon_time: 1704992401,
-            delta_time: 1708560000,
-            ecotone_time: 1710781201,
+            hardforks: HardforkSchedule {
+                regolith_time: 0,
+                canyon_time: 1704992401,
+                delta_time: 1708560000,
+                ecotone_time: 1710781201,
+                fjord_time: Some(1720627201),
+                granite_time: Some(1726070401),
+                holocene_time: Some(1736445601),
+                isthmus_time: Some(1746806401),
+                interop_time: None,
+            },
+            interop_dependency_set: Vec::new(),
         }
     }
 
@@ -476,19 +1414,32 @@ impl ChainConfig {
                 l1_fee_overhead: U256::from(2100),
                 l1_fee_scalar: U256::from(1000000),
                 unsafe_block_signer: addr("0xb830b99c95Ea32300039624Cb567d324D4b1D83C"),
+                operator_fee_params: U256::ZERO,
             },
             system_config_contract: addr("0xf272670eb55e895584501d564AfEB048bEd26194"),
             batch_inbox: addr("0xff00000000000000000000000000000000084532"),
+            batch_inbox_rotations: Vec::new(),
             deposit_contract: addr("0x49f53e41452C74589E85cA1677426Ba426459e85"),
             l2_to_l1_message_passer: addr("0x4200000000000000000000000000000000000016"),
+            system_accounts: SystemAccounts::default(),
+            protocol_versions_contract: addr("0x79ADD5713B383DAa0a138d3C4780C7A1804a8090"),
+            chain_op_config: ChainOpConfig::default(),
             max_channel_size: 100_000_000,
             channel_timeout: 300,
             seq_window_size: 3600,
             max_seq_drift: 600,
-            regolith_time: 0,
-            canyon_time: 1699981200,
-            delta_time: 1703203200,
-            ecotone_time: 1708534800,
+            hardforks: HardforkSchedule {
+                regolith_time: 0,
+                canyon_time: 1699981200,
+                delta_time: 1703203200,
+                ecotone_time: 1708534800,
+                fjord_time: Some(1716998400),
+                granite_time: Some(1723478400),
+                holocene_time: Some(1732633200),
+                isthmus_time: Some(1744905600),
+                interop_time: None,
+            },
+            interop_dependency_set: Vec::new(),
             blocktime: 2,
         }
     }
@@ -505,6 +1456,17 @@ impl Default for SystemAccounts {
     }
 }
 
+impl Default for ChainOpConfig {
+    /// The standard OP Stack parameters
+    fn default() -> Self {
+        Self {
+            eip1559_elasticity: 6,
+            eip1559_denominator: 50,
+            eip1559_denominator_canyon: 250,
+        }
+    }
+}
+
 /// Converts a [str] to an [Address]
 fn addr(s: &str) -> Address {
     Address::from_str(s).unwrap()
@@ -541,20 +1503,30 @@ pub struct ExternalChainConfig {
     l1_chain_id: u64,
     /// The L2 chain id
     l2_chain_id: u64,
-    /// Timestamp of the regolith hardfork
-    regolith_time: u64,
-    /// Timestamp of the canyon hardfork
-    canyon_time: u64,
-    /// Timestamp of the delta hardfork
-    delta_time: u64,
-    /// Timestamp of the ecotone hardfork
-    ecotone_time: u64,
+    /// The hardfork activation timestamps
+    #[serde(flatten)]
+    hardforks: HardforkSchedule,
     /// The batch inbox address
     batch_inbox_address: Address,
+    /// Batch inbox addresses replacing the batch inbox address from their L1 block
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    batch_inbox_rotations: Vec<BatchInboxRotation>,
     /// The deposit contract address
     deposit_contract_address: Address,
     /// The L1 system config contract address
     l1_system_config_address: Address,
+    /// The L1 protocol versions contract address
+    #[serde(default)]
+    protocol_versions_address: Address,
+    /// The L2 to L1 message passer address, if not the standard predeploy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    l2_to_l1_message_passer_address: Option<Address>,
+    /// The L2 system accounts and predeploys, if not the standard ones
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    system_accounts: Option<SystemAccounts>,
+    /// The EIP-1559 parameters of the L2 chain
+    #[serde(default)]
+    chain_op_config: ChainOpConfig,
 }
 
 /// The Genesis property of the `rollup.json` file used in `op-node`.
@@ -618,20 +1590,25 @@ impl From<ExternalChainConfig> for ChainConfig {
                 l1_fee_overhead: U256::from_be_bytes(external.genesis.system_config.overhead.0),
                 l1_fee_scalar: U256::from_be_bytes(external.genesis.system_config.scalar.0),
                 unsafe_block_signer: Address::ZERO,
+                operator_fee_params: U256::ZERO,
             },
             batch_inbox: external.batch_inbox_address,
+            batch_inbox_rotations: external.batch_inbox_rotations,
             deposit_contract: external.deposit_contract_address,
             system_config_contract: external.l1_system_config_address,
             max_channel_size: 100_000_000,
             channel_timeout: external.channel_timeout,
             seq_window_size: external.seq_window_size,
             max_seq_drift: external.max_sequencer_drift,
-            regolith_time: external.regolith_time,
-            canyon_time: external.canyon_time,
-            delta_time: external.delta_time,
-            ecotone_time: external.ecotone_time,
+            hardforks: external.hardforks,
+            interop_dependency_set: Vec::new(),
             blocktime: external.block_time,
-            l2_to_l1_message_passer: addr("0x4200000000000000000000000000000000000016"),
+            l2_to_l1_message_passer: external
+                .l2_to_l1_message_passer_address
+                .unwrap_or(addr("0x4200000000000000000000000000000000000016")),
+            system_accounts: external.system_accounts.unwrap_or_default(),
+            protocol_versions_contract: external.protocol_versions_address,
+            chain_op_config: external.chain_op_config,
         }
     }
 }
@@ -668,13 +1645,17 @@ impl From<ChainConfig> for ExternalChainConfig {
             channel_timeout: chain_config.channel_timeout,
             l1_chain_id: chain_config.l1_chain_id,
             l2_chain_id: chain_config.l2_chain_id,
-            regolith_time: chain_config.regolith_time,
-            canyon_time: chain_config.canyon_time,
-            delta_time: chain_config.delta_time,
-            ecotone_time: chain_config.ecotone_time,
+            hardforks: chain_config.hardforks,
             batch_inbox_address: chain_config.batch_inbox,
+            batch_inbox_rotations: chain_config.batch_inbox_rotations,
             deposit_contract_address: chain_config.deposit_contract,
             l1_system_config_address: chain_config.system_config_contract,
+            protocol_versions_address: chain_config.protocol_versions_contract,
+            l2_to_l1_message_passer_address: Some(chain_config.l2_to_l1_message_passer)
+                .filter(|address| *address != addr("0x4200000000000000000000000000000000000016")),
+            system_accounts: Some(chain_config.system_accounts)
+                .filter(|accounts| *accounts != SystemAccounts::default()),
+            chain_op_config: chain_config.chain_op_config,
         }
     }
 }
@@ -703,7 +1684,7 @@ mod test {
         assert_eq!(external_config.l1_chain_id, chain_config.l1_chain_id);
         assert_eq!(external_config.l2_chain_id, chain_config.l2_chain_id);
         assert_eq!(external_config.block_time, chain_config.blocktime);
-        assert_eq!(external_config.regolith_time, chain_config.regolith_time);
+        assert_eq!(external_config.hardforks, chain_config.hardforks);
         assert_eq!(
             external_config.batch_inbox_address,
             chain_config.batch_inbox
@@ -787,6 +1768,7 @@ mod test {
             "canyon_time": 2,
             "delta_time": 3,
             "ecotone_time": 4,
+            "fjord_time": 5,
             "batch_inbox_address": "0xff00000000000000000000000000000000000000",
             "deposit_contract_address": "0x6900000000000000000000000000000000000001",
             "l1_system_config_address": "0x6900000000000000000000000000000000000009"
@@ -832,15 +1814,182 @@ mod test {
         assert_eq!(chain.channel_timeout, 120);
         assert_eq!(chain.seq_window_size, 200);
         assert_eq!(chain.max_seq_drift, 300);
-        assert_eq!(chain.regolith_time, 1);
-        assert_eq!(chain.canyon_time, 2);
-        assert_eq!(chain.delta_time, 3);
-        assert_eq!(chain.ecotone_time, 4);
+        assert_eq!(chain.hardforks.regolith_time, 1);
+        assert_eq!(chain.hardforks.canyon_time, 2);
+        assert_eq!(chain.hardforks.delta_time, 3);
+        assert_eq!(chain.hardforks.ecotone_time, 4);
+        assert_eq!(chain.hardforks.fjord_time, Some(5));
+        assert_eq!(chain.hardforks.granite_time, None);
         assert_eq!(chain.blocktime, 2);
         assert_eq!(
             chain.l2_to_l1_message_passer,
             addr("0x4200000000000000000000000000000000000016")
         );
+        assert_eq!(chain.system_accounts, SystemAccounts::default());
+        assert!(chain.validate().is_ok());
+    }
+
+    #[test]
+    fn test_hardfork_schedule() {
+        let mut chain = ChainConfig::optimism();
+        chain.hardforks = HardforkSchedule {
+            ecotone_time: 10,
+            fjord_time: Some(20),
+            ..Default::default()
+        };
+
+        assert!(chain.hardforks.is_delta_active(0));
+        assert!(!chain.hardforks.is_ecotone_active(9));
+        assert!(chain.hardforks.is_ecotone_activation_block(10));
+        assert!(!chain.hardforks.is_ecotone_but_not_first_block(10));
+        assert!(chain.hardforks.is_ecotone_but_not_first_block(12));
+        assert!(!chain.hardforks.is_fjord_active(19));
+        assert!(chain.hardforks.is_fjord_active(20));
+        assert!(!chain.hardforks.is_granite_active(u64::MAX));
+        assert!(!chain.hardforks.is_isthmus_active(u64::MAX));
+        assert_eq!(chain.hardforks.unsupported_fork(19), None);
+        assert_eq!(chain.hardforks.unsupported_fork(20), Some("fjord"));
+        assert_eq!(chain.hardforks.scheduled_unsupported_forks(), vec!["fjord"]);
+
+        assert_eq!(chain.max_channel_bank_size(19), chain.max_channel_size);
+        assert_eq!(chain.max_channel_bank_size(20), FJORD_MAX_CHANNEL_BANK_SIZE);
+        assert_eq!(chain.max_sequencer_drift(19), chain.max_seq_drift);
+        assert_eq!(chain.max_sequencer_drift(20), FJORD_MAX_SEQUENCER_DRIFT);
+        assert_eq!(chain.max_channel_timeout(u64::MAX), chain.channel_timeout);
+
+        // the schedule is flattened into the chain config
+        let provider: Serialized<ChainProvider> = chain.clone().into();
+        let extracted: ChainProvider = Figment::from(provider)
+            .merge(Toml::string("[chain]\ngranite_time = 30"))
+            .extract()
+            .unwrap();
+
+        assert_eq!(extracted.chain.hardforks.fjord_time, Some(20));
+        assert_eq!(extracted.chain.hardforks.granite_time, Some(30));
+    }
+
+    #[test]
+    fn test_custom_protocol_addresses() {
+        let mut chain = ChainConfig::optimism();
+        chain.l2_to_l1_message_passer = addr("0x4200000000000000000000000000000000000099");
+        chain.system_accounts.fee_vault = addr("0x4200000000000000000000000000000000000098");
+
+        let external: ExternalChainConfig = chain.clone().into();
+        let json = serde_json::to_string(&external).unwrap();
+        let imported: ChainConfig = serde_json::from_str::<ExternalChainConfig>(&json)
+            .unwrap()
+            .into();
+
+        assert_eq!(
+            imported.l2_to_l1_message_passer,
+            chain.l2_to_l1_message_passer
+        );
+        assert_eq!(imported.system_accounts, chain.system_accounts);
+
+        let standard: ExternalChainConfig = ChainConfig::optimism().into();
+        assert!(standard.batch_inbox_rotations.is_empty());
+        assert!(standard.system_accounts.is_none());
+        assert!(standard.l2_to_l1_message_passer_address.is_none());
+
+        let partial: SystemAccounts =
+            serde_json::from_str(r#"{"fee_vault":"0x4200000000000000000000000000000000000098"}"#)
+                .unwrap();
+        assert_eq!(partial, chain.system_accounts);
+
+        chain.batch_inbox = Address::ZERO;
+        chain.system_accounts.attributes_predeploy = Address::ZERO;
+        let err = chain.validate().unwrap_err().to_string();
+        assert!(err.contains("batch_inbox"));
+        assert!(err.contains("system_accounts.attributes_predeploy"));
+    }
+
+    #[test]
+    fn test_batch_inbox_rotations() {
+        let mut chain = ChainConfig::optimism();
+        let (first, second) = (Address::repeat_byte(1), Address::repeat_byte(2));
+        chain.batch_inbox_rotations = vec![
+            BatchInboxRotation {
+                l1_block: 100,
+                address: first,
+            },
+            BatchInboxRotation {
+                l1_block: 200,
+                address: second,
+            },
+        ];
+
+        assert_eq!(chain.batch_inbox_at(99), chain.batch_inbox);
+        assert_eq!(chain.batch_inbox_at(100), first);
+        assert_eq!(chain.batch_inbox_at(199), first);
+        assert_eq!(chain.batch_inbox_at(200), second);
+        assert!(chain.validate().is_ok());
+
+        // the rotations are kept in the rollup config
+        let external: ExternalChainConfig = chain.clone().into();
+        let json = serde_json::to_string(&external).unwrap();
+        let imported: ChainConfig = serde_json::from_str::<ExternalChainConfig>(&json)
+            .unwrap()
+            .into();
+        assert_eq!(imported.batch_inbox_rotations, chain.batch_inbox_rotations);
+
+        chain.batch_inbox_rotations.swap(0, 1);
+        assert!(chain.validate().is_err());
+    }
+
+    #[test]
+    fn test_op_node_rollup_config_schema() {
+        // the response of `optimism_rollupConfig` from op-node for a devnet
+        let op_node_json = r#"
+        {
+            "genesis": {
+              "l1": {
+                "hash": "0xdb52a58e7341447d1a9525d248ea07dbca7dfa0e105721dee1aa5a86163c088d",
+                "number": 0
+              },
+              "l2": {
+                "hash": "0xf85bca315a08237644b06a8350cda3bc0de1593745a91be93daeadb28fb3a32e",
+                "number": 0
+              },
+              "l2_time": 1685710775,
+              "system_config": {
+                "batcherAddr": "0x3c44cdddb6a900fa2b585dd299e03d12fa4293bc",
+                "overhead": "0x0000000000000000000000000000000000000000000000000000000000000834",
+                "scalar": "0x00000000000000000000000000000000000000000000000000000000000f4240",
+                "gasLimit": 30000000
+              }
+            },
+            "block_time": 2,
+            "max_sequencer_drift": 300,
+            "seq_window_size": 200,
+            "channel_timeout": 120,
+            "l1_chain_id": 900,
+            "l2_chain_id": 901,
+            "regolith_time": 0,
+            "canyon_time": 0,
+            "delta_time": 0,
+            "ecotone_time": 0,
+            "fjord_time": 0,
+            "granite_time": 10,
+            "batch_inbox_address": "0xff00000000000000000000000000000000000901",
+            "deposit_contract_address": "0x6900000000000000000000000000000000000001",
+            "l1_system_config_address": "0x6900000000000000000000000000000000000009",
+            "protocol_versions_address": "0x6900000000000000000000000000000000000010",
+            "chain_op_config": {
+              "eip1559Elasticity": 6,
+              "eip1559Denominator": 50,
+              "eip1559DenominatorCanyon": 250
+            }
+        }
+        "#;
+
+        let expected: serde_json::Value = serde_json::from_str(op_node_json).unwrap();
+        let chain: ChainConfig = serde_json::from_value::<ExternalChainConfig>(expected.clone())
+            .unwrap()
+            .into();
+        assert_eq!(chain.chain_op_config, ChainOpConfig::default());
+
+        let external = ExternalChainConfig::from(chain);
+        assert_eq!(serde_json::to_value(external).unwrap(), expected);
     }
 
     #[test]
@@ -861,7 +2010,7 @@ mod test {
         assert_eq!(optimism_config.l1_chain_id, desired_config.l1_chain_id);
         assert_eq!(optimism_config.l2_chain_id, desired_config.l2_chain_id);
         assert_eq!(optimism_config.blocktime, desired_config.blocktime);
-        assert_eq!(optimism_config.regolith_time, desired_config.regolith_time);
+        assert_eq!(optimism_config.hardforks, desired_config.hardforks);
         assert_eq!(optimism_config.batch_inbox, desired_config.batch_inbox);
         assert_eq!(
             optimism_config.deposit_contract,
@@ -921,6 +2070,136 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_config_for_rollups() -> eyre::Result<()> {
+        let dir = std::env::temp_dir().join("magi-test-rollups");
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join("rollups.toml");
+
+        std::fs::write(
+            &path,
+            r#"
+            [[rollup]]
+            network = "optimism"
+            l2_rpc_url = "http://localhost:8545"
+            l2_engine_url = "http://localhost:8551"
+            jwt_secret = "aa"
+            rpc_port = 9545
+            p2p_port = 9876
+
+            [[rollup]]
+            network = "base"
+            l2_rpc_url = "http://localhost:18545"
+            l2_engine_url = "http://localhost:18551"
+            jwt_secret = "bb"
+            rpc_port = 19545
+            p2p_port = 19876
+            "#,
+        )?;
+
+        let shared = Config {
+            l1_rpc_url: "http://localhost:5545".to_string(),
+            ..Default::default()
+        };
+
+        let configs = shared.for_rollups(&path)?;
+        assert_eq!(configs.len(), 2);
+        assert_eq!(configs[0].chain.l2_chain_id, 10);
+        assert_eq!(configs[1].chain.l2_chain_id, 8453);
+        assert_eq!(configs[1].l1_rpc_url, "http://localhost:5545");
+        assert_eq!(configs[1].l2_engine_url, "http://localhost:18551");
+        assert_eq!(configs[1].p2p_port, 19876);
+
+        // rollup-specific settings are not shared
+        std::fs::write(
+            &path,
+            r#"
+            [[rollup]]
+            network = "optimism"
+            l2_rpc_url = "http://localhost:8545"
+            l2_engine_url = "http://localhost:8551"
+            jwt_secret_file = "/secrets/optimism.hex"
+            rpc_port = 9545
+            p2p_port = 9876
+            data_dir = "/data/optimism"
+            sequencer = true
+            p2p_sequencer_keystore = "/keys/optimism.json"
+            unsafe_follow_url = "http://optimism-sequencer:8545"
+
+            [[rollup]]
+            network = "base"
+            l2_rpc_url = "http://localhost:18545"
+            l2_engine_url = "http://localhost:18551"
+            jwt_secret_file = "/secrets/base.hex"
+            rpc_port = 19545
+            p2p_port = 19876
+            data_dir = "/data/base"
+            p2p_sequencer_keystore = "/keys/base.json"
+            "#,
+        )?;
+
+        let configs = shared.for_rollups(&path)?;
+        assert_eq!(configs[0].jwt_secret, None);
+        assert_eq!(
+            configs[0].jwt_secret_file.as_deref(),
+            Some("/secrets/optimism.hex")
+        );
+        assert_eq!(
+            configs[1].jwt_secret_file.as_deref(),
+            Some("/secrets/base.hex")
+        );
+        assert_eq!(configs[0].data_dir.as_deref(), Some("/data/optimism"));
+        assert_eq!(configs[1].data_dir.as_deref(), Some("/data/base"));
+        assert!(configs[0].sequencer);
+        assert!(!configs[1].sequencer);
+        assert_eq!(
+            configs[0].p2p_sequencer_keystore.as_deref(),
+            Some("/keys/optimism.json")
+        );
+        assert_eq!(
+            configs[1].p2p_sequencer_keystore.as_deref(),
+            Some("/keys/base.json")
+        );
+        assert_eq!(
+            configs[0].unsafe_follow_url.as_deref(),
+            Some("http://optimism-sequencer:8545")
+        );
+        assert_eq!(configs[1].unsafe_follow_url, None);
+
+        // a shared sequencer key is rejected
+        let with_key = Config {
+            p2p_sequencer_key: Some("0x01".to_string()),
+            ..shared.clone()
+        };
+        let err = with_key.for_rollups(&path).unwrap_err();
+        assert!(err.to_string().contains("p2p_sequencer_key"));
+
+        std::fs::write(
+            &path,
+            r#"
+            [[rollup]]
+            network = "optimism"
+            l2_rpc_url = "http://localhost:8545"
+            l2_engine_url = "http://localhost:8551"
+            jwt_secret = "aa"
+            rpc_port = 9545
+            p2p_port = 9876
+
+            [[rollup]]
+            network = "base"
+            l2_rpc_url = "http://localhost:18545"
+            l2_engine_url = "http://localhost:18551"
+            jwt_secret = "bb"
+            rpc_port = 9545
+            p2p_port = 19876
+            "#,
+        )?;
+
+        assert!(shared.for_rollups(&path).is_err());
+
+        Ok(())
+    }
+
     #[test]
     #[should_panic(expected = "Invalid network name")]
     fn test_chain_config_unknown_chain() {
@@ -0,0 +1,396 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use alloy_primitives::{Address, B256, U256};
+use eyre::Result;
+use figment::{
+    providers::{Format, Toml},
+    Figment,
+};
+use serde::Deserialize;
+
+use crate::common::{BlockInfo, Epoch};
+
+use super::{addr, ChainConfig, ChainOpConfig, HardforkSchedule, SystemAccounts, SystemConfig};
+
+/// The maximum byte size of all pending channels before the fjord hardfork
+const MAX_CHANNEL_SIZE: u64 = 100_000_000;
+
+/// The channel timeout of standard chains before the granite hardfork, which the registry
+/// does not list per chain
+const CHANNEL_TIMEOUT: u64 = 300;
+
+/// Name of the file describing the L1 of the chains in a registry directory
+const SUPERCHAIN_FILE: &str = "superchain.toml";
+
+/// A chain definition of the superchain registry, such as
+/// `superchain/configs/mainnet/op.toml`
+#[derive(Debug, Clone, Deserialize)]
+struct ChainFile {
+    chain_id: u64,
+    batch_inbox_addr: Address,
+    block_time: u64,
+    seq_window_size: u64,
+    max_sequencer_drift: u64,
+    /// `eth-da` or `alt-da`
+    #[serde(default)]
+    data_availability_type: Option<String>,
+    #[serde(default)]
+    hardforks: Hardforks,
+    #[serde(default)]
+    optimism: Option<Eip1559Params>,
+    genesis: Genesis,
+    #[serde(default)]
+    roles: Roles,
+    addresses: Addresses,
+}
+
+/// The hardfork activation timestamps of a registry chain. Hardforks without a timestamp
+/// are not scheduled, except regolith which is active from genesis on every registry chain.
+#[derive(Debug, Clone, Default, Deserialize)]
+struct Hardforks {
+    regolith_time: Option<u64>,
+    canyon_time: Option<u64>,
+    delta_time: Option<u64>,
+    ecotone_time: Option<u64>,
+    fjord_time: Option<u64>,
+    granite_time: Option<u64>,
+    holocene_time: Option<u64>,
+    isthmus_time: Option<u64>,
+    interop_time: Option<u64>,
+}
+
+/// The EIP-1559 parameters of a registry chain
+#[derive(Debug, Clone, Deserialize)]
+struct Eip1559Params {
+    eip1559_elasticity: u64,
+    eip1559_denominator: u64,
+    eip1559_denominator_canyon: Option<u64>,
+}
+
+/// The genesis of a registry chain
+#[derive(Debug, Clone, Deserialize)]
+struct Genesis {
+    l2_time: u64,
+    l1: GenesisBlock,
+    l2: GenesisBlock,
+    system_config: GenesisSystemConfig,
+}
+
+/// A genesis block hash & number
+#[derive(Debug, Clone, Deserialize)]
+struct GenesisBlock {
+    hash: B256,
+    number: u64,
+}
+
+/// The genesis system config of a registry chain
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GenesisSystemConfig {
+    #[serde(alias = "batcherAddr")]
+    batcher_address: Address,
+    #[serde(default)]
+    overhead: B256,
+    #[serde(default)]
+    scalar: B256,
+    gas_limit: u64,
+}
+
+/// The privileged accounts of a registry chain
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Roles {
+    #[serde(default)]
+    unsafe_block_signer: Address,
+}
+
+/// The L1 contracts of a registry chain
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Addresses {
+    optimism_portal_proxy: Address,
+    system_config_proxy: Address,
+}
+
+/// The `superchain.toml` of a registry directory, shared by its chains
+#[derive(Debug, Clone, Deserialize)]
+struct SuperchainFile {
+    #[serde(default)]
+    protocol_versions_addr: Address,
+    l1: SuperchainL1,
+}
+
+/// The L1 of a superchain
+#[derive(Debug, Clone, Deserialize)]
+struct SuperchainL1 {
+    chain_id: u64,
+}
+
+/// A chain of the superchain registry, from which the [ChainConfig] is built so a new
+/// OP Stack chain only takes its registry TOML file
+#[derive(Debug, Clone)]
+pub struct RegistryChain {
+    /// The network name, from the file name of the chain
+    network: String,
+    /// The chain definition
+    chain: ChainFile,
+    /// The superchain the chain belongs to
+    superchain: SuperchainFile,
+}
+
+impl RegistryChain {
+    /// Reads the chain TOML at `path` and the `superchain.toml` next to it, laid out like
+    /// the `superchain/configs/<superchain>` directories of the registry
+    pub fn read(path: &Path) -> Result<Self> {
+        let superchain_path = path
+            .parent()
+            .map(|dir| dir.join(SUPERCHAIN_FILE))
+            .filter(|path| path.exists())
+            .ok_or(eyre::eyre!("no {} next to {:?}", SUPERCHAIN_FILE, path))?;
+
+        let network = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .ok_or(eyre::eyre!("invalid chain file {:?}", path))?;
+
+        Ok(Self {
+            network,
+            chain: read_toml(path)?,
+            superchain: read_toml(&superchain_path)?,
+        })
+    }
+
+    /// Builds and validates the [ChainConfig]
+    pub fn chain_config(&self) -> Result<ChainConfig> {
+        let chain = &self.chain;
+        if chain.data_availability_type.as_deref() == Some("alt-da") {
+            eyre::bail!("chain {} uses alt-DA, which is not supported", self.network);
+        }
+
+        let genesis = &chain.genesis;
+        let hardforks = &chain.hardforks;
+        let op_config = ChainOpConfig::default();
+        let chain_op_config = match &chain.optimism {
+            Some(params) => ChainOpConfig {
+                eip1559_elasticity: params.eip1559_elasticity,
+                eip1559_denominator: params.eip1559_denominator,
+                eip1559_denominator_canyon: params
+                    .eip1559_denominator_canyon
+                    .unwrap_or(op_config.eip1559_denominator_canyon),
+            },
+            None => op_config,
+        };
+
+        let config = ChainConfig {
+            network: self.network.clone(),
+            l1_chain_id: self.superchain.l1.chain_id,
+            l2_chain_id: chain.chain_id,
+            l1_start_epoch: Epoch {
+                hash: ethers::types::H256::from_slice(genesis.l1.hash.as_slice()),
+                number: genesis.l1.number,
+                timestamp: 0,
+            },
+            l2_genesis: BlockInfo {
+                hash: ethers::types::H256::from_slice(genesis.l2.hash.as_slice()),
+                number: genesis.l2.number,
+                parent_hash: ethers::types::H256::zero(),
+                timestamp: genesis.l2_time,
+            },
+            system_config: SystemConfig {
+                batch_sender: genesis.system_config.batcher_address,
+                gas_limit: U256::from(genesis.system_config.gas_limit),
+                l1_fee_overhead: U256::from_be_bytes(genesis.system_config.overhead.0),
+                l1_fee_scalar: U256::from_be_bytes(genesis.system_config.scalar.0),
+                unsafe_block_signer: chain.roles.unsafe_block_signer,
+                operator_fee_params: Default::default(),
+            },
+            batch_inbox: chain.batch_inbox_addr,
+            batch_inbox_rotations: Vec::new(),
+            deposit_contract: chain.addresses.optimism_portal_proxy,
+            system_config_contract: chain.addresses.system_config_proxy,
+            max_channel_size: MAX_CHANNEL_SIZE,
+            channel_timeout: CHANNEL_TIMEOUT,
+            seq_window_size: chain.seq_window_size,
+            max_seq_drift: chain.max_sequencer_drift,
+            hardforks: HardforkSchedule {
+                regolith_time: hardforks.regolith_time.unwrap_or(0),
+                canyon_time: hardforks.canyon_time.unwrap_or(u64::MAX),
+                delta_time: hardforks.delta_time.unwrap_or(u64::MAX),
+                ecotone_time: hardforks.ecotone_time.unwrap_or(u64::MAX),
+                fjord_time: hardforks.fjord_time,
+                granite_time: hardforks.granite_time,
+                holocene_time: hardforks.holocene_time,
+                isthmus_time: hardforks.isthmus_time,
+                interop_time: hardforks.interop_time,
+            },
+            interop_dependency_set: Vec::new(),
+            blocktime: chain.block_time,
+            l2_to_l1_message_passer: addr("0x4200000000000000000000000000000000000016"),
+            system_accounts: SystemAccounts::default(),
+            protocol_versions_contract: self.superchain.protocol_versions_addr,
+            chain_op_config,
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+/// Finds the registry TOML of the given network in `dir`, either at
+/// `<dir>/<superchain>/<network>.toml` or, for a network named `<superchain>/<chain>`, at
+/// `<dir>/<superchain>/<chain>.toml`. Fails if the network name matches chains of several
+/// superchains.
+pub fn find_chain(dir: &Path, network: &str) -> Result<Option<PathBuf>> {
+    let file = format!("{}.toml", network);
+    let direct = dir.join(&file);
+    if direct.is_file() {
+        return Ok(Some(direct));
+    }
+
+    if !dir.is_dir() {
+        return Ok(None);
+    }
+
+    let mut matches = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path().join(&file);
+        if path.is_file() {
+            matches.push(path);
+        }
+    }
+
+    match matches.len() {
+        0 | 1 => Ok(matches.pop()),
+        _ => {
+            matches.sort();
+            eyre::bail!(
+                "network {} matches several chains, name it as <superchain>/{}: {:?}",
+                network,
+                network,
+                matches
+            )
+        }
+    }
+}
+
+fn read_toml<T: for<'de> Deserialize<'de>>(path: &Path) -> Result<T> {
+    let contents = fs::read_to_string(path)?;
+    Figment::from(Toml::string(&contents))
+        .extract()
+        .map_err(|err| eyre::eyre!("invalid registry file {:?}: {}", path, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OP_MAINNET: &str = r#"
+        name = "OP Mainnet"
+        public_rpc = "https://mainnet.optimism.io"
+        superchain_level = 1
+        data_availability_type = "eth-da"
+        chain_id = 10
+        batch_inbox_addr = "0xFF00000000000000000000000000000000000010"
+        block_time = 2
+        seq_window_size = 3600
+        max_sequencer_drift = 600
+
+        [hardforks]
+          canyon_time = 1704992401
+          delta_time = 1708560000
+          ecotone_time = 1710374401
+          fjord_time = 1720627201
+          granite_time = 1726070401
+
+        [optimism]
+          eip1559_elasticity = 6
+          eip1559_denominator = 50
+          eip1559_denominator_canyon = 250
+
+        [genesis]
+          l2_time = 1686068903
+          [genesis.l1]
+            hash = "0x438335a20d98863a4c0c97999eb2481921ccd28553eac6f913af7c12aec04108"
+            number = 17422590
+          [genesis.l2]
+            hash = "0xdbf6a80fef073de06add9b0d14026d6e5a86c85f6d102c36d3d8e9cf89c2afd3"
+            number = 105235063
+          [genesis.system_config]
+            batcherAddress = "0x6887246668a3b87f54deb3b94ba47a6f63f32985"
+            overhead = "0x00000000000000000000000000000000000000000000000000000000000000bc"
+            scalar = "0x00000000000000000000000000000000000000000000000000000000000a6fe0"
+            gasLimit = 30000000
+
+        [roles]
+          UnsafeBlockSigner = "0xAAAA45d9549EDA09E70937013520214382Ffc4A2"
+          BatchSubmitter = "0x6887246668a3b87f54deb3b94ba47a6f63f32985"
+
+        [addresses]
+          OptimismPortalProxy = "0xbEb5Fc579115071764c7423A4f12eDde41f106Ed"
+          SystemConfigProxy = "0x229047fed2591dbec1eF1118d64F7aF3dB9EB290"
+          L1StandardBridgeProxy = "0x99C9fc46f92E8a1c0deC1b1747d010903E884bE1"
+    "#;
+
+    const MAINNET: &str = r#"
+        name = "Mainnet"
+        protocol_versions_addr = "0x8062AbC286f5e7D9428a0Ccb9AbD71e50d93b935"
+
+        [l1]
+          chain_id = 1
+          public_rpc = "https://ethereum-rpc.publicnode.com"
+    "#;
+
+    #[test]
+    fn test_registry_chain() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("magi-registry-{}", std::process::id()));
+        for superchain in ["mainnet", "sepolia"] {
+            fs::create_dir_all(dir.join(superchain))?;
+            fs::write(dir.join(superchain).join("superchain.toml"), MAINNET)?;
+            fs::write(dir.join(superchain).join("op.toml"), OP_MAINNET)?;
+        }
+
+        // chains of several superchains must be named with their superchain
+        assert!(find_chain(&dir, "op").is_err());
+        assert!(find_chain(&dir, "zora")?.is_none());
+        let path = find_chain(&dir, "mainnet/op")?.unwrap();
+
+        let chain = RegistryChain::read(&path)?.chain_config()?;
+        let preset = ChainConfig::optimism();
+        assert_eq!(chain.network, "op");
+        assert_eq!(chain.l1_chain_id, preset.l1_chain_id);
+        assert_eq!(chain.l2_chain_id, preset.l2_chain_id);
+        assert_eq!(chain.l1_start_epoch.hash, preset.l1_start_epoch.hash);
+        assert_eq!(chain.l2_genesis.hash, preset.l2_genesis.hash);
+        assert_eq!(chain.l2_genesis.timestamp, preset.l2_genesis.timestamp);
+        assert_eq!(chain.batch_inbox, preset.batch_inbox);
+        assert_eq!(chain.deposit_contract, preset.deposit_contract);
+        assert_eq!(chain.system_config_contract, preset.system_config_contract);
+        assert_eq!(
+            chain.protocol_versions_contract,
+            preset.protocol_versions_contract
+        );
+        assert_eq!(
+            chain.system_config.unsafe_block_signer,
+            preset.system_config.unsafe_block_signer
+        );
+        assert_eq!(
+            chain.system_config.l1_fee_scalar,
+            preset.system_config.l1_fee_scalar
+        );
+        assert_eq!(chain.chain_op_config, preset.chain_op_config);
+
+        assert_eq!(chain.hardforks.regolith_time, 0);
+        assert_eq!(chain.hardforks.ecotone_time, 1710374401);
+        assert_eq!(chain.hardforks.holocene_time, None);
+
+        // a chain file without its superchain is rejected
+        fs::remove_file(dir.join("sepolia").join("superchain.toml"))?;
+        assert!(RegistryChain::read(&dir.join("sepolia").join("op.toml")).is_err());
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
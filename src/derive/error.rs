@@ -0,0 +1,146 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// An error of the derivation loop, classified by how the driver recovers from it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DerivationError {
+    /// A transient failure, such as an unreachable RPC or a busy engine. The driver retries
+    /// the same step after a delay.
+    Temporary(String),
+    /// The derivation state no longer matches the chain, such as a batch whose L1 origin is
+    /// not known. The driver restarts derivation from the finalized head.
+    Reset(String),
+    /// Derived data that should never be produced, such as attributes rejected by the
+    /// engine, hinting at a consensus bug. The driver halts.
+    Critical(String),
+}
+
+impl DerivationError {
+    /// Creates a [DerivationError::Temporary] from the given error
+    pub fn temporary(err: impl fmt::Display) -> Self {
+        Self::Temporary(err.to_string())
+    }
+
+    /// Creates a [DerivationError::Reset] from the given error
+    pub fn reset(err: impl fmt::Display) -> Self {
+        Self::Reset(err.to_string())
+    }
+
+    /// Creates a [DerivationError::Critical] from the given error
+    pub fn critical(err: impl fmt::Display) -> Self {
+        Self::Critical(err.to_string())
+    }
+
+    /// Classifies an error of the derivation loop by the first [DerivationError] in its
+    /// chain. Errors without one are unexpected, so they are critical.
+    pub fn classify(err: &eyre::Report) -> Self {
+        err.chain()
+            .find_map(|cause| cause.downcast_ref::<Self>())
+            .map(|cause| match cause {
+                Self::Temporary(_) => Self::Temporary(format!("{:#}", err)),
+                Self::Reset(_) => Self::Reset(format!("{:#}", err)),
+                Self::Critical(_) => Self::Critical(format!("{:#}", err)),
+            })
+            .unwrap_or_else(|| Self::Critical(format!("{:#}", err)))
+    }
+
+    /// Returns the code of the error class, used in metrics and the health endpoints
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Temporary(_) => "temporary",
+            Self::Reset(_) => "reset",
+            Self::Critical(_) => "critical",
+        }
+    }
+
+    /// Returns the description of the error
+    pub fn message(&self) -> &str {
+        match self {
+            Self::Temporary(message) | Self::Reset(message) | Self::Critical(message) => message,
+        }
+    }
+}
+
+impl fmt::Display for DerivationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message())
+    }
+}
+
+impl std::error::Error for DerivationError {}
+
+/// The errors of the derivation loop since the node started
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DerivationErrors {
+    /// Number of errors since the node started
+    pub count: u64,
+    /// Number of iterations of the derivation loop that failed in a row, 0 once one succeeds
+    pub consecutive: u64,
+    /// The code of the last error
+    pub last_code: Option<String>,
+    /// The description of the last error
+    pub last_error: Option<String>,
+    /// Unix timestamp of the last error
+    pub last_error_at: Option<u64>,
+}
+
+impl DerivationErrors {
+    /// Records an error at the given unix timestamp
+    pub fn record(&mut self, err: &DerivationError, now: u64) {
+        self.count += 1;
+        self.consecutive += 1;
+        self.last_code = Some(err.code().to_string());
+        self.last_error = Some(err.message().to_string());
+        self.last_error_at = Some(now);
+    }
+
+    /// Records an iteration of the derivation loop without error
+    pub fn clear(&mut self) {
+        self.consecutive = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use eyre::WrapErr;
+
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let err: eyre::Report = DerivationError::temporary("connection refused").into();
+        let err = err.wrap_err("failed to handle attributes");
+        let classified = DerivationError::classify(&err);
+        assert_eq!(classified.code(), "temporary");
+        assert!(classified
+            .message()
+            .starts_with("failed to handle attributes"));
+        assert!(classified.message().contains("connection refused"));
+
+        let err = Err::<(), _>(DerivationError::reset("epoch not found"))
+            .wrap_err("derivation failed")
+            .unwrap_err();
+        assert_eq!(DerivationError::classify(&err).code(), "reset");
+
+        // unclassified errors are unexpected
+        let err = eyre::eyre!("lock poisoned");
+        assert_eq!(
+            DerivationError::classify(&err),
+            DerivationError::Critical("lock poisoned".to_string())
+        );
+
+        let mut errors = DerivationErrors::default();
+        errors.record(&DerivationError::temporary("engine syncing"), 10);
+        errors.record(&DerivationError::temporary("engine syncing"), 11);
+        assert_eq!(errors.count, 2);
+        assert_eq!(errors.consecutive, 2);
+        assert_eq!(errors.last_code.as_deref(), Some("temporary"));
+        assert_eq!(errors.last_error_at, Some(11));
+
+        errors.clear();
+        assert_eq!(errors.consecutive, 0);
+        assert_eq!(errors.count, 2);
+    }
+}
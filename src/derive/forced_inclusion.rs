@@ -0,0 +1,124 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+/// Number of recent deposit-only blocks kept in a [ForcedInclusionReport]
+pub const RECENT_DEPOSIT_ONLY_BLOCKS: usize = 32;
+
+/// Why a block was derived deposit-only
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DepositOnlyReason {
+    /// No batch was submitted within the sequencing window
+    NoBatch,
+    /// Batches were submitted within the sequencing window, but dropped as invalid
+    InvalidBatches,
+}
+
+impl DepositOnlyReason {
+    /// Returns the metric label of the reason
+    pub fn label(&self) -> &'static str {
+        match self {
+            DepositOnlyReason::NoBatch => "no_batch",
+            DepositOnlyReason::InvalidBatches => "invalid_batches",
+        }
+    }
+}
+
+/// A block derived deposit-only after the sequencing window of its epoch expired
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DepositOnlyBlock {
+    /// Timestamp of the block
+    pub timestamp: u64,
+    /// The L1 origin of the block
+    pub epoch: u64,
+    /// The L1 block at which the sequencing window expired
+    pub l1_block: u64,
+    /// Why the block is deposit-only
+    pub reason: DepositOnlyReason,
+    /// Number of batches dropped since the last valid batch
+    pub dropped_batches: u64,
+}
+
+/// The blocks derived deposit-only since the node started. From the verifier's
+/// perspective, these are blocks the batcher failed to submit in time, so operators can
+/// detect a failing batcher before users do.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ForcedInclusionReport {
+    /// Number of deposit-only blocks derived
+    pub deposit_only_blocks: u64,
+    /// Number of deposit-only blocks derived after batches were dropped as invalid
+    pub invalid_batch_blocks: u64,
+    /// The most recent deposit-only blocks, oldest first
+    pub recent: VecDeque<DepositOnlyBlock>,
+}
+
+impl ForcedInclusionReport {
+    /// Records a deposit-only block
+    pub fn record(&mut self, block: DepositOnlyBlock) {
+        self.deposit_only_blocks += 1;
+        if block.reason == DepositOnlyReason::InvalidBatches {
+            self.invalid_batch_blocks += 1;
+        }
+
+        if self.recent.len() == RECENT_DEPOSIT_ONLY_BLOCKS {
+            self.recent.pop_front();
+        }
+
+        self.recent.push_back(block);
+    }
+
+    /// Returns the most recent deposit-only block, if any
+    pub fn last(&self) -> Option<&DepositOnlyBlock> {
+        self.recent.back()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(timestamp: u64, dropped_batches: u64) -> DepositOnlyBlock {
+        DepositOnlyBlock {
+            timestamp,
+            epoch: 1,
+            l1_block: 10,
+            reason: if dropped_batches > 0 {
+                DepositOnlyReason::InvalidBatches
+            } else {
+                DepositOnlyReason::NoBatch
+            },
+            dropped_batches,
+        }
+    }
+
+    #[test]
+    fn test_record() {
+        let mut report = ForcedInclusionReport::default();
+        assert!(report.last().is_none());
+
+        report.record(block(0, 0));
+        report.record(block(2, 3));
+        assert_eq!(report.deposit_only_blocks, 2);
+        assert_eq!(report.invalid_batch_blocks, 1);
+        assert_eq!(report.last(), Some(&block(2, 3)));
+
+        // only the most recent blocks are kept
+        for i in 0..RECENT_DEPOSIT_ONLY_BLOCKS as u64 {
+            report.record(block(4 + i * 2, 0));
+        }
+
+        assert_eq!(
+            report.deposit_only_blocks,
+            2 + RECENT_DEPOSIT_ONLY_BLOCKS as u64
+        );
+        assert_eq!(report.recent.len(), RECENT_DEPOSIT_ONLY_BLOCKS);
+        assert_eq!(report.recent[0].timestamp, 4);
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["invalidBatchBlocks"], 1);
+        assert_eq!(json["recent"][0]["reason"], "noBatch");
+    }
+}
@@ -0,0 +1,342 @@
+use std::{
+    collections::VecDeque,
+    sync::{mpsc, Arc, Condvar, Mutex},
+    thread,
+    time::Duration,
+};
+
+use bytes::Bytes;
+use ethers::{types::H256, utils::keccak256};
+
+use crate::config::Config;
+
+use super::{
+    stages::{
+        batcher_transactions::{BatcherTransactionMessage, BatcherTransactions},
+        batches::{decode_batches, Batch},
+        channels::{Channel, Channels},
+    },
+    PurgeableIterator,
+};
+
+/// Interval at which a worker waiting for space checks whether its handles were dropped
+const WAIT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Decodes the channels of fetched L1 blocks in a background thread, ahead of the
+/// derivation pipeline.
+///
+/// The L1 chain watcher pushes the batcher transactions of every block it fetches, while
+/// the block waits in its buffer for derivation. The worker assembles them into channels
+/// with its own channel bank and decodes their batches, so decompression and decoding
+/// overlap with the derivation of earlier epochs. The [Batches](super::stages::batches::Batches)
+/// stage takes the decoded batches of a channel with the same id, inclusion block and
+/// data, and decodes channels missing from the cache itself, so derivation does not
+/// depend on the worker keeping up. Once the cache is full, the worker stops decoding
+/// until the stage takes a channel, and channels older than the taken one are dropped as
+/// the stage passed them. The cache is cleared when the pipeline is purged.
+#[derive(Debug, Clone)]
+pub struct Lookahead {
+    /// Channel to the worker thread
+    sender: mpsc::Sender<Message>,
+    /// The decoded channels shared with the worker
+    cache: Arc<Cache>,
+}
+
+/// The decoded channels, and a signal to the worker once there is space for more
+#[derive(Debug, Default)]
+struct Cache {
+    /// The decoded channels
+    channels: Mutex<DecodedChannels>,
+    /// Notified when channels are taken or purged
+    space: Condvar,
+}
+
+/// A message to the worker thread
+enum Message {
+    /// The batcher transactions of the next fetched L1 block
+    Transactions(BatcherTransactionMessage),
+    /// Clears the channel bank of the worker
+    Purge,
+}
+
+/// The decoded channels waiting to be taken by the batches stage
+#[derive(Debug, Default)]
+struct DecodedChannels {
+    /// Number of purges, so channels decoded before a purge are not inserted after it
+    generation: u64,
+    /// The decoded batches of each channel, oldest first
+    entries: VecDeque<(ChannelKey, Vec<Batch>)>,
+    /// Maximum number of entries
+    capacity: usize,
+}
+
+/// Identifies a channel by its id, inclusion block and the hash of its data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ChannelKey {
+    /// The channel id
+    id: u128,
+    /// The L1 block the channel was completed in
+    l1_inclusion_block: u64,
+    /// The hash of the channel data
+    data_hash: H256,
+}
+
+impl From<&Channel> for ChannelKey {
+    fn from(channel: &Channel) -> Self {
+        Self {
+            id: channel.id,
+            l1_inclusion_block: channel.l1_inclusion_block,
+            data_hash: H256::from(keccak256(&channel.data)),
+        }
+    }
+}
+
+impl Lookahead {
+    /// Starts the worker thread, keeping at most `capacity` decoded channels
+    pub fn start(config: Arc<Config>, capacity: usize) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let cache = Arc::new(Cache {
+            channels: Mutex::new(DecodedChannels {
+                capacity,
+                ..Default::default()
+            }),
+            space: Condvar::new(),
+        });
+
+        let worker_cache = cache.clone();
+        let spawned = thread::Builder::new()
+            .name("derivation-lookahead".to_string())
+            .spawn(move || run_worker(receiver, worker_cache, config));
+
+        if let Err(err) = spawned {
+            tracing::warn!("failed to start derivation lookahead: {}", err);
+        }
+
+        Self { sender, cache }
+    }
+
+    /// Sends the batcher transactions of a fetched L1 block to the worker. Blocks must be
+    /// pushed in order.
+    pub fn push(&self, txs: Vec<Bytes>, l1_origin: u64, l1_timestamp: u64) {
+        _ = self
+            .sender
+            .send(Message::Transactions(BatcherTransactionMessage {
+                txs,
+                l1_origin,
+                l1_timestamp,
+            }));
+    }
+
+    /// Takes the decoded batches of a channel, if the worker decoded it. The channels
+    /// completed in earlier L1 blocks are dropped either way.
+    pub(crate) fn take(&self, channel: &Channel) -> Option<Vec<Batch>> {
+        let key = ChannelKey::from(channel);
+        let mut cache = self.cache.channels.lock().ok()?;
+        cache
+            .entries
+            .retain(|(k, _)| k.l1_inclusion_block >= key.l1_inclusion_block);
+        self.cache.space.notify_all();
+
+        let index = cache.entries.iter().position(|(k, _)| *k == key)?;
+        cache.entries.remove(index).map(|(_, batches)| batches)
+    }
+
+    /// Drops the decoded channels and clears the channel bank of the worker
+    pub(crate) fn purge(&self) {
+        if let Ok(mut cache) = self.cache.channels.lock() {
+            cache.generation += 1;
+            cache.entries.clear();
+        }
+
+        self.cache.space.notify_all();
+        _ = self.sender.send(Message::Purge);
+    }
+}
+
+/// Assembles and decodes the channels of the received batcher transactions until every
+/// [Lookahead] handle is dropped
+fn run_worker(receiver: mpsc::Receiver<Message>, cache: Arc<Cache>, config: Arc<Config>) {
+    let (tx_sender, tx_receiver) = mpsc::channel();
+    let mut channels = Channels::new(BatcherTransactions::new(tx_receiver), config.clone());
+    let mut generation = 0;
+
+    while let Ok(message) = receiver.recv() {
+        let message = match message {
+            Message::Transactions(message) => message,
+            Message::Purge => {
+                channels.purge();
+                generation += 1;
+                continue;
+            }
+        };
+
+        // every call pulls at most one transaction, so this drains the channel bank
+        let mut remaining = message.txs.len().max(1);
+        if tx_sender.send(message).is_err() {
+            return;
+        }
+
+        while remaining > 0 {
+            let Some(channel) = channels.next() else {
+                remaining -= 1;
+                continue;
+            };
+
+            if !wait_for_space(&cache, generation) {
+                return;
+            }
+
+            let Ok(batches) = decode_batches(&channel, config.chain.l2_chain_id) else {
+                continue;
+            };
+
+            let Ok(mut decoded) = cache.channels.lock() else {
+                return;
+            };
+
+            if decoded.generation == generation {
+                decoded
+                    .entries
+                    .push_back((ChannelKey::from(&channel), batches));
+            }
+        }
+    }
+}
+
+/// Waits until the cache has space for another channel or is purged. Returns false once
+/// every [Lookahead] handle is dropped.
+fn wait_for_space(cache: &Arc<Cache>, generation: u64) -> bool {
+    let Ok(mut decoded) = cache.channels.lock() else {
+        return false;
+    };
+
+    while decoded.generation == generation && decoded.entries.len() >= decoded.capacity {
+        if Arc::strong_count(cache) == 1 {
+            return false;
+        }
+
+        decoded = match cache.space.wait_timeout(decoded, WAIT_INTERVAL) {
+            Ok((decoded, _)) => decoded,
+            Err(_) => return false,
+        };
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write, time::Duration};
+
+    use ethers::utils::rlp::RlpStream;
+    use libflate::zlib::Encoder;
+
+    use super::*;
+
+    /// Returns the data of a channel with a single batch
+    fn channel_data(timestamp: u64) -> Vec<u8> {
+        let mut batch = RlpStream::new_list(5);
+        batch
+            .append(&H256::zero())
+            .append(&1u64)
+            .append(&H256::zero())
+            .append(&timestamp)
+            .begin_list(0);
+
+        let batch_data = [vec![0], batch.out().to_vec()].concat();
+        let mut encoder = Encoder::new(Vec::new()).unwrap();
+        encoder
+            .write_all(&ethers::utils::rlp::encode(&batch_data))
+            .unwrap();
+        encoder.finish().into_result().unwrap()
+    }
+
+    /// Returns a batcher transaction holding a channel in a single frame
+    fn batcher_tx(id: u128, data: &[u8]) -> Bytes {
+        let mut tx = vec![0];
+        tx.extend_from_slice(&id.to_be_bytes());
+        tx.extend_from_slice(&0u16.to_be_bytes());
+        tx.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        tx.extend_from_slice(data);
+        tx.push(1);
+        tx.into()
+    }
+
+    fn wait_for(lookahead: &Lookahead, channel: &Channel) -> Option<Vec<Batch>> {
+        for _ in 0..100 {
+            if let Some(batches) = lookahead.take(channel) {
+                return Some(batches);
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        None
+    }
+
+    #[test]
+    fn test_decode_ahead() {
+        let lookahead = Lookahead::start(Arc::new(Config::default()), 4);
+        let channel = Channel {
+            id: 7,
+            data: channel_data(100),
+            l1_inclusion_block: 10,
+        };
+
+        lookahead.push(vec![batcher_tx(7, &channel.data)], 10, 1000);
+        let batches = wait_for(&lookahead, &channel).unwrap();
+        assert!(matches!(&batches[..], [Batch::Single(batch)] if batch.timestamp == 100));
+        assert!(lookahead.take(&channel).is_none());
+
+        // a channel with the same id but different data is not served from the cache
+        let other = Channel {
+            data: channel_data(102),
+            l1_inclusion_block: 11,
+            ..channel.clone()
+        };
+        lookahead.push(vec![batcher_tx(7, &other.data)], 11, 1012);
+        assert!(wait_for(&lookahead, &other).is_some());
+        assert!(lookahead.take(&channel).is_none());
+
+        // purging drops the decoded channels
+        lookahead.push(vec![batcher_tx(7, &channel.data)], 10, 1000);
+        thread::sleep(Duration::from_millis(100));
+        lookahead.purge();
+        assert!(lookahead.take(&channel).is_none());
+    }
+
+    #[test]
+    fn test_backpressure() {
+        let lookahead = Lookahead::start(Arc::new(Config::default()), 2);
+        let channels = (0..5)
+            .map(|i| Channel {
+                id: i as u128,
+                data: channel_data(100 + 2 * i),
+                l1_inclusion_block: 10 + i,
+            })
+            .collect::<Vec<_>>();
+
+        for channel in &channels {
+            let block = channel.l1_inclusion_block;
+            lookahead.push(
+                vec![batcher_tx(channel.id, &channel.data)],
+                block,
+                block * 12,
+            );
+        }
+
+        // the worker stops decoding once the cache is full, instead of dropping the oldest
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(lookahead.cache.channels.lock().unwrap().entries.len(), 2);
+        assert!(lookahead.take(&channels[0]).is_some());
+
+        // and resumes once channels are taken
+        assert!(wait_for(&lookahead, &channels[1]).is_some());
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(lookahead.cache.channels.lock().unwrap().entries.len(), 2);
+
+        // taking a channel drops the older channels derivation passed
+        assert!(wait_for(&lookahead, &channels[3]).is_some());
+        assert!(lookahead.take(&channels[2]).is_none());
+        assert!(wait_for(&lookahead, &channels[4]).is_some());
+    }
+}
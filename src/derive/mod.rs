@@ -1,4 +1,7 @@
-use std::sync::{mpsc, Arc, RwLock};
+use std::{
+    sync::{mpsc, Arc, RwLock},
+    time::Instant,
+};
 
 use bytes::Bytes;
 use eyre::Result;
@@ -6,11 +9,15 @@ use eyre::Result;
 use crate::{config::Config, engine::PayloadAttributes};
 
 use self::{
+    profile::{Profiled, Profiler, Stage},
     stages::{
         attributes::Attributes,
-        batcher_transactions::{BatcherTransactionMessage, BatcherTransactions},
+        batcher_transactions::{
+            BatcherTransaction, BatcherTransactionMessage, BatcherTransactions,
+        },
         batches::Batches,
-        channels::Channels,
+        block_input::BlockInput,
+        channels::{Channel, Channels},
     },
     state::State,
 };
@@ -20,14 +27,43 @@ pub mod stages;
 /// A module that keeps track of the current derivation state, caching previous L1 and L2 blocks
 pub mod state;
 
+/// A module that classifies the errors of the derivation loop
+pub mod error;
+pub use error::{DerivationError, DerivationErrors};
+
 /// A module that handles the Ecotone hardfork upgrade
 pub mod ecotone_upgrade;
 pub use ecotone_upgrade::get_ecotone_upgrade_transactions;
 
+/// A module that measures the throughput of the derivation pipeline
+pub mod profile;
+
+/// A module that reports the blocks derived deposit-only
+pub mod forced_inclusion;
+pub use forced_inclusion::ForcedInclusionReport;
+
+/// A module that overrides the gas limit of the blocks built by the sequencer
+mod overrides;
+pub use overrides::{AttributeOverrides, SequencerOverrides};
+
+/// A module that decodes channels ahead of the pipeline in the background
+mod lookahead;
+pub use lookahead::Lookahead;
+
+/// A module that bounds the work of a single step of the pipeline
+mod step;
+pub use step::{StepBudget, StepResult};
+
 /// A module that extends the [Iterator] trait with a `purge` method
 mod purgeable;
 pub use purgeable::PurgeableIterator;
 
+/// A pipeline stage pulling items from the previous stage
+pub type BoxedStage<T> = Box<dyn PurgeableIterator<Item = T>>;
+
+/// A function inserting a custom stage after a built-in one, wrapping its output
+type StageFn<T> = Box<dyn FnOnce(BoxedStage<T>) -> BoxedStage<T>>;
+
 /// The derivation pipeline is iterated on to update attributes for new blocks.
 pub struct Pipeline {
     /// A channel sender to send a `BatcherTransactionMessage`
@@ -36,18 +72,29 @@ pub struct Pipeline {
     attributes: Attributes,
     /// Pending `PayloadAttributes`
     pending_attributes: Option<PayloadAttributes>,
+    /// Profiles the stages if `profile_derivation` is enabled
+    profiler: Option<Arc<Profiler>>,
+    /// Decodes channels ahead of the pipeline if `derivation_lookahead` is set
+    lookahead: Option<Lookahead>,
+    /// Bounds the work of a step to `derivation_step_frames` frames
+    budget: Arc<StepBudget>,
+    /// Number of steps taken since the pipeline was created
+    steps: u64,
 }
 
 impl Iterator for Pipeline {
     type Item = PayloadAttributes;
 
     /// Returns the pending [PayloadAttributes].
-    /// If none exist it will call `Attributes::next()` to advance to the next block and return those attributes instead.
+    /// If none exist it steps the pipeline until the next attributes are derived or the
+    /// buffered input is consumed.
     fn next(&mut self) -> Option<Self::Item> {
-        if self.pending_attributes.is_some() {
-            self.pending_attributes.take()
-        } else {
-            self.attributes.next()
+        loop {
+            match self.step() {
+                StepResult::Attributes(attributes) => return Some(*attributes),
+                StepResult::Progress => continue,
+                StepResult::Idle => return None,
+            }
         }
     }
 }
@@ -55,26 +102,85 @@ impl Iterator for Pipeline {
 impl Pipeline {
     /// Creates a new [Pipeline] and initializes Batcher Transactions, [Channels], [Batches], and [Attributes]
     pub fn new(state: Arc<RwLock<State>>, config: Arc<Config>, seq: u64) -> Result<Self> {
-        let (tx, rx) = mpsc::channel();
-        let batcher_transactions = BatcherTransactions::new(rx);
-        let channels = Channels::new(batcher_transactions, config.clone());
-        let batches = Batches::new(channels, state.clone(), config.clone());
-        let attributes = Attributes::new(Box::new(batches), state, config, seq);
+        Self::builder(state, config, seq).build()
+    }
 
-        Ok(Self {
-            batcher_transaction_sender: tx,
-            attributes,
-            pending_attributes: None,
-        })
+    /// Returns a [PipelineBuilder], to insert custom stages between the built-in ones
+    pub fn builder(state: Arc<RwLock<State>>, config: Arc<Config>, seq: u64) -> PipelineBuilder {
+        PipelineBuilder {
+            state,
+            config,
+            seq,
+            batcher_transaction_stages: Vec::new(),
+            channel_stages: Vec::new(),
+            batch_stages: Vec::new(),
+        }
     }
 
     /// Sends Batcher Transactions & the L1 block they were received in to the Batcher Transactions receiver.
-    pub fn push_batcher_transactions(&self, txs: Vec<Bytes>, l1_origin: u64) -> Result<()> {
+    pub fn push_batcher_transactions(
+        &self,
+        txs: Vec<Bytes>,
+        l1_origin: u64,
+        l1_timestamp: u64,
+    ) -> Result<()> {
         self.batcher_transaction_sender
-            .send(BatcherTransactionMessage { txs, l1_origin })?;
+            .send(BatcherTransactionMessage {
+                txs,
+                l1_origin,
+                l1_timestamp,
+            })?;
+        self.budget.set_buffered_txs(true);
         Ok(())
     }
 
+    /// Advances the pipeline by a bounded amount of work: at most one batcher transaction,
+    /// `derivation_step_frames` frames, one channel and one batch, so callers can handle
+    /// other work between steps. The pending [PayloadAttributes] are returned first.
+    ///
+    /// A step never waits for I/O, and the number of steps taken to consume the same L1
+    /// data is always the same, see [Pipeline::steps].
+    pub fn step(&mut self) -> StepResult {
+        self.steps += 1;
+        if let Some(attributes) = self.pending_attributes.take() {
+            return StepResult::Attributes(Box::new(attributes));
+        }
+
+        self.budget.start_step();
+        let attributes = match &self.profiler {
+            Some(profiler) => {
+                let start = Instant::now();
+                let attributes = self.attributes.next();
+                profiler.record(Stage::Attributes, start.elapsed());
+
+                if attributes.is_some() {
+                    profiler.record_block();
+                }
+                profiler.maybe_report();
+
+                attributes
+            }
+            None => self.attributes.next(),
+        };
+
+        match attributes {
+            Some(attributes) => StepResult::Attributes(Box::new(attributes)),
+            None if self.error().is_none()
+                && self.budget.is_consumed()
+                && self.budget.is_buffered() =>
+            {
+                StepResult::Progress
+            }
+            None => StepResult::Idle,
+        }
+    }
+
+    /// Returns the number of steps taken since the pipeline was created, including the
+    /// steps taken by `next`
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
     /// Returns a reference to the pending [PayloadAttributes].
     /// If none are pending, it will call `self.next()` to advance to the next block and return those attributes instead.
     pub fn peek(&mut self) -> Option<&PayloadAttributes> {
@@ -86,18 +192,160 @@ impl Pipeline {
         self.pending_attributes.as_ref()
     }
 
-    /// Resets the state of `self.attributes` by calling `Attributes::purge()`
+    /// Returns true if [PayloadAttributes] can be derived from the ingested L1 data.
+    ///
+    /// Deriving the next attributes needs the safe head the previous ones produce, so at
+    /// most one is derived ahead and kept pending. Callers should hand the pending
+    /// attributes to the engine before ingesting more L1 blocks, so that L1 data waits
+    /// outside the pipeline while the engine is busy.
+    pub fn has_attributes(&mut self) -> bool {
+        self.peek().is_some()
+    }
+
+    /// Hands back attributes taken from the pipeline but not applied, such as after a
+    /// temporary engine failure, so the next call to `next` returns them again
+    pub fn retry(&mut self, attributes: PayloadAttributes) {
+        self.pending_attributes = Some(attributes);
+    }
+
+    /// Returns the error that stopped the pipeline, if any. A stopped pipeline derives no
+    /// attributes until it is purged.
+    pub fn error(&self) -> Option<&DerivationError> {
+        self.attributes.error()
+    }
+
+    /// Returns the [Lookahead] to push the batcher transactions of fetched L1 blocks to, if
+    /// enabled
+    pub fn lookahead(&self) -> Option<Lookahead> {
+        self.lookahead.clone()
+    }
+
+    /// Resets the state of `self.attributes` by calling `Attributes::purge()`, dropping
+    /// the pending [PayloadAttributes]
     pub fn purge(&mut self) -> Result<()> {
+        self.pending_attributes = None;
         self.attributes.purge();
+        self.budget.reset();
         Ok(())
     }
 }
 
-#[cfg(test)]
+/// Builds a [Pipeline] with custom stages, such as a batch filter, a metrics tap or an
+/// alt-DA resolver, inserted between the built-in stages.
+///
+/// A custom stage receives the output of the previous stage as a [BoxedStage] and returns
+/// the stage the next one pulls from, forwarding `purge` to the stage it wraps. Stages
+/// inserted after the same built-in stage run in the order they were added. When
+/// `profile_derivation` is enabled, the time of a custom stage is attributed to the
+/// built-in stage after it.
+pub struct PipelineBuilder {
+    /// The derivation state shared with the driver
+    state: Arc<RwLock<State>>,
+    /// The global Magi [Config]
+    config: Arc<Config>,
+    /// The sequence number of the safe head
+    seq: u64,
+    /// Stages inserted after the batcher transactions stage
+    batcher_transaction_stages: Vec<StageFn<BatcherTransaction>>,
+    /// Stages inserted after the channels stage
+    channel_stages: Vec<StageFn<Channel>>,
+    /// Stages inserted after the batches stage
+    batch_stages: Vec<StageFn<BlockInput<u64>>>,
+}
+
+impl PipelineBuilder {
+    /// Inserts a stage between the batcher transactions and the channels stages
+    pub fn with_batcher_transaction_stage(
+        mut self,
+        stage: impl FnOnce(BoxedStage<BatcherTransaction>) -> BoxedStage<BatcherTransaction> + 'static,
+    ) -> Self {
+        self.batcher_transaction_stages.push(Box::new(stage));
+        self
+    }
+
+    /// Inserts a stage between the channels and the batches stages
+    pub fn with_channel_stage(
+        mut self,
+        stage: impl FnOnce(BoxedStage<Channel>) -> BoxedStage<Channel> + 'static,
+    ) -> Self {
+        self.channel_stages.push(Box::new(stage));
+        self
+    }
+
+    /// Inserts a stage between the batches and the attributes stages
+    pub fn with_batch_stage(
+        mut self,
+        stage: impl FnOnce(BoxedStage<BlockInput<u64>>) -> BoxedStage<BlockInput<u64>> + 'static,
+    ) -> Self {
+        self.batch_stages.push(Box::new(stage));
+        self
+    }
+
+    /// Builds the [Pipeline]
+    pub fn build(self) -> Result<Pipeline> {
+        let profiler = self
+            .config
+            .profile_derivation
+            .then(|| Arc::new(Profiler::new()));
+        let lookahead = (self.config.derivation_lookahead > 0)
+            .then(|| Lookahead::start(self.config.clone(), self.config.derivation_lookahead));
+
+        let budget = Arc::new(StepBudget::new(self.config.derivation_step_frames));
+
+        let (tx, rx) = mpsc::channel();
+        let batcher_transactions = wrap(
+            Box::new(Profiled::new(
+                BatcherTransactions::new(rx).with_budget(budget.clone()),
+                Stage::BatcherTransactions,
+                profiler.clone(),
+            )),
+            self.batcher_transaction_stages,
+        );
+        let channels = wrap(
+            Box::new(Profiled::new(
+                Channels::new(batcher_transactions, self.config.clone())
+                    .with_budget(budget.clone()),
+                Stage::Channels,
+                profiler.clone(),
+            )),
+            self.channel_stages,
+        );
+        let mut batches = Batches::new(channels, self.state.clone(), self.config.clone())
+            .with_budget(budget.clone());
+        if let Some(lookahead) = &lookahead {
+            batches = batches.with_lookahead(lookahead.clone());
+        }
+        let batches = wrap(
+            Box::new(Profiled::new(batches, Stage::Batches, profiler.clone())),
+            self.batch_stages,
+        );
+        let attributes = Attributes::new(batches, self.state, self.config, self.seq);
+
+        Ok(Pipeline {
+            batcher_transaction_sender: tx,
+            attributes,
+            pending_attributes: None,
+            profiler,
+            lookahead,
+            budget,
+            steps: 0,
+        })
+    }
+}
+
+/// Wraps a built-in stage in the custom stages inserted after it
+fn wrap<T>(stage: BoxedStage<T>, custom: Vec<StageFn<T>>) -> BoxedStage<T> {
+    custom.into_iter().fold(stage, |stage, wrap| wrap(stage))
+}
+
+#[cfg(all(test, feature = "node"))]
 mod tests {
     use std::{
         env,
-        sync::{Arc, RwLock},
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            Arc, RwLock,
+        },
     };
 
     use ethers::{
@@ -108,7 +356,7 @@ mod tests {
 
     use crate::{
         common::RawTransaction,
-        config::{ChainConfig, Config},
+        config::{ChainConfig, Config, WatchdogAction},
         derive::*,
         l1::{BlockUpdate, ChainWatcher},
     };
@@ -125,11 +373,59 @@ mod tests {
                 l2_rpc_url: l2_rpc.to_string(),
                 chain: ChainConfig::optimism_sepolia(),
                 l2_engine_url: String::new(),
-                jwt_secret: String::new(),
+                jwt_secret: None,
                 checkpoint_sync_url: None,
                 rpc_port: 9545,
                 rpc_addr: "127.0.0.1".to_string(),
                 devnet: false,
+                supervisor_rpc_url: None,
+                p2p_port: 9876,
+                l2_engine_standby_urls: Vec::new(),
+                forkchoice_batch_size: 16,
+                forkchoice_batch_interval_ms: 200,
+                jwt_secret_file: None,
+                l1_rpc_budget: None,
+                l1_rpc_method_weights: Default::default(),
+                data_dir: None,
+                l1_epoch_retention: None,
+                l2_block_retention: None,
+                p2p_sync: false,
+                p2p_max_inbound_peers: 30,
+                p2p_max_outbound_peers: 20,
+                p2p_static_peers: Vec::new(),
+                p2p_bootnodes: Vec::new(),
+                sequencer: false,
+                p2p_sequencer_key: None,
+                p2p_sequencer_keystore: None,
+                p2p_sequencer_keystore_password_file: None,
+                p2p_sequencer_signer_url: None,
+                p2p_sequencer_signer_backup_url: None,
+                p2p_sequencer_address: None,
+                profile_derivation: false,
+                unsafe_follow_url: None,
+                unsafe_follow_max_lag: None,
+                readiness_max_head_lag: 60,
+                readiness_max_stall: 120,
+                rpc_api: vec!["optimism".to_string(), "opp2p".to_string()],
+                rpc_auth_token: None,
+                rpc_jwt_secret: None,
+                rpc_cors_origins: Vec::new(),
+                rpc_rate_limit: None,
+                l1_block_buffer: 64,
+                light_follow: false,
+                derivation_lookahead: 0,
+                sequencer_gas_limit: None,
+                rpc_precompute_outputs: false,
+                watchdog_stall_timeout: 180,
+                watchdog_action: WatchdogAction::Log,
+                rpc_sequencer_url: None,
+                rpc_sequencer_rate_limit: None,
+                driver_tick_timeout: 30,
+                dry_run: false,
+                dry_run_until: None,
+                unsafe_payload_retention: 256,
+                derivation_step_frames: 64,
+                halt_on_unsupported_fork: false,
             });
 
             let mut chain_watcher = ChainWatcher::new(
@@ -147,6 +443,7 @@ mod tests {
                     config.chain.l2_genesis,
                     config.chain.l1_start_epoch,
                     &provider,
+                    None,
                     config.clone(),
                 )
                 .await,
@@ -166,6 +463,7 @@ mod tests {
                 .push_batcher_transactions(
                     l1_info.batcher_transactions.clone(),
                     l1_info.block_info.number,
+                    l1_info.block_info.timestamp,
                 )
                 .unwrap();
 
@@ -196,4 +494,223 @@ mod tests {
             .map(|tx| H256::from_slice(&keccak256(&tx.0)))
             .collect()
     }
+    /// A custom stage counting the items passing through it and whether it was purged
+    struct Tap<I> {
+        inner: I,
+        seen: Arc<AtomicUsize>,
+        purged: Arc<AtomicBool>,
+    }
+
+    impl<I: Iterator> Iterator for Tap<I> {
+        type Item = I::Item;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            let item = self.inner.next();
+            if item.is_some() {
+                self.seen.fetch_add(1, Ordering::Relaxed);
+            }
+            item
+        }
+    }
+
+    impl<I: PurgeableIterator> PurgeableIterator for Tap<I> {
+        fn purge(&mut self) {
+            self.purged.store(true, Ordering::Relaxed);
+            self.inner.purge();
+        }
+    }
+
+    /// A custom stage dropping every item
+    struct DropAll<I>(I);
+
+    impl<I: Iterator> Iterator for DropAll<I> {
+        type Item = I::Item;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            for _ in self.0.by_ref() {}
+            None
+        }
+    }
+
+    impl<I: PurgeableIterator> PurgeableIterator for DropAll<I> {
+        fn purge(&mut self) {
+            self.0.purge();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_stages() -> eyre::Result<()> {
+        let config = Arc::new(Config {
+            l2_rpc_url: "http://127.0.0.1:1".to_string(),
+            chain: ChainConfig::optimism_sepolia(),
+            ..Default::default()
+        });
+        let provider = Provider::try_from(config.l2_rpc_url.as_str())?;
+        let state = Arc::new(RwLock::new(
+            State::new(
+                config.chain.l2_genesis,
+                config.chain.l1_start_epoch,
+                &provider,
+                None,
+                config.clone(),
+            )
+            .await,
+        ));
+
+        let data = Bytes::from(hex::decode(
+            include_str!("../../benches/data/single_batch_tx.hex").trim(),
+        )?);
+
+        let seen = Arc::new(AtomicUsize::new(0));
+        let purged = Arc::new(AtomicBool::new(false));
+        let (tap_seen, tap_purged) = (seen.clone(), purged.clone());
+        let mut pipeline = Pipeline::builder(state.clone(), config.clone(), 0)
+            .with_channel_stage(move |channels| {
+                Box::new(Tap {
+                    inner: channels,
+                    seen: tap_seen,
+                    purged: tap_purged,
+                })
+            })
+            .build()?;
+
+        pipeline.push_batcher_transactions(vec![data.clone()], 1, 0)?;
+        while pipeline.next().is_some() {}
+        assert_eq!(seen.load(Ordering::Relaxed), 1);
+
+        pipeline.purge()?;
+        assert!(purged.load(Ordering::Relaxed));
+
+        // a stage dropping every batcher transaction leaves no channels
+        let seen = Arc::new(AtomicUsize::new(0));
+        let tap_seen = seen.clone();
+        let mut pipeline = Pipeline::builder(state, config, 0)
+            .with_batcher_transaction_stage(|txs| Box::new(DropAll(txs)))
+            .with_channel_stage(move |channels| {
+                Box::new(Tap {
+                    inner: channels,
+                    seen: tap_seen,
+                    purged: Default::default(),
+                })
+            })
+            .build()?;
+
+        pipeline.push_batcher_transactions(vec![data], 1, 0)?;
+        while pipeline.next().is_some() {}
+        assert_eq!(seen.load(Ordering::Relaxed), 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_granite_channel_timeout() -> eyre::Result<()> {
+        let mut chain = ChainConfig::optimism_sepolia();
+        chain.hardforks.granite_time = Some(1000);
+        let config = Arc::new(Config {
+            l2_rpc_url: "http://127.0.0.1:1".to_string(),
+            chain,
+            ..Default::default()
+        });
+        let provider = Provider::try_from(config.l2_rpc_url.as_str())?;
+        let state = Arc::new(RwLock::new(
+            State::new(
+                config.chain.l2_genesis,
+                config.chain.l1_start_epoch,
+                &provider,
+                None,
+                config.clone(),
+            )
+            .await,
+        ));
+
+        // split the single frame of the batcher transaction into two frames
+        let data = hex::decode(include_str!("../../benches/data/single_batch_tx.hex").trim())?;
+        let (channel_id, frame_data) = (&data[1..17], &data[23..data.len() - 1]);
+        let (first, second) = frame_data.split_at(frame_data.len() / 2);
+        let frame = |number: u16, frame_data: &[u8], is_last: bool| {
+            let mut tx = vec![0];
+            tx.extend_from_slice(channel_id);
+            tx.extend_from_slice(&number.to_be_bytes());
+            tx.extend_from_slice(&(frame_data.len() as u32).to_be_bytes());
+            tx.extend_from_slice(frame_data);
+            tx.push(is_last as u8);
+            Bytes::from(tx)
+        };
+
+        // derives the channel with its frames included 60 L1 blocks apart, the last one at
+        // the given L1 timestamp
+        let derive = |last_timestamp: u64| -> eyre::Result<usize> {
+            let seen = Arc::new(AtomicUsize::new(0));
+            let tap_seen = seen.clone();
+            let mut pipeline = Pipeline::builder(state.clone(), config.clone(), 0)
+                .with_channel_stage(move |channels| {
+                    Box::new(Tap {
+                        inner: channels,
+                        seen: tap_seen,
+                        purged: Default::default(),
+                    })
+                })
+                .build()?;
+
+            pipeline.push_batcher_transactions(vec![frame(0, first, false)], 1, 900)?;
+            pipeline.push_batcher_transactions(vec![frame(1, second, true)], 61, last_timestamp)?;
+            while pipeline.next().is_some() {}
+            Ok(seen.load(Ordering::Relaxed))
+        };
+
+        // before granite the channel completes within the 300 block timeout, and from
+        // granite it expires after 50 L1 blocks
+        assert_eq!(derive(988)?, 1);
+        assert_eq!(derive(1000)?, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_step() -> eyre::Result<()> {
+        let config = Arc::new(Config {
+            l2_rpc_url: "http://127.0.0.1:1".to_string(),
+            chain: ChainConfig::optimism_sepolia(),
+            ..Default::default()
+        });
+        let provider = Provider::try_from(config.l2_rpc_url.as_str())?;
+        let state = Arc::new(RwLock::new(
+            State::new(
+                config.chain.l2_genesis,
+                config.chain.l1_start_epoch,
+                &provider,
+                None,
+                config.clone(),
+            )
+            .await,
+        ));
+
+        let data = Bytes::from(hex::decode(
+            include_str!("../../benches/data/single_batch_tx.hex").trim(),
+        )?);
+
+        let mut pipeline = Pipeline::new(state.clone(), config.clone(), 0)?;
+        assert_eq!(pipeline.step(), StepResult::Idle);
+
+        // a step reads a single batcher transaction, and reports the one still buffered
+        pipeline.push_batcher_transactions(vec![data.clone()], 1, 0)?;
+        pipeline.push_batcher_transactions(vec![data.clone()], 2, 0)?;
+        assert_eq!(pipeline.step(), StepResult::Progress);
+        assert_eq!(pipeline.step(), StepResult::Idle);
+        assert_eq!(pipeline.steps(), 3);
+
+        // iterating takes the same steps
+        let mut pipeline = Pipeline::new(state, config, 0)?;
+        pipeline.push_batcher_transactions(vec![data.clone()], 1, 0)?;
+        pipeline.push_batcher_transactions(vec![data.clone()], 2, 0)?;
+        assert!(pipeline.next().is_none());
+        assert_eq!(pipeline.steps(), 2);
+
+        // purging drops the buffered input
+        pipeline.push_batcher_transactions(vec![data], 3, 0)?;
+        pipeline.purge()?;
+        assert_eq!(pipeline.step(), StepResult::Idle);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,85 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, RwLock,
+};
+
+use ethers::types::U64;
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Config, engine::PayloadAttributes, l1::L1Info};
+
+/// The gas limit of the blocks built by the sequencer, replacing the gas limit of the
+/// genesis system config
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SequencerOverrides {
+    /// The gas limit of every block, until the gas limit is set on L1
+    pub gas_limit: Option<u64>,
+}
+
+/// The [SequencerOverrides] applied to the attributes built by the sequencer, shared with
+/// the admin RPC server.
+///
+/// Derived attributes never use the overrides, since derivation takes the gas limit from
+/// the L1 system config. The override only bridges the time until the gas limit is set
+/// through `setGasLimit` on L1, and stays disabled for good once the system config holds a
+/// gas limit other than the genesis one, so verifiers derive the same gas limit as the
+/// sequencer from then on.
+#[derive(Debug, Clone, Default)]
+pub struct AttributeOverrides {
+    /// The current overrides
+    overrides: Arc<RwLock<SequencerOverrides>>,
+    /// Whether the gas limit was set on L1, disabling the gas limit override
+    gas_limit_set: Arc<AtomicBool>,
+}
+
+impl AttributeOverrides {
+    /// Creates the overrides from the config
+    pub fn from_config(config: &Config) -> Self {
+        let overrides = SequencerOverrides {
+            gas_limit: config.sequencer_gas_limit,
+        };
+
+        Self {
+            overrides: Arc::new(RwLock::new(overrides)),
+            gas_limit_set: Default::default(),
+        }
+    }
+
+    /// Returns the current overrides
+    pub fn get(&self) -> SequencerOverrides {
+        *self.overrides.read().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Replaces the overrides, starting from the next block built
+    pub fn set(&self, overrides: SequencerOverrides) -> Result<()> {
+        if overrides.gas_limit == Some(0) {
+            eyre::bail!("the gas limit override must not be zero");
+        }
+
+        tracing::info!("sequencer overrides set to {:?}", overrides);
+        *self
+            .overrides
+            .write()
+            .unwrap_or_else(|err| err.into_inner()) = overrides;
+
+        Ok(())
+    }
+
+    /// Applies the overrides to the attributes of a block built on the given L1 origin
+    pub fn apply(&self, attributes: &mut PayloadAttributes, l1_info: &L1Info, config: &Config) {
+        let genesis_gas_limit = config.chain.system_config.gas_limit;
+        if l1_info.system_config.gas_limit != genesis_gas_limit {
+            self.gas_limit_set.store(true, Ordering::Relaxed);
+        }
+
+        if self.gas_limit_set.load(Ordering::Relaxed) {
+            return;
+        }
+
+        if let Some(gas_limit) = self.get().gas_limit {
+            attributes.gas_limit = U64::from(gas_limit);
+        }
+    }
+}
@@ -0,0 +1,283 @@
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::telemetry::allocations::{self, AllocationStats};
+
+use super::PurgeableIterator;
+
+/// Interval between two reports of the derivation profile
+const REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A stage of the derivation pipeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Decodes the frames of batcher transactions
+    BatcherTransactions,
+    /// Assembles frames into channels
+    Channels,
+    /// Decodes and validates batches
+    Batches,
+    /// Builds the payload attributes
+    Attributes,
+}
+
+impl Stage {
+    /// All stages, from the first to the last
+    const ALL: [Stage; 4] = [
+        Stage::BatcherTransactions,
+        Stage::Channels,
+        Stage::Batches,
+        Stage::Attributes,
+    ];
+
+    /// Returns the name of the stage
+    fn name(&self) -> &'static str {
+        match self {
+            Stage::BatcherTransactions => "batcher transactions",
+            Stage::Channels => "channels",
+            Stage::Batches => "batches",
+            Stage::Attributes => "attributes",
+        }
+    }
+}
+
+/// Measures the throughput of the derivation pipeline, the time spent in each stage and
+/// the allocations made meanwhile, enabled with `profile_derivation`.
+///
+/// Stages pull from the previous stage, so the time recorded for a stage includes the
+/// time of the stages before it. Reports subtract it to show the time of each stage alone.
+#[derive(Debug)]
+pub struct Profiler {
+    /// Nanoseconds spent in each stage and the stages before it
+    stage_nanos: [AtomicU64; 4],
+    /// Number of derived blocks
+    blocks: AtomicU64,
+    /// The totals at the previous report
+    last_report: Mutex<Snapshot>,
+}
+
+/// The totals of a [Profiler] at some point in time
+#[derive(Debug, Clone, Copy)]
+struct Snapshot {
+    /// When the snapshot was taken
+    time: Instant,
+    /// Nanoseconds spent in each stage and the stages before it
+    stage_nanos: [u64; 4],
+    /// Number of derived blocks
+    blocks: u64,
+    /// Allocations made by the process
+    allocations: AllocationStats,
+}
+
+impl Profiler {
+    /// Creates a new [Profiler] and starts counting allocations
+    pub fn new() -> Self {
+        allocations::enable();
+
+        let profiler = Self {
+            stage_nanos: Default::default(),
+            blocks: AtomicU64::new(0),
+            last_report: Mutex::new(Snapshot {
+                time: Instant::now(),
+                stage_nanos: [0; 4],
+                blocks: 0,
+                allocations: AllocationStats::default(),
+            }),
+        };
+
+        *profiler.last_report.lock().unwrap() = profiler.snapshot();
+        profiler
+    }
+
+    /// Records time spent in a stage, including the stages before it
+    pub fn record(&self, stage: Stage, elapsed: Duration) {
+        self.stage_nanos[stage as usize].fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Records a derived block
+    pub fn record_block(&self) {
+        self.blocks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the profile since the previous report and starts a new one
+    pub fn report(&self) -> ProfileReport {
+        let now = self.snapshot();
+        let mut last_report = self.last_report.lock().unwrap();
+        let report = ProfileReport::between(&last_report, &now);
+        *last_report = now;
+
+        report
+    }
+
+    /// Logs the profile since the previous report, if the report interval has elapsed
+    pub fn maybe_report(&self) {
+        let due = self.last_report.lock().unwrap().time.elapsed() >= REPORT_INTERVAL;
+        if due {
+            tracing::info!("derivation profile: {}", self.report());
+        }
+    }
+
+    /// Returns the current totals
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            time: Instant::now(),
+            stage_nanos: std::array::from_fn(|i| self.stage_nanos[i].load(Ordering::Relaxed)),
+            blocks: self.blocks.load(Ordering::Relaxed),
+            allocations: AllocationStats::now(),
+        }
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The derivation profile over an interval
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileReport {
+    /// Length of the interval
+    pub elapsed: Duration,
+    /// Number of blocks derived
+    pub blocks: u64,
+    /// Time spent in each stage alone, from the first to the last
+    pub stage_times: Vec<(Stage, Duration)>,
+    /// Allocations made by the process
+    pub allocations: AllocationStats,
+}
+
+impl ProfileReport {
+    /// Returns the profile between two snapshots
+    fn between(start: &Snapshot, end: &Snapshot) -> Self {
+        let inclusive: [u64; 4] =
+            std::array::from_fn(|i| end.stage_nanos[i].saturating_sub(start.stage_nanos[i]));
+
+        let stage_times = Stage::ALL
+            .iter()
+            .enumerate()
+            .map(|(i, stage)| {
+                let before = if i == 0 { 0 } else { inclusive[i - 1] };
+                let nanos = inclusive[i].saturating_sub(before);
+                (*stage, Duration::from_nanos(nanos))
+            })
+            .collect();
+
+        Self {
+            elapsed: end.time.saturating_duration_since(start.time),
+            blocks: end.blocks - start.blocks,
+            stage_times,
+            allocations: end.allocations.since(&start.allocations),
+        }
+    }
+
+    /// Returns the number of blocks derived per second
+    pub fn blocks_per_second(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.blocks as f64 / secs
+        } else {
+            0.0
+        }
+    }
+}
+
+impl fmt::Display for ProfileReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.1} blocks/s", self.blocks_per_second())?;
+        for (stage, time) in &self.stage_times {
+            write!(f, ", {} {:.1}ms", stage.name(), time.as_secs_f64() * 1000.0)?;
+        }
+
+        write!(
+            f,
+            ", {} allocations ({:.1} MB)",
+            self.allocations.allocations,
+            self.allocations.bytes as f64 / 1_000_000.0
+        )
+    }
+}
+
+/// A pipeline stage recording the time of each step in a [Profiler], if one is set
+#[derive(Debug)]
+pub struct Profiled<I> {
+    /// The profiled stage
+    inner: I,
+    /// Which stage is profiled
+    stage: Stage,
+    /// The profiler to record in
+    profiler: Option<Arc<Profiler>>,
+}
+
+impl<I> Profiled<I> {
+    /// Creates a new [Profiled] stage
+    pub fn new(inner: I, stage: Stage, profiler: Option<Arc<Profiler>>) -> Self {
+        Self {
+            inner,
+            stage,
+            profiler,
+        }
+    }
+}
+
+impl<I: Iterator> Iterator for Profiled<I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Some(profiler) = &self.profiler else {
+            return self.inner.next();
+        };
+
+        let start = Instant::now();
+        let item = self.inner.next();
+        profiler.record(self.stage, start.elapsed());
+
+        item
+    }
+}
+
+impl<I: PurgeableIterator> PurgeableIterator for Profiled<I> {
+    fn purge(&mut self) {
+        self.inner.purge();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let profiler = Profiler::new();
+        profiler.record(Stage::BatcherTransactions, Duration::from_millis(2));
+        profiler.record(Stage::Channels, Duration::from_millis(5));
+        profiler.record(Stage::Batches, Duration::from_millis(9));
+        profiler.record(Stage::Attributes, Duration::from_millis(10));
+        profiler.record_block();
+        profiler.record_block();
+
+        let report = profiler.report();
+        assert_eq!(report.blocks, 2);
+        assert_eq!(
+            report.stage_times,
+            vec![
+                (Stage::BatcherTransactions, Duration::from_millis(2)),
+                (Stage::Channels, Duration::from_millis(3)),
+                (Stage::Batches, Duration::from_millis(4)),
+                (Stage::Attributes, Duration::from_millis(1)),
+            ]
+        );
+        assert!(report.to_string().contains("batches 4.0ms"));
+
+        // a new report starts from the previous one
+        let report = profiler.report();
+        assert_eq!(report.blocks, 0);
+        assert!(report.stage_times.iter().all(|(_, time)| time.is_zero()));
+    }
+}
@@ -1,5 +1,12 @@
 /// Iterator that can purge itself
 pub trait PurgeableIterator: Iterator {
-    /// Purges and resets an iterator
+    /// Purges and resets an iterator, dropping partially consumed items such as the
+    /// remaining blocks of a span batch
     fn purge(&mut self);
 }
+
+impl<I: PurgeableIterator + ?Sized> PurgeableIterator for Box<I> {
+    fn purge(&mut self) {
+        (**self).purge();
+    }
+}
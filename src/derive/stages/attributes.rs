@@ -9,7 +9,9 @@ use eyre::Result;
 use crate::common::{Epoch, RawTransaction};
 use crate::config::{Config, SystemAccounts};
 use crate::derive::state::State;
-use crate::derive::{get_ecotone_upgrade_transactions, PurgeableIterator};
+use crate::derive::{
+    get_ecotone_upgrade_transactions, AttributeOverrides, DerivationError, PurgeableIterator,
+};
 use crate::engine::PayloadAttributes;
 use crate::l1::L1Info;
 
@@ -27,17 +29,36 @@ pub struct Attributes {
     epoch_hash: H256,
     /// The global Magi [Config]
     config: Arc<Config>,
+    /// The error that stopped the stage, until it is purged
+    error: Option<DerivationError>,
 }
 
 impl Iterator for Attributes {
     type Item = PayloadAttributes;
 
     /// Iterates over the next BlockInput and returns the [PayLoadAttributes](struct@PayloadAttributes) from this block.
+    /// Returns `None` once the L1 origin of a block is missing, until the stage is purged.
     fn next(&mut self) -> Option<Self::Item> {
-        self.block_input_iter
-            .next()
-            .map(|input| input.with_full_epoch(&self.state).unwrap())
-            .map(|batch| self.derive_attributes(batch))
+        if self.error.is_some() {
+            return None;
+        }
+
+        let input = self.block_input_iter.next()?;
+        let result = input
+            .with_full_epoch(&self.state)
+            .and_then(|input| self.derive_attributes(input));
+
+        match result {
+            Ok(attributes) => Some(attributes),
+            Err(err) => {
+                let error = match err.downcast_ref::<DerivationError>() {
+                    Some(error) => error.clone(),
+                    None => DerivationError::reset(err),
+                };
+                self.error = Some(error);
+                None
+            }
+        }
     }
 }
 
@@ -47,6 +68,7 @@ impl PurgeableIterator for Attributes {
         self.block_input_iter.purge();
         self.sequence_number = 0;
         self.epoch_hash = self.state.read().unwrap().safe_epoch.hash;
+        self.error = None;
     }
 }
 
@@ -66,49 +88,49 @@ impl Attributes {
             sequence_number: seq,
             epoch_hash,
             config,
+            error: None,
         }
     }
 
+    /// Returns the error that stopped the stage, if any
+    pub fn error(&self) -> Option<&DerivationError> {
+        self.error.as_ref()
+    }
+
     /// Processes a given Block Input and returns [PayloadAttributes] for the block.
     ///
-    /// Calls `derive_transactions` to generate the raw transactions
-    fn derive_attributes(&mut self, input: BlockInput<Epoch>) -> PayloadAttributes {
+    /// Calls `derive_transactions` to generate the raw transactions. Fails if the L1 origin
+    /// of the block is not in the derivation state.
+    fn derive_attributes(&mut self, input: BlockInput<Epoch>) -> Result<PayloadAttributes> {
         tracing::debug!("deriving attributes from block: {}", input.epoch.number);
         tracing::debug!("batch epoch hash: {:?}", input.epoch.hash);
 
         self.update_sequence_number(input.epoch.hash);
 
-        let state = self.state.read().unwrap();
-        let l1_info = state.l1_info_by_hash(input.epoch.hash).unwrap();
+        let state = self
+            .state
+            .read()
+            .map_err(|_| eyre::eyre!("lock poisoned"))?;
+        let l1_info = state
+            .l1_info_by_hash(input.epoch.hash)
+            .ok_or(eyre::eyre!("L1 origin {} not found", input.epoch.number))?;
 
-        let withdrawals = if input.timestamp >= self.config.chain.canyon_time {
-            Some(Vec::new())
-        } else {
-            None
-        };
-
-        let timestamp = U64([input.timestamp]);
+        let timestamp = input.timestamp;
         let l1_inclusion_block = Some(input.l1_inclusion_block);
-        let seq_number = Some(self.sequence_number);
-        let prev_randao = l1_info.block_info.mix_hash;
-        let epoch = Some(input.epoch);
-        let transactions = Some(self.derive_transactions(input, l1_info));
-        let suggested_fee_recipient = SystemAccounts::default().fee_vault;
-
-        PayloadAttributes {
-            timestamp,
-            prev_randao,
-            suggested_fee_recipient: ethers::types::Address::from_slice(
-                suggested_fee_recipient.as_slice(),
-            ),
-            transactions,
-            no_tx_pool: true,
-            gas_limit: U64::from(l1_info.system_config.gas_limit.to_be_bytes()),
-            withdrawals,
-            epoch,
+        let transactions = self.derive_transactions(input, l1_info)?;
+
+        let attributes = PayloadAttributes {
             l1_inclusion_block,
-            seq_number,
-        }
+            ..block_attributes(
+                l1_info,
+                self.sequence_number,
+                timestamp,
+                transactions,
+                &self.config,
+            )
+        };
+
+        Ok(attributes)
     }
 
     /// Derives the deposited transactions and all other L2 user transactions from a given block. Deposited txs include:
@@ -120,66 +142,15 @@ impl Attributes {
         &self,
         input: BlockInput<Epoch>,
         l1_info: &L1Info,
-    ) -> Vec<RawTransaction> {
-        let mut transactions = Vec::new();
-
-        // L1 info (attributes deposited) transaction, present in every block
-        let attributes_tx = self.derive_attributes_deposited(l1_info, input.timestamp);
-        transactions.push(attributes_tx);
-
-        // User deposit transactions, present in the first block of every epoch
-        if self.sequence_number == 0 {
-            let mut user_deposited_txs = self.derive_user_deposited();
-            transactions.append(&mut user_deposited_txs);
-        }
-
-        // Ecotone upgrade transactions
-        if self
-            .config
-            .chain
-            .is_ecotone_activation_block(input.timestamp)
-        {
-            tracing::info!("found Ecotone activation block; Upgrade transactions added");
-            let mut ecotone_upgrade_txs = get_ecotone_upgrade_transactions();
-            transactions.append(&mut ecotone_upgrade_txs);
-        }
+    ) -> Result<Vec<RawTransaction>> {
+        let mut transactions =
+            deposited_transactions(l1_info, self.sequence_number, input.timestamp, &self.config)?;
 
         // Remaining transactions
         let mut rest = input.transactions;
         transactions.append(&mut rest);
 
-        transactions
-    }
-
-    /// Derives the attributes deposited transaction for a given block and converts this to a [RawTransaction].
-    fn derive_attributes_deposited(
-        &self,
-        l1_info: &L1Info,
-        batch_timestamp: u64,
-    ) -> RawTransaction {
-        let seq = self.sequence_number;
-        let attributes_deposited =
-            AttributesDeposited::from_block_info(l1_info, seq, batch_timestamp, &self.config);
-        let attributes_tx = DepositedTransaction::from(attributes_deposited);
-        RawTransaction(attributes_tx.rlp_bytes().to_vec())
-    }
-
-    /// Derives the user deposited txs for the current epoch, and returns a [RawTransaction] vector
-    fn derive_user_deposited(&self) -> Vec<RawTransaction> {
-        let state = self.state.read().unwrap();
-        state
-            .l1_info_by_hash(self.epoch_hash)
-            .map(|info| &info.user_deposits)
-            .map(|deposits| {
-                deposits
-                    .iter()
-                    .map(|deposit| {
-                        let tx = DepositedTransaction::from(deposit.clone());
-                        RawTransaction(tx.rlp_bytes().to_vec())
-                    })
-                    .collect()
-            })
-            .unwrap_or_default()
+        Ok(transactions)
     }
 
     /// Sets the current sequence number. If `self.epoch_hash` != `batch_epoch_hash` this is set to 0; otherwise it increments by 1.
@@ -196,6 +167,142 @@ impl Attributes {
     }
 }
 
+/// Returns the [PayloadAttributes] of a block built by the sequencer on the given L1 origin.
+/// The block holds the deposited transactions, and is filled with transactions from the
+/// mempool of the engine.
+pub fn sequencer_attributes(
+    l1_info: &L1Info,
+    seq: u64,
+    timestamp: u64,
+    config: &Config,
+    overrides: &AttributeOverrides,
+) -> Result<PayloadAttributes> {
+    let transactions = deposited_transactions(l1_info, seq, timestamp, config)?;
+
+    let mut attributes = PayloadAttributes {
+        no_tx_pool: false,
+        ..block_attributes(l1_info, seq, timestamp, transactions, config)
+    };
+
+    overrides.apply(&mut attributes, l1_info, config);
+    Ok(attributes)
+}
+
+/// Returns the [PayloadAttributes] of the block at the given timestamp and position in
+/// the epoch of the given L1 origin, built from the given transactions only
+fn block_attributes(
+    l1_info: &L1Info,
+    seq: u64,
+    timestamp: u64,
+    transactions: Vec<RawTransaction>,
+    config: &Config,
+) -> PayloadAttributes {
+    let withdrawals = if config.chain.hardforks.is_canyon_active(timestamp) {
+        Some(Vec::new())
+    } else {
+        None
+    };
+
+    let epoch = Epoch {
+        number: l1_info.block_info.number,
+        hash: l1_info.block_info.hash,
+        timestamp: l1_info.block_info.timestamp,
+    };
+
+    let parent_beacon_block_root = if config.chain.hardforks.is_ecotone_active(timestamp) {
+        Some(
+            l1_info
+                .block_info
+                .parent_beacon_block_root
+                .unwrap_or_default(),
+        )
+    } else {
+        None
+    };
+
+    let suggested_fee_recipient = config.chain.system_accounts.fee_vault;
+
+    PayloadAttributes {
+        timestamp: U64([timestamp]),
+        prev_randao: l1_info.block_info.mix_hash,
+        suggested_fee_recipient: ethers::types::Address::from_slice(
+            suggested_fee_recipient.as_slice(),
+        ),
+        transactions: Some(transactions),
+        no_tx_pool: true,
+        gas_limit: U64::from(l1_info.system_config.gas_limit.saturating_to::<u64>()),
+        withdrawals,
+        parent_beacon_block_root,
+        epoch: Some(epoch),
+        l1_inclusion_block: None,
+        seq_number: Some(seq),
+    }
+}
+
+/// Returns the deposited transactions of a block:
+/// - L1 Attributes Deposited (exists as the first tx in every block)
+/// - User deposits sent to the L1 deposit contract (0 or more and will only exist in the first block of the epoch)
+/// - The Ecotone upgrade transactions in the Ecotone activation block
+///
+/// Fails with a critical error if the L1 info deposit cannot be built from the system
+/// config, or with `halt_on_unsupported_fork` if a hardfork whose rules are not implemented
+/// is active at the block.
+fn deposited_transactions(
+    l1_info: &L1Info,
+    seq: u64,
+    timestamp: u64,
+    config: &Config,
+) -> Result<Vec<RawTransaction>> {
+    if config.halt_on_unsupported_fork {
+        if let Some(fork) = config.chain.hardforks.unsupported_fork(timestamp) {
+            return Err(DerivationError::critical(format!(
+                "unsupported fork: {} is active at block timestamp {}",
+                fork, timestamp
+            ))
+            .into());
+        }
+    }
+
+    let mut transactions = Vec::new();
+
+    // L1 info (attributes deposited) transaction, present in every block
+    let attributes_deposited =
+        AttributesDeposited::from_block_info(l1_info, seq, timestamp, config).map_err(|err| {
+            DerivationError::critical(format!("failed to create L1 info tx: {}", err))
+        })?;
+    let attributes_tx = DepositedTransaction::from_attributes_deposited(
+        attributes_deposited,
+        &config.chain.system_accounts,
+    );
+    transactions.push(RawTransaction(attributes_tx.rlp_bytes().to_vec()));
+
+    // User deposit transactions, present in the first block of every epoch
+    if seq == 0 {
+        let mut user_deposited_txs = l1_info
+            .user_deposits
+            .iter()
+            .map(|deposit| {
+                let tx = DepositedTransaction::from(deposit.clone());
+                RawTransaction(tx.rlp_bytes().to_vec())
+            })
+            .collect();
+        transactions.append(&mut user_deposited_txs);
+    }
+
+    // Ecotone upgrade transactions
+    if config
+        .chain
+        .hardforks
+        .is_ecotone_activation_block(timestamp)
+    {
+        tracing::info!("found Ecotone activation block; Upgrade transactions added");
+        let mut ecotone_upgrade_txs = get_ecotone_upgrade_transactions();
+        transactions.append(&mut ecotone_upgrade_txs);
+    }
+
+    Ok(transactions)
+}
+
 /// Represents a deposited transaction
 #[derive(Debug)]
 struct DepositedTransaction {
@@ -217,9 +324,13 @@ struct DepositedTransaction {
     data: Vec<u8>,
 }
 
-impl From<AttributesDeposited> for DepositedTransaction {
-    /// Converts [AttributesDeposited] to a [DepositedTransaction]
-    fn from(attributes_deposited: AttributesDeposited) -> Self {
+impl DepositedTransaction {
+    /// Creates the L1 info [DepositedTransaction] sent by the depositor account of the
+    /// given [SystemAccounts] to their L1 block predeploy
+    fn from_attributes_deposited(
+        attributes_deposited: AttributesDeposited,
+        system_accounts: &SystemAccounts,
+    ) -> Self {
         let hash = attributes_deposited.hash.to_fixed_bytes();
         let seq = H256::from_low_u64_be(attributes_deposited.sequence_number).to_fixed_bytes();
         let h = keccak256([hash, seq].concat());
@@ -227,7 +338,6 @@ impl From<AttributesDeposited> for DepositedTransaction {
         let domain = H256::from_low_u64_be(1).to_fixed_bytes();
         let source_hash = H256::from_slice(&keccak256([domain, h].concat()));
 
-        let system_accounts = SystemAccounts::default();
         let from = system_accounts.attributes_depositor;
         let to = Some(system_accounts.attributes_predeploy);
 
@@ -249,12 +359,7 @@ impl From<AttributesDeposited> for DepositedTransaction {
 impl From<UserDeposited> for DepositedTransaction {
     /// Converts [UserDeposited] to a [DepositedTransaction]
     fn from(user_deposited: UserDeposited) -> Self {
-        let hash = user_deposited.l1_block_hash.to_fixed_bytes();
-        let log_index = user_deposited.log_index.into();
-        let h = keccak256([hash, log_index].concat());
-
-        let domain = H256::from_low_u64_be(0).to_fixed_bytes();
-        let source_hash = H256::from_slice(&keccak256([domain, h].concat()));
+        let source_hash = user_deposited.source_hash();
 
         let to = if user_deposited.is_creation {
             None
@@ -316,21 +421,59 @@ struct AttributesDeposited {
     fee_overhead: U256,
     /// The current L1 fee scalar to apply to L2 transactions cost computation. Unused after Ecotone hard fork.
     fee_scalar: U256,
+    /// The L1 epoch blob base fee
+    blob_base_fee: U256,
+    /// The scalar of the L1 base fee in the L1 cost function after the Ecotone hard fork
+    base_fee_scalar: u32,
+    /// The scalar of the L1 blob base fee in the L1 cost function after the Ecotone hard fork
+    blob_base_fee_scalar: u32,
     /// Gas limit: 1_000_000 if post-Regolith, otherwise 150_000_000
     gas: u64,
     /// False if post-Regolith, otherwise true
     is_system_tx: bool,
+    /// True if encoded in the Ecotone format, which applies from the block after the Ecotone activation block
+    is_ecotone: bool,
+    /// The operator fee scalar after the Isthmus hard fork
+    operator_fee_scalar: u32,
+    /// The operator fee constant after the Isthmus hard fork
+    operator_fee_constant: u64,
+    /// True if encoded in the Isthmus format, which applies from the block after the Isthmus activation block
+    is_isthmus: bool,
 }
 
 impl AttributesDeposited {
-    /// Creates [AttributesDeposited] from the given data.
-    fn from_block_info(l1_info: &L1Info, seq: u64, batch_timestamp: u64, config: &Config) -> Self {
-        let is_regolith = batch_timestamp >= config.chain.regolith_time;
+    /// Creates [AttributesDeposited] from the given data. Fails on ecotone scalars of an
+    /// unknown version after the Ecotone activation block.
+    fn from_block_info(
+        l1_info: &L1Info,
+        seq: u64,
+        batch_timestamp: u64,
+        config: &Config,
+    ) -> Result<Self> {
+        let is_regolith = config.chain.hardforks.is_regolith_active(batch_timestamp);
         let is_system_tx = !is_regolith;
 
         let gas = if is_regolith { 1_000_000 } else { 150_000_000 };
 
-        Self {
+        let is_ecotone = config
+            .chain
+            .hardforks
+            .is_ecotone_but_not_first_block(batch_timestamp);
+        let (base_fee_scalar, blob_base_fee_scalar) = match is_ecotone {
+            true => l1_info.system_config.ecotone_scalars()?,
+            false => (0, 0),
+        };
+
+        let is_isthmus = config
+            .chain
+            .hardforks
+            .is_isthmus_but_not_first_block(batch_timestamp);
+        let (operator_fee_scalar, operator_fee_constant) = match is_isthmus {
+            true => l1_info.system_config.operator_fee(),
+            false => (0, 0),
+        };
+
+        Ok(Self {
             number: l1_info.block_info.number,
             timestamp: l1_info.block_info.timestamp,
             base_fee: l1_info.block_info.base_fee,
@@ -339,13 +482,30 @@ impl AttributesDeposited {
             batcher_hash: H256::from_slice(l1_info.system_config.batcher_hash().as_slice()),
             fee_overhead: U256::from(l1_info.system_config.l1_fee_overhead.to_be_bytes()),
             fee_scalar: U256::from(l1_info.system_config.l1_fee_scalar.to_be_bytes()),
+            blob_base_fee: l1_info.block_info.blob_base_fee,
+            base_fee_scalar,
+            blob_base_fee_scalar,
             gas,
             is_system_tx,
-        }
+            is_ecotone,
+            operator_fee_scalar,
+            operator_fee_constant,
+            is_isthmus,
+        })
     }
 
-    /// Encodes [AttributesDeposited] into `setL1BlockValues` transaction calldata, including the selector.
+    /// Encodes [AttributesDeposited] into `setL1BlockValues` transaction calldata, including the selector,
+    /// into `setL1BlockValuesEcotone` calldata after the Ecotone activation block, or into
+    /// `setL1BlockValuesIsthmus` calldata after the Isthmus activation block.
     fn encode(&self) -> Vec<u8> {
+        if self.is_isthmus {
+            return self.encode_isthmus();
+        }
+
+        if self.is_ecotone {
+            return self.encode_ecotone();
+        }
+
         let tokens = vec![
             Token::Uint(self.number.into()),
             Token::Uint(self.timestamp.into()),
@@ -362,6 +522,40 @@ impl AttributesDeposited {
 
         [selector, data].concat()
     }
+
+    /// Encodes [AttributesDeposited] into tightly packed `setL1BlockValuesEcotone` transaction
+    /// calldata, including the selector. The Fjord hard fork keeps this format.
+    fn encode_ecotone(&self) -> Vec<u8> {
+        let word = |value: U256| {
+            let mut bytes = [0; 32];
+            value.to_big_endian(&mut bytes);
+            bytes
+        };
+
+        let mut data = hex::decode("440a5e20").unwrap();
+        data.extend_from_slice(&self.base_fee_scalar.to_be_bytes());
+        data.extend_from_slice(&self.blob_base_fee_scalar.to_be_bytes());
+        data.extend_from_slice(&self.sequence_number.to_be_bytes());
+        data.extend_from_slice(&self.timestamp.to_be_bytes());
+        data.extend_from_slice(&self.number.to_be_bytes());
+        data.extend_from_slice(&word(self.base_fee));
+        data.extend_from_slice(&word(self.blob_base_fee));
+        data.extend_from_slice(self.hash.as_bytes());
+        data.extend_from_slice(self.batcher_hash.as_bytes());
+
+        data
+    }
+
+    /// Encodes [AttributesDeposited] into `setL1BlockValuesIsthmus` transaction calldata,
+    /// the Ecotone fields followed by the operator fee scalar and constant.
+    fn encode_isthmus(&self) -> Vec<u8> {
+        let mut data = hex::decode("098999be").unwrap();
+        data.extend_from_slice(&self.encode_ecotone()[4..]);
+        data.extend_from_slice(&self.operator_fee_scalar.to_be_bytes());
+        data.extend_from_slice(&self.operator_fee_constant.to_be_bytes());
+
+        data
+    }
 }
 
 /// Represents a user deposited transaction.
@@ -389,6 +583,19 @@ pub struct UserDeposited {
     pub log_index: U256,
 }
 
+impl UserDeposited {
+    /// Computes the source hash of the deposit, which is derived from the L1 block hash
+    /// and the index of the emitted deposit event log using the user deposit domain.
+    pub fn source_hash(&self) -> H256 {
+        let hash = self.l1_block_hash.to_fixed_bytes();
+        let log_index = self.log_index.into();
+        let h = keccak256([hash, log_index].concat());
+
+        let domain = H256::from_low_u64_be(0).to_fixed_bytes();
+        H256::from_slice(&keccak256([domain, h].concat()))
+    }
+}
+
 impl TryFrom<Log> for UserDeposited {
     type Error = eyre::Report;
 
@@ -429,3 +636,270 @@ impl TryFrom<Log> for UserDeposited {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use ethers::{
+        types::{Block, Bytes, Transaction},
+        utils::rlp::Rlp,
+    };
+
+    use crate::{
+        common::AttributesDepositedCall,
+        config::{ChainConfig, SystemConfig},
+        derive::SequencerOverrides,
+        l1::l1_info::L1BlockInfo,
+    };
+
+    use super::*;
+
+    fn l1_info(scalar: [u8; 32]) -> Result<L1Info> {
+        let block = Block::<Transaction> {
+            number: Some(U64::from(100)),
+            hash: Some(H256::repeat_byte(0x11)),
+            timestamp: U256::from(1_000),
+            base_fee_per_gas: Some(U256::from(7)),
+            mix_hash: Some(H256::repeat_byte(0x22)),
+            excess_blob_gas: Some(U256::from(3_338_477)),
+            ..Default::default()
+        };
+
+        Ok(L1Info {
+            block_info: L1BlockInfo::from_block(&block, 1)?,
+            system_config: SystemConfig {
+                batch_sender: Default::default(),
+                gas_limit: Default::default(),
+                l1_fee_overhead: Default::default(),
+                l1_fee_scalar: alloy_primitives::U256::from_be_bytes(scalar),
+                unsafe_block_signer: Default::default(),
+                operator_fee_params: Default::default(),
+            },
+            user_deposits: Vec::new(),
+            batcher_transactions: Vec::new(),
+            finalized: false,
+        })
+    }
+
+    #[test]
+    fn test_l1_info_encoding_across_forks() -> Result<()> {
+        let mut chain = ChainConfig::optimism();
+        chain.hardforks.ecotone_time = 10;
+        chain.hardforks.fjord_time = Some(20);
+        let config = Config {
+            chain,
+            ..Default::default()
+        };
+
+        let mut scalar = [0; 32];
+        scalar[0] = 1;
+        scalar[24..28].copy_from_slice(&810_949u32.to_be_bytes());
+        scalar[28..32].copy_from_slice(&1_368u32.to_be_bytes());
+        let info = l1_info(scalar)?;
+        assert_eq!(info.block_info.blob_base_fee, U256::from(2));
+
+        // the ecotone activation block still uses the bedrock format
+        let data = AttributesDeposited::from_block_info(&info, 1, 10, &config)?.encode();
+        let call = AttributesDepositedCall::try_from_bedrock(Bytes::from(data))?;
+        assert_eq!(call.fee_scalar, U256::from_big_endian(&scalar));
+
+        // both scalars are packed in ecotone and fjord blocks
+        for timestamp in [12, 20] {
+            let data = AttributesDeposited::from_block_info(&info, 1, timestamp, &config)?.encode();
+            let call = AttributesDepositedCall::try_from_ecotone(Bytes::from(data))?;
+            assert_eq!(call.number, 100);
+            assert_eq!(call.sequence_number, 1);
+            assert_eq!(call.basefee, U256::from(7));
+            assert_eq!(call.hash, H256::repeat_byte(0x11));
+            assert_eq!(call.fee_scalar, U256::from(1_368));
+            assert_eq!(call.blob_base_fee_scalar, Some(810_949));
+            assert_eq!(call.blob_base_fee, Some(U256::from(2)));
+        }
+
+        // legacy scalars only set the base fee scalar
+        let mut legacy = [0; 32];
+        legacy[28..32].copy_from_slice(&1_368u32.to_be_bytes());
+        let info = l1_info(legacy)?;
+        let data = AttributesDeposited::from_block_info(&info, 0, 20, &config)?.encode();
+        let call = AttributesDepositedCall::try_from_ecotone(Bytes::from(data))?;
+        assert_eq!(call.fee_scalar, U256::from(1_368));
+        assert_eq!(call.blob_base_fee_scalar, Some(0));
+
+        // scalars of an unknown version, or with bytes outside their fields, are rejected
+        // after ecotone, but still encoded as is before
+        scalar[0] = 0;
+        let info = l1_info(scalar)?;
+        assert!(AttributesDeposited::from_block_info(&info, 0, 20, &config).is_err());
+        scalar[0] = 2;
+        let info = l1_info(scalar)?;
+        assert!(AttributesDeposited::from_block_info(&info, 0, 20, &config).is_err());
+        assert!(AttributesDeposited::from_block_info(&info, 0, 10, &config).is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fjord_deposited_transactions() -> Result<()> {
+        let mut chain = ChainConfig::optimism();
+        chain.hardforks.ecotone_time = 10;
+        chain.hardforks.fjord_time = Some(20);
+        chain.hardforks.granite_time = Some(40);
+        let config = Config {
+            chain,
+            ..Default::default()
+        };
+
+        let mut scalar = [0; 32];
+        scalar[0] = 1;
+        scalar[24..28].copy_from_slice(&810_949u32.to_be_bytes());
+        scalar[28..32].copy_from_slice(&1_368u32.to_be_bytes());
+        let info = l1_info(scalar)?;
+
+        // the L1 info deposit of fjord blocks carries both fee scalars and the blob base fee
+        for timestamp in [20, 22, 40] {
+            let transactions = deposited_transactions(&info, 1, timestamp, &config)?;
+            assert_eq!(transactions.len(), 1);
+
+            let (0x7E, rlp) = transactions[0].0.split_first().unwrap() else {
+                panic!("not a deposit transaction");
+            };
+            let data = Rlp::new(rlp).val_at::<Vec<u8>>(7)?;
+            let call = AttributesDepositedCall::try_from_ecotone(Bytes::from(data))?;
+            assert_eq!(call.sequence_number, 1);
+            assert_eq!(call.fee_scalar, U256::from(1_368));
+            assert_eq!(call.blob_base_fee_scalar, Some(810_949));
+            assert_eq!(call.blob_base_fee, Some(U256::from(2)));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_isthmus_l1_info() -> Result<()> {
+        let mut chain = ChainConfig::optimism();
+        chain.hardforks.ecotone_time = 10;
+        chain.hardforks.isthmus_time = Some(20);
+        let config = Config {
+            chain,
+            ..Default::default()
+        };
+
+        let mut info = l1_info([0; 32])?;
+        let mut params = [0; 32];
+        params[20..24].copy_from_slice(&1_500u32.to_be_bytes());
+        params[24..32].copy_from_slice(&42u64.to_be_bytes());
+        info.system_config.operator_fee_params = alloy_primitives::U256::from_be_bytes(params);
+
+        // the isthmus activation block still uses the ecotone format
+        let data = AttributesDeposited::from_block_info(&info, 1, 20, &config)?.encode();
+        let call = AttributesDepositedCall::try_from_ecotone(Bytes::from(data))?;
+        assert_eq!(call.operator_fee_scalar, None);
+
+        // and the following blocks carry the operator fee
+        let data = AttributesDeposited::from_block_info(&info, 1, 22, &config)?.encode();
+        let call = AttributesDepositedCall::try_from_ecotone(Bytes::from(data))?;
+        assert_eq!(call.number, 100);
+        assert_eq!(call.sequence_number, 1);
+        assert_eq!(call.hash, H256::repeat_byte(0x11));
+        assert_eq!(call.blob_base_fee, Some(U256::from(2)));
+        assert_eq!(call.operator_fee_scalar, Some(1_500));
+        assert_eq!(call.operator_fee_constant, Some(42));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sequencer_overrides() -> Result<()> {
+        let config = Config::default();
+        let mut info = l1_info([0; 32])?;
+        info.system_config.gas_limit = config.chain.system_config.gas_limit;
+
+        let overrides = AttributeOverrides::default();
+        overrides.set(SequencerOverrides {
+            gas_limit: Some(60_000_000),
+        })?;
+
+        let attributes = sequencer_attributes(&info, 0, 10, &config, &overrides)?;
+        assert_eq!(attributes.gas_limit.as_u64(), 60_000_000);
+        assert_eq!(
+            attributes.suggested_fee_recipient,
+            Address::from_slice(config.chain.system_accounts.fee_vault.as_slice())
+        );
+
+        // a gas limit set on L1 takes precedence
+        let genesis_gas_limit = info.system_config.gas_limit;
+        info.system_config.gas_limit = alloy_primitives::U256::from(40_000_000);
+        let attributes = sequencer_attributes(&info, 0, 10, &config, &overrides)?;
+        assert_eq!(attributes.gas_limit.as_u64(), 40_000_000);
+
+        // and keeps the override disabled once L1 sets it back to the genesis gas limit
+        info.system_config.gas_limit = genesis_gas_limit;
+        let attributes = sequencer_attributes(&info, 0, 10, &config, &overrides)?;
+        assert_eq!(attributes.gas_limit.as_u64(), genesis_gas_limit.to::<u64>());
+
+        assert!(overrides
+            .set(SequencerOverrides { gas_limit: Some(0) })
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unsupported_fork() -> Result<()> {
+        let mut config = Config::default();
+        let mut info = l1_info([0; 32])?;
+        info.system_config = config.chain.system_config;
+
+        // the finalized head of the built-in chains is past all their scheduled hardforks,
+        // and the blocks after it are derived by default
+        let timestamp = config.chain.hardforks.isthmus_time.unwrap() + config.chain.blocktime;
+        let overrides = AttributeOverrides::default();
+        let attributes = sequencer_attributes(&info, 1, timestamp, &config, &overrides)?;
+        let transactions = attributes.transactions.unwrap();
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(attributes.withdrawals, Some(Vec::new()));
+        assert!(attributes.parent_beacon_block_root.is_some());
+
+        // and halt the node when opted in
+        config.halt_on_unsupported_fork = true;
+        let err = sequencer_attributes(&info, 1, timestamp, &config, &overrides).unwrap_err();
+        assert_eq!(DerivationError::classify(&err).code(), "critical");
+
+        let fjord_time = config.chain.hardforks.fjord_time.unwrap();
+        assert!(sequencer_attributes(&info, 1, fjord_time - 2, &config, &overrides).is_ok());
+        assert!(sequencer_attributes(&info, 1, fjord_time, &config, &overrides).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parent_beacon_block_root() -> Result<()> {
+        let mut chain = ChainConfig::optimism();
+        chain.hardforks.ecotone_time = 10;
+        let config = Config {
+            chain,
+            ..Default::default()
+        };
+
+        let mut info = l1_info([0; 32])?;
+        info.block_info.parent_beacon_block_root = Some(H256::repeat_byte(0x22));
+
+        let attributes = block_attributes(&info, 0, 8, Vec::new(), &config);
+        assert_eq!(attributes.parent_beacon_block_root, None);
+        assert!(serde_json::to_value(&attributes)?
+            .get("parentBeaconBlockRoot")
+            .is_none());
+
+        let attributes = block_attributes(&info, 0, 10, Vec::new(), &config);
+        assert_eq!(
+            attributes.parent_beacon_block_root,
+            Some(H256::repeat_byte(0x22))
+        );
+
+        // an L1 origin without a beacon root builds on the zero root
+        info.block_info.parent_beacon_block_root = None;
+        let attributes = block_attributes(&info, 0, 10, Vec::new(), &config);
+        assert_eq!(attributes.parent_beacon_block_root, Some(H256::zero()));
+
+        Ok(())
+    }
+}
@@ -1,16 +1,25 @@
-use std::sync::mpsc;
+use std::sync::{mpsc, Arc};
 
+use bytes::Bytes;
 use eyre::Result;
 use std::collections::VecDeque;
 
-use crate::derive::PurgeableIterator;
+use crate::derive::{PurgeableIterator, StepBudget};
+
+/// The maximum byte length of the data of a frame
+pub const MAX_FRAME_DATA_LEN: u32 = 1_000_000;
+
+/// The byte length of a frame without its data
+const FRAME_OVERHEAD: usize = 23;
 
 /// Represents a transaction sent to the `Batch Inbox` on L1.
 pub struct BatcherTransactionMessage {
     /// The L2 transactions included in this batch
-    pub txs: Vec<bytes::Bytes>,
+    pub txs: Vec<Bytes>,
     /// The L1 block number this transaction was included in
     pub l1_origin: u64,
+    /// The timestamp of the L1 block this transaction was included in
+    pub l1_timestamp: u64,
 }
 
 /// Receives [BatcherTransactionMessage] messages from a channel and stores these in a [VecDeque].
@@ -19,6 +28,8 @@ pub struct BatcherTransactions {
     txs: VecDeque<BatcherTransaction>,
     /// [BatcherTransactionMessage] channel [receiver](mpsc::Receiver)
     transaction_rx: mpsc::Receiver<BatcherTransactionMessage>,
+    /// The budget of the pipeline step, if bounded
+    budget: Option<Arc<StepBudget>>,
 }
 
 impl Iterator for BatcherTransactions {
@@ -27,7 +38,16 @@ impl Iterator for BatcherTransactions {
     /// Receives new [BatcherTransactionMessage] messages from the channel and adds these to the deque. Pops and returns the first deque element.
     fn next(&mut self) -> Option<Self::Item> {
         self.process_incoming();
-        self.txs.pop_front()
+        let tx = self.txs.pop_front();
+
+        if let Some(budget) = &self.budget {
+            if tx.is_some() {
+                budget.take_tx();
+            }
+            budget.set_buffered_txs(!self.txs.is_empty());
+        }
+
+        tx
     }
 }
 
@@ -46,22 +66,38 @@ impl BatcherTransactions {
         Self {
             transaction_rx,
             txs: VecDeque::new(),
+            budget: None,
         }
     }
 
+    /// Records the transactions left after each call in the given [StepBudget]
+    pub fn with_budget(mut self, budget: Arc<StepBudget>) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
     ///  Receives new [BatcherTransactionMessage] messages from the channel and adds these to the end of the deque.
+    ///  L1 blocks without valid batcher transactions are added as a transaction without frames.
     pub fn process_incoming(&mut self) {
-        while let Ok(BatcherTransactionMessage { txs, l1_origin }) = self.transaction_rx.try_recv()
+        while let Ok(BatcherTransactionMessage {
+            txs,
+            l1_origin,
+            l1_timestamp,
+        }) = self.transaction_rx.try_recv()
         {
+            let len = self.txs.len();
             for data in txs {
-                let res = BatcherTransaction::new(&data, l1_origin).map(|tx| {
-                    self.txs.push_back(tx);
-                });
-
-                if res.is_err() {
-                    tracing::warn!("dropping invalid batcher transaction");
+                match BatcherTransaction::new(data, l1_origin, l1_timestamp) {
+                    Ok(tx) => self.txs.push_back(tx),
+                    Err(err) => tracing::warn!("dropping invalid batcher transaction: {}", err),
                 }
             }
+
+            // the channel bank expires channels as its origin advances, even without frames
+            if self.txs.len() == len {
+                self.txs
+                    .push_back(BatcherTransaction::empty(l1_origin, l1_timestamp));
+            }
         }
     }
 }
@@ -73,23 +109,56 @@ pub struct BatcherTransaction {
     pub version: u8,
     /// The rollup payload consisting of 1 or more frames.
     pub frames: Vec<Frame>,
+    /// The L1 block number this transaction was included in
+    pub l1_origin: u64,
+    /// The timestamp of the L1 block this transaction was included in
+    pub l1_timestamp: u64,
 }
 
 impl BatcherTransaction {
-    /// Creates a new [BatcherTransaction]
-    pub fn new(data: &[u8], l1_origin: u64) -> Result<Self> {
-        let version = data[0];
-        let frame_data = data.get(1..).ok_or(eyre::eyre!("No frame data"))?;
+    /// Creates a new [BatcherTransaction]. The frames borrow their data from the
+    /// transaction data instead of copying it. Any invalid frame invalidates the whole
+    /// transaction. Since every frame takes at least its 23 byte overhead, the frames of a
+    /// transaction are bounded by its size.
+    pub fn new(data: Bytes, l1_origin: u64, l1_timestamp: u64) -> Result<Self> {
+        let version = *data
+            .first()
+            .ok_or(eyre::eyre!("empty batcher transaction"))?;
+        if version != 0 {
+            eyre::bail!("unsupported batcher transaction version {}", version);
+        }
+
+        let frame_data = data.slice(1..);
+        if frame_data.is_empty() {
+            eyre::bail!("no frame data");
+        }
 
         let mut offset = 0;
         let mut frames = Vec::new();
         while offset < frame_data.len() {
-            let (frame, next_offset) = Frame::from_data(frame_data, offset, l1_origin)?;
+            let (frame, next_offset) =
+                Frame::from_data(&frame_data, offset, l1_origin, l1_timestamp)?;
             frames.push(frame);
             offset = next_offset;
         }
 
-        Ok(Self { version, frames })
+        Ok(Self {
+            version,
+            frames,
+            l1_origin,
+            l1_timestamp,
+        })
+    }
+
+    /// Creates a [BatcherTransaction] without frames, marking an L1 block without valid
+    /// batcher transactions
+    pub fn empty(l1_origin: u64, l1_timestamp: u64) -> Self {
+        Self {
+            version: 0,
+            frames: Vec::new(),
+            l1_origin,
+            l1_timestamp,
+        }
     }
 }
 
@@ -100,22 +169,30 @@ pub struct Frame {
     pub channel_id: u128,
     /// The index of the frame within the channel
     pub frame_number: u16,
-    /// The byte length of frame_data. Capped to 1,000,000 bytes.
+    /// The byte length of frame_data. Capped to [MAX_FRAME_DATA_LEN] bytes.
     pub frame_data_len: u32,
     /// A sequence of bytes belonging to the channel, logically after the previous frames
-    pub frame_data: Vec<u8>,
+    pub frame_data: Bytes,
     /// If the frame is the last in the channel
     pub is_last: bool,
     /// The L1 block number this frame was submitted in.
     pub l1_inclusion_block: u64,
+    /// The timestamp of the L1 block this frame was submitted in.
+    pub l1_inclusion_timestamp: u64,
 }
 
 impl Frame {
-    /// Converts a sequence of bytes into a [Frame]
-    fn from_data(data: &[u8], offset: usize, l1_inclusion_block: u64) -> Result<(Self, usize)> {
-        let data = &data[offset..];
-
-        if data.len() < 23 {
+    /// Decodes the [Frame] at the given offset of the frame data, returning it with the
+    /// offset of the next frame
+    fn from_data(
+        tx_data: &Bytes,
+        offset: usize,
+        l1_inclusion_block: u64,
+        l1_inclusion_timestamp: u64,
+    ) -> Result<(Self, usize)> {
+        let data = &tx_data[offset..];
+
+        if data.len() < FRAME_OVERHEAD {
             eyre::bail!("invalid frame size");
         }
 
@@ -123,13 +200,15 @@ impl Frame {
         let frame_number = u16::from_be_bytes(data[16..18].try_into()?);
         let frame_data_len = u32::from_be_bytes(data[18..22].try_into()?);
 
+        if frame_data_len > MAX_FRAME_DATA_LEN {
+            eyre::bail!("frame data of {} bytes is too large", frame_data_len);
+        }
+
         let frame_data_end = 22 + frame_data_len as usize;
-        if data.len() < frame_data_end {
+        if data.len() <= frame_data_end {
             eyre::bail!("invalid frame size");
         }
 
-        let frame_data = data[22..frame_data_end].to_vec();
-
         let is_last = if data[frame_data_end] > 1 {
             eyre::bail!("invalid is_last flag");
         } else {
@@ -140,9 +219,10 @@ impl Frame {
             channel_id,
             frame_number,
             frame_data_len,
-            frame_data,
+            frame_data: tx_data.slice(offset + 22..offset + frame_data_end),
             is_last,
             l1_inclusion_block,
+            l1_inclusion_timestamp,
         };
 
         tracing::debug!(
@@ -152,7 +232,7 @@ impl Frame {
             is_last
         );
 
-        Ok((frame, offset + data.len()))
+        Ok((frame, offset + frame_data_end + 1))
     }
 }
 
@@ -166,7 +246,7 @@ mod tests {
     fn test_decode_tx() {
         let data = hex::decode(TX_DATA).unwrap();
 
-        let tx = BatcherTransaction::new(&data, 123456).unwrap();
+        let tx = BatcherTransaction::new(Bytes::from(data.clone()), 123456, 0).unwrap();
         let frame = &tx.frames[0];
 
         assert_eq!(tx.version, 0);
@@ -178,15 +258,16 @@ mod tests {
 
     #[test]
     fn test_push_tx() {
-        let data = bytes::Bytes::from(hex::decode(TX_DATA).unwrap());
+        let data = Bytes::from(hex::decode(TX_DATA).unwrap());
         let txs = vec![data];
 
-        let (tx, rx) = mpsc::channel();
+        let (sender, rx) = mpsc::channel();
         let mut stage = BatcherTransactions::new(rx);
 
-        let res = tx.send(BatcherTransactionMessage {
+        let res = sender.send(BatcherTransactionMessage {
             txs,
             l1_origin: 123456,
+            l1_timestamp: 0,
         });
         assert!(res.is_ok());
 
@@ -199,5 +280,63 @@ mod tests {
         assert_eq!(tx.version, 0);
         assert_eq!(tx.frames.len(), 1);
         assert_eq!(frame.channel_id, 239159748140584302248388764660258118408);
+
+        // blocks without batcher transactions still advance the origin
+        sender
+            .send(BatcherTransactionMessage {
+                txs: Vec::new(),
+                l1_origin: 123457,
+                l1_timestamp: 12,
+            })
+            .unwrap();
+        stage.process_incoming();
+
+        assert_eq!(stage.txs.len(), 2);
+        assert!(stage.txs[1].frames.is_empty());
+        assert_eq!(stage.txs[1].l1_origin, 123457);
+    }
+
+    fn encode_frame(frame_number: u16, data: &[u8], is_last: bool) -> Vec<u8> {
+        let mut frame = 7u128.to_be_bytes().to_vec();
+        frame.extend_from_slice(&frame_number.to_be_bytes());
+        frame.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        frame.extend_from_slice(data);
+        frame.push(is_last as u8);
+        frame
+    }
+
+    #[test]
+    fn test_decode_frames() {
+        let mut data = vec![0];
+        data.extend(encode_frame(0, b"first", false));
+        data.extend(encode_frame(1, b"second", true));
+        let data = Bytes::from(data);
+
+        let tx = BatcherTransaction::new(data.clone(), 1, 0).unwrap();
+        assert_eq!(tx.frames.len(), 2);
+        assert_eq!(tx.frames[0].frame_data, &b"first"[..]);
+        assert_eq!(tx.frames[1].frame_data, &b"second"[..]);
+        assert!(tx.frames[1].is_last);
+
+        // the frame data borrows from the transaction data
+        let range = data.as_ptr_range();
+        assert!(range.contains(&tx.frames[1].frame_data.as_ptr()));
+
+        // a truncated frame invalidates the transaction
+        assert!(BatcherTransaction::new(data.slice(..data.len() - 1), 1, 0).is_err());
+
+        // so do oversized frames
+        let mut oversized = vec![0];
+        oversized.extend(encode_frame(0, &[], true));
+        oversized[19..23].copy_from_slice(&(MAX_FRAME_DATA_LEN + 1).to_be_bytes());
+        assert!(BatcherTransaction::new(Bytes::from(oversized), 1, 0).is_err());
+
+        // while transactions of many frames are kept whole
+        let mut many = vec![0];
+        for i in 0..=1024 {
+            many.extend(encode_frame(i as u16, &[], false));
+        }
+        let tx = BatcherTransaction::new(Bytes::from(many), 1, 0).unwrap();
+        assert_eq!(tx.frames.len(), 1025);
     }
 }
@@ -1,6 +1,6 @@
 use core::fmt::Debug;
 use std::cmp::Ordering;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, VecDeque};
 use std::io::Read;
 use std::sync::{Arc, RwLock};
 
@@ -8,9 +8,12 @@ use ethers::utils::rlp::Rlp;
 use eyre::Result;
 use libflate::zlib::Decoder;
 
+use crate::common::{BlockInfo, Epoch, RawTransaction};
 use crate::config::Config;
+use crate::derive::forced_inclusion::{DepositOnlyBlock, DepositOnlyReason};
 use crate::derive::state::State;
-use crate::derive::PurgeableIterator;
+use crate::derive::{Lookahead, PurgeableIterator, StepBudget};
+use crate::telemetry::metrics;
 
 use super::block_input::BlockInput;
 use super::channels::Channel;
@@ -21,14 +24,20 @@ use super::span_batch::SpanBatch;
 pub struct Batches<I> {
     /// Mapping of timestamps to batches
     batches: BTreeMap<u64, Batch>,
-    /// Pending block inputs to be outputted
-    pending_inputs: Vec<BlockInput<u64>>,
+    /// The block inputs of the accepted batch that have not been output yet
+    pending: PendingBatch,
     /// A [Channels](super::channels::Channels) iterator, which iterates over [BatcherTransaction](super::batcher_transactions::BatcherTransaction) and processes channel frames.
     channel_iter: I,
     /// The current derivation [State]. Contains cached L1 & L2 blocks and details of the current safe head & safe epoch.
     state: Arc<RwLock<State>>,
     /// The global Magi [Config]
     config: Arc<Config>,
+    /// Number of batches dropped as invalid since the last valid batch
+    dropped_batches: u64,
+    /// The channels decoded ahead in the background, if enabled
+    lookahead: Option<Lookahead>,
+    /// The budget of the pipeline step, telling whether earlier stages hold back input
+    budget: Option<Arc<StepBudget>>,
 }
 
 impl<I> Iterator for Batches<I>
@@ -50,11 +59,17 @@ impl<I> PurgeableIterator for Batches<I>
 where
     I: PurgeableIterator<Item = Channel>,
 {
-    /// Clears the channels iterator, batches mapping & pending block inputs
+    /// Clears the channels iterator, batches mapping & pending block inputs. A purge in the
+    /// middle of a span batch drops its remaining blocks, and the span is validated again
+    /// against the new safe head once its channel is read again.
     fn purge(&mut self) {
         self.channel_iter.purge();
         self.batches.clear();
-        self.pending_inputs.clear();
+        self.pending.reset();
+        self.dropped_batches = 0;
+        if let Some(lookahead) = &self.lookahead {
+            lookahead.purge();
+        }
     }
 }
 
@@ -63,12 +78,30 @@ impl<I> Batches<I> {
     pub fn new(channel_iter: I, state: Arc<RwLock<State>>, config: Arc<Config>) -> Self {
         Self {
             batches: BTreeMap::new(),
-            pending_inputs: Vec::new(),
+            pending: PendingBatch::default(),
             channel_iter,
             state,
             config,
+            dropped_batches: 0,
+            lookahead: None,
+            budget: None,
         }
     }
+
+    /// Takes the batches of channels decoded ahead by the given [Lookahead] instead of
+    /// decoding them again
+    pub fn with_lookahead(mut self, lookahead: Lookahead) -> Self {
+        self.lookahead = Some(lookahead);
+        self
+    }
+
+    /// Treats the input the earlier stages hold back under the given [StepBudget] as not
+    /// yet read, so a bounded step never derives a deposit-only block in place of a batch
+    /// still buffered
+    pub fn with_budget(mut self, budget: Arc<StepBudget>) -> Self {
+        self.budget = Some(budget);
+        self
+    }
 }
 
 impl<I> Batches<I>
@@ -81,17 +114,26 @@ where
     ///
     /// Checks validity of batches in batches mapping, removing any that are invalid.
     ///
-    /// Attempts to derive the first valid batch and returns the first Block Input in the batch. Remaining Block Inputs are inserted into `pending_inputs`,
+    /// Attempts to derive the first valid batch and returns the first Block Input in the batch. Remaining Block Inputs are kept `pending`,
     ///
     /// If there are already pending inputs, it will skip the above and simply return the first pending Block Input.
     fn try_next(&mut self) -> Result<Option<BlockInput<u64>>> {
-        if !self.pending_inputs.is_empty() {
-            return Ok(Some(self.pending_inputs.remove(0)));
+        if let Some(input) = self.pending.next() {
+            return Ok(Some(input));
         }
 
         let channel = self.channel_iter.next();
+        let buffered = self
+            .budget
+            .as_ref()
+            .is_some_and(|budget| budget.is_buffered());
+        let out_of_data = channel.is_none() && !buffered;
         if let Some(channel) = channel {
-            let batches = decode_batches(&channel, self.config.chain.l2_chain_id)?;
+            let decoded = self.lookahead.as_ref().and_then(|l| l.take(&channel));
+            let batches = match decoded {
+                Some(batches) => batches,
+                None => decode_batches(&channel, self.config.chain.l2_chain_id)?,
+            };
             batches.into_iter().for_each(|batch| {
                 let timestamp = batch.timestamp(&self.config);
                 tracing::debug!("saw batch: t={}", timestamp);
@@ -106,11 +148,19 @@ where
                     BatchStatus::Accept => {
                         let batch = batch.clone();
                         self.batches.remove(&timestamp);
+                        self.dropped_batches = 0;
                         break Some(batch);
                     }
-                    BatchStatus::Drop => {
-                        tracing::warn!("dropping invalid batch");
+                    BatchStatus::Drop(reason) => {
+                        tracing::warn!("dropping invalid batch: t={}, {}", timestamp, reason);
+                        metrics::DROPPED_BATCHES
+                            .with_label_values(&[
+                                &self.config.chain.l2_chain_id.to_string(),
+                                reason.label(),
+                            ])
+                            .inc();
                         self.batches.remove(&timestamp);
+                        self.dropped_batches += 1;
                     }
                     BatchStatus::Future | BatchStatus::Undecided => {
                         break None;
@@ -122,50 +172,53 @@ where
         };
 
         Ok(if let Some(derived_batch) = derived_batch {
-            let mut inputs = self.filter_inputs(derived_batch.as_inputs(&self.config));
-            if !inputs.is_empty() {
-                let first = inputs.remove(0);
-                self.pending_inputs.append(&mut inputs);
-                Some(first)
-            } else {
-                None
-            }
+            let inputs = self.filter_inputs(derived_batch.as_inputs(&self.config));
+            self.pending
+                .start(derived_batch.timestamp(&self.config), inputs);
+            self.pending.next()
         } else {
-            // No valid batches were found. If we are past the epoch number + sequencer window size, and aware of the next epoch -> return a new BlockInput with no transactions.
-            let state = self.state.read().unwrap();
-
-            let current_l1_block = state.current_epoch_num;
-            let safe_head = state.safe_head;
-            let epoch = state.safe_epoch;
-            let next_epoch = state.epoch_by_number(epoch.number + 1);
-            let seq_window_size = self.config.chain.seq_window_size;
-
-            if let Some(next_epoch) = next_epoch {
-                if current_l1_block > epoch.number + seq_window_size {
-                    let next_timestamp = safe_head.timestamp + self.config.chain.blocktime;
-                    let epoch = if next_timestamp < next_epoch.timestamp {
-                        // We're deriving empty batches in the current epoch
-                        Some(epoch)
-                    } else if current_l1_block > next_epoch.number + seq_window_size {
-                        // The `next_epoch` also passed seq window, so it is safe to create empty batches in `next_epoch` and advance to it
-                        Some(next_epoch)
-                    } else {
-                        // The `next_epoch` is still within its seq window, so don't create empty batches
-                        None
-                    };
-
-                    epoch.map(|epoch| BlockInput {
-                        epoch: epoch.number,
-                        timestamp: next_timestamp,
-                        transactions: Vec::new(),
-                        l1_inclusion_block: current_l1_block,
-                    })
+            // No valid batches were found. Once the sequencing window of the safe epoch has
+            // expired, derive deposit-only blocks in its place.
+            let mut state = self.state.write().unwrap();
+            let input = deposit_only_input(
+                &self.config,
+                state.safe_head,
+                state.safe_epoch,
+                state.epoch_by_number(state.safe_epoch.number + 1),
+                state.current_epoch_num,
+                out_of_data,
+            );
+
+            if let Some(input) = &input {
+                let reason = if self.dropped_batches > 0 {
+                    DepositOnlyReason::InvalidBatches
                 } else {
-                    None
-                }
-            } else {
-                None
+                    DepositOnlyReason::NoBatch
+                };
+
+                tracing::warn!(
+                    "sequencing window expired, deriving deposit-only block: t={}, epoch={}, dropped batches={}",
+                    input.timestamp,
+                    input.epoch,
+                    self.dropped_batches
+                );
+                metrics::DEPOSIT_ONLY_BLOCKS
+                    .with_label_values(&[
+                        &self.config.chain.l2_chain_id.to_string(),
+                        reason.label(),
+                    ])
+                    .inc();
+
+                state.forced_inclusion.record(DepositOnlyBlock {
+                    timestamp: input.timestamp,
+                    epoch: input.epoch,
+                    l1_block: input.l1_inclusion_block,
+                    reason,
+                    dropped_batches: self.dropped_batches,
+                });
             }
+
+            input
         })
     }
 
@@ -196,73 +249,56 @@ where
         // check timestamp range
         match batch.timestamp.cmp(&next_timestamp) {
             Ordering::Greater => return BatchStatus::Future,
-            Ordering::Less => {
-                tracing::warn!("past batch");
-                return BatchStatus::Drop;
-            }
+            Ordering::Less => return BatchStatus::Drop(DropReason::Past),
             Ordering::Equal => (),
         }
 
         // check that block builds on existing chain
         if batch.parent_hash != head.hash {
-            tracing::warn!("invalid parent hash");
-            return BatchStatus::Drop;
+            return BatchStatus::Drop(DropReason::ParentMismatch);
         }
 
         // check the inclusion delay
         if batch.epoch_num + self.config.chain.seq_window_size < batch.l1_inclusion_block {
-            tracing::warn!("inclusion window elapsed");
-            return BatchStatus::Drop;
+            return BatchStatus::Drop(DropReason::InclusionWindow);
         }
 
-        // check and set batch origin epoch
-        let batch_origin = if batch.epoch_num == epoch.number {
-            Some(epoch)
-        } else if batch.epoch_num == epoch.number + 1 {
-            next_epoch
-        } else {
-            tracing::warn!("invalid batch origin epoch number");
-            return BatchStatus::Drop;
+        // check and set batch origin epoch, which may only advance by one
+        let batch_origin = match batch.epoch_num.cmp(&epoch.number) {
+            Ordering::Less => return BatchStatus::Drop(DropReason::EpochTooOld),
+            Ordering::Equal => epoch,
+            Ordering::Greater if batch.epoch_num == epoch.number + 1 => match next_epoch {
+                Some(next_epoch) => next_epoch,
+                None => {
+                    tracing::debug!("batch origin not known");
+                    return BatchStatus::Undecided;
+                }
+            },
+            Ordering::Greater => return BatchStatus::Drop(DropReason::EpochTooFarAhead),
         };
 
-        if let Some(batch_origin) = batch_origin {
-            if batch.epoch_hash != batch_origin.hash {
-                tracing::warn!("invalid epoch hash");
-                return BatchStatus::Drop;
-            }
+        if batch.epoch_hash != batch_origin.hash {
+            return BatchStatus::Drop(DropReason::EpochHashMismatch);
+        }
 
-            if batch.timestamp < batch_origin.timestamp {
-                tracing::warn!("batch too old");
-                return BatchStatus::Drop;
-            }
+        if batch.timestamp < batch_origin.timestamp {
+            return BatchStatus::Drop(DropReason::TimestampBeforeOrigin);
+        }
 
-            // handle sequencer drift
-            if batch.timestamp > batch_origin.timestamp + self.config.chain.max_seq_drift {
-                if batch.transactions.is_empty() {
-                    if epoch.number == batch.epoch_num {
-                        if let Some(next_epoch) = next_epoch {
-                            if batch.timestamp >= next_epoch.timestamp {
-                                tracing::warn!("sequencer drift too large");
-                                return BatchStatus::Drop;
-                            }
-                        } else {
-                            tracing::debug!("sequencer drift undecided");
-                            return BatchStatus::Undecided;
-                        }
-                    }
-                } else {
-                    tracing::warn!("sequencer drift too large");
-                    return BatchStatus::Drop;
-                }
-            }
-        } else {
-            tracing::debug!("batch origin not known");
-            return BatchStatus::Undecided;
+        let origin_advanced = batch_origin.number != epoch.number;
+        if let Some(status) = drift_status(
+            &self.config,
+            batch.timestamp,
+            !batch.transactions.is_empty(),
+            batch_origin,
+            origin_advanced,
+            next_epoch,
+        ) {
+            return status;
         }
 
         if batch.has_invalid_transactions() {
-            tracing::warn!("invalid transaction");
-            return BatchStatus::Drop;
+            return BatchStatus::Drop(DropReason::InvalidTransaction);
         }
 
         BatchStatus::Accept
@@ -274,24 +310,14 @@ where
         let epoch = state.safe_epoch;
         let next_epoch = state.epoch_by_number(epoch.number + 1);
         let head = state.safe_head;
-        let next_timestamp = head.timestamp + self.config.chain.blocktime;
+        let blocktime = self.config.chain.blocktime;
+        let next_timestamp = head.timestamp + blocktime;
 
         let start_epoch_num = batch.start_epoch_num();
         let end_epoch_num = batch.l1_origin_num;
         let span_start_timestamp = batch.rel_timestamp + self.config.chain.l2_genesis.timestamp;
         let span_end_timestamp =
-            span_start_timestamp + batch.block_count * self.config.chain.blocktime;
-
-        // check batch timestamp
-
-        if span_end_timestamp < next_timestamp {
-            tracing::warn!("past batch");
-            return BatchStatus::Drop;
-        }
-
-        if span_start_timestamp > next_timestamp {
-            return BatchStatus::Future;
-        }
+            span_start_timestamp + batch.block_count.saturating_sub(1) * blocktime;
 
         // check for delta activation
 
@@ -302,85 +328,109 @@ where
         };
 
         if let Some(batch_origin) = batch_origin {
-            if batch_origin.timestamp < self.config.chain.delta_time {
-                tracing::warn!("span batch seen before delta start");
-                return BatchStatus::Drop;
+            if !self
+                .config
+                .chain
+                .hardforks
+                .is_delta_active(batch_origin.timestamp)
+            {
+                return BatchStatus::Drop(DropReason::BeforeDelta);
             }
         } else {
             return BatchStatus::Undecided;
         }
 
+        // check batch timestamp
+
+        if span_start_timestamp > next_timestamp {
+            return BatchStatus::Future;
+        }
+
+        if span_end_timestamp < next_timestamp {
+            return BatchStatus::Drop(DropReason::Past);
+        }
+
+        // an overlapping batch must start exactly at a block of the safe chain
+        if span_start_timestamp < next_timestamp
+            && (span_start_timestamp > head.timestamp
+                || !(head.timestamp - span_start_timestamp).is_multiple_of(blocktime))
+        {
+            return BatchStatus::Drop(DropReason::MisalignedTimestamp);
+        }
+
         // find previous l2 block
 
-        let prev_timestamp = span_start_timestamp - self.config.chain.blocktime;
+        let prev_timestamp = span_start_timestamp - blocktime;
         let (prev_l2_block, prev_l2_epoch) =
             if let Some(block) = state.l2_info_by_timestamp(prev_timestamp) {
                 block
             } else {
-                tracing::warn!("prev l2 block not found");
-                return BatchStatus::Drop;
+                return BatchStatus::Drop(DropReason::ParentUnknown);
             };
 
         // check that block builds on existing chain
 
         if prev_l2_block.hash.as_bytes()[..20] != batch.parent_check {
-            tracing::warn!("batch parent check failed");
-            return BatchStatus::Drop;
+            return BatchStatus::Drop(DropReason::ParentMismatch);
         }
 
         // sequencer window checks
 
         if start_epoch_num + self.config.chain.seq_window_size < batch.l1_inclusion_block {
-            tracing::warn!("sequence window check failed");
-            return BatchStatus::Drop;
+            return BatchStatus::Drop(DropReason::InclusionWindow);
         }
 
-        if start_epoch_num > prev_l2_block.number + 1 {
-            tracing::warn!("invalid start epoch number");
-            return BatchStatus::Drop;
+        if start_epoch_num > prev_l2_epoch.number + 1 {
+            return BatchStatus::Drop(DropReason::EpochTooFarAhead);
         }
 
         if let Some(l1_origin) = state.epoch_by_number(end_epoch_num) {
             if batch.l1_origin_check != l1_origin.hash.as_bytes()[..20] {
-                tracing::warn!("origin check failed");
-                return BatchStatus::Drop;
+                return BatchStatus::Drop(DropReason::EpochHashMismatch);
             }
         } else {
-            tracing::warn!("origin not found");
-            return BatchStatus::Drop;
+            tracing::debug!("span batch origin not known");
+            return BatchStatus::Undecided;
         }
 
         if start_epoch_num < prev_l2_epoch.number {
-            tracing::warn!("invalid start epoch number");
-            return BatchStatus::Drop;
+            return BatchStatus::Drop(DropReason::EpochTooOld);
         }
 
-        // check sequencer drift
+        // check the origin and sequencer drift of each new block
 
         let block_inputs = batch.block_inputs(&self.config);
-        for (i, input) in block_inputs.iter().enumerate() {
-            let input_epoch = state.epoch_by_number(input.epoch).unwrap();
-            let next_epoch = state.epoch_by_number(input.epoch + 1);
+        let mut prev_epoch_num = prev_l2_epoch.number;
+        for input in block_inputs.iter() {
+            let origin_advanced = input.epoch > prev_epoch_num;
+            prev_epoch_num = input.epoch;
+
+            if input.timestamp <= head.timestamp {
+                continue;
+            }
+
+            let Some(input_epoch) = state.epoch_by_number(input.epoch) else {
+                tracing::debug!("span batch origin not known");
+                return BatchStatus::Undecided;
+            };
 
             if input.timestamp < input_epoch.timestamp {
-                tracing::warn!("input timestamp too low");
-                return BatchStatus::Drop;
+                return BatchStatus::Drop(DropReason::TimestampBeforeOrigin);
             }
 
-            if input.timestamp > input_epoch.timestamp + self.config.chain.max_seq_drift {
-                if input.transactions.is_empty() {
-                    if !batch.origin_bits[i] {
-                        if let Some(next_epoch) = next_epoch {
-                            if input.timestamp >= next_epoch.timestamp {
-                                return BatchStatus::Drop;
-                            }
-                        } else {
-                            return BatchStatus::Undecided;
-                        }
-                    }
-                } else {
-                    return BatchStatus::Drop;
-                }
+            if let Some(status) = drift_status(
+                &self.config,
+                input.timestamp,
+                !input.transactions.is_empty(),
+                input_epoch,
+                origin_advanced,
+                state.epoch_by_number(input.epoch + 1),
+            ) {
+                return status;
+            }
+
+            if has_invalid_transactions(&input.transactions) {
+                return BatchStatus::Drop(DropReason::InvalidTransaction);
             }
         }
 
@@ -390,12 +440,10 @@ where
             if input.timestamp < next_timestamp {
                 if let Some((_, epoch)) = state.l2_info_by_timestamp(input.timestamp) {
                     if input.epoch != epoch.number {
-                        tracing::warn!("epoch mismatch in overlapped blocks");
-                        return BatchStatus::Drop;
+                        return BatchStatus::Drop(DropReason::OverlapMismatch);
                     }
                 } else {
-                    tracing::warn!("overlapped l2 block not found");
-                    return BatchStatus::Drop;
+                    return BatchStatus::Drop(DropReason::ParentUnknown);
                 }
             }
         }
@@ -404,8 +452,138 @@ where
     }
 }
 
+/// The block inputs of an accepted batch that have not been output yet. Only span batches
+/// leave inputs pending, since a single batch is output at once.
+#[derive(Debug, Default)]
+struct PendingBatch {
+    /// Timestamp of the batch
+    timestamp: u64,
+    /// The remaining block inputs, in order
+    inputs: VecDeque<BlockInput<u64>>,
+}
+
+impl PendingBatch {
+    /// Starts outputting the block inputs of a batch
+    fn start(&mut self, timestamp: u64, inputs: Vec<BlockInput<u64>>) {
+        self.timestamp = timestamp;
+        self.inputs = inputs.into();
+    }
+
+    /// Returns the next block input of the batch
+    fn next(&mut self) -> Option<BlockInput<u64>> {
+        self.inputs.pop_front()
+    }
+
+    /// Drops the remaining block inputs of the batch
+    fn reset(&mut self) {
+        if !self.inputs.is_empty() {
+            tracing::debug!(
+                "dropping {} pending blocks of batch: t={}",
+                self.inputs.len(),
+                self.timestamp
+            );
+        }
+
+        self.inputs.clear();
+    }
+}
+
+/// Checks the timestamp of a block against the maximum sequencer drift of its L1 origin.
+///
+/// Past the drift, a block may only be empty, and only if it keeps its origin because the
+/// next L1 block is still ahead of it, which preserves the L2 time >= L1 time invariant.
+/// Blocks advancing the origin are always allowed. Returns [None] if the block is within
+/// the rules.
+fn drift_status(
+    config: &Config,
+    timestamp: u64,
+    has_transactions: bool,
+    origin: Epoch,
+    origin_advanced: bool,
+    next_origin: Option<Epoch>,
+) -> Option<BatchStatus> {
+    let max_drift = config.chain.max_sequencer_drift(origin.timestamp);
+    if timestamp <= origin.timestamp + max_drift {
+        return None;
+    }
+
+    if has_transactions {
+        return Some(BatchStatus::Drop(DropReason::SequencerDrift));
+    }
+
+    if origin_advanced {
+        return None;
+    }
+
+    match next_origin {
+        Some(next_origin) if timestamp >= next_origin.timestamp => {
+            Some(BatchStatus::Drop(DropReason::OriginNotAdvanced))
+        }
+        Some(_) => {
+            tracing::info!("continuing with empty batch before late L1 block");
+            None
+        }
+        None => {
+            tracing::debug!("sequencer drift undecided");
+            Some(BatchStatus::Undecided)
+        }
+    }
+}
+
+/// Returns true if a transaction is empty or a deposit, which batches may not contain
+fn has_invalid_transactions(transactions: &[RawTransaction]) -> bool {
+    transactions
+        .iter()
+        .any(|tx| tx.0.is_empty() || tx.0[0] == 0x7E)
+}
+
+/// Returns the deposit-only block following the safe head if the sequencing window of the
+/// safe epoch has expired at the given L1 block. The window also expires at its last L1
+/// block once all of its data is consumed without finding a batch.
+///
+/// Deposit-only blocks keep the safe epoch until they reach the timestamp of the next
+/// epoch, so the L2 time never falls behind the L1 time, and then advance to the next
+/// epoch if its sequencing window has expired too. Each call derives a single block, so
+/// several skipped epochs are filled one block at a time as the safe head advances.
+fn deposit_only_input(
+    config: &Config,
+    safe_head: BlockInfo,
+    epoch: Epoch,
+    next_epoch: Option<Epoch>,
+    l1_block: u64,
+    out_of_data: bool,
+) -> Option<BlockInput<u64>> {
+    let expired = |epoch: &Epoch| {
+        let expiry = epoch.number + config.chain.seq_window_size;
+        expiry < l1_block || (expiry == l1_block && out_of_data)
+    };
+
+    if !expired(&epoch) {
+        return None;
+    }
+
+    let next_epoch = next_epoch?;
+    let next_timestamp = safe_head.timestamp + config.chain.blocktime;
+
+    let origin = if next_timestamp < next_epoch.timestamp {
+        epoch
+    } else if expired(&next_epoch) {
+        next_epoch
+    } else {
+        // batches for the next epoch can still be included
+        return None;
+    };
+
+    Some(BlockInput {
+        epoch: origin.number,
+        timestamp: next_timestamp,
+        transactions: Vec::new(),
+        l1_inclusion_block: l1_block,
+    })
+}
+
 /// Attempts to decode channel data into a [Batch] vector
-fn decode_batches(channel: &Channel, chain_id: u64) -> Result<Vec<Batch>> {
+pub fn decode_batches(channel: &Channel, chain_id: u64) -> Result<Vec<Batch>> {
     let mut channel_data = Vec::new();
     let d = Decoder::new(channel.data.as_slice())?;
     for b in d.bytes() {
@@ -481,7 +659,7 @@ impl Batch {
 #[derive(Debug, Clone, PartialEq)]
 enum BatchStatus {
     /// The batch is invalid
-    Drop,
+    Drop(DropReason),
     /// The batch is valid
     Accept,
     /// Not enough data to decide
@@ -489,3 +667,314 @@ enum BatchStatus {
     /// Batch is for a future block
     Future,
 }
+
+/// The batch validation rule violated by a dropped batch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DropReason {
+    /// The batch has no blocks after the safe head
+    Past,
+    /// The batch does not build on the safe chain
+    ParentMismatch,
+    /// The block preceding the batch is unknown
+    ParentUnknown,
+    /// The batch was included after the sequencing window of its origin
+    InclusionWindow,
+    /// The origin of the batch is older than the origin of its parent
+    EpochTooOld,
+    /// The origin of the batch skips an L1 block
+    EpochTooFarAhead,
+    /// The origin hash of the batch is not canonical
+    EpochHashMismatch,
+    /// A block timestamp is before its origin
+    TimestampBeforeOrigin,
+    /// A block with transactions exceeds the maximum sequencer drift
+    SequencerDrift,
+    /// An empty block exceeds the maximum sequencer drift without adopting the next origin
+    OriginNotAdvanced,
+    /// A block contains an empty or deposit transaction
+    InvalidTransaction,
+    /// A span batch has an origin before delta activation
+    BeforeDelta,
+    /// A span batch does not start at the timestamp of a block
+    MisalignedTimestamp,
+    /// A block overlapping the safe chain has a different origin
+    OverlapMismatch,
+}
+
+impl DropReason {
+    /// Returns the label of the reason in the `dropped_batches` metric
+    fn label(&self) -> &'static str {
+        match self {
+            DropReason::Past => "past",
+            DropReason::ParentMismatch => "parent_mismatch",
+            DropReason::ParentUnknown => "parent_unknown",
+            DropReason::InclusionWindow => "inclusion_window",
+            DropReason::EpochTooOld => "epoch_too_old",
+            DropReason::EpochTooFarAhead => "epoch_too_far_ahead",
+            DropReason::EpochHashMismatch => "epoch_hash_mismatch",
+            DropReason::TimestampBeforeOrigin => "timestamp_before_origin",
+            DropReason::SequencerDrift => "sequencer_drift",
+            DropReason::OriginNotAdvanced => "origin_not_advanced",
+            DropReason::InvalidTransaction => "invalid_transaction",
+            DropReason::BeforeDelta => "before_delta",
+            DropReason::MisalignedTimestamp => "misaligned_timestamp",
+            DropReason::OverlapMismatch => "overlap_mismatch",
+        }
+    }
+}
+
+impl std::fmt::Display for DropReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.label())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::{providers::Provider, types::H256};
+
+    use crate::{
+        config::HardforkSchedule,
+        l1::{l1_info::L1BlockInfo, L1Info},
+    };
+
+    use super::*;
+
+    /// A channels stage without channels, so batches are inserted directly
+    struct NoChannels;
+
+    impl Iterator for NoChannels {
+        type Item = Channel;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            None
+        }
+    }
+
+    impl PurgeableIterator for NoChannels {
+        fn purge(&mut self) {}
+    }
+
+    fn l2_block(number: u64) -> BlockInfo {
+        BlockInfo {
+            number,
+            hash: H256::from_low_u64_be(1000 + number),
+            parent_hash: H256::from_low_u64_be(999 + number),
+            timestamp: number * 2,
+        }
+    }
+
+    fn l1_info(config: &Config, epoch: Epoch) -> L1Info {
+        L1Info {
+            block_info: L1BlockInfo {
+                number: epoch.number,
+                hash: epoch.hash,
+                parent_hash: H256::zero(),
+                timestamp: epoch.timestamp,
+                base_fee: Default::default(),
+                mix_hash: H256::zero(),
+                parent_beacon_block_root: None,
+                blob_base_fee: Default::default(),
+            },
+            system_config: config.chain.system_config,
+            user_deposits: Vec::new(),
+            batcher_transactions: Vec::new(),
+            finalized: false,
+        }
+    }
+
+    /// Outputs the block inputs of the pending batches, advancing the safe head like the
+    /// driver does, until the given number of blocks is derived or derivation stalls
+    fn derive(
+        batches: &mut Batches<NoChannels>,
+        state: &Arc<RwLock<State>>,
+        blocks: usize,
+    ) -> Vec<(u64, u64, Vec<RawTransaction>)> {
+        let mut derived = Vec::new();
+        while derived.len() < blocks {
+            let Some(input) = batches.next() else {
+                break;
+            };
+
+            let mut state = state.write().unwrap();
+            let head = l2_block(state.safe_head.number + 1);
+            assert_eq!(input.timestamp, head.timestamp);
+
+            let safe_epoch = state.safe_epoch;
+            state.update_safe_head(head, safe_epoch);
+            derived.push((input.timestamp, input.epoch, input.transactions));
+        }
+
+        derived
+    }
+
+    #[tokio::test]
+    async fn test_purge_mid_span() -> Result<()> {
+        let mut config = Config {
+            l2_rpc_url: "http://127.0.0.1:1".to_string(),
+            ..Default::default()
+        };
+        config.chain.l2_genesis = l2_block(0);
+        config.chain.blocktime = 2;
+        config.chain.seq_window_size = 100;
+        config.chain.max_seq_drift = 600;
+        config.chain.hardforks = HardforkSchedule::default();
+        let config = Arc::new(config);
+
+        let provider = Provider::try_from(config.l2_rpc_url.as_str())?;
+        let state = Arc::new(RwLock::new(
+            State::new(
+                l2_block(0),
+                epoch(0),
+                &provider,
+                #[cfg(feature = "engine-client")]
+                None,
+                config.clone(),
+            )
+            .await,
+        ));
+
+        let start_state = |state: &mut State, head: BlockInfo| {
+            state.purge(head, epoch(0));
+            state.update_l1_info(l1_info(&config, epoch(0)));
+            state.update_l1_info(l1_info(&config, epoch(1)));
+        };
+        start_state(&mut state.write().unwrap(), l2_block(0));
+
+        // a span of 6 blocks in epoch 0 following the genesis block
+        let span = SpanBatch {
+            rel_timestamp: 2,
+            l1_origin_num: 0,
+            parent_check: l2_block(0).hash.as_bytes()[..20].try_into()?,
+            l1_origin_check: epoch(0).hash.as_bytes()[..20].try_into()?,
+            block_count: 6,
+            origin_bits: vec![false; 6],
+            block_tx_counts: vec![1; 6],
+            transactions: (1..=6).map(|i| RawTransaction(vec![2, i])).collect(),
+            l1_inclusion_block: 1,
+        };
+
+        let mut batches = Batches::new(NoChannels, state.clone(), config.clone());
+        batches.batches.insert(2, Batch::Span(span.clone()));
+        let expected = derive(&mut batches, &state, 6);
+        assert_eq!(expected.len(), 6);
+        assert!(batches.next().is_none());
+
+        // derive half of the span, then reorg back to its first block
+        start_state(&mut state.write().unwrap(), l2_block(0));
+        batches.purge();
+        batches.batches.insert(2, Batch::Span(span.clone()));
+        assert_eq!(derive(&mut batches, &state, 3), expected[..3]);
+        assert_eq!(batches.pending.inputs.len(), 3);
+
+        start_state(&mut state.write().unwrap(), l2_block(1));
+        batches.purge();
+        assert!(batches.pending.inputs.is_empty());
+        assert!(batches.next().is_none());
+
+        // the span read again overlaps the new safe head, and derives the same blocks
+        batches.batches.insert(2, Batch::Span(span));
+        assert_eq!(derive(&mut batches, &state, 6), expected[1..]);
+        assert_eq!(state.read().unwrap().safe_head, l2_block(6));
+
+        Ok(())
+    }
+
+    fn epoch(number: u64) -> Epoch {
+        Epoch {
+            number,
+            hash: H256::from_low_u64_be(number),
+            timestamp: number * 12,
+        }
+    }
+
+    #[test]
+    fn test_deposit_only_inputs() {
+        let mut config = Config::default();
+        config.chain.seq_window_size = 10;
+        config.chain.blocktime = 2;
+
+        let mut safe_head = BlockInfo {
+            timestamp: 0,
+            ..Default::default()
+        };
+        let mut safe_epoch = epoch(0);
+
+        // the window of epoch 0 is open until its last block is consumed
+        let input = deposit_only_input(&config, safe_head, safe_epoch, Some(epoch(1)), 9, true);
+        assert!(input.is_none());
+        let input = deposit_only_input(&config, safe_head, safe_epoch, Some(epoch(1)), 10, false);
+        assert!(input.is_none());
+        assert!(deposit_only_input(&config, safe_head, safe_epoch, None, 10, true).is_none());
+
+        // with the L1 head at block 12, the windows of epochs 0 to 2 have expired
+        let mut inputs = Vec::new();
+        while let Some(input) = deposit_only_input(
+            &config,
+            safe_head,
+            safe_epoch,
+            Some(epoch(safe_epoch.number + 1)),
+            12,
+            true,
+        ) {
+            assert!(input.transactions.is_empty());
+            assert_eq!(input.l1_inclusion_block, 12);
+
+            safe_head.timestamp = input.timestamp;
+            safe_epoch = epoch(input.epoch);
+            inputs.push((input.timestamp, input.epoch));
+        }
+
+        let expected = (1..=17)
+            .map(|block| (block * 2, block * 2 / 12))
+            .collect::<Vec<_>>();
+        assert_eq!(inputs, expected);
+
+        // epoch 3 is still within its sequencing window
+        assert_eq!(safe_epoch.number, 2);
+        assert_eq!(safe_head.timestamp, 34);
+    }
+
+    #[test]
+    fn test_sequencer_drift() {
+        let mut config = Config::default();
+        config.chain.max_seq_drift = 600;
+        config.chain.hardforks.fjord_time = Some(1200);
+
+        let origin = epoch(10);
+        let next = Some(epoch(11));
+        let late = Some(Epoch {
+            timestamp: 900,
+            ..epoch(11)
+        });
+
+        // within the pre-fjord drift of 600s past the origin at 120
+        assert_eq!(drift_status(&config, 720, true, origin, false, next), None);
+
+        // past the drift, blocks with transactions are dropped
+        assert_eq!(
+            drift_status(&config, 722, true, origin, false, next),
+            Some(BatchStatus::Drop(DropReason::SequencerDrift))
+        );
+
+        // empty blocks must adopt the next origin once its timestamp is reached
+        assert_eq!(
+            drift_status(&config, 722, false, origin, false, next),
+            Some(BatchStatus::Drop(DropReason::OriginNotAdvanced))
+        );
+        assert_eq!(drift_status(&config, 722, false, origin, false, late), None);
+        assert_eq!(drift_status(&config, 722, false, origin, true, next), None);
+        assert_eq!(
+            drift_status(&config, 722, false, origin, false, None),
+            Some(BatchStatus::Undecided)
+        );
+
+        // fjord raises the drift to 1800s
+        let origin = epoch(100);
+        assert_eq!(drift_status(&config, 3000, true, origin, false, next), None);
+        assert_eq!(
+            drift_status(&config, 3002, true, origin, false, next),
+            Some(BatchStatus::Drop(DropReason::SequencerDrift))
+        );
+    }
+}
@@ -1,7 +1,10 @@
-use std::sync::Arc;
+use std::{collections::VecDeque, sync::Arc};
 
 use super::batcher_transactions::{BatcherTransaction, Frame};
-use crate::{config::Config, derive::PurgeableIterator};
+use crate::{
+    config::Config,
+    derive::{PurgeableIterator, StepBudget},
+};
 
 /// Represents the `channel bank` stage to track & process channels
 pub struct Channels<I> {
@@ -9,12 +12,16 @@ pub struct Channels<I> {
     batcher_tx_iter: I,
     /// List of incomplete channels
     pending_channels: Vec<PendingChannel>,
-    /// A bank of frames and their version numbers pulled from a [BatcherTransaction]
-    frame_bank: Vec<Frame>,
-    /// The maximum total byte size of pending channels to hold in the bank
-    max_channel_size: u64,
-    /// The max timeout for a channel (as measured by the frame L1 block number)
-    channel_timeout: u64,
+    /// The frames of the latest [BatcherTransaction] not yet pushed into a channel
+    frame_bank: VecDeque<Frame>,
+    /// The L1 block of the latest batcher transaction
+    l1_origin: u64,
+    /// The timestamp of the L1 block of the latest batcher transaction
+    l1_timestamp: u64,
+    /// The global Magi config
+    config: Arc<Config>,
+    /// Bounds the frames pushed by a call, if set
+    budget: Option<Arc<StepBudget>>,
 }
 
 impl<I> Iterator for Channels<I>
@@ -38,6 +45,8 @@ where
         self.batcher_tx_iter.purge();
         self.pending_channels.clear();
         self.frame_bank.clear();
+        self.l1_origin = 0;
+        self.l1_timestamp = 0;
     }
 }
 
@@ -47,11 +56,20 @@ impl<I> Channels<I> {
         Self {
             batcher_tx_iter,
             pending_channels: Vec::new(),
-            frame_bank: Vec::new(),
-            max_channel_size: config.chain.max_channel_size,
-            channel_timeout: config.chain.channel_timeout,
+            frame_bank: VecDeque::new(),
+            l1_origin: 0,
+            l1_timestamp: 0,
+            config,
+            budget: None,
         }
     }
+
+    /// Pushes at most the frames the given [StepBudget] allows per call, keeping the
+    /// remaining frames for the next call
+    pub fn with_budget(mut self, budget: Arc<StepBudget>) -> Self {
+        self.budget = Some(budget);
+        self
+    }
 }
 
 impl<I> Channels<I>
@@ -60,6 +78,8 @@ where
 {
     /// Pushes a frame into the correct pending channel
     fn push_frame(&mut self, frame: Frame) {
+        self.advance_origin(frame.l1_inclusion_block, frame.l1_inclusion_timestamp);
+
         // Find a pending channel matching on the channel id
         let pending_index = self
             .pending_channels
@@ -70,23 +90,46 @@ where
         // Otherwise, construct a new pending channel with the frame's id
         if let Some(pending_index) = pending_index {
             self.pending_channels[pending_index].push_frame(frame);
-
-            if self.pending_channels[pending_index].is_timed_out(self.channel_timeout) {
-                self.pending_channels.remove(pending_index);
-            }
         } else {
             let pending = PendingChannel::new(frame);
             self.pending_channels.push(pending);
         }
     }
 
-    /// Pull the next batcher transaction from the BatcherTransactions stage
+    /// Pull the next batcher transaction from the BatcherTransactions stage, so frames are
+    /// streamed into the channels one transaction at a time. Only called once the frames of
+    /// the previous transaction are pushed, so the origin never passes unpushed frames.
     fn fill_bank(&mut self) {
-        let next_batcher_tx = self.batcher_tx_iter.next();
+        if let Some(tx) = self.batcher_tx_iter.next() {
+            self.advance_origin(tx.l1_origin, tx.l1_timestamp);
+            self.frame_bank.extend(tx.frames);
+        }
+    }
 
-        if let Some(tx) = next_batcher_tx {
-            self.frame_bank.append(&mut tx.frames.to_vec());
+    /// Moves the origin of the channel bank to a later L1 block, dropping the pending
+    /// channels which timed out at the new origin. The timeout is the one in effect at the
+    /// origin, so channels opened before granite expire under the granite timeout once it
+    /// activates. Frames of an expired channel open a new channel.
+    fn advance_origin(&mut self, l1_origin: u64, l1_timestamp: u64) {
+        if l1_origin < self.l1_origin {
+            return;
         }
+
+        self.l1_origin = l1_origin;
+        self.l1_timestamp = l1_timestamp;
+
+        let channel_timeout = self.config.chain.max_channel_timeout(l1_timestamp);
+        self.pending_channels.retain(|channel| {
+            let timed_out = channel.is_timed_out(l1_origin, channel_timeout);
+            if timed_out {
+                tracing::debug!(
+                    "channel {} timed out at L1 block {}",
+                    channel.channel_id,
+                    l1_origin
+                );
+            }
+            !timed_out
+        });
     }
 
     /// Fetch the completed channel if it is ready
@@ -102,23 +145,39 @@ where
         })
     }
 
-    /// Processes frames until there are either none left or a channel is ready
+    /// Processes frames until there are either none left, a channel is ready or the step
+    /// budget is used up
     fn process_frames(&mut self) -> Option<Channel> {
-        self.fill_bank();
+        if self.frame_bank.is_empty() {
+            self.fill_bank();
+        }
 
-        while !self.frame_bank.is_empty() {
+        let mut channel = None;
+        while channel.is_none() && !self.frame_bank.is_empty() && self.take_frame() {
             // Append the frame to the channel
-            let frame = self.frame_bank.remove(0);
+            let frame = self
+                .frame_bank
+                .pop_front()
+                .expect("frame bank is not empty");
             let frame_channel_id = frame.channel_id;
             self.push_frame(frame);
             self.prune();
 
-            if let Some(channel) = self.fetch_ready_channel(frame_channel_id) {
-                return Some(channel);
-            }
+            channel = self.fetch_ready_channel(frame_channel_id);
         }
 
-        None
+        if let Some(budget) = &self.budget {
+            budget.set_buffered_frames(!self.frame_bank.is_empty());
+        }
+
+        channel
+    }
+
+    /// Takes the step budget of a frame, returning false once it is used up
+    fn take_frame(&self) -> bool {
+        self.budget
+            .as_ref()
+            .is_none_or(|budget| budget.take_frame())
     }
 
     /// Removes a pending channel from the bank
@@ -137,9 +196,10 @@ where
             .sum::<u32>() as u64
     }
 
-    /// Prunes channels to the max size
+    /// Prunes channels to the max size at the current L1 timestamp
     fn prune(&mut self) {
-        while self.total_size() > self.max_channel_size {
+        let max_size = self.config.chain.max_channel_bank_size(self.l1_timestamp);
+        while self.total_size() > max_size {
             self.remove().expect("should have removed a channel");
         }
     }
@@ -154,10 +214,8 @@ pub struct PendingChannel {
     frames: Vec<Frame>,
     /// The number of frames seen
     size: Option<u16>,
-    /// The highest L1 block number frames in this channel were submitted in
-    highest_l1_block: u64,
-    /// The lowest L1 block number frames in this channel were submitted in
-    lowest_l1_block: u64,
+    /// The L1 block number the first frame of this channel was submitted in
+    open_l1_block: u64,
 }
 
 impl PendingChannel {
@@ -171,8 +229,7 @@ impl PendingChannel {
 
         Self {
             channel_id: frame.channel_id,
-            highest_l1_block: frame.l1_inclusion_block,
-            lowest_l1_block: frame.l1_inclusion_block,
+            open_l1_block: frame.l1_inclusion_block,
             frames: vec![frame],
             size,
         }
@@ -183,18 +240,22 @@ impl PendingChannel {
         self.size == Some(self.frames.len() as u16)
     }
 
-    /// Checks if the channel has timed out
-    pub fn is_timed_out(&self, max_timeout: u64) -> bool {
-        self.highest_l1_block - self.lowest_l1_block > max_timeout
+    /// Checks if the channel has timed out at the given L1 origin, which is the case once
+    /// more than `max_timeout` L1 blocks passed since it was opened
+    pub fn is_timed_out(&self, l1_origin: u64, max_timeout: u64) -> bool {
+        self.open_l1_block + max_timeout < l1_origin
     }
 
     /// Assembles the pending channel into channel data
     pub fn assemble(&self) -> Vec<u8> {
-        let mut frames = self.frames.clone();
+        let mut frames = self.frames.iter().collect::<Vec<_>>();
         frames.sort_by_key(|f| f.frame_number);
-        frames
-            .iter()
-            .fold(Vec::new(), |a, b| [a, b.frame_data.clone()].concat())
+
+        let size = frames.iter().map(|f| f.frame_data.len()).sum();
+        frames.iter().fold(Vec::with_capacity(size), |mut data, f| {
+            data.extend_from_slice(&f.frame_data);
+            data
+        })
     }
 
     /// The highest L1 inclusion block of frames in the channel.
@@ -216,12 +277,6 @@ impl PendingChannel {
             .any(|n| n == frame.frame_number);
 
         if !has_seen {
-            if frame.l1_inclusion_block > self.highest_l1_block {
-                self.highest_l1_block = frame.l1_inclusion_block;
-            } else if frame.l1_inclusion_block < self.lowest_l1_block {
-                self.lowest_l1_block = frame.l1_inclusion_block;
-            }
-
             if frame.is_last {
                 self.size = Some(frame.frame_number + 1);
             }
@@ -256,11 +311,13 @@ impl From<PendingChannel> for Channel {
 #[cfg(test)]
 mod tests {
     use crate::{
-        config::{ChainConfig, Config},
-        derive::stages::batcher_transactions::{
-            BatcherTransactionMessage, BatcherTransactions, Frame,
+        config::{ChainConfig, Config, WatchdogAction},
+        derive::{
+            stages::batcher_transactions::{BatcherTransactionMessage, BatcherTransactions, Frame},
+            StepBudget,
         },
     };
+    use bytes::Bytes;
     use std::sync::{mpsc, Arc};
 
     use super::Channels;
@@ -272,10 +329,11 @@ mod tests {
         let frame = Frame {
             channel_id: 5,
             frame_number: 0,
-            frame_data: Vec::new(),
+            frame_data: Bytes::new(),
             frame_data_len: 0,
             is_last: true,
             l1_inclusion_block: 0,
+            l1_inclusion_timestamp: 0,
         };
 
         stage.push_frame(frame);
@@ -292,19 +350,21 @@ mod tests {
         let frame_1 = Frame {
             channel_id: 5,
             frame_number: 0,
-            frame_data: Vec::new(),
+            frame_data: Bytes::new(),
             frame_data_len: 0,
             is_last: false,
             l1_inclusion_block: 0,
+            l1_inclusion_timestamp: 0,
         };
 
         let frame_2 = Frame {
             channel_id: 5,
             frame_number: 1,
-            frame_data: Vec::new(),
+            frame_data: Bytes::new(),
             frame_data_len: 0,
             is_last: true,
             l1_inclusion_block: 0,
+            l1_inclusion_timestamp: 0,
         };
 
         stage.push_frame(frame_1);
@@ -327,19 +387,21 @@ mod tests {
         let frame_1 = Frame {
             channel_id: 5,
             frame_number: 0,
-            frame_data: Vec::new(),
+            frame_data: Bytes::new(),
             frame_data_len: 0,
             is_last: false,
             l1_inclusion_block: 43,
+            l1_inclusion_timestamp: 0,
         };
 
         let frame_2 = Frame {
             channel_id: 5,
             frame_number: 1,
-            frame_data: Vec::new(),
+            frame_data: Bytes::new(),
             frame_data_len: 0,
             is_last: true,
             l1_inclusion_block: 96,
+            l1_inclusion_timestamp: 0,
         };
 
         stage.push_frame(frame_1);
@@ -358,10 +420,11 @@ mod tests {
         let frame_1 = Frame {
             channel_id: 5,
             frame_number: 0,
-            frame_data: Vec::new(),
+            frame_data: Bytes::new(),
             frame_data_len: 0,
             is_last: false,
             l1_inclusion_block: 43,
+            l1_inclusion_timestamp: 0,
         };
 
         stage.push_frame(frame_1);
@@ -378,26 +441,167 @@ mod tests {
         let frame_1 = Frame {
             channel_id: 5,
             frame_number: 0,
-            frame_data: Vec::new(),
+            frame_data: Bytes::new(),
             frame_data_len: 0,
             is_last: false,
             l1_inclusion_block: 0,
+            l1_inclusion_timestamp: 0,
         };
 
         let frame_2 = Frame {
             channel_id: 5,
             frame_number: 1,
-            frame_data: Vec::new(),
+            frame_data: Bytes::new(),
             frame_data_len: 0,
             is_last: true,
             l1_inclusion_block: 500,
+            l1_inclusion_timestamp: 0,
         };
 
         stage.push_frame(frame_1);
         assert_eq!(stage.pending_channels.len(), 1);
 
+        // the late frame cannot complete the expired channel, and opens a new one instead
         stage.push_frame(frame_2);
-        assert_eq!(stage.pending_channels.len(), 0);
+        assert_eq!(stage.pending_channels.len(), 1);
+        assert_eq!(stage.pending_channels[0].open_l1_block, 500);
+        assert!(!stage.pending_channels[0].is_complete());
+        assert!(stage.fetch_ready_channel(5).is_none());
+    }
+
+    #[test]
+    fn test_granite_channel_timeout() {
+        let mut chain = ChainConfig::optimism_sepolia();
+        chain.channel_timeout = 300;
+        chain.hardforks.granite_time = Some(1000);
+        let config = Config {
+            chain,
+            ..Default::default()
+        };
+
+        let (_tx, rx) = mpsc::channel();
+        let mut stage = Channels::new(BatcherTransactions::new(rx), Arc::new(config));
+
+        let frame = |channel_id, frame_number, l1_inclusion_block, l1_inclusion_timestamp| Frame {
+            channel_id,
+            frame_number,
+            frame_data: Bytes::new(),
+            frame_data_len: 0,
+            is_last: false,
+            l1_inclusion_block,
+            l1_inclusion_timestamp,
+        };
+
+        // before granite a channel may span the configured timeout
+        stage.push_frame(frame(1, 0, 0, 900));
+        stage.push_frame(frame(1, 1, 300, 990));
+        assert_eq!(stage.pending_channels.len(), 1);
+
+        // a channel opened before granite expires under the granite timeout once the
+        // origin activates it
+        stage.push_frame(frame(2, 0, 400, 1000));
+        assert_eq!(stage.pending_channels.len(), 1);
+        assert_eq!(stage.pending_channels[0].channel_id, 2);
+
+        // from granite channels time out after 50 L1 blocks
+        stage.push_frame(frame(2, 1, 450, 1010));
+        assert_eq!(stage.pending_channels[0].frames.len(), 2);
+
+        stage.push_frame(frame(2, 2, 451, 1012));
+        assert_eq!(stage.pending_channels.len(), 1);
+        assert_eq!(stage.pending_channels[0].open_l1_block, 451);
+        assert_eq!(stage.pending_channels[0].frames.len(), 1);
+    }
+
+    #[test]
+    fn test_expire_without_frames() {
+        let (mut stage, tx) = create_stage();
+
+        let data = |channel_id: u128, frame_number: u16, is_last: bool| {
+            let mut data = vec![0];
+            data.extend_from_slice(&channel_id.to_be_bytes());
+            data.extend_from_slice(&frame_number.to_be_bytes());
+            data.extend_from_slice(&1u32.to_be_bytes());
+            data.push(0xff);
+            data.push(is_last as u8);
+            Bytes::from(data)
+        };
+        let send = |txs, l1_origin| {
+            tx.send(BatcherTransactionMessage {
+                txs,
+                l1_origin,
+                l1_timestamp: 0,
+            })
+            .unwrap();
+        };
+
+        send(vec![data(1, 0, false)], 10);
+        assert!(stage.next().is_none());
+        assert_eq!(stage.pending_channels.len(), 1);
+
+        // an L1 block without batcher transactions still expires the channel
+        send(Vec::new(), 311);
+        assert!(stage.next().is_none());
+        assert!(stage.pending_channels.is_empty());
+
+        // the last frame then cannot complete it
+        send(vec![data(1, 1, true)], 312);
+        assert!(stage.next().is_none());
+
+        // while a channel completed within the timeout is ready
+        send(vec![data(2, 0, false)], 320);
+        send(vec![data(2, 1, true)], 620);
+        assert!(stage.next().is_none());
+        assert_eq!(stage.next().map(|c| c.id), Some(2));
+    }
+
+    #[test]
+    fn test_step_budget() {
+        let (stage, tx) = create_stage();
+        let budget = Arc::new(StepBudget::new(1));
+        let mut stage = stage.with_budget(budget.clone());
+
+        let frame = |channel_id: u128, frame_number: u16, is_last: bool| {
+            let mut data = channel_id.to_be_bytes().to_vec();
+            data.extend_from_slice(&frame_number.to_be_bytes());
+            data.extend_from_slice(&1u32.to_be_bytes());
+            data.push(0xff);
+            data.push(is_last as u8);
+            data
+        };
+
+        // a transaction with three frames, followed by one with a single frame channel
+        let mut data = vec![0];
+        (0..3).for_each(|i| data.extend(frame(1, i, i == 2)));
+        tx.send(BatcherTransactionMessage {
+            txs: vec![
+                Bytes::from(data),
+                Bytes::from([vec![0], frame(2, 0, true)].concat()),
+            ],
+            l1_origin: 10,
+            l1_timestamp: 0,
+        })
+        .unwrap();
+
+        // each step pushes a single frame and keeps the others for the next steps
+        for _ in 0..2 {
+            budget.start_step();
+            assert!(stage.next().is_none());
+            assert!(budget.is_consumed());
+            assert!(budget.is_buffered());
+        }
+
+        budget.start_step();
+        assert_eq!(stage.next().map(|c| c.id), Some(1));
+
+        // the next transaction is only read once the frames of the previous one are pushed
+        budget.start_step();
+        assert_eq!(stage.next().map(|c| c.id), Some(2));
+        assert!(!budget.is_buffered());
+
+        budget.start_step();
+        assert!(stage.next().is_none());
+        assert!(!budget.is_consumed());
     }
 
     fn create_stage() -> (
@@ -409,12 +613,60 @@ mod tests {
             l1_beacon_url: String::new(),
             l2_rpc_url: String::new(),
             l2_engine_url: String::new(),
-            jwt_secret: String::new(),
+            jwt_secret: None,
             rpc_port: 9545,
             rpc_addr: "127.0.0.1".to_string(),
             chain: ChainConfig::optimism_sepolia(),
             checkpoint_sync_url: None,
             devnet: false,
+            supervisor_rpc_url: None,
+            p2p_port: 9876,
+            l2_engine_standby_urls: Vec::new(),
+            forkchoice_batch_size: 16,
+            forkchoice_batch_interval_ms: 200,
+            jwt_secret_file: None,
+            l1_rpc_budget: None,
+            l1_rpc_method_weights: Default::default(),
+            data_dir: None,
+            l1_epoch_retention: None,
+            l2_block_retention: None,
+            p2p_sync: false,
+            p2p_max_inbound_peers: 30,
+            p2p_max_outbound_peers: 20,
+            p2p_static_peers: Vec::new(),
+            p2p_bootnodes: Vec::new(),
+            sequencer: false,
+            p2p_sequencer_key: None,
+            p2p_sequencer_keystore: None,
+            p2p_sequencer_keystore_password_file: None,
+            p2p_sequencer_signer_url: None,
+            p2p_sequencer_signer_backup_url: None,
+            p2p_sequencer_address: None,
+            profile_derivation: false,
+            unsafe_follow_url: None,
+            unsafe_follow_max_lag: None,
+            readiness_max_head_lag: 60,
+            readiness_max_stall: 120,
+            rpc_api: vec!["optimism".to_string(), "opp2p".to_string()],
+            rpc_auth_token: None,
+            rpc_jwt_secret: None,
+            rpc_cors_origins: Vec::new(),
+            rpc_rate_limit: None,
+            l1_block_buffer: 64,
+            light_follow: false,
+            derivation_lookahead: 0,
+            sequencer_gas_limit: None,
+            rpc_precompute_outputs: false,
+            watchdog_stall_timeout: 180,
+            watchdog_action: WatchdogAction::Log,
+            rpc_sequencer_url: None,
+            rpc_sequencer_rate_limit: None,
+            driver_tick_timeout: 30,
+            dry_run: false,
+            dry_run_until: None,
+            unsafe_payload_retention: 256,
+            derivation_step_frames: 64,
+            halt_on_unsupported_fork: false,
         };
 
         let (tx, rx) = mpsc::channel();
@@ -8,7 +8,7 @@ pub mod batcher_transactions;
 pub mod batches;
 
 /// A module to handle building a Block Input.
-mod block_input;
+pub mod block_input;
 
 /// A module to handle the channel bank derivation stage.
 pub mod channels;
@@ -1,17 +1,51 @@
-use std::{collections::BTreeMap, sync::Arc};
+use std::{
+    collections::BTreeMap,
+    mem::size_of,
+    sync::{Arc, RwLock, Weak},
+    time::Duration,
+};
 
 use ethers::{
     providers::{Http, Middleware, Provider},
     types::H256,
 };
+use eyre::Result;
+use tokio::time::{interval, MissedTickBehavior};
 
 use crate::{
-    common::{BlockInfo, Epoch},
+    common::{BlockInfo, Epoch, HeadInfo},
     config::Config,
-    driver::HeadInfo,
-    l1::L1Info,
+    derive::{stages::attributes::UserDeposited, AttributeOverrides, ForcedInclusionReport},
+    l1::{l1_info::BatcherTransactionData, L1Info},
+    store::{state_path, KvStore},
+    telemetry::metrics,
+};
+
+// Backfilling the L2 block refs from the engine
+#[cfg(feature = "engine-client")]
+use {
+    crate::{
+        common::RawTransaction, engine::EngineApi,
+        l1::deposit_validation::compute_transactions_root,
+    },
+    ethers::{
+        types::{transaction::optimism::DepositTransaction, Block},
+        utils::rlp::{Decodable, Rlp},
+    },
+    serde_json::{json, Value},
+    std::ops::RangeInclusive,
 };
 
+/// How often the in-memory state is pruned
+const PRUNE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Key prefix of persisted L1 epochs by block number
+const L1_NUMBER_PREFIX: &[u8] = b"l1n";
+/// Key prefix of persisted L1 epochs by block hash
+const L1_HASH_PREFIX: &[u8] = b"l1h";
+/// Key prefix of persisted L2 block refs by block number
+const L2_NUMBER_PREFIX: &[u8] = b"l2n";
+
 /// Represents the current derivation state. Consists of cached L1 & L2 blocks, and details of the current safe head & safe epoch.
 pub struct State {
     /// Map of L1 blocks from the current L1 safe epoch - ``seq_window_size``
@@ -26,19 +60,63 @@ pub struct State {
     pub safe_epoch: Epoch,
     /// The current epoch number. Same as the first L1 block number in this sequencing window.
     pub current_epoch_num: u64,
+    /// The blocks derived deposit-only since the node started
+    pub forced_inclusion: ForcedInclusionReport,
+    /// The gas limit override of the blocks built by the sequencer
+    pub overrides: AttributeOverrides,
     /// Global config
     config: Arc<Config>,
+    /// Persisted L1 epochs and L2 block refs, if a data directory is configured
+    store: Option<KvStore>,
 }
 
 impl State {
     /// Creates a new [State] and fetches and caches a range of L2 blocks.
+    ///
+    /// If a data directory is configured, L1 epochs and L2 block refs are also persisted
+    /// there. Lookups past the in-memory window are served from disk, and the L2 block
+    /// refs are loaded from disk on restart if they lead up to the finalized head.
+    ///
+    /// Otherwise, if an engine is given, the blocks are backfilled using `engine_getPayloadBodiesByRangeV1`
+    /// and a batch of L2 RPC header requests, falling back to fetching each block if that fails.
     pub async fn new(
         finalized_head: BlockInfo,
         finalized_epoch: Epoch,
         provider: &Provider<Http>,
+        #[cfg(feature = "engine-client")] engine: Option<&EngineApi>,
         config: Arc<Config>,
     ) -> Self {
-        let l2_refs = l2_refs(finalized_head.number, provider, &config).await;
+        let full_l2_retention = max_drift(&config) / config.chain.blocktime;
+        if config.data_dir.is_none()
+            && (l1_retention(&config) < config.chain.seq_window_size
+                || l2_retention(&config) < full_l2_retention)
+        {
+            tracing::warn!("state retention is below the sequencing window without a data directory, lookups of pruned blocks will fail");
+        }
+
+        let store = state_path(&config).and_then(|path| {
+            KvStore::open(&path)
+                .map_err(|err| tracing::warn!("could not open state store {:?}: {}", path, err))
+                .ok()
+        });
+
+        let stored_refs = store
+            .as_ref()
+            .and_then(|store| stored_l2_refs(store, finalized_head, &config));
+
+        let l2_refs = match stored_refs {
+            Some(refs) => refs,
+            None => {
+                l2_refs(
+                    finalized_head.number,
+                    provider,
+                    #[cfg(feature = "engine-client")]
+                    engine,
+                    &config,
+                )
+                .await
+            }
+        };
 
         Self {
             l1_info: BTreeMap::new(),
@@ -47,7 +125,10 @@ impl State {
             safe_head: finalized_head,
             safe_epoch: finalized_epoch,
             current_epoch_num: 0,
+            forced_inclusion: ForcedInclusionReport::default(),
+            overrides: AttributeOverrides::from_config(&config),
             config,
+            store,
         }
     }
 
@@ -63,31 +144,30 @@ impl State {
             .and_then(|hash| self.l1_info.get(hash))
     }
 
-    /// Returns a cached L2 block by block timestamp
-    pub fn l2_info_by_timestamp(&self, timestamp: u64) -> Option<&(BlockInfo, Epoch)> {
+    /// Returns an L2 block by block timestamp
+    pub fn l2_info_by_timestamp(&self, timestamp: u64) -> Option<(BlockInfo, Epoch)> {
         let block_num = (timestamp - self.config.chain.l2_genesis.timestamp)
             / self.config.chain.blocktime
             + self.config.chain.l2_genesis.number;
 
-        self.l2_refs.get(&block_num)
+        self.l2_refs
+            .get(&block_num)
+            .copied()
+            .or_else(|| self.load(&number_key(L2_NUMBER_PREFIX, block_num)))
     }
 
     /// Returns an epoch from an L1 block hash
     pub fn epoch_by_hash(&self, hash: H256) -> Option<Epoch> {
-        self.l1_info_by_hash(hash).map(|info| Epoch {
-            number: info.block_info.number,
-            hash: info.block_info.hash,
-            timestamp: info.block_info.timestamp,
-        })
+        self.l1_info_by_hash(hash)
+            .map(epoch_from_info)
+            .or_else(|| self.load(&hash_key(hash)))
     }
 
     /// Returns an epoch by number. Same as the first L1 block number in the epoch's sequencing window.
     pub fn epoch_by_number(&self, num: u64) -> Option<Epoch> {
-        self.l1_info_by_number(num).map(|info| Epoch {
-            number: info.block_info.number,
-            hash: info.block_info.hash,
-            timestamp: info.block_info.timestamp,
-        })
+        self.l1_info_by_number(num)
+            .map(epoch_from_info)
+            .or_else(|| self.load(&number_key(L1_NUMBER_PREFIX, num)))
     }
 
     /// Inserts data from the ``l1_info`` parameter into ``l1_hashes`` & ``l1_info`` maps.
@@ -96,23 +176,30 @@ impl State {
     pub fn update_l1_info(&mut self, l1_info: L1Info) {
         self.current_epoch_num = l1_info.block_info.number;
 
+        let epoch = epoch_from_info(&l1_info);
+        self.save(&number_key(L1_NUMBER_PREFIX, epoch.number), &epoch);
+        self.save(&hash_key(epoch.hash), &epoch);
+
         self.l1_hashes
             .insert(l1_info.block_info.number, l1_info.block_info.hash);
         self.l1_info.insert(l1_info.block_info.hash, l1_info);
-
-        self.prune();
     }
 
     /// Resets the state and updates the safe head with the given parameters.
     ///
     /// ``current_epoch_num`` is set to 0.
     ///
-    /// ``l1_info`` & ``l1_hashes`` mappings are cleared.
+    /// ``l1_info`` & ``l1_hashes`` mappings are cleared, and persisted epochs and L2 block
+    /// refs past the new safe head are deleted, since they may have been reorged out.
     pub fn purge(&mut self, safe_head: BlockInfo, safe_epoch: Epoch) {
         self.current_epoch_num = 0;
         self.l1_info.clear();
         self.l1_hashes.clear();
 
+        if let Err(err) = self.delete_stored_after(safe_head.number, safe_epoch.number) {
+            tracing::warn!("could not purge state store: {}", err);
+        }
+
         self.update_safe_head(safe_head, safe_epoch);
     }
 
@@ -125,16 +212,70 @@ impl State {
 
         self.l2_refs
             .insert(self.safe_head.number, (self.safe_head, self.safe_epoch));
+
+        self.save(
+            &number_key(L2_NUMBER_PREFIX, safe_head.number),
+            &(safe_head, safe_epoch),
+        );
+    }
+
+    /// Persists a value, if a store is configured
+    fn save<T: serde::Serialize>(&mut self, key: &[u8], value: &T) {
+        let Some(store) = &mut self.store else {
+            return;
+        };
+
+        let res = serde_json::to_vec(value)
+            .map_err(eyre::Report::from)
+            .and_then(|value| store.put(key, &value));
+
+        if let Err(err) = res {
+            tracing::warn!("could not persist state: {}", err);
+        }
+    }
+
+    /// Loads a persisted value, if a store is configured
+    fn load<T: serde::de::DeserializeOwned>(&self, key: &[u8]) -> Option<T> {
+        let value = self.store.as_ref()?.get(key).ok()??;
+        serde_json::from_slice(&value).ok()
+    }
+
+    /// Deletes the persisted L2 block refs after the given L2 block and the epochs after the given L1 block
+    fn delete_stored_after(&mut self, l2_block: u64, l1_block: u64) -> Result<()> {
+        let Some(store) = &mut self.store else {
+            return Ok(());
+        };
+
+        let l2_keys = store.keys(
+            number_key(L2_NUMBER_PREFIX, l2_block + 1)..number_key(L2_NUMBER_PREFIX, u64::MAX),
+        );
+        for key in l2_keys {
+            store.delete(&key)?;
+        }
+
+        let l1_keys = store.keys(
+            number_key(L1_NUMBER_PREFIX, l1_block + 1)..number_key(L1_NUMBER_PREFIX, u64::MAX),
+        );
+        for key in l1_keys {
+            if let Some(epoch) = store.get(&key)? {
+                let epoch: Epoch = serde_json::from_slice(&epoch)?;
+                store.delete(&hash_key(epoch.hash))?;
+            }
+
+            store.delete(&key)?;
+        }
+
+        Ok(())
     }
 
-    /// Removes keys from ``l1_info`` & ``l1_hashes`` mappings if older than ``self.safe_epoch.number`` - ``seq_window_size``.
+    /// Removes keys from ``l1_info`` & ``l1_hashes`` mappings if older than ``self.safe_epoch.number`` - ``l1_epoch_retention``.
     ///
-    /// Removes keys from the ``l2_refs`` mapping if older than ``self.safe_head.number`` - (``max_seq_drift`` / ``blocktime``)
-    fn prune(&mut self) {
+    /// Removes keys from the ``l2_refs`` mapping if older than ``self.safe_head.number`` - ``l2_block_retention``
+    pub fn prune(&mut self) {
         let prune_until = self
             .safe_epoch
             .number
-            .saturating_sub(self.config.chain.seq_window_size);
+            .saturating_sub(l1_retention(&self.config));
 
         while let Some((block_num, block_hash)) = self.l1_hashes.first_key_value() {
             if *block_num >= prune_until {
@@ -145,8 +286,10 @@ impl State {
             self.l1_hashes.pop_first();
         }
 
-        let prune_until =
-            self.safe_head.number - self.config.chain.max_seq_drift / self.config.chain.blocktime;
+        let prune_until = self
+            .safe_head
+            .number
+            .saturating_sub(l2_retention(&self.config));
 
         while let Some((num, _)) = self.l2_refs.first_key_value() {
             if *num >= prune_until {
@@ -156,21 +299,164 @@ impl State {
             self.l2_refs.pop_first();
         }
     }
+
+    /// Returns an estimate of the memory held by the in-memory L1 and L2 maps in bytes
+    pub fn memory_usage(&self) -> usize {
+        let l1_info = self
+            .l1_info
+            .values()
+            .map(|info| {
+                let deposits = info
+                    .user_deposits
+                    .iter()
+                    .map(|deposit| size_of::<UserDeposited>() + deposit.data.len())
+                    .sum::<usize>();
+
+                let batcher_transactions = info
+                    .batcher_transactions
+                    .iter()
+                    .map(|tx| size_of::<BatcherTransactionData>() + tx.len())
+                    .sum::<usize>();
+
+                size_of::<(H256, L1Info)>() + deposits + batcher_transactions
+            })
+            .sum::<usize>();
+
+        let l1_hashes = self.l1_hashes.len() * size_of::<(u64, H256)>();
+        let l2_refs = self.l2_refs.len() * size_of::<(u64, (BlockInfo, Epoch))>();
+
+        l1_info + l1_hashes + l2_refs
+    }
+
+    /// Prunes the state in the background and reports its size, until the state is dropped
+    pub fn spawn_pruning(state: &Arc<RwLock<Self>>) {
+        let state: Weak<RwLock<Self>> = Arc::downgrade(state);
+
+        tokio::spawn(async move {
+            let mut ticker = interval(PRUNE_INTERVAL);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+            loop {
+                ticker.tick().await;
+
+                let Some(state) = state.upgrade() else {
+                    break;
+                };
+
+                let mut state = state.write().unwrap_or_else(|err| err.into_inner());
+                state.prune();
+                state.report();
+            }
+        });
+    }
+
+    /// Reports the size of the in-memory state
+    fn report(&self) {
+        let chain_id = self.config.chain.l2_chain_id.to_string();
+        let chain = [chain_id.as_str()];
+
+        metrics::STATE_L1_EPOCHS
+            .with_label_values(&chain)
+            .set(self.l1_info.len() as i64);
+        metrics::STATE_L2_REFS
+            .with_label_values(&chain)
+            .set(self.l2_refs.len() as i64);
+        metrics::STATE_MEMORY_BYTES
+            .with_label_values(&chain)
+            .set(self.memory_usage() as i64);
+    }
+}
+
+/// Returns the number of L1 epochs before the safe epoch kept in memory
+fn l1_retention(config: &Config) -> u64 {
+    config
+        .l1_epoch_retention
+        .unwrap_or(config.chain.seq_window_size)
+}
+
+/// Returns the number of L2 block refs before the safe head kept in memory
+fn l2_retention(config: &Config) -> u64 {
+    config
+        .l2_block_retention
+        .unwrap_or(max_drift(config) / config.chain.blocktime)
+}
+
+/// Returns the largest sequencer drift of the chain, before or after fjord
+fn max_drift(config: &Config) -> u64 {
+    config
+        .chain
+        .max_seq_drift
+        .max(config.chain.max_sequencer_drift(u64::MAX))
+}
+
+/// Returns the key of a persisted L2 block ref
+#[cfg(feature = "node")]
+pub(crate) fn l2_ref_key(num: u64) -> Vec<u8> {
+    number_key(L2_NUMBER_PREFIX, num)
+}
+
+fn number_key(prefix: &[u8], num: u64) -> Vec<u8> {
+    [prefix, &num.to_be_bytes()].concat()
+}
+
+fn hash_key(hash: H256) -> Vec<u8> {
+    [L1_HASH_PREFIX, hash.as_bytes()].concat()
+}
+
+fn epoch_from_info(info: &L1Info) -> Epoch {
+    Epoch {
+        number: info.block_info.number,
+        hash: info.block_info.hash,
+        timestamp: info.block_info.timestamp,
+    }
+}
+
+/// Returns the persisted L2 block refs leading up to the given head, if all of them are stored
+fn stored_l2_refs(
+    store: &KvStore,
+    head: BlockInfo,
+    config: &Config,
+) -> Option<BTreeMap<u64, (BlockInfo, Epoch)>> {
+    let lookback = l2_retention(config);
+    let start = head
+        .number
+        .saturating_sub(lookback)
+        .max(config.chain.l2_genesis.number);
+
+    let refs = (start..=head.number)
+        .map(|num| {
+            let value = store.get(&number_key(L2_NUMBER_PREFIX, num)).ok()??;
+            let block_ref: (BlockInfo, Epoch) = serde_json::from_slice(&value).ok()?;
+            Some((num, block_ref))
+        })
+        .collect::<Option<BTreeMap<_, _>>>()?;
+
+    // the stored refs may belong to a different chain if the heads do not match
+    (refs.get(&head.number)?.0.hash == head.hash).then_some(refs)
 }
 
 /// Returns the L2 blocks from the given ``head_num`` - (``max_seq_drift`` / ``blocktime``) to ``head_num``.
 ///
 /// If the lookback period is before the genesis block, it will return L2 blocks starting from genesis.
-async fn l2_refs(
+pub(crate) async fn l2_refs(
     head_num: u64,
     provider: &Provider<Http>,
+    #[cfg(feature = "engine-client")] engine: Option<&EngineApi>,
     config: &Config,
 ) -> BTreeMap<u64, (BlockInfo, Epoch)> {
-    let lookback = config.chain.max_seq_drift / config.chain.blocktime;
+    let lookback = l2_retention(config);
     let start = head_num
         .saturating_sub(lookback)
         .max(config.chain.l2_genesis.number);
 
+    #[cfg(feature = "engine-client")]
+    if let Some(engine) = engine {
+        match backfill_l2_refs(start..=head_num, engine, config).await {
+            Ok(refs) => return refs,
+            Err(err) => tracing::warn!("could not backfill L2 blocks from the engine: {}", err),
+        }
+    }
+
     let mut refs = BTreeMap::new();
     for i in start..=head_num {
         let l2_block = provider.get_block_with_txs(i).await;
@@ -191,3 +477,209 @@ async fn l2_refs(
 
     refs
 }
+
+/// Returns the L2 blocks in the given range using the payload bodies from the engine
+/// and the block headers from the L2 RPC, without fetching each block individually.
+#[cfg(feature = "engine-client")]
+async fn backfill_l2_refs(
+    range: RangeInclusive<u64>,
+    engine: &EngineApi,
+    config: &Config,
+) -> Result<BTreeMap<u64, (BlockInfo, Epoch)>> {
+    let start = *range.start();
+    let count = range.end() - start + 1;
+
+    let headers = l2_headers(&config.l2_rpc_url, range).await?;
+    let bodies = engine.get_payload_bodies_by_range(start, count).await?;
+
+    if bodies.len() != headers.len() {
+        eyre::bail!(
+            "expected {} payload bodies, got {}",
+            headers.len(),
+            bodies.len()
+        );
+    }
+
+    let mut refs = BTreeMap::new();
+    let mut parent: Option<BlockInfo> = None;
+
+    for (header, body) in headers.into_iter().zip(bodies) {
+        let info = BlockInfo {
+            number: header
+                .number
+                .ok_or(eyre::eyre!("block not included"))?
+                .as_u64(),
+            hash: header.hash.ok_or(eyre::eyre!("block not included"))?,
+            parent_hash: header.parent_hash,
+            timestamp: header.timestamp.as_u64(),
+        };
+
+        // the chain may have reorged in between requests
+        if parent.is_some_and(|p| p.hash != info.parent_hash) {
+            eyre::bail!("L2 headers do not form a chain at block {}", info.number);
+        }
+
+        let body = body.ok_or(eyre::eyre!("engine is missing block {}", info.number))?;
+        if compute_transactions_root(&body.transactions) != header.transactions_root {
+            eyre::bail!(
+                "payload body does not match header of block {}",
+                info.number
+            );
+        }
+
+        let calldata = l1_info_calldata(&body.transactions)?;
+        let head_info = HeadInfo::try_from_l1_info_calldata(config, info, calldata)?;
+        refs.insert(info.number, (head_info.l2_block_info, head_info.l1_epoch));
+
+        parent = Some(info);
+    }
+
+    Ok(refs)
+}
+
+/// Fetches the headers of the given L2 blocks in a single JSON-RPC batch request
+#[cfg(feature = "engine-client")]
+async fn l2_headers(url: &str, range: RangeInclusive<u64>) -> Result<Vec<Block<H256>>> {
+    let requests = range
+        .clone()
+        .map(|num| {
+            json!({
+                "jsonrpc": "2.0",
+                "id": num,
+                "method": "eth_getBlockByNumber",
+                "params": [format!("{:#x}", num), false],
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut responses: Vec<Value> = reqwest::Client::new()
+        .post(url)
+        .json(&requests)
+        .send()
+        .await?
+        .json()
+        .await?;
+
+    responses.sort_by_key(|res| res["id"].as_u64());
+
+    let headers = responses
+        .into_iter()
+        .map(|mut res| {
+            let header = res["result"].take();
+            if header.is_null() {
+                eyre::bail!("block {} not found", res["id"]);
+            }
+
+            Ok(serde_json::from_value::<Block<H256>>(header)?)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    if headers.len() as u64 != range.end() - range.start() + 1 {
+        eyre::bail!("missing L2 headers in batch response");
+    }
+
+    Ok(headers)
+}
+
+/// Returns the calldata of the L1 attributes deposited transaction, which is the first
+/// transaction of every L2 block
+#[cfg(feature = "engine-client")]
+fn l1_info_calldata(transactions: &[RawTransaction]) -> Result<ethers::types::Bytes> {
+    let tx = transactions
+        .first()
+        .and_then(|tx| tx.0.split_first())
+        .filter(|(tx_type, _)| **tx_type == 0x7E)
+        .ok_or(eyre::eyre!(
+            "Could not find the L1 attributes deposited transaction"
+        ))?
+        .1;
+
+    let deposit = DepositTransaction::decode(&Rlp::new(tx))?;
+
+    Ok(deposit.tx.data.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::ChainConfig;
+
+    use super::*;
+
+    #[test]
+    fn test_prune_retention() {
+        let config = Config {
+            chain: ChainConfig::optimism(),
+            l1_epoch_retention: Some(2),
+            l2_block_retention: Some(3),
+            ..Default::default()
+        };
+
+        let mut state = State {
+            l1_info: BTreeMap::new(),
+            l1_hashes: (0..10)
+                .map(|num| (num, H256::from_low_u64_be(num)))
+                .collect(),
+            l2_refs: BTreeMap::new(),
+            safe_head: BlockInfo::default(),
+            safe_epoch: Epoch::default(),
+            current_epoch_num: 0,
+            forced_inclusion: ForcedInclusionReport::default(),
+            overrides: AttributeOverrides::default(),
+            config: Arc::new(config),
+            store: None,
+        };
+
+        for num in 0..10 {
+            let head = BlockInfo {
+                number: num,
+                ..Default::default()
+            };
+            let epoch = Epoch {
+                number: num,
+                ..Default::default()
+            };
+            state.update_safe_head(head, epoch);
+        }
+
+        let usage = state.memory_usage();
+        state.prune();
+
+        assert_eq!(
+            state.l1_hashes.keys().copied().collect::<Vec<_>>(),
+            vec![7, 8, 9]
+        );
+        assert_eq!(
+            state.l2_refs.keys().copied().collect::<Vec<_>>(),
+            vec![6, 7, 8, 9]
+        );
+        assert!(state.memory_usage() < usage);
+    }
+
+    #[test]
+    #[cfg(feature = "engine-client")]
+    fn test_l1_info_calldata() -> Result<()> {
+        use ethers::types::{Address, Bytes, TransactionRequest, U256};
+
+        let calldata = Bytes::from(vec![0x44, 0x0a, 0x5e, 0x20, 0x01]);
+        let deposit = DepositTransaction::new(
+            TransactionRequest::new()
+                .from(Address::repeat_byte(0xde))
+                .to(Address::repeat_byte(0x15))
+                .value(0)
+                .gas(1_000_000)
+                .data(calldata.clone()),
+            H256::repeat_byte(0x01),
+            Some(U256::zero()),
+            false,
+        );
+
+        let tx = RawTransaction([vec![0x7E], deposit.rlp().to_vec()].concat());
+        assert_eq!(l1_info_calldata(&[tx])?, calldata);
+
+        let legacy = RawTransaction(vec![0xf8, 0x00]);
+        assert!(l1_info_calldata(&[legacy]).is_err());
+        assert!(l1_info_calldata(&[]).is_err());
+
+        Ok(())
+    }
+}
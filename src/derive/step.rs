@@ -0,0 +1,94 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::engine::PayloadAttributes;
+
+/// The outcome of a single [step](super::Pipeline::step) of the derivation pipeline
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StepResult {
+    /// The attributes of the next block were derived
+    Attributes(Box<PayloadAttributes>),
+    /// Input was consumed without deriving attributes, and more is buffered for the next step
+    Progress,
+    /// The buffered input is consumed or the pipeline stopped, so the next step needs the
+    /// next L1 block
+    Idle,
+}
+
+/// Bounds the work of a pipeline step, shared by the built-in stages.
+///
+/// A step pulls at most one batcher transaction, pushes at most `max_frames` frames into
+/// channels, decodes at most one channel and accepts at most one batch. The stages record
+/// the input they hold back, so the pipeline tells a step stopped by the bound from one
+/// that ran out of input.
+#[derive(Debug, Default)]
+pub struct StepBudget {
+    /// Frames a step may push into channels, 0 for no bound
+    max_frames: usize,
+    /// Frames pushed in the current step
+    frames: AtomicUsize,
+    /// Batcher transactions and frames consumed in the current step
+    consumed: AtomicUsize,
+    /// Whether batcher transactions are waiting to be split into frames
+    buffered_txs: AtomicBool,
+    /// Whether frames are waiting to be pushed into channels
+    buffered_frames: AtomicBool,
+}
+
+impl StepBudget {
+    /// Creates a new [StepBudget] pushing at most `max_frames` frames per step
+    pub fn new(max_frames: usize) -> Self {
+        Self {
+            max_frames,
+            ..Default::default()
+        }
+    }
+
+    /// Starts a new step with the full budget
+    pub fn start_step(&self) {
+        self.frames.store(0, Ordering::Relaxed);
+        self.consumed.store(0, Ordering::Relaxed);
+    }
+
+    /// Takes the budget of a frame, returning false once the step used up its budget
+    pub fn take_frame(&self) -> bool {
+        let frames = self.frames.fetch_add(1, Ordering::Relaxed);
+        if self.max_frames > 0 && frames >= self.max_frames {
+            return false;
+        }
+
+        self.consumed.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+
+    /// Records a batcher transaction taken from the batcher transactions stage
+    pub fn take_tx(&self) {
+        self.consumed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records whether batcher transactions are waiting to be split into frames
+    pub fn set_buffered_txs(&self, buffered: bool) {
+        self.buffered_txs.store(buffered, Ordering::Relaxed);
+    }
+
+    /// Records whether frames are waiting to be pushed into channels
+    pub fn set_buffered_frames(&self, buffered: bool) {
+        self.buffered_frames.store(buffered, Ordering::Relaxed);
+    }
+
+    /// Returns true if the stages hold input not yet consumed
+    pub fn is_buffered(&self) -> bool {
+        self.buffered_txs.load(Ordering::Relaxed) || self.buffered_frames.load(Ordering::Relaxed)
+    }
+
+    /// Returns true if the current step consumed input
+    pub fn is_consumed(&self) -> bool {
+        self.consumed.load(Ordering::Relaxed) > 0
+    }
+
+    /// Forgets the buffered input, once the stages are purged
+    pub fn reset(&self) {
+        self.start_step();
+        self.set_buffered_txs(false);
+        self.set_buffered_frames(false);
+    }
+}
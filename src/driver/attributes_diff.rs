@@ -0,0 +1,381 @@
+use std::{
+    collections::VecDeque,
+    fmt::Debug,
+    sync::{Arc, RwLock},
+};
+
+use ethers::{
+    types::{Block, Bytes, Transaction, H256},
+    utils::{keccak256, rlp::Rlp},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    common::{AttributesDepositedCall, RawTransaction},
+    engine::PayloadAttributes,
+};
+
+/// Number of mismatches kept for the `debug_attributeMismatches` RPC method
+const MAX_MISMATCHES: usize = 32;
+
+/// Index of the data field in an RLP encoded deposit transaction
+const DEPOSIT_DATA_INDEX: usize = 7;
+
+/// The differences between derived [PayloadAttributes] and the unsafe block they replace,
+/// to tell whether the sequencer or the verifier derived the wrong block
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AttributesDiff {
+    /// The number of the replaced unsafe block
+    pub block_number: u64,
+    /// The hash of the replaced unsafe block
+    pub block_hash: H256,
+    /// The differences between the transaction lists
+    pub transactions: TransactionsDiff,
+    /// The differing fields of the L1 info deposit
+    pub l1_info: Vec<FieldDiff>,
+    /// The differing block fields
+    pub fields: Vec<FieldDiff>,
+}
+
+/// The differences between the derived and unsafe transaction lists
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionsDiff {
+    /// Number of derived transactions
+    pub derived_count: usize,
+    /// Number of transactions in the unsafe block
+    pub unsafe_count: usize,
+    /// Index of the first transaction that differs, if any
+    pub first_difference: Option<usize>,
+    /// Hashes of the derived transactions missing from the unsafe block
+    pub missing: Vec<H256>,
+    /// Hashes of the unsafe block transactions that were not derived
+    pub unexpected: Vec<H256>,
+}
+
+/// A field with different derived and unsafe values
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldDiff {
+    /// The field name
+    pub field: String,
+    /// The derived value
+    pub derived: String,
+    /// The value in the unsafe block
+    #[serde(rename = "unsafe")]
+    pub unsafe_block: String,
+}
+
+impl FieldDiff {
+    fn new(field: &str, derived: impl Debug, unsafe_block: impl Debug) -> Self {
+        Self {
+            field: field.to_string(),
+            derived: format!("{:?}", derived),
+            unsafe_block: format!("{:?}", unsafe_block),
+        }
+    }
+
+    fn differs(&self) -> bool {
+        self.derived != self.unsafe_block
+    }
+}
+
+impl AttributesDiff {
+    /// Compares derived attributes with the unsafe block at the same height
+    pub fn new(attributes: &PayloadAttributes, block: &Block<Transaction>) -> Self {
+        let derived = attributes.transactions.as_deref().unwrap_or_default();
+
+        let mut fields = vec![
+            FieldDiff::new(
+                "timestamp",
+                attributes.timestamp.as_u64(),
+                block.timestamp.as_u64(),
+            ),
+            FieldDiff::new(
+                "gasLimit",
+                attributes.gas_limit.as_u64(),
+                block.gas_limit.as_u64(),
+            ),
+            FieldDiff::new(
+                "feeRecipient",
+                attributes.suggested_fee_recipient,
+                block.author.unwrap_or_default(),
+            ),
+            FieldDiff::new(
+                "prevRandao",
+                attributes.prev_randao,
+                block.mix_hash.unwrap_or_default(),
+            ),
+        ];
+        if let Some(root) = attributes.parent_beacon_block_root {
+            fields.push(FieldDiff::new(
+                "parentBeaconBlockRoot",
+                root,
+                block.parent_beacon_block_root.unwrap_or_default(),
+            ));
+        }
+        fields.retain(FieldDiff::differs);
+
+        Self {
+            block_number: block.number.unwrap_or_default().as_u64(),
+            block_hash: block.hash.unwrap_or_default(),
+            transactions: TransactionsDiff::new(derived, &block.transactions),
+            l1_info: l1_info_diff(derived.first(), block.transactions.first()),
+            fields,
+        }
+    }
+
+    /// Returns a one line summary of the differences, for the logs
+    pub fn summary(&self) -> String {
+        let mut parts = Vec::new();
+
+        let txs = &self.transactions;
+        if let Some(index) = txs.first_difference {
+            parts.push(format!(
+                "transactions differ from index {} ({} derived, {} unsafe, {} missing, {} unexpected)",
+                index,
+                txs.derived_count,
+                txs.unsafe_count,
+                txs.missing.len(),
+                txs.unexpected.len()
+            ));
+        }
+
+        for diff in self.l1_info.iter().chain(&self.fields) {
+            parts.push(format!(
+                "{}: {} derived, {} unsafe",
+                diff.field, diff.derived, diff.unsafe_block
+            ));
+        }
+
+        if parts.is_empty() {
+            return "no differences".to_string();
+        }
+
+        parts.join("; ")
+    }
+}
+
+impl TransactionsDiff {
+    fn new(derived: &[RawTransaction], block: &[Transaction]) -> Self {
+        let derived = derived
+            .iter()
+            .map(|tx| H256(keccak256(&tx.0)))
+            .collect::<Vec<_>>();
+        let block = block.iter().map(|tx| tx.hash()).collect::<Vec<_>>();
+
+        let first_difference = (0..derived.len().max(block.len()))
+            .find(|index| derived.get(*index) != block.get(*index));
+
+        Self {
+            derived_count: derived.len(),
+            unsafe_count: block.len(),
+            first_difference,
+            missing: derived
+                .iter()
+                .filter(|hash| !block.contains(hash))
+                .copied()
+                .collect(),
+            unexpected: block
+                .iter()
+                .filter(|hash| !derived.contains(hash))
+                .copied()
+                .collect(),
+        }
+    }
+}
+
+/// Compares the decoded L1 info deposits of the derived and unsafe blocks
+fn l1_info_diff(derived: Option<&RawTransaction>, block: Option<&Transaction>) -> Vec<FieldDiff> {
+    let derived = derived.and_then(|tx| deposit_data(&tx.0));
+    let block = block.map(|tx| tx.input.clone());
+    if derived == block {
+        return Vec::new();
+    }
+
+    let (Some(derived), Some(block)) = (derived.clone(), block.clone()) else {
+        return vec![FieldDiff::new("calldata", derived, block)];
+    };
+
+    let (Some(derived_call), Some(block_call)) = (decode_l1_info(&derived), decode_l1_info(&block))
+    else {
+        return vec![FieldDiff::new("calldata", derived, block)];
+    };
+
+    let mut diffs = vec![
+        FieldDiff::new("number", derived_call.number, block_call.number),
+        FieldDiff::new("timestamp", derived_call.timestamp, block_call.timestamp),
+        FieldDiff::new("baseFee", derived_call.basefee, block_call.basefee),
+        FieldDiff::new("hash", derived_call.hash, block_call.hash),
+        FieldDiff::new(
+            "sequenceNumber",
+            derived_call.sequence_number,
+            block_call.sequence_number,
+        ),
+        FieldDiff::new(
+            "batcherHash",
+            derived_call.batcher_hash,
+            block_call.batcher_hash,
+        ),
+        FieldDiff::new(
+            "feeOverhead",
+            derived_call.fee_overhead,
+            block_call.fee_overhead,
+        ),
+        FieldDiff::new("feeScalar", derived_call.fee_scalar, block_call.fee_scalar),
+        FieldDiff::new(
+            "blobBaseFeeScalar",
+            derived_call.blob_base_fee_scalar,
+            block_call.blob_base_fee_scalar,
+        ),
+        FieldDiff::new(
+            "blobBaseFee",
+            derived_call.blob_base_fee,
+            block_call.blob_base_fee,
+        ),
+    ];
+    diffs.retain(FieldDiff::differs);
+
+    // the same values in different formats, around the Ecotone activation
+    if diffs.is_empty() {
+        diffs.push(FieldDiff::new("calldata", derived, block));
+    }
+
+    diffs
+}
+
+/// Returns the data of an encoded deposit transaction
+fn deposit_data(tx: &[u8]) -> Option<Bytes> {
+    let (0x7E, rlp) = tx.split_first()? else {
+        return None;
+    };
+
+    Rlp::new(rlp)
+        .val_at::<Vec<u8>>(DEPOSIT_DATA_INDEX)
+        .ok()
+        .map(Bytes::from)
+}
+
+fn decode_l1_info(calldata: &Bytes) -> Option<AttributesDepositedCall> {
+    AttributesDepositedCall::try_from_ecotone(calldata.clone())
+        .or_else(|_| AttributesDepositedCall::try_from_bedrock(calldata.clone()))
+        .ok()
+}
+
+/// The latest attribute mismatches, shared with the `debug_attributeMismatches` RPC method
+#[derive(Debug, Clone, Default)]
+pub struct AttributeMismatches {
+    /// The mismatches, oldest first
+    diffs: Arc<RwLock<VecDeque<AttributesDiff>>>,
+}
+
+impl AttributeMismatches {
+    /// Records a mismatch, dropping the oldest once full
+    pub fn record(&self, diff: AttributesDiff) {
+        let mut diffs = self.diffs.write().unwrap_or_else(|err| err.into_inner());
+        if diffs.len() >= MAX_MISMATCHES {
+            diffs.pop_front();
+        }
+
+        diffs.push_back(diff);
+    }
+
+    /// Returns the recorded mismatches, oldest first
+    pub fn recent(&self) -> Vec<AttributesDiff> {
+        let diffs = self.diffs.read().unwrap_or_else(|err| err.into_inner());
+        diffs.iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::{
+        types::{Address, U256, U64},
+        utils::rlp::RlpStream,
+    };
+
+    use super::*;
+
+    /// Returns Bedrock L1 info calldata for the given L1 block number and sequence number
+    fn l1_info_calldata(number: u64, sequence_number: u64) -> Vec<u8> {
+        let selector = &keccak256(
+            "setL1BlockValues(uint64,uint64,uint256,bytes32,uint64,bytes32,uint256,uint256)",
+        )[..4];
+        let mut calldata = selector.to_vec();
+        for word in [number, 1000, 7, 0, sequence_number, 0, 188, 684000] {
+            let mut bytes = [0u8; 32];
+            U256::from(word).to_big_endian(&mut bytes);
+            calldata.extend_from_slice(&bytes);
+        }
+
+        calldata
+    }
+
+    fn deposit_tx(calldata: &[u8]) -> RawTransaction {
+        let mut rlp = RlpStream::new_list(8);
+        rlp.append(&H256::zero())
+            .append(&Address::zero())
+            .append(&Address::zero())
+            .append(&0u64)
+            .append(&0u64)
+            .append(&1_000_000u64)
+            .append(&false)
+            .append(&calldata.to_vec());
+
+        RawTransaction([vec![0x7E], rlp.out().to_vec()].concat())
+    }
+
+    #[test]
+    fn test_attributes_diff() {
+        let user_tx = RawTransaction(vec![0x02, 0x01]);
+        let attributes = PayloadAttributes {
+            timestamp: U64::from(1000),
+            gas_limit: U64::from(30_000_000),
+            transactions: Some(vec![deposit_tx(&l1_info_calldata(100, 2)), user_tx.clone()]),
+            ..Default::default()
+        };
+
+        let block = Block {
+            number: Some(U64::from(50)),
+            timestamp: U256::from(1000),
+            gas_limit: U256::from(25_000_000),
+            author: Some(Address::zero()),
+            mix_hash: Some(H256::zero()),
+            transactions: vec![
+                Transaction {
+                    input: l1_info_calldata(100, 3).into(),
+                    ..Default::default()
+                },
+                Transaction {
+                    nonce: U256::from(9),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        let diff = AttributesDiff::new(&attributes, &block);
+        assert_eq!(diff.block_number, 50);
+        assert_eq!(diff.transactions.first_difference, Some(0));
+        assert_eq!(diff.transactions.missing.len(), 2);
+        assert_eq!(diff.transactions.unexpected.len(), 2);
+        assert_eq!(
+            diff.l1_info,
+            vec![FieldDiff::new("sequenceNumber", 2u64, 3u64)]
+        );
+        assert_eq!(
+            diff.fields,
+            vec![FieldDiff::new("gasLimit", 30_000_000u64, 25_000_000u64)]
+        );
+        assert!(diff
+            .summary()
+            .contains("gasLimit: 30000000 derived, 25000000 unsafe"));
+
+        let mismatches = AttributeMismatches::default();
+        for _ in 0..MAX_MISMATCHES + 1 {
+            mismatches.clone().record(diff.clone());
+        }
+        assert_eq!(mismatches.recent().len(), MAX_MISMATCHES);
+    }
+}
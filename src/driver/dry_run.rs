@@ -0,0 +1,363 @@
+use async_trait::async_trait;
+use ethers::{types::H256, utils::keccak256};
+use eyre::Result;
+use serde::Serialize;
+
+use crate::{
+    common::BlockInfo,
+    config::{ChainConfig, Config},
+    engine::{
+        Engine, ExecutionPayload, ForkChoiceUpdate, ForkchoiceState, PayloadAttributes, PayloadId,
+        PayloadStatus, Status,
+    },
+};
+
+use super::interop::DEPOSIT_TX_TYPE;
+
+/// Answers the forkchoice updates of a dry run without an execution client. Dry runs never
+/// build or execute payloads, so those requests fail.
+#[derive(Debug, Default)]
+pub struct DryRunEngine;
+
+#[async_trait]
+impl Engine for DryRunEngine {
+    async fn forkchoice_updated(
+        &self,
+        forkchoice_state: ForkchoiceState,
+        _payload_attributes: Option<PayloadAttributes>,
+    ) -> Result<ForkChoiceUpdate> {
+        Ok(ForkChoiceUpdate {
+            payload_status: PayloadStatus {
+                status: Status::Valid,
+                latest_valid_hash: Some(forkchoice_state.head_block_hash),
+                validation_error: None,
+            },
+            payload_id: None,
+        })
+    }
+
+    async fn new_payload(&self, _execution_payload: ExecutionPayload) -> Result<PayloadStatus> {
+        eyre::bail!("payloads are not executed in a dry run")
+    }
+
+    async fn get_payload(&self, _payload_id: PayloadId) -> Result<ExecutionPayload> {
+        eyre::bail!("payloads are not built in a dry run")
+    }
+}
+
+/// What a dry run derived, printed when it stops
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DryRunSummary {
+    /// Number of derived blocks
+    pub blocks: u64,
+    /// Number of transactions in the derived blocks, deposits included
+    pub transactions: u64,
+    /// Number of deposit transactions in the derived blocks
+    pub deposits: u64,
+    /// Number of L1 epochs the blocks were derived from
+    pub epochs: u64,
+    /// The first derived block
+    pub first_block: Option<u64>,
+    /// The last derived block
+    pub last_block: Option<u64>,
+    /// The epoch of the first derived block
+    pub first_epoch: Option<u64>,
+    /// The epoch of the last derived block
+    pub last_epoch: Option<u64>,
+    /// Number of blocks identical to the block of the L2 RPC at their height
+    pub matched: u64,
+    /// Number of blocks differing from the block of the L2 RPC at their height
+    pub mismatched: u64,
+    /// Chains the hashes of every derived attributes, so two runs deriving the same blocks
+    /// have the same digest
+    pub digest: H256,
+}
+
+/// Validates and hashes the derived attributes in place of the engine, enabled with
+/// `dry_run`
+#[derive(Debug)]
+pub struct DryRun {
+    /// The chain config, for the forks in effect at each block
+    chain: ChainConfig,
+    /// The L2 block after which the dry run stops, if any
+    until: Option<u64>,
+    /// What has been derived so far
+    summary: DryRunSummary,
+}
+
+impl DryRun {
+    /// Creates a new [DryRun] if enabled in the config
+    pub fn from_config(config: &Config) -> Option<Self> {
+        config.dry_run.then(|| Self {
+            chain: config.chain.clone(),
+            until: config.dry_run_until,
+            summary: DryRunSummary::default(),
+        })
+    }
+
+    /// Validates the structure of attributes built on top of `parent`, returning their hash
+    pub fn check(&self, attributes: &PayloadAttributes, parent: &BlockInfo) -> Result<H256> {
+        validate(attributes, parent, &self.chain)?;
+        Ok(hash_attributes(attributes))
+    }
+
+    /// Records checked attributes with the head they advanced to, and whether they match the
+    /// block of the L2 RPC at their height if it has one
+    pub fn record(
+        &mut self,
+        attributes: &PayloadAttributes,
+        head: &BlockInfo,
+        hash: H256,
+        matched: Option<bool>,
+    ) {
+        let summary = &mut self.summary;
+        let epoch = attributes.epoch.map(|epoch| epoch.number);
+        let transactions = attributes.transactions.as_deref().unwrap_or_default();
+
+        summary.blocks += 1;
+        summary.transactions += transactions.len() as u64;
+        summary.deposits += transactions
+            .iter()
+            .filter(|tx| tx.0.first() == Some(&DEPOSIT_TX_TYPE))
+            .count() as u64;
+
+        if epoch != summary.last_epoch {
+            summary.epochs += 1;
+        }
+
+        summary.first_block.get_or_insert(head.number);
+        summary.last_block = Some(head.number);
+        summary.first_epoch = summary.first_epoch.or(epoch);
+        summary.last_epoch = epoch;
+
+        match matched {
+            Some(true) => summary.matched += 1,
+            Some(false) => summary.mismatched += 1,
+            None => {}
+        }
+
+        summary.digest = H256(keccak256(
+            [summary.digest.as_bytes(), hash.as_bytes()].concat(),
+        ));
+    }
+
+    /// Returns true once the configured last block is derived
+    pub fn is_done(&self, safe_head: &BlockInfo) -> bool {
+        self.until.is_some_and(|until| safe_head.number >= until)
+    }
+
+    /// Logs the summary and prints it to stdout as JSON
+    pub fn report(&self) {
+        let summary = &self.summary;
+        tracing::info!(
+            "dry run derived {} blocks with {} transactions in {} epochs, digest {:?}",
+            summary.blocks,
+            summary.transactions,
+            summary.epochs,
+            summary.digest
+        );
+
+        match serde_json::to_string_pretty(summary) {
+            Ok(json) => println!("{}", json),
+            Err(err) => tracing::warn!("could not serialize the dry run summary: {}", err),
+        }
+    }
+}
+
+/// Returns the hash of the block a dry run derived without a block of the L2 RPC at its
+/// height, standing in for the block hash computed by the engine
+pub fn simulated_block_hash(parent: &BlockInfo, attributes_hash: H256) -> H256 {
+    H256(keccak256(
+        [parent.hash.as_bytes(), attributes_hash.as_bytes()].concat(),
+    ))
+}
+
+/// Checks that derived attributes are well formed on top of `parent`: they follow it by one
+/// block time, start with the deposits and the L1 info deposit, and set the fields of the
+/// forks active at their timestamp
+fn validate(attributes: &PayloadAttributes, parent: &BlockInfo, chain: &ChainConfig) -> Result<()> {
+    let timestamp = attributes.timestamp.as_u64();
+    if timestamp != parent.timestamp + chain.blocktime {
+        eyre::bail!(
+            "attributes at timestamp {} do not follow block {} at timestamp {}",
+            timestamp,
+            parent.number,
+            parent.timestamp
+        );
+    }
+
+    let epoch = attributes
+        .epoch
+        .ok_or(eyre::eyre!("attributes without epoch"))?;
+    if epoch.timestamp > timestamp {
+        eyre::bail!(
+            "attributes at timestamp {} precede their L1 origin",
+            timestamp
+        );
+    }
+
+    let transactions = attributes
+        .transactions
+        .as_ref()
+        .ok_or(eyre::eyre!("attributes without transactions"))?;
+
+    if transactions.iter().any(|tx| tx.0.is_empty()) {
+        eyre::bail!("attributes with an empty transaction");
+    }
+
+    let is_deposit = |index: usize| transactions[index].0.first() == Some(&DEPOSIT_TX_TYPE);
+    if transactions.is_empty() || !is_deposit(0) {
+        eyre::bail!("attributes do not start with the L1 info deposit");
+    }
+
+    let deposits = (0..transactions.len())
+        .take_while(|i| is_deposit(*i))
+        .count();
+    if (deposits..transactions.len()).any(is_deposit) {
+        eyre::bail!("attributes with a deposit after the sequenced transactions");
+    }
+
+    if !attributes.no_tx_pool {
+        eyre::bail!("derived attributes must not include transactions from the pool");
+    }
+
+    if attributes.gas_limit.is_zero() {
+        eyre::bail!("attributes without gas limit");
+    }
+
+    let withdrawals = attributes.withdrawals.as_ref().map(Vec::len);
+    let expected = chain.hardforks.is_canyon_active(timestamp).then_some(0);
+    if withdrawals != expected {
+        eyre::bail!(
+            "attributes with withdrawals {:?}, expected {:?}",
+            withdrawals,
+            expected
+        );
+    }
+
+    let ecotone = chain.hardforks.is_ecotone_active(timestamp);
+    if attributes.parent_beacon_block_root.is_some() != ecotone {
+        eyre::bail!("attributes with a parent beacon block root only expected after ecotone");
+    }
+
+    Ok(())
+}
+
+/// Hashes every field of the attributes sent to the engine
+fn hash_attributes(attributes: &PayloadAttributes) -> H256 {
+    let mut data = Vec::new();
+    data.extend_from_slice(&attributes.timestamp.as_u64().to_be_bytes());
+    data.extend_from_slice(attributes.prev_randao.as_bytes());
+    data.extend_from_slice(attributes.suggested_fee_recipient.as_bytes());
+    data.extend_from_slice(&attributes.gas_limit.as_u64().to_be_bytes());
+    data.extend_from_slice(
+        attributes
+            .parent_beacon_block_root
+            .unwrap_or_default()
+            .as_bytes(),
+    );
+
+    for tx in attributes.transactions.iter().flatten() {
+        data.extend_from_slice(&keccak256(&tx.0));
+    }
+
+    H256(keccak256(data))
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::U64;
+
+    use crate::common::{Epoch, RawTransaction};
+
+    use super::*;
+
+    #[test]
+    fn test_dry_run() -> Result<()> {
+        let chain = ChainConfig::optimism();
+        let config = Config {
+            chain: chain.clone(),
+            dry_run: true,
+            dry_run_until: Some(11),
+            ..Default::default()
+        };
+        let mut dry_run = DryRun::from_config(&config).unwrap();
+
+        let parent = BlockInfo {
+            number: 9,
+            timestamp: chain.l2_genesis.timestamp,
+            ..Default::default()
+        };
+        let next = BlockInfo {
+            number: 10,
+            timestamp: parent.timestamp + chain.blocktime,
+            ..Default::default()
+        };
+        let mut attributes = PayloadAttributes {
+            timestamp: U64::from(parent.timestamp + chain.blocktime),
+            prev_randao: H256::zero(),
+            suggested_fee_recipient: Default::default(),
+            transactions: Some(vec![
+                RawTransaction(vec![DEPOSIT_TX_TYPE, 1]),
+                RawTransaction(vec![2, 3]),
+            ]),
+            no_tx_pool: true,
+            gas_limit: U64::from(30_000_000),
+            withdrawals: chain
+                .hardforks
+                .is_canyon_active(next.timestamp)
+                .then(Vec::new),
+            parent_beacon_block_root: chain
+                .hardforks
+                .is_ecotone_active(next.timestamp)
+                .then(H256::zero),
+            epoch: Some(Epoch {
+                number: 1,
+                ..Default::default()
+            }),
+            l1_inclusion_block: None,
+            seq_number: None,
+        };
+
+        let hash = dry_run.check(&attributes, &parent)?;
+        dry_run.record(&attributes, &next, hash, Some(true));
+        assert!(!dry_run.is_done(&next));
+
+        // a deposit after the sequenced transactions is rejected
+        let mut invalid = attributes.clone();
+        invalid
+            .transactions
+            .as_mut()
+            .unwrap()
+            .push(RawTransaction(vec![DEPOSIT_TX_TYPE]));
+        assert!(dry_run.check(&invalid, &parent).is_err());
+
+        // so are attributes not following their parent
+        invalid = attributes.clone();
+        invalid.timestamp = U64::from(parent.timestamp);
+        assert!(dry_run.check(&invalid, &parent).is_err());
+
+        attributes.timestamp += U64::from(chain.blocktime);
+        let last = BlockInfo {
+            number: 11,
+            timestamp: next.timestamp + chain.blocktime,
+            ..Default::default()
+        };
+        let last_hash = dry_run.check(&attributes, &next)?;
+        assert_ne!(hash, last_hash);
+        dry_run.record(&attributes, &last, last_hash, None);
+
+        let summary = &dry_run.summary;
+        assert_eq!(summary.blocks, 2);
+        assert_eq!(summary.transactions, 4);
+        assert_eq!(summary.deposits, 2);
+        assert_eq!(summary.epochs, 1);
+        assert_eq!(summary.first_block, Some(10));
+        assert_eq!(summary.last_block, Some(11));
+        assert_eq!(summary.matched, 1);
+        assert!(dry_run.is_done(&last));
+
+        Ok(())
+    }
+}
@@ -1,4 +1,6 @@
+use std::future::Future;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use ethers::providers::{Http, Middleware, Provider};
 use ethers::types::Transaction;
@@ -9,12 +11,23 @@ use ethers::{
 use eyre::Result;
 
 use crate::{
-    common::{BlockInfo, Epoch},
+    common::{http, BlockInfo, Epoch},
     config::Config,
-    engine::{Engine, EngineApi, ExecutionPayload, ForkchoiceState, PayloadAttributes, Status},
+    derive::DerivationError,
+    engine::{
+        Engine, EngineApiErrorPayload, ExecutionPayload, ForkchoiceState, PayloadAttributes,
+        PayloadId, Status, INVALID_FORKCHOICE_STATE, INVALID_PAYLOAD_ATTRIBUTES, UNSUPPORTED_FORK,
+    },
 };
 
-/// The EngineDriver is responsible for initiating block production & validation via the [EngineApi]
+use super::{
+    attributes_diff::{AttributeMismatches, AttributesDiff},
+    dry_run,
+    interop::DEPOSIT_TX_TYPE,
+    watchdog::EngineActivity,
+};
+
+/// The EngineDriver is responsible for initiating block production & validation via the [Engine]
 pub struct EngineDriver<E: Engine> {
     /// The L2 execution engine
     engine: Arc<E>,
@@ -32,6 +45,18 @@ pub struct EngineDriver<E: Engine> {
     pub finalized_head: BlockInfo,
     /// Batch epoch of the finalized head
     pub finalized_epoch: Epoch,
+    /// Maximum number of blocks applied before a forkchoice update is sent
+    forkchoice_batch_size: u64,
+    /// Maximum delay before a pending forkchoice update is sent
+    forkchoice_batch_interval: Duration,
+    /// Number of blocks applied since the last forkchoice update
+    pending_forkchoice: u64,
+    /// Time of the last forkchoice update
+    last_forkchoice: Instant,
+    /// The latest differences between derived attributes and the unsafe blocks they replaced
+    mismatches: AttributeMismatches,
+    /// The request in flight to the engine, checked by the watchdog
+    activity: EngineActivity,
 }
 
 impl<E: Engine> EngineDriver<E> {
@@ -43,14 +68,65 @@ impl<E: Engine> EngineDriver<E> {
             if should_skip(&block, &attributes)? {
                 self.skip_attributes(attributes, block).await
             } else {
+                let diff = AttributesDiff::new(&attributes, &block);
+                tracing::warn!(
+                    "derived block {} differs from the unsafe block: {}",
+                    diff.block_number,
+                    diff.summary()
+                );
+                self.mismatches.record(diff);
+
                 self.unsafe_head = self.safe_head;
-                self.process_attributes(attributes).await
+                self.process_attributes(attributes).await?;
+
+                // the replaced unsafe blocks stay canonical until the forkchoice is updated
+                self.flush_forkchoice().await
             }
         } else {
             self.process_attributes(attributes).await
         }
     }
 
+    /// Advances the safe head from the given [PayloadAttributes] without the [Engine] in a dry
+    /// run, to the block of the L2 RPC at their height if it has one, or else to a simulated
+    /// block. Returns whether the attributes match the block of the L2 RPC.
+    pub async fn simulate_attributes(
+        &mut self,
+        attributes: &PayloadAttributes,
+        attributes_hash: H256,
+    ) -> Result<Option<bool>> {
+        let new_epoch = attributes
+            .epoch
+            .ok_or(eyre::eyre!("attributes without epoch"))?;
+        let timestamp = attributes.timestamp.as_u64();
+
+        let Some(block) = self.block_at(timestamp).await else {
+            let new_head = BlockInfo {
+                number: self.safe_head.number + 1,
+                hash: dry_run::simulated_block_hash(&self.safe_head, attributes_hash),
+                parent_hash: self.safe_head.hash,
+                timestamp,
+            };
+            self.update_safe_head(new_head, new_epoch, true)?;
+            return Ok(None);
+        };
+
+        let matched = should_skip(&block, attributes)?;
+        if !matched {
+            let diff = AttributesDiff::new(attributes, &block);
+            tracing::warn!(
+                "derived block {} differs from the L2 RPC block: {}",
+                diff.block_number,
+                diff.summary()
+            );
+            self.mismatches.record(diff);
+        }
+
+        // derivation continues from the L2 RPC block, so later batches still match their parent
+        self.update_safe_head(BlockInfo::try_from(block)?, new_epoch, true)?;
+        Ok(Some(matched))
+    }
+
     /// Instructs the engine to create a block and updates the forkchoice, based on a payload received via p2p gossip.
     pub async fn handle_unsafe_payload(&mut self, payload: &ExecutionPayload) -> Result<()> {
         self.push_payload(payload.clone()).await?;
@@ -66,26 +142,114 @@ impl<E: Engine> EngineDriver<E> {
         Ok(())
     }
 
+    /// Restores the unsafe head from the payloads stored before a restart, in order. The
+    /// unsafe head moves to the payloads the execution client still has, and the payloads
+    /// past its latest block are applied again. Restoring stops at a payload replaced by
+    /// another block on the execution client.
+    pub async fn restore_unsafe_head(&mut self, payloads: &[ExecutionPayload]) -> Result<()> {
+        for payload in payloads {
+            let number = payload.block_number.as_u64();
+            let request = async { Ok(self.provider.get_block(number).await?) };
+            let block = http::call("eth_getBlockByNumber", http::L2_TIMEOUT, request).await?;
+
+            match block.and_then(|block| block.hash) {
+                Some(hash) if hash == payload.block_hash => self.unsafe_head = payload.into(),
+                None if payload.parent_hash == self.unsafe_head.hash => {
+                    self.handle_unsafe_payload(payload).await?
+                }
+                _ => break,
+            }
+        }
+
+        if !payloads.is_empty() {
+            tracing::info!("restored unsafe head {}", self.unsafe_head.number);
+        }
+
+        Ok(())
+    }
+
+    /// Sends the forkchoice update coalesced from previously applied blocks, if any
+    pub async fn flush_forkchoice(&mut self) -> Result<()> {
+        if self.pending_forkchoice > 0 {
+            self.update_forkchoice().await?;
+        }
+
+        Ok(())
+    }
+
     /// Updates the [EngineDriver] finalized head & epoch
     pub fn update_finalized(&mut self, head: BlockInfo, epoch: Epoch) {
         self.finalized_head = head;
         self.finalized_epoch = epoch;
     }
 
+    /// Promotes an unsafe block to safe, and to finalized if `finalized`, once its output is
+    /// found proposed on L1 in light-follow mode, and sends the resulting forkchoice to the
+    /// [Engine]. Heads never move back, and blocks after the unsafe head are not promoted.
+    pub async fn promote(&mut self, head: BlockInfo, epoch: Epoch, finalized: bool) -> Result<()> {
+        if head.number > self.unsafe_head.number {
+            return Ok(());
+        }
+
+        let promote_safe = head.number > self.safe_head.number;
+        let promote_finalized = finalized && head.number > self.finalized_head.number;
+
+        if promote_safe {
+            self.safe_head = head;
+            self.safe_epoch = epoch;
+        }
+        if promote_finalized {
+            self.update_finalized(head, epoch);
+        }
+
+        if promote_safe || promote_finalized {
+            self.update_forkchoice().await?;
+        }
+
+        Ok(())
+    }
+
     /// Sets the [EngineDriver] unsafe & safe heads, and safe epoch to the current finalized head & epoch.
     pub fn reorg(&mut self) {
-        self.unsafe_head = self.finalized_head;
-        self.safe_head = self.finalized_head;
-        self.safe_epoch = self.finalized_epoch;
+        self.rewind(self.finalized_head, self.finalized_epoch);
+    }
+
+    /// Rewinds the safe and unsafe heads to the given block, which must not be before the finalized head
+    pub fn rewind(&mut self, head: BlockInfo, epoch: Epoch) {
+        self.unsafe_head = head;
+        self.safe_head = head;
+        self.safe_epoch = epoch;
+    }
+
+    /// Forcefully sets the unsafe, safe and finalized heads to the given block and sends the
+    /// resulting forkchoice to the [Engine]
+    #[cfg(any(feature = "rpc", test))]
+    pub async fn reset(&mut self, head: BlockInfo, epoch: Epoch) -> Result<()> {
+        self.update_finalized(head, epoch);
+        self.rewind(head, epoch);
+        self.update_forkchoice().await
+    }
+
+    /// Returns the recorded attribute mismatches
+    #[cfg(feature = "rpc")]
+    pub fn mismatches(&self) -> AttributeMismatches {
+        self.mismatches.clone()
+    }
+
+    /// Returns the tracker of the request in flight to the engine
+    pub fn activity(&self) -> EngineActivity {
+        self.activity.clone()
     }
 
     /// Sends a `ForkchoiceUpdated` message to check if the [Engine] is ready.
     pub async fn engine_ready(&self) -> bool {
         let forkchoice = self.create_forkchoice_state();
-        self.engine
-            .forkchoice_updated(forkchoice, None)
-            .await
-            .is_ok()
+        self.call(
+            "engine_forkchoiceUpdated",
+            self.engine.forkchoice_updated(forkchoice, None),
+        )
+        .await
+        .is_ok()
     }
 
     /// Initiates validation & production of a new block:
@@ -93,10 +257,32 @@ impl<E: Engine> EngineDriver<E> {
     /// - Executes the [ExecutionPayload] to create a block via `engine_newPayloadV2` (V3 post Ecotone)
     /// - Updates the [EngineDriver] `safe_head`, `safe_epoch`, and `unsafe_head`
     /// - Updates the forkchoice and sends this to the engine via `engine_forkchoiceUpdatedV2` (v3 post Ecotone)
+    ///
+    /// If the engine rejects the block as invalid, the batch is dropped and the deposit-only
+    /// block of the same attributes is built instead, as op-node does. A deposit-only block
+    /// rejected as invalid is a critical error.
     async fn process_attributes(&mut self, attributes: PayloadAttributes) -> Result<()> {
         let new_epoch = *attributes.epoch.as_ref().unwrap();
 
-        let payload = self.build_payload(attributes).await?;
+        let payload = match self.try_build_payload(attributes.clone()).await? {
+            Some(payload) => payload,
+            None => {
+                let deposits = deposit_only(&attributes).ok_or(DerivationError::critical(
+                    "engine rejected a deposit-only block as invalid",
+                ))?;
+
+                tracing::warn!(
+                    "engine rejected the derived block at {} as invalid, dropping the batch",
+                    attributes.timestamp
+                );
+
+                self.try_build_payload(deposits)
+                    .await?
+                    .ok_or(DerivationError::critical(
+                        "engine rejected a deposit-only block as invalid",
+                    ))?
+            }
+        };
 
         let new_head = BlockInfo {
             number: payload.block_number.as_u64(),
@@ -105,9 +291,12 @@ impl<E: Engine> EngineDriver<E> {
             timestamp: payload.timestamp.as_u64(),
         };
 
-        self.push_payload(payload).await?;
+        // building the payload updated the forkchoice up to its parent
+        self.pending_forkchoice = 0;
+        self.last_forkchoice = Instant::now();
+
         self.update_safe_head(new_head, new_epoch, true)?;
-        self.update_forkchoice().await?;
+        self.request_forkchoice().await?;
 
         Ok(())
     }
@@ -121,56 +310,169 @@ impl<E: Engine> EngineDriver<E> {
         let new_epoch = *attributes.epoch.as_ref().unwrap();
         let new_head = BlockInfo::try_from(block)?;
         self.update_safe_head(new_head, new_epoch, false)?;
-        self.update_forkchoice().await?;
+        self.request_forkchoice().await?;
 
         Ok(())
     }
 
-    /// Sends [PayloadAttributes] via a `ForkChoiceUpdated` message to the [Engine] and returns the [ExecutionPayload] sent by the Execution Client.
-    async fn build_payload(&self, attributes: PayloadAttributes) -> Result<ExecutionPayload> {
+    /// Sends [PayloadAttributes] via a `ForkChoiceUpdated` message to the [Engine] and
+    /// executes the [ExecutionPayload] sent by the Execution Client. Returns `None` if the
+    /// engine rejects the attributes or the payload as invalid.
+    async fn try_build_payload(
+        &self,
+        attributes: PayloadAttributes,
+    ) -> Result<Option<ExecutionPayload>> {
         let forkchoice = self.create_forkchoice_state();
 
         let update = self
-            .engine
-            .forkchoice_updated(forkchoice, Some(attributes))
+            .call(
+                "engine_forkchoiceUpdated",
+                self.engine.forkchoice_updated(forkchoice, Some(attributes)),
+            )
             .await?;
 
-        if update.payload_status.status != Status::Valid {
-            eyre::bail!("invalid payload attributes");
+        match update.payload_status.status {
+            Status::Valid => {}
+            Status::Syncing => {
+                return Err(
+                    DerivationError::temporary("engine syncing, payload not started").into(),
+                )
+            }
+            _ => return Ok(None),
         }
 
         let id = update
             .payload_id
             .ok_or(eyre::eyre!("engine did not return payload id"))?;
+        let payload = self.get_payload(id).await?;
 
-        self.engine.get_payload(id).await
+        let status = self
+            .call(
+                "engine_newPayload",
+                self.engine.new_payload(payload.clone()),
+            )
+            .await?;
+        match status.status {
+            Status::Valid | Status::Accepted => Ok(Some(payload)),
+            Status::Syncing => {
+                Err(DerivationError::temporary("engine syncing, payload not applied").into())
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Starts building a payload on top of the unsafe head from the given [PayloadAttributes],
+    /// returning the id to retrieve it with once it is complete
+    pub async fn start_payload(&self, attributes: PayloadAttributes) -> Result<PayloadId> {
+        let forkchoice = self.create_forkchoice_state();
+
+        let update = self
+            .call(
+                "engine_forkchoiceUpdated",
+                self.engine.forkchoice_updated(forkchoice, Some(attributes)),
+            )
+            .await?;
+
+        match update.payload_status.status {
+            Status::Valid => {}
+            Status::Syncing => {
+                return Err(
+                    DerivationError::temporary("engine syncing, payload not started").into(),
+                )
+            }
+            _ => return Err(DerivationError::critical("invalid payload attributes").into()),
+        }
+
+        update
+            .payload_id
+            .ok_or(eyre::eyre!("engine did not return payload id"))
+    }
+
+    /// Retrieves a payload started with [EngineDriver::start_payload], stopping the build
+    pub async fn get_payload(&self, id: PayloadId) -> Result<ExecutionPayload> {
+        self.call("engine_getPayload", self.engine.get_payload(id))
+            .await
     }
 
     /// Sends the given [ExecutionPayload] to the [Engine] via `NewPayload`
     async fn push_payload(&self, payload: ExecutionPayload) -> Result<()> {
-        let status = self.engine.new_payload(payload).await?;
-        if status.status != Status::Valid && status.status != Status::Accepted {
-            eyre::bail!("invalid execution payload");
+        let status = self
+            .call("engine_newPayload", self.engine.new_payload(payload))
+            .await?;
+        match status.status {
+            Status::Valid | Status::Accepted => {}
+            Status::Syncing => {
+                return Err(
+                    DerivationError::temporary("engine syncing, payload not applied").into(),
+                )
+            }
+            _ => return Err(DerivationError::critical("invalid execution payload").into()),
+        }
+
+        Ok(())
+    }
+
+    /// Coalesces forkchoice updates during derivation. The update is only sent once
+    /// `forkchoice_batch_size` blocks are pending or `forkchoice_batch_interval` has
+    /// passed since the last update, and always carries the latest safe & finalized heads.
+    async fn request_forkchoice(&mut self) -> Result<()> {
+        self.pending_forkchoice += 1;
+
+        if self.pending_forkchoice >= self.forkchoice_batch_size
+            || self.last_forkchoice.elapsed() >= self.forkchoice_batch_interval
+        {
+            self.update_forkchoice().await?;
         }
 
         Ok(())
     }
 
     /// Sends a `ForkChoiceUpdated` message to the [Engine] with the current `Forkchoice State` and no payload.
-    async fn update_forkchoice(&self) -> Result<()> {
+    async fn update_forkchoice(&mut self) -> Result<()> {
         let forkchoice = self.create_forkchoice_state();
 
-        let update = self.engine.forkchoice_updated(forkchoice, None).await?;
-        if update.payload_status.status != Status::Valid {
-            eyre::bail!(
-                "could not accept new forkchoice: {:?}",
-                update.payload_status.validation_error
-            );
+        let update = self
+            .call(
+                "engine_forkchoiceUpdated",
+                self.engine.forkchoice_updated(forkchoice, None),
+            )
+            .await?;
+        match update.payload_status.status {
+            Status::Valid => {}
+            Status::Syncing => {
+                return Err(
+                    DerivationError::temporary("engine syncing, forkchoice not updated").into(),
+                )
+            }
+            _ => {
+                return Err(DerivationError::reset(format!(
+                    "could not accept new forkchoice: {:?}",
+                    update.payload_status.validation_error
+                ))
+                .into())
+            }
         }
 
+        self.pending_forkchoice = 0;
+        self.last_forkchoice = Instant::now();
+
         Ok(())
     }
 
+    /// Sends a request to the [Engine], tracked by the watchdog and failed after the
+    /// [ENGINE_TIMEOUT](http::ENGINE_TIMEOUT) or the deadline of the driver tick. Errors are
+    /// classified by [classify_engine_error].
+    async fn call<T>(
+        &self,
+        method: &'static str,
+        request: impl Future<Output = Result<T>>,
+    ) -> Result<T> {
+        let _call = self.activity.start(method);
+        http::call(method, http::ENGINE_TIMEOUT, request)
+            .await
+            .map_err(|err| classify_engine_error(&err).into())
+    }
+
     /// Updates the current `safe_head` & `safe_epoch`.
     ///
     /// Also updates the current `unsafe_head` to the given `new_head` if `reorg_unsafe` is `true`, or if the updated `safe_head` is newer than the current `unsafe_head`
@@ -209,13 +511,46 @@ impl<E: Engine> EngineDriver<E> {
         let time_diff = timestamp as i64 - self.finalized_head.timestamp as i64;
         let blocks = time_diff / self.blocktime as i64;
         let block_num = self.finalized_head.number as i64 + blocks;
-        self.provider
-            .get_block_with_txs(block_num as u64)
+        let request = async { Ok(self.provider.get_block_with_txs(block_num as u64).await?) };
+        http::call("eth_getBlockByNumber", http::L2_TIMEOUT, request)
             .await
             .ok()?
     }
 }
 
+/// Classifies a failed Engine API request by its JSON-RPC error code. Payload attributes
+/// rejected as invalid and methods not supported at the payload timestamp are critical, an
+/// inconsistent forkchoice resets derivation, and transport errors, timeouts and any other
+/// error are temporary.
+fn classify_engine_error(err: &eyre::Report) -> DerivationError {
+    let message = format!("{:#}", err);
+    match err
+        .downcast_ref::<EngineApiErrorPayload>()
+        .map(|err| err.code)
+    {
+        Some(INVALID_PAYLOAD_ATTRIBUTES | UNSUPPORTED_FORK) => DerivationError::critical(message),
+        Some(INVALID_FORKCHOICE_STATE) => DerivationError::reset(message),
+        _ => DerivationError::temporary(message),
+    }
+}
+
+/// Returns the [PayloadAttributes] with only their deposited transactions, or `None` if
+/// they have no other transactions
+fn deposit_only(attributes: &PayloadAttributes) -> Option<PayloadAttributes> {
+    let transactions = attributes.transactions.as_ref()?;
+    let deposits = transactions
+        .iter()
+        .filter(|tx| tx.0.first() == Some(&DEPOSIT_TX_TYPE))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    (deposits.len() < transactions.len()).then(|| PayloadAttributes {
+        transactions: Some(deposits),
+        no_tx_pool: true,
+        ..attributes.clone()
+    })
+}
+
 /// True if transactions in [PayloadAttributes] are not the same as those in a fetched L2 [Block]
 fn should_skip(block: &Block<Transaction>, attributes: &PayloadAttributes) -> Result<bool> {
     tracing::debug!(
@@ -245,21 +580,22 @@ fn should_skip(block: &Block<Transaction>, attributes: &PayloadAttributes) -> Re
         && attributes.timestamp.as_u64() == block.timestamp.as_u64()
         && attributes.prev_randao == block.mix_hash.unwrap()
         && attributes.suggested_fee_recipient == block.author.unwrap()
-        && attributes.gas_limit.as_u64() == block.gas_limit.as_u64();
+        && attributes.gas_limit.as_u64() == block.gas_limit.as_u64()
+        && (attributes.parent_beacon_block_root.is_none()
+            || attributes.parent_beacon_block_root == block.parent_beacon_block_root);
 
     Ok(is_same)
 }
 
-impl EngineDriver<EngineApi> {
-    /// Creates a new [EngineDriver] and builds the [EngineApi] client
+impl<E: Engine> EngineDriver<E> {
+    /// Creates a new [EngineDriver] sending requests to the given [Engine]
     pub fn new(
+        engine: Arc<E>,
         finalized_head: BlockInfo,
         finalized_epoch: Epoch,
         provider: Provider<Http>,
         config: &Arc<Config>,
     ) -> Result<Self> {
-        let engine = Arc::new(EngineApi::new(&config.l2_engine_url, &config.jwt_secret));
-
         Ok(Self {
             engine,
             provider,
@@ -269,6 +605,325 @@ impl EngineDriver<EngineApi> {
             safe_epoch: finalized_epoch,
             finalized_head,
             finalized_epoch,
+            forkchoice_batch_size: config.forkchoice_batch_size,
+            forkchoice_batch_interval: Duration::from_millis(config.forkchoice_batch_interval_ms),
+            pending_forkchoice: 0,
+            last_forkchoice: Instant::now(),
+            mismatches: AttributeMismatches::default(),
+            activity: EngineActivity::default(),
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    };
+
+    use async_trait::async_trait;
+    use ethers::types::U64;
+
+    use crate::{
+        common::RawTransaction,
+        engine::{ForkChoiceUpdate, PayloadStatus},
+    };
+
+    use super::*;
+
+    /// Engine which counts the forkchoice updates it receives
+    #[derive(Default)]
+    struct CountingEngine {
+        forkchoice_updates: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl Engine for CountingEngine {
+        async fn forkchoice_updated(
+            &self,
+            _forkchoice_state: ForkchoiceState,
+            _payload_attributes: Option<PayloadAttributes>,
+        ) -> Result<ForkChoiceUpdate> {
+            self.forkchoice_updates.fetch_add(1, Ordering::SeqCst);
+
+            Ok(ForkChoiceUpdate {
+                payload_status: PayloadStatus {
+                    status: Status::Valid,
+                    latest_valid_hash: None,
+                    validation_error: None,
+                },
+                payload_id: None,
+            })
+        }
+
+        async fn new_payload(&self, _payload: ExecutionPayload) -> Result<PayloadStatus> {
+            eyre::bail!("counting engine does not execute payloads")
+        }
+
+        async fn get_payload(&self, _payload_id: PayloadId) -> Result<ExecutionPayload> {
+            eyre::bail!("counting engine does not build payloads")
+        }
+    }
+
+    #[tokio::test]
+    async fn test_forkchoice_coalescing() -> Result<()> {
+        let engine = Arc::new(CountingEngine::default());
+
+        let mut driver = EngineDriver {
+            engine: engine.clone(),
+            provider: Provider::try_from("http://127.0.0.1:8545")?,
+            blocktime: 2,
+            unsafe_head: BlockInfo::default(),
+            safe_head: BlockInfo::default(),
+            safe_epoch: Epoch::default(),
+            finalized_head: BlockInfo::default(),
+            finalized_epoch: Epoch::default(),
+            forkchoice_batch_size: 4,
+            forkchoice_batch_interval: Duration::from_secs(3600),
+            pending_forkchoice: 0,
+            last_forkchoice: Instant::now(),
+            mismatches: AttributeMismatches::default(),
+            activity: EngineActivity::default(),
+        };
+
+        for _ in 0..6 {
+            driver.request_forkchoice().await?;
+        }
+        assert_eq!(engine.forkchoice_updates.load(Ordering::SeqCst), 1);
+
+        driver.flush_forkchoice().await?;
+        assert_eq!(engine.forkchoice_updates.load(Ordering::SeqCst), 2);
+
+        // nothing is pending anymore
+        driver.flush_forkchoice().await?;
+        assert_eq!(engine.forkchoice_updates.load(Ordering::SeqCst), 2);
+
+        driver.forkchoice_batch_interval = Duration::ZERO;
+        driver.request_forkchoice().await?;
+        assert_eq!(engine.forkchoice_updates.load(Ordering::SeqCst), 3);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_reset() -> Result<()> {
+        let engine = Arc::new(CountingEngine::default());
+        let block = |number| BlockInfo {
+            number,
+            ..Default::default()
+        };
+
+        let mut driver = EngineDriver {
+            engine: engine.clone(),
+            provider: Provider::try_from("http://127.0.0.1:8545")?,
+            blocktime: 2,
+            unsafe_head: block(20),
+            safe_head: block(15),
+            safe_epoch: Epoch::default(),
+            finalized_head: block(10),
+            finalized_epoch: Epoch::default(),
+            forkchoice_batch_size: 1,
+            forkchoice_batch_interval: Duration::from_secs(3600),
+            pending_forkchoice: 0,
+            last_forkchoice: Instant::now(),
+            mismatches: AttributeMismatches::default(),
+            activity: EngineActivity::default(),
+        };
+
+        let epoch = Epoch {
+            number: 3,
+            ..Default::default()
+        };
+        driver.reset(block(5), epoch).await?;
+
+        assert_eq!(driver.unsafe_head, block(5));
+        assert_eq!(driver.safe_head, block(5));
+        assert_eq!(driver.finalized_head, block(5));
+        assert_eq!(driver.safe_epoch, epoch);
+        assert_eq!(driver.finalized_epoch, epoch);
+        assert_eq!(engine.forkchoice_updates.load(Ordering::SeqCst), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_promote() -> Result<()> {
+        let engine = Arc::new(CountingEngine::default());
+        let block = |number| BlockInfo {
+            number,
+            ..Default::default()
+        };
+
+        let mut driver = EngineDriver {
+            engine: engine.clone(),
+            provider: Provider::try_from("http://127.0.0.1:8545")?,
+            blocktime: 2,
+            unsafe_head: block(20),
+            safe_head: block(5),
+            safe_epoch: Epoch::default(),
+            finalized_head: block(5),
+            finalized_epoch: Epoch::default(),
+            forkchoice_batch_size: 1,
+            forkchoice_batch_interval: Duration::from_secs(3600),
+            pending_forkchoice: 0,
+            last_forkchoice: Instant::now(),
+            mismatches: AttributeMismatches::default(),
+            activity: EngineActivity::default(),
+        };
+
+        let epoch = Epoch {
+            number: 3,
+            ..Default::default()
+        };
+        driver.promote(block(15), epoch, false).await?;
+        assert_eq!(driver.safe_head, block(15));
+        assert_eq!(driver.safe_epoch, epoch);
+        assert_eq!(driver.finalized_head, block(5));
+
+        driver.promote(block(10), epoch, true).await?;
+        assert_eq!(driver.safe_head, block(15));
+        assert_eq!(driver.finalized_head, block(10));
+        assert_eq!(engine.forkchoice_updates.load(Ordering::SeqCst), 2);
+
+        // blocks after the unsafe head and older heads are not promoted
+        driver.promote(block(21), epoch, true).await?;
+        driver.promote(block(8), epoch, true).await?;
+        assert_eq!(driver.safe_head, block(15));
+        assert_eq!(driver.finalized_head, block(10));
+        assert_eq!(engine.forkchoice_updates.load(Ordering::SeqCst), 2);
+
+        Ok(())
+    }
+
+    /// Engine which builds payloads from the attributes it receives and rejects payloads
+    /// with transactions other than deposits as invalid
+    #[derive(Default)]
+    struct RejectingEngine {
+        /// Whether deposit-only payloads are rejected too
+        reject_deposits: bool,
+        /// The attributes of the payload being built
+        attributes: Mutex<Option<PayloadAttributes>>,
+        /// The transactions of the payloads applied
+        applied: Mutex<Vec<Vec<RawTransaction>>>,
+    }
+
+    #[async_trait]
+    impl Engine for RejectingEngine {
+        async fn forkchoice_updated(
+            &self,
+            _forkchoice_state: ForkchoiceState,
+            payload_attributes: Option<PayloadAttributes>,
+        ) -> Result<ForkChoiceUpdate> {
+            let payload_id = payload_attributes.as_ref().map(|_| U64::from(1));
+            *self.attributes.lock().unwrap() = payload_attributes;
+
+            Ok(ForkChoiceUpdate {
+                payload_status: PayloadStatus {
+                    status: Status::Valid,
+                    latest_valid_hash: None,
+                    validation_error: None,
+                },
+                payload_id,
+            })
+        }
+
+        async fn new_payload(&self, payload: ExecutionPayload) -> Result<PayloadStatus> {
+            let deposit_only = payload
+                .transactions
+                .iter()
+                .all(|tx| tx.0.first() == Some(&DEPOSIT_TX_TYPE));
+
+            let status = if deposit_only && !self.reject_deposits {
+                self.applied.lock().unwrap().push(payload.transactions);
+                Status::Valid
+            } else {
+                Status::Invalid
+            };
+
+            Ok(PayloadStatus {
+                status,
+                latest_valid_hash: None,
+                validation_error: None,
+            })
+        }
+
+        async fn get_payload(&self, _payload_id: PayloadId) -> Result<ExecutionPayload> {
+            let attributes = self.attributes.lock().unwrap().clone().unwrap();
+
+            Ok(ExecutionPayload {
+                block_number: U64::from(1),
+                timestamp: attributes.timestamp,
+                transactions: attributes.transactions.unwrap(),
+                ..Default::default()
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_invalid_attributes_fall_back_to_deposits() -> Result<()> {
+        let deposit = RawTransaction(vec![DEPOSIT_TX_TYPE, 1]);
+        let attributes = PayloadAttributes {
+            timestamp: U64::from(2),
+            transactions: Some(vec![deposit.clone(), RawTransaction(vec![2, 1])]),
+            epoch: Some(Epoch::default()),
+            ..Default::default()
+        };
+
+        for reject_deposits in [false, true] {
+            let engine = Arc::new(RejectingEngine {
+                reject_deposits,
+                ..Default::default()
+            });
+
+            let mut driver = EngineDriver {
+                engine: engine.clone(),
+                provider: Provider::try_from("http://127.0.0.1:8545")?,
+                blocktime: 2,
+                unsafe_head: BlockInfo::default(),
+                safe_head: BlockInfo::default(),
+                safe_epoch: Epoch::default(),
+                finalized_head: BlockInfo::default(),
+                finalized_epoch: Epoch::default(),
+                forkchoice_batch_size: 1,
+                forkchoice_batch_interval: Duration::from_secs(3600),
+                pending_forkchoice: 0,
+                last_forkchoice: Instant::now(),
+                mismatches: AttributeMismatches::default(),
+                activity: EngineActivity::default(),
+            };
+
+            let result = driver.process_attributes(attributes.clone()).await;
+            if reject_deposits {
+                let err = result.unwrap_err();
+                assert_eq!(DerivationError::classify(&err).code(), "critical");
+                assert_eq!(driver.safe_head.number, 0);
+            } else {
+                result?;
+                assert_eq!(driver.safe_head.number, 1);
+                assert_eq!(*engine.applied.lock().unwrap(), vec![vec![deposit.clone()]]);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_classify_engine_error() {
+        let error = |code| {
+            eyre::Report::new(EngineApiErrorPayload {
+                code,
+                message: "failed".to_string(),
+                data: None,
+            })
+            .wrap_err("Engine API POST error")
+        };
+
+        let code = |err: eyre::Report| classify_engine_error(&err).code();
+        assert_eq!(code(error(INVALID_PAYLOAD_ATTRIBUTES)), "critical");
+        assert_eq!(code(error(UNSUPPORTED_FORK)), "critical");
+        assert_eq!(code(error(INVALID_FORKCHOICE_STATE)), "reset");
+        assert_eq!(code(error(-32000)), "temporary");
+        assert_eq!(code(eyre::eyre!("connection refused")), "temporary");
+    }
+}
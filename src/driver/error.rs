@@ -0,0 +1,65 @@
+use std::fmt;
+
+use crate::derive::DerivationError;
+
+/// Exit code of the node halted by a critical derivation error
+pub const CRITICAL_ERROR_EXIT_CODE: i32 = 2;
+
+/// Exit code of the node which failed to start or whose dry run failed
+pub const FAILURE_EXIT_CODE: i32 = 1;
+
+/// An error stopping the [Driver](super::Driver). The driver never exits the process
+/// itself, so embedders decide how to stop, and the binary maps the error to its exit code.
+#[derive(Debug)]
+pub enum DriverError {
+    /// The driver could not start its components
+    Start(eyre::Report),
+    /// Derivation halted on a critical error, or could not be reset after an error
+    Critical(DerivationError),
+    /// A dry run derived attributes that failed validation
+    DryRun(DerivationError),
+}
+
+impl DriverError {
+    /// Returns the exit code of the node stopped by this error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Start(_) | Self::DryRun(_) => FAILURE_EXIT_CODE,
+            Self::Critical(_) => CRITICAL_ERROR_EXIT_CODE,
+        }
+    }
+}
+
+impl fmt::Display for DriverError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Start(err) => write!(f, "driver failed to start: {:#}", err),
+            Self::Critical(err) => write!(f, "derivation halted: {}", err),
+            Self::DryRun(err) => write!(f, "dry run failed: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for DriverError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_code() {
+        let err: eyre::Report =
+            DriverError::Critical(DerivationError::critical("bad block")).into();
+        let err = err.wrap_err("rollup stopped");
+        let driver_err = err.downcast_ref::<DriverError>().unwrap();
+        assert_eq!(driver_err.exit_code(), CRITICAL_ERROR_EXIT_CODE);
+        assert_eq!(driver_err.to_string(), "derivation halted: bad block");
+
+        let err = DriverError::DryRun(DerivationError::critical("invalid attributes"));
+        assert_eq!(err.exit_code(), FAILURE_EXIT_CODE);
+        assert_eq!(
+            DriverError::Start(eyre::eyre!("no engine")).exit_code(),
+            FAILURE_EXIT_CODE
+        );
+    }
+}
@@ -0,0 +1,152 @@
+use tokio::sync::broadcast;
+
+use crate::common::{BlockInfo, Epoch};
+
+/// Number of events buffered for each subscriber. Subscribers lagging further behind miss
+/// the oldest events and receive [broadcast::error::RecvError::Lagged] instead.
+pub const EVENT_CAPACITY: usize = 1024;
+
+/// A change of the L2 chain observed by the [Driver](super::Driver)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NodeEvent {
+    /// The unsafe head changed, moving forward or rewound by a reorg
+    UnsafeHeadUpdated(BlockInfo),
+    /// A derived block became the safe head
+    SafeHeadUpdated {
+        /// The new safe head
+        head: BlockInfo,
+        /// The L1 origin of the safe head
+        epoch: Epoch,
+    },
+    /// The finalized head moved forward
+    Finalized {
+        /// The new finalized head
+        head: BlockInfo,
+        /// The L1 origin of the finalized head
+        epoch: Epoch,
+    },
+    /// An L1 reorg rewound the safe and unsafe heads
+    Reorg {
+        /// The L1 head which is no longer canonical
+        l1_old_head: BlockInfo,
+        /// The new L1 head
+        l1_new_head: BlockInfo,
+        /// The number of L1 blocks between the old head and the common ancestor
+        depth: u64,
+        /// The L2 block the heads were rewound to
+        rewound_to: BlockInfo,
+    },
+    /// An iteration of the derivation loop failed, with the code and description of the
+    /// error
+    DerivationError(String),
+}
+
+/// Publishes [NodeEvent]s to any number of subscribers, remembering the heads last
+/// published so a head is only reported when it changes.
+#[derive(Debug)]
+pub struct EventPublisher {
+    /// The channel of the subscribers
+    sender: broadcast::Sender<NodeEvent>,
+    /// The unsafe head last published
+    unsafe_head: BlockInfo,
+    /// The finalized head last published
+    finalized_head: BlockInfo,
+}
+
+impl EventPublisher {
+    /// Creates a new [EventPublisher] publishing on the given channel, starting from the
+    /// given heads which are not published
+    pub fn new(
+        sender: broadcast::Sender<NodeEvent>,
+        unsafe_head: BlockInfo,
+        finalized_head: BlockInfo,
+    ) -> Self {
+        Self {
+            sender,
+            unsafe_head,
+            finalized_head,
+        }
+    }
+
+    /// Returns a new receiver of the events published from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<NodeEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Replaces the channel of the subscribers
+    pub fn set_sender(&mut self, sender: broadcast::Sender<NodeEvent>) {
+        self.sender = sender;
+    }
+
+    /// Publishes an event, which is dropped if there are no subscribers
+    pub fn publish(&self, event: NodeEvent) {
+        _ = self.sender.send(event);
+    }
+
+    /// Publishes the unsafe and finalized heads if they changed since last published
+    pub fn update_heads(
+        &mut self,
+        unsafe_head: BlockInfo,
+        finalized_head: BlockInfo,
+        finalized_epoch: Epoch,
+    ) {
+        if unsafe_head != self.unsafe_head {
+            self.unsafe_head = unsafe_head;
+            self.publish(NodeEvent::UnsafeHeadUpdated(unsafe_head));
+        }
+
+        if finalized_head != self.finalized_head {
+            self.finalized_head = finalized_head;
+            self.publish(NodeEvent::Finalized {
+                head: finalized_head,
+                epoch: finalized_epoch,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(number: u64) -> BlockInfo {
+        BlockInfo {
+            number,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_publish_changed_heads() {
+        let (sender, _) = broadcast::channel(EVENT_CAPACITY);
+        let mut publisher = EventPublisher::new(sender, block(10), block(5));
+
+        // events are dropped without subscribers
+        publisher.update_heads(block(11), block(5), Epoch::default());
+
+        let mut events = publisher.subscribe();
+        publisher.update_heads(block(11), block(5), Epoch::default());
+        assert!(events.try_recv().is_err());
+
+        publisher.update_heads(block(12), block(6), Epoch::default());
+        assert_eq!(
+            events.try_recv().unwrap(),
+            NodeEvent::UnsafeHeadUpdated(block(12))
+        );
+        assert_eq!(
+            events.try_recv().unwrap(),
+            NodeEvent::Finalized {
+                head: block(6),
+                epoch: Epoch::default()
+            }
+        );
+
+        // a rewound head is published too
+        publisher.update_heads(block(8), block(6), Epoch::default());
+        assert_eq!(
+            events.try_recv().unwrap(),
+            NodeEvent::UnsafeHeadUpdated(block(8))
+        );
+        assert!(events.try_recv().is_err());
+    }
+}
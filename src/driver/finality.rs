@@ -0,0 +1,173 @@
+use std::collections::VecDeque;
+
+use crate::common::{BlockInfo, Epoch};
+
+/// Tracks the L1 blocks each derived L2 block was built from, and finalizes L2 blocks
+/// once all of them are finalized on L1.
+///
+/// A derived block depends on the L1 origin of its epoch, for the L1 info and deposits,
+/// and on the L1 blocks carrying the frames of its channel, the last of which is the
+/// inclusion block of the channel. Since L1 finality only moves forward, the block is
+/// final once the later of the two is. A block is only finalized together with all of
+/// its ancestors, and the finalized head is always the start of an epoch, so derivation
+/// can restart from it.
+#[derive(Debug, Default)]
+pub struct FinalityTracker {
+    /// The unfinalized derived blocks, oldest first
+    blocks: VecDeque<DerivedBlock>,
+    /// The latest finalized L1 block
+    finalized_l1: BlockInfo,
+}
+
+/// A derived L2 block and the L1 data it was built from
+#[derive(Debug, Clone, Copy)]
+struct DerivedBlock {
+    /// The L2 block
+    head: BlockInfo,
+    /// The epoch of the block
+    epoch: Epoch,
+    /// The sequence number of the block in its epoch
+    seq: u64,
+    /// The L1 block that completed the channel of the block
+    l1_inclusion_block: u64,
+}
+
+impl DerivedBlock {
+    /// Returns the number of the last L1 block that contributed data to the block
+    fn last_contributing_block(&self) -> u64 {
+        self.l1_inclusion_block.max(self.epoch.number)
+    }
+}
+
+impl FinalityTracker {
+    /// Records a newly derived safe block
+    pub fn push(&mut self, head: BlockInfo, epoch: Epoch, seq: u64, l1_inclusion_block: u64) {
+        self.blocks.push_back(DerivedBlock {
+            head,
+            epoch,
+            seq,
+            l1_inclusion_block,
+        });
+    }
+
+    /// Sets the latest finalized L1 block
+    pub fn set_finalized_l1(&mut self, block: BlockInfo) {
+        self.finalized_l1 = block;
+    }
+
+    /// Returns the latest finalized L1 block
+    #[cfg(feature = "rpc")]
+    pub fn finalized_l1(&self) -> BlockInfo {
+        self.finalized_l1
+    }
+
+    /// Returns the latest epoch start whose data, and that of all earlier blocks, is
+    /// finalized on L1, and stops tracking it and its ancestors
+    pub fn finalize(&mut self) -> Option<(BlockInfo, Epoch)> {
+        let finalized = self
+            .blocks
+            .iter()
+            .take_while(|block| block.last_contributing_block() <= self.finalized_l1.number)
+            .enumerate()
+            .filter(|(_, block)| block.seq == 0)
+            .last()
+            .map(|(index, block)| (index, *block))?;
+
+        let (index, block) = finalized;
+        self.blocks.drain(..=index);
+
+        Some((block.head, block.epoch))
+    }
+
+    /// Returns the latest tracked epoch start that is still built from canonical L1 data
+    /// after an L1 reorg back to the given common ancestor, and stops tracking the blocks
+    /// after it
+    pub fn rewind(&mut self, ancestor: u64) -> Option<(BlockInfo, Epoch)> {
+        let index = self
+            .blocks
+            .iter()
+            .rposition(|block| block.seq == 0 && block.last_contributing_block() <= ancestor)?;
+
+        self.blocks.truncate(index + 1);
+        let block = self.blocks[index];
+
+        Some((block.head, block.epoch))
+    }
+
+    /// Stops tracking all unfinalized blocks
+    pub fn clear(&mut self) {
+        self.blocks.clear();
+    }
+
+    /// Returns true if no unfinalized blocks are tracked
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(number: u64) -> BlockInfo {
+        BlockInfo {
+            number,
+            ..Default::default()
+        }
+    }
+
+    fn epoch(number: u64) -> Epoch {
+        Epoch {
+            number,
+            ..Default::default()
+        }
+    }
+
+    fn tracker() -> FinalityTracker {
+        let mut tracker = FinalityTracker::default();
+        tracker.push(block(10), epoch(100), 0, 101);
+        tracker.push(block(11), epoch(100), 1, 101);
+        tracker.push(block(12), epoch(101), 0, 103);
+        tracker.push(block(13), epoch(101), 1, 103);
+        // a deposit-only block whose epoch is newer than its channel
+        tracker.push(block(14), epoch(106), 0, 105);
+        tracker.push(block(15), epoch(106), 1, 107);
+        tracker
+    }
+
+    #[test]
+    fn test_finalize() {
+        let mut tracker = tracker();
+        assert!(tracker.finalize().is_none());
+
+        tracker.set_finalized_l1(block(103));
+        let (head, epoch) = tracker.finalize().unwrap();
+        assert_eq!((head.number, epoch.number), (12, 101));
+        assert!(tracker.finalize().is_none());
+
+        // the channel of block 14 is final, but not its epoch
+        tracker.set_finalized_l1(block(105));
+        assert!(tracker.finalize().is_none());
+
+        tracker.set_finalized_l1(block(106));
+        assert_eq!(tracker.finalize().unwrap().0.number, 14);
+
+        tracker.set_finalized_l1(block(110));
+        assert!(tracker.finalize().is_none());
+        assert!(!tracker.is_empty());
+    }
+
+    #[test]
+    fn test_rewind() {
+        let (head, epoch) = tracker().rewind(104).unwrap();
+        assert_eq!((head.number, epoch.number), (12, 101));
+
+        let mut tracker = tracker();
+        assert_eq!(tracker.rewind(102).unwrap().0.number, 10);
+        tracker.set_finalized_l1(block(110));
+        assert_eq!(tracker.finalize().unwrap().0.number, 10);
+        assert!(tracker.is_empty());
+
+        assert!(self::tracker().rewind(100).is_none());
+    }
+}
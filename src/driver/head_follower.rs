@@ -0,0 +1,211 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use ethers::providers::{Http, Middleware, Provider};
+use eyre::Result;
+use tokio::{sync::mpsc, time::sleep};
+
+use crate::{
+    common::{http, BlockInfo},
+    engine::ExecutionPayload,
+};
+
+/// Maximum number of blocks fetched from the trusted RPC per poll
+const MAX_BLOCKS_PER_POLL: u64 = 32;
+
+/// Why following the trusted RPC is paused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PauseReason {
+    /// The safe head is too far behind the unsafe head
+    Lagging,
+    /// A derived block differs from the block followed at its height
+    Diverged,
+}
+
+/// Follows the unsafe head of an L2 RPC trusted to serve the blocks of the sequencer, so
+/// they are available as soon as they are built, while derivation runs behind to
+/// promote them to safe.
+///
+/// Following pauses while the safe head is more than `max_lag` blocks behind the unsafe
+/// head, and resumes once derivation has caught up. It stops for good if a derived block
+/// differs from the block followed at its height, as the RPC can no longer be trusted.
+pub struct HeadFollower {
+    /// Channel receiving the blocks fetched from the trusted RPC
+    payload_recv: mpsc::Receiver<ExecutionPayload>,
+    /// The unsafe head number shared with the polling task, which fetches the blocks after it
+    unsafe_head: Arc<AtomicU64>,
+    /// Whether the polling task is paused
+    paused_flag: Arc<AtomicBool>,
+    /// Why following is paused, if it is
+    paused: Option<PauseReason>,
+    /// Maximum distance of the safe head behind the unsafe head while following
+    max_lag: Option<u64>,
+}
+
+impl HeadFollower {
+    /// Starts polling the trusted RPC every block time for the blocks after the unsafe head
+    pub fn start(
+        url: &str,
+        blocktime: u64,
+        max_lag: Option<u64>,
+        unsafe_head: BlockInfo,
+    ) -> Result<Self> {
+        let provider = http::provider(url, http::L2_TIMEOUT)?;
+        let (sender, payload_recv) = mpsc::channel(MAX_BLOCKS_PER_POLL as usize);
+        let head = Arc::new(AtomicU64::new(unsafe_head.number));
+        let paused_flag = Arc::new(AtomicBool::new(false));
+
+        let poll_head = head.clone();
+        let poll_paused = paused_flag.clone();
+        tokio::spawn(async move {
+            loop {
+                sleep(Duration::from_secs(blocktime)).await;
+
+                if poll_paused.load(Ordering::Relaxed) {
+                    continue;
+                }
+
+                let head = poll_head.load(Ordering::Relaxed);
+                if let Err(err) = poll(&provider, &sender, head).await {
+                    tracing::warn!("failed to follow the trusted L2 RPC: {}", err);
+                }
+
+                if sender.is_closed() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self {
+            payload_recv,
+            unsafe_head: head,
+            paused_flag,
+            paused: None,
+            max_lag,
+        })
+    }
+
+    /// Returns the blocks fetched since the previous call
+    pub fn receive(&mut self) -> Vec<ExecutionPayload> {
+        let mut payloads = Vec::new();
+        while let Ok(payload) = self.payload_recv.try_recv() {
+            payloads.push(payload);
+        }
+
+        payloads
+    }
+
+    /// Updates the heads, pausing or resuming following depending on the lag of the safe head
+    pub fn update_heads(&mut self, unsafe_head: BlockInfo, safe_head: BlockInfo) {
+        self.unsafe_head
+            .store(unsafe_head.number, Ordering::Relaxed);
+
+        if self.paused == Some(PauseReason::Diverged) {
+            return;
+        }
+
+        let lag = unsafe_head.number.saturating_sub(safe_head.number);
+        let lagging = self.max_lag.is_some_and(|max_lag| lag > max_lag);
+
+        match (self.paused, lagging) {
+            (None, true) => {
+                tracing::warn!(
+                    "derivation is {} blocks behind the unsafe head, pausing unsafe head following",
+                    lag
+                );
+                self.pause(PauseReason::Lagging);
+            }
+            (Some(PauseReason::Lagging), false) => {
+                tracing::info!("derivation caught up, resuming unsafe head following");
+                self.paused = None;
+                self.paused_flag.store(false, Ordering::Relaxed);
+            }
+            _ => (),
+        }
+    }
+
+    /// Stops following after a derived block replaced the followed block at its height
+    pub fn diverged(&mut self, safe_head: BlockInfo) {
+        if self.paused != Some(PauseReason::Diverged) {
+            tracing::error!(
+                "derived block {} {:?} differs from the trusted L2 RPC, stopping unsafe head following",
+                safe_head.number,
+                safe_head.hash
+            );
+            self.pause(PauseReason::Diverged);
+        }
+    }
+
+    /// Returns why following is paused, if it is
+    pub fn paused(&self) -> Option<PauseReason> {
+        self.paused
+    }
+
+    /// Pauses following and the polling task
+    fn pause(&mut self, reason: PauseReason) {
+        self.paused = Some(reason);
+        self.paused_flag.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Sends the blocks of the trusted RPC after the given unsafe head
+async fn poll(
+    provider: &Provider<Http>,
+    sender: &mpsc::Sender<ExecutionPayload>,
+    unsafe_head: u64,
+) -> Result<()> {
+    let latest = provider.get_block_number().await?.as_u64();
+    let last = latest.min(unsafe_head + MAX_BLOCKS_PER_POLL);
+
+    for number in unsafe_head + 1..=last {
+        let block = provider
+            .get_block_with_txs(number)
+            .await?
+            .ok_or(eyre::eyre!("block {} not found", number))?;
+
+        sender.send(block.try_into()?).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(number: u64) -> BlockInfo {
+        BlockInfo {
+            number,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pause_following() -> Result<()> {
+        let mut follower = HeadFollower::start("http://127.0.0.1:1", 60, Some(10), block(0))?;
+
+        follower.update_heads(block(20), block(10));
+        assert_eq!(follower.paused(), None);
+
+        follower.update_heads(block(21), block(10));
+        assert_eq!(follower.paused(), Some(PauseReason::Lagging));
+        assert!(follower.paused_flag.load(Ordering::Relaxed));
+
+        follower.update_heads(block(21), block(15));
+        assert_eq!(follower.paused(), None);
+        assert!(!follower.paused_flag.load(Ordering::Relaxed));
+
+        // divergence stops following for good
+        follower.diverged(block(15));
+        follower.update_heads(block(16), block(15));
+        assert_eq!(follower.paused(), Some(PauseReason::Diverged));
+        assert_eq!(follower.unsafe_head.load(Ordering::Relaxed), 16);
+
+        Ok(())
+    }
+}
@@ -1,5 +1,5 @@
 use crate::config::Config;
-use crate::driver::types::HeadInfo;
+use crate::driver::HeadInfo;
 use ethers::middleware::Middleware;
 use ethers::providers::{JsonRpcClient, Provider, ProviderError};
 use ethers::types::{Block, BlockId, BlockNumber, Transaction};
@@ -78,7 +78,7 @@ impl HeadInfoQuery {
 mod test_utils {
     use super::*;
     use crate::common::{BlockInfo, Epoch};
-    use crate::config::{ChainConfig, Config};
+    use crate::config::{ChainConfig, Config, WatchdogAction};
     use ethers::types::H256;
     use std::str::FromStr;
 
@@ -157,6 +157,54 @@ mod test_utils {
             rpc_port: Default::default(),
             rpc_addr: Default::default(),
             devnet: false,
+            supervisor_rpc_url: None,
+            p2p_port: 9876,
+            l2_engine_standby_urls: Vec::new(),
+            forkchoice_batch_size: 16,
+            forkchoice_batch_interval_ms: 200,
+            jwt_secret_file: None,
+            l1_rpc_budget: None,
+            l1_rpc_method_weights: Default::default(),
+            data_dir: None,
+            l1_epoch_retention: None,
+            l2_block_retention: None,
+            p2p_sync: false,
+            p2p_max_inbound_peers: 30,
+            p2p_max_outbound_peers: 20,
+            p2p_static_peers: Vec::new(),
+            p2p_bootnodes: Vec::new(),
+            sequencer: false,
+            p2p_sequencer_key: None,
+            p2p_sequencer_keystore: None,
+            p2p_sequencer_keystore_password_file: None,
+            p2p_sequencer_signer_url: None,
+            p2p_sequencer_signer_backup_url: None,
+            p2p_sequencer_address: None,
+            profile_derivation: false,
+            unsafe_follow_url: None,
+            unsafe_follow_max_lag: None,
+            readiness_max_head_lag: 60,
+            readiness_max_stall: 120,
+            rpc_api: vec!["optimism".to_string(), "opp2p".to_string()],
+            rpc_auth_token: None,
+            rpc_jwt_secret: None,
+            rpc_cors_origins: Vec::new(),
+            rpc_rate_limit: None,
+            l1_block_buffer: 64,
+            light_follow: false,
+            derivation_lookahead: 0,
+            sequencer_gas_limit: None,
+            rpc_precompute_outputs: false,
+            watchdog_stall_timeout: 180,
+            watchdog_action: WatchdogAction::Log,
+            rpc_sequencer_url: None,
+            rpc_sequencer_rate_limit: None,
+            driver_tick_timeout: 30,
+            dry_run: false,
+            dry_run_until: None,
+            unsafe_payload_retention: 256,
+            derivation_step_frames: 64,
+            halt_on_unsupported_fork: false,
         }
     }
 
@@ -0,0 +1,470 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use ethers::{
+    abi::{decode, ParamType, Token},
+    types::{Address, Transaction, H256, U256},
+    utils::{
+        id, keccak256,
+        rlp::{Decodable, Rlp},
+    },
+};
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::time::sleep;
+
+use crate::{
+    common::{http, RawTransaction},
+    config::Config,
+    engine::{ExecutionPayload, PayloadAttributes},
+};
+
+/// The address of the `CrossL2Inbox` predeploy
+const CROSS_L2_INBOX: &str = "0x4200000000000000000000000000000000000022";
+
+/// The transaction type of deposit transactions, which never carry executing messages
+pub(super) const DEPOSIT_TX_TYPE: u8 = 0x7E;
+
+/// Number of times a failed validation of derived attributes is retried
+const VALIDATION_RETRIES: u32 = 10;
+
+/// Supervisor error code of a message whose initiating message does not match
+const CONFLICTING_DATA: i64 = -320600;
+
+/// Supervisor error code of a message initiated on a chain outside the dependency set
+const UNKNOWN_CHAIN: i64 = -320501;
+
+/// Identifies the initiating message referenced by an executing message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Identifier {
+    /// The account that emitted the initiating message
+    pub origin: Address,
+    /// The block number of the initiating message
+    pub block_number: u64,
+    /// The log index of the initiating message
+    pub log_index: u64,
+    /// The timestamp of the initiating message
+    pub timestamp: u64,
+    /// The chain id of the chain that emitted the initiating message
+    pub chain_id: u64,
+}
+
+/// A cross-chain message executed through the `CrossL2Inbox`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecutingMessage {
+    /// The initiating message identifier
+    pub identifier: Identifier,
+    /// The hash of the initiating message payload
+    pub payload_hash: H256,
+}
+
+impl ExecutingMessage {
+    /// Extracts the executing message from a transaction calling the `CrossL2Inbox`
+    /// `executeMessage` or `validateMessage` methods. Returns `None` for any other transaction.
+    pub fn from_transaction(tx: &Transaction) -> Option<Self> {
+        let inbox: Address = CROSS_L2_INBOX.parse().ok()?;
+        if tx.to != Some(inbox) || tx.input.len() < 4 {
+            return None;
+        }
+
+        let identifier = ParamType::Tuple(vec![
+            ParamType::Address,
+            ParamType::Uint(256),
+            ParamType::Uint(256),
+            ParamType::Uint(256),
+            ParamType::Uint(256),
+        ]);
+
+        let (selector, args) = tx.input.split_at(4);
+
+        if selector == id("executeMessage((address,uint256,uint256,uint256,uint256),address,bytes)")
+        {
+            let tokens = decode(&[identifier, ParamType::Address, ParamType::Bytes], args).ok()?;
+            let message = tokens[2].clone().into_bytes()?;
+
+            Some(Self {
+                identifier: parse_identifier(&tokens[0])?,
+                payload_hash: H256::from(keccak256(message)),
+            })
+        } else if selector
+            == id("validateMessage((address,uint256,uint256,uint256,uint256),bytes32)")
+        {
+            let tokens = decode(&[identifier, ParamType::FixedBytes(32)], args).ok()?;
+            let payload_hash = tokens[1].clone().into_fixed_bytes()?;
+
+            Some(Self {
+                identifier: parse_identifier(&tokens[0])?,
+                payload_hash: H256::from_slice(&payload_hash),
+            })
+        } else {
+            None
+        }
+    }
+}
+
+fn parse_identifier(token: &Token) -> Option<Identifier> {
+    let fields = token.clone().into_tuple()?;
+    let uint = |i: usize| -> Option<u64> {
+        let value: U256 = fields.get(i)?.clone().into_uint()?;
+        (value <= U256::from(u64::MAX)).then(|| value.as_u64())
+    };
+
+    Some(Identifier {
+        origin: fields.first()?.clone().into_address()?,
+        block_number: uint(1)?,
+        log_index: uint(2)?,
+        timestamp: uint(3)?,
+        chain_id: uint(4)?,
+    })
+}
+
+/// Extracts all executing messages from a list of raw transactions
+pub fn executing_messages(transactions: &[RawTransaction]) -> Vec<ExecutingMessage> {
+    transactions
+        .iter()
+        .filter(|tx| tx.0.first() != Some(&DEPOSIT_TX_TYPE))
+        .filter_map(|tx| Transaction::decode(&Rlp::new(&tx.0)).ok())
+        .filter_map(|tx| ExecutingMessage::from_transaction(&tx))
+        .collect()
+}
+
+/// The outcome of validating the executing messages of a block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MessageValidity {
+    /// All messages are valid
+    Valid,
+    /// At least one message is invalid, with the reason
+    Invalid(String),
+}
+
+/// Checks the validity of executing messages
+#[async_trait]
+pub trait MessageValidator: Send + Sync {
+    /// Checks the messages executed in a block with the given timestamp.
+    /// Returns an error if the validity could not be determined.
+    async fn check_messages(
+        &self,
+        messages: &[ExecutingMessage],
+        timestamp: u64,
+    ) -> Result<MessageValidity>;
+}
+
+/// Validates executing messages locally against the dependency set.
+/// This only checks that the initiating chain is part of the dependency set and that
+/// the initiating message is not from the future. It does not verify the message exists.
+#[derive(Debug, Clone)]
+pub struct DependencySetChecker {
+    /// The chain ids messages may be executed from, including the local chain
+    dependency_set: Vec<u64>,
+    /// The timestamp at which interop activates
+    interop_time: u64,
+}
+
+impl DependencySetChecker {
+    /// Creates a new [DependencySetChecker]
+    pub fn new(dependency_set: Vec<u64>, interop_time: u64) -> Self {
+        Self {
+            dependency_set,
+            interop_time,
+        }
+    }
+}
+
+#[async_trait]
+impl MessageValidator for DependencySetChecker {
+    async fn check_messages(
+        &self,
+        messages: &[ExecutingMessage],
+        timestamp: u64,
+    ) -> Result<MessageValidity> {
+        for message in messages {
+            let id = message.identifier;
+
+            let reason = if !self.dependency_set.contains(&id.chain_id) {
+                format!("chain {} is not in the dependency set", id.chain_id)
+            } else if id.timestamp > timestamp {
+                format!("initiating message from the future: {}", id.timestamp)
+            } else if id.timestamp < self.interop_time {
+                "initiating message before interop activation".to_string()
+            } else {
+                continue;
+            };
+
+            return Ok(MessageValidity::Invalid(reason));
+        }
+
+        Ok(MessageValidity::Valid)
+    }
+}
+
+/// Validates executing messages by consulting an `op-supervisor` compatible RPC
+#[derive(Debug, Clone)]
+pub struct SupervisorClient {
+    /// The supervisor RPC url
+    url: String,
+    /// Http client
+    client: reqwest::Client,
+}
+
+impl SupervisorClient {
+    /// Creates a new [SupervisorClient]
+    pub fn new(url: &str) -> Result<Self> {
+        let client = http::client(http::L2_TIMEOUT)?;
+
+        Ok(Self {
+            url: url.to_string(),
+            client,
+        })
+    }
+}
+
+#[async_trait]
+impl MessageValidator for SupervisorClient {
+    async fn check_messages(
+        &self,
+        messages: &[ExecutingMessage],
+        _timestamp: u64,
+    ) -> Result<MessageValidity> {
+        let body = json!({
+            "jsonrpc": "2.0",
+            "method": "supervisor_checkMessages",
+            "params": [messages, "unsafe"],
+            "id": 1,
+        });
+
+        let res = http::call("supervisor_checkMessages", http::L2_TIMEOUT, async {
+            Ok(self
+                .client
+                .post(&self.url)
+                .json(&body)
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<Value>()
+                .await?)
+        })
+        .await?;
+
+        parse_validity(&res)
+    }
+}
+
+/// Parses the response of `supervisor_checkMessages`. Only the errors which mark a message
+/// as invalid are an invalid result, any other error means the validity is unknown.
+fn parse_validity(res: &Value) -> Result<MessageValidity> {
+    let Some(err) = res.get("error") else {
+        return Ok(MessageValidity::Valid);
+    };
+
+    let code = err.get("code").and_then(Value::as_i64);
+    let message = err
+        .get("message")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+
+    match code {
+        Some(CONFLICTING_DATA | UNKNOWN_CHAIN) => Ok(MessageValidity::Invalid(message.to_string())),
+        _ => eyre::bail!("supervisor error: {}", err),
+    }
+}
+
+/// Driver hook which validates executing messages once the Interop fork is active
+pub struct InteropValidator {
+    /// The timestamp at which interop activates, if scheduled
+    interop_time: Option<u64>,
+    /// The message validator to consult
+    validator: Box<dyn MessageValidator>,
+}
+
+impl InteropValidator {
+    /// Creates a new [InteropValidator], consulting the supervisor RPC if one is
+    /// configured and falling back to the local dependency set checker otherwise.
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let interop_time = config.chain.hardforks.interop_time;
+
+        let validator: Box<dyn MessageValidator> = match &config.supervisor_rpc_url {
+            Some(url) => Box::new(SupervisorClient::new(url)?),
+            None => {
+                let mut dependency_set = config.chain.interop_dependency_set.clone();
+                if !dependency_set.contains(&config.chain.l2_chain_id) {
+                    dependency_set.push(config.chain.l2_chain_id);
+                }
+
+                Box::new(DependencySetChecker::new(
+                    dependency_set,
+                    interop_time.unwrap_or(u64::MAX),
+                ))
+            }
+        };
+
+        Ok(Self {
+            interop_time,
+            validator,
+        })
+    }
+
+    fn is_active(&self, timestamp: u64) -> bool {
+        self.interop_time.is_some_and(|time| timestamp >= time)
+    }
+
+    /// Checks the executing messages of an unsafe payload. Errors if the
+    /// messages could not be checked, in which case the payload should be retried.
+    pub async fn validate_payload(&self, payload: &ExecutionPayload) -> Result<MessageValidity> {
+        let timestamp = payload.timestamp.as_u64();
+        if !self.is_active(timestamp) {
+            return Ok(MessageValidity::Valid);
+        }
+
+        let messages = executing_messages(&payload.transactions);
+        if messages.is_empty() {
+            return Ok(MessageValidity::Valid);
+        }
+
+        let validity = self.validator.check_messages(&messages, timestamp).await?;
+        if let MessageValidity::Invalid(reason) = &validity {
+            tracing::warn!(
+                "rejecting unsafe block {} with invalid executing messages: {}",
+                payload.block_number,
+                reason
+            );
+        }
+
+        Ok(validity)
+    }
+
+    /// Replaces the derived attributes with a deposit-only block if they contain invalid
+    /// executing messages. Since derivation cannot skip a block, the check is retried until
+    /// the validator responds and an error is only returned after all retries failed.
+    pub async fn validate_attributes(&self, attributes: &mut PayloadAttributes) -> Result<()> {
+        let timestamp = attributes.timestamp.as_u64();
+        if !self.is_active(timestamp) {
+            return Ok(());
+        }
+
+        let Some(transactions) = attributes.transactions.as_mut() else {
+            return Ok(());
+        };
+
+        let messages = executing_messages(transactions);
+        if messages.is_empty() {
+            return Ok(());
+        }
+
+        let mut attempt = 0;
+        let validity = loop {
+            match self.validator.check_messages(&messages, timestamp).await {
+                Ok(validity) => break validity,
+                Err(err) if attempt < VALIDATION_RETRIES => {
+                    tracing::warn!("failed to validate executing messages: {}", err);
+                    attempt += 1;
+                    sleep(Duration::from_secs(1)).await;
+                }
+                Err(err) => return Err(err),
+            }
+        };
+
+        if let MessageValidity::Invalid(reason) = validity {
+            tracing::warn!(
+                "replacing block at {} with deposit-only block due to invalid executing messages: {}",
+                timestamp,
+                reason
+            );
+
+            transactions.retain(|tx| tx.0.first() == Some(&DEPOSIT_TX_TYPE));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::{abi::encode, types::Bytes};
+
+    use super::*;
+
+    fn identifier_token(chain_id: u64, timestamp: u64) -> Token {
+        Token::Tuple(vec![
+            Token::Address(Address::from_low_u64_be(0xabc)),
+            Token::Uint(U256::from(10)),
+            Token::Uint(U256::from(2)),
+            Token::Uint(U256::from(timestamp)),
+            Token::Uint(U256::from(chain_id)),
+        ])
+    }
+
+    fn execute_message_tx(chain_id: u64, timestamp: u64) -> Transaction {
+        let selector =
+            id("executeMessage((address,uint256,uint256,uint256,uint256),address,bytes)");
+        let args = encode(&[
+            identifier_token(chain_id, timestamp),
+            Token::Address(Address::from_low_u64_be(0xdef)),
+            Token::Bytes(vec![1, 2, 3]),
+        ]);
+
+        Transaction {
+            to: Some(CROSS_L2_INBOX.parse().unwrap()),
+            input: Bytes::from([selector.to_vec(), args].concat()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_executing_message() {
+        let message = ExecutingMessage::from_transaction(&execute_message_tx(901, 100)).unwrap();
+
+        assert_eq!(message.identifier.chain_id, 901);
+        assert_eq!(message.identifier.timestamp, 100);
+        assert_eq!(message.identifier.block_number, 10);
+        assert_eq!(message.identifier.log_index, 2);
+        assert_eq!(message.payload_hash, H256::from(keccak256([1, 2, 3])));
+
+        let mut other = execute_message_tx(901, 100);
+        other.to = Some(Address::from_low_u64_be(1));
+        assert!(ExecutingMessage::from_transaction(&other).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_dependency_set_checker() {
+        let checker = DependencySetChecker::new(vec![900, 901], 50);
+        let message = |chain_id, timestamp| {
+            ExecutingMessage::from_transaction(&execute_message_tx(chain_id, timestamp)).unwrap()
+        };
+
+        let check = |message| {
+            let checker = checker.clone();
+            async move { checker.check_messages(&[message], 100).await.unwrap() }
+        };
+
+        assert_eq!(check(message(901, 100)).await, MessageValidity::Valid);
+        assert_ne!(check(message(902, 100)).await, MessageValidity::Valid);
+        assert_ne!(check(message(901, 101)).await, MessageValidity::Valid);
+        assert_ne!(check(message(901, 40)).await, MessageValidity::Valid);
+    }
+
+    #[test]
+    fn test_parse_validity() {
+        let error = |code: i64| {
+            json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "error": { "code": code, "message": "failed" },
+            })
+        };
+
+        let valid = json!({ "jsonrpc": "2.0", "id": 1, "result": null });
+        assert_eq!(parse_validity(&valid).unwrap(), MessageValidity::Valid);
+
+        let invalid = MessageValidity::Invalid("failed".to_string());
+        assert_eq!(parse_validity(&error(CONFLICTING_DATA)).unwrap(), invalid);
+        assert_eq!(parse_validity(&error(UNKNOWN_CHAIN)).unwrap(), invalid);
+
+        // errors which do not mark the messages as invalid are retried
+        assert!(parse_validity(&error(-32601)).is_err());
+        assert!(parse_validity(&error(-321401)).is_err());
+        assert!(parse_validity(&json!({ "error": "unavailable" })).is_err());
+    }
+}
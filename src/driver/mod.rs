@@ -1,42 +1,110 @@
 use std::{
-    process,
-    sync::{mpsc::Receiver, Arc, RwLock},
-    time::Duration,
+    sync::{
+        mpsc::{channel, Receiver},
+        Arc, RwLock,
+    },
+    time::{Duration, Instant, SystemTime},
 };
 
-use ethers::{
-    providers::{Http, Provider},
-    types::Address,
-};
-use eyre::Result;
-use reqwest::Url;
+use ethers::types::Address;
+use eyre::{Result, WrapErr};
 use tokio::{
-    sync::watch::{self, Sender},
+    sync::{
+        broadcast, mpsc,
+        watch::{self, Sender},
+    },
     time::sleep,
 };
 
 use crate::{
-    common::{BlockInfo, Epoch},
-    config::Config,
-    derive::{state::State, Pipeline},
-    engine::{Engine, EngineApi, ExecutionPayload},
-    l1::{BlockUpdate, ChainWatcher},
+    common::{http, BlockInfo, Epoch},
+    config::{ChainConfig, Config},
+    derive::{state::State, DerivationError, DerivationErrors, Pipeline, StepResult},
+    engine::{Engine, EngineFailover, ExecutionPayload},
+    l1::{BlockUpdate, ChainWatcher, ProtocolVersionsMonitor},
     network::{handlers::block_handler::BlockHandler, service::Service},
-    rpc,
+    signer,
+    store::{self, DataDir, Snapshot, UnsafePayloads},
     telemetry::metrics,
 };
 
-use self::engine_driver::EngineDriver;
+#[cfg(feature = "rpc")]
+use crate::{
+    derive::ForcedInclusionReport,
+    l1::protocol_versions::SUPPORTED_PROTOCOL_VERSION,
+    rpc::{self, DerivationReset, L1BlockRef, SyncStatus},
+};
+
+use self::{
+    dry_run::{DryRun, DryRunEngine},
+    engine_driver::EngineDriver,
+    head_follower::HeadFollower,
+    interop::{InteropValidator, MessageValidity},
+    output_follower::OutputFollower,
+    sequencer::Sequencer,
+    watchdog::{Diagnostics, Progress, Watchdog},
+};
+
+/// A module to compare derived attributes with the unsafe blocks they replace
+mod attributes_diff;
+pub use attributes_diff::{AttributeMismatches, AttributesDiff, FieldDiff, TransactionsDiff};
+
+/// A module to derive blocks without an execution client
+mod dry_run;
 
 /// A module to handle block production & validation
 mod engine_driver;
 
+/// A module for the errors stopping the driver
+mod error;
+pub use error::{DriverError, CRITICAL_ERROR_EXIT_CODE, FAILURE_EXIT_CODE};
+
+/// A module to publish head events to library embedders
+mod events;
+pub use events::*;
+
+/// A module to finalize derived blocks once their L1 data is finalized
+mod finality;
+use finality::FinalityTracker;
+
+/// A module to follow the unsafe head of a trusted L2 RPC
+mod head_follower;
+
+/// A module to validate interop executing messages
+pub mod interop;
+
 /// A module to handle fetching blocks
 mod info;
 
-/// A module to handle conversions to a [HeadInfo] struct
-mod types;
-pub use types::*;
+/// A module to switch between full derivation and light-follow at runtime
+mod mode;
+pub use mode::{DerivationControl, DerivationMode};
+
+/// A module to promote unsafe blocks whose outputs are proposed on L1 in light-follow mode
+mod output_follower;
+
+/// A module to build and publish unsafe blocks when running as the sequencer
+mod sequencer;
+pub use sequencer::{SequencerControl, SequencerStatus, StopTarget};
+
+/// A module to report stalled driver components
+mod watchdog;
+pub use watchdog::WATCHDOG_EXIT_CODE;
+
+pub use crate::common::HeadInfo;
+
+/// Deepest L1 reorg handled by rewinding to the common ancestor instead of the finalized head
+const MAX_REWIND_DEPTH: u64 = 64;
+
+/// Time to wait for a payload requested from peers before requesting it again
+const SYNC_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Delay before retrying after a temporary derivation error, doubled for every further
+/// error in a row
+const RETRY_DELAY: Duration = Duration::from_millis(250);
+
+/// Maximum delay before retrying after temporary derivation errors
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(10);
 
 /// Driver is responsible for advancing the execution node by feeding
 /// the derived chain into the engine API
@@ -45,12 +113,12 @@ pub struct Driver<E: Engine> {
     pipeline: Pipeline,
     /// The engine driver
     engine_driver: EngineDriver<E>,
-    /// List of unfinalized L2 blocks with their epochs, L1 inclusions, and sequence numbers
-    unfinalized_blocks: Vec<(BlockInfo, Epoch, u64, u64)>,
-    /// Current finalized L1 block number
-    finalized_l1_block_number: u64,
+    /// The unfinalized derived L2 blocks and the L1 blocks they were built from
+    finality: FinalityTracker,
     /// List of unsafe blocks that have not been applied yet
     future_unsafe_blocks: Vec<ExecutionPayload>,
+    /// The latest applied unsafe payloads, kept to restore the unsafe head on restart
+    unsafe_payloads: Option<UnsafePayloads>,
     /// State struct to keep track of global state
     state: Arc<RwLock<State>>,
     /// L1 chain watcher
@@ -61,106 +129,482 @@ pub struct Driver<E: Engine> {
     unsafe_block_recv: Receiver<ExecutionPayload>,
     /// Channel to send unsafe signer updates to block handler
     unsafe_block_signer_sender: Sender<Address>,
+    /// Channel to request missing unsafe blocks from peers, if enabled
+    sync_request_sender: Option<mpsc::Sender<u64>>,
+    /// Channel to receive the unsafe blocks requested from peers
+    synced_payload_recv: Receiver<ExecutionPayload>,
+    /// The block number and time of the latest request to peers
+    last_sync_request: Option<(u64, Instant)>,
     /// Networking service
     network_service: Option<Service>,
-    /// Channel timeout length
-    channel_timeout: u64,
+    /// Builds and publishes unsafe blocks, when running as the sequencer
+    sequencer: Option<Sequencer>,
+    /// Follows the unsafe head of a trusted L2 RPC, if configured
+    head_follower: Option<HeadFollower>,
+    /// Follows the outputs proposed on L1 to promote unsafe blocks in light-follow mode,
+    /// unless running as the sequencer or in a dry run
+    output_follower: Option<OutputFollower>,
+    /// Whether the safe head was promoted from the outputs proposed on L1, so derivation
+    /// restarts from it once full derivation resumes
+    promoted_from_outputs: bool,
+    /// Whether L1 derivation is paused in light-follow mode, switched over the admin RPC
+    derivation: Arc<DerivationControl>,
+    /// The chain config, for the channel timeout in effect at each epoch
+    chain: ChainConfig,
+    /// Monitor for the protocol versions signaled on L1
+    #[cfg_attr(not(feature = "rpc"), allow(dead_code))]
+    protocol_versions: ProtocolVersionsMonitor,
+    /// Sync status shared with the RPC server
+    #[cfg(feature = "rpc")]
+    sync_status: Arc<RwLock<SyncStatus>>,
+    /// Deposit-only blocks report shared with the RPC server
+    #[cfg(feature = "rpc")]
+    forced_inclusion: Arc<RwLock<ForcedInclusionReport>>,
+    /// Channel to receive derivation resets requested over the admin RPC
+    #[cfg(feature = "rpc")]
+    reset_recv: mpsc::Receiver<DerivationReset>,
+    /// Validates interop executing messages before blocks are accepted
+    interop: InteropValidator,
+    /// The L2 chain id used to label metrics
+    chain_label: String,
+    /// The data directory, locked while the driver runs
+    _data_dir: Option<DataDir>,
+    /// Publishes head events to subscribers
+    events: EventPublisher,
+    /// Progress of the driver components, checked by the watchdog
+    progress: Progress,
+    /// The watchdog, spawned once the driver starts
+    watchdog: Option<Watchdog>,
+    /// Time an iteration of the driver loop may take
+    tick_timeout: Duration,
+    /// Validates and hashes the derived attributes in place of the engine, in a dry run
+    dry_run: Option<DryRun>,
+    /// The errors of the derivation loop
+    derivation_errors: DerivationErrors,
 }
 
-impl Driver<EngineApi> {
+impl Driver<EngineFailover> {
     /// Creates a new [Driver] from the given [Config]
     pub async fn from_config(config: Config, shutdown_recv: watch::Receiver<bool>) -> Result<Self> {
-        let client = reqwest::ClientBuilder::new()
-            .timeout(Duration::from_secs(5))
-            .build()?;
+        let engine = Arc::new(EngineFailover::from_config(&config)?);
+        engine.spawn_health_checks();
+        engine.spawn_secret_reloads()?;
+
+        Self::build(config, shutdown_recv, engine.clone(), Some(engine)).await
+    }
+}
+
+impl Driver<DryRunEngine> {
+    /// Creates a new [Driver] for a dry run, which derives blocks from L1 without sending
+    /// them to an execution client
+    pub async fn dry_run(config: Config, shutdown_recv: watch::Receiver<bool>) -> Result<Self> {
+        let config = Config {
+            dry_run: true,
+            ..config
+        };
+
+        Self::build(config, shutdown_recv, Arc::new(DryRunEngine), None).await
+    }
+}
 
-        let http = Http::new_with_client(Url::parse(&config.l2_rpc_url)?, client);
-        let provider = Provider::new(http);
+impl<E: Engine> Driver<E> {
+    /// Creates a new [Driver] which hands derived blocks to the given in-process [Engine]
+    /// instead of an Engine API endpoint, such as a [ChannelEngine](crate::engine::ChannelEngine)
+    /// served by the execution client embedding Magi.
+    pub async fn with_engine(
+        config: Config,
+        shutdown_recv: watch::Receiver<bool>,
+        engine: Arc<E>,
+    ) -> Result<Self> {
+        Self::build(config, shutdown_recv, engine, None).await
+    }
 
-        let head =
+    /// Creates a new [Driver] using the given [Engine]. If it is an [EngineFailover], it is
+    /// also used to backfill L2 blocks and exposed through the admin RPC.
+    async fn build(
+        config: Config,
+        shutdown_recv: watch::Receiver<bool>,
+        engine: Arc<E>,
+        failover: Option<Arc<EngineFailover>>,
+    ) -> Result<Self> {
+        if config.dry_run && (config.sequencer || config.unsafe_follow_url.is_some()) {
+            eyre::bail!("dry runs cannot sequence or follow unsafe blocks");
+        }
+
+        let unsupported_forks = config.chain.hardforks.scheduled_unsupported_forks();
+        if !unsupported_forks.is_empty() && !config.halt_on_unsupported_fork {
+            tracing::warn!(
+                "the {} hardfork rules are not fully implemented, blocks past their activation may differ from op-node",
+                unsupported_forks.join(", ")
+            );
+        }
+
+        let provider = http::provider(&config.l2_rpc_url, http::L2_TIMEOUT)?;
+
+        let mut head =
             info::HeadInfoQuery::get_head_info(&info::HeadInfoFetcher::from(&provider), &config)
                 .await;
 
+        let data_dir = DataDir::open(&config)?;
+
+        // an imported snapshot is ahead of a fresh execution client, which then syncs up
+        // to the safe head of the snapshot while derivation resumes from its checkpoint
+        let mut safe = head.clone();
+        let mut l1_start_block = get_l1_start_block(head.l1_epoch, &config.chain);
+        if let Some(snapshot) = Snapshot::load(&config)? {
+            if snapshot.finalized_head.number > head.l2_block_info.number {
+                tracing::info!(
+                    "starting from imported snapshot at safe block {} and L1 block {}",
+                    snapshot.safe_head.number,
+                    snapshot.checkpoint.l1_start_block
+                );
+                head = snapshot.finalized_head_info();
+                safe = snapshot.safe_head_info();
+                l1_start_block = snapshot.checkpoint.l1_start_block;
+            }
+        }
+
         let finalized_head = head.l2_block_info;
         let finalized_epoch = head.l1_epoch;
-        let finalized_seq = head.sequence_number;
 
-        tracing::info!("starting from head: {:?}", finalized_head.hash);
-
-        let l1_start_block =
-            get_l1_start_block(finalized_epoch.number, config.chain.channel_timeout);
+        tracing::info!("starting from head: {:?}", safe.l2_block_info.hash);
 
         let config = Arc::new(config);
-        let chain_watcher =
-            ChainWatcher::new(l1_start_block, finalized_head.number, config.clone())?;
-
-        let state = State::new(finalized_head, finalized_epoch, &provider, config.clone()).await;
+        let mut chain_watcher =
+            ChainWatcher::new(l1_start_block, safe.l2_block_info.number, config.clone())?;
+
+        let state = State::new(
+            safe.l2_block_info,
+            safe.l1_epoch,
+            &provider,
+            failover.as_ref().map(|failover| failover.active()),
+            config.clone(),
+        )
+        .await;
         let state = Arc::new(RwLock::new(state));
+        State::spawn_pruning(&state);
+
+        let mut engine_driver = EngineDriver::new(
+            engine,
+            finalized_head,
+            finalized_epoch,
+            provider.clone(),
+            &config,
+        )?;
+        engine_driver.unsafe_head = safe.l2_block_info;
+        engine_driver.safe_head = safe.l2_block_info;
+        engine_driver.safe_epoch = safe.l1_epoch;
+        let pipeline = Pipeline::new(state.clone(), config.clone(), safe.sequence_number)?;
+
+        // dry runs do not apply unsafe blocks
+        let unsafe_payloads = match config.dry_run {
+            true => None,
+            false => UnsafePayloads::open(&config)?,
+        };
+        if let Some(payloads) = &unsafe_payloads {
+            let payloads = payloads.load_after(safe.l2_block_info.number)?;
+            if let Err(err) = engine_driver.restore_unsafe_head(&payloads).await {
+                tracing::warn!("could not restore the unsafe head: {}", err);
+            }
+        }
+        if let Some(lookahead) = pipeline.lookahead() {
+            chain_watcher.set_lookahead(lookahead);
+        }
 
-        let engine_driver = EngineDriver::new(finalized_head, finalized_epoch, provider, &config)?;
-        let pipeline = Pipeline::new(state.clone(), config.clone(), finalized_seq)?;
+        let progress = Progress::new(engine_driver.activity());
+        let watchdog = Watchdog::new(&config, chain_watcher.heartbeat(), progress.clone());
 
-        let _addr = rpc::run_server(config.clone()).await?;
+        let protocol_versions = ProtocolVersionsMonitor::start(config.clone());
+        let interop = InteropValidator::from_config(&config)?;
 
         let (unsafe_block_signer_sender, unsafe_block_signer_recv) = watch::channel(
             Address::from_slice(config.chain.system_config.unsafe_block_signer.as_slice()),
         );
 
-        let (block_handler, unsafe_block_recv) =
-            BlockHandler::new(config.chain.l2_chain_id, unsafe_block_signer_recv);
+        let (block_handler, unsafe_block_recv) = BlockHandler::new(
+            config.chain.l2_chain_id,
+            config.chain.hardforks,
+            unsafe_block_signer_recv,
+        );
+
+        let mut service = Service::new(
+            format!("0.0.0.0:{}", config.p2p_port).parse()?,
+            config.chain.l2_chain_id,
+        )
+        .add_handler(Box::new(block_handler))
+        .set_peer_limits(config.p2p_max_inbound_peers, config.p2p_max_outbound_peers)
+        .set_static_peers(config.p2p_static_peers.clone())
+        .set_bootnodes(config.p2p_bootnodes.clone());
+
+        if let Some(path) = store::peerstore_path(&config) {
+            service = service.set_peerstore_path(path);
+        }
 
-        let service = Service::new("0.0.0.0:9876".parse()?, config.chain.l2_chain_id)
-            .add_handler(Box::new(block_handler));
+        let (synced_payload_sender, synced_payload_recv) = channel();
+        let sync_request_sender = if config.p2p_sync {
+            let (sender, recv) = mpsc::channel(16);
+            service = service.with_payload_sync(recv, synced_payload_sender);
+            Some(sender)
+        } else {
+            None
+        };
+
+        let signer = signer::from_config(&config)?;
+        let sequencer = if config.sequencer {
+            let (sender, recv) = mpsc::channel(16);
+            service = service.with_block_publishing(recv);
+
+            let signer = signer
+                .clone()
+                .ok_or(eyre::eyre!("the sequencer requires a sequencer key"))?;
+
+            let mut sequencer =
+                Sequencer::new(config.clone(), provider, state.clone(), signer, sender);
+            sequencer.resume(&mut engine_driver).await?;
+            Some(sequencer)
+        } else {
+            None
+        };
+
+        let mode = match config.light_follow {
+            true => DerivationMode::LightFollow,
+            false => DerivationMode::Full,
+        };
+        let derivation = Arc::new(DerivationControl::new(mode, config.sequencer)?);
+
+        let head_follower = config
+            .unsafe_follow_url
+            .as_ref()
+            .map(|url| {
+                HeadFollower::start(
+                    url,
+                    config.chain.blocktime,
+                    config.unsafe_follow_max_lag,
+                    engine_driver.unsafe_head,
+                )
+            })
+            .transpose()?;
+
+        // the sequencer always derives and dry runs have no unsafe chain to promote
+        let output_follower = match config.sequencer || config.dry_run {
+            true => None,
+            false => Some(OutputFollower::start(
+                config.clone(),
+                derivation.is_light_follow(),
+            )?),
+        };
+
+        #[cfg(feature = "rpc")]
+        let overrides = state
+            .read()
+            .map_err(|_| eyre::eyre!("lock poisoned"))?
+            .overrides
+            .clone();
+        #[cfg(feature = "rpc")]
+        let sync_status = Arc::new(RwLock::new(SyncStatus::default()));
+        #[cfg(feature = "rpc")]
+        let forced_inclusion = Arc::new(RwLock::new(ForcedInclusionReport::default()));
+        #[cfg(feature = "rpc")]
+        let (reset_sender, reset_recv) = mpsc::channel(1);
+        #[cfg(feature = "rpc")]
+        rpc::run_server(
+            config.clone(),
+            sync_status.clone(),
+            forced_inclusion.clone(),
+            service.peer_handles(),
+            failover,
+            signer.map(|signer| signer.info()),
+            rpc::DriverControls {
+                reset_sender,
+                sequencer: sequencer.as_ref().map(|sequencer| sequencer.control()),
+                derivation: derivation.clone(),
+                mismatches: engine_driver.mismatches(),
+                overrides,
+            },
+            shutdown_recv.clone(),
+        )
+        .await?;
+
+        // dry runs only derive the safe chain, so they never join the gossip network
+        let network_service = match config.dry_run {
+            true => None,
+            false => Some(service),
+        };
 
         Ok(Self {
             engine_driver,
             pipeline,
-            unfinalized_blocks: Vec::new(),
-            finalized_l1_block_number: 0,
+            finality: FinalityTracker::default(),
             future_unsafe_blocks: Vec::new(),
+            unsafe_payloads,
             state,
             chain_watcher,
             shutdown_recv,
             unsafe_block_recv,
             unsafe_block_signer_sender,
-            network_service: Some(service),
-            channel_timeout: config.chain.channel_timeout,
+            sync_request_sender,
+            synced_payload_recv,
+            last_sync_request: None,
+            network_service,
+            sequencer,
+            head_follower,
+            output_follower,
+            promoted_from_outputs: false,
+            derivation,
+            chain: config.chain.clone(),
+            protocol_versions,
+            #[cfg(feature = "rpc")]
+            sync_status,
+            #[cfg(feature = "rpc")]
+            forced_inclusion,
+            #[cfg(feature = "rpc")]
+            reset_recv,
+            interop,
+            chain_label: config.chain.l2_chain_id.to_string(),
+            _data_dir: data_dir,
+            events: EventPublisher::new(
+                broadcast::channel(EVENT_CAPACITY).0,
+                finalized_head,
+                finalized_head,
+            ),
+            progress,
+            watchdog: Some(watchdog),
+            tick_timeout: Duration::from_secs(config.driver_tick_timeout),
+            dry_run: DryRun::from_config(&config),
+            derivation_errors: DerivationErrors::default(),
         })
     }
 }
 
 impl<E: Engine> Driver<E> {
-    /// Runs the Driver
-    pub async fn start(&mut self) -> Result<()> {
+    /// Publishes the events of the driver on the given channel, so they can be subscribed
+    /// to before the driver is created
+    pub fn with_events(mut self, sender: broadcast::Sender<NodeEvent>) -> Self {
+        self.events.set_sender(sender);
+        self
+    }
+
+    /// Returns a receiver of the [NodeEvent]s published from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<NodeEvent> {
+        self.events.subscribe()
+    }
+
+    /// Runs the Driver until a shutdown is signaled or a dry run reaches its last block.
+    /// Errors if derivation halts, leaving it to the caller to stop the process.
+    pub async fn start(&mut self) -> Result<(), DriverError> {
         self.await_engine_ready().await;
-        self.chain_watcher.start()?;
+        if self.is_shutdown() {
+            return Ok(());
+        }
+
+        self.chain_watcher.start().map_err(DriverError::Start)?;
+
+        self.progress.derivation.beat();
+        if let Some(watchdog) = self.watchdog.take() {
+            watchdog.spawn();
+        }
 
         loop {
-            self.check_shutdown().await;
+            self.progress.derivation.beat();
+            if self.is_shutdown() || self.dry_run_done() {
+                self.shutdown();
+                return Ok(());
+            }
 
-            if let Err(err) = self.advance().await {
-                tracing::error!("fatal error: {:?}", err);
-                self.shutdown().await;
+            let deadline = http::Deadline::new(self.tick_timeout, self.shutdown_recv.clone());
+            match deadline.scope(self.advance()).await {
+                Ok(()) => self.derivation_errors.clear(),
+                // the calls in flight are cancelled by the shutdown, and a finished dry run
+                // stops regardless, both by the next iteration
+                Err(_) if self.is_shutdown() || self.dry_run_done() => {}
+                Err(err) => self.handle_error(DerivationError::classify(&err)).await?,
             }
         }
     }
 
-    /// Shuts down the driver
-    pub async fn shutdown(&self) {
-        process::exit(0);
+    /// Recovers from an error of the derivation loop as its class requires: temporary
+    /// errors are retried after a delay, reset errors restart derivation from the finalized
+    /// head and critical errors halt the driver
+    async fn handle_error(&mut self, err: DerivationError) -> Result<(), DriverError> {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+
+        self.derivation_errors.record(&err, now);
+        metrics::DERIVATION_ERRORS
+            .with_label_values(&[&self.chain_label, err.code()])
+            .inc();
+        self.events.publish(NodeEvent::DerivationError(format!(
+            "{}: {}",
+            err.code(),
+            err.message()
+        )));
+        #[cfg(feature = "rpc")]
+        if let Err(err) = self.update_sync_status() {
+            tracing::warn!("could not update the sync status: {}", err);
+        }
+
+        if let Some(dry_run) = &self.dry_run {
+            tracing::error!("dry run failed: {}", err);
+            dry_run.report();
+            return Err(DriverError::DryRun(err));
+        }
+
+        match err {
+            DerivationError::Temporary(_) => {
+                let retries = (self.derivation_errors.consecutive - 1).min(16) as u32;
+                let delay = (RETRY_DELAY * 2u32.pow(retries)).min(MAX_RETRY_DELAY);
+                tracing::warn!("temporary error: {}, retrying in {:?}", err, delay);
+
+                // the retry is cancelled by a shutdown, checked by the next iteration
+                let mut shutdown = self.shutdown_recv.clone();
+                _ = tokio::time::timeout(delay, shutdown.changed()).await;
+            }
+            DerivationError::Reset(_) => {
+                tracing::warn!("resetting derivation to the finalized head: {}", err);
+                if let Err(reset_err) = self.reset_to_finalized() {
+                    tracing::error!("could not reset derivation: {:?}", reset_err);
+                    return Err(DriverError::Critical(DerivationError::critical(format!(
+                        "could not reset derivation after {}: {:#}",
+                        err, reset_err
+                    ))));
+                }
+            }
+            DerivationError::Critical(_) => {
+                tracing::error!("critical error: {}", err);
+                return Err(DriverError::Critical(err));
+            }
+        }
+
+        Ok(())
     }
 
-    /// Checks for shutdown signal and shuts down if received
-    async fn check_shutdown(&self) {
-        if *self.shutdown_recv.borrow() {
-            self.shutdown().await;
+    /// Shuts down the driver, printing the summary of a dry run
+    fn shutdown(&self) {
+        if let Some(dry_run) = &self.dry_run {
+            dry_run.report();
         }
+
+        tracing::info!("driver stopped");
     }
 
-    /// Loops until the [EngineApi] is online and receives a response from the engine.
+    /// Returns `true` once the shutdown signal is received
+    fn is_shutdown(&self) -> bool {
+        *self.shutdown_recv.borrow()
+    }
+
+    /// Returns `true` once a dry run derived its last block
+    fn dry_run_done(&self) -> bool {
+        self.dry_run
+            .as_ref()
+            .is_some_and(|dry_run| dry_run.is_done(&self.engine_driver.safe_head))
+    }
+
+    /// Loops until the [Engine] is online and receives a response from the engine, or a
+    /// shutdown is signaled.
     async fn await_engine_ready(&self) {
-        while !self.engine_driver.engine_ready().await {
-            self.check_shutdown().await;
+        while !self.is_shutdown() && !self.engine_driver.engine_ready().await {
             sleep(Duration::from_secs(1)).await;
         }
     }
@@ -168,36 +612,194 @@ impl<E: Engine> Driver<E> {
     /// Attempts to advance the execution node forward using either L1 info or
     /// blocks received on the p2p network.
     async fn advance(&mut self) -> Result<()> {
-        self.advance_safe_head().await?;
+        #[cfg(feature = "rpc")]
+        self.handle_reset_requests().await?;
+        if self.progress.take_l1_watcher_restart() {
+            self.chain_watcher.restart_stalled()?;
+        }
+        if self.derivation.is_light_follow() {
+            // L1 blocks wait in the bounded channel of the watcher until derivation resumes
+            self.chain_watcher.supervise()?;
+            self.promote_proposed_outputs().await?;
+        } else {
+            self.resume_derivation()?;
+            self.advance_safe_head().await?;
+        }
         self.advance_unsafe_head().await?;
+        self.sequence().await;
+
+        if let Some(follower) = &self.output_follower {
+            follower.update(
+                self.engine_driver.unsafe_head.number,
+                self.derivation.is_light_follow(),
+            );
+        }
 
         self.update_finalized();
+        self.events.update_heads(
+            self.engine_driver.unsafe_head,
+            self.engine_driver.finalized_head,
+            self.engine_driver.finalized_epoch,
+        );
         self.update_metrics();
+        #[cfg(feature = "rpc")]
+        self.update_sync_status()?;
         self.try_start_networking()?;
 
         Ok(())
     }
 
-    /// Attempts to advance the execution node forward one L1 block using derived
-    /// L1 data. Errors if the most recent PayloadAttributes from the pipeline
-    /// does not successfully advance the node
+    /// Promotes the unsafe blocks whose outputs are proposed on L1 to safe and finalized,
+    /// while L1 derivation is paused in light-follow mode
+    async fn promote_proposed_outputs(&mut self) -> Result<()> {
+        let Some(follower) = &mut self.output_follower else {
+            return Ok(());
+        };
+
+        let outputs = follower.receive();
+        for output in outputs {
+            let (head, epoch) = (output.head.l2_block_info, output.head.l1_epoch);
+            let (safe, finalized) = (
+                self.engine_driver.safe_head,
+                self.engine_driver.finalized_head,
+            );
+
+            self.engine_driver
+                .promote(head, epoch, output.finalized)
+                .await?;
+
+            if self.engine_driver.finalized_head != finalized {
+                // the blocks tracked before light-follow are older than the finalized head
+                self.finality.clear();
+                tracing::info!("finalized block {} from the outputs on L1", head.number);
+            }
+            if self.engine_driver.safe_head != safe {
+                self.promoted_from_outputs = true;
+                tracing::info!(
+                    "promoted block {} to safe from the outputs on L1",
+                    head.number
+                );
+                self.events
+                    .publish(NodeEvent::SafeHeadUpdated { head, epoch });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Restarts derivation from the safe head promoted in light-follow mode, once full
+    /// derivation resumes, since the pipeline stopped at the last derived block
+    fn resume_derivation(&mut self) -> Result<()> {
+        if !std::mem::take(&mut self.promoted_from_outputs) {
+            return Ok(());
+        }
+
+        let (head, epoch) = (self.engine_driver.safe_head, self.engine_driver.safe_epoch);
+        tracing::info!("resuming derivation from promoted block {}", head.number);
+
+        self.reset_derivation(head, epoch)
+    }
+
+    /// Attempts to advance the execution node forward using derived L1 data, stepping the
+    /// pipeline and ingesting the buffered L1 blocks whenever it is idle, at most as many
+    /// as are buffered. While derived attributes are pending, no L1 blocks are ingested, so
+    /// they wait in the bounded channel of the [ChainWatcher], which stops fetching once it
+    /// is full. Errors if the most recent PayloadAttributes from the pipeline does not
+    /// successfully advance the node
     async fn advance_safe_head(&mut self) -> Result<()> {
-        self.handle_next_block_update().await?;
+        self.chain_watcher.supervise()?;
+        let mut buffered = self.chain_watcher.pending_updates().max(1);
         self.update_state_head()?;
 
-        for next_attributes in self.pipeline.by_ref() {
-            let l1_inclusion_block = next_attributes
-                .l1_inclusion_block
-                .ok_or(eyre::eyre!("attributes without inclusion block"))?;
+        loop {
+            let attributes = match self.pipeline.step() {
+                StepResult::Attributes(attributes) => *attributes,
+                StepResult::Progress => {
+                    // gossip and RPC tasks sharing the runtime run between steps
+                    tokio::task::yield_now().await;
+                    if http::Deadline::passed() {
+                        break;
+                    }
+                    continue;
+                }
+                StepResult::Idle => {
+                    if self.pipeline.error().is_some()
+                        || buffered == 0
+                        || !self.handle_next_block_update().await?
+                    {
+                        break;
+                    }
+                    buffered -= 1;
+                    self.update_state_head()?;
+                    continue;
+                }
+            };
+
+            self.progress.derivation.beat();
+
+            let mut next_attributes = attributes.clone();
+            if let Err(err) = self.interop.validate_attributes(&mut next_attributes).await {
+                self.pipeline.retry(attributes);
+                let err = DerivationError::temporary(err);
+                return Err(
+                    eyre::Report::new(err).wrap_err("failed to validate executing messages")
+                );
+            }
+
+            let l1_inclusion_block =
+                next_attributes
+                    .l1_inclusion_block
+                    .ok_or(DerivationError::critical(
+                        "attributes without inclusion block",
+                    ))?;
 
             let seq_number = next_attributes
                 .seq_number
-                .ok_or(eyre::eyre!("attributes without seq number"))?;
+                .ok_or(DerivationError::critical("attributes without seq number"))?;
+
+            let unsafe_head = self.engine_driver.unsafe_head;
+
+            match &mut self.dry_run {
+                Some(dry_run) => {
+                    let hash = dry_run
+                        .check(&next_attributes, &self.engine_driver.safe_head)
+                        .map_err(|e| {
+                            DerivationError::critical(format!("invalid attributes: {}", e))
+                        })?;
+                    let matched = self
+                        .engine_driver
+                        .simulate_attributes(&next_attributes, hash)
+                        .await?;
+                    dry_run.record(
+                        &next_attributes,
+                        &self.engine_driver.safe_head,
+                        hash,
+                        matched,
+                    );
+                }
+                None => {
+                    let safe_head = self.engine_driver.safe_head;
+                    let result = self.engine_driver.handle_attributes(next_attributes).await;
+
+                    // attributes the engine did not apply are handled again by the next
+                    // iteration, unless derivation is reset or halted meanwhile
+                    if let Err(err) = result {
+                        if self.engine_driver.safe_head == safe_head {
+                            self.pipeline.retry(attributes);
+                        }
+                        return Err(err.wrap_err("failed to handle attributes"));
+                    }
+                }
+            }
 
-            self.engine_driver
-                .handle_attributes(next_attributes)
-                .await
-                .map_err(|e| eyre::eyre!("failed to handle attributes: {}", e))?;
+            // the unsafe blocks are replaced if the derived block differs from them
+            let safe_head = self.engine_driver.safe_head;
+            let replaced = unsafe_head.number >= safe_head.number
+                && unsafe_head != safe_head
+                && self.engine_driver.unsafe_head == safe_head;
+            if let Some(follower) = self.head_follower.as_mut().filter(|_| replaced) {
+                follower.diverged(safe_head);
+            }
 
             tracing::info!(
                 "safe head updated: {} {:?}",
@@ -208,22 +810,44 @@ impl<E: Engine> Driver<E> {
             let new_safe_head = self.engine_driver.safe_head;
             let new_safe_epoch = self.engine_driver.safe_epoch;
 
+            self.events.publish(NodeEvent::SafeHeadUpdated {
+                head: new_safe_head,
+                epoch: new_safe_epoch,
+            });
+
             self.state
                 .write()
                 .map_err(|_| eyre::eyre!("lock poisoned"))?
                 .update_safe_head(new_safe_head, new_safe_epoch);
 
-            let unfinalized_entry = (
+            self.finality.push(
                 new_safe_head,
                 new_safe_epoch,
-                l1_inclusion_block,
                 seq_number,
+                l1_inclusion_block,
             );
 
-            self.unfinalized_blocks.push(unfinalized_entry);
+            // the driver stops before its next iteration
+            if self.dry_run_done() {
+                tracing::info!("dry run reached block {}", new_safe_head.number);
+                break;
+            }
+
+            // the remaining attributes are applied by the next iteration
+            if http::Deadline::passed() {
+                break;
+            }
         }
 
-        Ok(())
+        self.engine_driver
+            .flush_forkchoice()
+            .await
+            .wrap_err("failed to update forkchoice")?;
+
+        match self.pipeline.error() {
+            Some(err) => Err(err.clone().into()),
+            None => Ok(()),
+        }
     }
 
     /// Collects unsafe blocks received via p2p gossip and updates the forkchoice with the first available unsafe block.
@@ -232,6 +856,9 @@ impl<E: Engine> Driver<E> {
             self.future_unsafe_blocks.push(payload);
         }
 
+        self.receive_synced_payloads();
+        self.receive_followed_payloads();
+
         self.future_unsafe_blocks.retain(|payload| {
             let unsafe_block_num = payload.block_number.as_u64();
             let synced_block_num = self.engine_driver.unsafe_head.number;
@@ -245,12 +872,136 @@ impl<E: Engine> Driver<E> {
             .find(|p| p.parent_hash == self.engine_driver.unsafe_head.hash);
 
         if let Some(payload) = next_unsafe_payload {
-            _ = self.engine_driver.handle_unsafe_payload(payload).await;
+            match self.interop.validate_payload(payload).await {
+                Ok(MessageValidity::Valid) => {
+                    if self
+                        .engine_driver
+                        .handle_unsafe_payload(payload)
+                        .await
+                        .is_ok()
+                    {
+                        if let Some(payloads) = &mut self.unsafe_payloads {
+                            if let Err(err) = payloads.insert(payload) {
+                                tracing::warn!("could not store unsafe payload: {}", err);
+                            }
+                        }
+                    }
+                }
+                Ok(MessageValidity::Invalid(_)) => {
+                    let invalid_hash = payload.block_hash;
+                    self.future_unsafe_blocks
+                        .retain(|p| p.block_hash != invalid_hash);
+                }
+                Err(err) => tracing::warn!("failed to validate unsafe block: {}", err),
+            }
+        } else {
+            self.request_missing_payload();
         }
 
         Ok(())
     }
 
+    /// Builds and publishes the next unsafe block, when running as the sequencer. Sealed
+    /// blocks are stored with the other applied unsafe payloads.
+    async fn sequence(&mut self) {
+        if let Some(sequencer) = &mut self.sequencer {
+            match sequencer.step(&mut self.engine_driver).await {
+                Ok(Some(payload)) => {
+                    if let Some(payloads) = &mut self.unsafe_payloads {
+                        if let Err(err) = payloads.insert(&payload) {
+                            tracing::warn!("could not store unsafe payload: {}", err);
+                        }
+                    }
+                }
+                Ok(None) => {}
+                Err(err) => tracing::warn!("failed to sequence block: {}", err),
+            }
+        }
+    }
+
+    /// Queues the payloads received from peers that are the parent of a queued unsafe block.
+    /// Payloads from peers are unsigned, so they are only trusted if a signed gossip block
+    /// builds on them.
+    fn receive_synced_payloads(&mut self) {
+        while let Ok(payload) = self.synced_payload_recv.try_recv() {
+            let is_parent = self
+                .future_unsafe_blocks
+                .iter()
+                .any(|p| p.parent_hash == payload.block_hash);
+
+            let is_queued = self
+                .future_unsafe_blocks
+                .iter()
+                .any(|p| p.block_hash == payload.block_hash);
+
+            if is_parent && !is_queued {
+                tracing::debug!("received block {} from peers", payload.block_number);
+                self.future_unsafe_blocks.push(payload);
+            }
+        }
+    }
+
+    /// Queues the blocks fetched from the trusted L2 RPC, if followed, and updates the
+    /// heads that decide whether following is paused
+    fn receive_followed_payloads(&mut self) {
+        let Some(follower) = &mut self.head_follower else {
+            return;
+        };
+
+        // the safe head is not derived in light-follow mode, so it cannot lag behind
+        let unsafe_head = self.engine_driver.unsafe_head;
+        let safe_head = match self.derivation.is_light_follow() {
+            true => unsafe_head,
+            false => self.engine_driver.safe_head,
+        };
+
+        follower.update_heads(unsafe_head, safe_head);
+        if follower.paused().is_some() {
+            return;
+        }
+
+        for payload in follower.receive() {
+            let is_queued = self
+                .future_unsafe_blocks
+                .iter()
+                .any(|p| p.block_hash == payload.block_hash);
+
+            if !is_queued {
+                self.future_unsafe_blocks.push(payload);
+            }
+        }
+    }
+
+    /// Requests the parent of the lowest queued unsafe block from peers, if the queued
+    /// blocks do not connect to the unsafe head
+    fn request_missing_payload(&mut self) {
+        let Some(sender) = &self.sync_request_sender else {
+            return;
+        };
+
+        let Some(lowest) = self
+            .future_unsafe_blocks
+            .iter()
+            .map(|p| p.block_number.as_u64())
+            .min()
+        else {
+            return;
+        };
+
+        let missing = lowest - 1;
+        if missing <= self.engine_driver.unsafe_head.number {
+            return;
+        }
+
+        let recently_requested = self
+            .last_sync_request
+            .is_some_and(|(number, at)| number == missing && at.elapsed() < SYNC_REQUEST_TIMEOUT);
+
+        if !recently_requested && sender.try_send(missing).is_ok() {
+            self.last_sync_request = Some((missing, Instant::now()));
+        }
+    }
+
     /// Updates the [State] `safe_head`
     fn update_state_head(&self) -> Result<()> {
         let mut state = self
@@ -263,9 +1014,11 @@ impl<E: Engine> Driver<E> {
         Ok(())
     }
 
-    /// Ingests the next update from the block update channel
-    async fn handle_next_block_update(&mut self) -> Result<()> {
+    /// Ingests the next update from the block update channel, returning false if there
+    /// was none
+    async fn handle_next_block_update(&mut self) -> Result<bool> {
         let next = self.chain_watcher.try_recv_from_channel();
+        let received = next.is_ok();
 
         if let Ok(update) = next {
             match update {
@@ -280,6 +1033,7 @@ impl<E: Engine> Driver<E> {
                         // cloning `bytes::Bytes` is cheap
                         l1_info.batcher_transactions.clone(),
                         num,
+                        l1_info.block_info.timestamp,
                     )?;
 
                     self.state
@@ -287,60 +1041,129 @@ impl<E: Engine> Driver<E> {
                         .map_err(|_| eyre::eyre!("lock poisoned"))?
                         .update_l1_info(*l1_info);
                 }
-                BlockUpdate::Reorg => {
-                    tracing::warn!("reorg detected, purging pipeline");
+                BlockUpdate::Reorg {
+                    old_head,
+                    new_head,
+                    depth,
+                } => {
+                    let ancestor = old_head.number.saturating_sub(depth);
+                    let target = if depth <= MAX_REWIND_DEPTH {
+                        self.finality.rewind(ancestor)
+                    } else {
+                        None
+                    };
+
+                    match target {
+                        Some((head, epoch)) => {
+                            tracing::warn!(
+                                "reorg of depth {} detected, rewinding to block {}",
+                                depth,
+                                head.number
+                            );
+
+                            self.reset_derivation(head, epoch)?;
+                            self.engine_driver.rewind(head, epoch);
+                        }
+                        None => {
+                            tracing::warn!("reorg of depth {} detected, purging pipeline", depth);
+
+                            self.finality.clear();
+                            self.reset_derivation(
+                                self.engine_driver.finalized_head,
+                                self.engine_driver.finalized_epoch,
+                            )?;
+                            self.engine_driver.reorg();
+                        }
+                    }
+
+                    self.events.publish(NodeEvent::Reorg {
+                        l1_old_head: old_head,
+                        l1_new_head: new_head,
+                        depth,
+                        rewound_to: self.engine_driver.safe_head,
+                    });
+                }
+                BlockUpdate::FinalityUpdate(block) => {
+                    self.finality.set_finalized_l1(block);
+                }
+            }
+        }
 
-                    self.unfinalized_blocks.clear();
+        Ok(received)
+    }
 
-                    let l1_start_block = get_l1_start_block(
-                        self.engine_driver.finalized_epoch.number,
-                        self.channel_timeout,
-                    );
+    /// Restarts derivation from the given safe head, re-reading L1 far enough back
+    /// to recover the channels of the following blocks
+    fn reset_derivation(&mut self, head: BlockInfo, epoch: Epoch) -> Result<()> {
+        let l1_start_block = get_l1_start_block(epoch, &self.chain);
+        self.chain_watcher.restart(l1_start_block, head.number)?;
 
-                    self.chain_watcher
-                        .restart(l1_start_block, self.engine_driver.finalized_head.number)?;
+        self.state
+            .write()
+            .map_err(|_| eyre::eyre!("lock poisoned"))?
+            .purge(head, epoch);
 
-                    self.state
-                        .write()
-                        .map_err(|_| eyre::eyre!("lock poisoned"))?
-                        .purge(
-                            self.engine_driver.finalized_head,
-                            self.engine_driver.finalized_epoch,
-                        );
+        self.pipeline.purge()
+    }
 
-                    self.pipeline.purge()?;
-                    self.engine_driver.reorg();
-                }
-                BlockUpdate::FinalityUpdate(num) => {
-                    self.finalized_l1_block_number = num;
-                }
-            }
+    /// Restarts derivation from the finalized head, dropping the unfinalized blocks
+    fn reset_to_finalized(&mut self) -> Result<()> {
+        let (head, epoch) = (
+            self.engine_driver.finalized_head,
+            self.engine_driver.finalized_epoch,
+        );
+
+        self.finality.clear();
+        self.reset_derivation(head, epoch)?;
+        self.engine_driver.reorg();
+
+        self.events
+            .publish(NodeEvent::SafeHeadUpdated { head, epoch });
+
+        Ok(())
+    }
+
+    /// Handles the derivation resets requested over the admin RPC
+    #[cfg(feature = "rpc")]
+    async fn handle_reset_requests(&mut self) -> Result<()> {
+        while let Ok(request) = self.reset_recv.try_recv() {
+            let result = self.force_reset(request.head).await;
+            _ = request.response.send(result.map_err(|err| err.to_string()));
         }
 
         Ok(())
     }
 
-    /// Updates the current finalized L2 block in the [EngineDriver] based on their inclusion in finalized L1 blocks
-    fn update_finalized(&mut self) {
-        let new_finalized = self
-            .unfinalized_blocks
-            .iter()
-            .filter(|(_, _, inclusion, seq)| {
-                *inclusion <= self.finalized_l1_block_number && *seq == 0
-            })
-            .last();
+    /// Forcefully rewinds the unsafe, safe and finalized heads to the given head and
+    /// restarts derivation from its L1 origin, dropping all unfinalized and future blocks
+    #[cfg(feature = "rpc")]
+    async fn force_reset(&mut self, head: HeadInfo) -> Result<()> {
+        let (block, epoch) = (head.l2_block_info, head.l1_epoch);
+        tracing::warn!("resetting derivation to block {}", block.number);
 
-        if let Some((head, epoch, _, _)) = new_finalized {
-            self.engine_driver.update_finalized(*head, *epoch);
-        }
+        self.finality.clear();
+        self.future_unsafe_blocks.clear();
+        self.reset_derivation(block, epoch)?;
+        self.engine_driver.reset(block, epoch).await?;
+
+        self.events
+            .publish(NodeEvent::SafeHeadUpdated { head: block, epoch });
 
-        self.unfinalized_blocks
-            .retain(|(_, _, inclusion, _)| *inclusion > self.finalized_l1_block_number);
+        Ok(())
+    }
+
+    /// Updates the current finalized L2 block in the [EngineDriver] once all the L1 blocks
+    /// it was derived from are finalized
+    fn update_finalized(&mut self) {
+        if let Some((head, epoch)) = self.finality.finalize() {
+            self.engine_driver.update_finalized(head, epoch);
+        }
     }
 
-    /// Begins p2p networking if fully synced with no unfinalized blocks
+    /// Begins p2p networking if fully synced with no unfinalized blocks, or right away when
+    /// running as the sequencer
     fn try_start_networking(&mut self) -> Result<()> {
-        if self.synced() {
+        if self.synced() || self.sequencer.is_some() {
             if let Some(service) = self.network_service.take() {
                 service.start()?;
             }
@@ -351,21 +1174,85 @@ impl<E: Engine> Driver<E> {
 
     /// Updates Prometheus metrics
     fn update_metrics(&self) {
-        metrics::FINALIZED_HEAD.set(self.engine_driver.finalized_head.number as i64);
-        metrics::SAFE_HEAD.set(self.engine_driver.safe_head.number as i64);
-        metrics::SYNCED.set(self.synced() as i64);
+        let chain = [self.chain_label.as_str()];
+
+        metrics::FINALIZED_HEAD
+            .with_label_values(&chain)
+            .set(self.engine_driver.finalized_head.number as i64);
+        metrics::SAFE_HEAD
+            .with_label_values(&chain)
+            .set(self.engine_driver.safe_head.number as i64);
+        metrics::SYNCED
+            .with_label_values(&chain)
+            .set(self.synced() as i64);
+        metrics::L1_PENDING_BLOCKS
+            .with_label_values(&chain)
+            .set(self.chain_watcher.pending_updates() as i64);
+
+        self.progress.update(Diagnostics {
+            safe_head: self.engine_driver.safe_head.number,
+            unsafe_head: self.engine_driver.unsafe_head.number,
+            pending_l1_blocks: self.chain_watcher.pending_updates(),
+            future_unsafe_blocks: self.future_unsafe_blocks.len(),
+        });
+    }
+
+    /// Updates the [SyncStatus] served over RPC
+    #[cfg(feature = "rpc")]
+    fn update_sync_status(&self) -> Result<()> {
+        let mut status = self
+            .sync_status
+            .write()
+            .map_err(|_| eyre::eyre!("lock poisoned"))?;
+
+        let state = self
+            .state
+            .read()
+            .map_err(|_| eyre::eyre!("lock poisoned"))?;
+        status.current_l1 = state
+            .l1_info_by_number(state.current_epoch_num)
+            .map(|info| L1BlockRef::from(&info.block_info))
+            .unwrap_or_default();
+        status.finalized_l1 = self.finality.finalized_l1().into();
+        status.unsafe_l2 = self.engine_driver.unsafe_head;
+        status.safe_l2 = self.engine_driver.safe_head;
+        status.finalized_l2 = self.engine_driver.finalized_head;
+        status.supported_protocol_version = SUPPORTED_PROTOCOL_VERSION;
+        status.protocol_versions = self.protocol_versions.latest();
+        status.protocol_version_support = status
+            .protocol_versions
+            .map(|signal| signal.check(&SUPPORTED_PROTOCOL_VERSION));
+        if status.l1_watcher_restarts.count != self.chain_watcher.restarts().count {
+            status.l1_watcher_restarts = self.chain_watcher.restarts().clone();
+        }
+        if status.derivation_errors != self.derivation_errors {
+            status.derivation_errors = self.derivation_errors.clone();
+        }
+
+        let mut forced_inclusion = self
+            .forced_inclusion
+            .write()
+            .map_err(|_| eyre::eyre!("lock poisoned"))?;
+
+        if forced_inclusion.deposit_only_blocks != state.forced_inclusion.deposit_only_blocks {
+            *forced_inclusion = state.forced_inclusion.clone();
+        }
+
+        Ok(())
     }
 
     /// True if there are no unfinalized blocks
     fn synced(&self) -> bool {
-        !self.unfinalized_blocks.is_empty()
+        !self.finality.is_empty()
     }
 }
 
 /// Retrieves the L1 start block number.
 /// If an overflow occurs during subtraction, the function returns the genesis block #0.
-fn get_l1_start_block(epoch_number: u64, channel_timeout: u64) -> u64 {
-    epoch_number.saturating_sub(channel_timeout)
+pub(crate) fn get_l1_start_block(epoch: Epoch, chain: &ChainConfig) -> u64 {
+    epoch
+        .number
+        .saturating_sub(chain.max_channel_timeout(epoch.timestamp))
 }
 
 #[cfg(test)]
@@ -373,16 +1260,33 @@ mod tests {
     use std::{path::PathBuf, str::FromStr};
 
     use ethers::{
-        providers::{Http, Middleware},
+        providers::{Http, Middleware, Provider},
         types::{BlockId, BlockNumber},
     };
     use eyre::Result;
     use tokio::sync::watch::channel;
 
-    use crate::config::{ChainConfig, CliConfig};
+    use crate::config::CliConfig;
 
     use super::*;
 
+    #[test]
+    fn test_l1_start_block() {
+        let mut chain = ChainConfig::optimism_sepolia();
+        chain.channel_timeout = 300;
+        chain.hardforks.granite_time = Some(1000);
+
+        let epoch = |number, timestamp| Epoch {
+            number,
+            timestamp,
+            ..Default::default()
+        };
+
+        assert_eq!(get_l1_start_block(epoch(1000, 999), &chain), 700);
+        assert_eq!(get_l1_start_block(epoch(1000, 1000), &chain), 950);
+        assert_eq!(get_l1_start_block(epoch(10, 1000), &chain), 0);
+    }
+
     #[tokio::test]
     async fn test_new_driver_from_finalized_head() -> Result<()> {
         if std::env::var("L1_TEST_RPC_URL").is_ok() && std::env::var("L2_TEST_RPC_URL").is_ok() {
@@ -394,6 +1298,7 @@ mod tests {
                 l1_beacon_url: None,
                 l2_rpc_url: Some(l2_rpc.to_owned()),
                 l2_engine_url: None,
+                l2_engine_standby_urls: None,
                 jwt_secret: Some(
                     "d195a64e08587a3f1560686448867220c2727550ce3e0c95c7200d0ade0f9167".to_owned(),
                 ),
@@ -401,6 +1306,52 @@ mod tests {
                 rpc_port: None,
                 rpc_addr: None,
                 devnet: false,
+                supervisor_rpc_url: None,
+                p2p_port: None,
+                forkchoice_batch_size: None,
+                forkchoice_batch_interval_ms: None,
+                jwt_secret_file: None,
+                l1_rpc_budget: None,
+                data_dir: None,
+                l1_epoch_retention: None,
+                l2_block_retention: None,
+                p2p_sync: None,
+                p2p_max_inbound_peers: None,
+                p2p_max_outbound_peers: None,
+                p2p_static_peers: None,
+                p2p_bootnodes: None,
+                sequencer: None,
+                p2p_sequencer_key: None,
+                p2p_sequencer_keystore: None,
+                p2p_sequencer_keystore_password_file: None,
+                p2p_sequencer_signer_url: None,
+                p2p_sequencer_signer_backup_url: None,
+                p2p_sequencer_address: None,
+                profile_derivation: None,
+                unsafe_follow_url: None,
+                unsafe_follow_max_lag: None,
+                readiness_max_head_lag: None,
+                readiness_max_stall: None,
+                rpc_api: None,
+                rpc_auth_token: None,
+                rpc_jwt_secret: None,
+                rpc_cors_origins: None,
+                rpc_rate_limit: None,
+                l1_block_buffer: None,
+                light_follow: None,
+                derivation_lookahead: None,
+                sequencer_gas_limit: None,
+                rpc_precompute_outputs: None,
+                watchdog_stall_timeout: None,
+                watchdog_action: None,
+                rpc_sequencer_url: None,
+                rpc_sequencer_rate_limit: None,
+                driver_tick_timeout: None,
+                dry_run: None,
+                dry_run_until: None,
+                unsafe_payload_retention: None,
+                derivation_step_frames: None,
+                halt_on_unsupported_fork: None,
             };
             let config = Config::new(&config_path, cli_config, ChainConfig::optimism_sepolia());
             let (_shutdown_sender, shutdown_recv) = channel(false);
@@ -0,0 +1,89 @@
+use std::sync::RwLock;
+
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+
+/// How the driver advances the safe head
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DerivationMode {
+    /// Derive the safe head from L1
+    #[default]
+    Full,
+    /// Trust the unsafe heads from gossip and the trusted L2 RPC, and pause L1 derivation.
+    /// Unsafe blocks are promoted to safe and finalized once their outputs are proposed on L1.
+    LightFollow,
+}
+
+/// Admin control switching the [DerivationMode] at runtime, so a node can stop deriving
+/// during L1 fee spikes and catch up later without restarting
+#[derive(Debug, Default)]
+pub struct DerivationControl {
+    /// The current mode
+    mode: RwLock<DerivationMode>,
+    /// Whether the node runs as the sequencer, which must always derive
+    sequencer: bool,
+}
+
+impl DerivationControl {
+    /// Creates a new [DerivationControl] starting in the given mode
+    pub fn new(mode: DerivationMode, sequencer: bool) -> Result<Self> {
+        let control = Self {
+            mode: RwLock::new(DerivationMode::Full),
+            sequencer,
+        };
+
+        control.set_mode(mode)?;
+        Ok(control)
+    }
+
+    /// Returns the current mode
+    pub fn mode(&self) -> DerivationMode {
+        *self.mode.read().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Switches to the given mode. The sequencer cannot switch to light-follow.
+    pub fn set_mode(&self, mode: DerivationMode) -> Result<()> {
+        if mode == DerivationMode::LightFollow && self.sequencer {
+            eyre::bail!("the sequencer cannot run in light-follow mode");
+        }
+
+        let mut current = self.mode.write().unwrap_or_else(|err| err.into_inner());
+        if *current != mode {
+            tracing::info!("switching derivation mode to {:?}", mode);
+            *current = mode;
+        }
+
+        Ok(())
+    }
+
+    /// Returns true if L1 derivation is paused
+    pub fn is_light_follow(&self) -> bool {
+        self.mode() == DerivationMode::LightFollow
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_switch_mode() -> Result<()> {
+        let control = DerivationControl::new(DerivationMode::LightFollow, false)?;
+        assert!(control.is_light_follow());
+
+        control.set_mode(DerivationMode::Full)?;
+        assert_eq!(control.mode(), DerivationMode::Full);
+
+        let sequencer = DerivationControl::new(DerivationMode::Full, true)?;
+        assert!(sequencer.set_mode(DerivationMode::LightFollow).is_err());
+        assert!(DerivationControl::new(DerivationMode::LightFollow, true).is_err());
+
+        assert_eq!(
+            serde_json::to_string(&DerivationMode::LightFollow)?,
+            "\"lightFollow\""
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,179 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use ethers::{
+    providers::{Http, Middleware, Provider},
+    types::{Address, BlockId, BlockNumber},
+};
+use eyre::Result;
+use tokio::{sync::mpsc, time::sleep};
+
+use crate::{
+    common::{compute_l2_output_root, http, HeadInfo},
+    config::Config,
+    l1::{OutputSource, ProposedOutput, SharedL1},
+};
+
+/// How often the outputs proposed on L1 are polled
+const POLL_INTERVAL: Duration = Duration::from_secs(12);
+
+/// An unsafe block whose output root matches an output proposed on L1
+#[derive(Debug, Clone)]
+pub struct VerifiedOutput {
+    /// The verified block
+    pub head: HeadInfo,
+    /// Whether the output was proposed in a finalized L1 block
+    pub finalized: bool,
+}
+
+/// Follows the outputs proposed on L1 while L1 derivation is paused in light-follow mode,
+/// so the unsafe blocks they commit to can be promoted to safe and finalized.
+///
+/// The latest output proposed as of the latest L1 block makes its block safe, and the
+/// latest output proposed as of the finalized L1 block makes its block finalized: the
+/// proposer only proposes derived blocks, whose batches are at or before the proposal on
+/// L1, so they are finalized once the proposal is. Outputs are the root claims of the
+/// latest dispute games not won by the challenger, or the outputs of the
+/// `L2OutputOracle` before fault proofs.
+pub struct OutputFollower {
+    /// Channel receiving the verified outputs from the polling task
+    output_recv: mpsc::Receiver<VerifiedOutput>,
+    /// The unsafe head number shared with the polling task, which only verifies outputs up to it
+    unsafe_head: Arc<AtomicU64>,
+    /// Whether the polling task polls, only while in light-follow mode
+    active: Arc<AtomicBool>,
+}
+
+impl OutputFollower {
+    /// Starts polling the outputs proposed on L1 and verifying them against the blocks of
+    /// the local L2 execution RPC
+    pub fn start(config: Arc<Config>, active: bool) -> Result<Self> {
+        let l2_provider = http::provider(&config.l2_rpc_url, http::L2_TIMEOUT)?;
+        let (sender, output_recv) = mpsc::channel(2);
+        let unsafe_head = Arc::new(AtomicU64::new(0));
+        let active = Arc::new(AtomicBool::new(active));
+
+        let poll_head = unsafe_head.clone();
+        let poll_active = active.clone();
+        tokio::spawn(async move {
+            let l1 = SharedL1::get(&config);
+            let mut source = None;
+            let mut verified = [None; 2];
+
+            loop {
+                sleep(POLL_INTERVAL).await;
+
+                if sender.is_closed() {
+                    break;
+                }
+
+                if !poll_active.load(Ordering::Relaxed) {
+                    continue;
+                }
+
+                let source = match source {
+                    Some(source) => source,
+                    None => {
+                        let contract =
+                            Address::from_slice(config.chain.system_config_contract.as_slice());
+                        match OutputSource::fetch(l1.provider.as_ref(), contract).await {
+                            Ok(fetched) => *source.insert(fetched),
+                            Err(err) => {
+                                tracing::warn!("failed to find the L1 output source: {}", err);
+                                continue;
+                            }
+                        }
+                    }
+                };
+
+                let head = poll_head.load(Ordering::Relaxed);
+                for (index, (block, finalized)) in
+                    [(BlockNumber::Latest, false), (BlockNumber::Finalized, true)]
+                        .into_iter()
+                        .enumerate()
+                {
+                    let output = match source.latest_output(l1.provider.as_ref(), block).await {
+                        Ok(Some(output)) => output,
+                        Ok(None) => continue,
+                        Err(err) => {
+                            tracing::warn!("failed to read the outputs proposed on L1: {}", err);
+                            continue;
+                        }
+                    };
+
+                    if output.l2_block_number > head || verified[index] == Some(output) {
+                        continue;
+                    }
+
+                    match verify(&config, &l2_provider, output).await {
+                        Ok(Some(head)) => {
+                            verified[index] = Some(output);
+                            _ = sender.send(VerifiedOutput { head, finalized }).await;
+                        }
+                        Ok(None) => tracing::warn!(
+                            "output proposed for block {} differs from the local block",
+                            output.l2_block_number
+                        ),
+                        Err(err) => tracing::warn!(
+                            "failed to verify the output of block {}: {}",
+                            output.l2_block_number,
+                            err
+                        ),
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            output_recv,
+            unsafe_head,
+            active,
+        })
+    }
+
+    /// Returns the outputs verified since the previous call
+    pub fn receive(&mut self) -> Vec<VerifiedOutput> {
+        let mut outputs = Vec::new();
+        while let Ok(output) = self.output_recv.try_recv() {
+            outputs.push(output);
+        }
+
+        outputs
+    }
+
+    /// Updates the unsafe head number, and whether outputs are polled
+    pub fn update(&self, unsafe_head: u64, active: bool) {
+        self.unsafe_head.store(unsafe_head, Ordering::Relaxed);
+        self.active.store(active, Ordering::Relaxed);
+    }
+}
+
+/// Computes the output root of the local block at the height of the proposed output, and
+/// returns its head info if it matches the proposed root
+async fn verify(
+    config: &Config,
+    provider: &Provider<Http>,
+    output: ProposedOutput,
+) -> Result<Option<HeadInfo>> {
+    let block = provider
+        .get_block_with_txs(output.l2_block_number)
+        .await?
+        .ok_or(eyre::eyre!("block not found"))?;
+    let block_hash = block.hash.ok_or(eyre::eyre!("block hash not found"))?;
+
+    let message_passer = Address::from_slice(config.chain.l2_to_l1_message_passer.as_slice());
+    let proof = provider
+        .get_proof(message_passer, vec![], Some(BlockId::from(block_hash)))
+        .await?;
+
+    if compute_l2_output_root(block.clone(), proof.storage_hash) != output.output_root {
+        return Ok(None);
+    }
+
+    Ok(Some(HeadInfo::try_from_l2_block(config, block)?))
+}
@@ -0,0 +1,434 @@
+use std::{
+    sync::{Arc, RwLock},
+    time::SystemTime,
+};
+
+use ethers::{
+    providers::{Http, Middleware, Provider},
+    types::{BlockNumber, H256},
+};
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::{
+    common::{BlockInfo, Epoch},
+    config::Config,
+    derive::{stages::attributes::sequencer_attributes, state::State},
+    engine::{Engine, ExecutionPayload, PayloadId, PayloadVersion},
+    network::handlers::block_handler::{SignedBlock, UnsignedBlock},
+    signer::Signer,
+};
+
+use super::{engine_driver::EngineDriver, HeadInfo};
+
+/// Builds a block on top of the unsafe head every block time when running as the
+/// sequencer, and publishes it signed on the blocks gossip topic.
+///
+/// A block is started as soon as its parent is the unsafe head, so the engine fills it
+/// from its mempool for a block time, and sealed once its timestamp is reached.
+pub struct Sequencer {
+    /// The global Magi [Config]
+    config: Arc<Config>,
+    /// Provider for the local L2 execution RPC
+    provider: Provider<Http>,
+    /// The derivation [State] holding the L1 origins
+    state: Arc<RwLock<State>>,
+    /// Signs the published blocks
+    signer: Arc<dyn Signer>,
+    /// Channel to publish the signed blocks on
+    block_sender: mpsc::Sender<SignedBlock>,
+    /// The unsafe head with its L1 origin and sequence number
+    head: Option<HeadInfo>,
+    /// The block being built, if any
+    building: Option<PendingBlock>,
+    /// Admin controls stopping the sequencer, shared with the RPC server
+    control: Arc<SequencerControl>,
+}
+
+/// The block after which a scheduled sequencer stop takes effect
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum StopTarget {
+    /// The last block to build
+    BlockNumber(u64),
+    /// The timestamp of the last block to build
+    Timestamp(u64),
+}
+
+impl StopTarget {
+    /// Returns true if a block is past the target, so it must not be built
+    fn is_passed_by(&self, number: u64, timestamp: u64) -> bool {
+        match self {
+            StopTarget::BlockNumber(target) => number > *target,
+            StopTarget::Timestamp(target) => timestamp > *target,
+        }
+    }
+}
+
+/// The admin controlled state of the sequencer
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SequencerStatus {
+    /// Whether the sequencer stopped building blocks. The node keeps gossiping and
+    /// serving blocks while draining.
+    pub draining: bool,
+    /// The scheduled stop, if any
+    pub stop_at: Option<StopTarget>,
+    /// The unsafe head the sequencer builds on
+    pub head: Option<BlockInfo>,
+}
+
+/// Admin controls of the [Sequencer], to stop building blocks immediately or after a given
+/// block for sequencer handoffs and maintenance windows
+#[derive(Debug, Default)]
+pub struct SequencerControl {
+    /// The current status
+    status: RwLock<SequencerStatus>,
+}
+
+impl SequencerControl {
+    /// Returns the current status
+    pub fn status(&self) -> SequencerStatus {
+        self.read().clone()
+    }
+
+    /// Stops building blocks. The block being built, if any, is still sealed and published.
+    pub fn drain(&self) {
+        let mut status = self.write();
+        status.draining = true;
+        status.stop_at = None;
+        tracing::info!("sequencer draining");
+    }
+
+    /// Schedules a stop after the given block. Fails if the unsafe head is already past it.
+    pub fn stop_at(&self, target: StopTarget) -> Result<()> {
+        let mut status = self.write();
+        if let Some(head) = status.head {
+            if target.is_passed_by(head.number + 1, head.timestamp) {
+                eyre::bail!("the unsafe head {} is already past the target", head.number);
+            }
+        }
+
+        tracing::info!("sequencer stop scheduled after {:?}", target);
+        status.stop_at = Some(target);
+        Ok(())
+    }
+
+    /// Resumes building blocks and cancels the scheduled stop
+    pub fn start(&self) {
+        let mut status = self.write();
+        status.draining = false;
+        status.stop_at = None;
+        tracing::info!("sequencer started");
+    }
+
+    /// Records the unsafe head and returns true if the block following it must not be
+    /// built, draining the sequencer once a scheduled stop is reached
+    fn should_drain(&self, head: BlockInfo, timestamp: u64) -> bool {
+        let mut status = self.write();
+        status.head = Some(head);
+
+        let stop_reached = status
+            .stop_at
+            .is_some_and(|target| target.is_passed_by(head.number + 1, timestamp));
+
+        if stop_reached {
+            tracing::info!("sequencer stopped after block {}", head.number);
+            status.draining = true;
+            status.stop_at = None;
+        }
+
+        status.draining
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, SequencerStatus> {
+        self.status.read().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, SequencerStatus> {
+        self.status.write().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// A block being built by the engine
+struct PendingBlock {
+    /// The payload id returned by the engine
+    id: PayloadId,
+    /// Hash of the parent block
+    parent_hash: H256,
+    /// The L1 origin of the block
+    epoch: Epoch,
+    /// The parent beacon block root of the L1 origin
+    parent_beacon_block_root: Option<H256>,
+    /// Position of the block in its epoch
+    seq: u64,
+    /// Timestamp of the block
+    timestamp: u64,
+}
+
+impl Sequencer {
+    /// Creates a new [Sequencer] signing blocks with the given [Signer]
+    pub fn new(
+        config: Arc<Config>,
+        provider: Provider<Http>,
+        state: Arc<RwLock<State>>,
+        signer: Arc<dyn Signer>,
+        block_sender: mpsc::Sender<SignedBlock>,
+    ) -> Self {
+        Self {
+            config,
+            provider,
+            state,
+            signer,
+            block_sender,
+            head: None,
+            building: None,
+            control: Arc::new(SequencerControl::default()),
+        }
+    }
+
+    /// Returns the admin controls of the sequencer
+    #[cfg(feature = "rpc")]
+    pub fn control(&self) -> Arc<SequencerControl> {
+        self.control.clone()
+    }
+
+    /// Moves the unsafe head of the engine to the latest block of the execution client, so
+    /// the blocks sequenced before a restart are built upon instead of replaced
+    pub async fn resume<E: Engine>(&mut self, engine: &mut EngineDriver<E>) -> Result<()> {
+        let Some(block) = self
+            .provider
+            .get_block_with_txs(BlockNumber::Latest)
+            .await?
+        else {
+            return Ok(());
+        };
+
+        let head = HeadInfo::try_from_l2_block(&self.config, block)?;
+        if head.l2_block_info.number > engine.unsafe_head.number {
+            tracing::info!(
+                "resuming sequencing from block {}",
+                head.l2_block_info.number
+            );
+
+            engine.unsafe_head = head.l2_block_info;
+            self.head = Some(head);
+        }
+
+        Ok(())
+    }
+
+    /// Seals the block being built once its timestamp is reached, or starts building the
+    /// next block on top of the unsafe head. Returns the payload of a sealed block.
+    pub async fn step<E: Engine>(
+        &mut self,
+        engine: &mut EngineDriver<E>,
+    ) -> Result<Option<ExecutionPayload>> {
+        match self.building.take() {
+            Some(pending) if pending.parent_hash != engine.unsafe_head.hash => {
+                tracing::debug!("unsafe head changed, dropping block {}", pending.timestamp);
+                Ok(None)
+            }
+            Some(pending) if now() < pending.timestamp => {
+                self.building = Some(pending);
+                Ok(None)
+            }
+            Some(pending) => self.seal(engine, pending).await.map(Some),
+            None => self.start(engine).await.map(|_| None),
+        }
+    }
+
+    /// Starts building the block following the unsafe head, if its L1 origin is known and
+    /// the sequencer is not draining
+    async fn start<E: Engine>(&mut self, engine: &mut EngineDriver<E>) -> Result<()> {
+        let head = self.head_info(engine).await?;
+        let timestamp = head.l2_block_info.timestamp + self.config.chain.blocktime;
+
+        if self.control.should_drain(head.l2_block_info, timestamp) {
+            return Ok(());
+        }
+
+        // the block is built during the block time before its timestamp
+        if timestamp > now() + self.config.chain.blocktime {
+            return Ok(());
+        }
+
+        let (attributes, parent_beacon_block_root) = {
+            let state = self
+                .state
+                .read()
+                .map_err(|_| eyre::eyre!("lock poisoned"))?;
+
+            let Some(current) = state.l1_info_by_hash(head.l1_epoch.hash) else {
+                tracing::debug!("waiting for L1 origin {}", head.l1_epoch.number);
+                return Ok(());
+            };
+
+            let next = state
+                .l1_info_by_number(current.block_info.number + 1)
+                .filter(|next| timestamp >= next.block_info.timestamp);
+
+            let max_drift = self
+                .config
+                .chain
+                .max_sequencer_drift(current.block_info.timestamp);
+            let drift_exceeded = timestamp > current.block_info.timestamp + max_drift;
+
+            let (origin, seq) = match next {
+                Some(next) => (next, 0),
+                None if drift_exceeded => {
+                    tracing::warn!("sequencer drift exceeded, waiting for the next L1 block");
+                    return Ok(());
+                }
+                None => (current, head.sequence_number + 1),
+            };
+
+            let attributes =
+                sequencer_attributes(origin, seq, timestamp, &self.config, &state.overrides)?;
+            (attributes, origin.block_info.parent_beacon_block_root)
+        };
+
+        let epoch = attributes
+            .epoch
+            .ok_or(eyre::eyre!("attributes without epoch"))?;
+        let seq = attributes.seq_number.unwrap_or_default();
+
+        let id = engine.start_payload(attributes).await?;
+
+        self.building = Some(PendingBlock {
+            id,
+            parent_hash: head.l2_block_info.hash,
+            epoch,
+            parent_beacon_block_root,
+            seq,
+            timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Retrieves a built block, applies it as the unsafe head and publishes it
+    async fn seal<E: Engine>(
+        &mut self,
+        engine: &mut EngineDriver<E>,
+        pending: PendingBlock,
+    ) -> Result<ExecutionPayload> {
+        let payload = engine.get_payload(pending.id).await?;
+        engine.handle_unsafe_payload(&payload).await?;
+
+        tracing::info!(
+            "sequenced block {} with {} transactions",
+            payload.block_number,
+            payload.transactions.len()
+        );
+
+        self.head = Some(HeadInfo {
+            l2_block_info: (&payload).into(),
+            l1_epoch: pending.epoch,
+            sequence_number: pending.seq,
+        });
+
+        if let Err(err) = self
+            .publish(&payload, pending.parent_beacon_block_root)
+            .await
+        {
+            tracing::warn!("failed to publish block {}: {}", payload.block_number, err);
+        }
+
+        Ok(payload)
+    }
+
+    /// Signs a block and hands it to the network service for gossip
+    async fn publish(
+        &self,
+        payload: &ExecutionPayload,
+        parent_beacon_block_root: Option<H256>,
+    ) -> Result<()> {
+        let block = UnsignedBlock::new(
+            self.config.chain.l2_chain_id,
+            payload,
+            parent_beacon_block_root,
+            PayloadVersion::at(&self.config.chain.hardforks, payload.timestamp.as_u64()),
+        )?;
+
+        let signature = self.signer.sign(&block.signature_data()).await?;
+        self.block_sender.try_send(block.sign(&signature)?)?;
+
+        Ok(())
+    }
+
+    /// Returns the unsafe head with its L1 origin, fetching it if it was not sequenced here
+    async fn head_info<E: Engine>(&mut self, engine: &EngineDriver<E>) -> Result<HeadInfo> {
+        let unsafe_head = engine.unsafe_head;
+
+        let known = self.head.as_ref();
+        if let Some(head) = known.filter(|head| head.l2_block_info == unsafe_head) {
+            return Ok(head.clone());
+        }
+
+        let block = self
+            .provider
+            .get_block_with_txs(unsafe_head.hash)
+            .await?
+            .ok_or(eyre::eyre!("unsafe head {} not found", unsafe_head.number))?;
+
+        let head = HeadInfo::try_from_l2_block(&self.config, block)?;
+        self.head = Some(head.clone());
+
+        Ok(head)
+    }
+}
+
+/// Returns the current unix timestamp
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(number: u64) -> BlockInfo {
+        BlockInfo {
+            number,
+            timestamp: number * 2,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_sequencer_control() {
+        let control = SequencerControl::default();
+        assert!(!control.should_drain(block(10), 22));
+
+        // the stop takes effect once the target block is built
+        control.stop_at(StopTarget::BlockNumber(11)).unwrap();
+        assert!(!control.should_drain(block(10), 22));
+        assert!(control.should_drain(block(11), 24));
+        assert_eq!(
+            control.status(),
+            SequencerStatus {
+                draining: true,
+                stop_at: None,
+                head: Some(block(11)),
+            }
+        );
+
+        control.start();
+        assert!(!control.should_drain(block(11), 24));
+
+        // targets the unsafe head is already past are rejected
+        assert!(control.stop_at(StopTarget::Timestamp(20)).is_err());
+        control.stop_at(StopTarget::Timestamp(24)).unwrap();
+        assert!(!control.should_drain(block(11), 24));
+        assert!(control.should_drain(block(12), 26));
+
+        control.start();
+        control.drain();
+        assert!(control.should_drain(block(12), 26));
+    }
+}
@@ -0,0 +1,313 @@
+use std::{
+    collections::HashSet,
+    process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config::{Config, WatchdogAction},
+    l1::supervision::Heartbeat,
+    telemetry::metrics,
+};
+
+/// Exit code of the node when the watchdog stops it, so orchestrators can tell a stall from a
+/// crash
+pub const WATCHDOG_EXIT_CODE: i32 = 3;
+
+/// Interval of the watchdog checks
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A driver component monitored by the [Watchdog]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Component {
+    /// The task fetching L1 blocks
+    L1Watcher,
+    /// The driver loop deriving and applying blocks
+    Derivation,
+    /// The requests to the execution engine
+    Engine,
+}
+
+impl Component {
+    /// Returns the metric label of the component
+    pub fn label(&self) -> &'static str {
+        match self {
+            Component::L1Watcher => "l1_watcher",
+            Component::Derivation => "derivation",
+            Component::Engine => "engine",
+        }
+    }
+}
+
+/// The engine request in flight, to tell a hung engine from a stalled driver loop
+#[derive(Debug, Clone, Default)]
+pub struct EngineActivity {
+    /// The method of the request in flight and when it was sent
+    in_flight: Arc<Mutex<Option<(&'static str, Instant)>>>,
+}
+
+/// A request to the engine, in flight until dropped
+pub struct EngineCall {
+    activity: EngineActivity,
+}
+
+impl EngineActivity {
+    /// Records a request to the engine until the returned [EngineCall] is dropped
+    pub fn start(&self, method: &'static str) -> EngineCall {
+        *self.lock() = Some((method, Instant::now()));
+        EngineCall {
+            activity: self.clone(),
+        }
+    }
+
+    /// Returns the method of the request in flight and for how long it has been
+    pub fn in_flight(&self) -> Option<(&'static str, Duration)> {
+        self.lock()
+            .map(|(method, started)| (method, started.elapsed()))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<(&'static str, Instant)>> {
+        self.in_flight.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl Drop for EngineCall {
+    fn drop(&mut self) {
+        *self.activity.lock() = None;
+    }
+}
+
+/// The driver state included in the diagnostic dump of a stall
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Diagnostics {
+    /// Number of the safe head
+    pub safe_head: u64,
+    /// Number of the unsafe head
+    pub unsafe_head: u64,
+    /// Number of fetched L1 blocks waiting for derivation
+    pub pending_l1_blocks: usize,
+    /// Number of unsafe blocks waiting for their parent
+    pub future_unsafe_blocks: usize,
+}
+
+/// Progress of the driver components, updated by the driver and checked by the [Watchdog]
+#[derive(Debug, Clone, Default)]
+pub struct Progress {
+    /// Beaten by every iteration of the driver loop and every derived block
+    pub derivation: Heartbeat,
+    /// The engine request in flight
+    pub engine: EngineActivity,
+    /// The latest driver state
+    diagnostics: Arc<RwLock<Diagnostics>>,
+    /// Set by the watchdog to have the driver restart the L1 watcher
+    restart_l1_watcher: Arc<AtomicBool>,
+}
+
+impl Progress {
+    /// Creates the progress of a driver sending its engine requests through `engine`
+    pub fn new(engine: EngineActivity) -> Self {
+        Self {
+            engine,
+            ..Default::default()
+        }
+    }
+
+    /// Records the latest driver state
+    pub fn update(&self, diagnostics: Diagnostics) {
+        *self
+            .diagnostics
+            .write()
+            .unwrap_or_else(|err| err.into_inner()) = diagnostics;
+    }
+
+    /// Returns true once if the watchdog requested a restart of the L1 watcher
+    pub fn take_l1_watcher_restart(&self) -> bool {
+        self.restart_l1_watcher.swap(false, Ordering::Relaxed)
+    }
+
+    fn diagnostics(&self) -> Diagnostics {
+        *self
+            .diagnostics
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// Reports the driver components without progress for longer than the stall timeout.
+///
+/// A stall is logged with a diagnostic dump and counted once until the component recovers.
+/// Depending on the [WatchdogAction], the L1 watcher is then restarted by the driver, and the
+/// node exits with [WATCHDOG_EXIT_CODE]. The driver loop and the engine requests run on the
+/// driver task and cannot be restarted in place, so their stalls exit with the restart action.
+#[derive(Debug)]
+pub struct Watchdog {
+    /// Heartbeat of the L1 watcher task
+    l1_watcher: Heartbeat,
+    /// Progress of the other components
+    progress: Progress,
+    /// Time without progress after which a component is stalled
+    stall_timeout: Duration,
+    /// What to do once a component stalls
+    action: WatchdogAction,
+    /// The L2 chain id used to label metrics
+    chain_label: String,
+    /// The components reported stalled that have not recovered yet
+    stalled: HashSet<Component>,
+}
+
+impl Watchdog {
+    /// Creates a new [Watchdog] from the config
+    pub fn new(config: &Config, l1_watcher: Heartbeat, progress: Progress) -> Self {
+        Self {
+            l1_watcher,
+            progress,
+            stall_timeout: Duration::from_secs(config.watchdog_stall_timeout),
+            action: config.watchdog_action,
+            chain_label: config.chain.l2_chain_id.to_string(),
+            stalled: HashSet::new(),
+        }
+    }
+
+    /// Spawns the watchdog task, unless the stall timeout is zero
+    pub fn spawn(mut self) {
+        if self.stall_timeout.is_zero() {
+            return;
+        }
+
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(CHECK_INTERVAL).await;
+                self.check();
+            }
+        });
+    }
+
+    /// Reports the components that stalled since the previous check
+    fn check(&mut self) {
+        let stalls = self.stalls();
+        self.stalled
+            .retain(|component| stalls.iter().any(|(stalled, _)| stalled == component));
+
+        let new_stalls = stalls
+            .into_iter()
+            .filter(|(component, _)| self.stalled.insert(*component))
+            .collect::<Vec<_>>();
+
+        if new_stalls.is_empty() {
+            return;
+        }
+
+        for (component, elapsed) in &new_stalls {
+            tracing::error!(
+                "[watchdog] {} made no progress for {}s; {}",
+                component.label(),
+                elapsed.as_secs(),
+                self.dump()
+            );
+
+            metrics::WATCHDOG_STALLS
+                .with_label_values(&[&self.chain_label, component.label()])
+                .inc();
+        }
+
+        let restartable = new_stalls
+            .iter()
+            .all(|(component, _)| *component == Component::L1Watcher);
+
+        match self.action {
+            WatchdogAction::Log => {}
+            WatchdogAction::Restart if restartable => {
+                self.progress
+                    .restart_l1_watcher
+                    .store(true, Ordering::Relaxed);
+            }
+            WatchdogAction::Restart | WatchdogAction::Exit => {
+                tracing::error!("[watchdog] exiting with code {}", WATCHDOG_EXIT_CODE);
+                process::exit(WATCHDOG_EXIT_CODE);
+            }
+        }
+    }
+
+    /// Returns the components without progress for longer than the stall timeout, with the
+    /// time since their latest progress
+    fn stalls(&self) -> Vec<(Component, Duration)> {
+        let engine = self.progress.engine.in_flight().map(|(_, elapsed)| elapsed);
+
+        [
+            (Component::L1Watcher, Some(self.l1_watcher.elapsed())),
+            (
+                Component::Derivation,
+                Some(self.progress.derivation.elapsed()),
+            ),
+            (Component::Engine, engine),
+        ]
+        .into_iter()
+        .filter_map(|(component, elapsed)| Some((component, elapsed?)))
+        .filter(|(_, elapsed)| *elapsed > self.stall_timeout)
+        .collect()
+    }
+
+    /// Returns the state of every component and the driver queues, for the logs
+    fn dump(&self) -> String {
+        let engine = match self.progress.engine.in_flight() {
+            Some((method, elapsed)) => format!("{} in flight for {}s", method, elapsed.as_secs()),
+            None => "idle".to_string(),
+        };
+
+        let diagnostics = self.progress.diagnostics();
+        format!(
+            "l1 watcher: last progress {}s ago, derivation: last progress {}s ago, engine: {}, \
+             safe head: {}, unsafe head: {}, pending l1 blocks: {}, future unsafe blocks: {}",
+            self.l1_watcher.elapsed().as_secs(),
+            self.progress.derivation.elapsed().as_secs(),
+            engine,
+            diagnostics.safe_head,
+            diagnostics.unsafe_head,
+            diagnostics.pending_l1_blocks,
+            diagnostics.future_unsafe_blocks
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stalls() {
+        let config = Config::default();
+        let mut watchdog = Watchdog::new(&config, Heartbeat::default(), Progress::default());
+        watchdog.stall_timeout = Duration::from_millis(20);
+
+        std::thread::sleep(Duration::from_millis(30));
+        watchdog.progress.derivation.beat();
+        assert_eq!(watchdog.stalls().len(), 1);
+        assert_eq!(watchdog.stalls()[0].0, Component::L1Watcher);
+
+        // only requests in flight stall the engine
+        let call = watchdog.progress.engine.start("engine_newPayload");
+        std::thread::sleep(Duration::from_millis(30));
+        watchdog.l1_watcher.beat();
+        watchdog.progress.derivation.beat();
+        assert_eq!(watchdog.stalls()[0].0, Component::Engine);
+        assert!(watchdog.dump().contains("engine_newPayload in flight"));
+
+        drop(call);
+        assert!(watchdog.stalls().is_empty());
+
+        // stalls are reported once until the component recovers
+        std::thread::sleep(Duration::from_millis(30));
+        watchdog.progress.derivation.beat();
+        watchdog.check();
+        assert!(watchdog.stalled.contains(&Component::L1Watcher));
+        assert!(!watchdog.progress.take_l1_watcher_restart());
+
+        watchdog.l1_watcher.beat();
+        watchdog.check();
+        assert!(watchdog.stalled.is_empty());
+    }
+}
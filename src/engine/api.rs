@@ -1,21 +1,26 @@
 use std::collections::HashMap;
-use std::time::{Duration, SystemTime};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
 
 use again::RetryPolicy;
 use eyre::Result;
 use futures::prelude::*;
 use futures_timer::TryFutureExt;
-use reqwest::{header, Client};
+use reqwest::{header, Client, Response, StatusCode};
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+use crate::config::{Config, HardforkSchedule};
 use crate::engine::DEFAULT_AUTH_PORT;
+use crate::telemetry::metrics;
 
 use super::{
-    Engine, ExecutionPayload, ForkChoiceUpdate, ForkchoiceState, JwtSecret, PayloadAttributes,
-    PayloadId, PayloadStatus, ENGINE_FORKCHOICE_UPDATED_V2, ENGINE_GET_PAYLOAD_V2,
-    ENGINE_NEW_PAYLOAD_V2,
+    Engine, ExecutionPayload, ExecutionPayloadBody, ForkChoiceUpdate, ForkchoiceState, IpcClient,
+    JwtSecret, JwtSecretFile, PayloadAttributes, PayloadEnvelope, PayloadId, PayloadStatus,
+    ENGINE_EXCHANGE_CAPABILITIES, ENGINE_FORKCHOICE_UPDATED_V2, ENGINE_FORKCHOICE_UPDATED_V3,
+    ENGINE_GET_PAYLOAD_BODIES_BY_RANGE_V1, ENGINE_GET_PAYLOAD_V2, ENGINE_GET_PAYLOAD_V3,
+    ENGINE_GET_PAYLOAD_V4, ENGINE_NEW_PAYLOAD_V2, ENGINE_NEW_PAYLOAD_V3, ENGINE_NEW_PAYLOAD_V4,
 };
 
 use super::{JSONRPC_VERSION, STATIC_ID};
@@ -29,13 +34,42 @@ pub struct EngineApi {
     pub port: u16,
     /// HTTP Client
     pub client: Option<Client>,
+    /// IPC Client, set if the engine is reached over an `ipc://` socket
+    pub ipc: Option<Arc<IpcClient>>,
     /// A JWT secret used to authenticate with the engine api
     secret: JwtSecret,
+    /// A rotatable secret file, used instead of `secret` if set
+    secret_file: Option<Arc<JwtSecretFile>>,
+    /// The hardforks selecting the version of each Engine API method
+    hardforks: HardforkSchedule,
+    /// The timestamps of the payloads being built, to retrieve them with the method
+    /// version of their hardfork
+    payload_timestamps: Arc<Mutex<HashMap<PayloadId, u64>>>,
 }
 
+/// Maximum number of payloads being built whose timestamps are remembered
+const MAX_PENDING_PAYLOADS: usize = 64;
+
 impl EngineApi {
     /// Creates a new [`EngineApi`] with a base url and secret.
+    /// An `ipc://<path>` url connects over the IPC socket at `<path>` instead of HTTP.
     pub fn new(base_url: &str, secret_str: &str) -> Self {
+        if let Some(path) = IpcClient::path_from_url(base_url) {
+            // IPC sockets are not authenticated, so the secret is optional
+            let secret = JwtSecret::from_hex(secret_str).unwrap_or_else(|_| JwtSecret::random());
+
+            return Self {
+                base_url: base_url.to_string(),
+                port: 0,
+                client: None,
+                ipc: Some(Arc::new(IpcClient::new(path))),
+                secret,
+                secret_file: None,
+                hardforks: HardforkSchedule::default(),
+                payload_timestamps: Default::default(),
+            };
+        }
+
         let secret = JwtSecret::from_hex(secret_str).unwrap();
 
         // Gracefully parse the port from the base url
@@ -64,8 +98,136 @@ impl EngineApi {
             base_url,
             port,
             client: Some(client),
+            ipc: None,
             secret,
+            secret_file: None,
+            hardforks: HardforkSchedule::default(),
+            payload_timestamps: Default::default(),
+        }
+    }
+
+    /// Authenticates with the secret in the given file, which is reloaded if the
+    /// engine starts rejecting requests
+    pub fn with_secret_file(mut self, secret_file: Arc<JwtSecretFile>) -> Self {
+        self.secret_file = Some(secret_file);
+        self
+    }
+
+    /// Creates a new [`EngineApi`] for the given url with the JWT secret and hardforks of the
+    /// [Config]. Fails if the engine is reached over HTTP and no secret is configured.
+    pub fn from_config(url: &str, config: &Config) -> Result<Self> {
+        let secret = match &config.jwt_secret {
+            Some(secret) => secret.as_str(),
+            None if IpcClient::path_from_url(url).is_some() => "",
+            None => eyre::bail!(
+                "engine {} requires a jwt secret; check --jwt-secret or --jwt-file",
+                url
+            ),
+        };
+
+        Ok(Self::new(url, secret).with_hardforks(config.chain.hardforks))
+    }
+
+    /// Selects the version of each Engine API method by the hardforks of the given schedule.
+    /// Defaults to the V3 methods, as if Ecotone is active and Isthmus is not scheduled.
+    pub fn with_hardforks(mut self, hardforks: HardforkSchedule) -> Self {
+        self.hardforks = hardforks;
+        self
+    }
+
+    /// Returns the `engine_forkchoiceUpdated` version for the given payload attributes. Updates
+    /// without attributes are valid in any version.
+    fn forkchoice_updated_method(&self, attributes: Option<&PayloadAttributes>) -> &'static str {
+        match attributes {
+            Some(attributes)
+                if self
+                    .hardforks
+                    .is_ecotone_active(attributes.timestamp.as_u64()) =>
+            {
+                ENGINE_FORKCHOICE_UPDATED_V3
+            }
+            _ => ENGINE_FORKCHOICE_UPDATED_V2,
+        }
+    }
+
+    /// Returns the `engine_newPayload` version for a payload at the given timestamp
+    fn new_payload_method(&self, timestamp: u64) -> &'static str {
+        if self.hardforks.is_isthmus_active(timestamp) {
+            ENGINE_NEW_PAYLOAD_V4
+        } else if self.hardforks.is_ecotone_active(timestamp) {
+            ENGINE_NEW_PAYLOAD_V3
+        } else {
+            ENGINE_NEW_PAYLOAD_V2
+        }
+    }
+
+    /// Returns the `engine_getPayload` version for a payload at the given timestamp
+    fn get_payload_method(&self, timestamp: u64) -> &'static str {
+        if self.hardforks.is_isthmus_active(timestamp) {
+            ENGINE_GET_PAYLOAD_V4
+        } else if self.hardforks.is_ecotone_active(timestamp) {
+            ENGINE_GET_PAYLOAD_V3
+        } else {
+            ENGINE_GET_PAYLOAD_V2
+        }
+    }
+
+    /// Returns the params of an `engine_newPayload` request of the given version. V3 adds the
+    /// expected blob versioned hashes, always empty on L2, and the parent beacon block root.
+    /// V4 adds the execution requests, also always empty on L2.
+    fn new_payload_params(
+        method: &str,
+        mut execution_payload: ExecutionPayload,
+    ) -> Result<Vec<Value>> {
+        if method != ENGINE_NEW_PAYLOAD_V4 {
+            // blocks carry the withdrawals root from Canyon, but the engine only expects it
+            // in the payload from Isthmus
+            execution_payload.withdrawals_root = None;
+        } else if execution_payload.withdrawals_root.is_none() {
+            eyre::bail!("isthmus payload is missing the withdrawals root");
+        }
+
+        let parent_beacon_block_root = execution_payload.parent_beacon_block_root;
+        let mut params = vec![serde_json::to_value(execution_payload)?];
+
+        if method != ENGINE_NEW_PAYLOAD_V2 {
+            let root = parent_beacon_block_root.ok_or(eyre::eyre!(
+                "payload is missing the parent beacon block root"
+            ))?;
+            params.push(Value::Array(Vec::new()));
+            params.push(serde_json::to_value(root)?);
+        }
+
+        if method == ENGINE_NEW_PAYLOAD_V4 {
+            params.push(Value::Array(Vec::new()));
+        }
+
+        Ok(params)
+    }
+
+    /// Remembers the timestamp of a payload being built
+    fn track_payload(&self, payload_id: PayloadId, timestamp: u64) {
+        let mut timestamps = self.payload_timestamps.lock().unwrap();
+        if timestamps.len() >= MAX_PENDING_PAYLOADS {
+            timestamps.clear();
         }
+
+        timestamps.insert(payload_id, timestamp);
+    }
+
+    /// Returns the timestamp of a payload being built, or the current time if it was not
+    /// started by this client
+    fn payload_timestamp(&self, payload_id: PayloadId) -> u64 {
+        self.payload_timestamps
+            .lock()
+            .unwrap()
+            .remove(&payload_id)
+            .unwrap_or_else(|| {
+                SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .map(|now| now.as_secs())
+                    .unwrap_or_default()
+            })
     }
 
     /// Constructs the base engine api url for the given address
@@ -124,59 +286,179 @@ impl EngineApi {
         tracing::trace!("Sending request to url: {:?}", self.base_url);
         tracing::trace!("Sending request: {:?}", serde_json::to_string(&body));
 
+        let start = Instant::now();
+        let policy = RetryPolicy::fixed(Duration::ZERO).with_max_retries(5);
+
+        let res = match &self.ipc {
+            Some(ipc) => {
+                let res = policy.retry(|| ipc.request(&body)).await?;
+                metrics::ENGINE_REQUEST_LATENCY
+                    .with_label_values(&["ipc", method])
+                    .observe(start.elapsed().as_secs_f64());
+                res
+            }
+            None => {
+                let res = self.post_http(&body, &policy).await?;
+                metrics::ENGINE_REQUEST_LATENCY
+                    .with_label_values(&["http", method])
+                    .observe(start.elapsed().as_secs_f64());
+                res
+            }
+        };
+
+        if let Some(res) = res.result {
+            return Ok(res);
+        }
+
+        if let Some(err) = res.error {
+            return Err(eyre::Report::new(err).wrap_err("Engine API POST error"));
+        }
+
+        // This scenario shouldn't occur as the response should always have either data or an error
+        eyre::bail!("Failed to parse Engine API response")
+    }
+
+    /// Sends a request body over HTTP, authenticated with a fresh JWT on each attempt
+    async fn post_http<P>(
+        &self,
+        body: &HashMap<String, Value>,
+        policy: &RetryPolicy,
+    ) -> Result<EngineApiResponse<P>>
+    where
+        P: DeserializeOwned,
+    {
         // Send the client request
         let client = self
             .client
             .as_ref()
             .ok_or(eyre::eyre!("Driver missing http client"))?;
 
-        // Clone the secret so we can use it in the retry policy.
-        let secret_clone = self.secret.clone();
-
-        let policy = RetryPolicy::fixed(Duration::ZERO).with_max_retries(5);
-
         // Send the request
-        let res = policy
+        policy
             .retry(|| async {
-                // Construct the JWT Authorization Token
-                let claims = secret_clone.generate_claims(Some(SystemTime::now()));
-                let jwt = secret_clone
-                    .encode(&claims)
-                    .map_err(|_| eyre::eyre!("EngineApi failed to encode jwt with claims!"))?;
-
-                // Send the request
-                client
-                    .post(&self.base_url)
-                    .header(header::AUTHORIZATION, format!("Bearer {}", jwt))
-                    .json(&body)
-                    .send()
-                    .map_err(|e| eyre::eyre!(e))
-                    .timeout(Duration::from_secs(2))
-                    .await?
-                    .json::<EngineApiResponse<P>>()
+                let Some(secret_file) = &self.secret_file else {
+                    return self
+                        .send_http(client, body, &self.secret)
+                        .await?
+                        .json::<EngineApiResponse<P>>()
+                        .map_err(|e| eyre::eyre!(e))
+                        .timeout(Duration::from_secs(2))
+                        .map_err(|e| eyre::eyre!(e))
+                        .await;
+                };
+
+                let mut secrets = secret_file.valid_secrets();
+                let mut res = self.send_http(client, body, &secrets[0]).await?;
+
+                if is_auth_error(&res) {
+                    // the secret may have been rotated on the execution client
+                    match secret_file.reload() {
+                        Ok(_) => secrets = secret_file.valid_secrets(),
+                        Err(err) => tracing::warn!("failed to reload jwt secret: {}", err),
+                    }
+
+                    for secret in &secrets {
+                        res = self.send_http(client, body, secret).await?;
+                        if !is_auth_error(&res) {
+                            secret_file.accepted(secret);
+                            break;
+                        }
+                    }
+                }
+
+                res.json::<EngineApiResponse<P>>()
                     .map_err(|e| eyre::eyre!(e))
                     .timeout(Duration::from_secs(2))
                     .map_err(|e| eyre::eyre!(e))
                     .await
             })
-            .await?;
-
-        if let Some(res) = res.result {
-            return Ok(res);
-        }
-
-        if let Some(err) = res.error {
-            eyre::bail!("Engine API POST error: {}", err.message);
-        }
+            .await
+    }
 
-        // This scenario shouldn't occur as the response should always have either data or an error
-        eyre::bail!("Failed to parse Engine API response")
+    /// Sends a request body authenticated with a fresh JWT signed by the given secret
+    async fn send_http(
+        &self,
+        client: &Client,
+        body: &HashMap<String, Value>,
+        secret: &JwtSecret,
+    ) -> Result<Response> {
+        // Construct the JWT Authorization Token
+        let claims = secret.generate_claims(Some(SystemTime::now()));
+        let jwt = secret
+            .encode(&claims)
+            .map_err(|_| eyre::eyre!("EngineApi failed to encode jwt with claims!"))?;
+
+        client
+            .post(&self.base_url)
+            .header(header::AUTHORIZATION, format!("Bearer {}", jwt))
+            .json(body)
+            .send()
+            .map_err(|e| eyre::eyre!(e))
+            .timeout(Duration::from_secs(2))
+            .await
     }
 
     /// Calls the engine to verify it's available to receive requests
     pub async fn is_available(&self) -> bool {
         self.post::<Value>("eth_chainId", vec![]).await.is_ok()
     }
+
+    /// Sends an `engine_getPayloadBodiesByRangeV1` message to the engine.
+    /// Bodies of blocks unknown to the engine are returned as `None`.
+    pub async fn get_payload_bodies_by_range(
+        &self,
+        start: u64,
+        count: u64,
+    ) -> Result<Vec<Option<ExecutionPayloadBody>>> {
+        let params = vec![
+            Value::String(format!("{:#x}", start)),
+            Value::String(format!("{:#x}", count)),
+        ];
+
+        self.post(ENGINE_GET_PAYLOAD_BODIES_BY_RANGE_V1, params)
+            .await
+    }
+
+    /// Checks that the engine serves the engine api and is not syncing
+    pub async fn check_health(&self) -> Result<()> {
+        self.exchange_capabilities().await?;
+
+        let syncing = self.post::<Value>("eth_syncing", vec![]).await?;
+        if syncing != Value::Bool(false) {
+            eyre::bail!("engine is syncing");
+        }
+
+        Ok(())
+    }
+
+    /// Exchanges the used Engine API methods with the engine, returning the methods it
+    /// supports. Fails if the engine rejects the JWT.
+    pub async fn exchange_capabilities(&self) -> Result<Vec<String>> {
+        let capabilities = [
+            ENGINE_FORKCHOICE_UPDATED_V2,
+            ENGINE_FORKCHOICE_UPDATED_V3,
+            ENGINE_NEW_PAYLOAD_V2,
+            ENGINE_NEW_PAYLOAD_V3,
+            ENGINE_NEW_PAYLOAD_V4,
+            ENGINE_GET_PAYLOAD_V2,
+            ENGINE_GET_PAYLOAD_V3,
+            ENGINE_GET_PAYLOAD_V4,
+        ]
+        .into_iter()
+        .map(|method| Value::String(method.to_string()))
+        .collect();
+
+        self.post::<Vec<String>>(
+            ENGINE_EXCHANGE_CAPABILITIES,
+            vec![Value::Array(capabilities)],
+        )
+        .await
+    }
+}
+
+/// Returns true if the engine rejected the JWT of the request
+fn is_auth_error(res: &Response) -> bool {
+    res.status() == StatusCode::UNAUTHORIZED || res.status() == StatusCode::FORBIDDEN
 }
 
 /// Generic Engine API response
@@ -204,6 +486,14 @@ pub struct EngineApiErrorPayload {
     pub data: Option<Value>,
 }
 
+impl std::fmt::Display for EngineApiErrorPayload {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (code {})", self.message, self.code)
+    }
+}
+
+impl std::error::Error for EngineApiErrorPayload {}
+
 #[async_trait::async_trait]
 impl Engine for EngineApi {
     /// Sends an `engine_forkchoiceUpdatedV2` (V3 post Ecotone) message to the engine.
@@ -212,41 +502,42 @@ impl Engine for EngineApi {
         forkchoice_state: ForkchoiceState,
         payload_attributes: Option<PayloadAttributes>,
     ) -> Result<ForkChoiceUpdate> {
+        let method = self.forkchoice_updated_method(payload_attributes.as_ref());
+        let timestamp = payload_attributes.as_ref().map(|a| a.timestamp.as_u64());
+
         let payload_attributes_param = match payload_attributes {
             Some(payload_attributes) => serde_json::to_value(payload_attributes)?,
             None => Value::Null,
         };
         let forkchoice_state_param = serde_json::to_value(forkchoice_state)?;
         let params = vec![forkchoice_state_param, payload_attributes_param];
-        let res = self.post(ENGINE_FORKCHOICE_UPDATED_V2, params).await?;
+        let res: ForkChoiceUpdate = self.post(method, params).await?;
+
+        if let (Some(payload_id), Some(timestamp)) = (res.payload_id, timestamp) {
+            self.track_payload(payload_id, timestamp);
+        }
+
         Ok(res)
     }
 
-    /// Sends an `engine_newPayloadV2` (V3 post Ecotone) message to the engine.
+    /// Sends an `engine_newPayloadV2` (V3 post Ecotone, V4 post Isthmus) message to the engine.
     async fn new_payload(&self, execution_payload: ExecutionPayload) -> Result<PayloadStatus> {
-        let params = vec![serde_json::to_value(execution_payload)?];
-        let res = self.post(ENGINE_NEW_PAYLOAD_V2, params).await?;
+        let method = self.new_payload_method(execution_payload.timestamp.as_u64());
+        let params = Self::new_payload_params(method, execution_payload)?;
+        let res = self.post(method, params).await?;
         Ok(res)
     }
 
-    /// Sends an `engine_getPayloadV2` (V3 post Ecotone) message to the engine.
+    /// Sends an `engine_getPayloadV2` (V3 post Ecotone, V4 post Isthmus) message to the engine.
     async fn get_payload(&self, payload_id: PayloadId) -> Result<ExecutionPayload> {
+        let method = self.get_payload_method(self.payload_timestamp(payload_id));
         let encoded = format!("{:x}", payload_id);
         let padded = format!("0x{:0>16}", encoded);
         let params = vec![Value::String(padded)];
-        let res = self
-            .post::<GetPayloadResponse>(ENGINE_GET_PAYLOAD_V2, params)
-            .await?;
-        Ok(res.execution_payload)
-    }
-}
+        let res = self.post::<PayloadEnvelope>(method, params).await?;
 
-/// Wrapper around an [ExecutionPayload]
-#[derive(Debug, Serialize, Deserialize, Default)]
-#[serde(rename_all = "camelCase")]
-struct GetPayloadResponse {
-    /// The execution payload returned by the engine via `engine_getPayloadV2` (`engine_getPayloadV3` post Ecotone)
-    execution_payload: ExecutionPayload,
+        Ok(res.into_payload())
+    }
 }
 
 #[cfg(test)]
@@ -258,6 +549,8 @@ mod tests {
 
     use super::*;
 
+    use ethers::types::{H256, U64};
+
     const AUTH_ADDR: &str = "0.0.0.0";
     const SECRET: &str = "f79ae8046bc11c9927afe911db7143c51a806c4a537cc08e0d37140b0192f430";
 
@@ -304,4 +597,83 @@ mod tests {
         // server.stop().unwrap();
         // server.stopped().await;
     }
+
+    #[test]
+    fn test_method_versions() -> Result<()> {
+        let engine_api =
+            EngineApi::new("http://127.0.0.1:8551", SECRET).with_hardforks(HardforkSchedule {
+                ecotone_time: 10,
+                isthmus_time: Some(20),
+                ..Default::default()
+            });
+
+        assert_eq!(engine_api.new_payload_method(9), ENGINE_NEW_PAYLOAD_V2);
+        assert_eq!(engine_api.new_payload_method(10), ENGINE_NEW_PAYLOAD_V3);
+        assert_eq!(engine_api.new_payload_method(20), ENGINE_NEW_PAYLOAD_V4);
+        assert_eq!(engine_api.get_payload_method(19), ENGINE_GET_PAYLOAD_V3);
+        assert_eq!(engine_api.get_payload_method(20), ENGINE_GET_PAYLOAD_V4);
+
+        let attributes = PayloadAttributes {
+            timestamp: U64::from(10),
+            ..Default::default()
+        };
+        assert_eq!(
+            engine_api.forkchoice_updated_method(Some(&attributes)),
+            ENGINE_FORKCHOICE_UPDATED_V3
+        );
+        assert_eq!(
+            engine_api.forkchoice_updated_method(None),
+            ENGINE_FORKCHOICE_UPDATED_V2
+        );
+
+        // payloads are retrieved with the version of their timestamp, not the current time
+        engine_api.track_payload(U64::from(7), 15);
+        assert_eq!(engine_api.payload_timestamp(U64::from(7)), 15);
+        assert!(engine_api.payload_timestamp(U64::from(7)) > 20);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_new_payload_params() -> Result<()> {
+        let root = H256::repeat_byte(1);
+        let payload = ExecutionPayload {
+            withdrawals_root: Some(H256::repeat_byte(2)),
+            parent_beacon_block_root: Some(root),
+            ..Default::default()
+        };
+
+        let params = EngineApi::new_payload_params(ENGINE_NEW_PAYLOAD_V2, payload.clone())?;
+        assert_eq!(params.len(), 1);
+        assert!(params[0].get("withdrawalsRoot").is_none());
+        assert!(params[0].get("parentBeaconBlockRoot").is_none());
+
+        let params = EngineApi::new_payload_params(ENGINE_NEW_PAYLOAD_V3, payload.clone())?;
+        assert_eq!(params.len(), 3);
+        assert!(params[0].get("withdrawalsRoot").is_none());
+        assert_eq!(params[1], Value::Array(Vec::new()));
+        assert_eq!(params[2], serde_json::to_value(root)?);
+
+        let params = EngineApi::new_payload_params(ENGINE_NEW_PAYLOAD_V4, payload.clone())?;
+        assert_eq!(params.len(), 4);
+        assert_eq!(
+            params[0].get("withdrawalsRoot"),
+            Some(&serde_json::to_value(H256::repeat_byte(2))?)
+        );
+        assert_eq!(params[3], Value::Array(Vec::new()));
+
+        let payload = ExecutionPayload {
+            withdrawals_root: None,
+            ..payload
+        };
+        assert!(EngineApi::new_payload_params(ENGINE_NEW_PAYLOAD_V4, payload.clone()).is_err());
+
+        let payload = ExecutionPayload {
+            parent_beacon_block_root: None,
+            ..payload
+        };
+        assert!(EngineApi::new_payload_params(ENGINE_NEW_PAYLOAD_V3, payload).is_err());
+
+        Ok(())
+    }
 }
@@ -8,7 +8,12 @@ use eyre::Result;
 use jsonwebtoken::Algorithm;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::path::{Path, PathBuf};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, RwLock, Weak,
+};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 /// JWT hex encoded 256 bit secret key length.
 const JWT_SECRET_LEN: usize = 64;
@@ -19,10 +24,13 @@ const JWT_MAX_IAT_DIFF: Duration = Duration::from_secs(60);
 /// The execution layer client MUST support at least the following alg HMAC + SHA256 (HS256)
 const JWT_SIGNATURE_ALGO: Algorithm = Algorithm::HS256;
 
+/// How long the previous secret stays valid after the secret file is rotated.
+const JWT_ROTATION_GRACE_PERIOD: Duration = Duration::from_secs(600);
+
 /// JwtSecret is a 256-bit hex-encoded secret key used to perform JWT-based authentication.
 ///
 /// See: [Secret key - Engine API specs](https://github.com/ethereum/execution-apis/blob/main/src/engine/authentication.md#key-distribution)
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq)]
 pub struct JwtSecret([u8; 32]);
 
 impl JwtSecret {
@@ -81,6 +89,25 @@ impl JwtSecret {
         let algo = jsonwebtoken::Header::new(Algorithm::HS256);
         Ok(jsonwebtoken::encode(&algo, claims, &key)?)
     }
+
+    /// Validates a JWT signed with the secret, whose `iat` claim must be within 60 seconds
+    /// of the current time. The `exp` claim is optional.
+    pub fn validate(&self, token: &str) -> Result<()> {
+        let key = jsonwebtoken::DecodingKey::from_secret(&self.0);
+        let mut validation = jsonwebtoken::Validation::new(JWT_SIGNATURE_ALGO);
+        validation.required_spec_claims.clear();
+        validation.validate_exp = false;
+
+        let claims = jsonwebtoken::decode::<Claims>(token, &key, &validation)?.claims;
+        if !claims.valid() {
+            eyre::bail!(
+                "jwt issued at {} is outside the allowed time window",
+                claims.iat
+            );
+        }
+
+        Ok(())
+    }
 }
 
 impl std::fmt::Debug for JwtSecret {
@@ -89,6 +116,103 @@ impl std::fmt::Debug for JwtSecret {
     }
 }
 
+/// A [JwtSecret] read from a hex file, which can be reloaded to rotate the secret without downtime.
+///
+/// After a rotation, the previous secret stays valid for a grace period, so requests keep
+/// succeeding while the execution client has not picked up the new secret yet.
+#[derive(Debug)]
+pub struct JwtSecretFile {
+    /// Path to the hex encoded secret
+    path: PathBuf,
+    /// The current secret, and the previous secret with the time it was rotated out
+    secrets: RwLock<(JwtSecret, Option<(JwtSecret, Instant)>)>,
+    /// Set while the execution client only accepts the previous secret
+    prefer_previous: AtomicBool,
+}
+
+impl JwtSecretFile {
+    /// Reads the secret from the file at the given path
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let secret = JwtSecret::from_hex(std::fs::read_to_string(&path)?)?;
+
+        Ok(Self {
+            path,
+            secrets: RwLock::new((secret, None)),
+            prefer_previous: AtomicBool::new(false),
+        })
+    }
+
+    /// Re-reads the secret file. Returns `true` if the secret was rotated.
+    pub fn reload(&self) -> Result<bool> {
+        let secret = JwtSecret::from_hex(std::fs::read_to_string(&self.path)?)?;
+        let mut secrets = self
+            .secrets
+            .write()
+            .map_err(|_| eyre::eyre!("lock poisoned"))?;
+
+        if secrets.0 == secret {
+            return Ok(false);
+        }
+
+        let previous = std::mem::replace(&mut secrets.0, secret);
+        secrets.1 = Some((previous, Instant::now()));
+        self.prefer_previous.store(false, Ordering::SeqCst);
+
+        tracing::info!("rotated jwt secret from {:?}", self.path);
+
+        Ok(true)
+    }
+
+    /// Returns the secrets which may currently be accepted by the execution client,
+    /// the most likely one first
+    pub fn valid_secrets(&self) -> Vec<JwtSecret> {
+        let secrets = self.secrets.read().unwrap_or_else(|err| err.into_inner());
+
+        let previous = secrets
+            .1
+            .as_ref()
+            .filter(|(_, rotated)| rotated.elapsed() < JWT_ROTATION_GRACE_PERIOD)
+            .map(|(secret, _)| secret.clone());
+
+        match previous {
+            Some(previous) if self.prefer_previous.load(Ordering::SeqCst) => {
+                vec![previous, secrets.0.clone()]
+            }
+            Some(previous) => vec![secrets.0.clone(), previous],
+            None => vec![secrets.0.clone()],
+        }
+    }
+
+    /// Records the secret which was accepted by the execution client
+    pub fn accepted(&self, secret: &JwtSecret) {
+        let secrets = self.secrets.read().unwrap_or_else(|err| err.into_inner());
+        self.prefer_previous
+            .store(secrets.0 != *secret, Ordering::SeqCst);
+    }
+
+    /// Reloads the secret file whenever the process receives `SIGHUP`, until the
+    /// secret file is dropped
+    pub fn spawn_reload_on_sighup(self: &Arc<Self>) -> Result<()> {
+        let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
+        let secret_file: Weak<Self> = Arc::downgrade(self);
+
+        tokio::spawn(async move {
+            while hangup.recv().await.is_some() {
+                let Some(secret_file) = secret_file.upgrade() else {
+                    break;
+                };
+
+                if let Err(err) = secret_file.reload() {
+                    tracing::warn!("failed to reload jwt secret: {}", err);
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
+
 /// Claims are a set of information about an actor authorized by a JWT.
 ///
 /// The Engine API requires that the `iat` (issued-at) claim is provided.
@@ -97,6 +221,7 @@ pub(crate) struct Claims {
     /// The number of seconds since the UNIX_EPOCH.
     pub(crate) iat: u64,
     /// The expiration time of the JWT.
+    #[serde(default)]
     pub(crate) exp: u64,
 }
 
@@ -135,6 +260,33 @@ mod tests {
         assert!(secret_claims.valid());
     }
 
+    #[test]
+    fn rotate_secret_file() -> Result<()> {
+        const ROTATED: &str = "0000000000000000000000000000000000000000000000000000000000000001";
+
+        let path = std::env::temp_dir().join(format!("magi-jwt-{}.hex", std::process::id()));
+        std::fs::write(&path, SECRET)?;
+
+        let secret_file = JwtSecretFile::load(&path)?;
+        assert!(!secret_file.reload()?);
+        assert_eq!(secret_file.valid_secrets().len(), 1);
+
+        std::fs::write(&path, ROTATED)?;
+        assert!(secret_file.reload()?);
+
+        let old = JwtSecret::from_hex(SECRET)?;
+        let new = JwtSecret::from_hex(ROTATED)?;
+        assert_eq!(secret_file.valid_secrets(), vec![new.clone(), old.clone()]);
+
+        // the execution client has not rotated yet
+        secret_file.accepted(&old);
+        assert_eq!(secret_file.valid_secrets(), vec![old, new]);
+
+        std::fs::remove_file(&path)?;
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn encode_secret() {
         let secret = JwtSecret::from_hex(SECRET).unwrap();
@@ -0,0 +1,158 @@
+use async_trait::async_trait;
+use eyre::Result;
+use tokio::sync::{mpsc, oneshot};
+
+use super::{
+    Engine, ExecutionPayload, ForkChoiceUpdate, ForkchoiceState, PayloadAttributes, PayloadId,
+    PayloadStatus,
+};
+
+/// A request from the [ChannelEngine] to the execution client embedding Magi
+#[derive(Debug)]
+pub enum EngineRequest {
+    /// Corresponds to `engine_forkchoiceUpdated`
+    ForkchoiceUpdated {
+        /// The new forkchoice state
+        forkchoice_state: ForkchoiceState,
+        /// Attributes of a payload to start building, if any
+        payload_attributes: Option<PayloadAttributes>,
+        /// Channel to send the result to
+        response: oneshot::Sender<Result<ForkChoiceUpdate>>,
+    },
+    /// Corresponds to `engine_newPayload`
+    NewPayload {
+        /// The payload to execute
+        execution_payload: ExecutionPayload,
+        /// Channel to send the result to
+        response: oneshot::Sender<Result<PayloadStatus>>,
+    },
+    /// Corresponds to `engine_getPayload`
+    GetPayload {
+        /// The id of the payload build process
+        payload_id: PayloadId,
+        /// Channel to send the result to
+        response: oneshot::Sender<Result<ExecutionPayload>>,
+    },
+}
+
+/// An [Engine] which hands requests to an in-process execution client over channels,
+/// for when Magi runs embedded in the execution client rather than talking to it over
+/// the Engine API. The host serves the [EngineRequest]s itself: this is not a reth
+/// execution extension, and no reth integration is provided.
+#[derive(Debug, Clone)]
+pub struct ChannelEngine {
+    /// Channel to send requests to the execution client
+    sender: mpsc::Sender<EngineRequest>,
+}
+
+impl ChannelEngine {
+    /// Creates a new [ChannelEngine], returning the receiver the execution client
+    /// should serve requests from
+    pub fn new(buffer: usize) -> (Self, mpsc::Receiver<EngineRequest>) {
+        let (sender, receiver) = mpsc::channel(buffer);
+        (Self { sender }, receiver)
+    }
+
+    /// Sends a request and waits for the execution client to respond
+    async fn request<T>(
+        &self,
+        request: impl FnOnce(oneshot::Sender<Result<T>>) -> EngineRequest,
+    ) -> Result<T> {
+        let (response, recv) = oneshot::channel();
+
+        self.sender
+            .send(request(response))
+            .await
+            .map_err(|_| eyre::eyre!("execution client stopped serving engine requests"))?;
+
+        recv.await
+            .map_err(|_| eyre::eyre!("execution client dropped engine request"))?
+    }
+}
+
+#[async_trait]
+impl Engine for ChannelEngine {
+    async fn forkchoice_updated(
+        &self,
+        forkchoice_state: ForkchoiceState,
+        payload_attributes: Option<PayloadAttributes>,
+    ) -> Result<ForkChoiceUpdate> {
+        self.request(|response| EngineRequest::ForkchoiceUpdated {
+            forkchoice_state,
+            payload_attributes,
+            response,
+        })
+        .await
+    }
+
+    async fn new_payload(&self, execution_payload: ExecutionPayload) -> Result<PayloadStatus> {
+        self.request(|response| EngineRequest::NewPayload {
+            execution_payload,
+            response,
+        })
+        .await
+    }
+
+    async fn get_payload(&self, payload_id: PayloadId) -> Result<ExecutionPayload> {
+        self.request(|response| EngineRequest::GetPayload {
+            payload_id,
+            response,
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::Status;
+
+    #[tokio::test]
+    async fn test_channel_engine() -> Result<()> {
+        let (engine, mut requests) = ChannelEngine::new(1);
+
+        let host = tokio::spawn(async move {
+            while let Some(request) = requests.recv().await {
+                match request {
+                    EngineRequest::NewPayload {
+                        execution_payload,
+                        response,
+                    } => {
+                        _ = response.send(Ok(PayloadStatus {
+                            status: Status::Valid,
+                            latest_valid_hash: Some(execution_payload.block_hash),
+                            validation_error: None,
+                        }));
+                    }
+                    EngineRequest::GetPayload { response, .. } => {
+                        _ = response.send(Err(eyre::eyre!("unknown payload")));
+                    }
+                    EngineRequest::ForkchoiceUpdated { .. } => {
+                        // dropping the responder fails the request
+                    }
+                }
+            }
+        });
+
+        let status = engine.new_payload(ExecutionPayload::default()).await?;
+        assert_eq!(status.status, Status::Valid);
+
+        assert!(engine.get_payload(PayloadId::default()).await.is_err());
+        assert!(engine
+            .forkchoice_updated(
+                ForkchoiceState {
+                    head_block_hash: Default::default(),
+                    safe_block_hash: Default::default(),
+                    finalized_block_hash: Default::default(),
+                },
+                None
+            )
+            .await
+            .is_err());
+
+        drop(engine);
+        host.await?;
+
+        Ok(())
+    }
+}
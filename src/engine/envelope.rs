@@ -0,0 +1,704 @@
+use ethers::types::{Address, Bytes, H256};
+use eyre::Result;
+// the SSZ prelude shadows the serde derives, which are used by path
+use ssz_rs::{prelude::*, List, Vector, U256};
+
+use crate::{common::RawTransaction, config::HardforkSchedule};
+
+use super::ExecutionPayload;
+
+/// The SSZ encodings of an [ExecutionPayload], numbered as in the blocks gossip topics and
+/// the `payload_by_number` responses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PayloadVersion {
+    /// The Bedrock payload, without withdrawals
+    V1,
+    /// The Canyon payload, with an empty withdrawals list
+    V2,
+    /// The Ecotone payload, with the blob gas fields and the parent beacon block root
+    V3,
+    /// The Isthmus payload, with the withdrawals root
+    V4,
+}
+
+impl PayloadVersion {
+    /// Returns the version of the payloads at the given timestamp
+    pub fn at(hardforks: &HardforkSchedule, timestamp: u64) -> Self {
+        if hardforks.is_isthmus_active(timestamp) {
+            Self::V4
+        } else if hardforks.is_ecotone_active(timestamp) {
+            Self::V3
+        } else if hardforks.is_canyon_active(timestamp) {
+            Self::V2
+        } else {
+            Self::V1
+        }
+    }
+
+    /// Returns the number of the version
+    pub fn number(&self) -> u32 {
+        match self {
+            Self::V1 => 0,
+            Self::V2 => 1,
+            Self::V3 => 2,
+            Self::V4 => 3,
+        }
+    }
+
+    /// Returns true if the encoded envelope starts with the parent beacon block root
+    pub fn has_parent_beacon_block_root(&self) -> bool {
+        *self >= Self::V3
+    }
+}
+
+impl TryFrom<u32> for PayloadVersion {
+    type Error = eyre::Report;
+
+    fn try_from(value: u32) -> Result<Self> {
+        Ok(match value {
+            0 => Self::V1,
+            1 => Self::V2,
+            2 => Self::V3,
+            3 => Self::V4,
+            _ => eyre::bail!("unknown payload version {}", value),
+        })
+    }
+}
+
+/// An [ExecutionPayload] with the fields passed alongside it, in the shape of an
+/// `engine_getPayload` response.
+///
+/// The JSON encoding is the one of the Engine API, the SSZ encoding the one of the blocks
+/// gossip topics and the `payload_by_number` protocol: the parent beacon block root from
+/// Ecotone, followed by the payload. The blobs bundle and execution requests are always empty
+/// on L2 and only kept for compatibility with the Engine API.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PayloadEnvelope {
+    /// The execution payload
+    pub execution_payload: ExecutionPayload,
+    /// The fees of the payload, returned from `engine_getPayloadV2`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_value: Option<ethers::types::U256>,
+    /// The blobs of the payload, returned from `engine_getPayloadV3`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blobs_bundle: Option<BlobsBundle>,
+    /// Whether the payload should be preferred over a builder payload, returned from
+    /// `engine_getPayloadV3`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub should_override_builder: Option<bool>,
+    /// The parent beacon block root, present from Ecotone
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_beacon_block_root: Option<H256>,
+    /// The execution layer requests, returned from `engine_getPayloadV4`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub execution_requests: Option<Vec<Bytes>>,
+}
+
+/// The blobs of a payload, with their commitments and proofs
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct BlobsBundle {
+    /// The KZG commitments of the blobs
+    pub commitments: Vec<Bytes>,
+    /// The KZG proofs of the blobs
+    pub proofs: Vec<Bytes>,
+    /// The blobs
+    pub blobs: Vec<Bytes>,
+}
+
+impl PayloadEnvelope {
+    /// Wraps a payload, with the parent beacon block root it carries
+    pub fn new(payload: ExecutionPayload) -> Self {
+        Self {
+            parent_beacon_block_root: payload.parent_beacon_block_root,
+            execution_payload: payload,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the payload, carrying the parent beacon block root of the envelope
+    pub fn into_payload(self) -> ExecutionPayload {
+        ExecutionPayload {
+            parent_beacon_block_root: self.parent_beacon_block_root,
+            ..self.execution_payload
+        }
+    }
+
+    /// Encodes the envelope with the SSZ containers of the given version
+    pub fn encode_ssz(&self, version: PayloadVersion) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        if version.has_parent_beacon_block_root() {
+            let root = self.parent_beacon_block_root.ok_or(eyre::eyre!(
+                "ecotone payload without parent beacon block root"
+            ))?;
+            data.extend_from_slice(root.as_bytes());
+        }
+
+        data.extend(encode_ssz_payload(&self.execution_payload, version)?);
+        Ok(data)
+    }
+
+    /// Decodes an envelope encoded with the SSZ containers of the given version, the inverse
+    /// of [PayloadEnvelope::encode_ssz]
+    pub fn decode_ssz(version: PayloadVersion, data: &[u8]) -> Result<Self> {
+        let (parent_beacon_block_root, data) = if version.has_parent_beacon_block_root() {
+            if data.len() < 32 {
+                eyre::bail!("payload too short");
+            }
+            (Some(H256::from_slice(&data[..32])), &data[32..])
+        } else {
+            (None, data)
+        };
+
+        Ok(Self {
+            execution_payload: ExecutionPayload {
+                parent_beacon_block_root,
+                ..decode_ssz_payload(version, data)?
+            },
+            parent_beacon_block_root,
+            ..Default::default()
+        })
+    }
+}
+
+/// Encodes a payload with the SSZ container of the given version
+pub fn encode_ssz_payload(payload: &ExecutionPayload, version: PayloadVersion) -> Result<Vec<u8>> {
+    Ok(match version {
+        PayloadVersion::V1 => serialize(&ExecutionPayloadV1SSZ::try_from(payload)?)?,
+        PayloadVersion::V2 => serialize(&ExecutionPayloadV2SSZ::try_from(payload)?)?,
+        PayloadVersion::V3 => serialize(&ExecutionPayloadV3SSZ::try_from(payload)?)?,
+        PayloadVersion::V4 => serialize(&ExecutionPayloadV4SSZ::try_from(payload)?)?,
+    })
+}
+
+/// Decodes a payload encoded with the SSZ container of the given version, the inverse of
+/// [encode_ssz_payload]
+pub fn decode_ssz_payload(version: PayloadVersion, data: &[u8]) -> Result<ExecutionPayload> {
+    Ok(match version {
+        PayloadVersion::V1 => deserialize::<ExecutionPayloadV1SSZ>(data)?.into(),
+        PayloadVersion::V2 => deserialize::<ExecutionPayloadV2SSZ>(data)?.into(),
+        PayloadVersion::V3 => deserialize::<ExecutionPayloadV3SSZ>(data)?.into(),
+        PayloadVersion::V4 => deserialize::<ExecutionPayloadV4SSZ>(data)?.into(),
+    })
+}
+
+/// A type alias for a vector of 32 bytes, representing a Bytes32 hash
+type Bytes32 = Vector<u8, 32>;
+/// A type alias for a vector of 20 bytes, representing an address
+type VecAddress = Vector<u8, 20>;
+/// A type alias for a byte list, representing a transaction
+type Transaction = List<u8, 1073741824>;
+
+/// The pre Canyon/Shanghai [ExecutionPayload] - the withdrawals field should not exist
+#[derive(SimpleSerialize, Default)]
+struct ExecutionPayloadV1SSZ {
+    /// Block hash of the parent block
+    parent_hash: Bytes32,
+    /// Fee recipient of the block. Set to the sequencer fee vault
+    fee_recipient: VecAddress,
+    /// State root of the block
+    state_root: Bytes32,
+    /// Receipts root of the block
+    receipts_root: Bytes32,
+    /// Logs bloom of the block
+    logs_bloom: Vector<u8, 256>,
+    /// The block mix_digest
+    prev_randao: Bytes32,
+    /// The block number
+    block_number: u64,
+    /// The block gas limit
+    gas_limit: u64,
+    /// Total gas used in the block
+    gas_used: u64,
+    /// Timestamp of the block
+    timestamp: u64,
+    /// Any extra data included in the block
+    extra_data: List<u8, 32>,
+    /// Base fee per gas of the block
+    base_fee_per_gas: U256,
+    /// Hash of the block
+    block_hash: Bytes32,
+    /// Transactions in the block
+    transactions: List<Transaction, 1048576>,
+}
+
+impl From<ExecutionPayloadV1SSZ> for ExecutionPayload {
+    fn from(value: ExecutionPayloadV1SSZ) -> Self {
+        Self {
+            parent_hash: convert_hash(value.parent_hash),
+            fee_recipient: convert_address(value.fee_recipient),
+            state_root: convert_hash(value.state_root),
+            receipts_root: convert_hash(value.receipts_root),
+            logs_bloom: convert_byte_vector(value.logs_bloom),
+            prev_randao: convert_hash(value.prev_randao),
+            block_number: value.block_number.into(),
+            gas_limit: value.gas_limit.into(),
+            gas_used: value.gas_used.into(),
+            timestamp: value.timestamp.into(),
+            extra_data: convert_byte_list(value.extra_data),
+            base_fee_per_gas: convert_uint(value.base_fee_per_gas),
+            block_hash: convert_hash(value.block_hash),
+            transactions: convert_tx_list(value.transactions),
+            withdrawals: None,
+            blob_gas_used: None,
+            excess_blob_gas: None,
+            withdrawals_root: None,
+            parent_beacon_block_root: None,
+        }
+    }
+}
+
+impl TryFrom<&ExecutionPayload> for ExecutionPayloadV1SSZ {
+    type Error = eyre::Report;
+
+    fn try_from(value: &ExecutionPayload) -> Result<Self> {
+        Ok(Self {
+            parent_hash: encode_hash(value.parent_hash)?,
+            fee_recipient: encode_address(value.fee_recipient)?,
+            state_root: encode_hash(value.state_root)?,
+            receipts_root: encode_hash(value.receipts_root)?,
+            logs_bloom: encode_byte_vector(&value.logs_bloom)?,
+            prev_randao: encode_hash(value.prev_randao)?,
+            block_number: value.block_number.as_u64(),
+            gas_limit: value.gas_limit.as_u64(),
+            gas_used: value.gas_used.as_u64(),
+            timestamp: value.timestamp.as_u64(),
+            extra_data: encode_byte_list(&value.extra_data)?,
+            base_fee_per_gas: value.base_fee_per_gas.as_u64().into(),
+            block_hash: encode_hash(value.block_hash)?,
+            transactions: encode_tx_list(&value.transactions)?,
+        })
+    }
+}
+
+/// The Canyon/Shanghai [ExecutionPayload] - the withdrawals field should be an empty [List]
+#[derive(SimpleSerialize, Default)]
+struct ExecutionPayloadV2SSZ {
+    /// Block hash of the parent block
+    parent_hash: Bytes32,
+    /// Fee recipient of the block. Set to the sequencer fee vault
+    fee_recipient: VecAddress,
+    /// State root of the block
+    state_root: Bytes32,
+    /// Receipts root of the block
+    receipts_root: Bytes32,
+    /// Logs bloom of the block
+    logs_bloom: Vector<u8, 256>,
+    /// The block mix_digest
+    prev_randao: Bytes32,
+    /// The block number
+    block_number: u64,
+    /// The block gas limit
+    gas_limit: u64,
+    /// Total gas used in the block
+    gas_used: u64,
+    /// Timestamp of the block
+    timestamp: u64,
+    /// Any extra data included in the block
+    extra_data: List<u8, 32>,
+    /// Base fee per gas of the block
+    base_fee_per_gas: U256,
+    /// Hash of the block
+    block_hash: Bytes32,
+    /// Transactions in the block
+    transactions: List<Transaction, 1048576>,
+    /// An empty list. This is unused and only exists for L1 compatibility.
+    withdrawals: List<Withdrawal, 16>,
+}
+
+/// This represents an L1 validator Withdrawal, and is unused in OP stack rollups.
+/// Exists only for L1 compatibility
+#[derive(SimpleSerialize, Default)]
+struct Withdrawal {
+    /// Index of the withdrawal
+    index: u64,
+    /// Index of the validator
+    validator_index: u64,
+    /// Account address that has withdrawn
+    address: VecAddress,
+    /// The amount withdrawn
+    amount: u64,
+}
+
+impl From<ExecutionPayloadV2SSZ> for ExecutionPayload {
+    /// Converts an ExecutionPayloadV2SSZ received via p2p gossip into an [ExecutionPayload] used by the engine.
+    fn from(value: ExecutionPayloadV2SSZ) -> Self {
+        Self {
+            parent_hash: convert_hash(value.parent_hash),
+            fee_recipient: convert_address(value.fee_recipient),
+            state_root: convert_hash(value.state_root),
+            receipts_root: convert_hash(value.receipts_root),
+            logs_bloom: convert_byte_vector(value.logs_bloom),
+            prev_randao: convert_hash(value.prev_randao),
+            block_number: value.block_number.into(),
+            gas_limit: value.gas_limit.into(),
+            gas_used: value.gas_used.into(),
+            timestamp: value.timestamp.into(),
+            extra_data: convert_byte_list(value.extra_data),
+            base_fee_per_gas: convert_uint(value.base_fee_per_gas),
+            block_hash: convert_hash(value.block_hash),
+            transactions: convert_tx_list(value.transactions),
+            withdrawals: Some(Vec::new()),
+            blob_gas_used: None,
+            excess_blob_gas: None,
+            withdrawals_root: None,
+            parent_beacon_block_root: None,
+        }
+    }
+}
+
+impl TryFrom<&ExecutionPayload> for ExecutionPayloadV2SSZ {
+    type Error = eyre::Report;
+
+    fn try_from(value: &ExecutionPayload) -> Result<Self> {
+        let v1 = ExecutionPayloadV1SSZ::try_from(value)?;
+
+        Ok(Self {
+            parent_hash: v1.parent_hash,
+            fee_recipient: v1.fee_recipient,
+            state_root: v1.state_root,
+            receipts_root: v1.receipts_root,
+            logs_bloom: v1.logs_bloom,
+            prev_randao: v1.prev_randao,
+            block_number: v1.block_number,
+            gas_limit: v1.gas_limit,
+            gas_used: v1.gas_used,
+            timestamp: v1.timestamp,
+            extra_data: v1.extra_data,
+            base_fee_per_gas: v1.base_fee_per_gas,
+            block_hash: v1.block_hash,
+            transactions: v1.transactions,
+            withdrawals: List::default(),
+        })
+    }
+}
+
+/// The Ecotone [ExecutionPayload] - adds the blob gas fields, always zero on L2
+#[derive(SimpleSerialize, Default)]
+struct ExecutionPayloadV3SSZ {
+    parent_hash: Bytes32,
+    fee_recipient: VecAddress,
+    state_root: Bytes32,
+    receipts_root: Bytes32,
+    logs_bloom: Vector<u8, 256>,
+    prev_randao: Bytes32,
+    block_number: u64,
+    gas_limit: u64,
+    gas_used: u64,
+    timestamp: u64,
+    extra_data: List<u8, 32>,
+    base_fee_per_gas: U256,
+    block_hash: Bytes32,
+    transactions: List<Transaction, 1048576>,
+    withdrawals: List<Withdrawal, 16>,
+    blob_gas_used: u64,
+    excess_blob_gas: u64,
+}
+
+impl From<ExecutionPayloadV3SSZ> for ExecutionPayload {
+    fn from(value: ExecutionPayloadV3SSZ) -> Self {
+        Self {
+            parent_hash: convert_hash(value.parent_hash),
+            fee_recipient: convert_address(value.fee_recipient),
+            state_root: convert_hash(value.state_root),
+            receipts_root: convert_hash(value.receipts_root),
+            logs_bloom: convert_byte_vector(value.logs_bloom),
+            prev_randao: convert_hash(value.prev_randao),
+            block_number: value.block_number.into(),
+            gas_limit: value.gas_limit.into(),
+            gas_used: value.gas_used.into(),
+            timestamp: value.timestamp.into(),
+            extra_data: convert_byte_list(value.extra_data),
+            base_fee_per_gas: convert_uint(value.base_fee_per_gas),
+            block_hash: convert_hash(value.block_hash),
+            transactions: convert_tx_list(value.transactions),
+            withdrawals: Some(Vec::new()),
+            blob_gas_used: Some(value.blob_gas_used.into()),
+            excess_blob_gas: Some(value.excess_blob_gas.into()),
+            withdrawals_root: None,
+            parent_beacon_block_root: None,
+        }
+    }
+}
+
+impl TryFrom<&ExecutionPayload> for ExecutionPayloadV3SSZ {
+    type Error = eyre::Report;
+
+    fn try_from(value: &ExecutionPayload) -> Result<Self> {
+        let v2 = ExecutionPayloadV2SSZ::try_from(value)?;
+
+        Ok(Self {
+            parent_hash: v2.parent_hash,
+            fee_recipient: v2.fee_recipient,
+            state_root: v2.state_root,
+            receipts_root: v2.receipts_root,
+            logs_bloom: v2.logs_bloom,
+            prev_randao: v2.prev_randao,
+            block_number: v2.block_number,
+            gas_limit: v2.gas_limit,
+            gas_used: v2.gas_used,
+            timestamp: v2.timestamp,
+            extra_data: v2.extra_data,
+            base_fee_per_gas: v2.base_fee_per_gas,
+            block_hash: v2.block_hash,
+            transactions: v2.transactions,
+            withdrawals: v2.withdrawals,
+            blob_gas_used: value.blob_gas_used.unwrap_or_default().as_u64(),
+            excess_blob_gas: value.excess_blob_gas.unwrap_or_default().as_u64(),
+        })
+    }
+}
+
+/// The Isthmus [ExecutionPayload] - adds the storage root of the `L2ToL1MessagePasser`
+#[derive(SimpleSerialize, Default)]
+struct ExecutionPayloadV4SSZ {
+    parent_hash: Bytes32,
+    fee_recipient: VecAddress,
+    state_root: Bytes32,
+    receipts_root: Bytes32,
+    logs_bloom: Vector<u8, 256>,
+    prev_randao: Bytes32,
+    block_number: u64,
+    gas_limit: u64,
+    gas_used: u64,
+    timestamp: u64,
+    extra_data: List<u8, 32>,
+    base_fee_per_gas: U256,
+    block_hash: Bytes32,
+    transactions: List<Transaction, 1048576>,
+    withdrawals: List<Withdrawal, 16>,
+    blob_gas_used: u64,
+    excess_blob_gas: u64,
+    withdrawals_root: Bytes32,
+}
+
+impl From<ExecutionPayloadV4SSZ> for ExecutionPayload {
+    fn from(value: ExecutionPayloadV4SSZ) -> Self {
+        let withdrawals_root = convert_hash(value.withdrawals_root);
+        let v3 = ExecutionPayloadV3SSZ {
+            parent_hash: value.parent_hash,
+            fee_recipient: value.fee_recipient,
+            state_root: value.state_root,
+            receipts_root: value.receipts_root,
+            logs_bloom: value.logs_bloom,
+            prev_randao: value.prev_randao,
+            block_number: value.block_number,
+            gas_limit: value.gas_limit,
+            gas_used: value.gas_used,
+            timestamp: value.timestamp,
+            extra_data: value.extra_data,
+            base_fee_per_gas: value.base_fee_per_gas,
+            block_hash: value.block_hash,
+            transactions: value.transactions,
+            withdrawals: value.withdrawals,
+            blob_gas_used: value.blob_gas_used,
+            excess_blob_gas: value.excess_blob_gas,
+        };
+
+        Self {
+            withdrawals_root: Some(withdrawals_root),
+            ..ExecutionPayload::from(v3)
+        }
+    }
+}
+
+impl TryFrom<&ExecutionPayload> for ExecutionPayloadV4SSZ {
+    type Error = eyre::Report;
+
+    fn try_from(value: &ExecutionPayload) -> Result<Self> {
+        let v3 = ExecutionPayloadV3SSZ::try_from(value)?;
+        let withdrawals_root = value
+            .withdrawals_root
+            .ok_or(eyre::eyre!("isthmus payload without withdrawals root"))?;
+
+        Ok(Self {
+            parent_hash: v3.parent_hash,
+            fee_recipient: v3.fee_recipient,
+            state_root: v3.state_root,
+            receipts_root: v3.receipts_root,
+            logs_bloom: v3.logs_bloom,
+            prev_randao: v3.prev_randao,
+            block_number: v3.block_number,
+            gas_limit: v3.gas_limit,
+            gas_used: v3.gas_used,
+            timestamp: v3.timestamp,
+            extra_data: v3.extra_data,
+            base_fee_per_gas: v3.base_fee_per_gas,
+            block_hash: v3.block_hash,
+            transactions: v3.transactions,
+            withdrawals: v3.withdrawals,
+            blob_gas_used: v3.blob_gas_used,
+            excess_blob_gas: v3.excess_blob_gas,
+            withdrawals_root: encode_hash(withdrawals_root)?,
+        })
+    }
+}
+
+/// Converts [Bytes32] into [H256]
+fn convert_hash(bytes: Bytes32) -> H256 {
+    H256::from_slice(bytes.as_slice())
+}
+
+/// Converts [VecAddress] into [Address]
+fn convert_address(address: VecAddress) -> Address {
+    Address::from_slice(address.as_slice())
+}
+
+/// Converts an [ssz_rs::Vector] of bytes into [Bytes]
+fn convert_byte_vector<const N: usize>(vector: Vector<u8, N>) -> Bytes {
+    Bytes::from(vector.to_vec())
+}
+
+/// Converts an [ssz_rs::List] of bytes into [Bytes]
+fn convert_byte_list<const N: usize>(list: List<u8, N>) -> Bytes {
+    Bytes::from(list.to_vec())
+}
+
+/// Converts a [U256] into [ethers::types::U64]
+fn convert_uint(value: U256) -> ethers::types::U64 {
+    let bytes = value.to_bytes_le();
+    ethers::types::U256::from_little_endian(&bytes)
+        .as_u64()
+        .into()
+}
+
+/// Converts [ssz_rs::List] of [Transaction] into a vector of [RawTransaction]
+fn convert_tx_list(value: List<Transaction, 1048576>) -> Vec<RawTransaction> {
+    value.iter().map(|tx| RawTransaction(tx.to_vec())).collect()
+}
+
+/// Converts [H256] into [Bytes32]
+fn encode_hash(hash: H256) -> Result<Bytes32> {
+    encode_byte_vector(hash.as_bytes())
+}
+
+/// Converts [Address] into [VecAddress]
+fn encode_address(address: Address) -> Result<VecAddress> {
+    encode_byte_vector(address.as_bytes())
+}
+
+/// Converts bytes into an [ssz_rs::Vector] of bytes
+fn encode_byte_vector<const N: usize>(bytes: &[u8]) -> Result<Vector<u8, N>> {
+    Vector::try_from(bytes.to_vec()).map_err(|(_, err)| eyre::eyre!("{}", err))
+}
+
+/// Converts bytes into an [ssz_rs::List] of bytes
+fn encode_byte_list<const N: usize>(bytes: &[u8]) -> Result<List<u8, N>> {
+    List::try_from(bytes.to_vec()).map_err(|(_, err)| eyre::eyre!("{}", err))
+}
+
+/// Converts a vector of [RawTransaction] into an [ssz_rs::List] of [Transaction]
+fn encode_tx_list(txs: &[RawTransaction]) -> Result<List<Transaction, 1048576>> {
+    let txs = txs
+        .iter()
+        .map(|tx| encode_byte_list(&tx.0))
+        .collect::<Result<Vec<_>>>()?;
+
+    List::try_from(txs).map_err(|(_, err)| eyre::eyre!("{}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload(version: PayloadVersion) -> ExecutionPayload {
+        let mut payload = ExecutionPayload {
+            block_number: 5.into(),
+            logs_bloom: vec![0; 256].into(),
+            extra_data: vec![1, 2].into(),
+            base_fee_per_gas: 7.into(),
+            block_hash: H256::repeat_byte(3),
+            transactions: vec![RawTransaction(vec![4, 5, 6])],
+            ..Default::default()
+        };
+
+        if version >= PayloadVersion::V2 {
+            payload.withdrawals = Some(Vec::new());
+        }
+        if version >= PayloadVersion::V3 {
+            payload.blob_gas_used = Some(0.into());
+            payload.excess_blob_gas = Some(0.into());
+            payload.parent_beacon_block_root = Some(H256::repeat_byte(9));
+        }
+        if version >= PayloadVersion::V4 {
+            payload.withdrawals_root = Some(H256::repeat_byte(8));
+        }
+
+        payload
+    }
+
+    #[test]
+    fn test_ssz_round_trip() -> Result<()> {
+        let versions = [
+            PayloadVersion::V1,
+            PayloadVersion::V2,
+            PayloadVersion::V3,
+            PayloadVersion::V4,
+        ];
+
+        for version in versions {
+            let envelope = PayloadEnvelope::new(payload(version));
+            let data = envelope.encode_ssz(version)?;
+
+            let number = PayloadVersion::try_from(version.number())?;
+            assert_eq!(PayloadEnvelope::decode_ssz(number, &data)?, envelope);
+        }
+
+        // the root is required from Ecotone, and the withdrawals root from Isthmus
+        let envelope = PayloadEnvelope::new(payload(PayloadVersion::V2));
+        assert!(envelope.encode_ssz(PayloadVersion::V3).is_err());
+        let envelope = PayloadEnvelope::new(payload(PayloadVersion::V3));
+        assert!(envelope.encode_ssz(PayloadVersion::V4).is_err());
+
+        assert!(PayloadEnvelope::decode_ssz(PayloadVersion::V3, &[0; 16]).is_err());
+        assert!(PayloadVersion::try_from(4).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_round_trip() -> Result<()> {
+        let envelope = PayloadEnvelope {
+            block_value: Some(100.into()),
+            blobs_bundle: Some(BlobsBundle::default()),
+            should_override_builder: Some(false),
+            ..PayloadEnvelope::new(payload(PayloadVersion::V3))
+        };
+
+        let json = serde_json::to_value(&envelope)?;
+        assert_eq!(
+            json["parentBeaconBlockRoot"],
+            serde_json::to_value(H256::repeat_byte(9))?
+        );
+        assert_eq!(json["blobsBundle"]["commitments"], serde_json::json!([]));
+        assert!(json["executionPayload"]
+            .get("parentBeaconBlockRoot")
+            .is_none());
+        assert!(json.get("executionRequests").is_none());
+
+        let decoded: PayloadEnvelope = serde_json::from_value(json)?;
+        assert_eq!(decoded.into_payload(), envelope.execution_payload);
+
+        // fields added in later versions are optional
+        let json = serde_json::json!({ "executionPayload": envelope.execution_payload });
+        let decoded: PayloadEnvelope = serde_json::from_value(json)?;
+        assert!(decoded.parent_beacon_block_root.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_payload_version() {
+        let hardforks = HardforkSchedule {
+            canyon_time: 10,
+            ecotone_time: 20,
+            isthmus_time: Some(30),
+            ..Default::default()
+        };
+
+        assert_eq!(PayloadVersion::at(&hardforks, 9), PayloadVersion::V1);
+        assert_eq!(PayloadVersion::at(&hardforks, 10), PayloadVersion::V2);
+        assert_eq!(PayloadVersion::at(&hardforks, 29), PayloadVersion::V3);
+        assert_eq!(PayloadVersion::at(&hardforks, 30), PayloadVersion::V4);
+    }
+}
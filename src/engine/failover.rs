@@ -0,0 +1,311 @@
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
+        Arc, Weak,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+use tokio::time::{interval, MissedTickBehavior};
+
+use crate::{config::Config, telemetry::metrics};
+
+use super::{
+    Engine, EngineApi, ExecutionPayload, ForkChoiceUpdate, ForkchoiceState, JwtSecretFile,
+    PayloadAttributes, PayloadId, PayloadStatus,
+};
+
+/// Consecutive failed requests after which the active engine is failed over
+const FAILOVER_THRESHOLD: u32 = 3;
+
+/// How often the health of all engine endpoints is checked
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// An [Engine] backed by a primary and any number of standby execution clients.
+///
+/// All requests go to the active endpoint. After [FAILOVER_THRESHOLD] consecutive
+/// failures, or if a health check finds the active endpoint unhealthy while a standby
+/// is healthy, requests are switched to the next endpoint. This allows the execution
+/// client to be upgraded without downtime.
+#[derive(Debug)]
+pub struct EngineFailover {
+    /// The engine endpoints, the first being the primary
+    engines: Vec<EngineApi>,
+    /// The result of the latest health check of each endpoint
+    healthy: Vec<AtomicBool>,
+    /// Index of the active endpoint
+    active: AtomicUsize,
+    /// Consecutive failed requests to the active endpoint
+    failures: AtomicU32,
+    /// The rotatable JWT secret file shared by all endpoints, if configured
+    secret_file: Option<Arc<JwtSecretFile>>,
+}
+
+/// The status of a single engine endpoint
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EngineEndpointStatus {
+    /// The engine url
+    pub url: String,
+    /// Whether requests are currently sent to this endpoint
+    pub active: bool,
+    /// Whether the latest health check succeeded
+    pub healthy: bool,
+}
+
+impl EngineFailover {
+    /// Creates a new [EngineFailover]. The first engine is the primary.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if no engines are given.
+    pub fn new(engines: Vec<EngineApi>) -> Self {
+        assert!(!engines.is_empty(), "at least one engine is required");
+
+        let healthy = engines.iter().map(|_| AtomicBool::new(true)).collect();
+
+        Self {
+            engines,
+            healthy,
+            active: AtomicUsize::new(0),
+            failures: AtomicU32::new(0),
+            secret_file: None,
+        }
+    }
+
+    /// Creates a new [EngineFailover] from the primary and standby engine urls in the [Config].
+    /// If a JWT secret file is configured, all endpoints authenticate with the secret in the file.
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let secret_file = config.jwt_secret_file.as_ref().and_then(|path| {
+            JwtSecretFile::load(path)
+                .map(Arc::new)
+                .map_err(|err| tracing::warn!("could not load jwt secret file {}: {}", path, err))
+                .ok()
+        });
+
+        let engines = std::iter::once(&config.l2_engine_url)
+            .chain(&config.l2_engine_standby_urls)
+            .map(|url| {
+                let engine = EngineApi::from_config(url, config)?;
+                Ok(match &secret_file {
+                    Some(secret_file) => engine.with_secret_file(secret_file.clone()),
+                    None => engine,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            secret_file,
+            ..Self::new(engines)
+        })
+    }
+
+    /// Reloads the JWT secret file on `SIGHUP`, if one is configured
+    pub fn spawn_secret_reloads(&self) -> Result<()> {
+        match &self.secret_file {
+            Some(secret_file) => secret_file.spawn_reload_on_sighup(),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the active engine
+    pub fn active(&self) -> &EngineApi {
+        &self.engines[self.active.load(Ordering::SeqCst)]
+    }
+
+    /// Returns the status of all engine endpoints
+    pub fn status(&self) -> Vec<EngineEndpointStatus> {
+        let active = self.active.load(Ordering::SeqCst);
+
+        self.engines
+            .iter()
+            .zip(&self.healthy)
+            .enumerate()
+            .map(|(i, (engine, healthy))| EngineEndpointStatus {
+                url: engine.base_url.clone(),
+                active: i == active,
+                healthy: healthy.load(Ordering::SeqCst),
+            })
+            .collect()
+    }
+
+    /// Switches requests to the endpoint at the given index
+    pub fn switch_to(&self, index: usize) -> Result<()> {
+        let engine = self
+            .engines
+            .get(index)
+            .ok_or(eyre::eyre!("no engine at index {}", index))?;
+
+        let previous = self.active.swap(index, Ordering::SeqCst);
+        self.failures.store(0, Ordering::SeqCst);
+
+        if previous != index {
+            tracing::warn!("switched engine to {}", engine.base_url);
+            metrics::ACTIVE_ENGINE.set(index as i64);
+        }
+
+        Ok(())
+    }
+
+    /// Checks the health of all endpoints, failing over if the active endpoint is
+    /// unhealthy and a standby is healthy
+    pub async fn check_health(&self) {
+        for (engine, healthy) in self.engines.iter().zip(&self.healthy) {
+            let res = engine.check_health().await;
+
+            if let Err(err) = &res {
+                tracing::debug!("engine {} unhealthy: {}", engine.base_url, err);
+            }
+
+            healthy.store(res.is_ok(), Ordering::SeqCst);
+        }
+
+        let active = self.active.load(Ordering::SeqCst);
+        if self.healthy[active].load(Ordering::SeqCst) {
+            return;
+        }
+
+        if let Some(next) = self.next_healthy(active) {
+            _ = self.switch_to(next);
+        }
+    }
+
+    /// Periodically checks the health of all endpoints until the failover is dropped
+    pub fn spawn_health_checks(self: &Arc<Self>) {
+        // standbys are pointless to monitor without anything to fail over to
+        if self.engines.len() < 2 {
+            return;
+        }
+
+        let failover: Weak<Self> = Arc::downgrade(self);
+
+        tokio::spawn(async move {
+            let mut ticker = interval(HEALTH_CHECK_INTERVAL);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+            loop {
+                ticker.tick().await;
+
+                match failover.upgrade() {
+                    Some(failover) => failover.check_health().await,
+                    None => break,
+                }
+            }
+        });
+    }
+
+    /// Returns the index of the first healthy endpoint after the given one
+    fn next_healthy(&self, from: usize) -> Option<usize> {
+        (1..self.engines.len())
+            .map(|offset| (from + offset) % self.engines.len())
+            .find(|&i| self.healthy[i].load(Ordering::SeqCst))
+    }
+
+    /// Sends a request to the active endpoint, tracking consecutive failures
+    async fn call<'a, T, F, Fut>(&'a self, request: F) -> Result<T>
+    where
+        F: FnOnce(&'a EngineApi) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let active = self.active.load(Ordering::SeqCst);
+        let res = request(&self.engines[active]).await;
+
+        if res.is_ok() {
+            self.failures.store(0, Ordering::SeqCst);
+            return res;
+        }
+
+        let failures = self.failures.fetch_add(1, Ordering::SeqCst) + 1;
+        if failures >= FAILOVER_THRESHOLD && self.engines.len() > 1 {
+            self.healthy[active].store(false, Ordering::SeqCst);
+
+            let next = self
+                .next_healthy(active)
+                .unwrap_or((active + 1) % self.engines.len());
+
+            // another request may have failed over already
+            if self
+                .active
+                .compare_exchange(active, next, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+            {
+                self.failures.store(0, Ordering::SeqCst);
+                tracing::warn!(
+                    "engine {} failed {} consecutive requests, failing over to {}",
+                    self.engines[active].base_url,
+                    failures,
+                    self.engines[next].base_url
+                );
+                metrics::ACTIVE_ENGINE.set(next as i64);
+            }
+        }
+
+        res
+    }
+}
+
+#[async_trait]
+impl Engine for EngineFailover {
+    async fn forkchoice_updated(
+        &self,
+        forkchoice_state: ForkchoiceState,
+        payload_attributes: Option<PayloadAttributes>,
+    ) -> Result<ForkChoiceUpdate> {
+        self.call(|engine| engine.forkchoice_updated(forkchoice_state, payload_attributes))
+            .await
+    }
+
+    async fn new_payload(&self, execution_payload: ExecutionPayload) -> Result<PayloadStatus> {
+        self.call(|engine| engine.new_payload(execution_payload))
+            .await
+    }
+
+    async fn get_payload(&self, payload_id: PayloadId) -> Result<ExecutionPayload> {
+        self.call(|engine| engine.get_payload(payload_id)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "f79ae8046bc11c9927afe911db7143c51a806c4a537cc08e0d37140b0192f430";
+
+    fn failover() -> EngineFailover {
+        // nothing listens on these ports, so every request fails
+        EngineFailover::new(vec![
+            EngineApi::new("http://127.0.0.1:1", SECRET),
+            EngineApi::new("http://127.0.0.1:2", SECRET),
+        ])
+    }
+
+    #[tokio::test]
+    async fn test_failover_on_persistent_errors() {
+        let failover = failover();
+
+        for _ in 0..FAILOVER_THRESHOLD - 1 {
+            assert!(failover.get_payload(Default::default()).await.is_err());
+            assert_eq!(failover.active().base_url, "http://127.0.0.1:1");
+        }
+
+        assert!(failover.get_payload(Default::default()).await.is_err());
+        assert_eq!(failover.active().base_url, "http://127.0.0.1:2");
+
+        let status = failover.status();
+        assert!(!status[0].active && !status[0].healthy);
+        assert!(status[1].active && status[1].healthy);
+    }
+
+    #[test]
+    fn test_switch_to() {
+        let failover = failover();
+
+        failover.switch_to(1).unwrap();
+        assert_eq!(failover.active().base_url, "http://127.0.0.1:2");
+        assert!(failover.switch_to(2).is_err());
+        assert_eq!(failover.active().base_url, "http://127.0.0.1:2");
+    }
+}
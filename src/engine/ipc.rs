@@ -0,0 +1,171 @@
+use std::path::{Path, PathBuf};
+
+use eyre::Result;
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::UnixStream,
+    sync::Mutex,
+    time::timeout,
+};
+
+use super::ENGINE_IPC_TIMEOUT;
+
+/// The url scheme selecting the IPC transport
+pub const IPC_SCHEME: &str = "ipc://";
+
+/// A JSON-RPC client connected to the IPC socket of a co-located execution client.
+///
+/// Requests are sent one at a time over a single connection. If a request fails, the
+/// connection is dropped and re-established on the next request, so a restarted
+/// execution client is picked up transparently.
+#[derive(Debug)]
+pub struct IpcClient {
+    /// Path to the IPC socket
+    path: PathBuf,
+    /// The open connection, if any
+    conn: Mutex<Option<BufReader<UnixStream>>>,
+}
+
+impl IpcClient {
+    /// Creates a new [IpcClient] for the socket at the given path. No connection is
+    /// made until the first request.
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            conn: Mutex::new(None),
+        }
+    }
+
+    /// Parses the socket path from an `ipc://` url
+    pub fn path_from_url(url: &str) -> Option<&str> {
+        url.strip_prefix(IPC_SCHEME)
+    }
+
+    /// Returns the path to the IPC socket
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Sends a JSON-RPC request body and waits for the response
+    pub async fn request<B, T>(&self, body: &B) -> Result<T>
+    where
+        B: Serialize,
+        T: DeserializeOwned,
+    {
+        let mut conn = self.conn.lock().await;
+
+        let res = match timeout(ENGINE_IPC_TIMEOUT, self.send(&mut conn, body)).await {
+            Ok(res) => res,
+            Err(_) => Err(eyre::eyre!("ipc request timed out")),
+        };
+
+        if res.is_err() {
+            // a partially read response would corrupt later requests, so reconnect
+            *conn = None;
+        }
+
+        res
+    }
+
+    async fn send<B, T>(&self, conn: &mut Option<BufReader<UnixStream>>, body: &B) -> Result<T>
+    where
+        B: Serialize,
+        T: DeserializeOwned,
+    {
+        let stream = match conn {
+            Some(stream) => stream,
+            None => {
+                tracing::debug!("connecting to engine ipc socket {:?}", self.path);
+                let stream = UnixStream::connect(&self.path).await?;
+                conn.insert(BufReader::new(stream))
+            }
+        };
+
+        let mut req = serde_json::to_vec(body)?;
+        req.push(b'\n');
+        stream.get_mut().write_all(&req).await?;
+
+        read_response(stream).await
+    }
+}
+
+/// Reads a single JSON value from the stream. Responses are newline terminated, but a
+/// value may itself contain newlines, so lines are accumulated until the value is complete.
+async fn read_response<R, T>(reader: &mut R) -> Result<T>
+where
+    R: AsyncBufRead + Unpin,
+    T: DeserializeOwned,
+{
+    let mut buf = Vec::new();
+
+    loop {
+        if reader.read_until(b'\n', &mut buf).await? == 0 {
+            eyre::bail!("ipc connection closed");
+        }
+
+        match serde_json::from_slice(&buf) {
+            Ok(value) => return Ok(value),
+            Err(err) if err.is_eof() => continue,
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+    use tokio::net::UnixListener;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_ipc_request_and_reconnect() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("magi-engine-{}.ipc", std::process::id()));
+        _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path)?;
+
+        // answers a single request per connection, then hangs up
+        let server = tokio::spawn(async move {
+            for id in 0..2 {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut stream = BufReader::new(stream);
+                let mut line = String::new();
+                stream.read_line(&mut line).await.unwrap();
+
+                let req: Value = serde_json::from_str(&line).unwrap();
+                let res = format!(
+                    "{{\"jsonrpc\":\"2.0\",\n\"id\":{},\"result\":{}}}\n",
+                    id, req["params"][0]
+                );
+                stream.get_mut().write_all(res.as_bytes()).await.unwrap();
+            }
+        });
+
+        let client = IpcClient::new(&path);
+        let body = json!({"jsonrpc": "2.0", "id": 1, "method": "eth_chainId", "params": [10]});
+
+        let res: Value = client.request(&body).await?;
+        assert_eq!(res["result"], 10);
+
+        // the server closed the first connection
+        assert!(client.request::<_, Value>(&body).await.is_err());
+
+        let res: Value = client.request(&body).await?;
+        assert_eq!(res["id"], 1);
+
+        server.await?;
+        std::fs::remove_file(&path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_path_from_url() {
+        assert_eq!(
+            IpcClient::path_from_url("ipc:///data/geth.ipc"),
+            Some("/data/geth.ipc")
+        );
+        assert_eq!(IpcClient::path_from_url("http://localhost:8551"), None);
+    }
+}
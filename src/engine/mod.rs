@@ -5,16 +5,42 @@
 mod payload;
 pub use payload::*;
 
+/// Payload Envelope Encodings
+#[cfg(feature = "engine-client")]
+mod envelope;
+#[cfg(feature = "engine-client")]
+pub use envelope::*;
+
 /// Forkchoice Types
 mod fork;
 pub use fork::*;
 
 /// The Engine Drive
+#[cfg(feature = "engine-client")]
 mod api;
+#[cfg(feature = "engine-client")]
 pub use api::*;
 
+/// IPC Transport
+#[cfg(feature = "engine-client")]
+mod ipc;
+#[cfg(feature = "engine-client")]
+pub use ipc::*;
+
+/// Engine Failover
+#[cfg(feature = "engine-client")]
+mod failover;
+#[cfg(feature = "engine-client")]
+pub use failover::*;
+
+/// In-process Engine
+mod channel;
+pub use channel::*;
+
 /// Auth module
+#[cfg(feature = "engine-client")]
 mod auth;
+#[cfg(feature = "engine-client")]
 pub use auth::*;
 
 /// Common Types
@@ -29,7 +55,7 @@ pub use traits::*;
 mod mock_engine;
 pub use mock_engine::*;
 
-#[cfg(test)]
+#[cfg(all(test, feature = "engine-client"))]
 mod tests {
     use crate::engine::EngineApi;
 
@@ -48,6 +48,14 @@ pub struct ExecutionPayload {
     /// None if not present (pre-Ecotone)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub excess_blob_gas: Option<U64>,
+    /// The storage root of the `L2ToL1MessagePasser`. None if not present (pre-Isthmus)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub withdrawals_root: Option<H256>,
+    /// The beacon block root of the L1 origin, None if not present (pre-Ecotone). This value is
+    /// passed alongside the payload in `engine_newPayloadV3` and is skipped during serialization
+    /// and deserialization.
+    #[serde(skip)]
+    pub parent_beacon_block_root: Option<H256>,
 }
 
 impl TryFrom<Block<Transaction>> for ExecutionPayload {
@@ -64,9 +72,9 @@ impl TryFrom<Block<Transaction>> for ExecutionPayload {
 
         Ok(ExecutionPayload {
             parent_hash: value.parent_hash,
-            fee_recipient: ethers::types::Address::from_slice(
-                SystemAccounts::default().fee_vault.as_slice(),
-            ),
+            fee_recipient: value.author.unwrap_or_else(|| {
+                ethers::types::Address::from_slice(SystemAccounts::default().fee_vault.as_slice())
+            }),
             state_root: value.state_root,
             receipts_root: value.receipts_root,
             logs_bloom: value.logs_bloom.unwrap().as_bytes().to_vec().into(),
@@ -86,10 +94,25 @@ impl TryFrom<Block<Transaction>> for ExecutionPayload {
             withdrawals: Some(Vec::new()),
             blob_gas_used: value.blob_gas_used.map(|v| v.as_u64().into()),
             excess_blob_gas: value.excess_blob_gas.map(|v| v.as_u64().into()),
+            withdrawals_root: value.withdrawals_root,
+            parent_beacon_block_root: value.parent_beacon_block_root,
         })
     }
 }
 
+/// ## ExecutionPayloadBody
+///
+/// The transactions and withdrawals of a block, as returned by `engine_getPayloadBodiesByRangeV1`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecutionPayloadBody {
+    /// An array of transaction objects where each object is a byte list
+    pub transactions: Vec<RawTransaction>,
+    /// An array of beaconchain withdrawals. Always empty as this exists only for L1 compatibility
+    #[serde(default)]
+    pub withdrawals: Option<Vec<()>>,
+}
+
 /// ## PayloadAttributes
 ///
 /// L2 extended payload attributes for Optimism.
@@ -116,6 +139,10 @@ pub struct PayloadAttributes {
     /// to Canyon, this value is always None. After Canyon it is an empty array. Note that we use
     /// the () type here since we never have a non empty array.
     pub withdrawals: Option<Vec<()>>,
+    /// The beacon block root of the L1 origin. Prior to Ecotone, this value is always None and
+    /// is not serialized.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_beacon_block_root: Option<H256>,
     /// The batch epoch number from derivation. This value is not expected by the engine is skipped
     /// during serialization and deserialization.
     #[serde(skip)]
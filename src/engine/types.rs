@@ -12,17 +12,50 @@ pub const JSONRPC_VERSION: &str = "2.0";
 /// The new payload method string
 pub const ENGINE_NEW_PAYLOAD_V2: &str = "engine_newPayloadV2";
 
+/// The new payload method string post Ecotone
+pub const ENGINE_NEW_PAYLOAD_V3: &str = "engine_newPayloadV3";
+
+/// The new payload method string post Isthmus
+pub const ENGINE_NEW_PAYLOAD_V4: &str = "engine_newPayloadV4";
+
 /// The new payload timeout
 pub const ENGINE_NEW_PAYLOAD_TIMEOUT: Duration = Duration::from_secs(8);
 
 /// The get payload method string
 pub const ENGINE_GET_PAYLOAD_V2: &str = "engine_getPayloadV2";
 
+/// The get payload method string post Ecotone
+pub const ENGINE_GET_PAYLOAD_V3: &str = "engine_getPayloadV3";
+
+/// The get payload method string post Isthmus
+pub const ENGINE_GET_PAYLOAD_V4: &str = "engine_getPayloadV4";
+
 /// The get payload timeout
 pub const ENGINE_GET_PAYLOAD_TIMEOUT: Duration = Duration::from_secs(2);
 
 /// The forkchoice updated method string
 pub const ENGINE_FORKCHOICE_UPDATED_V2: &str = "engine_forkchoiceUpdatedV2";
 
+/// The forkchoice updated method string post Ecotone
+pub const ENGINE_FORKCHOICE_UPDATED_V3: &str = "engine_forkchoiceUpdatedV3";
+
 /// The forkchoice updated timeout
 pub const ENGINE_FORKCHOICE_UPDATED_TIMEOUT: Duration = Duration::from_secs(8);
+
+/// The get payload bodies by range method string
+pub const ENGINE_GET_PAYLOAD_BODIES_BY_RANGE_V1: &str = "engine_getPayloadBodiesByRangeV1";
+
+/// The exchange capabilities method string
+pub const ENGINE_EXCHANGE_CAPABILITIES: &str = "engine_exchangeCapabilities";
+
+/// The timeout for a single request over the IPC transport
+pub const ENGINE_IPC_TIMEOUT: Duration = Duration::from_secs(4);
+
+/// The error code of a forkchoice state the engine rejects as inconsistent
+pub const INVALID_FORKCHOICE_STATE: i64 = -38002;
+
+/// The error code of payload attributes the engine rejects as invalid
+pub const INVALID_PAYLOAD_ATTRIBUTES: i64 = -38003;
+
+/// The error code of a method version the engine does not support at the payload timestamp
+pub const UNSUPPORTED_FORK: i64 = -38005;
@@ -4,12 +4,20 @@ use eyre::Result;
 use serde::Deserialize;
 use serde_json::Value;
 
+use crate::common::http;
+
+use super::file_source;
+
 /// The blob fetcher is responsible for fetching blob data from the L1 beacon chain,
 /// along with relevant parsing and validation.
 ///
 /// Consensus layer info required for deriving the slot at which a specific blob was
 /// included in the beacon chain is fetched on the first call to [`Self::get_slot_from_time`]
 /// and cached for all subsequent calls.
+///
+/// If the beacon url is a `file://` url, the beacon API responses are instead read from
+/// the directory, each stored under its API path with a `.json` extension, such as
+/// `eth/v1/beacon/blob_sidecars/<slot>.json`.
 pub struct BlobFetcher {
     l1_beacon_url: String,
     client: reqwest::Client,
@@ -34,7 +42,8 @@ impl BlobFetcher {
     pub fn new(l1_beacon_url: String) -> Self {
         Self {
             l1_beacon_url,
-            client: reqwest::Client::new(),
+            client: http::client(http::BEACON_TIMEOUT)
+                .expect("reqwest::Client could not be built, TLS backend could not be initialized"),
             genesis_timestamp: AtomicU64::new(0),
             seconds_per_slot: AtomicU64::new(0),
         }
@@ -82,25 +91,18 @@ impl BlobFetcher {
 
     /// Fetch the blob sidecars for a given slot.
     pub async fn fetch_blob_sidecars(&self, slot: u64) -> Result<Vec<BlobSidecar>> {
-        let base_url = format!("{}/eth/v1/beacon/blob_sidecars", self.l1_beacon_url);
-        let full_url = format!("{}/{}", base_url, slot);
-
-        let res = self.client.get(full_url).send().await?.error_for_status()?;
-        let res = serde_json::from_slice::<Value>(&res.bytes().await?)?;
-        let res = res.get("data").ok_or(eyre::eyre!("No data in response"))?;
+        let res = self
+            .get(&format!("eth/v1/beacon/blob_sidecars/{}", slot))
+            .await?;
 
-        let blobs = serde_json::from_value::<Vec<BlobSidecar>>(res.clone())?;
+        let blobs = serde_json::from_value::<Vec<BlobSidecar>>(res)?;
 
         Ok(blobs)
     }
 
     /// Fetch the genesis timestamp from the beacon chain.
     pub async fn fetch_beacon_genesis_timestamp(&self) -> Result<u64> {
-        let base_url = format!("{}/eth/v1/beacon/genesis", self.l1_beacon_url);
-
-        let res = self.client.get(base_url).send().await?.error_for_status()?;
-        let res = serde_json::from_slice::<Value>(&res.bytes().await?)?;
-        let res = res.get("data").ok_or(eyre::eyre!("No data in response"))?;
+        let res = self.get("eth/v1/beacon/genesis").await?;
         let res = res.get("genesis_time").ok_or(eyre::eyre!("No time"))?;
 
         let genesis_time = res.as_str().ok_or(eyre::eyre!("Expected string"))?;
@@ -111,13 +113,27 @@ impl BlobFetcher {
 
     /// Fetch the beacon chain spec.
     pub async fn fetch_beacon_spec(&self) -> Result<Value> {
-        let base_url = format!("{}/eth/v1/config/spec", self.l1_beacon_url);
+        self.get("eth/v1/config/spec").await
+    }
+
+    /// Fetch the data of a beacon API response, reading it from the exported files if
+    /// the beacon url is a `file://` url.
+    async fn get(&self, path: &str) -> Result<Value> {
+        let res = match file_source::dir_from_url(&self.l1_beacon_url) {
+            Some(dir) => std::fs::read(dir.join(format!("{}.json", path)))?,
+            None => {
+                let url = format!("{}/{}", self.l1_beacon_url, path);
+                let res = self.client.get(url).send().await?.error_for_status()?;
+                res.bytes().await?.to_vec()
+            }
+        };
 
-        let res = self.client.get(base_url).send().await?.error_for_status()?;
-        let res = serde_json::from_slice::<Value>(&res.bytes().await?)?;
-        let res = res.get("data").ok_or(eyre::eyre!("No data in response"))?;
+        let mut res = serde_json::from_slice::<Value>(&res)?;
+        let res = res
+            .get_mut("data")
+            .ok_or(eyre::eyre!("No data in response"))?;
 
-        Ok(res.clone())
+        Ok(res.take())
     }
 }
 
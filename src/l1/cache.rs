@@ -0,0 +1,88 @@
+use std::sync::Mutex;
+
+use ethers::types::H256;
+use lru::LruCache;
+
+use crate::telemetry::metrics;
+
+/// A size limited cache of L1 data keyed by block hash, evicting the least recently
+/// used entries once full.
+///
+/// Since a block hash commits to the block contents, entries stay valid across reorgs
+/// and unfinalized data can be cached safely. Hits and misses are counted in the
+/// `l1_cache_hits` and `l1_cache_misses` metrics, labeled by the cache name.
+pub struct HashCache<T> {
+    /// The cache name used as the metrics label
+    name: &'static str,
+    /// The cached entries
+    entries: Mutex<LruCache<H256, T>>,
+}
+
+impl<T: Clone> HashCache<T> {
+    /// Creates a new [HashCache] holding at most `capacity` entries
+    pub fn new(name: &'static str, capacity: usize) -> Self {
+        Self {
+            name,
+            entries: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+
+    /// Returns the entry for the given block hash, if cached
+    pub fn get(&self, hash: &H256) -> Option<T> {
+        let value = self.entries().get(hash).cloned();
+
+        match value {
+            Some(_) => metrics::L1_CACHE_HITS.with_label_values(&[self.name]).inc(),
+            None => metrics::L1_CACHE_MISSES
+                .with_label_values(&[self.name])
+                .inc(),
+        }
+
+        value
+    }
+
+    /// Caches the entry for the given block hash
+    pub fn insert(&self, hash: H256, value: T) {
+        self.entries().put(hash, value);
+    }
+
+    /// Returns the number of cached entries
+    pub fn len(&self) -> usize {
+        self.entries().len()
+    }
+
+    /// Returns true if nothing is cached
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn entries(&self) -> std::sync::MutexGuard<'_, LruCache<H256, T>> {
+        self.entries.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lru_eviction() {
+        let cache = HashCache::new("test", 2);
+        let hash = H256::from_low_u64_be;
+
+        cache.insert(hash(1), 1);
+        cache.insert(hash(2), 2);
+
+        // touching the first entry makes the second the least recently used
+        assert_eq!(cache.get(&hash(1)), Some(1));
+        cache.insert(hash(3), 3);
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&hash(2)), None);
+        assert_eq!(cache.get(&hash(1)), Some(1));
+        assert_eq!(cache.get(&hash(3)), Some(3));
+
+        let hits = metrics::L1_CACHE_HITS.with_label_values(&["test"]).get();
+        assert_eq!(hits, 3);
+    }
+}
@@ -1,6 +1,9 @@
-use std::{collections::HashMap, sync::Arc, time::Duration};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
-use bytes::Bytes;
 use ethers::{
     providers::{Http, HttpRateLimitRetryPolicy, Middleware, Provider, RetryClient},
     types::{Address, Block, BlockNumber, Filter, Transaction, H256},
@@ -9,41 +12,55 @@ use ethers::{
 use eyre::Result;
 use once_cell::sync::Lazy;
 use reqwest::Url;
-use tokio::{spawn, sync::mpsc, task::JoinHandle, time::sleep};
+use tokio::{
+    spawn,
+    sync::mpsc,
+    task::JoinHandle,
+    time::{sleep, timeout},
+};
 
 use crate::{
-    common::BlockInfo,
+    common::{http, BlockInfo},
     config::{Config, SystemConfig},
-    derive::stages::attributes::UserDeposited,
+    derive::{stages::attributes::UserDeposited, DerivationError, Lookahead},
     l1::decode_blob_data,
+    telemetry::metrics,
 };
 
-use super::{l1_info::L1BlockInfo, BlobFetcher, L1Info, SystemConfigUpdate};
+use super::{
+    deposit_validation::{may_contain_deposits, validate_deposits, TRANSACTION_DEPOSITED_TOPIC},
+    file_source,
+    l1_info::L1BlockInfo,
+    shared::SharedL1,
+    supervision::{Heartbeat, RestartReason, WatcherRestarts, HEARTBEAT_INTERVAL, STALL_TIMEOUT},
+    BlobFetcher, FileClient, L1Info, RateLimitedClient, RateLimiter, SystemConfigUpdate,
+};
 
 static CONFIG_UPDATE_TOPIC: Lazy<H256> =
     Lazy::new(|| H256::from_slice(&keccak256("ConfigUpdate(uint256,uint8,bytes)")));
 
-static TRANSACTION_DEPOSITED_TOPIC: Lazy<H256> = Lazy::new(|| {
-    H256::from_slice(&keccak256(
-        "TransactionDeposited(address,address,uint256,bytes)",
-    ))
-});
-
 /// The transaction type used to identify transactions that carry blobs
 /// according to EIP 4844.
 const BLOB_CARRYING_TRANSACTION_TYPE: u64 = 3;
 
-/// The data contained in a batcher transaction.
-/// The actual source of this data can be either calldata or blobs.
-pub type BatcherTransactionData = Bytes;
+/// The transaction type of EIP 7702 set code transactions, which are never batcher
+/// transactions.
+const SET_CODE_TRANSACTION_TYPE: u64 = 4;
+
+pub use super::l1_info::BatcherTransactionData;
 
 /// Handles watching the L1 chain and monitoring for new blocks, deposits,
 /// and batcher transactions. The monitoring loop is spawned in a seperate
 /// task and communication happens via the internal channels. When ChainWatcher
 /// is dropped, the monitoring task is automatically aborted.
+///
+/// The task is supervised by [ChainWatcher::supervise], which restarts it after the
+/// latest received block if it died or stopped sending heartbeats.
 pub struct ChainWatcher {
     /// Task handle for the monitoring loop
     handle: Option<JoinHandle<()>>,
+    /// Heartbeat of the monitoring loop
+    heartbeat: Heartbeat,
     /// Global config
     config: Arc<Config>,
     /// The L1 starting block
@@ -52,6 +69,12 @@ pub struct ChainWatcher {
     l2_start_block: u64,
     /// Channel for receiving block updates for each new block
     block_update_receiver: Option<mpsc::Receiver<BlockUpdate>>,
+    /// The latest block received since the task was started at the starting block
+    last_block: Option<BlockInfo>,
+    /// The restarts of the monitoring task
+    restarts: WatcherRestarts,
+    /// Receives the batcher transactions of every fetched block, if set
+    lookahead: Option<Lookahead>,
 }
 
 /// Updates L1Info
@@ -59,9 +82,16 @@ pub enum BlockUpdate {
     /// A new block extending the current chain
     NewBlock(Box<L1Info>),
     /// Updates the most recent finalized block
-    FinalityUpdate(u64),
-    /// Reorg detected
-    Reorg,
+    FinalityUpdate(BlockInfo),
+    /// The newest block does not extend the previous head
+    Reorg {
+        /// The previous head, which is no longer canonical
+        old_head: BlockInfo,
+        /// The new head
+        new_head: BlockInfo,
+        /// The number of blocks between the old head and the common ancestor
+        depth: u64,
+    },
 }
 
 /// Watcher actually ingests the L1 blocks. Should be run in another
@@ -69,12 +99,16 @@ pub enum BlockUpdate {
 struct InnerWatcher {
     /// Global Config
     config: Arc<Config>,
+    /// L1 sources shared with other watchers on the same L1 chain
+    l1: Arc<SharedL1>,
     /// Ethers provider for L1
-    provider: Arc<Provider<RetryClient<Http>>>,
+    provider: Arc<Provider<RetryClient<RateLimitedClient>>>,
     /// L1 beacon node to fetch blobs
     blob_fetcher: Arc<BlobFetcher>,
     /// Channel to send block updates
     block_update_sender: mpsc::Sender<BlockUpdate>,
+    /// Heartbeat checked by the [ChainWatcher]
+    heartbeat: Heartbeat,
     /// Most recent ingested block
     current_block: u64,
     /// Most recent block
@@ -90,6 +124,8 @@ struct InnerWatcher {
     system_config: SystemConfig,
     /// Next system config if it exists and the L1 block number it activates
     system_config_update: (u64, Option<SystemConfig>),
+    /// Receives the batcher transactions of every fetched block, if set
+    lookahead: Option<Lookahead>,
 }
 
 impl Drop for ChainWatcher {
@@ -106,47 +142,148 @@ impl ChainWatcher {
     pub fn new(l1_start_block: u64, l2_start_block: u64, config: Arc<Config>) -> Result<Self> {
         Ok(Self {
             handle: None,
+            heartbeat: Heartbeat::default(),
             config,
             l1_start_block,
             l2_start_block,
             block_update_receiver: None,
+            last_block: None,
+            restarts: WatcherRestarts::default(),
+            lookahead: None,
         })
     }
 
+    /// Pushes the batcher transactions of every fetched block to the given [Lookahead], so
+    /// their channels are decoded while the block waits for derivation. Takes effect
+    /// when the monitoring task is started.
+    pub fn set_lookahead(&mut self, lookahead: Lookahead) {
+        self.lookahead = Some(lookahead);
+    }
+
     /// Starts the chain watcher at the given block numbers
     pub fn start(&mut self) -> Result<()> {
-        if let Some(handle) = self.handle.take() {
-            handle.abort();
+        self.spawn(self.l1_start_block, None)
+    }
+
+    /// Resets the chain watcher at the given block numbers
+    pub fn restart(&mut self, l1_start_block: u64, l2_start_block: u64) -> Result<()> {
+        self.l1_start_block = l1_start_block;
+        self.l2_start_block = l2_start_block;
+        self.last_block = None;
+
+        self.spawn(l1_start_block, None)
+    }
+
+    /// Restarts the monitoring task if it died or sent no heartbeat within the
+    /// [STALL_TIMEOUT], returning why. It resumes after the latest received block, so
+    /// block updates buffered by the previous task are fetched again.
+    pub fn supervise(&mut self) -> Result<Option<RestartReason>> {
+        let reason = match &self.handle {
+            None => return Ok(None),
+            Some(handle) if handle.is_finished() => RestartReason::Died,
+            Some(_) if self.heartbeat.elapsed() > STALL_TIMEOUT => RestartReason::Stalled,
+            Some(_) => return Ok(None),
+        };
+
+        self.respawn(reason)?;
+        Ok(Some(reason))
+    }
+
+    /// Restarts the monitoring task after the latest received block, as reported stalled by
+    /// the watchdog
+    pub fn restart_stalled(&mut self) -> Result<()> {
+        self.respawn(RestartReason::Stalled)
+    }
+
+    /// Returns the heartbeat of the monitoring task, kept across restarts
+    pub fn heartbeat(&self) -> Heartbeat {
+        self.heartbeat.clone()
+    }
+
+    /// Restarts the monitoring task after the latest received block and records the restart
+    fn respawn(&mut self, reason: RestartReason) -> Result<()> {
+        tracing::warn!(
+            "[l1] chain watcher {}, restarting after block {:?}",
+            reason.label(),
+            self.last_block
+        );
+
+        match self.last_block {
+            Some(last_block) => {
+                // the task resumes with the system config in effect after the block, which
+                // is only known while it is cached, so derivation resets otherwise
+                let l1 = SharedL1::get(&self.config);
+                let contract = self.config.chain.system_config_contract;
+                let system_config =
+                    l1.system_config(contract, last_block.hash).ok_or_else(|| {
+                        DerivationError::reset(format!(
+                            "system config after L1 block {} not cached",
+                            last_block.number
+                        ))
+                    })?;
+
+                self.spawn(last_block.number, Some((last_block, system_config)))?;
+            }
+            None => self.spawn(self.l1_start_block, None)?,
         }
 
-        let (handle, recv) = start_watcher(
-            self.l1_start_block,
-            self.l2_start_block,
-            self.config.clone(),
-        )?;
+        metrics::L1_WATCHER_RESTARTS
+            .with_label_values(&[&self.config.chain.l2_chain_id.to_string(), reason.label()])
+            .inc();
 
-        self.handle = Some(handle);
-        self.block_update_receiver = Some(recv);
+        self.restarts.count += 1;
+        self.restarts.last_reason = Some(reason);
+        self.restarts.last_restart = Some(now());
+        self.restarts.last_resumed_after = self.last_block.map(|block| block.number);
 
         Ok(())
     }
 
-    /// Resets the chain watcher at the given block numbers
-    pub fn restart(&mut self, l1_start_block: u64, l2_start_block: u64) -> Result<()> {
+    /// Returns the restarts of the monitoring task
+    pub fn restarts(&self) -> &WatcherRestarts {
+        &self.restarts
+    }
+
+    /// Spawns the monitoring task at the given block, replacing the running one. If
+    /// `resume` is set with the block and the system config in effect after it, the block was
+    /// already received and the task starts after it.
+    fn spawn(
+        &mut self,
+        l1_start_block: u64,
+        resume: Option<(BlockInfo, SystemConfig)>,
+    ) -> Result<()> {
         if let Some(handle) = self.handle.take() {
             handle.abort();
         }
 
-        let (handle, recv) = start_watcher(l1_start_block, l2_start_block, self.config.clone())?;
+        self.heartbeat.beat();
+        let (handle, recv) = start_watcher(
+            l1_start_block,
+            self.l2_start_block,
+            resume,
+            self.config.clone(),
+            self.heartbeat.clone(),
+            self.lookahead.clone(),
+        )?;
 
         self.handle = Some(handle);
         self.block_update_receiver = Some(recv);
-        self.l1_start_block = l1_start_block;
-        self.l2_start_block = l2_start_block;
 
         Ok(())
     }
 
+    /// Records the latest received block
+    fn received(&mut self, update: &BlockUpdate) {
+        if let BlockUpdate::NewBlock(l1_info) = update {
+            self.last_block = Some(BlockInfo {
+                hash: l1_info.block_info.hash,
+                number: l1_info.block_info.number,
+                parent_hash: l1_info.block_info.parent_hash,
+                timestamp: l1_info.block_info.timestamp,
+            });
+        }
+    }
+
     /// Attempts to receive a message from the block update channel.
     /// Returns an error if the channel contains no messages.
     pub fn try_recv_from_channel(&mut self) -> Result<BlockUpdate> {
@@ -155,33 +292,63 @@ impl ChainWatcher {
             .as_mut()
             .ok_or(eyre::eyre!("the watcher hasn't started"))?;
 
-        receiver.try_recv().map_err(eyre::Report::from)
+        let update = receiver.try_recv().map_err(eyre::Report::from)?;
+        self.received(&update);
+
+        Ok(update)
+    }
+
+    /// Returns the number of block updates waiting in the channel
+    pub fn pending_updates(&self) -> usize {
+        self.block_update_receiver
+            .as_ref()
+            .map_or(0, |receiver| receiver.len())
     }
 
     /// Asynchronously receives from the block update channel.
     /// Returns `None` if the channel contains no messages.
     pub async fn recv_from_channel(&mut self) -> Option<BlockUpdate> {
-        match &mut self.block_update_receiver {
+        let update = match &mut self.block_update_receiver {
             Some(receiver) => receiver.recv().await,
             None => None,
-        }
+        }?;
+
+        self.received(&update);
+        Some(update)
     }
 }
 
 impl InnerWatcher {
+    /// Creates a watcher ingesting from the given block. If `resume` is set with the
+    /// already received block and the system config in effect after it, the watcher
+    /// ingests from the block after it, detecting a reorg of the block.
     async fn new(
         config: Arc<Config>,
         block_update_sender: mpsc::Sender<BlockUpdate>,
+        heartbeat: Heartbeat,
         l1_start_block: u64,
         l2_start_block: u64,
+        resume: Option<(BlockInfo, SystemConfig)>,
     ) -> Self {
-        let provider = generate_http_provider(&config.l1_rpc_url);
-        let blob_fetcher = Arc::new(BlobFetcher::new(config.l1_beacon_url.clone()));
+        let l1 = SharedL1::get(&config);
+        let provider = l1.provider.clone();
+        let blob_fetcher = l1.blob_fetcher.clone();
+
+        let (resumed_block, resumed_config) = resume.unzip();
+        let cached = if resumed_config.is_some() {
+            resumed_config
+        } else if l2_start_block != config.chain.l2_genesis.number {
+            cached_system_config(&l1, config.chain.system_config_contract, l1_start_block).await
+        } else {
+            None
+        };
 
-        let system_config = if l2_start_block == config.chain.l2_genesis.number {
+        let system_config = if let Some(system_config) = cached {
+            system_config
+        } else if l2_start_block == config.chain.l2_genesis.number {
             config.chain.system_config
         } else {
-            let l2_provider = generate_http_provider(&config.l2_rpc_url);
+            let l2_provider = generate_http_provider(&config.l2_rpc_url, None);
 
             let block = l2_provider
                 .get_block_with_txs(l2_start_block - 1)
@@ -211,35 +378,37 @@ impl InnerWatcher {
                 gas_limit,
                 // TODO: fetch from contract
                 unsafe_block_signer: config.chain.system_config.unsafe_block_signer,
+                operator_fee_params: Default::default(),
             }
         };
 
         Self {
             config,
+            l1,
             provider,
             blob_fetcher,
             block_update_sender,
-            current_block: l1_start_block,
+            heartbeat,
+            current_block: l1_start_block + resumed_block.is_some() as u64,
             head_block: 0,
             finalized_block: 0,
-            unfinalized_blocks: Vec::new(),
+            unfinalized_blocks: resumed_block.into_iter().collect(),
             deposits: HashMap::new(),
             system_config,
             system_config_update: (l1_start_block, None),
+            lookahead: None,
         }
     }
 
     async fn try_ingest_block(&mut self) -> Result<()> {
         if self.current_block > self.finalized_block {
-            let finalized_block = self.get_finalized().await?;
+            let finalized = self.get_finalized().await?;
 
             // Only update finalized block if it has changed to avoid spamming the channel.
-            if self.finalized_block < finalized_block {
-                tracing::debug!("[l1] finalized block updated to {}", finalized_block);
-                self.finalized_block = finalized_block;
-                self.block_update_sender
-                    .send(BlockUpdate::FinalityUpdate(finalized_block))
-                    .await?;
+            if self.finalized_block < finalized.number {
+                tracing::debug!("[l1] finalized block updated to {}", finalized.number);
+                self.finalized_block = finalized.number;
+                self.send(BlockUpdate::FinalityUpdate(finalized)).await?;
 
                 self.unfinalized_blocks
                     .retain(|b| b.number > self.finalized_block)
@@ -252,17 +421,26 @@ impl InnerWatcher {
         }
 
         if self.current_block <= self.head_block {
-            self.update_system_config().await?;
+            self.scan_logs().await?;
+            self.apply_system_config_update();
 
             let block = self.get_block(self.current_block).await?;
-            let user_deposits = self.get_deposits(self.current_block).await?;
+            let user_deposits = self.get_deposits(self.current_block)?;
+            self.verify_deposits(&block, &user_deposits).await?;
             let batcher_transactions = self.get_batcher_transactions(&block).await?;
 
             let finalized = self.current_block >= self.finalized_block;
 
+            let block_info = L1BlockInfo::from_block(&block, self.config.chain.l1_chain_id)?;
+            self.l1.insert_system_config(
+                self.config.chain.system_config_contract,
+                block_info.hash,
+                self.system_config,
+            );
+
             let l1_info = L1Info {
                 system_config: self.system_config,
-                block_info: L1BlockInfo::try_from(&block)?,
+                block_info,
                 batcher_transactions,
                 user_deposits,
                 finalized,
@@ -280,12 +458,20 @@ impl InnerWatcher {
             }
 
             let update = if self.check_reorg() {
-                BlockUpdate::Reorg
+                self.reorg_update().await?
             } else {
+                if let Some(lookahead) = &self.lookahead {
+                    lookahead.push(
+                        l1_info.batcher_transactions.clone(),
+                        l1_info.block_info.number,
+                        l1_info.block_info.timestamp,
+                    );
+                }
+
                 BlockUpdate::NewBlock(Box::new(l1_info))
             };
 
-            self.block_update_sender.send(update).await?;
+            self.send(update).await?;
 
             self.current_block += 1;
         } else {
@@ -295,11 +481,33 @@ impl InnerWatcher {
         Ok(())
     }
 
-    async fn update_system_config(&mut self) -> Result<()> {
+    /// Sends a block update, beating the heartbeat while the channel is full so waiting
+    /// for derivation to catch up is not mistaken for a stuck task
+    async fn send(&self, update: BlockUpdate) -> Result<()> {
+        if self.block_update_sender.capacity() == 0 {
+            tracing::debug!("[l1] waiting for derivation to catch up");
+        }
+
+        loop {
+            match timeout(HEARTBEAT_INTERVAL, self.block_update_sender.reserve()).await {
+                Ok(permit) => {
+                    permit?.send(update);
+                    return Ok(());
+                }
+                Err(_) => self.heartbeat.beat(),
+            }
+        }
+    }
+
+    /// Scans the logs of the system config updates and deposits due at the current block,
+    /// sending both scans in one batch
+    async fn scan_logs(&mut self) -> Result<()> {
         let (last_update_block, _) = self.system_config_update;
+        let start_block = self.current_block;
 
-        if last_update_block < self.current_block {
-            let to_block = last_update_block + 1000;
+        let config_scan = (last_update_block < start_block).then(|| {
+            // Never search past the head, since updates in later blocks would be missed
+            let to_block = (last_update_block + 1000).min(self.head_block);
             let filter = Filter::new()
                 .address(ethers::types::Address::from_slice(
                     self.config.chain.system_config_contract.as_slice(),
@@ -308,37 +516,46 @@ impl InnerWatcher {
                 .from_block(last_update_block + 1)
                 .to_block(to_block);
 
-            let updates = self.provider.get_logs(&filter).await?;
-            let update = updates.into_iter().next();
+            (to_block, filter)
+        });
 
-            let update_block = update.as_ref().and_then(|update| update.block_number);
-            let update = update.and_then(|update| SystemConfigUpdate::try_from(update).ok());
+        let deposit_scan = (!self.deposits.contains_key(&start_block)).then(|| {
+            let end_block = self.head_block.min(start_block + 1000);
+            let filter = Filter::new()
+                .address(ethers::types::Address::from_slice(
+                    self.config.chain.deposit_contract.as_slice(),
+                ))
+                .topic0(*TRANSACTION_DEPOSITED_TOPIC)
+                .from_block(start_block)
+                .to_block(end_block);
+
+            (end_block, filter)
+        });
+
+        let filters = config_scan
+            .iter()
+            .chain(&deposit_scan)
+            .map(|(_, filter)| filter.clone())
+            .collect::<Vec<_>>();
+
+        if filters.is_empty() {
+            return Ok(());
+        }
 
-            if let Some((update_block, update)) = update_block.zip(update) {
+        let mut logs = self.l1.get_logs(&filters).await?.into_iter();
+
+        if let Some((to_block, _)) = config_scan {
+            let updates = logs.next().unwrap_or_default();
+            let update_block = updates.first().and_then(|update| update.block_number);
+
+            if let Some(update_block) = update_block {
+                // All updates emitted in the same block activate together
                 let mut config = self.system_config;
-                match update {
-                    SystemConfigUpdate::BatchSender(addr) => {
-                        config.batch_sender =
-                            alloy_primitives::Address::from_slice(addr.as_bytes());
-                    }
-                    SystemConfigUpdate::Fees(overhead, scalar) => {
-                        let mut oh: [u8; 32] = [0; 32];
-                        overhead.to_big_endian(&mut oh);
-                        config.l1_fee_overhead = alloy_primitives::U256::from_be_bytes(oh);
-                        let mut s: [u8; 32] = [0; 32];
-                        scalar.to_big_endian(&mut s);
-                        config.l1_fee_scalar = alloy_primitives::U256::from_be_bytes(s);
-                    }
-                    SystemConfigUpdate::Gas(gas) => {
-                        let mut g: [u8; 32] = [0; 32];
-                        gas.to_big_endian(&mut g);
-                        config.gas_limit = alloy_primitives::U256::from_be_bytes(g);
-                    }
-                    SystemConfigUpdate::UnsafeBlockSigner(addr) => {
-                        config.unsafe_block_signer =
-                            alloy_primitives::Address::from_slice(addr.as_bytes());
-                    }
-                }
+                updates
+                    .into_iter()
+                    .take_while(|update| update.block_number == Some(update_block))
+                    .filter_map(|update| SystemConfigUpdate::try_from(update).ok())
+                    .for_each(|update| update.apply(&mut config));
 
                 self.system_config_update = (update_block.as_u64(), Some(config));
             } else {
@@ -346,6 +563,31 @@ impl InnerWatcher {
             }
         }
 
+        if let Some((end_block, _)) = deposit_scan {
+            let deposit_logs = logs
+                .next()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|log| UserDeposited::try_from(log).unwrap())
+                .collect::<Vec<UserDeposited>>();
+
+            for num in start_block..=end_block {
+                let deposits = deposit_logs
+                    .iter()
+                    .filter(|d| d.l1_block_num == num)
+                    .cloned()
+                    .collect();
+
+                self.deposits.insert(num, deposits);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Activates the pending system config if it takes effect at the current block.
+    /// Updates emitted in a block apply to the batcher transactions of that same block.
+    fn apply_system_config_update(&mut self) {
         let (last_update_block, next_config) = self.system_config_update;
 
         if last_update_block == self.current_block {
@@ -355,8 +597,6 @@ impl InnerWatcher {
                 self.system_config = next_config;
             }
         }
-
-        Ok(())
     }
 
     fn check_reorg(&self) -> bool {
@@ -370,20 +610,62 @@ impl InnerWatcher {
         }
     }
 
-    async fn get_finalized(&self) -> Result<u64> {
+    /// Builds the reorg update for the newest block by walking back the unfinalized
+    /// blocks until one is still part of the canonical chain
+    async fn reorg_update(&self) -> Result<BlockUpdate> {
+        let len = self.unfinalized_blocks.len();
+        let new_head = self.unfinalized_blocks[len - 1];
+        let old_head = self.unfinalized_blocks[len - 2];
+
+        // if no unfinalized block is canonical, the reorg reaches past the oldest one
+        let mut ancestor = self.unfinalized_blocks[0].number.saturating_sub(1);
+        for block in self.unfinalized_blocks[..len - 1].iter().rev() {
+            let canonical = self.provider.get_block(block.number).await?;
+            if canonical.and_then(|b| b.hash) == Some(block.hash) {
+                ancestor = block.number;
+                break;
+            }
+        }
+
+        let depth = old_head.number.saturating_sub(ancestor);
+
+        tracing::warn!(
+            "[l1] reorg of depth {} detected: block {} {:?} replaced by {} {:?}",
+            depth,
+            old_head.number,
+            old_head.hash,
+            new_head.number,
+            new_head.hash
+        );
+
+        metrics::L1_REORGS.inc();
+        metrics::L1_REORG_DEPTH.set(depth as i64);
+
+        Ok(BlockUpdate::Reorg {
+            old_head,
+            new_head,
+            depth,
+        })
+    }
+
+    async fn get_finalized(&self) -> Result<BlockInfo> {
         let block_number = match self.config.devnet {
             false => BlockNumber::Finalized,
             true => BlockNumber::Latest,
         };
 
-        Ok(self
+        let block = self
             .provider
             .get_block(block_number)
             .await?
-            .ok_or(eyre::eyre!("block not found"))?
-            .number
-            .ok_or(eyre::eyre!("block pending"))?
-            .as_u64())
+            .ok_or(eyre::eyre!("block not found"))?;
+
+        Ok(BlockInfo {
+            hash: block.hash.ok_or(eyre::eyre!("block pending"))?,
+            number: block.number.ok_or(eyre::eyre!("block pending"))?.as_u64(),
+            parent_hash: block.parent_hash,
+            timestamp: block.timestamp.as_u64(),
+        })
     }
 
     async fn get_head(&self) -> Result<u64> {
@@ -398,47 +680,38 @@ impl InnerWatcher {
     }
 
     async fn get_block(&self, block_num: u64) -> Result<Block<Transaction>> {
-        self.provider
-            .get_block_with_txs(block_num)
-            .await?
-            .ok_or(eyre::eyre!("block not found"))
-    }
-
-    async fn get_deposits(&mut self, block_num: u64) -> Result<Vec<UserDeposited>> {
-        match self.deposits.remove(&block_num) {
-            Some(deposits) => Ok(deposits),
-            None => {
-                let end_block = self.head_block.min(block_num + 1000);
-
-                let deposit_filter = Filter::new()
-                    .address(ethers::types::Address::from_slice(
-                        self.config.chain.deposit_contract.as_slice(),
-                    ))
-                    .topic0(*TRANSACTION_DEPOSITED_TOPIC)
-                    .from_block(block_num)
-                    .to_block(end_block);
-
-                let deposit_logs = self
-                    .provider
-                    .get_logs(&deposit_filter)
-                    .await?
-                    .into_iter()
-                    .map(|log| UserDeposited::try_from(log).unwrap())
-                    .collect::<Vec<UserDeposited>>();
+        self.l1
+            .get_block_with_txs(block_num, block_num <= self.finalized_block)
+            .await
+    }
 
-                for num in block_num..=end_block {
-                    let deposits = deposit_logs
-                        .iter()
-                        .filter(|d| d.l1_block_num == num)
-                        .cloned()
-                        .collect();
+    /// Returns the deposits of a block, which must have been scanned by [Self::scan_logs]
+    fn get_deposits(&mut self, block_num: u64) -> Result<Vec<UserDeposited>> {
+        self.deposits
+            .remove(&block_num)
+            .ok_or(eyre::eyre!("deposits of block {} not scanned", block_num))
+    }
 
-                    self.deposits.insert(num, deposits);
-                }
+    /// Verifies the deposits of a block against its receipts. Blocks whose logs bloom
+    /// rules out deposits are skipped to avoid fetching receipts for every block.
+    async fn verify_deposits(
+        &self,
+        block: &Block<Transaction>,
+        deposits: &[UserDeposited],
+    ) -> Result<()> {
+        let deposit_contract =
+            ethers::types::Address::from_slice(self.config.chain.deposit_contract.as_slice());
 
-                Ok(self.deposits.remove(&block_num).unwrap())
-            }
+        if deposits.is_empty() && !may_contain_deposits(block, deposit_contract) {
+            return Ok(());
         }
+
+        let receipts = self.l1.get_block_receipts(block).await?;
+
+        validate_deposits(block, &receipts, deposits, deposit_contract).map_err(|err| {
+            tracing::error!("rejecting L1 block {}: {}", self.current_block, err);
+            err
+        })
     }
 
     /// Given a block, return a list of [`BatcherTransactionData`] containing either the
@@ -447,37 +720,27 @@ impl InnerWatcher {
         &self,
         block: &Block<Transaction>,
     ) -> Result<Vec<BatcherTransactionData>> {
-        let mut batcher_transactions_data = Vec::new();
-        let mut indexed_blobs = Vec::new();
-        let mut blob_index = 0;
-
-        for tx in block.transactions.iter() {
-            let tx_blob_hashes: Vec<String> = tx
-                .other
-                .get_deserialized("blobVersionedHashes")
-                .unwrap_or(Ok(Vec::new()))
-                .unwrap_or_default();
-
-            if !self.is_valid_batcher_transaction(tx) {
-                blob_index += tx_blob_hashes.len();
-                continue;
-            }
-
-            let tx_type = tx.transaction_type.map(|t| t.as_u64()).unwrap_or(0);
-            if tx_type != BLOB_CARRYING_TRANSACTION_TYPE {
-                batcher_transactions_data.push(tx.input.0.clone());
-                continue;
-            }
-
-            for blob_hash in tx_blob_hashes {
-                indexed_blobs.push((blob_index, blob_hash));
-                blob_index += 1;
-            }
-        }
+        let ecotone = self
+            .config
+            .chain
+            .hardforks
+            .is_ecotone_active(block.timestamp.as_u64());
+
+        let data = batcher_data(
+            &block.transactions,
+            |tx| self.is_valid_batcher_transaction(tx),
+            ecotone,
+        );
 
-        // if at this point there are no blobs, return early
-        if indexed_blobs.is_empty() {
-            return Ok(batcher_transactions_data);
+        // if there are no blobs, return early
+        if !data.iter().any(|d| matches!(d, BatcherData::Blob(_))) {
+            return Ok(data
+                .into_iter()
+                .filter_map(|d| match d {
+                    BatcherData::Calldata(data) => Some(data),
+                    BatcherData::Blob(_) => None,
+                })
+                .collect());
         }
 
         let slot = self
@@ -489,8 +752,17 @@ impl InnerWatcher {
         let blobs = self.blob_fetcher.fetch_blob_sidecars(slot).await?;
         tracing::debug!("fetched {} blobs for slot {}", blobs.len(), slot);
 
-        for (blob_index, _) in indexed_blobs {
-            let Some(blob_sidecar) = blobs.iter().find(|b| b.index == blob_index as u64) else {
+        let mut batcher_transactions_data = Vec::with_capacity(data.len());
+        for data in data {
+            let blob_index = match data {
+                BatcherData::Calldata(data) => {
+                    batcher_transactions_data.push(data);
+                    continue;
+                }
+                BatcherData::Blob(blob_index) => blob_index,
+            };
+
+            let Some(blob_sidecar) = blobs.iter().find(|b| b.index == blob_index) else {
                 // This can happen in the case the blob retention window has expired
                 // and the data is no longer available. This case is not handled yet.
                 eyre::bail!("blob index {} not found in fetched sidecars", blob_index);
@@ -506,40 +778,147 @@ impl InnerWatcher {
     }
 
     /// Check if a transaction was sent from the batch sender to the batch inbox.
+    /// The batch sender is taken from the system config active at the current block,
+    /// and the batch inbox from the inbox rotations of the chain config, so that batcher
+    /// and inbox rotations are respected.
     #[inline]
     fn is_valid_batcher_transaction(&self, tx: &Transaction) -> bool {
-        let batch_sender = ethers::types::Address::from_slice(
-            self.config.chain.system_config.batch_sender.as_slice(),
-        );
-        let batch_inbox =
-            ethers::types::Address::from_slice(self.config.chain.batch_inbox.as_slice());
+        let batch_sender =
+            ethers::types::Address::from_slice(self.system_config.batch_sender.as_slice());
+        let batch_inbox = self.config.chain.batch_inbox_at(self.current_block);
+        let batch_inbox = ethers::types::Address::from_slice(batch_inbox.as_slice());
         tx.from == batch_sender && tx.to.map(|to| to == batch_inbox).unwrap_or(false)
     }
 }
 
-fn generate_http_provider(url: &str) -> Arc<Provider<RetryClient<Http>>> {
-    let client = reqwest::ClientBuilder::new()
-        .timeout(Duration::from_secs(5))
-        .build()
-        .unwrap();
-    let http = Http::new_with_client(Url::parse(url).expect("invalid rpc url"), client);
+/// The data of a batcher transaction, before its blobs are fetched
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum BatcherData {
+    /// The calldata of the transaction
+    Calldata(BatcherTransactionData),
+    /// The index of one of the transaction's blobs among the blobs of the block
+    Blob(u64),
+}
+
+/// Extracts the data of the batcher transactions in a block, in transaction order.
+///
+/// Transactions of every type are batcher transactions if they are sent by the batch
+/// sender to the batch inbox, which `is_batcher_tx` checks, so legacy, access list, EIP-1559 and EIP-7702 transactions
+/// all carry their data as calldata. From ecotone, blob-carrying transactions carry
+/// theirs in blobs instead and their calldata is ignored. Before ecotone, blobs are not
+/// used and the calldata of every type is used. The blobs of all other transactions
+/// count towards the blob indexes, since sidecars are indexed within the whole block.
+fn batcher_data(
+    txs: &[Transaction],
+    is_batcher_tx: impl Fn(&Transaction) -> bool,
+    ecotone: bool,
+) -> Vec<BatcherData> {
+    let mut data = Vec::new();
+    let mut blob_index = 0;
+
+    for tx in txs {
+        let tx_blob_hashes: Vec<H256> = tx
+            .other
+            .get_deserialized("blobVersionedHashes")
+            .unwrap_or(Ok(Vec::new()))
+            .unwrap_or_default();
+
+        if !is_batcher_tx(tx) {
+            blob_index += tx_blob_hashes.len() as u64;
+            continue;
+        }
+
+        let tx_type = tx.transaction_type.map(|t| t.as_u64()).unwrap_or(0);
+        if tx_type == SET_CODE_TRANSACTION_TYPE {
+            tracing::warn!("ignoring EIP-7702 batcher transaction {:?}", tx.hash);
+            continue;
+        }
+
+        if !ecotone || tx_type != BLOB_CARRYING_TRANSACTION_TYPE {
+            data.push(BatcherData::Calldata(tx.input.0.clone()));
+            blob_index += tx_blob_hashes.len() as u64;
+            continue;
+        }
+
+        if !tx.input.is_empty() {
+            tracing::warn!(
+                "ignoring the calldata of blob batcher transaction {:?}",
+                tx.hash
+            );
+        }
+
+        for _ in tx_blob_hashes {
+            data.push(BatcherData::Blob(blob_index));
+            blob_index += 1;
+        }
+    }
+
+    data
+}
+
+pub(crate) fn generate_http_provider(
+    url: &str,
+    limiter: Option<Arc<RateLimiter>>,
+) -> Arc<Provider<RetryClient<RateLimitedClient>>> {
     let policy = Box::new(HttpRateLimitRetryPolicy);
-    let client = RetryClient::new(http, policy, 100, 50);
+    let client = RetryClient::new(generate_client(url, limiter), policy, 100, 50);
     Arc::new(Provider::new(client))
 }
 
+/// Creates the client of an RPC url, which may be a `file://` url of exported L1 data
+pub(crate) fn generate_client(url: &str, limiter: Option<Arc<RateLimiter>>) -> RateLimitedClient {
+    match file_source::dir_from_url(url) {
+        Some(dir) => RateLimitedClient::from_files(FileClient::new(dir)),
+        None => {
+            let client = http::client(http::L1_TIMEOUT).unwrap();
+            let http = Http::new_with_client(Url::parse(url).expect("invalid rpc url"), client);
+            RateLimitedClient::new(http, limiter)
+        }
+    }
+}
+
+/// Returns the cached system config in effect after the given L1 block, if any
+async fn cached_system_config(
+    l1: &SharedL1,
+    contract: alloy_primitives::Address,
+    block_num: u64,
+) -> Option<SystemConfig> {
+    let block = l1.provider.get_block(block_num).await.ok()??;
+    l1.system_config(contract, block.hash?)
+}
+
+/// Returns the current unix timestamp
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
 fn start_watcher(
     l1_start_block: u64,
     l2_start_block: u64,
+    resume: Option<(BlockInfo, SystemConfig)>,
     config: Arc<Config>,
+    heartbeat: Heartbeat,
+    lookahead: Option<Lookahead>,
 ) -> Result<(JoinHandle<()>, mpsc::Receiver<BlockUpdate>)> {
-    let (block_update_sender, block_update_receiver) = mpsc::channel(1000);
+    let (block_update_sender, block_update_receiver) = mpsc::channel(config.l1_block_buffer.max(1));
 
     let handle = spawn(async move {
-        let mut watcher =
-            InnerWatcher::new(config, block_update_sender, l1_start_block, l2_start_block).await;
+        let mut watcher = InnerWatcher::new(
+            config,
+            block_update_sender,
+            heartbeat,
+            l1_start_block,
+            l2_start_block,
+            resume,
+        )
+        .await;
+        watcher.lookahead = lookahead;
 
         loop {
+            watcher.heartbeat.beat();
             tracing::debug!("fetching L1 data for block {}", watcher.current_block);
             if let Err(err) = watcher.try_ingest_block().await {
                 tracing::warn!(
@@ -556,19 +935,241 @@ fn start_watcher(
 
 #[cfg(test)]
 mod tests {
-    use std::sync::Arc;
+    use std::{sync::Arc, time::Duration};
 
     use ethers::{
         providers::{Http, Middleware, Provider},
-        types::{BlockId, BlockNumber},
+        types::{Address, BlockId, BlockNumber, Transaction, H256},
     };
     use tokio::sync::mpsc;
 
     use crate::{
-        config::{ChainConfig, Config},
-        l1::chain_watcher::InnerWatcher,
+        common::BlockInfo,
+        config::{BatchInboxRotation, ChainConfig, Config},
+        derive::DerivationError,
+        l1::{
+            chain_watcher::{batcher_data, BatcherData, ChainWatcher, InnerWatcher},
+            shared::SharedL1,
+            supervision::{Heartbeat, RestartReason},
+            SystemConfigUpdate,
+        },
     };
 
+    #[tokio::test]
+    async fn test_supervise_restarts_dead_task() -> eyre::Result<()> {
+        let dir = std::env::temp_dir().join(format!("magi-supervise-{}", std::process::id()));
+        let config = Arc::new(Config {
+            l1_rpc_url: format!("file://{}", dir.display()),
+            l2_rpc_url: "http://127.0.0.1:1".to_string(),
+            chain: ChainConfig::optimism(),
+            ..Default::default()
+        });
+
+        // the task panics without an L2 RPC to read the system config from
+        let mut watcher = ChainWatcher::new(100, 5, config.clone())?;
+        assert_eq!(watcher.supervise()?, None);
+
+        watcher.start()?;
+        for _ in 0..500 {
+            if watcher.handle.as_ref().unwrap().is_finished() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        assert_eq!(watcher.supervise()?, Some(RestartReason::Died));
+        assert_eq!(watcher.restarts().count, 1);
+        assert_eq!(watcher.restarts().last_resumed_after, None);
+
+        // resuming after a received block needs the system config in effect after it
+        let block = BlockInfo {
+            hash: H256::repeat_byte(0x12),
+            number: 120,
+            parent_hash: H256::repeat_byte(0x11),
+            timestamp: 1_000,
+        };
+        watcher.last_block = Some(block);
+        let err = watcher.restart_stalled().unwrap_err();
+        assert_eq!(DerivationError::classify(&err).code(), "reset");
+
+        let contract = config.chain.system_config_contract;
+        let l1 = SharedL1::get(&config);
+        l1.insert_system_config(contract, block.hash, config.chain.system_config);
+        watcher.restart_stalled()?;
+        assert_eq!(watcher.restarts().last_resumed_after, Some(120));
+
+        // the resumed task detects a reorg of the received block
+        let inner = InnerWatcher::new(
+            config.clone(),
+            mpsc::channel(1).0,
+            Heartbeat::default(),
+            block.number,
+            5,
+            Some((block, config.chain.system_config)),
+        )
+        .await;
+        assert_eq!(inner.current_block, 121);
+        assert_eq!(inner.unfinalized_blocks, vec![block]);
+
+        Ok(())
+    }
+
+    fn batcher_tx(from: Address, to: Address) -> Transaction {
+        Transaction {
+            from,
+            to: Some(to),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_batcher_data_by_tx_type() {
+        let (sender, inbox) = (Address::repeat_byte(1), Address::repeat_byte(2));
+        let tx = |tx_type: u64, input: &[u8], blobs: usize, to: Address| {
+            let mut tx = batcher_tx(sender, to);
+            tx.transaction_type = Some(tx_type.into());
+            tx.input = input.to_vec().into();
+            if blobs > 0 {
+                let hashes = vec![H256::repeat_byte(1); blobs];
+                tx.other.insert(
+                    "blobVersionedHashes".to_string(),
+                    serde_json::to_value(hashes).unwrap(),
+                );
+            }
+            tx
+        };
+
+        let txs = vec![
+            // legacy, access list and EIP-1559 transactions carry calldata
+            tx(0, &[0, 1], 0, inbox),
+            tx(1, &[0, 2], 0, inbox),
+            tx(2, &[0, 3], 0, inbox),
+            // EIP-7702 transactions are not batcher transactions
+            tx(4, &[0, 4], 0, inbox),
+            // the blobs of other transactions count towards the blob indexes
+            tx(3, &[], 2, Address::zero()),
+            // blob transactions carry blobs and their calldata is ignored
+            tx(3, &[0, 5], 2, inbox),
+            tx(2, &[0, 6], 0, Address::zero()),
+        ];
+        let is_batcher_tx = |tx: &Transaction| tx.from == sender && tx.to == Some(inbox);
+
+        let calldata = |data: &[u8]| BatcherData::Calldata(data.to_vec().into());
+        assert_eq!(
+            batcher_data(&txs, is_batcher_tx, true),
+            vec![
+                calldata(&[0, 1]),
+                calldata(&[0, 2]),
+                calldata(&[0, 3]),
+                BatcherData::Blob(2),
+                BatcherData::Blob(3),
+            ]
+        );
+
+        // before ecotone the calldata of blob transactions is used
+        let data = batcher_data(&txs, is_batcher_tx, false);
+        assert_eq!(data.len(), 4);
+        assert_eq!(data[3], calldata(&[0, 5]));
+
+        // data keeps the order of the transactions
+        let txs = vec![tx(3, &[], 1, inbox), tx(2, &[0, 7], 0, inbox)];
+        assert_eq!(
+            batcher_data(&txs, is_batcher_tx, true),
+            vec![BatcherData::Blob(0), calldata(&[0, 7])]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_batch_sender_rotation() {
+        let config = Arc::new(Config {
+            l1_rpc_url: "http://localhost:8545".to_string(),
+            l2_rpc_url: "http://localhost:9545".to_string(),
+            chain: ChainConfig::optimism_sepolia(),
+            ..Default::default()
+        });
+
+        let l2_genesis = config.chain.l2_genesis.number;
+        let batch_inbox = Address::from_slice(config.chain.batch_inbox.as_slice());
+        let old_sender = Address::from_slice(config.chain.system_config.batch_sender.as_slice());
+        let new_sender = Address::from_low_u64_be(0xba7c4e5);
+
+        let mut watcher = InnerWatcher::new(
+            config.clone(),
+            mpsc::channel(1).0,
+            Heartbeat::default(),
+            99,
+            l2_genesis,
+            None,
+        )
+        .await;
+
+        let mut rotated = watcher.system_config;
+        SystemConfigUpdate::BatchSender(new_sender).apply(&mut rotated);
+        watcher.system_config_update = (100, Some(rotated));
+
+        // the last block before the rotation still accepts the old batcher
+        watcher.current_block = 99;
+        watcher.apply_system_config_update();
+        assert!(watcher.is_valid_batcher_transaction(&batcher_tx(old_sender, batch_inbox)));
+        assert!(!watcher.is_valid_batcher_transaction(&batcher_tx(new_sender, batch_inbox)));
+
+        // the rotation applies to the block which emitted the update
+        watcher.current_block = 100;
+        watcher.apply_system_config_update();
+        assert!(!watcher.is_valid_batcher_transaction(&batcher_tx(old_sender, batch_inbox)));
+        assert!(watcher.is_valid_batcher_transaction(&batcher_tx(new_sender, batch_inbox)));
+
+        // and remains in effect for subsequent blocks
+        watcher.current_block = 101;
+        watcher.apply_system_config_update();
+        assert!(watcher.is_valid_batcher_transaction(&batcher_tx(new_sender, batch_inbox)));
+
+        // transactions not sent to the batch inbox are always rejected
+        assert!(!watcher.is_valid_batcher_transaction(&batcher_tx(new_sender, new_sender)));
+    }
+
+    #[tokio::test]
+    async fn test_batch_inbox_rotation() {
+        let new_inbox = Address::repeat_byte(0xbb);
+        let mut chain = ChainConfig::optimism_sepolia();
+        chain.batch_inbox_rotations = vec![BatchInboxRotation {
+            l1_block: 100,
+            address: alloy_primitives::Address::from_slice(new_inbox.as_bytes()),
+        }];
+
+        let config = Arc::new(Config {
+            l1_rpc_url: "http://localhost:8545".to_string(),
+            l2_rpc_url: "http://localhost:9545".to_string(),
+            chain,
+            ..Default::default()
+        });
+
+        let old_inbox = Address::from_slice(config.chain.batch_inbox.as_slice());
+        let sender = Address::from_slice(config.chain.system_config.batch_sender.as_slice());
+
+        let mut watcher = InnerWatcher::new(
+            config.clone(),
+            mpsc::channel(1).0,
+            Heartbeat::default(),
+            99,
+            config.chain.l2_genesis.number,
+            None,
+        )
+        .await;
+
+        // the last block before the rotation only accepts the old inbox
+        watcher.current_block = 99;
+        assert!(watcher.is_valid_batcher_transaction(&batcher_tx(sender, old_inbox)));
+        assert!(!watcher.is_valid_batcher_transaction(&batcher_tx(sender, new_inbox)));
+
+        // the new inbox takes over from its activation block
+        for block in [100, 101] {
+            watcher.current_block = block;
+            assert!(!watcher.is_valid_batcher_transaction(&batcher_tx(sender, old_inbox)));
+            assert!(watcher.is_valid_batcher_transaction(&batcher_tx(sender, new_inbox)));
+        }
+    }
+
     #[tokio::test]
     async fn test_get_batcher_transactions() {
         let Ok(l1_beacon_url) = std::env::var("L1_TEST_BEACON_RPC_URL") else {
@@ -593,7 +1194,8 @@ mod tests {
             .unwrap()
             .unwrap();
 
-        let watcher_inner = InnerWatcher::new(config, mpsc::channel(1).0, 0, 0).await;
+        let watcher_inner =
+            InnerWatcher::new(config, mpsc::channel(1).0, Heartbeat::default(), 0, 0, None).await;
 
         let batcher_transactions = watcher_inner
             .get_batcher_transactions(&l1_block)
@@ -1,6 +1,8 @@
 use ethers::types::{Address, Log, U256};
 use eyre::Result;
 
+use crate::config::SystemConfig;
+
 /// Represents a system config update event
 #[derive(Debug)]
 pub enum SystemConfigUpdate {
@@ -12,6 +14,38 @@ pub enum SystemConfigUpdate {
     Gas(U256),
     /// The unsafe block signer has been updated
     UnsafeBlockSigner(Address),
+    /// The operator fee scalar and constant have been updated
+    OperatorFee(U256),
+}
+
+impl SystemConfigUpdate {
+    /// Applies the update to the given [SystemConfig]
+    pub fn apply(self, config: &mut SystemConfig) {
+        match self {
+            Self::BatchSender(addr) => {
+                config.batch_sender = alloy_primitives::Address::from_slice(addr.as_bytes());
+            }
+            Self::Fees(overhead, scalar) => {
+                config.l1_fee_overhead = to_alloy_u256(overhead);
+                config.l1_fee_scalar = to_alloy_u256(scalar);
+            }
+            Self::Gas(gas) => {
+                config.gas_limit = to_alloy_u256(gas);
+            }
+            Self::UnsafeBlockSigner(addr) => {
+                config.unsafe_block_signer = alloy_primitives::Address::from_slice(addr.as_bytes());
+            }
+            Self::OperatorFee(params) => {
+                config.operator_fee_params = to_alloy_u256(params);
+            }
+        }
+    }
+}
+
+fn to_alloy_u256(value: U256) -> alloy_primitives::U256 {
+    let mut bytes: [u8; 32] = [0; 32];
+    value.to_big_endian(&mut bytes);
+    alloy_primitives::U256::from_be_bytes(bytes)
 }
 
 impl TryFrom<Log> for SystemConfigUpdate {
@@ -78,6 +112,14 @@ impl TryFrom<Log> for SystemConfigUpdate {
                 let addr = Address::from_slice(addr_bytes);
                 Ok(Self::UnsafeBlockSigner(addr))
             }
+            5 => {
+                let params = log
+                    .data
+                    .get(64..96)
+                    .ok_or(eyre::eyre!("invalid system config update"))?;
+
+                Ok(Self::OperatorFee(U256::from_big_endian(params)))
+            }
             _ => Err(eyre::eyre!("invalid system config update")),
         }
     }
@@ -0,0 +1,422 @@
+use ethers::{
+    abi::ethereum_types::BloomInput,
+    types::{Address, Block, Log, Transaction, TransactionReceipt, H256},
+    utils::{
+        keccak256,
+        rlp::{self, RlpStream},
+    },
+};
+use eyre::Result;
+use once_cell::sync::Lazy;
+
+use crate::{common::RawTransaction, derive::stages::attributes::UserDeposited};
+
+pub(super) static TRANSACTION_DEPOSITED_TOPIC: Lazy<H256> = Lazy::new(|| {
+    H256::from_slice(&keccak256(
+        "TransactionDeposited(address,address,uint256,bytes)",
+    ))
+});
+
+/// Returns true if the logs bloom of the block indicates that it may contain
+/// `TransactionDeposited` events emitted by the deposit contract.
+pub fn may_contain_deposits<T>(block: &Block<T>, deposit_contract: Address) -> bool {
+    match block.logs_bloom {
+        Some(bloom) => {
+            bloom.contains_input(BloomInput::Raw(deposit_contract.as_bytes()))
+                && bloom.contains_input(BloomInput::Raw(TRANSACTION_DEPOSITED_TOPIC.as_bytes()))
+        }
+        None => true,
+    }
+}
+
+/// Cross-checks the deposits derived from the `TransactionDeposited` log filter against
+/// the receipts of the L1 block they were emitted in.
+///
+/// The receipts are first authenticated against the receipts root of the block header.
+/// The deposit logs contained in the receipts must then match the derived deposits one to
+/// one, in the same order, with identical mint, value, gas and calldata fields and the same
+/// source hash. Any inconsistency is returned as an error so that the epoch is rejected.
+pub fn validate_deposits(
+    block: &Block<Transaction>,
+    receipts: &[TransactionReceipt],
+    deposits: &[UserDeposited],
+    deposit_contract: Address,
+) -> Result<()> {
+    let number = block.number.ok_or(eyre::eyre!("block not included"))?;
+    let hash = block.hash.ok_or(eyre::eyre!("block not included"))?;
+
+    let receipts_root = compute_receipts_root(receipts);
+    if receipts_root != block.receipts_root {
+        eyre::bail!(
+            "receipts root mismatch for block {}: expected {:?}, computed {:?}",
+            number,
+            block.receipts_root,
+            receipts_root
+        );
+    }
+
+    let receipt_deposits = receipts
+        .iter()
+        .flat_map(|receipt| receipt.logs.iter())
+        .filter(|log| is_deposit_log(log, deposit_contract))
+        .cloned()
+        .map(UserDeposited::try_from)
+        .collect::<Result<Vec<_>>>()?;
+
+    if receipt_deposits.len() != deposits.len() {
+        eyre::bail!(
+            "deposit count mismatch for block {}: {} in receipts, {} derived",
+            number,
+            receipt_deposits.len(),
+            deposits.len()
+        );
+    }
+
+    let mut last_log_index = None;
+
+    for (expected, derived) in receipt_deposits.iter().zip(deposits) {
+        if last_log_index.is_some_and(|last| derived.log_index <= last) {
+            eyre::bail!(
+                "deposit logs out of order in block {} at log index {}",
+                number,
+                derived.log_index
+            );
+        }
+        last_log_index = Some(derived.log_index);
+
+        if derived.l1_block_hash != hash || derived.l1_block_num != number.as_u64() {
+            eyre::bail!(
+                "deposit at log index {} does not belong to block {}",
+                derived.log_index,
+                number
+            );
+        }
+
+        if derived.source_hash() != expected.source_hash() {
+            eyre::bail!(
+                "source hash mismatch for deposit at log index {} in block {}",
+                derived.log_index,
+                number
+            );
+        }
+
+        if derived.mint != expected.mint || derived.value != expected.value {
+            eyre::bail!(
+                "mint or value mismatch for deposit at log index {} in block {}",
+                derived.log_index,
+                number
+            );
+        }
+
+        if derived.from != expected.from
+            || derived.to != expected.to
+            || derived.gas != expected.gas
+            || derived.is_creation != expected.is_creation
+            || derived.data != expected.data
+        {
+            eyre::bail!(
+                "deposit at log index {} in block {} does not match its receipt",
+                derived.log_index,
+                number
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns true if the log is a `TransactionDeposited` event from the deposit contract.
+fn is_deposit_log(log: &Log, deposit_contract: Address) -> bool {
+    log.address == deposit_contract
+        && log.topics.first() == Some(&*TRANSACTION_DEPOSITED_TOPIC)
+        && log.removed != Some(true)
+}
+
+/// Computes the receipts trie root from a list of receipts ordered by transaction index.
+pub fn compute_receipts_root(receipts: &[TransactionReceipt]) -> H256 {
+    let encoded = receipts.iter().map(encode_receipt).collect::<Vec<_>>();
+    ordered_trie_root(&encoded)
+}
+
+/// Computes the transactions trie root from a list of EIP-2718 encoded transactions.
+pub fn compute_transactions_root(transactions: &[RawTransaction]) -> H256 {
+    let encoded = transactions
+        .iter()
+        .map(|tx| tx.0.clone())
+        .collect::<Vec<_>>();
+    ordered_trie_root(&encoded)
+}
+
+/// Encodes an L1 receipt following EIP-2718: typed receipts are prefixed by their type byte.
+fn encode_receipt(receipt: &TransactionReceipt) -> Vec<u8> {
+    let mut s = RlpStream::new_list(4);
+    s.append(&receipt.status.unwrap_or_default());
+    s.append(&receipt.cumulative_gas_used);
+    s.append(&receipt.logs_bloom);
+    s.append_list(&receipt.logs);
+    let payload = s.out().to_vec();
+
+    match receipt.transaction_type.map(|t| t.as_u64()).unwrap_or(0) {
+        0 => payload,
+        tx_type => [vec![tx_type as u8], payload].concat(),
+    }
+}
+
+/// Computes the root of a Merkle Patricia trie in which each value is keyed by the
+/// RLP encoding of its position in the list.
+fn ordered_trie_root(values: &[Vec<u8>]) -> H256 {
+    let mut items = values
+        .iter()
+        .enumerate()
+        .map(|(i, value)| (to_nibbles(&rlp::encode(&i)), value.as_slice()))
+        .collect::<Vec<_>>();
+
+    items.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let refs = items
+        .iter()
+        .map(|(key, value)| (key.as_slice(), *value))
+        .collect::<Vec<_>>();
+
+    let root = encode_node(&refs);
+    H256::from_slice(&keccak256(root))
+}
+
+/// Splits each byte into two nibbles.
+fn to_nibbles(bytes: &[u8]) -> Vec<u8> {
+    bytes.iter().flat_map(|b| [b >> 4, b & 0x0f]).collect()
+}
+
+/// Hex prefix encoding of a nibble path as specified in the yellow paper.
+fn hex_prefix(nibbles: &[u8], is_leaf: bool) -> Vec<u8> {
+    let flag = if is_leaf { 2 } else { 0 };
+    let mut out = Vec::with_capacity(nibbles.len() / 2 + 1);
+
+    let rest = if nibbles.len() % 2 == 1 {
+        out.push(((flag + 1) << 4) | nibbles[0]);
+        &nibbles[1..]
+    } else {
+        out.push(flag << 4);
+        nibbles
+    };
+
+    out.extend(rest.chunks(2).map(|pair| (pair[0] << 4) | pair[1]));
+    out
+}
+
+/// RLP encodes the trie node covering the given sorted key suffixes.
+fn encode_node(items: &[(&[u8], &[u8])]) -> Vec<u8> {
+    match items {
+        [] => rlp::NULL_RLP.to_vec(),
+        [(key, value)] => {
+            let mut s = RlpStream::new_list(2);
+            s.append(&hex_prefix(key, true));
+            s.append(value);
+            s.out().to_vec()
+        }
+        _ => {
+            let prefix_len = common_prefix_len(items);
+            if prefix_len > 0 {
+                let rest = items
+                    .iter()
+                    .map(|(key, value)| (&key[prefix_len..], *value))
+                    .collect::<Vec<_>>();
+
+                let mut s = RlpStream::new_list(2);
+                s.append(&hex_prefix(&items[0].0[..prefix_len], false));
+                append_node_ref(&mut s, &encode_branch(&rest));
+                s.out().to_vec()
+            } else {
+                encode_branch(items)
+            }
+        }
+    }
+}
+
+/// RLP encodes a branch node. Keys ending at this node are stored in the value slot.
+fn encode_branch(items: &[(&[u8], &[u8])]) -> Vec<u8> {
+    let mut s = RlpStream::new_list(17);
+
+    for nibble in 0..16 {
+        let children = items
+            .iter()
+            .filter(|(key, _)| key.first() == Some(&nibble))
+            .map(|(key, value)| (&key[1..], *value))
+            .collect::<Vec<_>>();
+
+        if children.is_empty() {
+            s.append_empty_data();
+        } else {
+            append_node_ref(&mut s, &encode_node(&children));
+        }
+    }
+
+    match items.iter().find(|(key, _)| key.is_empty()) {
+        Some((_, value)) => s.append(value),
+        None => s.append_empty_data(),
+    };
+
+    s.out().to_vec()
+}
+
+/// Appends a reference to a child node: nodes shorter than 32 bytes are inlined,
+/// larger nodes are referenced by their hash.
+fn append_node_ref(s: &mut RlpStream, node: &[u8]) {
+    if node.len() < 32 {
+        s.append_raw(node, 1);
+    } else {
+        s.append(&H256::from_slice(&keccak256(node)));
+    }
+}
+
+fn common_prefix_len(items: &[(&[u8], &[u8])]) -> usize {
+    let first = items[0].0;
+    items[1..].iter().fold(first.len(), |len, (key, _)| {
+        first
+            .iter()
+            .zip(key.iter())
+            .take(len)
+            .take_while(|(a, b)| a == b)
+            .count()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use ethers::types::{Bloom, Bytes, TransactionReceipt, H256, U256, U64};
+
+    use super::*;
+
+    fn trie_root(items: &[(&[u8], &[u8])]) -> H256 {
+        let nibbles = items
+            .iter()
+            .map(|(key, value)| (to_nibbles(key), *value))
+            .collect::<Vec<_>>();
+
+        let mut refs = nibbles
+            .iter()
+            .map(|(key, value)| (key.as_slice(), *value))
+            .collect::<Vec<_>>();
+
+        refs.sort_by(|a, b| a.0.cmp(b.0));
+        H256::from_slice(&keccak256(encode_node(&refs)))
+    }
+
+    fn deposit_log(deposit_contract: Address, block_hash: H256, log_index: u64) -> Log {
+        let mut opaque_data = Vec::new();
+        opaque_data.extend_from_slice(&[0u8; 31]);
+        opaque_data.push(7); // mint
+        opaque_data.extend_from_slice(&[0u8; 31]);
+        opaque_data.push(5); // value
+        opaque_data.extend_from_slice(&100_000u64.to_be_bytes());
+        opaque_data.push(0); // is_creation
+        opaque_data.extend_from_slice(&[0xab, 0xcd]);
+
+        Log {
+            address: deposit_contract,
+            topics: vec![
+                *TRANSACTION_DEPOSITED_TOPIC,
+                H256::from_low_u64_be(1),
+                H256::from_low_u64_be(2),
+                H256::zero(),
+            ],
+            data: Bytes::from(ethers::abi::encode(&[ethers::abi::Token::Bytes(
+                opaque_data,
+            )])),
+            block_hash: Some(block_hash),
+            block_number: Some(U64::from(10)),
+            log_index: Some(U256::from(log_index)),
+            ..Default::default()
+        }
+    }
+
+    fn block_with_receipts(receipts: &[TransactionReceipt], hash: H256) -> Block<Transaction> {
+        Block {
+            number: Some(U64::from(10)),
+            hash: Some(hash),
+            receipts_root: compute_receipts_root(receipts),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_empty_trie_root() {
+        assert_eq!(
+            ordered_trie_root(&[]),
+            H256::from_str("0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421")
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_trie_root_vectors() {
+        let root = trie_root(&[
+            (b"doe", b"reindeer"),
+            (b"dog", b"puppy"),
+            (b"dogglesworth", b"cat"),
+        ]);
+
+        assert_eq!(
+            root,
+            H256::from_str("0x8aad789dff2f538bca5d8ea56e8abe10f4c7ba3a5dea95fea4cd6e7c3a1168d3")
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_validate_deposits() -> Result<()> {
+        let deposit_contract = Address::from_low_u64_be(0xdead);
+        let hash = H256::from_low_u64_be(0xbeef);
+
+        let receipts = vec![
+            TransactionReceipt {
+                status: Some(U64::from(1)),
+                cumulative_gas_used: U256::from(21_000),
+                logs_bloom: Bloom::zero(),
+                transaction_type: Some(U64::from(2)),
+                ..Default::default()
+            },
+            TransactionReceipt {
+                status: Some(U64::from(1)),
+                cumulative_gas_used: U256::from(80_000),
+                logs: vec![
+                    deposit_log(deposit_contract, hash, 0),
+                    deposit_log(deposit_contract, hash, 1),
+                ],
+                ..Default::default()
+            },
+        ];
+
+        let block = block_with_receipts(&receipts, hash);
+
+        let deposits = receipts[1]
+            .logs
+            .iter()
+            .cloned()
+            .map(UserDeposited::try_from)
+            .collect::<Result<Vec<_>>>()?;
+
+        validate_deposits(&block, &receipts, &deposits, deposit_contract)?;
+
+        // tampered mint value
+        let mut tampered = deposits.clone();
+        tampered[0].mint = U256::from(8);
+        assert!(validate_deposits(&block, &receipts, &tampered, deposit_contract).is_err());
+
+        // wrong ordering
+        let reordered = vec![deposits[1].clone(), deposits[0].clone()];
+        assert!(validate_deposits(&block, &receipts, &reordered, deposit_contract).is_err());
+
+        // missing deposit
+        assert!(validate_deposits(&block, &receipts, &deposits[..1], deposit_contract).is_err());
+
+        // receipts which do not match the header
+        let mut bad_block = block.clone();
+        bad_block.receipts_root = H256::zero();
+        assert!(validate_deposits(&bad_block, &receipts, &deposits, deposit_contract).is_err());
+
+        Ok(())
+    }
+}
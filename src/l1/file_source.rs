@@ -0,0 +1,291 @@
+use std::{
+    fmt::Debug,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use async_trait::async_trait;
+use ethers::{
+    providers::{HttpClientError, JsonRpcClient, JsonRpcError},
+    types::{Filter, FilteredParams, Log, TransactionReceipt, U64},
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+/// The url scheme selecting exported L1 data instead of an L1 RPC or beacon API
+const FILE_SCHEME: &str = "file://";
+
+/// Returns the directory of a `file://` url, or None if the url is not a file url
+pub fn dir_from_url(url: &str) -> Option<&Path> {
+    url.strip_prefix(FILE_SCHEME).map(Path::new)
+}
+
+/// A JSON-RPC client serving L1 blocks, receipts and logs from a directory of exported
+/// L1 data instead of an L1 RPC, so derivation can run without network access.
+///
+/// The directory holds the L1 RPC responses of every exported block, named after its
+/// number:
+///
+/// - `blocks/<number>.json`: the `eth_getBlockByNumber` result with full transactions
+/// - `receipts/<number>.json`: the `eth_getBlockReceipts` result
+///
+/// The highest exported block is served as the latest, safe and finalized block, and
+/// logs are filtered from the receipts. Blobs are read by the
+/// [BlobFetcher](super::BlobFetcher) from the same directory.
+#[derive(Debug, Clone)]
+pub struct FileClient {
+    /// The directory of the exported data
+    dir: PathBuf,
+}
+
+impl FileClient {
+    /// Creates a new [FileClient] serving the data exported to the given directory
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Returns the number of the highest exported block
+    fn latest(&self) -> Result<u64, HttpClientError> {
+        let entries = fs::read_dir(self.dir.join("blocks")).map_err(|err| {
+            error(format!(
+                "cannot read exported blocks in {:?}: {}",
+                self.dir, err
+            ))
+        })?;
+
+        entries
+            .filter_map(|entry| entry.ok()?.path().file_stem()?.to_str()?.parse().ok())
+            .max()
+            .ok_or_else(|| error(format!("no exported blocks in {:?}", self.dir)))
+    }
+
+    /// Resolves a block number or tag parameter
+    fn block_number(&self, param: Option<&Value>) -> Result<u64, HttpClientError> {
+        match param.and_then(Value::as_str) {
+            None | Some("latest" | "safe" | "finalized" | "pending") => self.latest(),
+            Some("earliest") => Ok(0),
+            Some(number) => u64::from_str_radix(number.trim_start_matches("0x"), 16)
+                .map_err(|_| error(format!("invalid block number {}", number))),
+        }
+    }
+
+    /// Reads the exported file of a block, if it exists
+    fn read(&self, kind: &str, number: u64) -> Result<Option<Value>, HttpClientError> {
+        let path = self.dir.join(kind).join(format!("{}.json", number));
+        match fs::read(&path) {
+            Ok(data) => decode_slice(&data).map(Some),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(error(format!("cannot read {:?}: {}", path, err))),
+        }
+    }
+
+    /// Serves `eth_getBlockByNumber`, with transaction hashes unless full transactions
+    /// are requested
+    fn get_block(&self, params: &[Value]) -> Result<Value, HttpClientError> {
+        let number = self.block_number(params.first())?;
+        let full = params.get(1).and_then(Value::as_bool).unwrap_or(false);
+
+        let Some(mut block) = self.read("blocks", number)? else {
+            return Ok(Value::Null);
+        };
+
+        if !full {
+            if let Some(Value::Array(txs)) = block.get_mut("transactions") {
+                for tx in txs.iter_mut() {
+                    if let Some(hash) = tx.get("hash").cloned() {
+                        *tx = hash;
+                    }
+                }
+            }
+        }
+
+        Ok(block)
+    }
+
+    /// Serves `eth_getBlockReceipts`. Receipts must be exported for every block.
+    fn get_receipts(&self, number: u64) -> Result<Value, HttpClientError> {
+        self.read("receipts", number)?
+            .ok_or_else(|| error(format!("receipts of block {} not exported", number)))
+    }
+
+    /// Serves `eth_getLogs` from the receipts of the blocks in the filtered range
+    fn get_logs(&self, params: &[Value]) -> Result<Value, HttpClientError> {
+        let filter: Filter = decode(params.first().cloned().unwrap_or_default())?;
+        if filter.get_block_hash().is_some() {
+            return Err(error(
+                "log filters by block hash are not supported".to_string(),
+            ));
+        }
+
+        let latest = self.latest()?;
+        let from = filter.get_from_block().map_or(latest, |n| n.as_u64());
+        let to = filter
+            .get_to_block()
+            .map_or(latest, |n| n.as_u64())
+            .min(latest);
+        let params = FilteredParams::new(Some(filter));
+
+        let mut logs: Vec<Log> = Vec::new();
+        for number in from..=to {
+            let receipts: Vec<TransactionReceipt> = decode(self.get_receipts(number)?)?;
+            logs.extend(
+                receipts
+                    .into_iter()
+                    .flat_map(|receipt| receipt.logs)
+                    .filter(|log| params.filter_address(log) && params.filter_topics(log)),
+            );
+        }
+
+        encode(logs)
+    }
+}
+
+#[async_trait]
+impl JsonRpcClient for FileClient {
+    type Error = HttpClientError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let params = match encode(params)? {
+            Value::Array(params) => params,
+            _ => Vec::new(),
+        };
+
+        let result = match method {
+            "eth_blockNumber" => encode(U64::from(self.latest()?))?,
+            "eth_getBlockByNumber" => self.get_block(&params)?,
+            "eth_getBlockReceipts" => self.get_receipts(self.block_number(params.first())?)?,
+            "eth_getLogs" => self.get_logs(&params)?,
+            _ => {
+                return Err(error(format!(
+                    "{} is not served from exported L1 data",
+                    method
+                )))
+            }
+        };
+
+        decode(result)
+    }
+}
+
+fn error(message: String) -> HttpClientError {
+    HttpClientError::JsonRpcError(JsonRpcError {
+        code: -32000,
+        message,
+        data: None,
+    })
+}
+
+fn encode<T: Serialize>(value: T) -> Result<Value, HttpClientError> {
+    serde_json::to_value(value).map_err(|err| HttpClientError::SerdeJson {
+        err,
+        text: String::new(),
+    })
+}
+
+fn decode<R: DeserializeOwned>(value: Value) -> Result<R, HttpClientError> {
+    R::deserialize(&value).map_err(|err| HttpClientError::SerdeJson {
+        err,
+        text: value.to_string(),
+    })
+}
+
+fn decode_slice(data: &[u8]) -> Result<Value, HttpClientError> {
+    serde_json::from_slice(data).map_err(|err| HttpClientError::SerdeJson {
+        err,
+        text: String::from_utf8_lossy(data).into_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::{
+        providers::{Middleware, Provider},
+        types::{Address, Block, BlockNumber, Transaction, H256},
+    };
+
+    use super::*;
+
+    fn write(dir: &Path, kind: &str, number: u64, value: Value) {
+        fs::create_dir_all(dir.join(kind)).unwrap();
+        fs::write(
+            dir.join(kind).join(format!("{}.json", number)),
+            value.to_string(),
+        )
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_serve_exported_data() -> eyre::Result<()> {
+        let dir = std::env::temp_dir().join(format!("magi-l1-files-{}", std::process::id()));
+        let contract = Address::repeat_byte(1);
+        let topic = H256::repeat_byte(2);
+
+        for number in 10..=12u64 {
+            let hash = H256::from_low_u64_be(number);
+            let tx = Transaction {
+                hash: H256::from_low_u64_be(100 + number),
+                block_hash: Some(hash),
+                block_number: Some(number.into()),
+                ..Default::default()
+            };
+            let block = Block {
+                hash: Some(hash),
+                number: Some(number.into()),
+                transactions: vec![tx],
+                ..Default::default()
+            };
+            write(&dir, "blocks", number, serde_json::to_value(block)?);
+
+            let log = Log {
+                address: contract,
+                topics: vec![if number == 11 { topic } else { H256::zero() }],
+                block_number: Some(number.into()),
+                ..Default::default()
+            };
+            let receipt = TransactionReceipt {
+                block_hash: Some(hash),
+                block_number: Some(number.into()),
+                logs: vec![log],
+                ..Default::default()
+            };
+            write(
+                &dir,
+                "receipts",
+                number,
+                serde_json::to_value(vec![receipt])?,
+            );
+        }
+
+        let provider = Provider::new(FileClient::new(&dir));
+
+        assert_eq!(provider.get_block_number().await?.as_u64(), 12);
+
+        let finalized = provider.get_block(BlockNumber::Finalized).await?.unwrap();
+        assert_eq!(finalized.number, Some(12.into()));
+        assert_eq!(finalized.transactions, vec![H256::from_low_u64_be(112)]);
+
+        let block: Block<Transaction> = provider.get_block_with_txs(11).await?.unwrap();
+        assert_eq!(block.transactions[0].hash, H256::from_low_u64_be(111));
+        assert!(provider.get_block(13).await?.is_none());
+
+        assert_eq!(provider.get_block_receipts(10).await?.len(), 1);
+
+        let filter = Filter::new()
+            .address(contract)
+            .topic0(topic)
+            .from_block(10)
+            .to_block(20);
+        let logs = provider.get_logs(&filter).await?;
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].block_number, Some(11.into()));
+
+        assert!(provider.get_chainid().await.is_err());
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
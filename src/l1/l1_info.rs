@@ -1,8 +1,64 @@
+use bytes::Bytes;
 use ethers::types::{Block, Transaction, H256, U256};
 
 use crate::{config::SystemConfig, derive::stages::attributes::UserDeposited};
 
-use super::chain_watcher::BatcherTransactionData;
+/// The minimum blob base fee in wei
+const MIN_BLOB_BASE_FEE: u64 = 1;
+
+/// The update fraction of the blob base fee from Cancun
+const CANCUN_BLOB_BASE_FEE_UPDATE_FRACTION: u64 = 3_338_477;
+
+/// The update fraction of the blob base fee from Prague, kept by Osaka
+const PRAGUE_BLOB_BASE_FEE_UPDATE_FRACTION: u64 = 5_007_716;
+
+/// The update fraction of the blob base fee from the first blob parameter only fork
+const BPO1_BLOB_BASE_FEE_UPDATE_FRACTION: u64 = 8_346_193;
+
+/// The update fraction of the blob base fee from the second blob parameter only fork
+const BPO2_BLOB_BASE_FEE_UPDATE_FRACTION: u64 = 11_684_671;
+
+/// The activation timestamps of the L1 forks changing the blob base fee update fraction
+struct BlobSchedule {
+    /// Prague activation timestamp
+    prague: u64,
+    /// BPO1 activation timestamp
+    bpo1: u64,
+    /// BPO2 activation timestamp
+    bpo2: u64,
+}
+
+impl BlobSchedule {
+    /// Returns the blob schedule of a known L1 chain
+    fn for_chain(l1_chain_id: u64) -> Option<Self> {
+        let (prague, bpo1, bpo2) = match l1_chain_id {
+            1 => (1_746_612_311, 1_765_290_071, 1_767_747_671),
+            11_155_111 => (1_741_159_776, 1_761_017_184, 1_761_607_008),
+            17_000 => (1_740_434_112, 1_759_800_000, 1_760_389_824),
+            560_048 => (1_742_999_832, 1_762_365_720, 1_762_955_544),
+            _ => return None,
+        };
+
+        Some(Self { prague, bpo1, bpo2 })
+    }
+
+    /// Returns the update fraction in effect at the given timestamp
+    fn update_fraction(&self, timestamp: u64) -> u64 {
+        if timestamp >= self.bpo2 {
+            BPO2_BLOB_BASE_FEE_UPDATE_FRACTION
+        } else if timestamp >= self.bpo1 {
+            BPO1_BLOB_BASE_FEE_UPDATE_FRACTION
+        } else if timestamp >= self.prague {
+            PRAGUE_BLOB_BASE_FEE_UPDATE_FRACTION
+        } else {
+            CANCUN_BLOB_BASE_FEE_UPDATE_FRACTION
+        }
+    }
+}
+
+/// The data contained in a batcher transaction.
+/// The actual source of this data can be either calldata or blobs.
+pub type BatcherTransactionData = Bytes;
 
 /// Data tied to a specific L1 block
 #[derive(Debug)]
@@ -26,6 +82,8 @@ pub struct L1BlockInfo {
     pub number: u64,
     /// L1 block hash
     pub hash: H256,
+    /// L1 parent block hash
+    pub parent_hash: H256,
     /// L1 block timestamp
     pub timestamp: u64,
     /// L1 base fee per gas
@@ -34,12 +92,16 @@ pub struct L1BlockInfo {
     pub mix_hash: H256,
     /// Post-Ecotone beacon block root
     pub parent_beacon_block_root: Option<H256>,
+    /// L1 blob base fee, which is 1 before the block has blob gas
+    pub blob_base_fee: U256,
 }
 
-impl TryFrom<&Block<Transaction>> for L1BlockInfo {
-    type Error = eyre::Error;
-
-    fn try_from(value: &Block<Transaction>) -> std::result::Result<Self, Self::Error> {
+impl L1BlockInfo {
+    /// Creates the [L1BlockInfo] of a block of the given L1 chain. The blob base fee uses the
+    /// update fraction of the L1 fork active at the block. On L1 chains without a known
+    /// schedule, blocks with a Prague header use the Prague fraction, and later blob
+    /// parameter only forks are not supported.
+    pub fn from_block(value: &Block<Transaction>, l1_chain_id: u64) -> eyre::Result<Self> {
         let number = value
             .number
             .ok_or(eyre::eyre!("block not included"))?
@@ -57,13 +119,90 @@ impl TryFrom<&Block<Transaction>> for L1BlockInfo {
 
         let parent_beacon_block_root = value.parent_beacon_block_root;
 
+        let update_fraction = match BlobSchedule::for_chain(l1_chain_id) {
+            Some(schedule) => schedule.update_fraction(timestamp),
+            None if value.other.contains_key("requestsHash") => {
+                PRAGUE_BLOB_BASE_FEE_UPDATE_FRACTION
+            }
+            None => CANCUN_BLOB_BASE_FEE_UPDATE_FRACTION,
+        };
+
+        let blob_base_fee = value
+            .excess_blob_gas
+            .map(|excess_blob_gas| blob_base_fee(excess_blob_gas, update_fraction))
+            .unwrap_or(U256::from(MIN_BLOB_BASE_FEE));
+
         Ok(L1BlockInfo {
             number,
             hash,
+            parent_hash: value.parent_hash,
             timestamp,
             base_fee,
             mix_hash,
             parent_beacon_block_root,
+            blob_base_fee,
         })
     }
 }
+
+/// Returns the blob base fee of a block with the given excess blob gas and update fraction,
+/// as defined by [EIP-4844](https://eips.ethereum.org/EIPS/eip-4844#gas-accounting)
+fn blob_base_fee(excess_blob_gas: U256, update_fraction: u64) -> U256 {
+    let factor = U256::from(MIN_BLOB_BASE_FEE);
+    let denominator = U256::from(update_fraction);
+
+    let mut output = U256::zero();
+    let mut accum = factor * denominator;
+    let mut i = U256::one();
+
+    while !accum.is_zero() {
+        output = output.saturating_add(accum);
+        accum = accum.saturating_mul(excess_blob_gas) / (denominator * i);
+        i += U256::one();
+    }
+
+    output / denominator
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blob_base_fee() -> eyre::Result<()> {
+        let block = |timestamp: u64, prague_header: bool| {
+            let mut block = Block::<Transaction> {
+                number: Some(1.into()),
+                hash: Some(H256::repeat_byte(1)),
+                timestamp: timestamp.into(),
+                base_fee_per_gas: Some(U256::one()),
+                mix_hash: Some(H256::zero()),
+                excess_blob_gas: Some(U256::from(10_000_000)),
+                ..Default::default()
+            };
+            if prague_header {
+                block
+                    .other
+                    .insert("requestsHash".to_string(), H256::zero().to_string().into());
+            }
+            block
+        };
+        let fee = |block: &Block<Transaction>, l1_chain_id: u64| -> eyre::Result<u64> {
+            Ok(L1BlockInfo::from_block(block, l1_chain_id)?
+                .blob_base_fee
+                .as_u64())
+        };
+
+        // mainnet blocks before Prague, after Prague and after each blob parameter only fork
+        assert_eq!(fee(&block(1_746_612_310, false), 1)?, 19);
+        assert_eq!(fee(&block(1_746_612_311, true), 1)?, 7);
+        assert_eq!(fee(&block(1_765_290_071, true), 1)?, 3);
+        assert_eq!(fee(&block(1_767_747_671, true), 1)?, 2);
+
+        // unknown L1 chains follow the header
+        assert_eq!(fee(&block(0, false), 900)?, 19);
+        assert_eq!(fee(&block(0, true), 900)?, 7);
+
+        Ok(())
+    }
+}
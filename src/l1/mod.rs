@@ -1,21 +1,70 @@
 /// Module reposnsible for listening to the L1 chain and monitoring for new
 /// blocks and events.
+#[cfg(feature = "l1-client")]
 pub mod chain_watcher;
+#[cfg(feature = "l1-client")]
 pub use chain_watcher::{BlockUpdate, ChainWatcher};
 
 /// module responsible for parsing logs to extract system config updates
 pub mod config_updates;
 pub use config_updates::SystemConfigUpdate;
 
+/// Module responsible for verifying deposits against L1 receipts
+pub mod deposit_validation;
+
 /// L1 block info
 pub mod l1_info;
 pub use l1_info::L1Info;
 
 /// Module responsible for extracting batcher transaction data from
 /// L1 batcher transaction data or blobs (after the Ecotone hardfork)
+#[cfg(feature = "l1-client")]
 pub mod blob_fetcher;
+#[cfg(feature = "l1-client")]
 pub use blob_fetcher::{BlobFetcher, BlobSidecar};
 
+/// Module responsible for monitoring the L1 `ProtocolVersions` contract
+#[cfg(feature = "l1-client")]
+pub mod protocol_versions;
+#[cfg(feature = "l1-client")]
+pub use protocol_versions::{ProtocolVersionSignal, ProtocolVersionsMonitor};
+
+/// Module responsible for reading the L2 outputs proposed on L1
+#[cfg(feature = "l1-client")]
+pub mod outputs;
+#[cfg(feature = "l1-client")]
+pub use outputs::{OutputSource, ProposedOutput};
+
+/// Caches of L1 data keyed by block hash
+#[cfg(feature = "l1-client")]
+pub mod cache;
+#[cfg(feature = "l1-client")]
+pub use cache::HashCache;
+
+/// L1 data sources shared between rollups following the same L1 chain
+#[cfg(feature = "l1-client")]
+pub mod shared;
+#[cfg(feature = "l1-client")]
+pub use shared::SharedL1;
+
+/// Serving L1 data from files exported for offline derivation
+#[cfg(feature = "l1-client")]
+pub mod file_source;
+#[cfg(feature = "l1-client")]
+pub use file_source::FileClient;
+
+/// Supervision of the background L1 tasks
+#[cfg(feature = "l1-client")]
+pub mod supervision;
+#[cfg(feature = "l1-client")]
+pub use supervision::{RestartReason, WatcherRestarts};
+
+/// Client-side rate limiting of L1 requests
+#[cfg(feature = "l1-client")]
+pub mod rate_limit;
+#[cfg(feature = "l1-client")]
+pub use rate_limit::{RateLimitedClient, RateLimiter};
+
 /// Helper module for decoding blob data
 pub mod blob_encoding;
 pub use blob_encoding::decode_blob_data;
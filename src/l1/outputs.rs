@@ -0,0 +1,188 @@
+use ethers::{
+    providers::Middleware,
+    types::{
+        transaction::eip2718::TypedTransaction, Address, BlockId, BlockNumber, TransactionRequest,
+        H256, U256,
+    },
+    utils::id,
+};
+use eyre::Result;
+
+/// Number of the latest dispute games searched for one not won by the challenger
+const MAX_SEARCHED_GAMES: u64 = 16;
+
+/// Status of a dispute game whose root claim was proven invalid
+const CHALLENGER_WINS: u8 = 1;
+
+/// An L2 output root proposed on L1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProposedOutput {
+    /// The L2 block committed to by the output
+    pub l2_block_number: u64,
+    /// The proposed output root
+    pub output_root: H256,
+}
+
+/// The L1 contract L2 outputs are proposed to, read from the `SystemConfig` contract
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputSource {
+    /// Outputs are the root claims of the games created by the `DisputeGameFactory`,
+    /// once fault proofs are enabled
+    DisputeGameFactory(Address),
+    /// Outputs are proposed to the `L2OutputOracle`, before fault proofs
+    L2OutputOracle(Address),
+}
+
+impl OutputSource {
+    /// Reads the output source from the `SystemConfig` contract, preferring the
+    /// `DisputeGameFactory` if one is set
+    pub async fn fetch<M: Middleware>(provider: &M, system_config: Address) -> Result<Self>
+    where
+        M::Error: 'static,
+    {
+        let factory = call(provider, system_config, "disputeGameFactory()", &[], None).await;
+        if let Ok(factory) = factory.and_then(|data| address(&data, 0)) {
+            if !factory.is_zero() {
+                return Ok(Self::DisputeGameFactory(factory));
+            }
+        }
+
+        let oracle = call(provider, system_config, "l2OutputOracle()", &[], None).await?;
+        let oracle = address(&oracle, 0)?;
+        if oracle.is_zero() {
+            eyre::bail!("no output source set on the system config contract");
+        }
+
+        Ok(Self::L2OutputOracle(oracle))
+    }
+
+    /// Returns the latest output proposed as of the given L1 block, skipping the dispute
+    /// games won by the challenger. Returns `None` if no output is proposed yet.
+    pub async fn latest_output<M: Middleware>(
+        &self,
+        provider: &M,
+        block: BlockNumber,
+    ) -> Result<Option<ProposedOutput>>
+    where
+        M::Error: 'static,
+    {
+        let block = Some(BlockId::Number(block));
+
+        match *self {
+            Self::DisputeGameFactory(factory) => {
+                let count = call(provider, factory, "gameCount()", &[], block).await?;
+                let count = word(&count, 0)?.as_u64();
+
+                for index in (count.saturating_sub(MAX_SEARCHED_GAMES)..count).rev() {
+                    let game =
+                        call(provider, factory, "gameAtIndex(uint256)", &[index], block).await?;
+                    let proxy = address(&game, 2)?;
+
+                    let status = call(provider, proxy, "status()", &[], block).await?;
+                    if word(&status, 0)?.low_u32() as u8 == CHALLENGER_WINS {
+                        continue;
+                    }
+
+                    let root = call(provider, proxy, "rootClaim()", &[], block).await?;
+                    let number = call(provider, proxy, "l2BlockNumber()", &[], block).await?;
+
+                    return Ok(Some(ProposedOutput {
+                        l2_block_number: word(&number, 0)?.as_u64(),
+                        output_root: H256::from_slice(&word_bytes(&root, 0)?),
+                    }));
+                }
+
+                Ok(None)
+            }
+            Self::L2OutputOracle(oracle) => {
+                let next = call(provider, oracle, "nextOutputIndex()", &[], block).await?;
+                let Some(index) = word(&next, 0)?.as_u64().checked_sub(1) else {
+                    return Ok(None);
+                };
+
+                let output =
+                    call(provider, oracle, "getL2Output(uint256)", &[index], block).await?;
+
+                Ok(Some(decode_oracle_output(&output)?))
+            }
+        }
+    }
+}
+
+/// Decodes the `OutputProposal` returned by `L2OutputOracle.getL2Output`, made of the
+/// output root, the L1 timestamp of the proposal and the L2 block number
+fn decode_oracle_output(data: &[u8]) -> Result<ProposedOutput> {
+    Ok(ProposedOutput {
+        l2_block_number: word(data, 2)?.as_u64(),
+        output_root: H256::from_slice(&word_bytes(data, 0)?),
+    })
+}
+
+/// Calls a view function of a contract with the given integer arguments
+async fn call<M: Middleware>(
+    provider: &M,
+    contract: Address,
+    signature: &str,
+    args: &[u64],
+    block: Option<BlockId>,
+) -> Result<Vec<u8>>
+where
+    M::Error: 'static,
+{
+    let mut data = id(signature).to_vec();
+    for arg in args {
+        data.extend_from_slice(H256::from_low_u64_be(*arg).as_bytes());
+    }
+
+    let tx: TypedTransaction = TransactionRequest::new().to(contract).data(data).into();
+    Ok(provider.call(&tx, block).await?.to_vec())
+}
+
+/// Returns the ABI word at the given index of the returned data
+fn word_bytes(data: &[u8], index: usize) -> Result<[u8; 32]> {
+    let word = data
+        .get(index * 32..(index + 1) * 32)
+        .ok_or(eyre::eyre!("invalid contract response"))?;
+
+    Ok(word.try_into()?)
+}
+
+/// Returns the ABI word at the given index of the returned data as an integer
+fn word(data: &[u8], index: usize) -> Result<U256> {
+    Ok(U256::from_big_endian(&word_bytes(data, index)?))
+}
+
+/// Returns the ABI word at the given index of the returned data as an address
+fn address(data: &[u8], index: usize) -> Result<Address> {
+    Ok(Address::from_slice(&word_bytes(data, index)?[12..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn test_decode_oracle_output() -> Result<()> {
+        let root =
+            H256::from_str("0x9f4c2e0b6b1c3d3cc6e4b59d0e6c1e34e3d4ccbd7a2a2ae0a14bc4e0a1e1c2d3")?;
+        let mut data = root.as_bytes().to_vec();
+        data.extend_from_slice(H256::from_low_u64_be(1_700_000_000).as_bytes());
+        data.extend_from_slice(H256::from_low_u64_be(12_345).as_bytes());
+
+        let output = decode_oracle_output(&data)?;
+        assert_eq!(output.output_root, root);
+        assert_eq!(output.l2_block_number, 12_345);
+
+        assert!(decode_oracle_output(&data[..64]).is_err());
+
+        let mut game = H256::from_low_u64_be(0).as_bytes().to_vec();
+        game.extend_from_slice(H256::from_low_u64_be(1_700_000_000).as_bytes());
+        game.extend_from_slice(&[0; 12]);
+        game.extend_from_slice(&[0xab; 20]);
+        assert_eq!(address(&game, 2)?, Address::repeat_byte(0xab));
+
+        Ok(())
+    }
+}
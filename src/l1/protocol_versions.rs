@@ -0,0 +1,385 @@
+use std::{cmp::Ordering, fmt, sync::Arc, time::Duration};
+
+use ethers::{
+    providers::Middleware,
+    types::{transaction::eip2718::TypedTransaction, Address, TransactionRequest, H256},
+    utils::id,
+};
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    spawn,
+    sync::watch,
+    task::JoinHandle,
+    time::{interval, MissedTickBehavior},
+};
+
+use crate::{config::Config, telemetry::metrics};
+
+use super::SharedL1;
+
+/// The hardforks signaled on the `ProtocolVersions` contract whose derivation rules are
+/// implemented, in activation order, with the protocol version major introducing them.
+/// Later hardforks are reported by [HardforkSchedule::unsupported_fork].
+///
+/// [HardforkSchedule::unsupported_fork]: crate::config::HardforkSchedule::unsupported_fork
+pub const SUPPORTED_HARDFORKS: &[(&str, u32)] = &[("canyon", 4), ("delta", 5), ("ecotone", 6)];
+
+/// The newest protocol version supported by this version of Magi, that of the newest
+/// supported hardfork
+pub const SUPPORTED_PROTOCOL_VERSION: ProtocolVersion = ProtocolVersion {
+    build: [0; 8],
+    major: SUPPORTED_HARDFORKS[SUPPORTED_HARDFORKS.len() - 1].1,
+    minor: 0,
+    patch: 0,
+    pre_release: 0,
+};
+
+/// How often the `ProtocolVersions` contract is polled
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A protocol version as signaled by the L1 `ProtocolVersions` contract.
+/// See the [Optimism spec](https://specs.optimism.io/protocol/superchain-upgrades.html#protocol-version-format) for the format.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProtocolVersion {
+    /// Opaque build identifier, versions with different builds are not comparable
+    pub build: [u8; 8],
+    /// Major version, incremented for breaking changes
+    pub major: u32,
+    /// Minor version, incremented for non-breaking changes
+    pub minor: u32,
+    /// Patch version, incremented for backwards compatible fixes
+    pub patch: u32,
+    /// Pre-release version, zero for a full release
+    pub pre_release: u32,
+}
+
+impl ProtocolVersion {
+    /// Returns true if no version is signaled
+    pub fn is_zero(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Compares the precedence of two versions. A pre-release has a lower
+    /// precedence than the full release of the same version.
+    pub fn precedence(&self, other: &Self) -> Ordering {
+        let pre_release = |v: &Self| v.pre_release.wrapping_sub(1);
+
+        (self.major, self.minor, self.patch, pre_release(self)).cmp(&(
+            other.major,
+            other.minor,
+            other.patch,
+            pre_release(other),
+        ))
+    }
+}
+
+impl TryFrom<H256> for ProtocolVersion {
+    type Error = eyre::Report;
+
+    fn try_from(value: H256) -> Result<Self> {
+        let bytes = value.as_bytes();
+
+        if bytes[0] != 0 {
+            eyre::bail!("unknown protocol version type {}", bytes[0]);
+        }
+
+        let word = |start: usize| u32::from_be_bytes(bytes[start..start + 4].try_into().unwrap());
+
+        Ok(Self {
+            build: bytes[8..16].try_into()?,
+            major: word(16),
+            minor: word(20),
+            patch: word(24),
+            pre_release: word(28),
+        })
+    }
+}
+
+impl fmt::Display for ProtocolVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "v{}.{}.{}", self.major, self.minor, self.patch)?;
+
+        if self.pre_release != 0 {
+            write!(f, "-{}", self.pre_release)?;
+        }
+
+        if self.build != [0; 8] {
+            write!(f, "+0x{}", hex::encode(self.build))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether Magi supports the protocol versions signaled on L1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProtocolVersionSupport {
+    /// Both the required and recommended versions are supported
+    Supported,
+    /// The recommended version is newer than the supported version
+    UpgradeRecommended,
+    /// The required version is newer than the supported version
+    UpgradeRequired,
+    /// A signaled version has another build than the supported version, so the two
+    /// cannot be compared
+    Incomparable,
+}
+
+/// The required and recommended protocol versions signaled on L1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProtocolVersionSignal {
+    /// The protocol version required to follow the chain
+    pub required: ProtocolVersion,
+    /// The protocol version recommended to follow the chain
+    pub recommended: ProtocolVersion,
+}
+
+impl ProtocolVersionSignal {
+    /// Compares the signaled versions against the given supported version. Versions of
+    /// another build are not comparable, and only reported as such.
+    pub fn check(&self, supported: &ProtocolVersion) -> ProtocolVersionSupport {
+        let is_other_build = |v: &ProtocolVersion| !v.is_zero() && v.build != supported.build;
+        let is_newer = |v: &ProtocolVersion| {
+            !v.is_zero() && !is_other_build(v) && v.precedence(supported).is_gt()
+        };
+
+        if is_newer(&self.required) {
+            ProtocolVersionSupport::UpgradeRequired
+        } else if is_newer(&self.recommended) {
+            ProtocolVersionSupport::UpgradeRecommended
+        } else if is_other_build(&self.required) || is_other_build(&self.recommended) {
+            ProtocolVersionSupport::Incomparable
+        } else {
+            ProtocolVersionSupport::Supported
+        }
+    }
+}
+
+/// Periodically polls the L1 `ProtocolVersions` contract and publishes the
+/// signaled versions. The polling task is aborted when the monitor is dropped.
+#[derive(Debug)]
+pub struct ProtocolVersionsMonitor {
+    /// Task handle for the polling loop
+    handle: Option<JoinHandle<()>>,
+    /// Channel to receive the latest signaled versions from
+    signal_recv: watch::Receiver<Option<ProtocolVersionSignal>>,
+}
+
+impl Drop for ProtocolVersionsMonitor {
+    fn drop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            handle.abort();
+        }
+    }
+}
+
+impl ProtocolVersionsMonitor {
+    /// Starts monitoring the contract. If no `ProtocolVersions` contract is
+    /// configured, no task is spawned and no signal is ever published.
+    pub fn start(config: Arc<Config>) -> Self {
+        let (signal_sender, signal_recv) = watch::channel(None);
+
+        let contract = Address::from_slice(config.chain.protocol_versions_contract.as_slice());
+
+        if contract.is_zero() {
+            return Self {
+                handle: None,
+                signal_recv,
+            };
+        }
+
+        let handle = spawn(async move {
+            let provider = SharedL1::get(&config).provider.clone();
+            let mut ticker = interval(POLL_INTERVAL);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+            loop {
+                ticker.tick().await;
+
+                match fetch_signal(&provider, contract).await {
+                    Ok(signal) => {
+                        report(&signal);
+                        _ = signal_sender.send(Some(signal));
+                    }
+                    Err(err) => tracing::warn!("failed to fetch protocol versions: {}", err),
+                }
+            }
+        });
+
+        Self {
+            handle: Some(handle),
+            signal_recv,
+        }
+    }
+
+    /// Returns the most recently signaled versions, if any have been fetched
+    pub fn latest(&self) -> Option<ProtocolVersionSignal> {
+        *self.signal_recv.borrow()
+    }
+}
+
+/// Reads the required and recommended versions from the contract
+async fn fetch_signal<M: Middleware>(
+    provider: &M,
+    contract: Address,
+) -> Result<ProtocolVersionSignal>
+where
+    M::Error: 'static,
+{
+    let call = |signature: &str| -> TypedTransaction {
+        TransactionRequest::new()
+            .to(contract)
+            .data(id(signature).to_vec())
+            .into()
+    };
+
+    let required = provider.call(&call("required()"), None).await?;
+    let recommended = provider.call(&call("recommended()"), None).await?;
+
+    let decode = |bytes: &[u8]| -> Result<ProtocolVersion> {
+        let word = bytes
+            .get(..32)
+            .ok_or(eyre::eyre!("invalid protocol version response"))?;
+        ProtocolVersion::try_from(H256::from_slice(word))
+    };
+
+    Ok(ProtocolVersionSignal {
+        required: decode(&required)?,
+        recommended: decode(&recommended)?,
+    })
+}
+
+/// Logs and records metrics for the signaled versions
+fn report(signal: &ProtocolVersionSignal) {
+    let support = signal.check(&SUPPORTED_PROTOCOL_VERSION);
+
+    match support {
+        ProtocolVersionSupport::UpgradeRequired => tracing::error!(
+            "protocol version {} is required but magi only supports {}, upgrade is mandatory",
+            signal.required,
+            SUPPORTED_PROTOCOL_VERSION
+        ),
+        ProtocolVersionSupport::UpgradeRecommended => tracing::warn!(
+            "protocol version {} is recommended but magi only supports {}",
+            signal.recommended,
+            SUPPORTED_PROTOCOL_VERSION
+        ),
+        ProtocolVersionSupport::Incomparable => tracing::warn!(
+            "protocol versions required {} and recommended {} cannot be compared with {}",
+            signal.required,
+            signal.recommended,
+            SUPPORTED_PROTOCOL_VERSION
+        ),
+        ProtocolVersionSupport::Supported => tracing::debug!(
+            "protocol versions supported: required {}, recommended {}",
+            signal.required,
+            signal.recommended
+        ),
+    }
+
+    metrics::PROTOCOL_VERSION_UPGRADE_REQUIRED
+        .set((support == ProtocolVersionSupport::UpgradeRequired) as i64);
+    metrics::PROTOCOL_VERSION_UPGRADE_RECOMMENDED.set(matches!(
+        support,
+        ProtocolVersionSupport::UpgradeRecommended | ProtocolVersionSupport::UpgradeRequired
+    ) as i64);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn version(major: u32, minor: u32, patch: u32, pre_release: u32) -> ProtocolVersion {
+        ProtocolVersion {
+            build: [0; 8],
+            major,
+            minor,
+            patch,
+            pre_release,
+        }
+    }
+
+    #[test]
+    fn test_decode_protocol_version() -> Result<()> {
+        let word =
+            H256::from_str("0x0000000000000000000000000000000000000007000000010000000200000003")?;
+        let version = ProtocolVersion::try_from(word)?;
+
+        assert_eq!(version.major, 7);
+        assert_eq!(version.minor, 1);
+        assert_eq!(version.patch, 2);
+        assert_eq!(version.pre_release, 3);
+        assert_eq!(version.to_string(), "v7.1.2-3");
+
+        let unknown_type =
+            H256::from_str("0x0100000000000000000000000000000000000007000000000000000000000000")?;
+        assert!(ProtocolVersion::try_from(unknown_type).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_protocol_version_precedence() {
+        assert!(version(6, 0, 0, 0).precedence(&version(5, 9, 9, 0)).is_gt());
+        assert!(version(6, 0, 1, 0).precedence(&version(6, 0, 0, 0)).is_gt());
+        assert!(version(6, 0, 0, 1).precedence(&version(6, 0, 0, 0)).is_lt());
+        assert!(version(6, 0, 0, 2).precedence(&version(6, 0, 0, 1)).is_gt());
+        assert!(version(6, 0, 0, 0).precedence(&version(6, 0, 0, 0)).is_eq());
+    }
+
+    #[test]
+    fn test_protocol_version_support() {
+        let supported = version(6, 0, 0, 0);
+
+        let signal = ProtocolVersionSignal {
+            required: version(5, 0, 0, 0),
+            recommended: version(6, 0, 0, 0),
+        };
+        assert_eq!(signal.check(&supported), ProtocolVersionSupport::Supported);
+
+        let signal = ProtocolVersionSignal {
+            required: version(6, 0, 0, 0),
+            recommended: version(7, 0, 0, 1),
+        };
+        assert_eq!(
+            signal.check(&supported),
+            ProtocolVersionSupport::UpgradeRecommended
+        );
+
+        let signal = ProtocolVersionSignal {
+            required: version(7, 0, 0, 0),
+            recommended: version(7, 0, 0, 0),
+        };
+        assert_eq!(
+            signal.check(&supported),
+            ProtocolVersionSupport::UpgradeRequired
+        );
+
+        let unset = ProtocolVersionSignal {
+            required: ProtocolVersion::default(),
+            recommended: ProtocolVersion::default(),
+        };
+        assert_eq!(unset.check(&supported), ProtocolVersionSupport::Supported);
+
+        // versions of another build cannot be compared
+        let other_build = ProtocolVersion {
+            build: [1; 8],
+            ..version(5, 0, 0, 0)
+        };
+        let signal = ProtocolVersionSignal {
+            required: other_build,
+            recommended: version(6, 0, 0, 0),
+        };
+        assert_eq!(
+            signal.check(&supported),
+            ProtocolVersionSupport::Incomparable
+        );
+
+        // the supported version is that of the newest supported hardfork
+        assert_eq!(SUPPORTED_PROTOCOL_VERSION, supported);
+    }
+}
@@ -0,0 +1,440 @@
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use ethers::providers::{Http, HttpClientError, JsonRpcClient, JsonRpcError};
+use reqwest::StatusCode;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::time::sleep;
+
+use crate::{common::http, config::Config, telemetry::metrics};
+
+use super::FileClient;
+
+/// Weight of methods without a known or configured weight
+const DEFAULT_WEIGHT: u32 = 20;
+
+/// The request rate is never slowed down below this fraction of the budget
+const MIN_RATE_FRACTION: f64 = 1.0 / 16.0;
+
+/// Time without rate limit responses after which the request rate is raised again
+const RECOVERY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Time after which a batch of requests fails
+const BATCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Returns the default weight of a JSON-RPC method, in the compute units charged by
+/// common RPC providers
+pub fn default_weight(method: &str) -> u32 {
+    match method {
+        "eth_chainId" | "net_version" | "eth_blockNumber" => 10,
+        "eth_getTransactionReceipt" => 15,
+        "eth_getBlockByNumber" | "eth_getBlockByHash" => 16,
+        "eth_getProof" => 21,
+        "eth_call" => 26,
+        "eth_getLogs" => 75,
+        "eth_getBlockReceipts" => 500,
+        _ => DEFAULT_WEIGHT,
+    }
+}
+
+/// A token bucket limiting the compute units spent on L1 requests per second.
+///
+/// Each request takes its method's weight from the bucket, waiting for the bucket to
+/// refill if it is empty. When the provider responds with a rate limit error anyway,
+/// the refill rate is halved, and it is slowly raised back to the budget once rate
+/// limit errors stop.
+#[derive(Debug)]
+pub struct RateLimiter {
+    /// Compute units per second
+    budget: f64,
+    /// Weights overriding the default method weights
+    weights: HashMap<String, u32>,
+    /// The bucket state
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// Available compute units. Negative if requests are queued on the refill.
+    tokens: f64,
+    /// Current refill rate in compute units per second
+    rate: f64,
+    /// Time of the last refill
+    refilled: Instant,
+    /// Time of the last rate change
+    adjusted: Instant,
+}
+
+impl RateLimiter {
+    /// Creates a new [RateLimiter] with the given budget in compute units per second
+    pub fn new(budget: u32, weights: HashMap<String, u32>) -> Self {
+        let budget = budget.max(1) as f64;
+        let now = Instant::now();
+
+        Self {
+            budget,
+            weights,
+            bucket: Mutex::new(Bucket {
+                tokens: budget,
+                rate: budget,
+                refilled: now,
+                adjusted: now,
+            }),
+        }
+    }
+
+    /// Creates the [RateLimiter] for the L1 RPC, if a budget is configured
+    pub fn from_config(config: &Config) -> Option<Arc<Self>> {
+        config
+            .l1_rpc_budget
+            .map(|budget| Arc::new(Self::new(budget, config.l1_rpc_method_weights.clone())))
+    }
+
+    /// Returns the weight of a method
+    pub fn weight(&self, method: &str) -> u32 {
+        self.weights
+            .get(method)
+            .copied()
+            .unwrap_or_else(|| default_weight(method))
+    }
+
+    /// Returns the current refill rate in compute units per second
+    pub fn rate(&self) -> f64 {
+        self.bucket().rate
+    }
+
+    /// Waits until a request to the given method fits in the budget
+    pub async fn acquire(&self, method: &str) {
+        let wait = self.reserve(self.weight(method) as f64, Instant::now());
+        if !wait.is_zero() {
+            sleep(wait).await;
+        }
+    }
+
+    /// Takes the weight from the bucket, returning how long to wait until the bucket
+    /// has refilled enough to cover it
+    fn reserve(&self, weight: f64, now: Instant) -> Duration {
+        let mut bucket = self.bucket();
+
+        let elapsed = now.saturating_duration_since(bucket.refilled).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * bucket.rate).min(self.budget);
+        bucket.refilled = now;
+
+        // taking the weight up front queues concurrent requests behind each other
+        bucket.tokens -= weight;
+
+        if bucket.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-bucket.tokens / bucket.rate)
+        }
+    }
+
+    /// Slows down after the provider rejected a request for exceeding its rate limit
+    pub fn on_rate_limited(&self) {
+        self.slow_down(Instant::now());
+    }
+
+    /// Raises the request rate back towards the budget after a successful request
+    pub fn on_success(&self) {
+        self.recover(Instant::now());
+    }
+
+    fn slow_down(&self, now: Instant) {
+        let mut bucket = self.bucket();
+
+        bucket.rate = (bucket.rate / 2.0).max(self.budget * MIN_RATE_FRACTION);
+        bucket.tokens = bucket.tokens.min(0.0);
+        bucket.adjusted = now;
+
+        tracing::warn!(
+            "l1 rpc rate limited, slowing down to {:.0} compute units per second",
+            bucket.rate
+        );
+    }
+
+    fn recover(&self, now: Instant) {
+        let mut bucket = self.bucket();
+
+        if bucket.rate < self.budget
+            && now.saturating_duration_since(bucket.adjusted) >= RECOVERY_INTERVAL
+        {
+            bucket.rate = (bucket.rate * 1.5).min(self.budget);
+            bucket.adjusted = now;
+
+            tracing::debug!(
+                "l1 rpc rate raised to {:.0} compute units per second",
+                bucket.rate
+            );
+        }
+    }
+
+    fn bucket(&self) -> std::sync::MutexGuard<'_, Bucket> {
+        self.bucket.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// An HTTP JSON-RPC client that spends its requests from a [RateLimiter], if one is set.
+/// It may instead serve exported L1 data from a [FileClient], which is never limited.
+#[derive(Debug)]
+pub struct RateLimitedClient {
+    /// The underlying client
+    inner: Transport,
+    /// The rate limiter, if the requests are limited
+    limiter: Option<Arc<RateLimiter>>,
+}
+
+/// The transport of a [RateLimitedClient]
+#[derive(Debug)]
+enum Transport {
+    /// An L1 RPC, with the client sending batches of requests to it
+    Http(Http, reqwest::Client),
+    /// Exported L1 data, which is never rate limited
+    Files(FileClient),
+}
+
+/// The response to one request of a JSON-RPC batch
+#[derive(Debug, Deserialize)]
+struct BatchResponse {
+    id: usize,
+    #[serde(default)]
+    result: Option<Value>,
+    #[serde(default)]
+    error: Option<JsonRpcError>,
+}
+
+impl RateLimitedClient {
+    /// Creates a new [RateLimitedClient]
+    pub fn new(inner: Http, limiter: Option<Arc<RateLimiter>>) -> Self {
+        let client = http::client(BATCH_TIMEOUT).unwrap_or_default();
+
+        Self {
+            inner: Transport::Http(inner, client),
+            limiter,
+        }
+    }
+
+    /// Creates a [RateLimitedClient] serving exported L1 data from a [FileClient]
+    pub fn from_files(inner: FileClient) -> Self {
+        Self {
+            inner: Transport::Files(inner),
+            limiter: None,
+        }
+    }
+
+    /// Sends a request to the given method for each of the params in one JSON-RPC batch,
+    /// returning the results in order. The batch fails if any of its requests fails.
+    /// Exported L1 data is served request by request.
+    pub async fn request_batch<T, R>(
+        &self,
+        method: &str,
+        params: &[T],
+    ) -> Result<Vec<R>, HttpClientError>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let (http, client) = match &self.inner {
+            Transport::Http(http, client) => (http, client),
+            Transport::Files(files) => {
+                let mut results = Vec::with_capacity(params.len());
+                for params in params {
+                    results.push(files.request(method, params).await?);
+                }
+                return Ok(results);
+            }
+        };
+
+        if let Some(limiter) = &self.limiter {
+            for _ in params {
+                limiter.acquire(method).await;
+            }
+        }
+
+        let requests = params
+            .iter()
+            .enumerate()
+            .map(|(id, params)| json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }))
+            .collect::<Vec<_>>();
+
+        let res = send_batch(client, http, &requests).await;
+        if let Some(limiter) = &self.limiter {
+            match &res {
+                Err(err) if is_rate_limited(err) => {
+                    metrics::L1_RATE_LIMITED.inc();
+                    limiter.on_rate_limited();
+                }
+                _ => limiter.on_success(),
+            }
+        }
+
+        let mut responses = res?;
+        responses.sort_by_key(|response| response.id);
+        if responses.len() != params.len() {
+            return Err(HttpClientError::JsonRpcError(JsonRpcError {
+                code: -32000,
+                message: format!(
+                    "expected {} batch responses, got {}",
+                    params.len(),
+                    responses.len()
+                ),
+                data: None,
+            }));
+        }
+
+        responses
+            .into_iter()
+            .map(|response| {
+                if let Some(err) = response.error {
+                    return Err(HttpClientError::JsonRpcError(err));
+                }
+
+                let result = response.result.unwrap_or_default();
+                R::deserialize(&result).map_err(|err| HttpClientError::SerdeJson {
+                    err,
+                    text: result.to_string(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Posts a JSON-RPC batch, failing if the response is not a batch response
+async fn send_batch(
+    client: &reqwest::Client,
+    http: &Http,
+    requests: &[Value],
+) -> Result<Vec<BatchResponse>, HttpClientError> {
+    let response = client
+        .post(http.url().clone())
+        .json(requests)
+        .send()
+        .await?
+        .error_for_status()?;
+    let text = response.text().await?;
+
+    serde_json::from_str(&text).map_err(|err| HttpClientError::SerdeJson { err, text })
+}
+
+#[async_trait]
+impl JsonRpcClient for RateLimitedClient {
+    type Error = HttpClientError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let http = match &self.inner {
+            Transport::Http(http, _) => http,
+            Transport::Files(files) => return files.request(method, params).await,
+        };
+
+        let Some(limiter) = &self.limiter else {
+            return http.request(method, params).await;
+        };
+
+        limiter.acquire(method).await;
+
+        let res = http.request(method, params).await;
+        match &res {
+            Err(err) if is_rate_limited(err) => {
+                metrics::L1_RATE_LIMITED.inc();
+                limiter.on_rate_limited();
+            }
+            _ => limiter.on_success(),
+        }
+
+        res
+    }
+}
+
+/// Returns true if the provider rejected the request for exceeding its rate limit
+pub(crate) fn is_rate_limited(err: &HttpClientError) -> bool {
+    fn is_rate_limit_error(err: &JsonRpcError) -> bool {
+        // 429 is used by alchemy, -32005 by infura
+        err.code == 429 || err.code == -32005 || err.message.contains("rate limit")
+    }
+
+    match err {
+        HttpClientError::ReqwestError(err) => err.status() == Some(StatusCode::TOO_MANY_REQUESTS),
+        HttpClientError::JsonRpcError(err) => is_rate_limit_error(err),
+        HttpClientError::SerdeJson { text, .. } => {
+            let text = text.to_lowercase();
+            text.contains("too many requests") || text.contains("rate limit")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket() {
+        let limiter = RateLimiter::new(100, HashMap::from([("eth_getLogs".to_string(), 50)]));
+        let start = limiter.bucket().refilled;
+
+        assert_eq!(limiter.weight("eth_getLogs"), 50);
+        assert_eq!(limiter.weight("eth_getBlockByNumber"), 16);
+
+        // the bucket starts full
+        assert_eq!(limiter.reserve(50.0, start), Duration::ZERO);
+        assert_eq!(limiter.reserve(50.0, start), Duration::ZERO);
+        assert_eq!(limiter.reserve(50.0, start), Duration::from_millis(500));
+
+        // refilled by one second, half of which covers the queued request
+        let later = start + Duration::from_secs(1);
+        assert_eq!(limiter.reserve(50.0, later), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_adaptive_slowdown() {
+        let limiter = RateLimiter::new(160, HashMap::new());
+        let start = limiter.bucket().adjusted;
+
+        limiter.slow_down(start);
+        assert_eq!(limiter.rate(), 80.0);
+
+        for _ in 0..10 {
+            limiter.slow_down(start);
+        }
+        assert_eq!(limiter.rate(), 10.0);
+
+        limiter.recover(start + Duration::from_secs(1));
+        assert_eq!(limiter.rate(), 10.0);
+
+        limiter.recover(start + RECOVERY_INTERVAL);
+        assert_eq!(limiter.rate(), 15.0);
+
+        let mut now = start + RECOVERY_INTERVAL;
+        for _ in 0..10 {
+            now += RECOVERY_INTERVAL;
+            limiter.recover(now);
+        }
+        assert_eq!(limiter.rate(), 160.0);
+    }
+
+    #[test]
+    fn test_is_rate_limited() {
+        let err = HttpClientError::JsonRpcError(JsonRpcError {
+            code: -32005,
+            message: "project rate limit exceeded".to_string(),
+            data: None,
+        });
+        assert!(is_rate_limited(&err));
+
+        let err = HttpClientError::JsonRpcError(JsonRpcError {
+            code: -32000,
+            message: "header not found".to_string(),
+            data: None,
+        });
+        assert!(!is_rate_limited(&err));
+    }
+}
@@ -0,0 +1,464 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use alloy_primitives::Address;
+use ethers::{
+    providers::{HttpClientError, Middleware, Provider, ProviderError, RetryClient, RpcError},
+    types::{Block, Filter, Log, Transaction, TransactionReceipt, H256},
+    utils::keccak256,
+};
+use eyre::Result;
+use once_cell::sync::Lazy;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::config::{Config, SystemConfig};
+
+use super::{
+    chain_watcher::{generate_client, generate_http_provider},
+    rate_limit::is_rate_limited,
+    BlobFetcher, HashCache, RateLimitedClient, RateLimiter,
+};
+
+/// Maximum number of blocks kept in the block and receipt caches
+const MAX_CACHED_BLOCKS: usize = 256;
+
+/// Maximum number of system config snapshots kept in the cache
+const MAX_CACHED_SYSTEM_CONFIGS: usize = 1024;
+
+/// Maximum number of requests in a JSON-RPC batch, below the limits of common L1 RPCs
+const MAX_BATCH_SIZE: usize = 100;
+
+/// Shared L1 sources, keyed by L1 RPC url and beacon url
+type Registry = HashMap<(String, String), Arc<SharedL1>>;
+
+static REGISTRY: Lazy<Mutex<Registry>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// L1 data sources shared between all chain watchers following the same L1 chain.
+///
+/// When multiple rollups run in the same process, or a watcher is restarted after a
+/// reorg, the same L1 blocks and receipts are requested again. They are cached here by
+/// block hash so that they are only fetched once. Blocks are requested by number, which
+/// can only be resolved to a hash without a request once the block is finalized.
+///
+/// Receipts are fetched with `eth_getBlockReceipts`, and log scans due together are sent
+/// in one JSON-RPC batch. The first time the L1 RPC rejects either as not supported, its
+/// capability is recorded, and receipts are fetched by transaction in batches or batches
+/// are sent request by request instead.
+pub struct SharedL1 {
+    /// Ethers provider for L1
+    pub provider: Arc<Provider<RetryClient<RateLimitedClient>>>,
+    /// Client sending JSON-RPC batches to L1, sharing the rate limiter of the provider
+    batch_client: RateLimitedClient,
+    /// Whether the L1 RPC is not known to reject `eth_getBlockReceipts`
+    block_receipts_supported: AtomicBool,
+    /// Whether the L1 RPC is not known to reject JSON-RPC batches
+    batches_supported: AtomicBool,
+    /// L1 beacon node to fetch blobs
+    pub blob_fetcher: Arc<BlobFetcher>,
+    /// Hashes of cached finalized blocks by number
+    finalized_hashes: Mutex<BTreeMap<u64, H256>>,
+    /// Cached blocks with transactions
+    blocks: HashCache<Block<Transaction>>,
+    /// Cached block receipts
+    receipts: HashCache<Vec<TransactionReceipt>>,
+    /// The system config in effect after each block, keyed by [system_config_key] since
+    /// rollups sharing an L1 have different system config contracts
+    system_configs: HashCache<SystemConfig>,
+}
+
+impl SharedL1 {
+    /// Returns the shared L1 sources for the L1 urls in the [Config], creating them if needed.
+    /// The L1 request budget is shared too, and is taken from the first config.
+    pub fn get(config: &Config) -> Arc<Self> {
+        let key = (config.l1_rpc_url.clone(), config.l1_beacon_url.clone());
+        let mut registry = REGISTRY.lock().unwrap_or_else(|err| err.into_inner());
+
+        registry
+            .entry(key)
+            .or_insert_with(|| {
+                let limiter = RateLimiter::from_config(config);
+
+                Arc::new(Self {
+                    provider: generate_http_provider(&config.l1_rpc_url, limiter.clone()),
+                    batch_client: generate_client(&config.l1_rpc_url, limiter),
+                    block_receipts_supported: AtomicBool::new(true),
+                    batches_supported: AtomicBool::new(true),
+                    blob_fetcher: Arc::new(BlobFetcher::new(config.l1_beacon_url.clone())),
+                    finalized_hashes: Mutex::new(BTreeMap::new()),
+                    blocks: HashCache::new("blocks", MAX_CACHED_BLOCKS),
+                    receipts: HashCache::new("receipts", MAX_CACHED_BLOCKS),
+                    system_configs: HashCache::new("system_configs", MAX_CACHED_SYSTEM_CONFIGS),
+                })
+            })
+            .clone()
+    }
+
+    /// Fetches a block with its transactions. Finalized blocks are served from the cache.
+    pub async fn get_block_with_txs(
+        &self,
+        block_num: u64,
+        finalized: bool,
+    ) -> Result<Block<Transaction>> {
+        if finalized {
+            if let Some(block) = self
+                .finalized_hash(block_num)
+                .and_then(|h| self.blocks.get(&h))
+            {
+                return Ok(block);
+            }
+        }
+
+        let block = self
+            .provider
+            .get_block_with_txs(block_num)
+            .await?
+            .ok_or(eyre::eyre!("block not found"))?;
+
+        let hash = block.hash.ok_or(eyre::eyre!("block pending"))?;
+        self.blocks.insert(hash, block.clone());
+
+        if finalized {
+            let mut hashes = lock(&self.finalized_hashes);
+            hashes.insert(block_num, hash);
+
+            while hashes.len() > MAX_CACHED_BLOCKS {
+                hashes.pop_first();
+            }
+        }
+
+        Ok(block)
+    }
+
+    /// Fetches the receipts of a block, serving them from the cache if possible
+    pub async fn get_block_receipts(
+        &self,
+        block: &Block<Transaction>,
+    ) -> Result<Vec<TransactionReceipt>> {
+        let hash = block.hash.ok_or(eyre::eyre!("block pending"))?;
+        let number = block.number.ok_or(eyre::eyre!("block pending"))?;
+
+        if let Some(receipts) = self.receipts.get(&hash) {
+            return Ok(receipts);
+        }
+
+        let receipts = if self.block_receipts_supported.load(Ordering::Relaxed) {
+            match self.provider.get_block_receipts(number).await {
+                Err(err) if is_unsupported(&err) => {
+                    tracing::warn!(
+                        "[l1] eth_getBlockReceipts is not supported, fetching receipts by transaction"
+                    );
+                    self.block_receipts_supported
+                        .store(false, Ordering::Relaxed);
+                    self.get_transaction_receipts(block).await?
+                }
+                res => res?,
+            }
+        } else {
+            self.get_transaction_receipts(block).await?
+        };
+
+        // the block may have been reorged out since it was fetched
+        if receipts.iter().any(|r| r.block_hash != Some(hash)) {
+            eyre::bail!("receipts do not belong to block {:?}", hash);
+        }
+
+        self.receipts.insert(hash, receipts.clone());
+
+        Ok(receipts)
+    }
+
+    /// Fetches the logs matching each filter, in one batch if there are several
+    pub async fn get_logs(&self, filters: &[Filter]) -> Result<Vec<Vec<Log>>> {
+        let params = filters.iter().map(|filter| [filter]).collect::<Vec<_>>();
+        self.request_batch("eth_getLogs", &params).await
+    }
+
+    /// Fetches the receipts of the transactions of a block in batches
+    async fn get_transaction_receipts(
+        &self,
+        block: &Block<Transaction>,
+    ) -> Result<Vec<TransactionReceipt>> {
+        let params = block
+            .transactions
+            .iter()
+            .map(|tx| [tx.hash])
+            .collect::<Vec<_>>();
+        let receipts: Vec<Option<TransactionReceipt>> = self
+            .request_batch("eth_getTransactionReceipt", &params)
+            .await?;
+
+        receipts
+            .into_iter()
+            .zip(&block.transactions)
+            .map(|(receipt, tx)| receipt.ok_or(eyre::eyre!("receipt of {:?} not found", tx.hash)))
+            .collect()
+    }
+
+    /// Sends a request to the given method for each of the params, in JSON-RPC batches of
+    /// at most [MAX_BATCH_SIZE] requests unless the L1 RPC does not serve batches
+    async fn request_batch<T, R>(&self, method: &str, params: &[T]) -> Result<Vec<R>>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: Debug + Serialize + DeserializeOwned + Send,
+    {
+        let mut results = Vec::with_capacity(params.len());
+        for chunk in params.chunks(MAX_BATCH_SIZE) {
+            results.extend(self.request_chunk(method, chunk).await?);
+        }
+
+        Ok(results)
+    }
+
+    /// Sends the requests of a chunk in one JSON-RPC batch. If the batch fails, the requests
+    /// are sent one by one, and only once the L1 RPC responds that it does not serve
+    /// batches, batches are not sent anymore.
+    async fn request_chunk<T, R>(&self, method: &str, params: &[T]) -> Result<Vec<R>>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: Debug + Serialize + DeserializeOwned + Send,
+    {
+        if params.len() < 2 || !self.batches_supported.load(Ordering::Relaxed) {
+            return self.request_each(method, params).await;
+        }
+
+        match self.batch_client.request_batch(method, params).await {
+            Ok(results) => Ok(results),
+            Err(err) if is_rate_limited(&err) => Err(err.into()),
+            Err(err) if is_batch_unsupported(&err) => {
+                tracing::warn!(
+                    "[l1] batch requests are not supported ({}), sending requests one by one",
+                    err
+                );
+                self.batches_supported.store(false, Ordering::Relaxed);
+
+                self.request_each(method, params).await
+            }
+            Err(err) => {
+                tracing::debug!(
+                    "[l1] batch request failed ({}), sending requests one by one",
+                    err
+                );
+                self.request_each(method, params).await
+            }
+        }
+    }
+
+    /// Sends a request to the given method for each of the params, one after another
+    async fn request_each<T, R>(&self, method: &str, params: &[T]) -> Result<Vec<R>>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: Debug + Serialize + DeserializeOwned + Send,
+    {
+        let mut results = Vec::with_capacity(params.len());
+        for params in params {
+            results.push(self.provider.request(method, params).await?);
+        }
+
+        Ok(results)
+    }
+
+    /// Returns the system config of the given contract in effect after the given block, if
+    /// known
+    pub fn system_config(&self, contract: Address, block_hash: H256) -> Option<SystemConfig> {
+        self.system_configs
+            .get(&system_config_key(contract, block_hash))
+    }
+
+    /// Records the system config of the given contract in effect after the given block
+    pub fn insert_system_config(&self, contract: Address, block_hash: H256, config: SystemConfig) {
+        self.system_configs
+            .insert(system_config_key(contract, block_hash), config);
+    }
+
+    fn finalized_hash(&self, block_num: u64) -> Option<H256> {
+        lock(&self.finalized_hashes).get(&block_num).copied()
+    }
+}
+
+/// Returns true if the L1 RPC rejected a request because it does not serve its method
+fn is_unsupported(err: &ProviderError) -> bool {
+    err.as_error_response().is_some_and(|err| {
+        let message = err.message.to_lowercase();
+        err.code == -32601
+            || message.contains("not supported")
+            || message.contains("does not exist")
+            || message.contains("method not found")
+    })
+}
+
+/// Returns true if the L1 RPC rejected a JSON-RPC batch because it does not serve batches,
+/// rather than failing one of its requests
+fn is_batch_unsupported(err: &HttpClientError) -> bool {
+    let is_unsupported_message = |message: &str| {
+        let message = message.to_lowercase();
+        message.contains("batch")
+            && ["not supported", "unsupported", "disabled", "not allowed"]
+                .iter()
+                .any(|reason| message.contains(reason))
+    };
+
+    match err {
+        HttpClientError::JsonRpcError(err) => is_unsupported_message(&err.message),
+        // a single error response to the whole batch, since it is not a batch response
+        HttpClientError::SerdeJson { text, .. } => {
+            let Ok(response) = serde_json::from_str::<serde_json::Value>(text) else {
+                return false;
+            };
+
+            let Some(error) = response.get("error") else {
+                return false;
+            };
+
+            let code = error.get("code").and_then(|code| code.as_i64());
+            let message = error
+                .get("message")
+                .and_then(|message| message.as_str())
+                .unwrap_or_default();
+
+            code == Some(-32601)
+                || message.to_lowercase().contains("method not found")
+                || is_unsupported_message(message)
+        }
+        _ => false,
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Returns the cache key of the system config of a contract after a block
+fn system_config_key(contract: Address, block_hash: H256) -> H256 {
+    H256(keccak256(
+        [contract.as_slice(), block_hash.as_bytes()].concat(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn test_shared_between_watchers() {
+        let config = |l1_rpc_url: &str| Config {
+            l1_rpc_url: l1_rpc_url.to_string(),
+            l1_beacon_url: "http://localhost:5052".to_string(),
+            ..Default::default()
+        };
+
+        let a = SharedL1::get(&config("http://localhost:8545"));
+        let b = SharedL1::get(&config("http://localhost:8545"));
+        let c = SharedL1::get(&config("http://localhost:8546"));
+
+        assert!(Arc::ptr_eq(&a, &b));
+        assert!(!Arc::ptr_eq(&a, &c));
+
+        // rollups on the same L1 never read each other's system config
+        let block_hash = H256::repeat_byte(1);
+        let (rollup_a, rollup_b) = (Address::repeat_byte(0xaa), Address::repeat_byte(0xbb));
+        a.insert_system_config(rollup_a, block_hash, Config::default().chain.system_config);
+        assert!(b.system_config(rollup_a, block_hash).is_some());
+        assert!(b.system_config(rollup_b, block_hash).is_none());
+    }
+
+    #[test]
+    fn test_batch_unsupported() {
+        let single_error = |code: i64, message: &str| HttpClientError::SerdeJson {
+            err: serde_json::from_str::<Vec<u8>>("{}").unwrap_err(),
+            text: format!(
+                r#"{{"jsonrpc":"2.0","id":null,"error":{{"code":{},"message":"{}"}}}}"#,
+                code, message
+            ),
+        };
+        let request_error = |message: &str| {
+            HttpClientError::JsonRpcError(ethers::providers::JsonRpcError {
+                code: -32000,
+                message: message.to_string(),
+                data: None,
+            })
+        };
+
+        assert!(is_batch_unsupported(&single_error(
+            -32601,
+            "the method does not exist"
+        )));
+        assert!(is_batch_unsupported(&single_error(
+            -32600,
+            "batch requests are disabled"
+        )));
+        assert!(is_batch_unsupported(&request_error("batch not supported")));
+
+        // failures of the batch or of its requests do not disable batches
+        assert!(!is_batch_unsupported(&single_error(
+            -32000,
+            "request timed out"
+        )));
+        assert!(!is_batch_unsupported(&request_error("header not found")));
+        assert!(!is_batch_unsupported(&request_error(
+            "expected 3 batch responses, got 2"
+        )));
+    }
+
+    #[cfg(feature = "rpc")]
+    #[tokio::test]
+    async fn test_receipts_fallback() -> Result<()> {
+        use jsonrpsee::{server::ServerBuilder, RpcModule};
+
+        let block_hash = H256::repeat_byte(1);
+
+        // an L1 RPC serving receipts by transaction only
+        let mut module = RpcModule::new(());
+        module.register_method("eth_getTransactionReceipt", move |params, _| {
+            let (hash,): (H256,) = params.parse()?;
+            Ok::<_, jsonrpsee::core::Error>(TransactionReceipt {
+                transaction_hash: hash,
+                block_hash: Some(block_hash),
+                ..Default::default()
+            })
+        })?;
+
+        let server = ServerBuilder::default().build("127.0.0.1:0").await?;
+        let url = format!("http://{}", server.local_addr()?);
+        let _handle = server.start(module)?;
+
+        let l1 = SharedL1::get(&Config {
+            l1_rpc_url: url,
+            l1_beacon_url: "http://localhost:5052".to_string(),
+            ..Default::default()
+        });
+
+        let hashes = [H256::repeat_byte(2), H256::repeat_byte(3)];
+        let block = Block {
+            hash: Some(block_hash),
+            number: Some(10.into()),
+            transactions: hashes
+                .iter()
+                .map(|&hash| Transaction {
+                    hash,
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+
+        let receipts = l1.get_block_receipts(&block).await?;
+        let receipt_hashes = receipts
+            .iter()
+            .map(|receipt| receipt.transaction_hash)
+            .collect::<Vec<_>>();
+
+        assert_eq!(receipt_hashes, hashes);
+        assert!(!l1.block_receipts_supported.load(Ordering::Relaxed));
+        assert!(l1.batches_supported.load(Ordering::Relaxed));
+
+        Ok(())
+    }
+}
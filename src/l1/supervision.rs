@@ -0,0 +1,96 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Time without a heartbeat after which a watcher task is considered stuck
+pub const STALL_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Interval of the heartbeats sent while a watcher task waits for derivation to catch up
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Liveness signal of a background task, updated by the task and checked by its supervisor
+#[derive(Debug, Clone)]
+pub struct Heartbeat {
+    /// When the heartbeat was created
+    start: Instant,
+    /// Milliseconds since `start` of the latest beat
+    last_beat: Arc<AtomicU64>,
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+            last_beat: Arc::new(AtomicU64::new(0)),
+        }
+    }
+}
+
+impl Heartbeat {
+    /// Records that the task is alive
+    pub fn beat(&self) {
+        let elapsed = self.start.elapsed().as_millis() as u64;
+        self.last_beat.store(elapsed, Ordering::Relaxed);
+    }
+
+    /// Returns the time since the latest beat
+    pub fn elapsed(&self) -> Duration {
+        let last_beat = Duration::from_millis(self.last_beat.load(Ordering::Relaxed));
+        self.start.elapsed().saturating_sub(last_beat)
+    }
+}
+
+/// Why a watcher task was restarted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RestartReason {
+    /// The task exited, usually after a panic
+    Died,
+    /// The task stopped sending heartbeats
+    Stalled,
+}
+
+impl RestartReason {
+    /// Returns the metric label of the reason
+    pub fn label(&self) -> &'static str {
+        match self {
+            RestartReason::Died => "died",
+            RestartReason::Stalled => "stalled",
+        }
+    }
+}
+
+/// The restarts of a supervised watcher task
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WatcherRestarts {
+    /// Number of restarts since the node started
+    pub count: u64,
+    /// Why the task was last restarted
+    pub last_reason: Option<RestartReason>,
+    /// Unix timestamp of the last restart
+    pub last_restart: Option<u64>,
+    /// The L1 block the task resumed after at its last restart
+    pub last_resumed_after: Option<u64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heartbeat() {
+        let heartbeat = Heartbeat::default();
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(heartbeat.elapsed() >= Duration::from_millis(20));
+
+        // clones share the beats
+        heartbeat.clone().beat();
+        assert!(heartbeat.elapsed() < Duration::from_millis(20));
+    }
+}
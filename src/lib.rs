@@ -25,6 +25,8 @@
 //! - [`network`]: Manages peer-to-peer networking.
 //! - [`telemetry`]: Handles application telemetry and logging.
 //! - [`rpc`]: Implements the RPC server for external queries.
+//! - [`store`]: Persists node state in an embedded key-value store.
+//! - [`signer`]: Signs with keys held in memory or by a remote signer.
 //! - [`runner`]: Manages the node's operation in various synchronization modes.
 //! - [`version`]: Provides version information for the `magi` crate.
 //!
@@ -39,6 +41,16 @@
 //!
 //! Then, refer to the individual modules for specific functionality.
 //!
+//! ## Cargo Features
+//!
+//! - `l1-client`: Fetches L1 blocks, receipts and blobs from the L1 RPC and beacon API.
+//! - `engine-client`: Provides the HTTP and IPC clients of the L2 Engine API.
+//! - `node`: Enables the [`driver`], [`runner`], [`network`] and [`signer`] modules and the binaries.
+//! - `rpc`: Enables the [`rpc`] server of the node.
+//!
+//! All features are enabled by default. Disabling them builds `magi` as a derivation
+//! library with only the pipeline, its stages and the shared types.
+//!
 #![warn(missing_docs)]
 /// A module for ingesting L1 chain data
 pub mod l1;
@@ -53,21 +65,32 @@ pub mod config;
 pub mod derive;
 
 /// A module for driving op-geth via the L2 Engine API
+#[cfg(feature = "node")]
 pub mod driver;
 
 /// A module for the L2 Engine API
 pub mod engine;
 
 /// Peer to peer networking
+#[cfg(feature = "node")]
 pub mod network;
 
 /// Application telemetry and logging
 pub mod telemetry;
 
 /// RPC module to host rpc server
+#[cfg(feature = "rpc")]
 pub mod rpc;
 
+/// An embedded key-value store for persisting node state
+pub mod store;
+
+/// Signers for the sequencer key
+#[cfg(feature = "node")]
+pub mod signer;
+
 /// A module to handle running Magi in different sync modes
+#[cfg(feature = "node")]
 pub mod runner;
 
 /// A module to get current Magi version.
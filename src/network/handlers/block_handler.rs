@@ -1,21 +1,31 @@
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::time::SystemTime;
 
-use ethers::types::{Address, Bytes, Signature, H256};
+use ethers::types::{Address, Signature, H256};
 use ethers::utils::keccak256;
 use eyre::Result;
 use libp2p::gossipsub::{IdentTopic, Message, MessageAcceptance, TopicHash};
-use ssz_rs::{prelude::*, List, Vector, U256};
 use tokio::sync::watch;
 
-use crate::{common::RawTransaction, engine::ExecutionPayload};
+use crate::config::HardforkSchedule;
+use crate::engine::{ExecutionPayload, PayloadEnvelope, PayloadVersion};
 
 use super::Handler;
 
+/// Maximum size of a decompressed gossip message, as in the [specs]
+///
+/// [specs]: https://specs.optimism.io/protocol/rollup-node-p2p.html#gossipsub-topics
+pub const MAX_GOSSIP_SIZE: usize = 10 * (1 << 20);
+
+/// Length of the sequencer signature prefixed to the blocks gossip messages
+const SIGNATURE_LEN: usize = 65;
+
 /// Responsible for managing blocks received via p2p gossip
 pub struct BlockHandler {
     /// Chain ID of the L2 blockchain. Used to filter out gossip messages intended for other blockchains.
     chain_id: u64,
+    /// The hardforks of the L2 blockchain, selecting the payload version of each block
+    hardforks: HardforkSchedule,
     /// A channel sender to forward new blocks to other modules
     block_sender: Sender<ExecutionPayload>,
     /// A [watch::Receiver] to monitor changes to the unsafe block signer.
@@ -24,15 +34,20 @@ pub struct BlockHandler {
     blocks_v1_topic: IdentTopic,
     /// The libp2p topic for Canyon/Delta blocks: `/optimism/{chain_id}/1/blocks`
     blocks_v2_topic: IdentTopic,
+    /// The libp2p topic for Ecotone blocks: `/optimism/{chain_id}/2/blocks`
     blocks_v3_topic: IdentTopic,
+    /// The libp2p topic for Isthmus blocks: `/optimism/{chain_id}/3/blocks`
+    blocks_v4_topic: IdentTopic,
 }
 
-struct ExecutionPayloadEnvelope {
-    payload: ExecutionPayload,
+/// A block received via p2p gossip
+struct ReceivedBlock {
+    /// The payload and its parent beacon block root
+    envelope: PayloadEnvelope,
+    /// The signature of the sequencer
     signature: Signature,
+    /// The hash of the signed envelope
     hash: PayloadHash,
-    #[allow(unused)]
-    parent_beacon_block_root: Option<H256>,
 }
 
 impl Handler for BlockHandler {
@@ -40,20 +55,34 @@ impl Handler for BlockHandler {
     fn handle(&self, msg: Message) -> MessageAcceptance {
         tracing::debug!("received block");
 
-        let decoded = if msg.topic == self.blocks_v1_topic.hash() {
-            decode_pre_ecotone_block_msg::<ExecutionPayloadV1SSZ>(msg.data)
+        let version = if msg.topic == self.blocks_v1_topic.hash() {
+            PayloadVersion::V1
         } else if msg.topic == self.blocks_v2_topic.hash() {
-            decode_pre_ecotone_block_msg::<ExecutionPayloadV2SSZ>(msg.data)
+            PayloadVersion::V2
         } else if msg.topic == self.blocks_v3_topic.hash() {
-            decode_post_ecotone_block_msg(msg.data)
+            PayloadVersion::V3
+        } else if msg.topic == self.blocks_v4_topic.hash() {
+            PayloadVersion::V4
         } else {
             return MessageAcceptance::Reject;
         };
 
-        match decoded {
-            Ok(envelope) => {
-                if self.block_valid(&envelope) {
-                    _ = self.block_sender.send(envelope.payload);
+        match decode_block_msg(version, msg.data) {
+            Ok(block) => {
+                let timestamp = block.envelope.execution_payload.timestamp.as_u64();
+                let expected = PayloadVersion::at(&self.hardforks, timestamp);
+                if expected != version {
+                    tracing::warn!(
+                        "unsafe block at timestamp {} gossiped as {:?} instead of {:?}",
+                        timestamp,
+                        version,
+                        expected
+                    );
+                    return MessageAcceptance::Reject;
+                }
+
+                if self.block_valid(&block) {
+                    _ = self.block_sender.send(block.envelope.into_payload());
                     MessageAcceptance::Accept
                 } else {
                     tracing::warn!("invalid unsafe block");
@@ -69,25 +98,34 @@ impl Handler for BlockHandler {
 
     /// The gossip topics accepted for new blocks
     fn topics(&self) -> Vec<TopicHash> {
-        vec![self.blocks_v1_topic.hash(), self.blocks_v2_topic.hash()]
+        vec![
+            self.blocks_v1_topic.hash(),
+            self.blocks_v2_topic.hash(),
+            self.blocks_v3_topic.hash(),
+            self.blocks_v4_topic.hash(),
+        ]
     }
 }
 
 impl BlockHandler {
-    /// Creates a new [BlockHandler] and opens a channel
+    /// Creates a new [BlockHandler] and opens a channel. Blocks are only accepted on the topic
+    /// of the payload version active at their timestamp.
     pub fn new(
         chain_id: u64,
+        hardforks: HardforkSchedule,
         unsafe_recv: watch::Receiver<Address>,
     ) -> (Self, Receiver<ExecutionPayload>) {
         let (sender, recv) = channel();
 
         let handler = Self {
             chain_id,
+            hardforks,
             block_sender: sender,
             unsafe_signer_recv: unsafe_recv,
             blocks_v1_topic: IdentTopic::new(format!("/optimism/{}/0/blocks", chain_id)),
             blocks_v2_topic: IdentTopic::new(format!("/optimism/{}/1/blocks", chain_id)),
             blocks_v3_topic: IdentTopic::new(format!("/optimism/{}/2/blocks", chain_id)),
+            blocks_v4_topic: IdentTopic::new(format!("/optimism/{}/3/blocks", chain_id)),
         };
 
         (handler, recv)
@@ -96,76 +134,161 @@ impl BlockHandler {
     /// Determines if a block is valid.
     ///
     /// True if the block is less than 1 minute old, and correctly signed by the unsafe block signer.
-    fn block_valid(&self, envelope: &ExecutionPayloadEnvelope) -> bool {
+    /// The signature is only verified for blocks on time.
+    fn block_valid(&self, block: &ReceivedBlock) -> bool {
         let current_timestamp = SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)
             .unwrap()
             .as_secs();
 
-        let is_future = envelope.payload.timestamp.as_u64() > current_timestamp + 5;
-        let is_past = envelope.payload.timestamp.as_u64() < current_timestamp - 60;
-        let time_valid = !(is_future || is_past);
+        let timestamp = block.envelope.execution_payload.timestamp.as_u64();
+        let is_future = timestamp > current_timestamp + 5;
+        let is_past = timestamp < current_timestamp - 60;
+        if is_future || is_past {
+            return false;
+        }
 
-        let msg = envelope.hash.signature_message(self.chain_id);
+        let msg = block.hash.signature_message(self.chain_id);
         let block_signer = *self.unsafe_signer_recv.borrow();
-        let sig_valid = envelope.signature.verify(msg, block_signer).is_ok();
-
-        time_valid && sig_valid
+        block.signature.verify(msg, block_signer).is_ok()
     }
 }
 
-/// Decodes a sequence of bytes to an [ExecutionPayloadEnvelope]
-fn decode_pre_ecotone_block_msg<T>(data: Vec<u8>) -> Result<ExecutionPayloadEnvelope>
-where
-    T: SimpleSerialize,
-    ExecutionPayload: From<T>,
-{
+/// Decodes a blocks gossip message of the given payload version: the snappy compressed
+/// signature of the sequencer, followed by the SSZ encoded [PayloadEnvelope], which carries
+/// the parent beacon block root from Ecotone as described in the [specs]. The signature
+/// covers the whole envelope.
+///
+/// The sizes of the compressed and decompressed message are checked before it is
+/// decompressed, so a small message cannot expand into a large allocation.
+///
+/// [specs]: https://specs.optimism.io/protocol/rollup-node-p2p.html#block-encoding
+fn decode_block_msg(version: PayloadVersion, data: Vec<u8>) -> Result<ReceivedBlock> {
+    if data.len() > snap::raw::max_compress_len(MAX_GOSSIP_SIZE) {
+        eyre::bail!("block message of {} bytes too large", data.len());
+    }
+
+    let len = snap::raw::decompress_len(&data)?;
+    let min_len = min_block_msg_len(version);
+    if len < min_len {
+        eyre::bail!(
+            "block message of {} bytes shorter than the {} bytes of a {:?} block",
+            len,
+            min_len,
+            version
+        );
+    }
+    if len > MAX_GOSSIP_SIZE {
+        eyre::bail!("block message of {} bytes too large", len);
+    }
+
     let mut decoder = snap::raw::Decoder::new();
     let decompressed = decoder.decompress_vec(&data)?;
-    let sig_data = &decompressed[..65];
-    let block_data = &decompressed[65..];
 
+    let (sig_data, envelope_data) = decompressed.split_at(SIGNATURE_LEN);
     let signature = Signature::try_from(sig_data)?;
+    let envelope = PayloadEnvelope::decode_ssz(version, envelope_data)?;
+    let hash = PayloadHash::from(envelope_data);
 
-    let payload: T = deserialize(block_data)?;
-    let payload: ExecutionPayload = ExecutionPayload::from(payload);
-
-    let hash = PayloadHash::from(block_data);
-
-    Ok(ExecutionPayloadEnvelope {
-        parent_beacon_block_root: None,
+    Ok(ReceivedBlock {
+        envelope,
         signature,
-        payload,
         hash,
     })
 }
 
-/// Decodes a sequence of bytes to an [ExecutionPayloadEnvelope]. The Ecotone V3
-/// block topic encoding includes the parent beacon block root as described in the [specs].
-///
-/// [specs]: https://specs.optimism.io/protocol/rollup-node-p2p.html#block-encoding
-fn decode_post_ecotone_block_msg(data: Vec<u8>) -> Result<ExecutionPayloadEnvelope> {
-    let mut decoder = snap::raw::Decoder::new();
-    let decompressed = decoder.decompress_vec(&data)?;
-    let sig_data = &decompressed[..65];
-    let parent_beacon_block_root = &decompressed[65..97];
-    let block_data = &decompressed[97..];
+/// Returns the length of a blocks gossip message of the given version without extra data,
+/// transactions nor withdrawals: the signature, the parent beacon block root from Ecotone and
+/// the fixed size fields of the payload
+fn min_block_msg_len(version: PayloadVersion) -> usize {
+    let payload_len = match version {
+        PayloadVersion::V1 => 508,
+        // the offset of the withdrawals
+        PayloadVersion::V2 => 512,
+        // the blob gas used and excess blob gas
+        PayloadVersion::V3 => 528,
+        // the withdrawals root
+        PayloadVersion::V4 => 560,
+    };
+
+    let root_len = if version.has_parent_beacon_block_root() {
+        32
+    } else {
+        0
+    };
+
+    SIGNATURE_LEN + root_len + payload_len
+}
 
-    let signature = Signature::try_from(sig_data)?;
+/// An unsafe block signed by the sequencer and encoded for the blocks gossip topic
+#[derive(Debug, Clone)]
+pub struct SignedBlock {
+    /// The blocks topic of the payload version
+    pub topic: IdentTopic,
+    /// The snappy compressed signature and SSZ encoded [PayloadEnvelope]
+    pub data: Vec<u8>,
+}
+
+/// An unsafe block encoded for the blocks gossip topic, waiting to be signed by the sequencer
+pub struct UnsignedBlock {
+    /// Chain ID of the L2 blockchain
+    chain_id: u64,
+    /// The payload version of the blocks topic
+    version: PayloadVersion,
+    /// The SSZ encoded [PayloadEnvelope]
+    data: Vec<u8>,
+}
 
-    let parent_beacon_block_root = Some(H256::from_slice(parent_beacon_block_root));
+impl UnsignedBlock {
+    /// Encodes a payload with the SSZ containers of the given version. Ecotone payloads must
+    /// be published with the parent beacon block root.
+    pub fn new(
+        chain_id: u64,
+        payload: &ExecutionPayload,
+        parent_beacon_block_root: Option<H256>,
+        version: PayloadVersion,
+    ) -> Result<Self> {
+        let envelope = PayloadEnvelope {
+            parent_beacon_block_root,
+            ..PayloadEnvelope::new(payload.clone())
+        };
 
-    let payload: ExecutionPayloadV3SSZ = deserialize(block_data)?;
-    let payload = ExecutionPayload::from(payload);
+        Ok(Self {
+            chain_id,
+            version,
+            data: envelope.encode_ssz(version)?,
+        })
+    }
 
-    let hash = PayloadHash::from(block_data);
+    /// The data whose keccak256 hash the sequencer signs
+    pub fn signature_data(&self) -> Vec<u8> {
+        PayloadHash::from(self.data.as_slice()).signature_data(self.chain_id)
+    }
 
-    Ok(ExecutionPayloadEnvelope {
-        parent_beacon_block_root,
-        signature,
-        payload,
-        hash,
-    })
+    /// Attaches the signature of the sequencer and compresses the block for gossip
+    pub fn sign(self, signature: &Signature) -> Result<SignedBlock> {
+        let mut sig_data = [0; SIGNATURE_LEN];
+        signature.r.to_big_endian(&mut sig_data[..32]);
+        signature.s.to_big_endian(&mut sig_data[32..64]);
+        // the recovery id is encoded without the legacy offset of 27
+        sig_data[64] = if signature.v >= 27 {
+            signature.v - 27
+        } else {
+            signature.v
+        } as u8;
+
+        let message = [sig_data.as_slice(), &self.data].concat();
+
+        let mut encoder = snap::raw::Encoder::new();
+        let data = encoder.compress_vec(&message)?;
+        let topic = IdentTopic::new(format!(
+            "/optimism/{}/{}/blocks",
+            self.chain_id,
+            self.version.number()
+        ));
+
+        Ok(SignedBlock { topic, data })
+    }
 }
 
 /// Represents the Keccak256 hash of the block
@@ -181,233 +304,164 @@ impl From<&[u8]> for PayloadHash {
 impl PayloadHash {
     /// The expected message that should be signed by the unsafe block signer.
     fn signature_message(&self, chain_id: u64) -> H256 {
+        keccak256(self.signature_data(chain_id)).into()
+    }
+
+    /// The data hashed into the signature message
+    fn signature_data(&self, chain_id: u64) -> Vec<u8> {
         let domain = H256::zero();
         let chain_id = H256::from_low_u64_be(chain_id);
         let payload_hash = self.0;
 
-        let data: Vec<u8> = [
+        [
             domain.as_bytes(),
             chain_id.as_bytes(),
             payload_hash.as_bytes(),
         ]
-        .concat();
-
-        keccak256(data).into()
+        .concat()
     }
 }
 
-/// A type alias for a vector of 32 bytes, representing a Bytes32 hash
-type Bytes32 = Vector<u8, 32>;
-/// A type alias for a vector of 20 bytes, representing an address
-type VecAddress = Vector<u8, 20>;
-/// A type alias for a byte list, representing a transaction
-type Transaction = List<u8, 1073741824>;
-
-/// The pre Canyon/Shanghai [ExecutionPayload] - the withdrawals field should not exist
-#[derive(SimpleSerialize, Default)]
-struct ExecutionPayloadV1SSZ {
-    /// Block hash of the parent block
-    pub parent_hash: Bytes32,
-    /// Fee recipient of the block. Set to the sequencer fee vault
-    pub fee_recipient: VecAddress,
-    /// State root of the block
-    pub state_root: Bytes32,
-    /// Receipts root of the block
-    pub receipts_root: Bytes32,
-    /// Logs bloom of the block
-    pub logs_bloom: Vector<u8, 256>,
-    /// The block mix_digest
-    pub prev_randao: Bytes32,
-    /// The block number
-    pub block_number: u64,
-    /// The block gas limit
-    pub gas_limit: u64,
-    /// Total gas used in the block
-    pub gas_used: u64,
-    /// Timestamp of the block
-    pub timestamp: u64,
-    /// Any extra data included in the block
-    pub extra_data: List<u8, 32>,
-    /// Base fee per gas of the block
-    pub base_fee_per_gas: U256,
-    /// Hash of the block
-    pub block_hash: Bytes32,
-    /// Transactions in the block
-    pub transactions: List<Transaction, 1048576>,
-}
+#[cfg(test)]
+mod tests {
+    use ethers::signers::{LocalWallet, Signer};
 
-impl From<ExecutionPayloadV1SSZ> for ExecutionPayload {
-    fn from(value: ExecutionPayloadV1SSZ) -> Self {
-        Self {
-            parent_hash: convert_hash(value.parent_hash),
-            fee_recipient: convert_address(value.fee_recipient),
-            state_root: convert_hash(value.state_root),
-            receipts_root: convert_hash(value.receipts_root),
-            logs_bloom: convert_byte_vector(value.logs_bloom),
-            prev_randao: convert_hash(value.prev_randao),
-            block_number: value.block_number.into(),
-            gas_limit: value.gas_limit.into(),
-            gas_used: value.gas_used.into(),
-            timestamp: value.timestamp.into(),
-            extra_data: convert_byte_list(value.extra_data),
-            base_fee_per_gas: convert_uint(value.base_fee_per_gas),
-            block_hash: convert_hash(value.block_hash),
-            transactions: convert_tx_list(value.transactions),
-            withdrawals: None,
-            blob_gas_used: None,
-            excess_blob_gas: None,
-        }
-    }
-}
+    use crate::common::RawTransaction;
 
-/// The Canyon/Shanghai [ExecutionPayload] - the withdrawals field should be an empty [List]
-#[derive(SimpleSerialize, Default)]
-struct ExecutionPayloadV2SSZ {
-    /// Block hash of the parent block
-    pub parent_hash: Bytes32,
-    /// Fee recipient of the block. Set to the sequencer fee vault
-    pub fee_recipient: VecAddress,
-    /// State root of the block
-    pub state_root: Bytes32,
-    /// Receipts root of the block
-    pub receipts_root: Bytes32,
-    /// Logs bloom of the block
-    pub logs_bloom: Vector<u8, 256>,
-    /// The block mix_digest
-    pub prev_randao: Bytes32,
-    /// The block number
-    pub block_number: u64,
-    /// The block gas limit
-    pub gas_limit: u64,
-    /// Total gas used in the block
-    pub gas_used: u64,
-    /// Timestamp of the block
-    pub timestamp: u64,
-    /// Any extra data included in the block
-    pub extra_data: List<u8, 32>,
-    /// Base fee per gas of the block
-    pub base_fee_per_gas: U256,
-    /// Hash of the block
-    pub block_hash: Bytes32,
-    /// Transactions in the block
-    pub transactions: List<Transaction, 1048576>,
-    /// An empty list. This is unused and only exists for L1 compatibility.
-    pub withdrawals: List<Withdrawal, 16>,
-}
+    use super::*;
 
-/// This represents an L1 validator Withdrawal, and is unused in OP stack rollups.
-/// Exists only for L1 compatibility
-#[derive(SimpleSerialize, Default)]
-struct Withdrawal {
-    /// Index of the withdrawal
-    index: u64,
-    /// Index of the validator
-    validator_index: u64,
-    /// Account address that has withdrawn
-    address: VecAddress,
-    /// The amount withdrawn
-    amount: u64,
-}
+    #[test]
+    fn test_signed_block_round_trip() -> Result<()> {
+        let wallet: LocalWallet =
+            "0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318".parse()?;
+        let (_signer_sender, signer_recv) = watch::channel(wallet.address());
+        let (handler, _) = BlockHandler::new(10, HardforkSchedule::default(), signer_recv);
 
-impl From<ExecutionPayloadV2SSZ> for ExecutionPayload {
-    /// Converts an ExecutionPayloadV2SSZ received via p2p gossip into an [ExecutionPayload] used by the engine.
-    fn from(value: ExecutionPayloadV2SSZ) -> Self {
-        Self {
-            parent_hash: convert_hash(value.parent_hash),
-            fee_recipient: convert_address(value.fee_recipient),
-            state_root: convert_hash(value.state_root),
-            receipts_root: convert_hash(value.receipts_root),
-            logs_bloom: convert_byte_vector(value.logs_bloom),
-            prev_randao: convert_hash(value.prev_randao),
-            block_number: value.block_number.into(),
-            gas_limit: value.gas_limit.into(),
-            gas_used: value.gas_used.into(),
-            timestamp: value.timestamp.into(),
-            extra_data: convert_byte_list(value.extra_data),
-            base_fee_per_gas: convert_uint(value.base_fee_per_gas),
-            block_hash: convert_hash(value.block_hash),
-            transactions: convert_tx_list(value.transactions),
-            withdrawals: Some(Vec::new()),
-            blob_gas_used: None,
-            excess_blob_gas: None,
-        }
-    }
-}
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_secs();
 
-#[derive(SimpleSerialize, Default)]
-struct ExecutionPayloadV3SSZ {
-    pub parent_hash: Bytes32,
-    pub fee_recipient: VecAddress,
-    pub state_root: Bytes32,
-    pub receipts_root: Bytes32,
-    pub logs_bloom: Vector<u8, 256>,
-    pub prev_randao: Bytes32,
-    pub block_number: u64,
-    pub gas_limit: u64,
-    pub gas_used: u64,
-    pub timestamp: u64,
-    pub extra_data: List<u8, 32>,
-    pub base_fee_per_gas: U256,
-    pub block_hash: Bytes32,
-    pub transactions: List<Transaction, 1048576>,
-    pub withdrawals: List<Withdrawal, 16>,
-    pub blob_gas_used: u64,
-    pub excess_blob_gas: u64,
-}
+        let mut payload = ExecutionPayload {
+            block_number: 5.into(),
+            timestamp: timestamp.into(),
+            logs_bloom: vec![0; 256].into(),
+            extra_data: vec![1, 2].into(),
+            base_fee_per_gas: 7.into(),
+            block_hash: H256::repeat_byte(3),
+            transactions: vec![RawTransaction(vec![4, 5, 6])],
+            ..Default::default()
+        };
 
-impl From<ExecutionPayloadV3SSZ> for ExecutionPayload {
-    fn from(value: ExecutionPayloadV3SSZ) -> Self {
-        Self {
-            parent_hash: convert_hash(value.parent_hash),
-            fee_recipient: convert_address(value.fee_recipient),
-            state_root: convert_hash(value.state_root),
-            receipts_root: convert_hash(value.receipts_root),
-            logs_bloom: convert_byte_vector(value.logs_bloom),
-            prev_randao: convert_hash(value.prev_randao),
-            block_number: value.block_number.into(),
-            gas_limit: value.gas_limit.into(),
-            gas_used: value.gas_used.into(),
-            timestamp: value.timestamp.into(),
-            extra_data: convert_byte_list(value.extra_data),
-            base_fee_per_gas: convert_uint(value.base_fee_per_gas),
-            block_hash: convert_hash(value.block_hash),
-            transactions: convert_tx_list(value.transactions),
-            withdrawals: Some(Vec::new()),
-            blob_gas_used: Some(value.blob_gas_used.into()),
-            excess_blob_gas: Some(value.excess_blob_gas.into()),
-        }
+        let block = UnsignedBlock::new(10, &payload, None, PayloadVersion::V1)?;
+        let signature = wallet.sign_hash(keccak256(block.signature_data()).into())?;
+        let signed = block.sign(&signature)?;
+        assert_eq!(signed.topic.hash(), handler.blocks_v1_topic.hash());
+
+        let block = decode_block_msg(PayloadVersion::V1, signed.data)?;
+        assert_eq!(block.envelope.execution_payload, payload);
+        assert!(handler.block_valid(&block));
+
+        payload.withdrawals = Some(Vec::new());
+        payload.blob_gas_used = Some(0.into());
+        payload.excess_blob_gas = Some(0.into());
+        assert!(UnsignedBlock::new(10, &payload, None, PayloadVersion::V3).is_err());
+
+        let root = H256::repeat_byte(9);
+        let block = UnsignedBlock::new(10, &payload, Some(root), PayloadVersion::V3)?;
+        let signature = wallet.sign_hash(keccak256(block.signature_data()).into())?;
+        let signed = block.sign(&signature)?;
+        assert_eq!(signed.topic.hash(), handler.blocks_v3_topic.hash());
+
+        let block = decode_block_msg(PayloadVersion::V3, signed.data)?;
+        assert_eq!(block.envelope.parent_beacon_block_root, Some(root));
+        assert!(handler.block_valid(&block));
+        assert_eq!(
+            block.envelope.into_payload(),
+            ExecutionPayload {
+                parent_beacon_block_root: Some(root),
+                ..payload.clone()
+            }
+        );
+
+        // the signature covers the parent beacon block root
+        let block = UnsignedBlock::new(10, &payload, Some(root), PayloadVersion::V3)?;
+        let signature = wallet.sign_hash(keccak256(block.signature_data()).into())?;
+        let mut message =
+            snap::raw::Decoder::new().decompress_vec(&block.sign(&signature)?.data)?;
+        message[65] ^= 1;
+        let data = snap::raw::Encoder::new().compress_vec(&message)?;
+        assert!(!handler.block_valid(&decode_block_msg(PayloadVersion::V3, data)?));
+
+        // blocks signed by another key are rejected
+        let other = LocalWallet::new(&mut rand::thread_rng());
+        let block = UnsignedBlock::new(10, &payload, Some(root), PayloadVersion::V3)?;
+        let signature = other.sign_hash(keccak256(block.signature_data()).into())?;
+        let block = decode_block_msg(PayloadVersion::V3, block.sign(&signature)?.data)?;
+        assert!(!handler.block_valid(&block));
+
+        // blocks are only accepted on the topic of the payload version at their timestamp
+        let message = |signed: SignedBlock| Message {
+            source: None,
+            data: signed.data,
+            sequence_number: None,
+            topic: signed.topic.hash(),
+        };
+        let block = UnsignedBlock::new(10, &payload, Some(root), PayloadVersion::V3)?;
+        let signature = wallet.sign_hash(keccak256(block.signature_data()).into())?;
+        let accepted = handler.handle(message(block.sign(&signature)?));
+        assert!(matches!(accepted, MessageAcceptance::Accept));
+
+        let block = UnsignedBlock::new(10, &payload, None, PayloadVersion::V2)?;
+        let signature = wallet.sign_hash(keccak256(block.signature_data()).into())?;
+        let rejected = handler.handle(message(block.sign(&signature)?));
+        assert!(matches!(rejected, MessageAcceptance::Reject));
+
+        Ok(())
     }
-}
 
-/// Converts [Bytes32] into [H256]
-fn convert_hash(bytes: Bytes32) -> H256 {
-    H256::from_slice(bytes.as_slice())
-}
+    #[test]
+    fn test_block_msg_limits() -> Result<()> {
+        let (_signer_sender, signer_recv) = watch::channel(Address::zero());
+        let (handler, _) = BlockHandler::new(10, HardforkSchedule::default(), signer_recv);
+        assert!(handler.topics().contains(&handler.blocks_v3_topic.hash()));
+        assert!(handler.topics().contains(&handler.blocks_v4_topic.hash()));
 
-/// Converts [VecAddress] into [Address]
-fn convert_address(address: VecAddress) -> Address {
-    Address::from_slice(address.as_slice())
-}
+        let compress = |data: &[u8]| snap::raw::Encoder::new().compress_vec(data);
+        let payload = ExecutionPayload {
+            logs_bloom: vec![0; 256].into(),
+            withdrawals: Some(Vec::new()),
+            blob_gas_used: Some(0.into()),
+            excess_blob_gas: Some(0.into()),
+            withdrawals_root: Some(H256::zero()),
+            ..Default::default()
+        };
 
-/// Converts an [ssz_rs::Vector] of bytes into [Bytes]
-fn convert_byte_vector<const N: usize>(vector: Vector<u8, N>) -> Bytes {
-    Bytes::from(vector.to_vec())
-}
+        for version in [
+            PayloadVersion::V1,
+            PayloadVersion::V2,
+            PayloadVersion::V3,
+            PayloadVersion::V4,
+        ] {
+            let envelope = PayloadEnvelope {
+                parent_beacon_block_root: Some(H256::zero()),
+                ..PayloadEnvelope::new(payload.clone())
+            };
+            let message = [vec![0; SIGNATURE_LEN], envelope.encode_ssz(version)?].concat();
+            assert_eq!(message.len(), min_block_msg_len(version));
+
+            // messages too short for the version are rejected before decoding
+            let err = decode_block_msg(version, compress(&message[1..])?).err();
+            assert!(err.unwrap().to_string().contains("shorter"));
+        }
 
-/// Converts an [ssz_rs::List] of bytes into [Bytes]
-fn convert_byte_list<const N: usize>(list: List<u8, N>) -> Bytes {
-    Bytes::from(list.to_vec())
-}
+        // so are messages decompressing past the maximum gossip size
+        let data = compress(&vec![0; MAX_GOSSIP_SIZE + 1])?;
+        let err = decode_block_msg(PayloadVersion::V1, data).err();
+        assert!(err.unwrap().to_string().contains("too large"));
 
-/// Converts a [U256] into [ethers::types::U64]
-fn convert_uint(value: U256) -> ethers::types::U64 {
-    let bytes = value.to_bytes_le();
-    ethers::types::U256::from_little_endian(&bytes)
-        .as_u64()
-        .into()
-}
+        // and messages which are not valid snappy
+        assert!(decode_block_msg(PayloadVersion::V1, vec![0xff; 700]).is_err());
 
-/// Converts [ssz_rs::List] of [Transaction] into a vector of [RawTransaction]
-fn convert_tx_list(value: List<Transaction, 1048576>) -> Vec<RawTransaction> {
-    value.iter().map(|tx| RawTransaction(tx.to_vec())).collect()
+        Ok(())
+    }
 }
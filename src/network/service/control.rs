@@ -0,0 +1,158 @@
+use std::{
+    str::FromStr,
+    sync::{Arc, RwLock},
+};
+
+use eyre::Result;
+use libp2p::{Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, oneshot};
+
+use super::PeerDump;
+
+/// A peer management request to the running [Service](super::Service)
+#[derive(Debug)]
+pub enum PeerCommand {
+    /// Disconnects a peer and denies its connections until it is unblocked
+    Block(PeerId, oneshot::Sender<Result<(), String>>),
+    /// Lifts the block of a peer
+    Unblock(PeerId, oneshot::Sender<Result<(), String>>),
+    /// Dials a peer
+    Connect(Multiaddr, oneshot::Sender<Result<(), String>>),
+}
+
+/// The peer counts returned by the `opp2p_peerStats` RPC method
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerCounts {
+    /// Number of connected peers
+    pub connected: usize,
+    /// Number of nodes in the discovery table
+    pub table: usize,
+    /// Number of mesh peers on the v1 blocks topic
+    pub blocks_topic: usize,
+    /// Number of mesh peers on the v2 blocks topic
+    #[serde(rename = "blocksTopicV2")]
+    pub blocks_topic_v2: usize,
+    /// Number of mesh peers on the v3 blocks topic
+    #[serde(rename = "blocksTopicV3")]
+    pub blocks_topic_v3: usize,
+    /// Number of banned and blocked peers
+    pub banned: usize,
+    /// Number of node records in the peerstore
+    pub known: usize,
+}
+
+/// Handles to inspect and manage the peers of a [Service](super::Service), for the
+/// `opp2p` RPC methods
+#[derive(Debug, Clone)]
+pub struct PeerHandles {
+    /// The stats of the connected and banned peers
+    pub dump: Arc<RwLock<PeerDump>>,
+    /// The peer counts, updated at every peer heartbeat
+    pub counts: Arc<RwLock<PeerCounts>>,
+    /// The node records of the discovery table
+    pub discovery_table: Arc<RwLock<Vec<String>>>,
+    /// Channel to send peer management requests to the service
+    commands: mpsc::Sender<PeerCommand>,
+}
+
+impl Default for PeerHandles {
+    /// Returns the handles of a service that is not running
+    fn default() -> Self {
+        Self::new(mpsc::channel(1).0)
+    }
+}
+
+impl PeerHandles {
+    /// Creates new [PeerHandles] sending requests to the given channel
+    pub fn new(commands: mpsc::Sender<PeerCommand>) -> Self {
+        Self {
+            dump: Default::default(),
+            counts: Default::default(),
+            discovery_table: Default::default(),
+            commands,
+        }
+    }
+
+    /// Blocks the peer with the given id
+    pub async fn block(&self, peer_id: &str) -> Result<()> {
+        let peer_id = parse_peer_id(peer_id)?;
+        self.request(|response| PeerCommand::Block(peer_id, response))
+            .await
+    }
+
+    /// Unblocks the peer with the given id
+    pub async fn unblock(&self, peer_id: &str) -> Result<()> {
+        let peer_id = parse_peer_id(peer_id)?;
+        self.request(|response| PeerCommand::Unblock(peer_id, response))
+            .await
+    }
+
+    /// Dials the peer at the given multiaddr
+    pub async fn connect(&self, addr: &str) -> Result<()> {
+        let addr = addr
+            .parse::<Multiaddr>()
+            .map_err(|err| eyre::eyre!("invalid multiaddr {}: {}", addr, err))?;
+
+        self.request(|response| PeerCommand::Connect(addr, response))
+            .await
+    }
+
+    /// Sends a request to the service and waits for its response
+    async fn request(
+        &self,
+        command: impl FnOnce(oneshot::Sender<Result<(), String>>) -> PeerCommand,
+    ) -> Result<()> {
+        let (response, response_recv) = oneshot::channel();
+        self.commands
+            .send(command(response))
+            .await
+            .map_err(|_| eyre::eyre!("p2p service stopped"))?;
+
+        response_recv
+            .await
+            .map_err(|_| eyre::eyre!("p2p service stopped"))?
+            .map_err(|err| eyre::eyre!(err))
+    }
+}
+
+fn parse_peer_id(peer_id: &str) -> Result<PeerId> {
+    PeerId::from_str(peer_id).map_err(|err| eyre::eyre!("invalid peer id {}: {}", peer_id, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_peer_commands() -> Result<()> {
+        assert!(PeerHandles::default()
+            .block(&PeerId::random().to_string())
+            .await
+            .is_err());
+
+        let (sender, mut commands) = mpsc::channel(1);
+        let handles = PeerHandles::new(sender);
+        tokio::spawn(async move {
+            while let Some(command) = commands.recv().await {
+                match command {
+                    PeerCommand::Block(_, response) => _ = response.send(Ok(())),
+                    PeerCommand::Unblock(peer_id, response) => {
+                        _ = response.send(Err(format!("peer {} is not blocked", peer_id)))
+                    }
+                    PeerCommand::Connect(_, response) => _ = response.send(Ok(())),
+                }
+            }
+        });
+
+        let peer_id = PeerId::random().to_string();
+        handles.block(&peer_id).await?;
+        assert!(handles.unblock(&peer_id).await.is_err());
+        assert!(handles.block("not a peer id").await.is_err());
+        handles.connect("/ip4/10.0.0.1/tcp/9222").await?;
+        assert!(handles.connect("10.0.0.1:9222").await.is_err());
+
+        Ok(())
+    }
+}
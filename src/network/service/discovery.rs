@@ -1,4 +1,8 @@
-use std::{str::FromStr, time::Duration};
+use std::{
+    str::FromStr,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
 
 use discv5::{
     enr::{CombinedKey, Enr, EnrBuilder, NodeId},
@@ -14,10 +18,15 @@ use unsigned_varint::{decode, encode};
 
 use super::types::{NetworkAddress, Peer};
 
-/// Starts the [Discv5] discovery service and continually tries to find new peers.
-/// Returns a [Receiver] to receive [Peer] structs
-pub fn start(addr: NetworkAddress, chain_id: u64) -> Result<Receiver<Peer>> {
-    let bootnodes = bootnodes();
+/// Starts the [Discv5] discovery service from the given bootnodes and continually tries to
+/// find new peers. Returns a [Receiver] to receive [Peer] structs. The node records of the
+/// routing table are written to `table` after every lookup.
+pub fn start(
+    addr: NetworkAddress,
+    chain_id: u64,
+    bootnodes: Vec<Enr<CombinedKey>>,
+    table: Arc<RwLock<Vec<String>>>,
+) -> Result<Receiver<Peer>> {
     let mut disc = create_disc(chain_id)?;
 
     let (sender, recv) = mpsc::channel::<Peer>(256);
@@ -46,6 +55,14 @@ pub fn start(addr: NetworkAddress, chain_id: u64) -> Result<Receiver<Peer>> {
                 }
             }
 
+            if let Ok(mut table) = table.write() {
+                *table = disc
+                    .table_entries_enr()
+                    .iter()
+                    .map(|enr| enr.to_base64())
+                    .collect();
+            }
+
             sleep(Duration::from_secs(10)).await;
         }
     });
@@ -119,7 +136,7 @@ impl From<OpStackEnrData> for Vec<u8> {
 }
 
 /// Default bootnodes to use. Currently consists of 2 Base bootnodes & 1 Optimism bootnode.
-fn bootnodes() -> Vec<Enr<CombinedKey>> {
+pub fn bootnodes() -> Vec<Enr<CombinedKey>> {
     let bootnodes = [
         "enr:-J64QBbwPjPLZ6IOOToOLsSjtFUjjzN66qmBZdUexpO32Klrc458Q24kbty2PdRaLacHM5z-cZQr8mjeQu3pik6jPSOGAYYFIqBfgmlkgnY0gmlwhDaRWFWHb3BzdGFja4SzlAUAiXNlY3AyNTZrMaECmeSnJh7zjKrDSPoNMGXoopeDF4hhpj5I0OsQUUt4u8uDdGNwgiQGg3VkcIIkBg",
         "enr:-J64QAlTCDa188Hl1OGv5_2Kj2nWCsvxMVc_rEnLtw7RPFbOfqUOV6khXT_PH6cC603I2ynY31rSQ8sI9gLeJbfFGaWGAYYFIrpdgmlkgnY0gmlwhANWgzCHb3BzdGFja4SzlAUAiXNlY3AyNTZrMaECkySjcg-2v0uWAsFsZZu43qNHppGr2D5F913Qqs5jDCGDdGNwgiQGg3VkcIIkBg",
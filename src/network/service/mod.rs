@@ -1,24 +1,68 @@
-use std::{net::SocketAddr, time::Duration};
+use std::{
+    collections::HashMap, convert::Infallible, net::SocketAddr, path::PathBuf, sync::mpsc::Sender,
+    time::Duration,
+};
+
+use discv5::enr::{CombinedKey, Enr};
 
 use eyre::Result;
 use futures::{prelude::*, select};
 use libp2p::{
-    gossipsub::{self, IdentTopic, Message, MessageId},
+    gossipsub::{self, IdentTopic, Message, MessageAcceptance, MessageId, TopicHash},
     mplex::MplexConfig,
+    multiaddr::Protocol,
     noise, ping,
     swarm::{NetworkBehaviour, SwarmBuilder, SwarmEvent},
     tcp, Multiaddr, PeerId, Swarm, Transport,
 };
 use libp2p_identity::Keypair;
 use openssl::sha::sha256;
+use tokio::{
+    sync::mpsc,
+    time::{interval, MissedTickBehavior},
+};
+
+use crate::engine::ExecutionPayload;
 
-use super::{handlers::Handler, service::types::NetworkAddress};
+use super::{
+    handlers::{
+        block_handler::{SignedBlock, MAX_GOSSIP_SIZE},
+        Handler,
+    },
+    service::types::{NetworkAddress, Peer},
+};
+
+use self::{
+    control::PeerCommand,
+    payload_sync::{PayloadSync, SyncEvent},
+    peers::PeerManager,
+    peerstore::Peerstore,
+};
 
+pub use control::{PeerCounts, PeerHandles};
+pub use peers::{
+    Direction, PeerDump, PeerStats, DEFAULT_MAX_INBOUND_PEERS, DEFAULT_MAX_OUTBOUND_PEERS,
+};
+
+/// A module to inspect and manage the peers of a running service
+mod control;
 /// A module to handle peer discovery
 mod discovery;
+/// A module to request execution payloads from peers
+mod payload_sync;
+/// A module to score, ban and limit peers
+mod peers;
+/// A module to persist known peers across restarts
+mod peerstore;
 /// A module to handle commonly used types in the p2p system.
 mod types;
 
+/// How often peer scores decay and peer stats are published
+const PEER_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often the peerstore is written to disk
+const PEERSTORE_SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
 /// Responsible for management of the `Discv5` & `libp2p` services.
 pub struct Service {
     /// Handles validation & processing of inbound messages
@@ -29,16 +73,45 @@ pub struct Service {
     chain_id: u64,
     /// A unique keypair to validate the node's identity
     keypair: Option<Keypair>,
+    /// Receives block numbers to request from peers, and sends back the received payloads
+    payload_sync: Option<(mpsc::Receiver<u64>, Sender<ExecutionPayload>)>,
+    /// Receives the signed blocks to publish, when running as the sequencer
+    block_publishing: Option<mpsc::Receiver<SignedBlock>>,
+    /// Maximum number of inbound connections
+    max_inbound_peers: usize,
+    /// Maximum number of outbound connections
+    max_outbound_peers: usize,
+    /// The peer stats and management channel shared with the RPC server
+    peer_handles: PeerHandles,
+    /// Receives the peer management requests sent through the [PeerHandles]
+    peer_commands: mpsc::Receiver<PeerCommand>,
+    /// Multiaddrs of peers that are always dialed and never pruned
+    static_peers: Vec<String>,
+    /// Node records replacing the default bootnodes, if not empty
+    bootnodes: Vec<String>,
+    /// The path to persist the peerstore at, if any
+    peerstore_path: Option<PathBuf>,
 }
 
 impl Service {
     /// Creates a new [Service]
     pub fn new(addr: SocketAddr, chain_id: u64) -> Self {
+        let (command_sender, peer_commands) = mpsc::channel(16);
+
         Self {
             handlers: Vec::new(),
             addr,
             chain_id,
             keypair: None,
+            payload_sync: None,
+            block_publishing: None,
+            max_inbound_peers: DEFAULT_MAX_INBOUND_PEERS,
+            max_outbound_peers: DEFAULT_MAX_OUTBOUND_PEERS,
+            peer_handles: PeerHandles::new(command_sender),
+            peer_commands,
+            static_peers: Vec::new(),
+            bootnodes: Vec::new(),
+            peerstore_path: None,
         }
     }
 
@@ -54,35 +127,181 @@ impl Service {
         self
     }
 
+    /// Sets the maximum number of inbound and outbound connections
+    pub fn set_peer_limits(mut self, max_inbound: usize, max_outbound: usize) -> Self {
+        self.max_inbound_peers = max_inbound;
+        self.max_outbound_peers = max_outbound;
+        self
+    }
+
+    /// Sets the static peers. Each multiaddr must end with the `/p2p/<peer id>` of the peer.
+    pub fn set_static_peers(mut self, static_peers: Vec<String>) -> Self {
+        self.static_peers = static_peers;
+        self
+    }
+
+    /// Replaces the default bootnodes with the given node records, unless empty
+    pub fn set_bootnodes(mut self, bootnodes: Vec<String>) -> Self {
+        self.bootnodes = bootnodes;
+        self
+    }
+
+    /// Persists the discovered node records and the peer scores at the given path, and
+    /// reconnects to the stored peers on start
+    pub fn set_peerstore_path(mut self, path: PathBuf) -> Self {
+        self.peerstore_path = Some(path);
+        self
+    }
+
+    /// Returns the handles to inspect and manage the peers while the service runs
+    pub fn peer_handles(&self) -> PeerHandles {
+        self.peer_handles.clone()
+    }
+
+    /// Requests the payloads of the block numbers received on `requests` from peers, and
+    /// sends the payloads of the responses to `payloads`. The payloads are not validated.
+    pub fn with_payload_sync(
+        mut self,
+        requests: mpsc::Receiver<u64>,
+        payloads: Sender<ExecutionPayload>,
+    ) -> Self {
+        self.payload_sync = Some((requests, payloads));
+        self
+    }
+
+    /// Publishes the signed blocks received on `blocks` on their gossip topics
+    pub fn with_block_publishing(mut self, blocks: mpsc::Receiver<SignedBlock>) -> Self {
+        self.block_publishing = Some(blocks);
+        self
+    }
+
     /// Starts the Discv5 peer discovery & libp2p services
     /// and continually listens for new peers and messages to handle
     pub fn start(mut self) -> Result<()> {
         let addr = NetworkAddress::try_from(self.addr)?;
         let keypair = self.keypair.unwrap_or_else(Keypair::generate_secp256k1);
 
-        let mut swarm = create_swarm(keypair, &self.handlers)?;
-        let mut peer_recv = discovery::start(addr, self.chain_id)?;
+        let static_peers = parse_static_peers(&self.static_peers)?;
+        let mut bootnodes = parse_bootnodes(&self.bootnodes)?;
+        if bootnodes.is_empty() {
+            bootnodes = discovery::bootnodes();
+        }
+
+        let peerstore_path = self.peerstore_path;
+        let mut peerstore = peerstore_path
+            .as_deref()
+            .map(Peerstore::load)
+            .unwrap_or_default();
+        let stored_enrs = peerstore.parsed_enrs();
+
+        let mut peers = PeerManager::new(
+            self.max_inbound_peers,
+            self.max_outbound_peers,
+            self.peer_handles.dump.clone(),
+            self.chain_id,
+        )
+        .with_static_peers(static_peers);
+        peers.restore(&peerstore);
+
+        let mut swarm = create_swarm(keypair, &self.handlers, peers, self.chain_id)?;
+
+        bootnodes.extend(stored_enrs.iter().cloned());
+        let peer_handles = self.peer_handles;
+        let mut peer_recv = discovery::start(
+            addr,
+            self.chain_id,
+            bootnodes,
+            peer_handles.discovery_table.clone(),
+        )?;
 
         let multiaddr = Multiaddr::from(addr);
         swarm
             .listen_on(multiaddr)
             .map_err(|_| eyre::eyre!("swarm listen failed"))?;
 
+        // reconnect to the peers of the previous run without waiting for discovery
+        for peer in stored_enrs.iter().flat_map(Peer::try_from) {
+            _ = swarm.dial(Multiaddr::from(peer));
+        }
+
         let mut handlers = Vec::new();
         handlers.append(&mut self.handlers);
 
+        let (mut sync_requests, sync_payloads) = match self.payload_sync {
+            Some((requests, payloads)) => (Some(requests), Some(payloads)),
+            None => (None, None),
+        };
+
+        let mut block_publishing = self.block_publishing;
+        let mut peer_commands = Some(self.peer_commands);
+        let blocks_topics: Vec<TopicHash> = (0..3)
+            .map(|version| {
+                TopicHash::from_raw(format!("/optimism/{}/{}/blocks", self.chain_id, version))
+            })
+            .collect();
+
         tokio::spawn(async move {
+            let mut heartbeat = interval(PEER_HEARTBEAT_INTERVAL);
+            heartbeat.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+            let mut save_peerstore = interval(PEERSTORE_SAVE_INTERVAL);
+            save_peerstore.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
             loop {
                 select! {
                     peer = peer_recv.recv().fuse() => {
                         if let Some(peer) = peer {
+                            peerstore.add_enr(&peer.enr);
+
                             let peer = Multiaddr::from(peer);
                             _ = swarm.dial(peer);
                         }
                     },
+                    number = next_message(&mut sync_requests).fuse() => {
+                        swarm.behaviour_mut().payload_sync.request(number);
+                    },
+                    block = next_message(&mut block_publishing).fuse() => {
+                        let gossipsub = &mut swarm.behaviour_mut().gossipsub;
+                        if let Err(err) = gossipsub.publish(block.topic, block.data) {
+                            tracing::warn!("failed to publish block: {}", err);
+                        }
+                    },
+                    command = next_message(&mut peer_commands).fuse() => {
+                        handle_command(&mut swarm, command);
+                    },
+                    _ = heartbeat.tick().fuse() => {
+                        let behaviour = swarm.behaviour_mut();
+                        let gossipsub = &behaviour.gossipsub;
+                        behaviour.peers.heartbeat(|peer_id| gossipsub.peer_score(peer_id));
+
+                        let connected = swarm.connected_peers().count();
+                        let behaviour = swarm.behaviour();
+                        let gossipsub = &behaviour.gossipsub;
+                        let mesh = |version: usize| gossipsub.mesh_peers(&blocks_topics[version]).count();
+                        let counts = PeerCounts {
+                            connected,
+                            table: peer_handles.discovery_table.read().map_or(0, |table| table.len()),
+                            blocks_topic: mesh(0),
+                            blocks_topic_v2: mesh(1),
+                            blocks_topic_v3: mesh(2),
+                            banned: behaviour.peers.banned_count(),
+                            known: peerstore.enrs.len(),
+                        };
+                        if let Ok(mut shared) = peer_handles.counts.write() {
+                            *shared = counts;
+                        }
+                    },
+                    _ = save_peerstore.tick().fuse() => {
+                        if let Some(path) = &peerstore_path {
+                            swarm.behaviour().peers.persist(&mut peerstore);
+                            if let Err(err) = peerstore.save(path) {
+                                tracing::warn!("failed to save peerstore: {}", err);
+                            }
+                        }
+                    },
                     event = swarm.select_next_some() => {
                         if let SwarmEvent::Behaviour(event) = event {
-                            event.handle(&mut swarm, &handlers);
+                            event.handle(&mut swarm, &handlers, sync_payloads.as_ref());
                         }
                     },
                 }
@@ -93,11 +312,83 @@ impl Service {
     }
 }
 
-/// Computes the message ID of a `gossipsub` message
+/// Applies a peer management request and sends back its result
+fn handle_command(swarm: &mut Swarm<Behaviour>, command: PeerCommand) {
+    match command {
+        PeerCommand::Block(peer_id, response) => {
+            swarm.behaviour_mut().peers.block(peer_id);
+            _ = response.send(Ok(()));
+        }
+        PeerCommand::Unblock(peer_id, response) => {
+            let result = match swarm.behaviour_mut().peers.unblock(&peer_id) {
+                true => Ok(()),
+                false => Err(format!("peer {} is not blocked", peer_id)),
+            };
+            _ = response.send(result);
+        }
+        PeerCommand::Connect(addr, response) => {
+            let result = swarm.dial(addr).map_err(|err| err.to_string());
+            _ = response.send(result);
+        }
+    }
+}
+
+/// Parses static peer multiaddrs into the peer id and address of each peer
+fn parse_static_peers(static_peers: &[String]) -> Result<HashMap<PeerId, Multiaddr>> {
+    static_peers
+        .iter()
+        .map(|addr| {
+            let multiaddr: Multiaddr = addr
+                .parse()
+                .map_err(|err| eyre::eyre!("invalid static peer {}: {}", addr, err))?;
+
+            let peer_id = match multiaddr.iter().last() {
+                Some(Protocol::P2p(hash)) => PeerId::from_multihash(hash).ok(),
+                _ => None,
+            }
+            .ok_or(eyre::eyre!("static peer {} has no /p2p/ peer id", addr))?;
+
+            Ok((peer_id, multiaddr))
+        })
+        .collect()
+}
+
+/// Parses bootnode records
+fn parse_bootnodes(bootnodes: &[String]) -> Result<Vec<Enr<CombinedKey>>> {
+    bootnodes
+        .iter()
+        .map(|enr| {
+            enr.parse()
+                .map_err(|err| eyre::eyre!("invalid bootnode {}: {}", enr, err))
+        })
+        .collect()
+}
+
+/// Waits for the next message on an optional channel, such as the block numbers to request
+/// from peers if payload sync is enabled
+async fn next_message<T>(messages: &mut Option<mpsc::Receiver<T>>) -> T {
+    match messages {
+        Some(recv) => match recv.recv().await {
+            Some(message) => message,
+            None => {
+                *messages = None;
+                future::pending().await
+            }
+        },
+        None => future::pending().await,
+    }
+}
+
+/// Computes the message ID of a `gossipsub` message. Messages decompressing past the maximum
+/// gossip size are hashed as invalid snappy, without being decompressed.
 fn compute_message_id(msg: &Message) -> MessageId {
-    let mut decoder = snap::raw::Decoder::new();
-    let id = match decoder.decompress_vec(&msg.data) {
-        Ok(data) => {
+    let decompressed = snap::raw::decompress_len(&msg.data)
+        .ok()
+        .filter(|len| *len <= MAX_GOSSIP_SIZE)
+        .and_then(|_| snap::raw::Decoder::new().decompress_vec(&msg.data).ok());
+
+    let id = match decompressed {
+        Some(data) => {
             let domain_valid_snappy: Vec<u8> = vec![0x1, 0x0, 0x0, 0x0];
             sha256(
                 [domain_valid_snappy.as_slice(), data.as_slice()]
@@ -106,7 +397,7 @@ fn compute_message_id(msg: &Message) -> MessageId {
             )[..20]
                 .to_vec()
         }
-        Err(_) => {
+        None => {
             let domain_invalid_snappy: Vec<u8> = vec![0x0, 0x0, 0x0, 0x0];
             sha256(
                 [domain_invalid_snappy.as_slice(), msg.data.as_slice()]
@@ -121,14 +412,19 @@ fn compute_message_id(msg: &Message) -> MessageId {
 }
 
 /// Creates the libp2p [Swarm]
-fn create_swarm(keypair: Keypair, handlers: &[Box<dyn Handler>]) -> Result<Swarm<Behaviour>> {
+fn create_swarm(
+    keypair: Keypair,
+    handlers: &[Box<dyn Handler>],
+    peers: PeerManager,
+    chain_id: u64,
+) -> Result<Swarm<Behaviour>> {
     let transport = tcp::tokio::Transport::new(tcp::Config::default())
         .upgrade(libp2p::core::upgrade::Version::V1Lazy)
         .authenticate(noise::Config::new(&keypair)?)
         .multiplex(MplexConfig::default())
         .boxed();
 
-    let behaviour = Behaviour::new(handlers)?;
+    let behaviour = Behaviour::new(handlers, peers, chain_id)?;
 
     Ok(
         SwarmBuilder::with_tokio_executor(transport, behaviour, PeerId::from(keypair.public()))
@@ -140,15 +436,19 @@ fn create_swarm(keypair: Keypair, handlers: &[Box<dyn Handler>]) -> Result<Swarm
 #[derive(NetworkBehaviour)]
 #[behaviour(out_event = "Event")]
 struct Behaviour {
+    /// Scores and bans peers, and enforces the connection limits
+    peers: PeerManager,
     /// Adds [libp2p::ping] to respond to inbound pings, and send periodic outbound pings
     ping: ping::Behaviour,
     /// Adds [libp2p::gossipsub] to enable gossipsub as the routing layer
     gossipsub: gossipsub::Behaviour,
+    /// Requests execution payloads from peers with the `payload_by_number` req/resp protocol
+    payload_sync: PayloadSync,
 }
 
 impl Behaviour {
     /// Configures the swarm behaviors, subscribes to the gossip topics, and returns a new [Behaviour]
-    fn new(handlers: &[Box<dyn Handler>]) -> Result<Self> {
+    fn new(handlers: &[Box<dyn Handler>], peers: PeerManager, chain_id: u64) -> Result<Self> {
         let ping = ping::Behaviour::default();
 
         let gossipsub_config = gossipsub::ConfigBuilder::default()
@@ -164,6 +464,7 @@ impl Behaviour {
             .validation_mode(gossipsub::ValidationMode::None)
             .validate_messages()
             .message_id_fn(compute_message_id)
+            .max_transmit_size(snap::raw::max_compress_len(MAX_GOSSIP_SIZE))
             .build()
             .map_err(|_| eyre::eyre!("gossipsub config creation failed"))?;
 
@@ -171,6 +472,14 @@ impl Behaviour {
             gossipsub::Behaviour::new(gossipsub::MessageAuthenticity::Anonymous, gossipsub_config)
                 .map_err(|_| eyre::eyre!("gossipsub behaviour creation failed"))?;
 
+        // without topic parameters, only broken gossip promises and ip colocation are scored
+        gossipsub
+            .with_peer_score(
+                gossipsub::PeerScoreParams::default(),
+                gossipsub::PeerScoreThresholds::default(),
+            )
+            .map_err(|err| eyre::eyre!("gossipsub peer scoring failed: {}", err))?;
+
         handlers
             .iter()
             .flat_map(|handler| {
@@ -187,7 +496,12 @@ impl Behaviour {
             })
             .collect::<Result<Vec<bool>>>()?;
 
-        Ok(Self { ping, gossipsub })
+        Ok(Self {
+            peers,
+            ping,
+            gossipsub,
+            payload_sync: PayloadSync::new(chain_id),
+        })
     }
 }
 
@@ -198,33 +512,90 @@ enum Event {
     Ping(ping::Event),
     /// Represents a [gossipsub::Event]
     Gossipsub(gossipsub::Event),
+    /// Represents a [SyncEvent]
+    PayloadSync(SyncEvent),
+}
+
+impl From<Infallible> for Event {
+    /// The peer manager emits no events
+    fn from(value: Infallible) -> Self {
+        match value {}
+    }
 }
 
 impl Event {
-    /// Handles received gossipsub messages. Ping messages are ignored.
+    /// Handles received gossipsub messages and payload responses. Ping messages are ignored.
     /// Reports back to [libp2p::gossipsub] to apply peer scoring and forward the message to other peers if accepted.
-    fn handle(self, swarm: &mut Swarm<Behaviour>, handlers: &[Box<dyn Handler>]) {
-        if let Self::Gossipsub(gossipsub::Event::Message {
-            propagation_source,
-            message_id,
-            message,
-        }) = self
-        {
-            let handler = handlers
-                .iter()
-                .find(|h| h.topics().contains(&message.topic));
-            if let Some(handler) = handler {
-                let status = handler.handle(message);
-
-                _ = swarm
-                    .behaviour_mut()
-                    .gossipsub
-                    .report_message_validation_result(&message_id, &propagation_source, status);
+    fn handle(
+        self,
+        swarm: &mut Swarm<Behaviour>,
+        handlers: &[Box<dyn Handler>],
+        sync_payloads: Option<&Sender<ExecutionPayload>>,
+    ) {
+        match self {
+            Self::Gossipsub(gossipsub::Event::Message {
+                propagation_source,
+                message_id,
+                message,
+            }) => {
+                let handler = handlers
+                    .iter()
+                    .find(|h| h.topics().contains(&message.topic));
+                if let Some(handler) = handler {
+                    let status = handler.handle(message);
+                    if matches!(status, MessageAcceptance::Reject) {
+                        swarm
+                            .behaviour_mut()
+                            .peers
+                            .on_invalid_block(&propagation_source);
+                    }
+
+                    _ = swarm
+                        .behaviour_mut()
+                        .gossipsub
+                        .report_message_validation_result(&message_id, &propagation_source, status);
+                }
+            }
+            Self::PayloadSync(SyncEvent::Response {
+                peer_id,
+                number,
+                latency,
+                payload,
+            }) => {
+                let peers = &mut swarm.behaviour_mut().peers;
+                peers.on_response(&peer_id, latency, payload.is_ok());
+
+                match payload {
+                    Ok(Some(payload)) => {
+                        if let Some(sender) = sync_payloads {
+                            _ = sender.send(*payload);
+                        }
+                    }
+                    Ok(None) => tracing::debug!("peer {} does not know block {}", peer_id, number),
+                    Err(err) => {
+                        tracing::debug!("invalid response for block {}: {}", number, err)
+                    }
+                }
+            }
+            Self::PayloadSync(SyncEvent::Timeout { peer_id, number }) => {
+                tracing::debug!("request for block {} to {} timed out", number, peer_id);
+                swarm.behaviour_mut().peers.on_timeout(&peer_id);
             }
+            Self::PayloadSync(SyncEvent::Request(number)) => {
+                tracing::debug!("peer requested block {}", number);
+            }
+            _ => {}
         }
     }
 }
 
+impl From<SyncEvent> for Event {
+    /// Converts [SyncEvent] to [Event]
+    fn from(value: SyncEvent) -> Self {
+        Event::PayloadSync(value)
+    }
+}
+
 impl From<ping::Event> for Event {
     /// Converts [ping::Event] to [Event]
     fn from(value: ping::Event) -> Self {
@@ -238,3 +609,22 @@ impl From<gossipsub::Event> for Event {
         Event::Gossipsub(value)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_static_peers() -> Result<()> {
+        let peer_id = PeerId::random();
+        let addr = format!("/ip4/10.0.0.1/tcp/9222/p2p/{}", peer_id);
+
+        let static_peers = parse_static_peers(std::slice::from_ref(&addr))?;
+        assert_eq!(static_peers[&peer_id], addr.parse::<Multiaddr>()?);
+
+        assert!(parse_static_peers(&["/ip4/10.0.0.1/tcp/9222".to_string()]).is_err());
+        assert!(parse_static_peers(&["10.0.0.1:9222".to_string()]).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,372 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Read},
+    iter,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use eyre::Result;
+use futures::{future::BoxFuture, AsyncReadExt, AsyncWriteExt, FutureExt};
+use libp2p::{
+    core::{Endpoint, InboundUpgrade, OutboundUpgrade, UpgradeInfo},
+    swarm::{
+        ConnectionDenied, ConnectionId, FromSwarm, NegotiatedSubstream, NetworkBehaviour,
+        NotifyHandler, OneShotHandler, OneShotHandlerConfig, PollParameters, SubstreamProtocol,
+        THandler, THandlerInEvent, ToSwarm,
+    },
+    Multiaddr, PeerId,
+};
+
+use crate::engine::{ExecutionPayload, PayloadEnvelope};
+
+/// Maximum size of a `payload_by_number` response, before and after decompression
+const MAX_RESPONSE_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Result code of a successful response
+const RESULT_SUCCESS: u8 = 0;
+
+/// Result code of a response to a request for an unknown payload
+const RESULT_NOT_FOUND: u8 = 1;
+
+/// Time after which a request without a response is reported as timed out
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Returns the protocol id of the `payload_by_number` req/resp protocol of a chain
+fn protocol_id(chain_id: u64) -> String {
+    format!("/opstack/req/payload_by_number/{}/0/", chain_id)
+}
+
+/// A [NetworkBehaviour] requesting execution payloads by block number from peers with the
+/// `payload_by_number` req/resp protocol described in the [specs].
+///
+/// Requests are spread over the connected peers in turn, and every response or timeout is
+/// reported with the peer and its latency. Payloads received in responses are unsigned, so
+/// they must be checked against a trusted block before they are used.
+///
+/// Inbound requests are answered as not found, because the node does not serve payloads.
+///
+/// [specs]: https://specs.optimism.io/protocol/rollup-node-p2p.html#payload_by_number
+pub struct PayloadSync {
+    /// The protocol id
+    protocol: String,
+    /// The connected peers
+    peers: Vec<PeerId>,
+    /// Index of the peer to send the next request to
+    next_peer: usize,
+    /// The requests waiting for a response, in the order they were sent
+    pending: VecDeque<(PeerId, u64, Instant)>,
+    /// Actions waiting to be returned to the swarm
+    actions: VecDeque<ToSwarm<SyncEvent, PayloadRequest>>,
+}
+
+/// An event emitted by [PayloadSync]
+#[derive(Debug)]
+pub enum SyncEvent {
+    /// A peer responded to a request
+    Response {
+        /// The peer that responded
+        peer_id: PeerId,
+        /// The requested block number
+        number: u64,
+        /// Time between the request and the response
+        latency: Duration,
+        /// The payload, `None` if the peer does not know it, or the reason the response was
+        /// unusable
+        payload: Result<Option<Box<ExecutionPayload>>, String>,
+    },
+    /// A peer did not respond to a request in time
+    Timeout {
+        /// The peer the request was sent to
+        peer_id: PeerId,
+        /// The requested block number
+        number: u64,
+    },
+    /// A peer requested a payload
+    Request(u64),
+}
+
+/// A message received on a `payload_by_number` substream
+#[derive(Debug)]
+pub enum Message {
+    /// A response to an outbound request
+    Response {
+        /// The requested block number
+        number: u64,
+        /// The decoded payload, if found
+        payload: Result<Option<Box<ExecutionPayload>>, String>,
+    },
+    /// An inbound request
+    Request(u64),
+}
+
+impl PayloadSync {
+    /// Creates a new [PayloadSync] for the given chain
+    pub fn new(chain_id: u64) -> Self {
+        Self {
+            protocol: protocol_id(chain_id),
+            peers: Vec::new(),
+            next_peer: 0,
+            pending: VecDeque::new(),
+            actions: VecDeque::new(),
+        }
+    }
+
+    /// Requests the payload of a block from the next connected peer
+    pub fn request(&mut self, number: u64) {
+        if self.peers.is_empty() {
+            tracing::debug!("no peers to request block {} from", number);
+            return;
+        }
+
+        let peer_id = self.peers[self.next_peer % self.peers.len()];
+        self.next_peer = self.next_peer.wrapping_add(1);
+
+        tracing::debug!("requesting block {} from {}", number, peer_id);
+
+        self.pending.push_back((peer_id, number, Instant::now()));
+
+        self.actions.push_back(ToSwarm::NotifyHandler {
+            peer_id,
+            handler: NotifyHandler::Any,
+            event: PayloadRequest {
+                protocol: self.protocol.clone(),
+                number,
+            },
+        });
+    }
+
+    fn handler(&self) -> THandler<Self> {
+        let listener = PayloadRequestListener {
+            protocol: self.protocol.clone(),
+        };
+
+        OneShotHandler::new(
+            SubstreamProtocol::new(listener, ()),
+            OneShotHandlerConfig::default(),
+        )
+    }
+}
+
+impl NetworkBehaviour for PayloadSync {
+    type ConnectionHandler = OneShotHandler<PayloadRequestListener, PayloadRequest, Message>;
+    type OutEvent = SyncEvent;
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        _peer: PeerId,
+        _local_addr: &Multiaddr,
+        _remote_addr: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(self.handler())
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        _peer: PeerId,
+        _addr: &Multiaddr,
+        _role_override: Endpoint,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(self.handler())
+    }
+
+    fn on_swarm_event(&mut self, event: FromSwarm<Self::ConnectionHandler>) {
+        match event {
+            FromSwarm::ConnectionEstablished(event) if event.other_established == 0 => {
+                self.peers.push(event.peer_id);
+            }
+            FromSwarm::ConnectionClosed(event) if event.remaining_established == 0 => {
+                self.peers.retain(|peer| *peer != event.peer_id);
+            }
+            _ => {}
+        }
+    }
+
+    fn on_connection_handler_event(
+        &mut self,
+        peer_id: PeerId,
+        _connection_id: ConnectionId,
+        message: Message,
+    ) {
+        let event = match message {
+            Message::Response { number, payload } => {
+                let position = self
+                    .pending
+                    .iter()
+                    .position(|(peer, num, _)| *peer == peer_id && *num == number);
+
+                // responses arriving after the timeout was reported are dropped
+                let Some((_, _, sent)) = position.and_then(|i| self.pending.remove(i)) else {
+                    return;
+                };
+
+                SyncEvent::Response {
+                    peer_id,
+                    number,
+                    latency: sent.elapsed(),
+                    payload,
+                }
+            }
+            Message::Request(number) => SyncEvent::Request(number),
+        };
+
+        self.actions.push_back(ToSwarm::GenerateEvent(event));
+    }
+
+    fn poll(
+        &mut self,
+        _cx: &mut Context<'_>,
+        _params: &mut impl PollParameters,
+    ) -> Poll<ToSwarm<SyncEvent, THandlerInEvent<Self>>> {
+        while let Some(&(peer_id, number, sent)) = self.pending.front() {
+            if sent.elapsed() < REQUEST_TIMEOUT {
+                break;
+            }
+
+            self.pending.pop_front();
+            self.actions
+                .push_back(ToSwarm::GenerateEvent(SyncEvent::Timeout {
+                    peer_id,
+                    number,
+                }));
+        }
+
+        match self.actions.pop_front() {
+            Some(action) => Poll::Ready(action),
+            None => Poll::Pending,
+        }
+    }
+}
+
+/// An outbound request for the payload of a block
+#[derive(Debug)]
+pub struct PayloadRequest {
+    /// The protocol id
+    protocol: String,
+    /// The requested block number
+    number: u64,
+}
+
+impl UpgradeInfo for PayloadRequest {
+    type Info = String;
+    type InfoIter = iter::Once<String>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        iter::once(self.protocol.clone())
+    }
+}
+
+impl OutboundUpgrade<NegotiatedSubstream> for PayloadRequest {
+    type Output = Message;
+    type Error = io::Error;
+    type Future = BoxFuture<'static, Result<Message, io::Error>>;
+
+    fn upgrade_outbound(self, mut socket: NegotiatedSubstream, _: Self::Info) -> Self::Future {
+        async move {
+            socket.write_all(&self.number.to_le_bytes()).await?;
+            socket.close().await?;
+
+            let mut response = Vec::new();
+            socket
+                .take(MAX_RESPONSE_SIZE)
+                .read_to_end(&mut response)
+                .await?;
+
+            Ok(Message::Response {
+                number: self.number,
+                payload: decode_response(&response)
+                    .map(|payload| payload.map(Box::new))
+                    .map_err(|err| err.to_string()),
+            })
+        }
+        .boxed()
+    }
+}
+
+/// Listens for inbound payload requests
+#[derive(Debug, Clone)]
+pub struct PayloadRequestListener {
+    /// The protocol id
+    protocol: String,
+}
+
+impl UpgradeInfo for PayloadRequestListener {
+    type Info = String;
+    type InfoIter = iter::Once<String>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        iter::once(self.protocol.clone())
+    }
+}
+
+impl InboundUpgrade<NegotiatedSubstream> for PayloadRequestListener {
+    type Output = Message;
+    type Error = io::Error;
+    type Future = BoxFuture<'static, Result<Message, io::Error>>;
+
+    fn upgrade_inbound(self, mut socket: NegotiatedSubstream, _: Self::Info) -> Self::Future {
+        async move {
+            let mut number = [0; 8];
+            socket.read_exact(&mut number).await?;
+
+            socket.write_all(&[RESULT_NOT_FOUND]).await?;
+            socket.close().await?;
+
+            Ok(Message::Request(u64::from_le_bytes(number)))
+        }
+        .boxed()
+    }
+}
+
+/// Decodes a `payload_by_number` response: a result code, a little endian version number and
+/// the snappy framed SSZ encoded payload. Returns `None` if the peer does not know the payload.
+fn decode_response(data: &[u8]) -> Result<Option<ExecutionPayload>> {
+    let (&result, data) = data.split_first().ok_or(eyre::eyre!("empty response"))?;
+
+    if result != RESULT_SUCCESS {
+        tracing::trace!("peer responded with result code {}", result);
+        return Ok(None);
+    }
+
+    if data.len() < 4 {
+        eyre::bail!("response too short");
+    }
+
+    let version = u32::from_le_bytes(data[..4].try_into()?);
+
+    let mut payload = Vec::new();
+    snap::read::FrameDecoder::new(&data[4..])
+        .take(MAX_RESPONSE_SIZE)
+        .read_to_end(&mut payload)?;
+
+    let envelope = PayloadEnvelope::decode_ssz(version.try_into()?, &payload)?;
+    Ok(Some(envelope.into_payload()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn test_decode_response() -> Result<()> {
+        assert!(decode_response(&[RESULT_NOT_FOUND])?.is_none());
+        assert!(decode_response(&[]).is_err());
+        assert!(decode_response(&[RESULT_SUCCESS, 0, 0]).is_err());
+
+        let mut response = vec![RESULT_SUCCESS];
+        response.extend_from_slice(&7u32.to_le_bytes());
+
+        let mut encoder = snap::write::FrameEncoder::new(Vec::new());
+        encoder.write_all(&[0; 64])?;
+        response.extend_from_slice(&encoder.into_inner()?);
+
+        let err = decode_response(&response).unwrap_err();
+        assert_eq!(err.to_string(), "unknown payload version 7");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,797 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    convert::Infallible,
+    fmt,
+    str::FromStr,
+    sync::{Arc, RwLock},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use libp2p::{
+    core::{ConnectedPoint, Endpoint},
+    swarm::{
+        dial_opts::{DialOpts, PeerCondition},
+        dummy, CloseConnection, ConnectionDenied, ConnectionId, FromSwarm, NetworkBehaviour,
+        PollParameters, THandler, THandlerInEvent, THandlerOutEvent, ToSwarm,
+    },
+    Multiaddr, PeerId,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::telemetry::metrics;
+
+use super::peerstore::{Peerstore, StoredBan};
+
+/// Peers scoring at or below this are banned
+const BAN_THRESHOLD: f64 = -100.0;
+
+/// Scores are clamped to this range, so a long good history cannot hide misbehavior
+const MAX_SCORE: f64 = 100.0;
+
+/// Fraction of the score kept at every heartbeat, decaying penalties and rewards over time
+const SCORE_DECAY: f64 = 0.9;
+
+/// Penalty for gossiping an invalid block
+const INVALID_BLOCK_PENALTY: f64 = -40.0;
+
+/// Penalty for responding with an undecodable payload
+const INVALID_RESPONSE_PENALTY: f64 = -40.0;
+
+/// Penalty for not responding to a request in time
+const TIMEOUT_PENALTY: f64 = -10.0;
+
+/// Penalty for a response slower than [SLOW_RESPONSE]
+const SLOW_RESPONSE_PENALTY: f64 = -5.0;
+
+/// Reward for a timely valid response
+const RESPONSE_REWARD: f64 = 1.0;
+
+/// Responses taking longer than this are penalized
+const SLOW_RESPONSE: Duration = Duration::from_secs(2);
+
+/// Peers with a gossipsub score at or below this are banned for gossip misbehavior. This
+/// matches the default gossipsub graylist threshold.
+const GOSSIP_BAN_THRESHOLD: f64 = -80.0;
+
+/// Duration of the first ban of a peer. Every further ban doubles it.
+const BAN_DURATION: Duration = Duration::from_secs(10 * 60);
+
+/// Maximum number of times the ban duration is doubled
+const MAX_BAN_DOUBLINGS: u32 = 7;
+
+/// Maximum number of disconnected peers whose score is remembered
+const MAX_KNOWN_SCORES: usize = 1024;
+
+pub use crate::config::{DEFAULT_MAX_INBOUND_PEERS, DEFAULT_MAX_OUTBOUND_PEERS};
+
+/// A [NetworkBehaviour] managing the connected peers.
+///
+/// Peers are scored on the blocks they gossip, the responses to payload requests and their
+/// gossipsub score. Peers whose score drops to the ban threshold are disconnected and banned,
+/// with a ban duration doubling on every further ban. Connections over the inbound and
+/// outbound limits are denied.
+///
+/// Static peers are exempt from bans and connection limits, and are redialed at every
+/// heartbeat while disconnected. Peers blocked by the operator are denied until unblocked,
+/// static peers included.
+///
+/// The current stats are published in a shared [PeerDump] for the `opp2p_peers` RPC.
+pub struct PeerManager {
+    /// Maximum number of inbound connections
+    max_inbound: usize,
+    /// Maximum number of outbound connections
+    max_outbound: usize,
+    /// Number of established inbound connections
+    inbound: usize,
+    /// Number of established outbound connections
+    outbound: usize,
+    /// The connected peers
+    peers: HashMap<PeerId, PeerState>,
+    /// The static peers and their addresses
+    static_peers: HashMap<PeerId, Multiaddr>,
+    /// The scores of disconnected peers, restored when they reconnect
+    known_scores: HashMap<PeerId, f64>,
+    /// The banned peers, kept after the ban expires to extend the next ban
+    bans: HashMap<PeerId, Ban>,
+    /// The peers blocked by the operator
+    blocked: HashSet<PeerId>,
+    /// The peer stats shared with the RPC server
+    dump: Arc<RwLock<PeerDump>>,
+    /// Actions waiting to be returned to the swarm
+    actions: VecDeque<ToSwarm<Infallible, THandlerInEvent<Self>>>,
+    /// The L2 chain id used to label metrics
+    chain_label: String,
+}
+
+/// The state of a connected peer
+#[derive(Debug)]
+struct PeerState {
+    /// Whether the first connection to the peer was inbound or outbound
+    direction: Direction,
+    /// The address of the first connection to the peer
+    address: Multiaddr,
+    /// Number of established connections
+    connections: usize,
+    /// Time the peer connected
+    connected_at: Instant,
+    /// The application score of the peer
+    score: f64,
+    /// The latest gossipsub score of the peer
+    gossip_score: f64,
+    /// Number of invalid blocks gossiped by the peer
+    invalid_blocks: u64,
+    /// Number of valid responses
+    responses: u64,
+    /// Number of responses slower than [SLOW_RESPONSE]
+    slow_responses: u64,
+    /// Number of requests that timed out or got an invalid response
+    failed_requests: u64,
+}
+
+/// A ban of a peer
+#[derive(Debug)]
+struct Ban {
+    /// Time the ban expires
+    until: Instant,
+    /// Number of times the peer has been banned
+    count: u32,
+}
+
+/// The direction of a connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// The peer dialed us
+    Inbound,
+    /// We dialed the peer
+    Outbound,
+}
+
+/// The stats of a connected peer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerStats {
+    /// The address of the peer
+    pub address: String,
+    /// Whether the first connection to the peer was inbound or outbound
+    pub direction: Direction,
+    /// Seconds since the peer connected
+    pub connected_secs: u64,
+    /// The application score of the peer
+    pub score: f64,
+    /// The latest gossipsub score of the peer
+    pub gossip_score: f64,
+    /// Number of invalid blocks gossiped by the peer
+    pub invalid_blocks: u64,
+    /// Number of valid responses to payload requests
+    pub responses: u64,
+    /// Number of slow responses to payload requests
+    pub slow_responses: u64,
+    /// Number of payload requests that timed out or got an invalid response
+    pub failed_requests: u64,
+    /// Whether the peer is a static peer
+    pub is_static: bool,
+}
+
+/// The response of the `opp2p_peers` RPC method
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerDump {
+    /// Number of connected peers
+    pub total_connected: usize,
+    /// The connected peers by peer id
+    pub peers: BTreeMap<String, PeerStats>,
+    /// The currently banned peer ids
+    pub banned_peers: Vec<String>,
+    /// The peer ids blocked by the operator
+    pub blocked_peers: Vec<String>,
+}
+
+impl PeerManager {
+    /// Creates a new [PeerManager] with the given connection limits
+    pub fn new(
+        max_inbound: usize,
+        max_outbound: usize,
+        dump: Arc<RwLock<PeerDump>>,
+        chain_id: u64,
+    ) -> Self {
+        Self {
+            max_inbound,
+            max_outbound,
+            inbound: 0,
+            outbound: 0,
+            peers: HashMap::new(),
+            static_peers: HashMap::new(),
+            known_scores: HashMap::new(),
+            bans: HashMap::new(),
+            blocked: HashSet::new(),
+            dump,
+            actions: VecDeque::new(),
+            chain_label: chain_id.to_string(),
+        }
+    }
+
+    /// Sets the static peers, which are always dialed and never banned or pruned
+    pub fn with_static_peers(mut self, static_peers: HashMap<PeerId, Multiaddr>) -> Self {
+        self.static_peers = static_peers;
+        self
+    }
+
+    /// Restores the peer scores and bans from a [Peerstore]
+    pub fn restore(&mut self, peerstore: &Peerstore) {
+        let now = Instant::now();
+
+        for (peer_id, score) in &peerstore.scores {
+            if let Ok(peer_id) = PeerId::from_str(peer_id) {
+                self.known_scores.insert(peer_id, *score);
+            }
+        }
+
+        for (peer_id, ban) in &peerstore.bans {
+            if let Ok(peer_id) = PeerId::from_str(peer_id) {
+                let ban = Ban {
+                    until: now + Duration::from_secs(ban.remaining_secs),
+                    count: ban.count,
+                };
+                self.bans.insert(peer_id, ban);
+            }
+        }
+
+        self.blocked = peerstore
+            .blocked
+            .iter()
+            .filter_map(|peer_id| PeerId::from_str(peer_id).ok())
+            .collect();
+    }
+
+    /// Stores the peer scores, active bans and blocked peers in a [Peerstore]
+    pub fn persist(&self, peerstore: &mut Peerstore) {
+        let now = Instant::now();
+
+        let connected = self
+            .peers
+            .iter()
+            .map(|(peer_id, peer)| (peer_id, peer.score));
+        peerstore.scores = self
+            .known_scores
+            .iter()
+            .map(|(peer_id, score)| (peer_id, *score))
+            .chain(connected)
+            .map(|(peer_id, score)| (peer_id.to_string(), score))
+            .collect();
+
+        peerstore.bans = self
+            .bans
+            .iter()
+            .filter(|(_, ban)| ban.until > now)
+            .map(|(peer_id, ban)| {
+                let ban = StoredBan {
+                    remaining_secs: (ban.until - now).as_secs(),
+                    count: ban.count,
+                };
+                (peer_id.to_string(), ban)
+            })
+            .collect();
+
+        peerstore.blocked = self.blocked.iter().map(PeerId::to_string).collect();
+        peerstore.blocked.sort();
+    }
+
+    /// Blocks a peer until it is unblocked, disconnecting it if connected
+    pub fn block(&mut self, peer_id: PeerId) {
+        if self.blocked.insert(peer_id) {
+            tracing::info!("blocked peer {}", peer_id);
+        }
+
+        if self.peers.contains_key(&peer_id) {
+            self.actions.push_back(ToSwarm::CloseConnection {
+                peer_id,
+                connection: CloseConnection::All,
+            });
+        }
+
+        self.publish();
+    }
+
+    /// Unblocks a peer. Returns false if the peer was not blocked.
+    pub fn unblock(&mut self, peer_id: &PeerId) -> bool {
+        let unblocked = self.blocked.remove(peer_id);
+        if unblocked {
+            tracing::info!("unblocked peer {}", peer_id);
+            self.publish();
+        }
+
+        unblocked
+    }
+
+    /// Returns the number of banned and blocked peers
+    pub fn banned_count(&self) -> usize {
+        let banned = self
+            .bans
+            .keys()
+            .filter(|peer_id| self.is_banned(peer_id) && !self.blocked.contains(peer_id))
+            .count();
+
+        banned + self.blocked.len()
+    }
+
+    /// Penalizes a peer for gossiping an invalid block
+    pub fn on_invalid_block(&mut self, peer_id: &PeerId) {
+        if let Some(peer) = self.peers.get_mut(peer_id) {
+            peer.invalid_blocks += 1;
+        }
+
+        self.adjust_score(peer_id, INVALID_BLOCK_PENALTY, "invalid block");
+    }
+
+    /// Scores a response to a payload request
+    pub fn on_response(&mut self, peer_id: &PeerId, latency: Duration, valid: bool) {
+        let slow = latency > SLOW_RESPONSE;
+
+        if let Some(peer) = self.peers.get_mut(peer_id) {
+            match (valid, slow) {
+                (false, _) => peer.failed_requests += 1,
+                (true, true) => {
+                    peer.responses += 1;
+                    peer.slow_responses += 1;
+                }
+                (true, false) => peer.responses += 1,
+            }
+        }
+
+        match (valid, slow) {
+            (false, _) => self.adjust_score(peer_id, INVALID_RESPONSE_PENALTY, "invalid response"),
+            (true, true) => self.adjust_score(peer_id, SLOW_RESPONSE_PENALTY, "slow response"),
+            (true, false) => self.adjust_score(peer_id, RESPONSE_REWARD, "response"),
+        }
+    }
+
+    /// Penalizes a peer for not responding to a payload request in time
+    pub fn on_timeout(&mut self, peer_id: &PeerId) {
+        if let Some(peer) = self.peers.get_mut(peer_id) {
+            peer.failed_requests += 1;
+        }
+
+        self.adjust_score(peer_id, TIMEOUT_PENALTY, "request timeout");
+    }
+
+    /// Decays the scores, bans peers whose gossipsub score is too low, and publishes the
+    /// current stats. The gossipsub score of a peer is looked up with `gossip_score`.
+    pub fn heartbeat(&mut self, gossip_score: impl Fn(&PeerId) -> Option<f64>) {
+        let mut misbehaving = Vec::new();
+
+        for (peer_id, peer) in self.peers.iter_mut() {
+            peer.score *= SCORE_DECAY;
+            peer.gossip_score = gossip_score(peer_id).unwrap_or_default();
+
+            let banned = self
+                .bans
+                .get(peer_id)
+                .is_some_and(|ban| ban.until > Instant::now());
+
+            if peer.gossip_score <= GOSSIP_BAN_THRESHOLD && !banned {
+                misbehaving.push(*peer_id);
+            }
+        }
+
+        for peer_id in misbehaving {
+            self.ban(peer_id, "gossip misbehavior");
+        }
+
+        let now = Instant::now();
+        self.bans
+            .retain(|_, ban| ban.until + ban_duration(ban.count) > now);
+
+        for (peer_id, address) in &self.static_peers {
+            if !self.peers.contains_key(peer_id) {
+                let opts = DialOpts::peer_id(*peer_id)
+                    .condition(PeerCondition::Disconnected)
+                    .addresses(vec![address.clone()])
+                    .build();
+
+                self.actions.push_back(ToSwarm::Dial { opts });
+            }
+        }
+
+        self.publish();
+    }
+
+    /// Returns true if the peer is currently banned
+    pub fn is_banned(&self, peer_id: &PeerId) -> bool {
+        self.bans
+            .get(peer_id)
+            .is_some_and(|ban| ban.until > Instant::now())
+    }
+
+    fn adjust_score(&mut self, peer_id: &PeerId, delta: f64, reason: &str) {
+        // banned peers are being disconnected already
+        if self.is_banned(peer_id) {
+            return;
+        }
+
+        let Some(peer) = self.peers.get_mut(peer_id) else {
+            return;
+        };
+
+        peer.score = (peer.score + delta).clamp(BAN_THRESHOLD, MAX_SCORE);
+        tracing::trace!("peer {} scored {} for {}", peer_id, delta, reason);
+
+        if peer.score <= BAN_THRESHOLD {
+            self.ban(*peer_id, reason);
+        }
+    }
+
+    /// Disconnects and bans a peer, unless it is a static peer
+    fn ban(&mut self, peer_id: PeerId, reason: &str) {
+        if self.static_peers.contains_key(&peer_id) {
+            tracing::debug!("not banning static peer {}: {}", peer_id, reason);
+            return;
+        }
+
+        let now = Instant::now();
+        let ban = self.bans.entry(peer_id).or_insert(Ban {
+            until: now,
+            count: 0,
+        });
+
+        let duration = ban_duration(ban.count);
+        ban.until = now + duration;
+        ban.count += 1;
+
+        tracing::warn!(
+            "banning peer {} for {} secs: {}",
+            peer_id,
+            duration.as_secs(),
+            reason
+        );
+        metrics::P2P_PEER_BANS
+            .with_label_values(&[self.chain_label.as_str()])
+            .inc();
+
+        self.actions.push_back(ToSwarm::CloseConnection {
+            peer_id,
+            connection: CloseConnection::All,
+        });
+    }
+
+    /// Denies a connection to a blocked or banned peer, or over the limit of its direction
+    fn check_connection(
+        &self,
+        peer_id: &PeerId,
+        direction: Direction,
+    ) -> Result<(), ConnectionDenied> {
+        if self.blocked.contains(peer_id) {
+            return Err(ConnectionDenied::new(PeerError::Blocked));
+        }
+
+        if self.static_peers.contains_key(peer_id) {
+            return Ok(());
+        }
+
+        if self.is_banned(peer_id) {
+            return Err(ConnectionDenied::new(PeerError::Banned));
+        }
+
+        match direction {
+            Direction::Inbound if self.inbound >= self.max_inbound => {
+                Err(ConnectionDenied::new(PeerError::TooManyInbound))
+            }
+            Direction::Outbound if self.outbound >= self.max_outbound => {
+                Err(ConnectionDenied::new(PeerError::TooManyOutbound))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Publishes the current stats to the shared [PeerDump]
+    fn publish(&self) {
+        let peers = self
+            .peers
+            .iter()
+            .map(|(peer_id, peer)| {
+                let stats = PeerStats {
+                    address: peer.address.to_string(),
+                    direction: peer.direction,
+                    connected_secs: peer.connected_at.elapsed().as_secs(),
+                    score: peer.score,
+                    gossip_score: peer.gossip_score,
+                    invalid_blocks: peer.invalid_blocks,
+                    responses: peer.responses,
+                    slow_responses: peer.slow_responses,
+                    failed_requests: peer.failed_requests,
+                    is_static: self.static_peers.contains_key(peer_id),
+                };
+
+                (peer_id.to_string(), stats)
+            })
+            .collect();
+
+        let banned_peers = self
+            .bans
+            .keys()
+            .filter(|peer_id| self.is_banned(peer_id))
+            .map(|peer_id| peer_id.to_string())
+            .collect();
+
+        let mut blocked_peers: Vec<_> = self.blocked.iter().map(PeerId::to_string).collect();
+        blocked_peers.sort();
+
+        metrics::P2P_PEERS
+            .with_label_values(&[self.chain_label.as_str()])
+            .set(self.peers.len() as i64);
+
+        let dump = PeerDump {
+            total_connected: self.peers.len(),
+            peers,
+            banned_peers,
+            blocked_peers,
+        };
+
+        if let Ok(mut shared) = self.dump.write() {
+            *shared = dump;
+        }
+    }
+}
+
+/// Returns the duration of a ban of a peer banned `count` times before
+fn ban_duration(count: u32) -> Duration {
+    BAN_DURATION * 2u32.pow(count.min(MAX_BAN_DOUBLINGS))
+}
+
+impl NetworkBehaviour for PeerManager {
+    type ConnectionHandler = dummy::ConnectionHandler;
+    type OutEvent = Infallible;
+
+    fn handle_pending_outbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        maybe_peer: Option<PeerId>,
+        _addresses: &[Multiaddr],
+        _effective_role: Endpoint,
+    ) -> Result<Vec<Multiaddr>, ConnectionDenied> {
+        if let Some(peer_id) = maybe_peer {
+            self.check_connection(&peer_id, Direction::Outbound)?;
+        } else if self.outbound >= self.max_outbound {
+            return Err(ConnectionDenied::new(PeerError::TooManyOutbound));
+        }
+
+        Ok(Vec::new())
+    }
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        peer: PeerId,
+        _local_addr: &Multiaddr,
+        _remote_addr: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.check_connection(&peer, Direction::Inbound)?;
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        peer: PeerId,
+        _addr: &Multiaddr,
+        _role_override: Endpoint,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.check_connection(&peer, Direction::Outbound)?;
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn on_swarm_event(&mut self, event: FromSwarm<Self::ConnectionHandler>) {
+        match event {
+            FromSwarm::ConnectionEstablished(event) => {
+                let (direction, address) = match event.endpoint {
+                    ConnectedPoint::Dialer { address, .. } => (Direction::Outbound, address),
+                    ConnectedPoint::Listener { send_back_addr, .. } => {
+                        (Direction::Inbound, send_back_addr)
+                    }
+                };
+
+                match direction {
+                    Direction::Inbound => self.inbound += 1,
+                    Direction::Outbound => self.outbound += 1,
+                }
+
+                let known_scores = &mut self.known_scores;
+                self.peers
+                    .entry(event.peer_id)
+                    .or_insert_with(|| PeerState {
+                        direction,
+                        address: address.clone(),
+                        connections: 0,
+                        connected_at: Instant::now(),
+                        score: known_scores.remove(&event.peer_id).unwrap_or_default(),
+                        gossip_score: 0.0,
+                        invalid_blocks: 0,
+                        responses: 0,
+                        slow_responses: 0,
+                        failed_requests: 0,
+                    })
+                    .connections += 1;
+
+                self.publish();
+            }
+            FromSwarm::ConnectionClosed(event) => {
+                match event.endpoint {
+                    ConnectedPoint::Dialer { .. } => {
+                        self.outbound = self.outbound.saturating_sub(1)
+                    }
+                    ConnectedPoint::Listener { .. } => {
+                        self.inbound = self.inbound.saturating_sub(1)
+                    }
+                }
+
+                if event.remaining_established == 0 {
+                    let peer = self.peers.remove(&event.peer_id);
+                    let score = peer.map(|peer| peer.score).unwrap_or_default();
+
+                    if score.abs() >= 1.0 && self.known_scores.len() < MAX_KNOWN_SCORES {
+                        self.known_scores.insert(event.peer_id, score);
+                    }
+                }
+
+                self.publish();
+            }
+            _ => {}
+        }
+    }
+
+    fn on_connection_handler_event(
+        &mut self,
+        _peer_id: PeerId,
+        _connection_id: ConnectionId,
+        event: THandlerOutEvent<Self>,
+    ) {
+        match event {}
+    }
+
+    fn poll(
+        &mut self,
+        _cx: &mut Context<'_>,
+        _params: &mut impl PollParameters,
+    ) -> Poll<ToSwarm<Infallible, THandlerInEvent<Self>>> {
+        match self.actions.pop_front() {
+            Some(action) => Poll::Ready(action),
+            None => Poll::Pending,
+        }
+    }
+}
+
+/// The reason a connection was denied
+#[derive(Debug)]
+enum PeerError {
+    /// The peer is blocked by the operator
+    Blocked,
+    /// The peer is banned
+    Banned,
+    /// The inbound connection limit is reached
+    TooManyInbound,
+    /// The outbound connection limit is reached
+    TooManyOutbound,
+}
+
+impl fmt::Display for PeerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Blocked => write!(f, "peer is blocked"),
+            Self::Banned => write!(f, "peer is banned"),
+            Self::TooManyInbound => write!(f, "too many inbound connections"),
+            Self::TooManyOutbound => write!(f, "too many outbound connections"),
+        }
+    }
+}
+
+impl std::error::Error for PeerError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connect(manager: &mut PeerManager, peer_id: PeerId) {
+        manager.peers.insert(
+            peer_id,
+            PeerState {
+                direction: Direction::Inbound,
+                address: Multiaddr::empty(),
+                connections: 1,
+                connected_at: Instant::now(),
+                score: 0.0,
+                gossip_score: 0.0,
+                invalid_blocks: 0,
+                responses: 0,
+                slow_responses: 0,
+                failed_requests: 0,
+            },
+        );
+        manager.inbound += 1;
+    }
+
+    #[test]
+    fn test_ban_and_limits() {
+        let dump = Arc::new(RwLock::new(PeerDump::default()));
+        let mut manager = PeerManager::new(2, 1, dump.clone(), 10);
+
+        let bad = PeerId::random();
+        let good = PeerId::random();
+        connect(&mut manager, bad);
+        connect(&mut manager, good);
+
+        assert!(manager
+            .check_connection(&PeerId::random(), Direction::Inbound)
+            .is_err());
+        assert!(manager
+            .check_connection(&PeerId::random(), Direction::Outbound)
+            .is_ok());
+
+        manager.on_response(&good, Duration::from_millis(100), true);
+        manager.on_response(&good, Duration::from_secs(5), true);
+
+        manager.on_invalid_block(&bad);
+        manager.on_response(&bad, Duration::from_millis(100), false);
+        assert!(!manager.is_banned(&bad));
+        manager.on_invalid_block(&bad);
+
+        assert!(manager.is_banned(&bad));
+        assert!(!manager.is_banned(&good));
+        assert!(manager.check_connection(&bad, Direction::Outbound).is_err());
+        assert_eq!(manager.bans[&bad].count, 1);
+
+        manager.heartbeat(|peer_id| (*peer_id == good).then_some(GOSSIP_BAN_THRESHOLD));
+        assert!(manager.is_banned(&good));
+
+        let dump = dump.read().unwrap();
+        assert_eq!(dump.total_connected, 2);
+        assert_eq!(dump.banned_peers.len(), 2);
+
+        let stats = &dump.peers[&bad.to_string()];
+        assert_eq!(stats.invalid_blocks, 2);
+        assert_eq!(stats.failed_requests, 1);
+        assert_eq!(dump.peers[&good.to_string()].slow_responses, 1);
+    }
+
+    #[test]
+    fn test_block_peers() {
+        let dump = Arc::new(RwLock::new(PeerDump::default()));
+        let static_peer = PeerId::random();
+        let mut manager = PeerManager::new(2, 2, dump.clone(), 10)
+            .with_static_peers(HashMap::from([(static_peer, Multiaddr::empty())]));
+
+        let peer = PeerId::random();
+        connect(&mut manager, peer);
+
+        manager.block(peer);
+        manager.block(static_peer);
+        assert!(matches!(
+            manager.actions.pop_front(),
+            Some(ToSwarm::CloseConnection { peer_id, .. }) if peer_id == peer
+        ));
+        assert!(manager.actions.is_empty());
+        assert!(manager.check_connection(&peer, Direction::Inbound).is_err());
+        assert!(manager
+            .check_connection(&static_peer, Direction::Outbound)
+            .is_err());
+        assert_eq!(manager.banned_count(), 2);
+        assert_eq!(dump.read().unwrap().blocked_peers.len(), 2);
+
+        // blocks survive restarts
+        let mut peerstore = Peerstore::default();
+        manager.persist(&mut peerstore);
+        let mut restored = PeerManager::new(2, 2, Default::default(), 10);
+        restored.restore(&peerstore);
+        assert!(restored
+            .check_connection(&peer, Direction::Inbound)
+            .is_err());
+
+        assert!(manager.unblock(&peer));
+        assert!(!manager.unblock(&peer));
+        assert!(manager.check_connection(&peer, Direction::Inbound).is_ok());
+        assert_eq!(
+            dump.read().unwrap().blocked_peers,
+            vec![static_peer.to_string()]
+        );
+    }
+}
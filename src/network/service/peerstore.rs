@@ -0,0 +1,123 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fs,
+    path::Path,
+};
+
+use discv5::enr::{CombinedKey, Enr};
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::store::write_atomic;
+
+/// Maximum number of discovered node records kept in the peerstore
+const MAX_ENRS: usize = 128;
+
+/// The peers known to the node, persisted in the data directory so a restarted node
+/// reconnects to them without waiting for discovery
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Peerstore {
+    /// The most recently discovered node records, oldest first
+    pub enrs: VecDeque<String>,
+    /// The scores of known peers by peer id
+    pub scores: BTreeMap<String, f64>,
+    /// The active bans by peer id
+    pub bans: BTreeMap<String, StoredBan>,
+    /// The peer ids blocked by the operator
+    #[serde(default)]
+    pub blocked: Vec<String>,
+}
+
+/// A ban of a peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredBan {
+    /// Seconds until the ban expires
+    pub remaining_secs: u64,
+    /// Number of times the peer has been banned
+    pub count: u32,
+}
+
+impl Peerstore {
+    /// Loads the peerstore at the given path. Returns an empty peerstore if the file does
+    /// not exist or cannot be parsed.
+    pub fn load(path: &Path) -> Self {
+        let Ok(data) = fs::read(path) else {
+            return Self::default();
+        };
+
+        serde_json::from_slice(&data).unwrap_or_else(|err| {
+            tracing::warn!("discarding unreadable peerstore {:?}: {}", path, err);
+            Self::default()
+        })
+    }
+
+    /// Writes the peerstore to the given path, replacing the file atomically
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write_atomic(path, &serde_json::to_vec(self)?)
+    }
+
+    /// Records a discovered node, dropping the oldest records over [MAX_ENRS]
+    pub fn add_enr(&mut self, enr: &Enr<CombinedKey>) {
+        let enr = enr.to_base64();
+
+        self.enrs.retain(|known| *known != enr);
+        self.enrs.push_back(enr);
+
+        while self.enrs.len() > MAX_ENRS {
+            self.enrs.pop_front();
+        }
+    }
+
+    /// Returns the stored node records that can still be parsed
+    pub fn parsed_enrs(&self) -> Vec<Enr<CombinedKey>> {
+        self.enrs
+            .iter()
+            .filter_map(|enr| enr.parse().ok())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use discv5::enr::EnrBuilder;
+
+    use super::*;
+
+    #[test]
+    fn test_save_and_load() -> Result<()> {
+        let path = std::env::temp_dir()
+            .join(format!("magi-peerstore-{}", std::process::id()))
+            .join("peerstore.json");
+
+        let key = CombinedKey::generate_secp256k1();
+        let enr = EnrBuilder::new("v4").build(&key)?;
+
+        let mut peerstore = Peerstore::default();
+        peerstore.add_enr(&enr);
+        peerstore.add_enr(&enr);
+        peerstore.scores.insert("peer".to_string(), -12.5);
+        peerstore.bans.insert(
+            "banned".to_string(),
+            StoredBan {
+                remaining_secs: 60,
+                count: 2,
+            },
+        );
+
+        peerstore.blocked.push("blocked".to_string());
+
+        assert_eq!(peerstore.enrs.len(), 1);
+
+        peerstore.save(&path)?;
+        let loaded = Peerstore::load(&path);
+        assert_eq!(loaded, peerstore);
+        assert_eq!(loaded.parsed_enrs(), vec![enr]);
+
+        fs::write(&path, "not json")?;
+        assert_eq!(Peerstore::load(&path), Peerstore::default());
+
+        fs::remove_dir_all(path.parent().unwrap())?;
+
+        Ok(())
+    }
+}
@@ -18,6 +18,8 @@ pub struct NetworkAddress {
 pub struct Peer {
     /// The peer's [Ipv4Addr] and port
     pub addr: NetworkAddress,
+    /// The peer's node record
+    pub enr: Enr<CombinedKey>,
 }
 
 impl TryFrom<&Enr<CombinedKey>> for NetworkAddress {
@@ -73,7 +75,10 @@ impl TryFrom<&Enr<CombinedKey>> for Peer {
     /// Converts an [Enr] to a Peer
     fn try_from(value: &Enr<CombinedKey>) -> Result<Self> {
         let addr = NetworkAddress::try_from(value)?;
-        Ok(Peer { addr })
+        Ok(Peer {
+            addr,
+            enr: value.clone(),
+        })
     }
 }
 
@@ -0,0 +1,429 @@
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    error::Error,
+    future::Future,
+    hash::Hash,
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use eyre::Result;
+use hyper::{
+    header::{
+        HeaderValue, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS,
+        ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_REQUEST_METHOD, AUTHORIZATION, ORIGIN,
+        RETRY_AFTER, UPGRADE, VARY, WWW_AUTHENTICATE,
+    },
+    Body, HeaderMap, Method, Request, Response, StatusCode,
+};
+use jsonrpsee::server::logger::{HttpRequest, Logger, MethodKind, Params, TransportProtocol};
+use tower::{Layer, Service};
+
+use crate::{config::Config, engine::JwtSecret};
+
+/// Number of keys tracked by the rate limiter before idle ones are forgotten
+const MAX_TRACKED_KEYS: usize = 10_000;
+
+thread_local! {
+    /// The remote address of the request being dispatched on this thread, set by
+    /// [RemoteAddrLogger]
+    static REMOTE_ADDR: Cell<Option<SocketAddr>> = const { Cell::new(None) };
+}
+
+/// Controls access to the RPC server: requests must carry the configured bearer token or
+/// a JWT signed with the configured secret, browsers may only call it from the allowed
+/// origins, and each IP address is limited to `rpc_rate_limit` requests per second.
+///
+/// The messages of a WebSocket connection are handled by the server without passing
+/// through the [AccessLayer], so WebSocket upgrades are refused while rate limiting.
+#[derive(Debug)]
+pub struct AccessControl {
+    /// The bearer token accepted in the `Authorization` header
+    token: Option<String>,
+    /// The secret of the JWTs accepted in the `Authorization` header
+    jwt_secret: Option<JwtSecret>,
+    /// The origins allowed to call the server from a browser, any if it contains `*`
+    cors_origins: Vec<String>,
+    /// The per IP rate limiter
+    rate_limiter: Option<RateLimiter<IpAddr>>,
+}
+
+impl AccessControl {
+    /// Creates a new [AccessControl] from the `rpc_*` settings of the [Config]
+    pub fn new(config: &Config) -> Result<Self> {
+        let jwt_secret = config
+            .rpc_jwt_secret
+            .as_ref()
+            .map(|secret| {
+                JwtSecret::from_hex(secret)
+                    .map_err(|err| eyre::eyre!("invalid rpc jwt secret: {}", err))
+            })
+            .transpose()?;
+
+        if config.rpc_rate_limit == Some(0) {
+            eyre::bail!("the rpc rate limit must be at least one request per second");
+        }
+
+        Ok(Self {
+            token: config.rpc_auth_token.clone(),
+            jwt_secret,
+            cors_origins: config.rpc_cors_origins.clone(),
+            rate_limiter: config.rpc_rate_limit.map(RateLimiter::new),
+        })
+    }
+
+    /// Returns whether the request is authorized, always true if no credentials are configured
+    fn authorized(&self, headers: &HeaderMap) -> bool {
+        if self.token.is_none() && self.jwt_secret.is_none() {
+            return true;
+        }
+
+        let Some(credentials) = headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+        else {
+            return false;
+        };
+
+        let token_valid = self
+            .token
+            .as_ref()
+            .is_some_and(|token| constant_time_eq(token.as_bytes(), credentials.as_bytes()));
+
+        let jwt_valid = self
+            .jwt_secret
+            .as_ref()
+            .is_some_and(|secret| secret.validate(credentials).is_ok());
+
+        token_valid || jwt_valid
+    }
+
+    /// Returns whether the origin of a browser request is allowed. Requests without an
+    /// origin are not sent by browsers, and any origin is allowed if none are configured.
+    fn origin_allowed(&self, origin: Option<&HeaderValue>) -> bool {
+        let Some(origin) = origin else {
+            return true;
+        };
+
+        self.cors_origins.is_empty()
+            || self
+                .cors_origins
+                .iter()
+                .any(|allowed| allowed == "*" || origin.as_bytes() == allowed.as_bytes())
+    }
+
+    /// Adds the CORS headers allowing the origin to read the response
+    fn add_cors_headers(&self, headers: &mut HeaderMap, origin: Option<HeaderValue>) {
+        let Some(origin) = origin else {
+            return;
+        };
+
+        if self.cors_origins.is_empty() {
+            return;
+        }
+
+        headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+        headers.insert(VARY, HeaderValue::from_static("Origin"));
+    }
+
+    /// Responds to a CORS preflight request
+    fn preflight(&self, origin: Option<HeaderValue>) -> Result<Response<Body>, hyper::http::Error> {
+        let allowed = !self.cors_origins.is_empty() && self.origin_allowed(origin.as_ref());
+        if !allowed {
+            return Response::builder()
+                .status(StatusCode::FORBIDDEN)
+                .body(Body::empty());
+        }
+
+        let mut response = Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .header(ACCESS_CONTROL_ALLOW_METHODS, "GET, POST, OPTIONS")
+            .header(ACCESS_CONTROL_ALLOW_HEADERS, "Authorization, Content-Type")
+            .body(Body::empty())?;
+
+        self.add_cors_headers(response.headers_mut(), origin);
+        Ok(response)
+    }
+}
+
+/// Limits the requests of each key, such as an IP address, with a token bucket holding one
+/// second of requests
+#[derive(Debug)]
+pub(super) struct RateLimiter<K> {
+    /// Requests per second allowed for each key
+    rate: f64,
+    /// The remaining requests of each key and when they were last updated
+    buckets: Mutex<HashMap<K, (f64, Instant)>>,
+}
+
+impl<K: Hash + Eq> RateLimiter<K> {
+    /// Creates a new [RateLimiter] allowing `rate` requests per second for each key
+    pub(super) fn new(rate: u32) -> Self {
+        Self {
+            rate: rate as f64,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes a request from the bucket of the key, returning false if it is empty
+    pub(super) fn try_acquire(&self, key: K, now: Instant) -> bool {
+        let mut buckets = self.buckets.lock().unwrap();
+
+        // a bucket untouched for a second is full again, so it can be forgotten
+        if buckets.len() >= MAX_TRACKED_KEYS {
+            buckets.retain(|_, (_, updated)| {
+                now.saturating_duration_since(*updated) < Duration::from_secs(1)
+            });
+        }
+
+        let (tokens, updated) = buckets.entry(key).or_insert((self.rate, now));
+        let refill = now.saturating_duration_since(*updated).as_secs_f64() * self.rate;
+        *tokens = (*tokens + refill).min(self.rate);
+        *updated = now;
+
+        if *tokens >= 1.0 {
+            *tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// A [Logger] recording the remote address of each connection, which jsonrpsee only
+/// exposes to loggers, for the [AccessLayer] to rate limit by IP address.
+///
+/// The server reports the address while the middleware calls into it, on the same thread
+/// and before the call returns, so the layer reads it from a thread local right after.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RemoteAddrLogger;
+
+impl Logger for RemoteAddrLogger {
+    type Instant = ();
+
+    fn on_connect(&self, remote_addr: SocketAddr, _: &HttpRequest, _: TransportProtocol) {
+        REMOTE_ADDR.with(|addr| addr.set(Some(remote_addr)));
+    }
+
+    fn on_request(&self, _: TransportProtocol) {}
+
+    fn on_call(&self, _: &str, _: Params, _: MethodKind, _: TransportProtocol) {}
+
+    fn on_result(&self, _: &str, _: bool, _: (), _: TransportProtocol) {}
+
+    fn on_response(&self, _: &str, _: (), _: TransportProtocol) {}
+
+    fn on_disconnect(&self, _: SocketAddr, _: TransportProtocol) {}
+}
+
+/// A layer enforcing an [AccessControl] in front of the RPC server. Requires the server
+/// to use the [RemoteAddrLogger] for rate limiting.
+#[derive(Debug, Clone)]
+pub struct AccessLayer {
+    /// The access rules of the server
+    access: Arc<AccessControl>,
+}
+
+impl AccessLayer {
+    /// Creates a new [AccessLayer]
+    pub fn new(access: AccessControl) -> Self {
+        Self {
+            access: Arc::new(access),
+        }
+    }
+}
+
+impl<S> Layer<S> for AccessLayer {
+    type Service = AccessService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AccessService {
+            inner,
+            access: self.access.clone(),
+        }
+    }
+}
+
+/// The service created by an [AccessLayer]
+#[derive(Debug, Clone)]
+pub struct AccessService<S> {
+    /// The RPC service
+    inner: S,
+    /// The access rules of the server
+    access: Arc<AccessControl>,
+}
+
+impl<S> Service<Request<Body>> for AccessService<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>>,
+    S::Error: Into<Box<dyn Error + Send + Sync>> + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = Box<dyn Error + Send + Sync + 'static>;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let access = self.access.clone();
+        let origin = req.headers().get(ORIGIN).cloned();
+
+        if req.method() == Method::OPTIONS
+            && req.headers().contains_key(ACCESS_CONTROL_REQUEST_METHOD)
+        {
+            return Box::pin(async move { Ok(access.preflight(origin)?) });
+        }
+
+        if !access.origin_allowed(origin.as_ref()) {
+            return Box::pin(async move { Ok(error_response(StatusCode::FORBIDDEN)?) });
+        }
+
+        if access.rate_limiter.is_some() && is_websocket_upgrade(req.headers()) {
+            return Box::pin(async move { Ok(error_response(StatusCode::FORBIDDEN)?) });
+        }
+
+        let authorized = access.authorized(req.headers());
+
+        // the server does not handle the request until the returned future is polled, so
+        // it can still be dropped if the request is rejected
+        REMOTE_ADDR.with(|addr| addr.set(None));
+        let fut = self.inner.call(req);
+        let remote_addr = REMOTE_ADDR.with(|addr| addr.take());
+
+        let limited = match (&access.rate_limiter, remote_addr) {
+            (Some(limiter), Some(addr)) => !limiter.try_acquire(addr.ip(), Instant::now()),
+            _ => false,
+        };
+
+        Box::pin(async move {
+            if limited {
+                let mut response = error_response(StatusCode::TOO_MANY_REQUESTS)?;
+                response
+                    .headers_mut()
+                    .insert(RETRY_AFTER, HeaderValue::from_static("1"));
+                return Ok(response);
+            }
+
+            if !authorized {
+                let mut response = error_response(StatusCode::UNAUTHORIZED)?;
+                response
+                    .headers_mut()
+                    .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+                return Ok(response);
+            }
+
+            let mut response = fut.await.map_err(Into::into)?;
+            access.add_cors_headers(response.headers_mut(), origin);
+            Ok(response)
+        })
+    }
+}
+
+/// Returns an empty response with the given status
+fn error_response(status: StatusCode) -> Result<Response<Body>, hyper::http::Error> {
+    Response::builder().status(status).body(Body::empty())
+}
+
+/// Returns whether the request asks to upgrade the connection to a WebSocket
+fn is_websocket_upgrade(headers: &HeaderMap) -> bool {
+    headers
+        .get(UPGRADE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.eq_ignore_ascii_case("websocket"))
+}
+
+/// Compares two byte strings in constant time, so a token cannot be guessed from timings
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "f79ae8046bc11c9927afe911db7143c51a806c4a537cc08e0d37140b0192f430";
+
+    fn headers(authorization: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, authorization.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_authorization() -> Result<()> {
+        let open = AccessControl::new(&Config::default())?;
+        assert!(open.authorized(&HeaderMap::new()));
+
+        let config = Config {
+            rpc_auth_token: Some("token".to_string()),
+            rpc_jwt_secret: Some(SECRET.to_string()),
+            ..Default::default()
+        };
+        let access = AccessControl::new(&config)?;
+
+        assert!(!access.authorized(&HeaderMap::new()));
+        assert!(access.authorized(&headers("Bearer token")));
+        assert!(!access.authorized(&headers("Bearer tokens")));
+        assert!(!access.authorized(&headers("token")));
+
+        let secret = JwtSecret::from_hex(SECRET)?;
+        let jwt = secret.encode(&secret.generate_claims(None)).unwrap();
+        assert!(access.authorized(&headers(&format!("Bearer {}", jwt))));
+
+        let other = JwtSecret::random();
+        let jwt = other.encode(&other.generate_claims(None)).unwrap();
+        assert!(!access.authorized(&headers(&format!("Bearer {}", jwt))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cors_origins() -> Result<()> {
+        let origin = HeaderValue::from_static("https://app.example");
+
+        let open = AccessControl::new(&Config::default())?;
+        assert!(open.origin_allowed(Some(&origin)));
+        assert_eq!(open.preflight(Some(origin.clone()))?.status(), 403);
+
+        let config = Config {
+            rpc_cors_origins: vec!["https://app.example".to_string()],
+            ..Default::default()
+        };
+        let access = AccessControl::new(&config)?;
+
+        assert!(access.origin_allowed(None));
+        assert!(access.origin_allowed(Some(&origin)));
+        assert!(!access.origin_allowed(Some(&HeaderValue::from_static("https://evil.example"))));
+
+        let response = access.preflight(Some(origin.clone()))?;
+        assert_eq!(response.status(), 204);
+        assert_eq!(response.headers()[ACCESS_CONTROL_ALLOW_ORIGIN], origin);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let limiter = RateLimiter::new(2);
+        let start = Instant::now();
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let other: IpAddr = "10.0.0.2".parse().unwrap();
+
+        assert!(limiter.try_acquire(ip, start));
+        assert!(limiter.try_acquire(ip, start));
+        assert!(!limiter.try_acquire(ip, start));
+        assert!(limiter.try_acquire(other, start));
+
+        // the bucket refills at the rate limit
+        assert!(limiter.try_acquire(ip, start + Duration::from_millis(500)));
+        assert!(!limiter.try_acquire(ip, start + Duration::from_millis(500)));
+    }
+}
@@ -0,0 +1,156 @@
+use std::time::{Duration, Instant};
+
+use ethers::{
+    providers::{Http, Provider, ProviderError, RpcError},
+    types::{Bytes, H256},
+    utils::keccak256,
+};
+use eyre::Result;
+use jsonrpsee::{
+    core::Error,
+    types::{error::CallError, ErrorObject},
+};
+
+use crate::{common::http, config::Config};
+
+use super::{access::RateLimiter, proxy::upstream_error};
+
+/// Number of attempts to forward a transaction before the error is returned
+const MAX_ATTEMPTS: u32 = 3;
+
+/// Delay before the first retry, doubled for every further retry
+const RETRY_DELAY: Duration = Duration::from_millis(200);
+
+/// Timeout of a request to the sequencer RPC
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// JSON-RPC error code of the transactions rejected by the local rate limit
+const LIMIT_EXCEEDED: i32 = -32005;
+
+/// Relays the raw transactions of `eth_sendRawTransaction` to the sequencer RPC.
+///
+/// Requests failing in transport are retried, while the errors returned by the sequencer,
+/// such as a nonce too low, are returned to the caller unchanged.
+#[derive(Debug)]
+pub struct TxForwarder {
+    /// Provider for the sequencer RPC
+    provider: Provider<Http>,
+    /// Limits the transactions forwarded per second, if configured
+    rate_limiter: Option<RateLimiter<()>>,
+}
+
+impl TxForwarder {
+    /// Creates a forwarder to the configured sequencer RPC, if any
+    pub fn from_config(config: &Config) -> Result<Option<Self>> {
+        let Some(url) = &config.rpc_sequencer_url else {
+            return Ok(None);
+        };
+
+        if config.rpc_sequencer_rate_limit == Some(0) {
+            eyre::bail!("the sequencer rate limit must be at least one transaction per second");
+        }
+
+        Ok(Some(Self {
+            provider: http::provider(url, REQUEST_TIMEOUT)?,
+            rate_limiter: config.rpc_sequencer_rate_limit.map(RateLimiter::new),
+        }))
+    }
+
+    /// Forwards a raw transaction to the sequencer, returning its hash
+    pub async fn send_raw_transaction(&self, tx: Bytes) -> Result<H256, Error> {
+        if let Some(limiter) = &self.rate_limiter {
+            if !limiter.try_acquire((), Instant::now()) {
+                return Err(Error::Call(CallError::Custom(ErrorObject::owned(
+                    LIMIT_EXCEEDED,
+                    "transaction rate limit exceeded",
+                    None::<()>,
+                ))));
+            }
+        }
+
+        let hash = H256(keccak256(&tx));
+        let mut delay = RETRY_DELAY;
+        let mut attempt = 0;
+
+        loop {
+            attempt += 1;
+            let err = match self
+                .provider
+                .request::<_, H256>("eth_sendRawTransaction", [&tx])
+                .await
+            {
+                Ok(hash) => return Ok(hash),
+                Err(err) => err,
+            };
+
+            // a failed attempt may have reached the sequencer before its response was lost
+            if attempt > 1 && is_already_known(&err) {
+                return Ok(hash);
+            }
+
+            if !is_retryable(&err) || attempt >= MAX_ATTEMPTS {
+                return Err(upstream_error(err));
+            }
+
+            tracing::debug!("retrying transaction {:?} after {}", hash, err);
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+        }
+    }
+}
+
+/// Returns true unless the sequencer responded with an error
+fn is_retryable(err: &ProviderError) -> bool {
+    err.as_error_response().is_none()
+}
+
+/// Returns true if the sequencer already has the transaction in its pool
+fn is_already_known(err: &ProviderError) -> bool {
+    err.as_error_response()
+        .is_some_and(|err| err.message.contains("already known"))
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::providers::{HttpClientError, JsonRpcError};
+
+    use super::*;
+
+    #[test]
+    fn test_retryable_errors() {
+        let response = |message: &str| {
+            ProviderError::JsonRpcClientError(Box::new(HttpClientError::JsonRpcError(
+                JsonRpcError {
+                    code: -32000,
+                    message: message.to_string(),
+                    data: None,
+                },
+            )))
+        };
+
+        assert!(is_retryable(&ProviderError::CustomError(
+            "connection refused".to_string()
+        )));
+        assert!(!is_retryable(&response("nonce too low")));
+        assert!(!is_already_known(&response("nonce too low")));
+        assert!(is_already_known(&response("already known")));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit() -> Result<()> {
+        let config = Config {
+            rpc_sequencer_url: Some("http://127.0.0.1:1".to_string()),
+            rpc_sequencer_rate_limit: Some(1),
+            ..Default::default()
+        };
+        let forwarder = TxForwarder::from_config(&config)?.unwrap();
+
+        // the sequencer is unreachable, so the first transaction fails after its retries
+        assert!(forwarder.send_raw_transaction(Bytes::new()).await.is_err());
+        let err = forwarder.send_raw_transaction(Bytes::new()).await;
+        assert!(err.unwrap_err().to_string().contains("rate limit"));
+
+        assert!(TxForwarder::from_config(&Config::default())?.is_none());
+        Ok(())
+    }
+}
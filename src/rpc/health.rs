@@ -0,0 +1,409 @@
+use std::{
+    error::Error,
+    future::Future,
+    pin::Pin,
+    str::FromStr,
+    sync::{Arc, Mutex, RwLock},
+    task::{Context, Poll},
+    time::{Duration, Instant, SystemTime},
+};
+
+use ethers::providers::{Http, Middleware, Provider};
+use hyper::{header::CONTENT_TYPE, Body, Method, Request, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use tower::{Layer, Service};
+
+use crate::{
+    config::Config,
+    derive::DerivationErrors,
+    driver::DerivationControl,
+    engine::EngineFailover,
+    l1::{file_source, FileClient, RateLimitedClient, WatcherRestarts},
+};
+
+use super::SyncStatus;
+
+/// Time after which an unresponsive L1 RPC fails its check
+const L1_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The health of one component of the node
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ComponentHealth {
+    /// Whether the component is healthy
+    pub healthy: bool,
+    /// What was observed
+    pub detail: String,
+}
+
+impl ComponentHealth {
+    fn new(healthy: bool, detail: String) -> Self {
+        Self { healthy, detail }
+    }
+}
+
+/// The response of the `/healthz` and `/readyz` endpoints
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthReport {
+    /// Whether the checked components are all healthy
+    pub healthy: bool,
+    /// Whether the L1 RPC responds
+    pub l1: ComponentHealth,
+    /// Whether the active engine endpoint passes its health checks
+    pub engine: ComponentHealth,
+    /// Whether derivation ingested an L1 block recently
+    pub derivation: ComponentHealth,
+    /// Whether the L1 chain watcher ran without restarts recently
+    pub l1_watcher: ComponentHealth,
+    /// Whether the last iteration of the derivation loop succeeded
+    pub derivation_errors: ComponentHealth,
+    /// Whether the unsafe head is close to the current time
+    pub head_lag: ComponentHealth,
+}
+
+/// Checks the components of the node for the health endpoints.
+///
+/// The node is healthy as long as derivation keeps ingesting L1 blocks, which only
+/// fails when the node is stuck and should be restarted. It is ready when the L1 RPC and
+/// the engine are reachable, derivation is progressing, the L1 chain watcher was not
+/// restarted within the last `readiness_max_stall` seconds, the last iteration of the
+/// derivation loop succeeded and the unsafe head trails the current time by at most
+/// `readiness_max_head_lag` seconds.
+#[derive(Debug)]
+pub struct HealthChecker {
+    /// Provider for the L1 RPC
+    l1_provider: Option<Provider<RateLimitedClient>>,
+    /// The engine endpoints, unless the engine is embedded
+    engine: Option<Arc<EngineFailover>>,
+    /// The sync status, kept up to date by the driver
+    sync_status: Arc<RwLock<SyncStatus>>,
+    /// Maximum seconds the unsafe head may trail the current time
+    max_head_lag: u64,
+    /// Maximum seconds without derivation ingesting an L1 block
+    max_stall: u64,
+    /// The latest ingested L1 block and when it was first seen
+    last_progress: Mutex<(u64, Instant)>,
+    /// The derivation mode control, as derivation is not expected to progress in light-follow
+    derivation: Option<Arc<DerivationControl>>,
+}
+
+impl HealthChecker {
+    /// Creates a new [HealthChecker]
+    pub fn new(
+        config: &Config,
+        engine: Option<Arc<EngineFailover>>,
+        sync_status: Arc<RwLock<SyncStatus>>,
+    ) -> Self {
+        Self {
+            l1_provider: l1_client(&config.l1_rpc_url).map(Provider::new),
+            engine,
+            sync_status,
+            max_head_lag: config.readiness_max_head_lag,
+            max_stall: config.readiness_max_stall,
+            last_progress: Mutex::new((0, Instant::now())),
+            derivation: None,
+        }
+    }
+
+    /// Treats derivation as progressing while it is paused in light-follow mode
+    pub fn with_derivation_control(mut self, derivation: Arc<DerivationControl>) -> Self {
+        self.derivation = Some(derivation);
+        self
+    }
+
+    /// Returns the liveness of the node, which only depends on derivation progressing
+    pub async fn health(&self) -> HealthReport {
+        let mut report = self.check().await;
+        report.healthy = report.derivation.healthy;
+        report
+    }
+
+    /// Returns the readiness of the node, which depends on all components
+    pub async fn readiness(&self) -> HealthReport {
+        self.check().await
+    }
+
+    /// Checks every component
+    async fn check(&self) -> HealthReport {
+        let status = self
+            .sync_status
+            .read()
+            .map(|s| s.clone())
+            .unwrap_or_default();
+
+        let l1 = self.check_l1().await;
+        let engine = self.check_engine();
+        let derivation = self.check_derivation(status.current_l1.number);
+        let l1_watcher = self.check_l1_watcher(&status.l1_watcher_restarts, now());
+        let derivation_errors = check_derivation_errors(&status.derivation_errors, now());
+        let head_lag = self.check_head_lag(status.unsafe_l2.timestamp, now());
+
+        HealthReport {
+            healthy: l1.healthy
+                && engine.healthy
+                && derivation.healthy
+                && l1_watcher.healthy
+                && derivation_errors.healthy
+                && head_lag.healthy,
+            l1,
+            engine,
+            derivation,
+            l1_watcher,
+            derivation_errors,
+            head_lag,
+        }
+    }
+
+    async fn check_l1(&self) -> ComponentHealth {
+        let Some(provider) = &self.l1_provider else {
+            return ComponentHealth::new(false, "invalid L1 RPC url".to_string());
+        };
+
+        match tokio::time::timeout(L1_CHECK_TIMEOUT, provider.get_block_number()).await {
+            Ok(Ok(number)) => ComponentHealth::new(true, format!("latest block {}", number)),
+            Ok(Err(err)) => ComponentHealth::new(false, err.to_string()),
+            Err(_) => ComponentHealth::new(false, "no response".to_string()),
+        }
+    }
+
+    fn check_engine(&self) -> ComponentHealth {
+        let Some(engine) = &self.engine else {
+            return ComponentHealth::new(true, "embedded engine".to_string());
+        };
+
+        match engine.status().into_iter().find(|status| status.active) {
+            Some(status) if status.healthy => ComponentHealth::new(true, status.url),
+            Some(status) => ComponentHealth::new(false, format!("{} is unhealthy", status.url)),
+            None => ComponentHealth::new(false, "no active engine".to_string()),
+        }
+    }
+
+    fn check_derivation(&self, current_l1: u64) -> ComponentHealth {
+        let mut last_progress = self.last_progress.lock().unwrap();
+        if self
+            .derivation
+            .as_ref()
+            .is_some_and(|d| d.is_light_follow())
+        {
+            *last_progress = (current_l1, Instant::now());
+            return ComponentHealth::new(true, "paused in light-follow mode".to_string());
+        }
+
+        if current_l1 != last_progress.0 {
+            *last_progress = (current_l1, Instant::now());
+        }
+
+        let stalled = last_progress.1.elapsed().as_secs();
+        let detail = format!("L1 block {} ingested {}s ago", current_l1, stalled);
+
+        ComponentHealth::new(stalled <= self.max_stall, detail)
+    }
+
+    fn check_l1_watcher(&self, restarts: &WatcherRestarts, now: u64) -> ComponentHealth {
+        let (Some(reason), Some(last_restart)) = (restarts.last_reason, restarts.last_restart)
+        else {
+            return ComponentHealth::new(true, "never restarted".to_string());
+        };
+
+        let since = now.saturating_sub(last_restart);
+        let detail = format!(
+            "restarted {} times, last {}s ago after it {}",
+            restarts.count,
+            since,
+            reason.label()
+        );
+
+        ComponentHealth::new(since > self.max_stall, detail)
+    }
+
+    fn check_head_lag(&self, head_timestamp: u64, now: u64) -> ComponentHealth {
+        let lag = now.saturating_sub(head_timestamp);
+        ComponentHealth::new(
+            lag <= self.max_head_lag,
+            format!("unsafe head is {}s behind", lag),
+        )
+    }
+}
+
+/// Returns an unlimited client for the L1 RPC url, which may be a `file://` url of
+/// exported L1 data
+fn l1_client(url: &str) -> Option<RateLimitedClient> {
+    match file_source::dir_from_url(url) {
+        Some(dir) => Some(RateLimitedClient::from_files(FileClient::new(dir))),
+        None => Some(RateLimitedClient::new(Http::from_str(url).ok()?, None)),
+    }
+}
+
+/// A layer serving the `/healthz` and `/readyz` endpoints of a [HealthChecker] in front
+/// of the RPC server. They respond with a [HealthReport], with status 200 if healthy and
+/// 503 otherwise.
+#[derive(Debug, Clone)]
+pub struct HealthLayer {
+    /// The checker of the node
+    checker: Arc<HealthChecker>,
+}
+
+impl HealthLayer {
+    /// Creates a new [HealthLayer]
+    pub fn new(checker: HealthChecker) -> Self {
+        Self {
+            checker: Arc::new(checker),
+        }
+    }
+}
+
+impl<S> Layer<S> for HealthLayer {
+    type Service = HealthService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        HealthService {
+            inner,
+            checker: self.checker.clone(),
+        }
+    }
+}
+
+/// The service created by a [HealthLayer]
+#[derive(Debug, Clone)]
+pub struct HealthService<S> {
+    /// The RPC service
+    inner: S,
+    /// The checker of the node
+    checker: Arc<HealthChecker>,
+}
+
+impl<S> Service<Request<Body>> for HealthService<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>>,
+    S::Error: Into<Box<dyn Error + Send + Sync>> + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = Box<dyn Error + Send + Sync + 'static>;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let readiness = match (req.method(), req.uri().path()) {
+            (&Method::GET, "/healthz") => false,
+            (&Method::GET, "/readyz") => true,
+            _ => {
+                let fut = self.inner.call(req);
+                return Box::pin(async move { fut.await.map_err(Into::into) });
+            }
+        };
+
+        let checker = self.checker.clone();
+        Box::pin(async move {
+            let report = if readiness {
+                checker.readiness().await
+            } else {
+                checker.health().await
+            };
+
+            let status = if report.healthy {
+                StatusCode::OK
+            } else {
+                StatusCode::SERVICE_UNAVAILABLE
+            };
+
+            let response = Response::builder()
+                .status(status)
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from(serde_json::to_vec(&report)?))?;
+
+            Ok(response)
+        })
+    }
+}
+
+/// Checks that the last iteration of the derivation loop succeeded, reporting the code of
+/// the last error
+fn check_derivation_errors(errors: &DerivationErrors, now: u64) -> ComponentHealth {
+    let (Some(code), Some(at)) = (&errors.last_code, errors.last_error_at) else {
+        return ComponentHealth::new(true, "no errors".to_string());
+    };
+
+    let detail = format!(
+        "{} errors, {} in a row, last {}s ago: {} error: {}",
+        errors.count,
+        errors.consecutive,
+        now.saturating_sub(at),
+        code,
+        errors.last_error.as_deref().unwrap_or_default()
+    );
+
+    ComponentHealth::new(errors.consecutive == 0, detail)
+}
+
+/// Returns the current unix timestamp
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_readiness_thresholds() {
+        let config = Config {
+            l1_rpc_url: "http://127.0.0.1:1".to_string(),
+            readiness_max_head_lag: 60,
+            readiness_max_stall: 0,
+            ..Default::default()
+        };
+        let checker = HealthChecker::new(&config, None, Default::default());
+
+        assert!(checker.check_head_lag(1000, 1060).healthy);
+        assert!(!checker.check_head_lag(1000, 1061).healthy);
+
+        // derivation stalls once no new L1 block is ingested within the threshold
+        assert!(checker.check_derivation(5).healthy);
+        std::thread::sleep(Duration::from_millis(1100));
+        assert!(!checker.check_derivation(5).healthy);
+        assert!(checker.check_derivation(6).healthy);
+
+        // derivation is expected to stall in light-follow mode
+        let derivation = Arc::new(DerivationControl::default());
+        let checker = checker.with_derivation_control(derivation.clone());
+        std::thread::sleep(Duration::from_millis(1100));
+        assert!(!checker.check_derivation(6).healthy);
+        derivation
+            .set_mode(crate::driver::DerivationMode::LightFollow)
+            .unwrap();
+        assert!(checker.check_derivation(6).healthy);
+
+        assert!(checker.check_engine().healthy);
+
+        // the node is not ready right after the L1 chain watcher restarted
+        let mut restarts = WatcherRestarts::default();
+        assert!(checker.check_l1_watcher(&restarts, 1000).healthy);
+        restarts.count = 1;
+        restarts.last_reason = Some(crate::l1::RestartReason::Stalled);
+        restarts.last_restart = Some(1000);
+        assert!(!checker.check_l1_watcher(&restarts, 1000).healthy);
+        assert!(checker.check_l1_watcher(&restarts, 1001).healthy);
+
+        // nor while derivation fails
+        let mut errors = DerivationErrors::default();
+        assert!(check_derivation_errors(&errors, 1000).healthy);
+        errors.record(
+            &crate::derive::DerivationError::temporary("engine syncing"),
+            990,
+        );
+        let health = check_derivation_errors(&errors, 1000);
+        assert!(!health.healthy);
+        assert!(health
+            .detail
+            .contains("10s ago: temporary error: engine syncing"));
+        errors.clear();
+        assert!(check_derivation_errors(&errors, 1000).healthy);
+    }
+}
@@ -1,26 +1,63 @@
-use std::{fmt::Display, net::SocketAddr, sync::Arc};
+use std::{
+    fmt::Display,
+    net::SocketAddr,
+    sync::{Arc, RwLock},
+};
 
 use crate::{
+    common::{BlockInfo, HeadInfo},
     config::{Config, ExternalChainConfig},
+    derive::{AttributeOverrides, DerivationErrors, ForcedInclusionReport, SequencerOverrides},
+    driver::{
+        AttributeMismatches, AttributesDiff, DerivationControl, DerivationMode, SequencerControl,
+        SequencerStatus, StopTarget,
+    },
+    engine::{EngineEndpointStatus, EngineFailover},
+    l1::{
+        l1_info::L1BlockInfo,
+        protocol_versions::{ProtocolVersion, ProtocolVersionSupport},
+        ProtocolVersionSignal, WatcherRestarts,
+    },
+    network::service::{PeerCounts, PeerDump, PeerHandles},
+    signer::SignerInfo,
     version::Version,
 };
 
 use eyre::Result;
 
+use self::{
+    access::{AccessControl, AccessLayer, RemoteAddrLogger},
+    health::{HealthChecker, HealthLayer},
+    outputs::OutputCache,
+};
+
 use ethers::{
     providers::{Middleware, Provider},
-    types::{Block, BlockId, H256},
-    utils::keccak256,
+    types::H256,
 };
 
 use jsonrpsee::{
     core::{async_trait, Error},
     proc_macros::rpc,
     server::ServerBuilder,
+    RpcModule,
 };
 
 use serde::{Deserialize, Serialize};
 
+use tokio::sync::{mpsc, oneshot, watch};
+
+/// Authentication, CORS and rate limiting of the RPC server
+pub mod access;
+/// Forwarding of raw transactions to the sequencer
+pub mod forward;
+/// The `/healthz` and `/readyz` endpoints
+pub mod health;
+/// The cache of the output roots served by `optimism_outputAtBlock`
+pub mod outputs;
+/// The `eth` namespace forwarded to the execution client
+pub mod proxy;
+
 /// This trait defines a set of RPC methods that can be
 /// queried by clients under the `optimism` namespace
 #[rpc(server, namespace = "optimism")]
@@ -30,13 +67,128 @@ pub trait Rpc {
     #[method(name = "outputAtBlock")]
     async fn output_at_block(&self, block_number: u64) -> Result<OutputRootResponse, Error>;
 
-    /// Returns the rollup configuration options.
+    /// Returns the effective rollup configuration, in the schema of op-node.
     #[method(name = "rollupConfig")]
     async fn rollup_config(&self) -> Result<ExternalChainConfig, Error>;
 
     /// Returns details about the Magi version of the node.
     #[method(name = "version")]
     async fn version(&self) -> Result<String, Error>;
+
+    /// Returns the current sync status of the node.
+    #[method(name = "syncStatus")]
+    async fn sync_status(&self) -> Result<SyncStatus, Error>;
+}
+
+/// Administrative RPC methods under the `admin` namespace
+#[rpc(server, namespace = "admin")]
+pub trait AdminRpc {
+    /// Returns the status of the primary and standby engine endpoints.
+    #[method(name = "engineStatus")]
+    async fn engine_status(&self) -> Result<Vec<EngineEndpointStatus>, Error>;
+
+    /// Forces requests to be sent to the engine endpoint at the given index,
+    /// where 0 is the primary. Useful to drain an execution client before upgrading it.
+    #[method(name = "switchEngine")]
+    async fn switch_engine(&self, index: usize) -> Result<(), Error>;
+
+    /// Returns the address and source of the sequencer key, if one is configured. The key
+    /// itself is never returned.
+    #[method(name = "signer")]
+    async fn signer(&self) -> Result<Option<SignerInfo>, Error>;
+
+    /// Returns the blocks derived deposit-only since the node started, because no valid
+    /// batch was submitted within the sequencing window.
+    #[method(name = "forcedInclusionReport")]
+    async fn forced_inclusion_report(&self) -> Result<ForcedInclusionReport, Error>;
+
+    /// Forcefully rewinds the node and the engine to the given L2 block, which becomes the
+    /// unsafe, safe and finalized head, and restarts derivation from its L1 origin. The
+    /// block must be the first block of its epoch. Returns the new head.
+    #[method(name = "resetDerivation")]
+    async fn reset_derivation(&self, block_number: u64) -> Result<BlockInfo, Error>;
+
+    /// Returns whether the sequencer is draining, its scheduled stop and its unsafe head.
+    #[method(name = "sequencerStatus")]
+    async fn sequencer_status(&self) -> Result<SequencerStatus, Error>;
+
+    /// Schedules the sequencer to stop building blocks after the given block number or
+    /// timestamp, then drain.
+    #[method(name = "stopSequencerAt")]
+    async fn stop_sequencer_at(&self, target: StopTarget) -> Result<(), Error>;
+
+    /// Stops building new blocks while gossiping and serving blocks as usual.
+    #[method(name = "drainSequencer")]
+    async fn drain_sequencer(&self) -> Result<(), Error>;
+
+    /// Resumes building blocks and cancels any scheduled stop.
+    #[method(name = "startSequencer")]
+    async fn start_sequencer(&self) -> Result<(), Error>;
+
+    /// Returns whether the node derives the safe head from L1 or runs in light-follow mode.
+    #[method(name = "derivationMode")]
+    async fn derivation_mode(&self) -> Result<DerivationMode, Error>;
+
+    /// Switches between full derivation and light-follow mode, which only trusts the unsafe
+    /// heads and pauses L1 derivation until switched back. The safe and finalized heads do
+    /// not advance in light-follow mode.
+    #[method(name = "setDerivationMode")]
+    async fn set_derivation_mode(&self, mode: DerivationMode) -> Result<(), Error>;
+
+    /// Returns the gas limit override of the blocks built by the sequencer.
+    #[method(name = "sequencerOverrides")]
+    async fn sequencer_overrides(&self) -> Result<SequencerOverrides, Error>;
+
+    /// Replaces the gas limit override, starting from the next block built. Derived blocks
+    /// never use it.
+    #[method(name = "setSequencerOverrides")]
+    async fn set_sequencer_overrides(&self, overrides: SequencerOverrides) -> Result<(), Error>;
+}
+
+/// Diagnostic RPC methods under the `debug` namespace
+#[rpc(server, namespace = "debug")]
+pub trait DebugRpc {
+    /// Returns the errors of the derivation loop since the node started.
+    #[method(name = "derivationErrors")]
+    async fn derivation_errors(&self) -> Result<DerivationErrors, Error>;
+
+    /// Returns the latest differences between derived blocks and the unsafe blocks they
+    /// replaced, oldest first.
+    #[method(name = "attributeMismatches")]
+    async fn attribute_mismatches(&self) -> Result<Vec<AttributesDiff>, Error>;
+}
+
+/// P2P RPC methods under the `opp2p` namespace
+#[rpc(server, namespace = "opp2p")]
+pub trait P2pRpc {
+    /// Returns the stats of the connected peers, and the banned peers unless `connected` is set
+    #[method(name = "peers")]
+    async fn peers(&self, connected: bool) -> Result<PeerDump, Error>;
+
+    /// Returns the number of connected, discovered, banned and known peers, and the mesh
+    /// peers of every blocks topic
+    #[method(name = "peerStats")]
+    async fn peer_stats(&self) -> Result<PeerCounts, Error>;
+
+    /// Returns the node records in the discovery table
+    #[method(name = "discoveryTable")]
+    async fn discovery_table(&self) -> Result<Vec<String>, Error>;
+
+    /// Disconnects a peer and denies its connections until it is unblocked
+    #[method(name = "blockPeer")]
+    async fn block_peer(&self, peer_id: String) -> Result<(), Error>;
+
+    /// Lifts the block of a peer
+    #[method(name = "unblockPeer")]
+    async fn unblock_peer(&self, peer_id: String) -> Result<(), Error>;
+
+    /// Returns the blocked peer ids
+    #[method(name = "listBlockedPeers")]
+    async fn list_blocked_peers(&self) -> Result<Vec<String>, Error>;
+
+    /// Dials the peer at the given multiaddr
+    #[method(name = "connectPeer")]
+    async fn connect_peer(&self, addr: String) -> Result<(), Error>;
 }
 
 /// The Magi RPC server which implements the same `optimism` namespace methods as `op-node`
@@ -46,6 +198,10 @@ pub struct RpcServerImpl {
     version: Version,
     /// The Magi [Config]
     config: Arc<Config>,
+    /// The sync status, kept up to date by the driver
+    sync_status: Arc<RwLock<SyncStatus>>,
+    /// The cached output roots
+    outputs: Arc<OutputCache>,
 }
 
 #[async_trait]
@@ -53,54 +209,269 @@ impl RpcServer for RpcServerImpl {
     /// Returns the L2 output information for a given block.
     /// See the [Optimism spec](https://specs.optimism.io/protocol/rollup-node.html?highlight=rpc#l2-output-rpc-method) for more details
     async fn output_at_block(&self, block_number: u64) -> Result<OutputRootResponse, Error> {
-        let l2_provider = convert_err(Provider::try_from(self.config.l2_rpc_url.clone()))?;
-
-        let block = convert_err(l2_provider.get_block(block_number).await)?
-            .ok_or(Error::Custom("unable to get block".to_string()))?;
-        let state_root = block.state_root;
-        let block_hash = block
-            .hash
-            .ok_or(Error::Custom("block hash not found".to_string()))?;
-        let locations = vec![];
-        let block_id = Some(BlockId::from(block_hash));
-
-        let state_proof = convert_err(
-            l2_provider
-                .get_proof(
-                    ethers::types::Address::from_slice(
-                        self.config.chain.l2_to_l1_message_passer.as_slice(),
-                    ),
-                    locations,
-                    block_id,
-                )
-                .await,
-        )?;
-
-        let withdrawal_storage_root = state_proof.storage_hash;
-
-        let output_root = compute_l2_output_root(block, state_proof.storage_hash);
-
-        let version: H256 = Default::default();
-
-        Ok(OutputRootResponse {
-            output_root,
-            version,
-            state_root,
-            withdrawal_storage_root,
-        })
+        convert_err(self.outputs.output_at_block(block_number).await)
     }
 
     /// Returns the rollup configuration options.
     async fn rollup_config(&self) -> Result<ExternalChainConfig, Error> {
-        let config = (*self.config).clone();
-
-        Ok(ExternalChainConfig::from(config.chain))
+        Ok(ExternalChainConfig::from(self.config.chain.clone()))
     }
 
     /// Returns details about the Magi version of the node.
     async fn version(&self) -> Result<String, Error> {
         Ok(self.version.to_string())
     }
+
+    /// Returns the current sync status of the node.
+    async fn sync_status(&self) -> Result<SyncStatus, Error> {
+        let status = self
+            .sync_status
+            .read()
+            .map_err(|_| Error::Custom("lock poisoned".to_string()))?;
+
+        Ok(status.clone())
+    }
+}
+
+/// A request to the driver to reset derivation to the given head, answered once the
+/// heads are reset
+#[derive(Debug)]
+pub struct DerivationReset {
+    /// The new unsafe, safe and finalized head
+    pub head: HeadInfo,
+    /// Receives the outcome of the reset
+    pub response: oneshot::Sender<Result<(), String>>,
+}
+
+/// The Magi admin RPC server
+#[derive(Debug)]
+pub struct AdminRpcServerImpl {
+    /// The Magi [Config]
+    config: Arc<Config>,
+    /// The engine endpoints used by the driver, unless the engine is embedded
+    engine: Option<Arc<EngineFailover>>,
+    /// The sequencer signer, if configured
+    signer: Option<SignerInfo>,
+    /// The deposit-only blocks report, kept up to date by the driver
+    forced_inclusion: Arc<RwLock<ForcedInclusionReport>>,
+    /// Channel to request derivation resets from the driver
+    reset_sender: mpsc::Sender<DerivationReset>,
+    /// The sequencer controls, when running as the sequencer
+    sequencer: Option<Arc<SequencerControl>>,
+    /// The derivation mode control
+    derivation: Arc<DerivationControl>,
+    /// The gas limit override of the sequencer
+    overrides: AttributeOverrides,
+}
+
+impl AdminRpcServerImpl {
+    fn engine(&self) -> Result<&EngineFailover, Error> {
+        self.engine
+            .as_deref()
+            .ok_or(Error::Custom("no engine endpoints are used".to_string()))
+    }
+
+    fn sequencer(&self) -> Result<&SequencerControl, Error> {
+        self.sequencer.as_deref().ok_or(Error::Custom(
+            "the node is not running as the sequencer".to_string(),
+        ))
+    }
+}
+
+#[async_trait]
+impl AdminRpcServer for AdminRpcServerImpl {
+    /// Returns the status of the primary and standby engine endpoints.
+    async fn engine_status(&self) -> Result<Vec<EngineEndpointStatus>, Error> {
+        Ok(self.engine()?.status())
+    }
+
+    /// Forces requests to be sent to the engine endpoint at the given index.
+    async fn switch_engine(&self, index: usize) -> Result<(), Error> {
+        convert_err(self.engine()?.switch_to(index))
+    }
+
+    /// Returns the address and source of the sequencer key.
+    async fn signer(&self) -> Result<Option<SignerInfo>, Error> {
+        Ok(self.signer.clone())
+    }
+
+    /// Returns the blocks derived deposit-only since the node started.
+    async fn forced_inclusion_report(&self) -> Result<ForcedInclusionReport, Error> {
+        let report = self
+            .forced_inclusion
+            .read()
+            .map_err(|_| Error::Custom("lock poisoned".to_string()))?;
+
+        Ok(report.clone())
+    }
+
+    /// Forcefully rewinds the node and the engine to the given L2 block.
+    async fn reset_derivation(&self, block_number: u64) -> Result<BlockInfo, Error> {
+        let l2_provider = convert_err(Provider::try_from(self.config.l2_rpc_url.clone()))?;
+        let block = convert_err(l2_provider.get_block_with_txs(block_number).await)?
+            .ok_or(Error::Custom(format!("block {} not found", block_number)))?;
+        let head = convert_err(HeadInfo::try_from_l2_block(&self.config, block))?;
+
+        // derivation restarts with the sequence number of an epoch start
+        if head.sequence_number > 0 {
+            return Err(Error::Custom(format!(
+                "block {} is not the first block of its epoch, which starts at block {}",
+                block_number,
+                block_number.saturating_sub(head.sequence_number)
+            )));
+        }
+
+        let block = head.l2_block_info;
+        let (response, response_recv) = oneshot::channel();
+        let request = DerivationReset { head, response };
+        self.reset_sender
+            .send(request)
+            .await
+            .map_err(|_| Error::Custom("driver stopped".to_string()))?;
+
+        response_recv
+            .await
+            .map_err(|_| Error::Custom("driver stopped".to_string()))?
+            .map_err(Error::Custom)?;
+
+        Ok(block)
+    }
+
+    /// Returns the admin controlled state of the sequencer.
+    async fn sequencer_status(&self) -> Result<SequencerStatus, Error> {
+        Ok(self.sequencer()?.status())
+    }
+
+    /// Schedules the sequencer to stop after the given block.
+    async fn stop_sequencer_at(&self, target: StopTarget) -> Result<(), Error> {
+        convert_err(self.sequencer()?.stop_at(target))
+    }
+
+    /// Stops building new blocks.
+    async fn drain_sequencer(&self) -> Result<(), Error> {
+        self.sequencer()?.drain();
+        Ok(())
+    }
+
+    /// Resumes building blocks.
+    async fn start_sequencer(&self) -> Result<(), Error> {
+        self.sequencer()?.start();
+        Ok(())
+    }
+
+    /// Returns the derivation mode.
+    async fn derivation_mode(&self) -> Result<DerivationMode, Error> {
+        Ok(self.derivation.mode())
+    }
+
+    /// Switches the derivation mode.
+    async fn set_derivation_mode(&self, mode: DerivationMode) -> Result<(), Error> {
+        convert_err(self.derivation.set_mode(mode))
+    }
+
+    /// Returns the gas limit override.
+    async fn sequencer_overrides(&self) -> Result<SequencerOverrides, Error> {
+        Ok(self.overrides.get())
+    }
+
+    /// Replaces the gas limit override.
+    async fn set_sequencer_overrides(&self, overrides: SequencerOverrides) -> Result<(), Error> {
+        convert_err(self.overrides.set(overrides))
+    }
+}
+
+/// The Magi debug RPC server
+#[derive(Debug)]
+pub struct DebugRpcServerImpl {
+    /// The sync status, kept up to date by the driver
+    sync_status: Arc<RwLock<SyncStatus>>,
+    /// The attribute mismatches recorded by the driver
+    mismatches: AttributeMismatches,
+}
+
+#[async_trait]
+impl DebugRpcServer for DebugRpcServerImpl {
+    /// Returns the errors of the derivation loop.
+    async fn derivation_errors(&self) -> Result<DerivationErrors, Error> {
+        self.sync_status
+            .read()
+            .map(|status| status.derivation_errors.clone())
+            .map_err(|_| Error::Custom("lock poisoned".to_string()))
+    }
+
+    /// Returns the latest attribute mismatches.
+    async fn attribute_mismatches(&self) -> Result<Vec<AttributesDiff>, Error> {
+        Ok(self.mismatches.recent())
+    }
+}
+
+/// The Magi P2P RPC server
+#[derive(Debug)]
+pub struct P2pRpcServerImpl {
+    /// The peer stats and management channel of the networking service
+    peers: PeerHandles,
+}
+
+#[async_trait]
+impl P2pRpcServer for P2pRpcServerImpl {
+    /// Returns the stats of the connected and banned peers
+    async fn peers(&self, connected: bool) -> Result<PeerDump, Error> {
+        let mut dump = self
+            .peers
+            .dump
+            .read()
+            .map_err(|_| Error::Custom("lock poisoned".to_string()))?
+            .clone();
+
+        if connected {
+            dump.banned_peers.clear();
+            dump.blocked_peers.clear();
+        }
+
+        Ok(dump)
+    }
+
+    /// Returns the peer counts of the latest peer heartbeat
+    async fn peer_stats(&self) -> Result<PeerCounts, Error> {
+        self.peers
+            .counts
+            .read()
+            .map(|counts| counts.clone())
+            .map_err(|_| Error::Custom("lock poisoned".to_string()))
+    }
+
+    /// Returns the node records of the latest discovery lookup
+    async fn discovery_table(&self) -> Result<Vec<String>, Error> {
+        self.peers
+            .discovery_table
+            .read()
+            .map(|table| table.clone())
+            .map_err(|_| Error::Custom("lock poisoned".to_string()))
+    }
+
+    /// Blocks a peer
+    async fn block_peer(&self, peer_id: String) -> Result<(), Error> {
+        convert_err(self.peers.block(&peer_id).await)
+    }
+
+    /// Unblocks a peer
+    async fn unblock_peer(&self, peer_id: String) -> Result<(), Error> {
+        convert_err(self.peers.unblock(&peer_id).await)
+    }
+
+    /// Returns the blocked peer ids
+    async fn list_blocked_peers(&self) -> Result<Vec<String>, Error> {
+        self.peers
+            .dump
+            .read()
+            .map(|dump| dump.blocked_peers.clone())
+            .map_err(|_| Error::Custom("lock poisoned".to_string()))
+    }
+
+    /// Dials a peer
+    async fn connect_peer(&self, addr: String) -> Result<(), Error> {
+        convert_err(self.peers.connect(&addr).await)
+    }
 }
 
 /// Converts a generic error to a [jsonrpsee::core::error] if one exists
@@ -108,48 +479,123 @@ fn convert_err<T, E: Display>(res: Result<T, E>) -> Result<T, Error> {
     res.map_err(|err| Error::Custom(err.to_string()))
 }
 
-/// Computes the L2 output root.
-/// Refer to the [Optimism Spec](https://specs.optimism.io/protocol/proposals.html#l2-output-commitment-construction) for details
-fn compute_l2_output_root(block: Block<H256>, storage_root: H256) -> H256 {
-    let version: H256 = Default::default();
-    let digest = keccak256(
-        [
-            version.to_fixed_bytes(),
-            block.state_root.to_fixed_bytes(),
-            storage_root.to_fixed_bytes(),
-            block.hash.unwrap().to_fixed_bytes(),
-        ]
-        .concat(),
-    );
-
-    H256::from_slice(&digest)
+/// Handles of the driver used by the admin RPC methods
+#[derive(Debug)]
+pub struct DriverControls {
+    /// Channel to request derivation resets from the driver
+    pub reset_sender: mpsc::Sender<DerivationReset>,
+    /// The sequencer controls, when running as the sequencer
+    pub sequencer: Option<Arc<SequencerControl>>,
+    /// The derivation mode control
+    pub derivation: Arc<DerivationControl>,
+    /// The attribute mismatches recorded by the driver
+    pub mismatches: AttributeMismatches,
+    /// The gas limit override of the derivation state
+    pub overrides: AttributeOverrides,
 }
 
-/// Starts the Magi RPC server
-pub async fn run_server(config: Arc<Config>) -> Result<SocketAddr> {
+/// Starts the Magi RPC server, stopped once the shutdown signal is received
+#[allow(clippy::too_many_arguments)]
+pub async fn run_server(
+    config: Arc<Config>,
+    sync_status: Arc<RwLock<SyncStatus>>,
+    forced_inclusion: Arc<RwLock<ForcedInclusionReport>>,
+    peers: PeerHandles,
+    engine: Option<Arc<EngineFailover>>,
+    signer: Option<SignerInfo>,
+    controls: DriverControls,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<SocketAddr> {
     let port = config.rpc_port;
     let addr = config.rpc_addr.clone();
 
+    let health = HealthChecker::new(&config, engine.clone(), sync_status.clone())
+        .with_derivation_control(controls.derivation.clone());
+    let access = AccessControl::new(&config)?;
+    let middleware = tower::ServiceBuilder::new()
+        .layer(HealthLayer::new(health))
+        .layer(AccessLayer::new(access));
+
+    let mut module = RpcModule::new(());
+    let mut namespaces = config.rpc_api.clone();
+    namespaces.sort();
+    namespaces.dedup();
+    for namespace in namespaces {
+        match namespace.as_str() {
+            "optimism" => {
+                let outputs = Arc::new(OutputCache::new(&config, sync_status.clone()));
+                if config.rpc_precompute_outputs {
+                    outputs.clone().start_precompute();
+                }
+
+                module.merge(
+                    RpcServerImpl {
+                        config: config.clone(),
+                        version: Version::build(),
+                        sync_status: sync_status.clone(),
+                        outputs,
+                    }
+                    .into_rpc(),
+                )?
+            }
+            "opp2p" => module.merge(
+                P2pRpcServerImpl {
+                    peers: peers.clone(),
+                }
+                .into_rpc(),
+            )?,
+            "admin" => module.merge(
+                AdminRpcServerImpl {
+                    config: config.clone(),
+                    engine: engine.clone(),
+                    signer: signer.clone(),
+                    forced_inclusion: forced_inclusion.clone(),
+                    reset_sender: controls.reset_sender.clone(),
+                    sequencer: controls.sequencer.clone(),
+                    derivation: controls.derivation.clone(),
+                    overrides: controls.overrides.clone(),
+                }
+                .into_rpc(),
+            )?,
+            "debug" => module.merge(
+                DebugRpcServerImpl {
+                    sync_status: sync_status.clone(),
+                    mismatches: controls.mismatches.clone(),
+                }
+                .into_rpc(),
+            )?,
+            "eth" => module.merge(proxy::proxy_module(&config, sync_status.clone())?)?,
+            _ => eyre::bail!(
+                "unknown rpc namespace {}; expected optimism, opp2p, admin, debug or eth",
+                namespace
+            ),
+        }
+    }
+
     let server = ServerBuilder::default()
+        .set_logger(RemoteAddrLogger)
+        .set_middleware(middleware)
         .build(format!("{}:{}", addr, port))
         .await?;
     let addr = server.local_addr()?;
-    let rpc_impl = RpcServerImpl {
-        config,
-        version: Version::build(),
-    };
-    let handle = server.start(rpc_impl.into_rpc())?;
-
-    // In this example we don't care about doing shutdown so let's it run forever.
-    // You may use the `ServerHandle` to shut it down or manage it yourself.
-    tokio::spawn(handle.stopped());
+
+    let handle = server.start(module)?;
+
+    // the server runs forever once no shutdown can be signaled anymore
+    tokio::spawn(async move {
+        if shutdown.wait_for(|shutdown| *shutdown).await.is_ok() {
+            _ = handle.stop();
+            tracing::info!("rpc server stopped");
+        }
+        handle.stopped().await;
+    });
     tracing::info!("rpc server started at port {}", port);
 
     Ok(addr)
 }
 
 /// The response for the `optimism_outputAtBlock` RPC method.
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct OutputRootResponse {
     /// The output root which serves as a commitment to the current state of the chain
@@ -162,10 +608,72 @@ pub struct OutputRootResponse {
     pub withdrawal_storage_root: H256,
 }
 
+/// An L1 block of the `optimism_syncStatus` response, encoded as the `L1BlockRef` of op-node
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct L1BlockRef {
+    /// The block hash
+    pub hash: H256,
+    /// The block number
+    pub number: u64,
+    /// The parent block hash
+    pub parent_hash: H256,
+    /// The block timestamp
+    pub timestamp: u64,
+}
+
+impl From<BlockInfo> for L1BlockRef {
+    fn from(block: BlockInfo) -> Self {
+        Self {
+            hash: block.hash,
+            number: block.number,
+            parent_hash: block.parent_hash,
+            timestamp: block.timestamp,
+        }
+    }
+}
+
+impl From<&L1BlockInfo> for L1BlockRef {
+    fn from(block: &L1BlockInfo) -> Self {
+        Self {
+            hash: block.hash,
+            number: block.number,
+            parent_hash: block.parent_hash,
+            timestamp: block.timestamp,
+        }
+    }
+}
+
+/// The response for the `optimism_syncStatus` RPC method.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncStatus {
+    /// The most recent L1 block ingested by the derivation pipeline
+    pub current_l1: L1BlockRef,
+    /// The most recent finalized L1 block
+    pub finalized_l1: L1BlockRef,
+    /// The unsafe L2 head
+    pub unsafe_l2: BlockInfo,
+    /// The safe L2 head
+    pub safe_l2: BlockInfo,
+    /// The finalized L2 head
+    pub finalized_l2: BlockInfo,
+    /// The newest protocol version supported by this node
+    pub supported_protocol_version: ProtocolVersion,
+    /// The protocol versions signaled by the L1 `ProtocolVersions` contract
+    pub protocol_versions: Option<ProtocolVersionSignal>,
+    /// Whether the signaled protocol versions are supported by this node
+    pub protocol_version_support: Option<ProtocolVersionSupport>,
+    /// The restarts of the L1 chain watcher after it died or stalled
+    pub l1_watcher_restarts: WatcherRestarts,
+    /// The errors of the derivation loop
+    pub derivation_errors: DerivationErrors,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::config::{ChainConfig, CliConfig, ExternalChainConfig};
+    use crate::engine::EngineApi;
     use reqwest;
     use serde_json::json;
     use std::{path::PathBuf, str::FromStr};
@@ -187,11 +695,58 @@ mod tests {
             l1_beacon_url: Some("".to_string()),
             l2_rpc_url: None,
             l2_engine_url: None,
+            l2_engine_standby_urls: None,
             jwt_secret: Some("".to_string()),
             checkpoint_sync_url: None,
             rpc_port: Some(8080),
             rpc_addr: Some("127.0.0.1".to_string()),
             devnet: false,
+            supervisor_rpc_url: None,
+            p2p_port: None,
+            forkchoice_batch_size: None,
+            forkchoice_batch_interval_ms: None,
+            jwt_secret_file: None,
+            l1_rpc_budget: None,
+            data_dir: None,
+            l1_epoch_retention: None,
+            l2_block_retention: None,
+            p2p_sync: None,
+            p2p_max_inbound_peers: None,
+            p2p_max_outbound_peers: None,
+            p2p_static_peers: None,
+            p2p_bootnodes: None,
+            sequencer: None,
+            p2p_sequencer_key: None,
+            p2p_sequencer_keystore: None,
+            p2p_sequencer_keystore_password_file: None,
+            p2p_sequencer_signer_url: None,
+            p2p_sequencer_signer_backup_url: None,
+            p2p_sequencer_address: None,
+            profile_derivation: None,
+            unsafe_follow_url: None,
+            unsafe_follow_max_lag: None,
+            readiness_max_head_lag: None,
+            readiness_max_stall: None,
+            rpc_api: None,
+            rpc_auth_token: None,
+            rpc_jwt_secret: None,
+            rpc_cors_origins: None,
+            rpc_rate_limit: None,
+            l1_block_buffer: None,
+            light_follow: None,
+            derivation_lookahead: None,
+            sequencer_gas_limit: None,
+            rpc_precompute_outputs: None,
+            watchdog_stall_timeout: None,
+            watchdog_action: None,
+            rpc_sequencer_url: None,
+            rpc_sequencer_rate_limit: None,
+            driver_tick_timeout: None,
+            dry_run: None,
+            dry_run_until: None,
+            unsafe_payload_retention: None,
+            derivation_step_frames: None,
+            halt_on_unsupported_fork: None,
         };
 
         tracing_subscriber::fmt().init();
@@ -203,9 +758,28 @@ mod tests {
             ChainConfig::optimism_sepolia(),
         ));
 
-        let addr = run_server(config.clone())
-            .await
-            .expect("Failed to start server");
+        let engine = Arc::new(EngineFailover::new(vec![EngineApi::new(
+            "http://127.0.0.1:8551",
+            "f79ae8046bc11c9927afe911db7143c51a806c4a537cc08e0d37140b0192f430",
+        )]));
+        let addr = run_server(
+            config.clone(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Some(engine),
+            None,
+            DriverControls {
+                reset_sender: mpsc::channel(1).0,
+                sequencer: None,
+                derivation: Default::default(),
+                mismatches: Default::default(),
+                overrides: Default::default(),
+            },
+            watch::channel(false).1,
+        )
+        .await
+        .expect("Failed to start server");
 
         sleep(Duration::from_millis(100)).await;
 
@@ -238,6 +812,247 @@ mod tests {
         );
 
         println!("{:#?}", rpc_chain_config);
+
+        // the node is not ready without a reachable L1 RPC
+        let response = client
+            .get(format!("http://{}/readyz", addr))
+            .send()
+            .await
+            .expect("Failed to send request");
+        assert_eq!(response.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+
+        let report: health::HealthReport = response.json().await?;
+        assert!(!report.healthy);
+        assert!(!report.l1.healthy);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rpc_access() -> Result<()> {
+        let config = Arc::new(Config {
+            rpc_addr: "127.0.0.1".to_string(),
+            rpc_port: 0,
+            rpc_api: vec!["optimism".to_string()],
+            rpc_auth_token: Some("secret".to_string()),
+            rpc_rate_limit: Some(3),
+            ..Default::default()
+        });
+
+        let addr = run_server(
+            config,
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            None,
+            None,
+            DriverControls {
+                reset_sender: mpsc::channel(1).0,
+                sequencer: None,
+                derivation: Default::default(),
+                mismatches: Default::default(),
+                overrides: Default::default(),
+            },
+            watch::channel(false).1,
+        )
+        .await?;
+        let client = reqwest::Client::new();
+        let call = |method: &str, token: Option<&str>| {
+            let mut request = client.post(format!("http://{}", addr)).json(&json!({
+                "jsonrpc": "2.0",
+                "method": method,
+                "params": [],
+                "id": 1,
+            }));
+            if let Some(token) = token {
+                request = request.bearer_auth(token);
+            }
+            request.send()
+        };
+
+        let response = call("optimism_version", None).await?;
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+        let response = call("optimism_version", Some("secret")).await?;
+        assert!(response.status().is_success());
+
+        // disabled namespaces are not served
+        let response: serde_json::Value =
+            call("admin_signer", Some("secret")).await?.json().await?;
+        assert_eq!(response["error"]["code"], -32601);
+
+        // the fourth request within a second is over the limit of the IP address
+        let response = call("optimism_version", Some("secret")).await?;
+        assert_eq!(response.status(), reqwest::StatusCode::TOO_MANY_REQUESTS);
+
+        // the health endpoints stay open and are not rate limited
+        let response = client
+            .get(format!("http://{}/healthz", addr))
+            .send()
+            .await?;
+        assert_ne!(response.status(), reqwest::StatusCode::TOO_MANY_REQUESTS);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rpc_websocket_rate_limit() -> Result<()> {
+        let start = |rpc_rate_limit: Option<u32>| {
+            let config = Config {
+                rpc_addr: "127.0.0.1".to_string(),
+                rpc_port: 0,
+                rpc_api: vec!["optimism".to_string()],
+                rpc_rate_limit,
+                ..Default::default()
+            };
+            run_server(
+                Arc::new(config),
+                Default::default(),
+                Default::default(),
+                Default::default(),
+                None,
+                None,
+                DriverControls {
+                    reset_sender: mpsc::channel(1).0,
+                    sequencer: None,
+                    derivation: Default::default(),
+                    mismatches: Default::default(),
+                    overrides: Default::default(),
+                },
+                watch::channel(false).1,
+            )
+        };
+
+        let upgrade = |addr: SocketAddr| {
+            reqwest::Client::new()
+                .get(format!("http://{}", addr))
+                .header("Connection", "Upgrade")
+                .header("Upgrade", "websocket")
+                .header("Sec-WebSocket-Version", "13")
+                .header("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ==")
+                .send()
+        };
+
+        let addr = start(None).await?;
+        let response = upgrade(addr).await?;
+        assert_eq!(response.status(), reqwest::StatusCode::SWITCHING_PROTOCOLS);
+
+        // the messages of a WebSocket connection would bypass the rate limit
+        let addr = start(Some(100)).await?;
+        let response = upgrade(addr).await?;
+        assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rpc_namespaces() -> Result<()> {
+        let call = |addr: SocketAddr, method: &str| {
+            reqwest::Client::new()
+                .post(format!("http://{}", addr))
+                .json(&json!({
+                    "jsonrpc": "2.0",
+                    "method": method,
+                    "params": [],
+                    "id": 1,
+                }))
+                .send()
+        };
+
+        let start = |rpc_api: &[&str]| {
+            let config = Config {
+                rpc_addr: "127.0.0.1".to_string(),
+                rpc_port: 0,
+                rpc_api: rpc_api.iter().map(|api| api.to_string()).collect(),
+                ..Default::default()
+            };
+            run_server(
+                Arc::new(config),
+                Default::default(),
+                Default::default(),
+                Default::default(),
+                None,
+                None,
+                DriverControls {
+                    reset_sender: mpsc::channel(1).0,
+                    sequencer: None,
+                    derivation: Default::default(),
+                    mismatches: Default::default(),
+                    overrides: Default::default(),
+                },
+                watch::channel(false).1,
+            )
+        };
+
+        // the debug methods are only served once the namespace is listed
+        let addr = start(&["optimism"]).await?;
+        let response: serde_json::Value =
+            call(addr, "debug_derivationErrors").await?.json().await?;
+        assert_eq!(response["error"]["code"], -32601);
+
+        let addr = start(&["optimism", "debug"]).await?;
+        let response: serde_json::Value =
+            call(addr, "debug_derivationErrors").await?.json().await?;
+        assert_eq!(response["result"]["count"], 0);
+        let response: serde_json::Value = call(addr, "debug_attributeMismatches")
+            .await?
+            .json()
+            .await?;
+        assert_eq!(response["result"], json!([]));
+
+        let err = start(&["trace"]).await.unwrap_err();
+        assert!(err.to_string().contains("unknown rpc namespace trace"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rpc_shutdown() -> Result<()> {
+        let config = Arc::new(Config {
+            rpc_addr: "127.0.0.1".to_string(),
+            rpc_port: 0,
+            rpc_api: vec!["optimism".to_string()],
+            ..Default::default()
+        });
+
+        let (shutdown_sender, shutdown) = watch::channel(false);
+        let addr = run_server(
+            config,
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            None,
+            None,
+            DriverControls {
+                reset_sender: mpsc::channel(1).0,
+                sequencer: None,
+                derivation: Default::default(),
+                mismatches: Default::default(),
+                overrides: Default::default(),
+            },
+            shutdown,
+        )
+        .await?;
+
+        // a new client for every call, so no pooled connection outlives the server
+        let call = || {
+            reqwest::Client::new()
+                .post(format!("http://{}", addr))
+                .json(&json!({
+                    "jsonrpc": "2.0",
+                    "method": "optimism_version",
+                    "params": [],
+                    "id": 1,
+                }))
+                .send()
+        };
+        assert!(call().await?.status().is_success());
+
+        // the server of a stopped rollup stops with it
+        shutdown_sender.send(true)?;
+        sleep(Duration::from_millis(100)).await;
+        assert!(call().await.is_err());
+
         Ok(())
     }
 }
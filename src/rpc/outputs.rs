@@ -0,0 +1,339 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
+};
+
+use ethers::{
+    providers::{Http, Middleware, Provider},
+    types::{Address, BlockId, H256},
+};
+use eyre::Result;
+
+use crate::{
+    common::{compute_l2_output_root, http},
+    config::Config,
+    store::{outputs_path, KvStore},
+};
+
+use super::{OutputRootResponse, SyncStatus};
+
+/// Number of output roots kept in memory without a data directory
+const MEMORY_CAPACITY: usize = 4096;
+
+/// Maximum number of output roots precomputed per safe head check
+const MAX_PRECOMPUTED: u64 = 64;
+
+/// Interval of the safe head checks of the precomputation task
+const PRECOMPUTE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Length of a stored entry: the block hash, output root, state root and withdrawal
+/// storage root, followed by whether the block hash was confirmed once finalized
+const ENTRY_LEN: usize = 4 * 32 + 1;
+
+/// Caches the output roots served by `optimism_outputAtBlock`, so repeated requests for
+/// historical blocks do not each cost a storage proof from the L2 RPC.
+///
+/// Only the output roots of safe blocks are cached, in `<data_dir>/<chain id>/outputs.db`
+/// if a data directory is configured, and in memory otherwise. Cached outputs are served
+/// once the block hash at their height is confirmed, so an L1 reorg replacing the block
+/// also replaces its output root. Outputs confirmed once their block is finalized are
+/// served directly from then on.
+#[derive(Debug)]
+pub struct OutputCache {
+    /// The L2 execution RPC
+    l2_rpc_url: String,
+    /// The `L2ToL1MessagePasser` predeploy, committed to by the output root
+    message_passer: Address,
+    /// The safe and finalized heads reported by the driver
+    sync_status: Arc<RwLock<SyncStatus>>,
+    /// The cached output roots
+    entries: Mutex<Entries>,
+}
+
+/// A cached output root
+#[derive(Debug, Clone)]
+struct CachedOutput {
+    /// The hash of the block
+    block_hash: H256,
+    /// The output root of the block
+    output: OutputRootResponse,
+    /// Whether the block hash was confirmed once the block was finalized
+    finalized: bool,
+}
+
+/// Where the cached output roots are kept
+#[derive(Debug)]
+enum Entries {
+    /// Persisted in the data directory
+    Disk(KvStore),
+    /// Kept in memory, oldest blocks dropped first
+    Memory(BTreeMap<u64, CachedOutput>),
+}
+
+impl OutputCache {
+    /// Creates a new [OutputCache], opening its store if a data directory is configured
+    pub fn new(config: &Config, sync_status: Arc<RwLock<SyncStatus>>) -> Self {
+        let store = outputs_path(config).and_then(|path| {
+            KvStore::open(&path)
+                .map_err(|err| tracing::warn!("could not open output store {:?}: {}", path, err))
+                .ok()
+        });
+
+        let entries = match store {
+            Some(store) => Entries::Disk(store),
+            None => Entries::Memory(BTreeMap::new()),
+        };
+
+        Self {
+            l2_rpc_url: config.l2_rpc_url.clone(),
+            message_passer: Address::from_slice(config.chain.l2_to_l1_message_passer.as_slice()),
+            sync_status,
+            entries: Mutex::new(entries),
+        }
+    }
+
+    /// Returns the output root of the given block, from the cache if possible
+    pub async fn output_at_block(&self, number: u64) -> Result<OutputRootResponse> {
+        let (safe, _) = self.heads();
+
+        if let Some(cached) = self.get(number) {
+            if self.confirm(number, &cached).await? {
+                return Ok(cached.output);
+            }
+        }
+
+        let cached = self.compute(number).await?;
+        if number <= safe {
+            self.insert(number, &cached);
+        }
+
+        Ok(cached.output)
+    }
+
+    /// Returns true if the cached output is still the output of the block at its height,
+    /// marking it as finalized once confirmed for a finalized block
+    async fn confirm(&self, number: u64, cached: &CachedOutput) -> Result<bool> {
+        if cached.finalized {
+            return Ok(true);
+        }
+
+        let (_, finalized) = self.heads();
+        let block = self.provider()?.get_block(number).await?;
+        if block.and_then(|block| block.hash) != Some(cached.block_hash) {
+            return Ok(false);
+        }
+
+        if number <= finalized {
+            let confirmed = CachedOutput {
+                finalized: true,
+                ..cached.clone()
+            };
+            self.insert(number, &confirmed);
+        }
+
+        Ok(true)
+    }
+
+    /// Spawns a task computing the output root of every new safe block as the safe head
+    /// advances
+    pub fn start_precompute(self: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut next = None;
+
+            loop {
+                tokio::time::sleep(PRECOMPUTE_INTERVAL).await;
+
+                let (safe, _) = self.heads();
+                if safe == 0 {
+                    continue;
+                }
+
+                // starts at the safe head, and follows it back after a rewind
+                let start = next.unwrap_or(safe).min(safe + 1);
+                let end = safe.min(start + MAX_PRECOMPUTED - 1);
+
+                for number in start..=end {
+                    // outputs cached before a rewind are recomputed if their block changed
+                    let confirmed = match self.get(number) {
+                        Some(cached) => self.confirm(number, &cached).await.unwrap_or(false),
+                        None => false,
+                    };
+
+                    if !confirmed {
+                        match self.compute(number).await {
+                            Ok(cached) => self.insert(number, &cached),
+                            Err(err) => {
+                                tracing::debug!("could not precompute output {}: {}", number, err);
+                                break;
+                            }
+                        }
+                    }
+
+                    next = Some(number + 1);
+                }
+            }
+        });
+    }
+
+    /// Computes the output root of a block from its header and a storage proof of the
+    /// message passer
+    async fn compute(&self, number: u64) -> Result<CachedOutput> {
+        let (_, finalized) = self.heads();
+        let provider = self.provider()?;
+        let block = provider
+            .get_block(number)
+            .await?
+            .ok_or(eyre::eyre!("unable to get block"))?;
+        let state_root = block.state_root;
+        let block_hash = block.hash.ok_or(eyre::eyre!("block hash not found"))?;
+
+        let state_proof = provider
+            .get_proof(self.message_passer, vec![], Some(BlockId::from(block_hash)))
+            .await?;
+
+        let withdrawal_storage_root = state_proof.storage_hash;
+        let output_root = compute_l2_output_root(block, withdrawal_storage_root);
+
+        Ok(CachedOutput {
+            block_hash,
+            output: OutputRootResponse {
+                output_root,
+                version: H256::zero(),
+                state_root,
+                withdrawal_storage_root,
+            },
+            finalized: number <= finalized,
+        })
+    }
+
+    fn provider(&self) -> Result<Provider<Http>> {
+        http::provider(&self.l2_rpc_url, http::L2_TIMEOUT)
+    }
+
+    /// Returns the numbers of the safe and finalized heads
+    fn heads(&self) -> (u64, u64) {
+        let status = self
+            .sync_status
+            .read()
+            .unwrap_or_else(|err| err.into_inner());
+
+        (status.safe_l2.number, status.finalized_l2.number)
+    }
+
+    fn get(&self, number: u64) -> Option<CachedOutput> {
+        let entries = self.entries.lock().unwrap_or_else(|err| err.into_inner());
+        match &*entries {
+            Entries::Disk(store) => store
+                .get(&number.to_be_bytes())
+                .ok()
+                .flatten()
+                .and_then(|value| decode_entry(&value)),
+            Entries::Memory(map) => map.get(&number).cloned(),
+        }
+    }
+
+    fn insert(&self, number: u64, cached: &CachedOutput) {
+        let mut entries = self.entries.lock().unwrap_or_else(|err| err.into_inner());
+        match &mut *entries {
+            Entries::Disk(store) => {
+                if let Err(err) = store.put(&number.to_be_bytes(), &encode_entry(cached)) {
+                    tracing::warn!("could not store output {}: {}", number, err);
+                }
+            }
+            Entries::Memory(map) => {
+                map.insert(number, cached.clone());
+                if map.len() > MEMORY_CAPACITY {
+                    map.pop_first();
+                }
+            }
+        }
+    }
+}
+
+fn encode_entry(cached: &CachedOutput) -> Vec<u8> {
+    let output = &cached.output;
+    [
+        cached.block_hash,
+        output.output_root,
+        output.state_root,
+        output.withdrawal_storage_root,
+    ]
+    .iter()
+    .flat_map(|hash| hash.to_fixed_bytes())
+    .chain([cached.finalized as u8])
+    .collect()
+}
+
+fn decode_entry(value: &[u8]) -> Option<CachedOutput> {
+    if value.len() != ENTRY_LEN {
+        return None;
+    }
+
+    let hash = |index: usize| H256::from_slice(&value[index * 32..(index + 1) * 32]);
+
+    Some(CachedOutput {
+        block_hash: hash(0),
+        output: OutputRootResponse {
+            output_root: hash(1),
+            version: H256::zero(),
+            state_root: hash(2),
+            withdrawal_storage_root: hash(3),
+        },
+        finalized: value[ENTRY_LEN - 1] == 1,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cached_outputs() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("magi-outputs-{}", std::process::id()));
+        let config = Config {
+            data_dir: Some(dir.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+
+        let cached = CachedOutput {
+            block_hash: H256::repeat_byte(1),
+            output: OutputRootResponse {
+                output_root: H256::repeat_byte(2),
+                version: H256::zero(),
+                state_root: H256::repeat_byte(3),
+                withdrawal_storage_root: H256::repeat_byte(4),
+            },
+            finalized: true,
+        };
+
+        let cache = OutputCache::new(&config, Default::default());
+        assert!(cache.get(10).is_none());
+        cache.insert(10, &cached);
+        drop(cache);
+
+        // the outputs are kept across restarts
+        let cache = OutputCache::new(&config, Default::default());
+        let restored = cache.get(10).unwrap();
+        assert_eq!(restored.block_hash, cached.block_hash);
+        assert_eq!(restored.output.output_root, cached.output.output_root);
+        assert_eq!(
+            restored.output.withdrawal_storage_root,
+            cached.output.withdrawal_storage_root
+        );
+        assert!(restored.finalized);
+
+        // outputs cached before they were confirmed as finalized are checked again
+        cache.insert(
+            11,
+            &CachedOutput {
+                finalized: false,
+                ..cached
+            },
+        );
+        assert!(!cache.get(11).unwrap().finalized);
+
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,220 @@
+use std::sync::{Arc, RwLock};
+
+use ethers::providers::{Http, Provider, ProviderError, RpcError};
+use eyre::Result;
+use jsonrpsee::{
+    core::Error,
+    types::{error::CallError, ErrorObject, Params},
+    RpcModule,
+};
+use serde_json::Value;
+
+use crate::{
+    common::{http, BlockInfo},
+    config::Config,
+};
+
+use super::{forward::TxForwarder, SyncStatus};
+
+/// Where a proxied method takes its block parameter
+#[derive(Debug, Clone, Copy)]
+enum BlockParam {
+    /// The method has no block parameter
+    None,
+    /// The block tag, number or EIP-1898 block object at the given position
+    At(usize),
+    /// The `fromBlock` and `toBlock` of the filter object at the given position
+    Filter(usize),
+}
+
+/// The `eth` methods forwarded to the execution client
+const PROXIED_METHODS: &[(&str, BlockParam)] = &[
+    ("eth_blockNumber", BlockParam::None),
+    ("eth_chainId", BlockParam::None),
+    ("eth_syncing", BlockParam::None),
+    ("eth_gasPrice", BlockParam::None),
+    ("eth_maxPriorityFeePerGas", BlockParam::None),
+    ("eth_blobBaseFee", BlockParam::None),
+    ("eth_feeHistory", BlockParam::At(1)),
+    ("eth_getBlockByHash", BlockParam::None),
+    ("eth_getBlockByNumber", BlockParam::At(0)),
+    ("eth_getBlockReceipts", BlockParam::At(0)),
+    ("eth_getBlockTransactionCountByHash", BlockParam::None),
+    ("eth_getBlockTransactionCountByNumber", BlockParam::At(0)),
+    ("eth_getTransactionByHash", BlockParam::None),
+    ("eth_getTransactionByBlockHashAndIndex", BlockParam::None),
+    ("eth_getTransactionByBlockNumberAndIndex", BlockParam::At(0)),
+    ("eth_getTransactionReceipt", BlockParam::None),
+    ("eth_getBalance", BlockParam::At(1)),
+    ("eth_getCode", BlockParam::At(1)),
+    ("eth_getStorageAt", BlockParam::At(2)),
+    ("eth_getTransactionCount", BlockParam::At(1)),
+    ("eth_getProof", BlockParam::At(2)),
+    ("eth_call", BlockParam::At(1)),
+    ("eth_estimateGas", BlockParam::At(1)),
+    ("eth_createAccessList", BlockParam::At(1)),
+    ("eth_getLogs", BlockParam::Filter(0)),
+];
+
+/// Forwards the `eth` calls of a proxied method to the execution client
+struct Proxy {
+    /// Provider for the L2 execution RPC
+    provider: Provider<Http>,
+    /// The heads resolving the `safe` and `finalized` tags
+    sync_status: Arc<RwLock<SyncStatus>>,
+}
+
+/// Returns the `eth` namespace methods, forwarded to the execution client at the L2 RPC
+/// with the `safe` and `finalized` block tags resolved to the heads derived by magi.
+///
+/// The tags are forwarded unchanged until the driver reported its heads. If a sequencer RPC
+/// is configured, `eth_sendRawTransaction` is forwarded to it.
+pub fn proxy_module(
+    config: &Config,
+    sync_status: Arc<RwLock<SyncStatus>>,
+) -> Result<RpcModule<()>> {
+    let proxy = Arc::new(Proxy {
+        provider: http::provider(&config.l2_rpc_url, http::L2_TIMEOUT)?,
+        sync_status,
+    });
+
+    let mut module = RpcModule::new(());
+    for &(method, block_param) in PROXIED_METHODS {
+        let proxy = proxy.clone();
+        module.register_async_method(method, move |params, _| {
+            let proxy = proxy.clone();
+            async move { proxy.forward(method, block_param, params).await }
+        })?;
+    }
+
+    if let Some(forwarder) = TxForwarder::from_config(config)? {
+        let forwarder = Arc::new(forwarder);
+        module.register_async_method("eth_sendRawTransaction", move |params, _| {
+            let forwarder = forwarder.clone();
+            async move { forwarder.send_raw_transaction(params.one()?).await }
+        })?;
+    }
+
+    Ok(module)
+}
+
+impl Proxy {
+    async fn forward(
+        &self,
+        method: &str,
+        block_param: BlockParam,
+        params: Params<'static>,
+    ) -> Result<Value, Error> {
+        let mut params = params.parse::<Option<Vec<Value>>>()?.unwrap_or_default();
+
+        let (safe, finalized) = {
+            let status = self
+                .sync_status
+                .read()
+                .map_err(|_| Error::Custom("lock poisoned".to_string()))?;
+            (status.safe_l2, status.finalized_l2)
+        };
+        resolve_tags(&mut params, block_param, safe, finalized);
+
+        self.provider
+            .request::<_, Value>(method, params)
+            .await
+            .map_err(upstream_error)
+    }
+}
+
+/// Replaces the `safe` and `finalized` tags of the block parameter by the number of the
+/// given heads, unless the heads are not known yet
+fn resolve_tags(
+    params: &mut [Value],
+    block_param: BlockParam,
+    safe: BlockInfo,
+    finalized: BlockInfo,
+) {
+    if safe.hash.is_zero() {
+        return;
+    }
+
+    let resolve = |value: &mut Value| {
+        let head = match value.as_str() {
+            Some("safe") => safe,
+            Some("finalized") => finalized,
+            _ => return,
+        };
+
+        *value = Value::String(format!("{:#x}", head.number));
+    };
+
+    match block_param {
+        BlockParam::None => {}
+        BlockParam::At(index) => match params.get_mut(index) {
+            Some(Value::Object(block)) => {
+                if let Some(number) = block.get_mut("blockNumber") {
+                    resolve(number);
+                }
+            }
+            Some(value) => resolve(value),
+            None => {}
+        },
+        BlockParam::Filter(index) => {
+            if let Some(Value::Object(filter)) = params.get_mut(index) {
+                for key in ["fromBlock", "toBlock"] {
+                    if let Some(value) = filter.get_mut(key) {
+                        resolve(value);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Returns the error of the upstream RPC, keeping its code and data
+pub(super) fn upstream_error(err: ProviderError) -> Error {
+    match err.as_error_response() {
+        Some(err) => Error::Call(CallError::Custom(ErrorObject::owned(
+            err.code as i32,
+            err.message.clone(),
+            err.data.clone(),
+        ))),
+        None => Error::Custom(err.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::H256;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_resolve_tags() {
+        let head = |number| BlockInfo {
+            number,
+            hash: H256::repeat_byte(1),
+            ..Default::default()
+        };
+
+        let mut params = vec![json!("0xabc"), json!("safe")];
+        resolve_tags(&mut params, BlockParam::At(1), head(20), head(10));
+        assert_eq!(params[1], json!("0x14"));
+
+        let mut params = vec![json!("0xabc"), json!({"blockNumber": "finalized"})];
+        resolve_tags(&mut params, BlockParam::At(1), head(20), head(10));
+        assert_eq!(params[1], json!({"blockNumber": "0xa"}));
+
+        let mut params = vec![json!({"fromBlock": "finalized", "toBlock": "latest"})];
+        resolve_tags(&mut params, BlockParam::Filter(0), head(20), head(10));
+        assert_eq!(params[0], json!({"fromBlock": "0xa", "toBlock": "latest"}));
+
+        // the tags are forwarded until the heads are known
+        let mut params = vec![json!("safe"), json!(false)];
+        resolve_tags(
+            &mut params,
+            BlockParam::At(0),
+            BlockInfo::default(),
+            head(10),
+        );
+        assert_eq!(params[0], json!("safe"));
+    }
+}
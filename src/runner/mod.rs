@@ -1,18 +1,22 @@
-use std::{process, time::Duration};
+use std::{sync::Arc, time::Duration};
 
 use ethers::{
-    providers::{Middleware, Provider},
+    providers::Middleware,
     types::{Block, BlockId, BlockNumber, Transaction, H256},
 };
 use eyre::Result;
 use tokio::{
-    sync::watch::{channel, Receiver},
+    sync::{
+        broadcast,
+        watch::{channel, Receiver, Sender},
+    },
     time::sleep,
 };
 
 use crate::{
+    common::http,
     config::{Config, SyncMode},
-    driver::{Driver, HeadInfo},
+    driver::{Driver, HeadInfo, NodeEvent, EVENT_CAPACITY},
     engine::{Engine, EngineApi, ExecutionPayload, ForkchoiceState, Status},
 };
 
@@ -31,28 +35,35 @@ pub struct Runner {
     checkpoint_hash: Option<String>,
     /// Receiver to listen for SIGINT signals
     shutdown_recv: Receiver<bool>,
+    /// Channel the [Driver] publishes its [NodeEvent]s on
+    events: broadcast::Sender<NodeEvent>,
 }
 
 impl Runner {
     /// Creates a new [Runner] from a [Config] and registers the SIGINT signal handler.
     pub fn from_config(config: Config) -> Self {
-        let (shutdown_sender, shutdown_recv) = channel(false);
-        ctrlc::set_handler(move || {
-            tracing::info!("shutting down");
-            shutdown_sender
-                .send(true)
-                .expect("could not send shutdown signal");
-        })
-        .expect("could not register shutdown handler");
+        Self::new(config, shutdown_signal())
+    }
 
+    /// Creates a new [Runner] from a [Config] which listens to the given shutdown signal.
+    /// This allows several runners in the same process to share one signal handler.
+    pub fn new(config: Config, shutdown_recv: Receiver<bool>) -> Self {
         Self {
             config,
             sync_mode: SyncMode::Full,
             checkpoint_hash: None,
             shutdown_recv,
+            events: broadcast::channel(EVENT_CAPACITY).0,
         }
     }
 
+    /// Returns a receiver of the head updates, finalizations, reorgs and derivation
+    /// errors of the node, for embedders to observe its progress. Receivers created
+    /// before the node is started receive every event from the start.
+    pub fn subscribe(&self) -> broadcast::Receiver<NodeEvent> {
+        self.events.subscribe()
+    }
+
     /// Sets the [SyncMode]
     pub fn with_sync_mode(mut self, sync_mode: SyncMode) -> Self {
         self.sync_mode = sync_mode;
@@ -67,6 +78,10 @@ impl Runner {
 
     /// Begins the syncing process
     pub async fn run(self) -> Result<()> {
+        if self.config.dry_run && self.sync_mode != SyncMode::Full {
+            eyre::bail!("dry runs only support full sync");
+        }
+
         match self.sync_mode {
             SyncMode::Fast => self.fast_sync().await,
             SyncMode::Challenge => self.challenge_sync().await,
@@ -100,11 +115,13 @@ impl Runner {
     ///
     /// Note: the `admin` RPC method must be available on the execution client as checkpoint_sync relies on `admin_addPeer`
     pub async fn checkpoint_sync(&self) -> Result<()> {
-        let l2_provider = Provider::try_from(&self.config.l2_rpc_url)?;
-        let checkpoint_sync_url =
-            Provider::try_from(self.config.checkpoint_sync_url.as_ref().ok_or(eyre::eyre!(
+        let l2_provider = http::provider(&self.config.l2_rpc_url, http::L2_TIMEOUT)?;
+        let checkpoint_sync_url = http::provider(
+            self.config.checkpoint_sync_url.as_ref().ok_or(eyre::eyre!(
                 "a checkpoint sync rpc url is required for checkpoint sync"
-            ))?)?;
+            ))?,
+            http::L2_TIMEOUT,
+        )?;
 
         let checkpoint_block = match self.checkpoint_hash {
             Some(ref checkpoint) => {
@@ -123,8 +140,7 @@ impl Runner {
                         .await?
                         .expect("could not get checkpoint block"),
                     false => {
-                        tracing::error!("the provided checkpoint block is not an epoch boundary");
-                        process::exit(1);
+                        eyre::bail!("the provided checkpoint block is not an epoch boundary")
                     }
                 }
             }
@@ -138,7 +154,9 @@ impl Runner {
                     .ok_or_else(|| eyre::eyre!("could not find block"))?;
 
                 while !is_epoch_boundary(l2_block.clone(), &self.config)? {
-                    self.check_shutdown()?;
+                    if self.is_shutdown() {
+                        return Ok(());
+                    }
                     block_number -= 1.into();
                 }
 
@@ -157,9 +175,11 @@ impl Runner {
         let checkpoint_hash = checkpoint_block.hash.expect("block hash is missing");
         tracing::info!("using checkpoint block {}", checkpoint_hash);
 
-        let engine_api = EngineApi::new(&self.config.l2_engine_url, &self.config.jwt_secret);
+        let engine_api = EngineApi::from_config(&self.config.l2_engine_url, &self.config)?;
         while !engine_api.is_available().await {
-            self.check_shutdown()?;
+            if self.is_shutdown() {
+                return Ok(());
+            }
             sleep(Duration::from_secs(3)).await;
         }
 
@@ -180,8 +200,7 @@ impl Runner {
 
         let payload_res = engine_api.new_payload(checkpoint_payload.clone()).await?;
         if let Status::Invalid | Status::InvalidBlockHash = payload_res.status {
-            tracing::error!("the provided checkpoint payload is invalid, exiting");
-            process::exit(1);
+            eyre::bail!("the provided checkpoint payload is invalid");
         }
 
         // make the execution client start syncing up to the checkpoint
@@ -190,14 +209,15 @@ impl Runner {
             .forkchoice_updated(forkchoice_state, None)
             .await?;
         if let Status::Invalid | Status::InvalidBlockHash = forkchoice_res.payload_status.status {
-            tracing::error!("could not accept forkchoice, exiting");
-            process::exit(1);
+            eyre::bail!("could not accept forkchoice of the checkpoint block");
         }
 
         tracing::info!("syncing execution client to the checkpoint block...",);
 
         while l2_provider.get_block_number().await? < checkpoint_payload.block_number {
-            self.check_shutdown()?;
+            if self.is_shutdown() {
+                return Ok(());
+            }
             sleep(Duration::from_secs(3)).await;
         }
 
@@ -208,29 +228,76 @@ impl Runner {
     }
 
     /// Creates and starts the [Driver] which handles the derivation sync process.
+    ///
+    /// The driver listens to its own shutdown signal, following the one of the runner. Once
+    /// the driver stops, its signal is set, so its RPC server stops with it while other
+    /// runners sharing the signal of this runner keep running.
     async fn start_driver(&self) -> Result<()> {
-        let mut driver =
-            Driver::from_config(self.config.clone(), self.shutdown_recv.clone()).await?;
+        let (stop_sender, stop_recv) = channel(false);
+        let stop_sender = Arc::new(stop_sender);
+        let forward = tokio::spawn(forward_shutdown(
+            self.shutdown_recv.clone(),
+            stop_sender.clone(),
+        ));
+
+        let result = self.run_driver(stop_recv).await;
+
+        stop_sender.send_replace(true);
+        forward.abort();
+        result
+    }
 
-        if let Err(err) = driver.start().await {
-            tracing::error!("driver failure: {}", err);
-            std::process::exit(1);
+    /// Creates and runs the [Driver] until it stops, listening to the given shutdown signal.
+    /// Errors with the [DriverError](crate::driver::DriverError) of a failed driver.
+    async fn run_driver(&self, shutdown_recv: Receiver<bool>) -> Result<()> {
+        if self.config.dry_run {
+            let mut driver = Driver::dry_run(self.config.clone(), shutdown_recv)
+                .await?
+                .with_events(self.events.clone());
+            driver.start().await?;
+            return Ok(());
         }
 
+        let mut driver = Driver::from_config(self.config.clone(), shutdown_recv)
+            .await?
+            .with_events(self.events.clone());
+        driver.start().await?;
         Ok(())
     }
 
-    /// Exits if a SIGINT signal is received
-    fn check_shutdown(&self) -> Result<()> {
+    /// Returns `true` once a SIGINT signal is received
+    fn is_shutdown(&self) -> bool {
         if *self.shutdown_recv.borrow() {
             tracing::warn!("shutting down");
-            process::exit(0);
+            return true;
         }
 
-        Ok(())
+        false
+    }
+}
+
+/// Forwards the shutdown signal of a runner to the signal of its driver
+async fn forward_shutdown(mut shutdown_recv: Receiver<bool>, stop_sender: Arc<Sender<bool>>) {
+    if shutdown_recv.wait_for(|shutdown| *shutdown).await.is_ok() {
+        stop_sender.send_replace(true);
     }
 }
 
+/// Registers the SIGINT signal handler and returns a receiver for the shutdown signal.
+/// Can only be called once per process.
+pub fn shutdown_signal() -> Receiver<bool> {
+    let (shutdown_sender, shutdown_recv) = channel(false);
+    ctrlc::set_handler(move || {
+        tracing::info!("shutting down");
+        shutdown_sender
+            .send(true)
+            .expect("could not send shutdown signal");
+    })
+    .expect("could not register shutdown handler");
+
+    shutdown_recv
+}
+
 /// Returns `true` if the L2 block is the first in an epoch (sequence number 0)
 fn is_epoch_boundary(l2_block: Block<Transaction>, config: &Config) -> Result<bool> {
     let head_info = HeadInfo::try_from_l2_block(config, l2_block)?;
@@ -0,0 +1,286 @@
+use std::{
+    fs::{self, File, TryLockError},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Config, version::Version};
+
+use super::{chain_dir, migrate_legacy_log, sync_parent, KvStore};
+
+/// The version of the data directory layout written by this release
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Name of the schema file in the chain directory
+const SCHEMA_FILE: &str = "schema.json";
+
+/// Name of the lock file in the chain directory
+const LOCK_FILE: &str = "LOCK";
+
+/// The key-value databases of a chain directory
+const DATABASES: &[&str] = &["state.db", "outputs.db", "unsafe.db"];
+
+/// A migration of the data directory layout to the next schema version
+struct Migration {
+    /// The schema version migrated from
+    from: u32,
+    /// What the migration changes, for the logs
+    description: &'static str,
+    /// Migrates the given chain directory
+    run: fn(&Path) -> Result<()>,
+}
+
+/// The migrations between schema versions, in order. Version 0 is the layout written before
+/// the schema file was introduced.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 0,
+        description: "add the schema file",
+        run: |_| Ok(()),
+    },
+    Migration {
+        from: 1,
+        description: "checksum the records of the key-value stores",
+        run: |path| {
+            DATABASES
+                .iter()
+                .try_for_each(|name| migrate_legacy_log(&path.join(name)))
+        },
+    },
+];
+
+/// The schema file of a chain directory
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Schema {
+    /// The version of the layout
+    pub version: u32,
+    /// The release that last wrote the schema file
+    pub written_by: String,
+}
+
+/// The data directory of a chain, locked against other magi processes while held.
+///
+/// Opening it migrates the layout to [SCHEMA_VERSION], recording the new version after each
+/// migration so an interrupted upgrade resumes where it stopped. Directories written by a
+/// newer release are refused rather than downgraded.
+#[derive(Debug)]
+pub struct DataDir {
+    /// The chain directory
+    path: PathBuf,
+    /// The locked lock file, unlocked when dropped
+    _lock: File,
+}
+
+impl DataDir {
+    /// Opens and migrates the data directory of the configured chain, if a data directory is
+    /// configured. Fails if another process holds it.
+    pub fn open(config: &Config) -> Result<Option<Self>> {
+        chain_dir(config).map(Self::open_path).transpose()
+    }
+
+    /// Opens and migrates the given chain directory, creating it if needed
+    pub fn open_path(path: PathBuf) -> Result<Self> {
+        fs::create_dir_all(&path)?;
+
+        let lock = File::create(path.join(LOCK_FILE))?;
+        match lock.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                eyre::bail!("data directory {:?} is in use by another process", path)
+            }
+            Err(TryLockError::Error(err)) => return Err(err.into()),
+        }
+
+        let data_dir = Self { path, _lock: lock };
+        data_dir.migrate()?;
+
+        Ok(data_dir)
+    }
+
+    /// Returns the chain directory
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the schema file of the directory, if it has been written
+    pub fn schema(&self) -> Result<Option<Schema>> {
+        let path = self.path.join(SCHEMA_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        Ok(Some(serde_json::from_slice(&fs::read(path)?)?))
+    }
+
+    /// Returns the databases present in the directory
+    pub fn databases(&self) -> Result<Vec<DatabaseInfo>> {
+        DATABASES
+            .iter()
+            .map(|name| self.path.join(name))
+            .filter(|path| path.exists())
+            .map(|path| {
+                let store = KvStore::open(&path)?;
+                Ok(DatabaseInfo {
+                    path,
+                    keys: store.len(),
+                    size: store.size(),
+                    garbage: store.garbage(),
+                })
+            })
+            .collect()
+    }
+
+    /// Compacts every database in the directory, returning their sizes after compaction
+    pub fn compact(&self) -> Result<Vec<DatabaseInfo>> {
+        for info in self.databases()? {
+            KvStore::open(&info.path)?.compact()?;
+        }
+
+        self.databases()
+    }
+
+    fn migrate(&self) -> Result<()> {
+        let mut version = match self.schema()? {
+            Some(schema) => schema.version,
+            None if self.is_empty()? => SCHEMA_VERSION,
+            None => 0,
+        };
+
+        if version > SCHEMA_VERSION {
+            eyre::bail!(
+                "data directory {:?} has schema version {}, newer than the supported version {}",
+                self.path,
+                version,
+                SCHEMA_VERSION
+            );
+        }
+
+        let current = version;
+        for migration in MIGRATIONS.iter().filter(|m| m.from >= current) {
+            tracing::info!(
+                "migrating data directory {:?} to schema version {}: {}",
+                self.path,
+                migration.from + 1,
+                migration.description
+            );
+
+            (migration.run)(&self.path)?;
+            version = migration.from + 1;
+            self.write_schema(version)?;
+        }
+
+        if self.schema()?.is_none() {
+            self.write_schema(version)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns true if the directory holds nothing but the lock file
+    fn is_empty(&self) -> Result<bool> {
+        for entry in fs::read_dir(&self.path)? {
+            if entry?.file_name() != LOCK_FILE {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn write_schema(&self, version: u32) -> Result<()> {
+        let schema = Schema {
+            version,
+            written_by: Version::build().to_string(),
+        };
+
+        write_atomic(
+            &self.path.join(SCHEMA_FILE),
+            &serde_json::to_vec_pretty(&schema)?,
+        )
+    }
+}
+
+/// The size and contents of a database in the data directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DatabaseInfo {
+    /// Path to the database
+    pub path: PathBuf,
+    /// Number of keys in the database
+    pub keys: usize,
+    /// Size of the database file in bytes
+    pub size: u64,
+    /// Bytes taken by overwritten and deleted records, reclaimed by compaction
+    pub garbage: u64,
+}
+
+/// Replaces a file atomically: the data is written to a temporary file, synced to disk and
+/// renamed over the path, so a crash leaves either the old or the new file in place
+pub fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty());
+    if let Some(dir) = dir {
+        fs::create_dir_all(dir)?;
+    }
+
+    let tmp_path = path.with_extension("tmp");
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)?;
+
+    sync_parent(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_data_dir() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("magi-datadir-{}", std::process::id()));
+        _ = fs::remove_dir_all(&path);
+
+        // a directory from before the schema file is migrated, checksumming the records of
+        // the stores written without them
+        fs::create_dir_all(&path)?;
+        let legacy = |key: &[u8], value: &[u8]| {
+            let lens = [
+                (key.len() as u32).to_le_bytes(),
+                (value.len() as u32).to_le_bytes(),
+            ];
+            [lens.concat(), key.to_vec(), value.to_vec()].concat()
+        };
+        let state = [legacy(b"a", b"0"), legacy(b"a", b"1"), legacy(b"c", b"")].concat();
+        fs::write(path.join("state.db"), state)?;
+        assert!(KvStore::open(path.join("state.db")).is_err());
+        KvStore::open(path.join("unsafe.db"))?.put(b"b", b"2")?;
+
+        let data_dir = DataDir::open_path(path.clone())?;
+        assert_eq!(data_dir.schema()?.unwrap().version, SCHEMA_VERSION);
+        assert!(DataDir::open_path(path.clone()).is_err());
+
+        let databases = data_dir.compact()?;
+        assert_eq!(databases.len(), 2);
+        assert!(databases.iter().all(|db| db.garbage == 0));
+        assert!(databases[1].path.ends_with("unsafe.db"));
+        let state = KvStore::open(path.join("state.db"))?;
+        assert_eq!(state.get(b"a")?, Some(b"1".to_vec()));
+        assert_eq!(state.len(), 2);
+        drop(state);
+        drop(data_dir);
+
+        // directories written by newer releases are refused
+        let schema = Schema {
+            version: SCHEMA_VERSION + 1,
+            written_by: String::new(),
+        };
+        write_atomic(&path.join(SCHEMA_FILE), &serde_json::to_vec(&schema)?)?;
+        assert!(DataDir::open_path(path.clone()).is_err());
+
+        fs::remove_dir_all(&path)?;
+        Ok(())
+    }
+}
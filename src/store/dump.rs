@@ -0,0 +1,168 @@
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use ethers::providers::{Http, Middleware, Provider};
+use eyre::Result;
+
+use crate::{
+    config::Config,
+    engine::{ExecutionPayload, PayloadEnvelope, PayloadVersion},
+};
+
+/// The file format of dumped execution payloads
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DumpFormat {
+    /// A JSON [PayloadEnvelope], in the shape of an `engine_getPayload` response so it
+    /// carries everything needed to replay it with `engine_newPayload`
+    #[default]
+    Json,
+    /// The SSZ encoded payload, prefixed with its little endian version number
+    Ssz,
+}
+
+impl DumpFormat {
+    /// Returns the file extension of the format
+    fn extension(&self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Ssz => "ssz",
+        }
+    }
+}
+
+impl FromStr for DumpFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::Json),
+            "ssz" => Ok(Self::Ssz),
+            _ => Err("invalid dump format".to_string()),
+        }
+    }
+}
+
+impl fmt::Display for DumpFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.extension())
+    }
+}
+
+/// Fetches the L2 blocks from `from` to `to`, or to the unsafe head, from the configured L2
+/// RPC and writes them as execution payloads to the given directory, one file per block
+/// named after its number. Returns the number of written blocks.
+pub async fn dump_blocks(
+    config: &Config,
+    from: u64,
+    to: Option<u64>,
+    dir: &Path,
+    format: DumpFormat,
+) -> Result<u64> {
+    let provider = Provider::<Http>::try_from(config.l2_rpc_url.as_str())?;
+    let to = match to {
+        Some(to) => to,
+        None => provider.get_block_number().await?.as_u64(),
+    };
+
+    fs::create_dir_all(dir)?;
+
+    for number in from..=to {
+        let block = provider
+            .get_block_with_txs(number)
+            .await?
+            .ok_or(eyre::eyre!("block {} not found", number))?;
+
+        let payload: ExecutionPayload = block.try_into()?;
+        let version = PayloadVersion::at(&config.chain.hardforks, payload.timestamp.as_u64());
+        write_payload(dir, &payload, format, version)?;
+        tracing::debug!("dumped block {}", number);
+    }
+
+    Ok(to.saturating_sub(from) + 1)
+}
+
+/// Writes an execution payload to the given directory, returning the path of the file. SSZ
+/// payloads are encoded with the given version.
+pub fn write_payload(
+    dir: &Path,
+    payload: &ExecutionPayload,
+    format: DumpFormat,
+    version: PayloadVersion,
+) -> Result<PathBuf> {
+    let path = dir.join(format!("{}.{}", payload.block_number, format.extension()));
+    let envelope = PayloadEnvelope::new(payload.clone());
+
+    let data = match format {
+        DumpFormat::Json => serde_json::to_vec_pretty(&envelope)?,
+        DumpFormat::Ssz => {
+            let data = envelope.encode_ssz(version)?;
+            [version.number().to_le_bytes().as_slice(), &data].concat()
+        }
+    };
+
+    fs::write(&path, data)?;
+    Ok(path)
+}
+
+/// Reads an execution payload written by [write_payload], in the format of its extension
+pub fn read_payload(path: &Path) -> Result<ExecutionPayload> {
+    let data = fs::read(path)?;
+
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => {
+            let envelope: PayloadEnvelope = serde_json::from_slice(&data)?;
+            Ok(envelope.into_payload())
+        }
+        Some("ssz") if data.len() >= 4 => {
+            let version = u32::from_le_bytes(data[..4].try_into()?);
+            let envelope = PayloadEnvelope::decode_ssz(version.try_into()?, &data[4..])?;
+            Ok(envelope.into_payload())
+        }
+        _ => eyre::bail!("unknown payload file {:?}", path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::H256;
+
+    use crate::common::RawTransaction;
+
+    use super::*;
+
+    #[test]
+    fn test_payload_round_trip() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("magi-dump-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+
+        let mut payload = ExecutionPayload {
+            block_number: 5.into(),
+            logs_bloom: vec![0; 256].into(),
+            extra_data: vec![1, 2].into(),
+            base_fee_per_gas: 7.into(),
+            block_hash: H256::repeat_byte(3),
+            transactions: vec![RawTransaction(vec![4, 5, 6])],
+            withdrawals: Some(Vec::new()),
+            blob_gas_used: Some(0.into()),
+            excess_blob_gas: Some(0.into()),
+            parent_beacon_block_root: Some(H256::repeat_byte(9)),
+            ..Default::default()
+        };
+
+        for format in [DumpFormat::Json, DumpFormat::Ssz] {
+            let path = write_payload(&dir, &payload, format, PayloadVersion::V3)?;
+            assert_eq!(path.file_name().unwrap(), format!("5.{}", format).as_str());
+            assert_eq!(read_payload(&path)?, payload);
+        }
+
+        // ecotone payloads cannot be encoded without the parent beacon block root
+        payload.parent_beacon_block_root = None;
+        assert!(write_payload(&dir, &payload, DumpFormat::Ssz, PayloadVersion::V3).is_err());
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,500 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    ops::RangeBounds,
+    path::{Path, PathBuf},
+};
+
+use eyre::Result;
+
+use crate::config::Config;
+
+/// Snapshots of the derivation state
+#[cfg(feature = "node")]
+pub mod snapshot;
+#[cfg(feature = "node")]
+pub use snapshot::{PipelineCheckpoint, Snapshot};
+
+/// Exports of L2 blocks as execution payload files
+#[cfg(feature = "node")]
+pub mod dump;
+
+/// The latest unsafe payloads, restored on restart
+#[cfg(feature = "node")]
+pub mod unsafe_payloads;
+#[cfg(feature = "node")]
+pub use unsafe_payloads::UnsafePayloads;
+
+/// Schema versioning, locking and maintenance of the data directory
+pub mod datadir;
+pub use datadir::{write_atomic, DataDir};
+
+/// Marks a deleted key in place of the value length
+const TOMBSTONE: u32 = u32::MAX;
+
+/// Written at the start of every log, telling it apart from the logs of schema version 1,
+/// whose records have no checksum
+const MAGIC: &[u8; 8] = b"magikv02";
+
+/// Size of a record header holding the checksum, key and value lengths
+const HEADER_LEN: u64 = 12;
+
+/// Size of a record header in the logs of schema version 1, without the checksum
+const LEGACY_HEADER_LEN: u64 = 8;
+
+/// The log is compacted once it holds more than this many bytes of overwritten records
+const MIN_COMPACTION_BYTES: u64 = 16 * 1024 * 1024;
+
+/// An embedded key-value store persisted in an append-only log file.
+///
+/// Every write appends a record to the log, and an in-memory index maps each key to the
+/// location of its latest value, so reads take a single positioned read from disk. Keys
+/// are ordered, which allows range scans over big-endian encoded numbers. Once the log
+/// holds more overwritten than live data, it is rewritten with only the live records.
+///
+/// Each record carries a CRC-32 checksum of its lengths, key and value. A record torn by a
+/// crash, or failing its checksum, is discarded with the rest of the log after it when the
+/// log is opened.
+#[derive(Debug)]
+pub struct KvStore {
+    /// Path to the log file
+    path: PathBuf,
+    /// The log file
+    file: File,
+    /// Offset and length of the latest value of each key
+    index: BTreeMap<Vec<u8>, (u64, u32)>,
+    /// Length of the log
+    len: u64,
+    /// Bytes taken by overwritten and deleted records
+    garbage: u64,
+}
+
+impl KvStore {
+    /// Opens the store at the given path, creating it and its parent directories if needed
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .create(true)
+            .append(true)
+            .open(&path)?;
+
+        let mut store = Self {
+            path,
+            file: file.try_clone()?,
+            index: BTreeMap::new(),
+            len: 0,
+            garbage: 0,
+        };
+
+        if file.metadata()?.len() == 0 {
+            file.write_all(MAGIC)?;
+            file.sync_all()?;
+        }
+
+        let valid_len = store.load_index(&mut file)?;
+        if valid_len < file.metadata()?.len() {
+            tracing::warn!("discarding torn record at the end of {:?}", store.path);
+            file.set_len(valid_len)?;
+        }
+
+        store.len = valid_len;
+
+        Ok(store)
+    }
+
+    /// Returns the value of a key
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let Some(&(offset, len)) = self.index.get(key) else {
+            return Ok(None);
+        };
+
+        let mut value = vec![0; len as usize];
+        read_exact_at(&self.file, &mut value, offset)?;
+
+        Ok(Some(value))
+    }
+
+    /// Sets the value of a key
+    pub fn put(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        let value_len = u32::try_from(value.len())
+            .ok()
+            .filter(|len| *len != TOMBSTONE)
+            .ok_or(eyre::eyre!("value too large"))?;
+
+        let offset = self.append(key, value_len, value)?;
+        if let Some((_, len)) = self.index.insert(key.to_vec(), (offset, value_len)) {
+            self.garbage += record_len(key, len);
+        }
+
+        self.maybe_compact()
+    }
+
+    /// Deletes a key
+    pub fn delete(&mut self, key: &[u8]) -> Result<()> {
+        let Some((_, len)) = self.index.remove(key) else {
+            return Ok(());
+        };
+
+        self.append(key, TOMBSTONE, &[])?;
+        self.garbage += record_len(key, len) + record_len(key, 0);
+
+        self.maybe_compact()
+    }
+
+    /// Returns the keys in the given range, in order
+    pub fn keys<R: RangeBounds<Vec<u8>>>(&self, range: R) -> Vec<Vec<u8>> {
+        self.index
+            .range(range)
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    /// Returns the number of keys in the store
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Returns true if the store holds no keys
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Returns the size of the log in bytes
+    pub fn size(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns the bytes taken by overwritten and deleted records, reclaimed by compaction
+    pub fn garbage(&self) -> u64 {
+        self.garbage
+    }
+
+    /// Rewrites the log with only the latest value of each key. The new log is synced before
+    /// it replaces the old one, and the directory after, so a crash leaves either log.
+    pub fn compact(&mut self) -> Result<()> {
+        let tmp_path = self.path.with_extension("compact");
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        let mut index = BTreeMap::new();
+        let mut len = MAGIC.len() as u64;
+        writer.write_all(MAGIC)?;
+
+        for (key, &(offset, value_len)) in &self.index {
+            let mut value = vec![0; value_len as usize];
+            read_exact_at(&self.file, &mut value, offset)?;
+
+            write_record(&mut writer, key, value_len, &value)?;
+            index.insert(
+                key.clone(),
+                (len + HEADER_LEN + key.len() as u64, value_len),
+            );
+            len += record_len(key, value_len);
+        }
+
+        writer.into_inner()?.sync_all()?;
+        fs::rename(&tmp_path, &self.path)?;
+        sync_parent(&self.path)?;
+
+        self.file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .open(&self.path)?;
+        self.index = index;
+        self.len = len;
+        self.garbage = 0;
+
+        Ok(())
+    }
+
+    fn maybe_compact(&mut self) -> Result<()> {
+        if self.garbage > MIN_COMPACTION_BYTES && self.garbage > self.len - self.garbage {
+            self.compact()?;
+        }
+
+        Ok(())
+    }
+
+    /// Appends a record, returning the offset of its value
+    fn append(&mut self, key: &[u8], value_len: u32, value: &[u8]) -> Result<u64> {
+        let mut record = Vec::with_capacity(HEADER_LEN as usize + key.len() + value.len());
+        write_record(&mut record, key, value_len, value)?;
+        self.file.write_all(&record)?;
+
+        let offset = self.len + HEADER_LEN + key.len() as u64;
+        self.len += record.len() as u64;
+
+        Ok(offset)
+    }
+
+    /// Replays the log into the index, returning the length of the log up to the last
+    /// complete record with a valid checksum
+    fn load_index(&mut self, file: &mut File) -> Result<u64> {
+        file.seek(SeekFrom::Start(0))?;
+
+        let file_len = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+
+        let mut magic = [0; MAGIC.len()];
+        if reader.read_exact(&mut magic).is_err() || &magic != MAGIC {
+            eyre::bail!(
+                "{:?} is not a checksummed store, open its data directory to migrate it",
+                self.path
+            );
+        }
+
+        let mut offset = MAGIC.len() as u64;
+        loop {
+            let mut header = [0; HEADER_LEN as usize];
+            if offset + HEADER_LEN > file_len || reader.read_exact(&mut header).is_err() {
+                return Ok(offset);
+            }
+
+            let checksum = u32::from_le_bytes(header[..4].try_into()?);
+            let key_len = u32::from_le_bytes(header[4..8].try_into()?);
+            let value_len = u32::from_le_bytes(header[8..].try_into()?);
+            let stored_len = if value_len == TOMBSTONE { 0 } else { value_len };
+
+            let end = offset + HEADER_LEN + key_len as u64 + stored_len as u64;
+            if end > file_len {
+                return Ok(offset);
+            }
+
+            let mut key = vec![0; key_len as usize];
+            let mut value = vec![0; stored_len as usize];
+            reader.read_exact(&mut key)?;
+            reader.read_exact(&mut value)?;
+            if record_checksum(&key, value_len, &value) != checksum {
+                return Ok(offset);
+            }
+
+            let previous = if value_len == TOMBSTONE {
+                self.garbage += HEADER_LEN + key_len as u64;
+                self.index.remove(&key)
+            } else {
+                let value_offset = offset + HEADER_LEN + key_len as u64;
+                self.index.insert(key.clone(), (value_offset, value_len))
+            };
+
+            if let Some((_, len)) = previous {
+                self.garbage += record_len(&key, len);
+            }
+
+            offset = end;
+        }
+    }
+}
+
+/// Rewrites a log of schema version 1 with checksummed records, keeping the latest value
+/// of each key. Logs already rewritten are left as they are, so an interrupted migration
+/// can run again.
+pub(crate) fn migrate_legacy_log(path: &Path) -> Result<()> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+
+    if data.starts_with(MAGIC) {
+        return Ok(());
+    }
+
+    let mut entries = BTreeMap::new();
+    let mut offset = 0;
+    while offset + LEGACY_HEADER_LEN as usize <= data.len() {
+        let header = &data[offset..offset + LEGACY_HEADER_LEN as usize];
+        let key_len = u32::from_le_bytes(header[..4].try_into()?) as usize;
+        let value_len = u32::from_le_bytes(header[4..].try_into()?);
+        let stored_len = if value_len == TOMBSTONE {
+            0
+        } else {
+            value_len as usize
+        };
+
+        let key_start = offset + LEGACY_HEADER_LEN as usize;
+        let end = key_start + key_len + stored_len;
+        if end > data.len() {
+            break;
+        }
+
+        let key = data[key_start..key_start + key_len].to_vec();
+        if value_len == TOMBSTONE {
+            entries.remove(&key);
+        } else {
+            entries.insert(key, &data[key_start + key_len..end]);
+        }
+
+        offset = end;
+    }
+
+    let tmp_path = path.with_extension("migrate");
+    let mut writer = BufWriter::new(File::create(&tmp_path)?);
+    writer.write_all(MAGIC)?;
+    for (key, value) in entries {
+        write_record(&mut writer, &key, value.len() as u32, value)?;
+    }
+
+    writer.into_inner()?.sync_all()?;
+    fs::rename(&tmp_path, path)?;
+    sync_parent(path)
+}
+
+/// Returns the directory holding the data of the configured chain, if a data directory
+/// is configured
+pub fn chain_dir(config: &Config) -> Option<PathBuf> {
+    let data_dir = config.data_dir.as_ref()?;
+    let chain_id = config.chain.l2_chain_id.to_string();
+
+    Some(Path::new(data_dir).join(chain_id))
+}
+
+/// Returns the path of the derivation state store of the configured chain, if a data
+/// directory is configured
+pub fn state_path(config: &Config) -> Option<PathBuf> {
+    chain_dir(config).map(|dir| dir.join("state.db"))
+}
+
+/// Returns the path of the output root cache of the configured chain, if a data directory
+/// is configured
+pub fn outputs_path(config: &Config) -> Option<PathBuf> {
+    chain_dir(config).map(|dir| dir.join("outputs.db"))
+}
+
+/// Returns the path of the unsafe payload store of the configured chain, if a data
+/// directory is configured
+pub fn unsafe_payloads_path(config: &Config) -> Option<PathBuf> {
+    chain_dir(config).map(|dir| dir.join("unsafe.db"))
+}
+
+/// Returns the path of the p2p peerstore of the configured chain, if a data directory is
+/// configured
+pub fn peerstore_path(config: &Config) -> Option<PathBuf> {
+    chain_dir(config).map(|dir| dir.join("peerstore.json"))
+}
+
+fn record_len(key: &[u8], value_len: u32) -> u64 {
+    HEADER_LEN + key.len() as u64 + value_len as u64
+}
+
+fn record_checksum(key: &[u8], value_len: u32, value: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&(key.len() as u32).to_le_bytes());
+    hasher.update(&value_len.to_le_bytes());
+    hasher.update(key);
+    hasher.update(value);
+    hasher.finalize()
+}
+
+fn write_record<W: Write>(writer: &mut W, key: &[u8], value_len: u32, value: &[u8]) -> Result<()> {
+    writer.write_all(&record_checksum(key, value_len, value).to_le_bytes())?;
+    writer.write_all(&(key.len() as u32).to_le_bytes())?;
+    writer.write_all(&value_len.to_le_bytes())?;
+    writer.write_all(key)?;
+    writer.write_all(value)?;
+
+    Ok(())
+}
+
+/// Reads the exact length of the buffer from the given offset of the file
+#[cfg(unix)]
+fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+    std::os::unix::fs::FileExt::read_exact_at(file, buf, offset)
+}
+
+/// Reads the exact length of the buffer from the given offset of the file
+#[cfg(windows)]
+fn read_exact_at(file: &File, mut buf: &mut [u8], mut offset: u64) -> std::io::Result<()> {
+    while !buf.is_empty() {
+        match std::os::windows::fs::FileExt::seek_read(file, buf, offset)? {
+            0 => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+            n => {
+                buf = &mut buf[n..];
+                offset += n as u64;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Syncs the directory entry of a renamed file, as the rename is only durable once its
+/// directory is synced
+#[cfg(unix)]
+pub(crate) fn sync_parent(path: &Path) -> Result<()> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        File::open(dir)?.sync_all()?;
+    }
+
+    Ok(())
+}
+
+/// Directories cannot be opened for syncing on other platforms, where the file system
+/// journals renames
+#[cfg(not(unix))]
+pub(crate) fn sync_parent(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("magi-kv-{}-{}", name, std::process::id()));
+        _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_reopen() -> Result<()> {
+        let path = temp_path("reopen");
+
+        let mut store = KvStore::open(&path)?;
+        store.put(b"a", b"1")?;
+        store.put(b"b", b"2")?;
+        store.put(b"a", b"3")?;
+        store.delete(b"b")?;
+        store.put(b"c", b"")?;
+        drop(store);
+
+        // simulate a crash in the middle of a write
+        let mut file = OpenOptions::new().append(true).open(&path)?;
+        write_record(&mut file, b"d", 4, b"4")?;
+        drop(file);
+
+        // and of a record failing its checksum
+        KvStore::open(&path)?;
+        let mut record = Vec::new();
+        write_record(&mut record, b"f", 1, b"6")?;
+        record[HEADER_LEN as usize + 1] = b'7';
+        OpenOptions::new()
+            .append(true)
+            .open(&path)?
+            .write_all(&record)?;
+
+        let mut store = KvStore::open(&path)?;
+        assert_eq!(store.get(b"a")?, Some(b"3".to_vec()));
+        assert_eq!(store.get(b"b")?, None);
+        assert_eq!(store.get(b"c")?, Some(Vec::new()));
+        assert_eq!(store.get(b"d")?, None);
+        assert_eq!(store.get(b"f")?, None);
+        assert_eq!(store.keys(..), vec![b"a".to_vec(), b"c".to_vec()]);
+
+        store.compact()?;
+        store.put(b"e", b"5")?;
+        assert_eq!(store.get(b"a")?, Some(b"3".to_vec()));
+        assert_eq!(store.get(b"e")?, Some(b"5".to_vec()));
+
+        let store = KvStore::open(&path)?;
+        assert_eq!(store.len(), 3);
+        assert_eq!(store.get(b"e")?, Some(b"5".to_vec()));
+
+        fs::remove_file(&path)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,344 @@
+use std::{fs, path::Path};
+
+use ethers::{
+    providers::{Http, Middleware, Provider},
+    types::{BlockId, BlockNumber},
+};
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    common::{http, BlockInfo, Epoch, HeadInfo},
+    config::Config,
+    derive::state::{l2_ref_key, l2_refs},
+    driver::get_l1_start_block,
+};
+
+use super::{state_path, write_atomic, DataDir, KvStore};
+
+/// Version of the snapshot file format. Version 1 and 2 snapshots carry no pipeline
+/// checkpoint, and are still read to resume from their finalized head.
+pub const SNAPSHOT_VERSION: u32 = 3;
+
+/// Key of the imported snapshot in the state store
+const SNAPSHOT_KEY: &[u8] = b"snapshot";
+
+/// A portable snapshot of the derivation state of a synced node.
+///
+/// A snapshot holds the safe and finalized heads with their epochs, the checkpoint the
+/// derivation pipeline resumes from, and the L2 block refs it needs to resume at the safe
+/// head. Importing it into the data directory of a new node lets it resume derivation from
+/// the safe head without re-deriving the chain or trusting a checkpoint sync url.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Version of the snapshot format
+    pub version: u32,
+    /// The L2 chain id the snapshot belongs to
+    pub chain_id: u64,
+    /// The safe head
+    pub safe_head: BlockInfo,
+    /// The epoch of the safe head
+    pub safe_epoch: Epoch,
+    /// Sequence number of the safe head in its epoch
+    pub safe_seq: u64,
+    /// The finalized head
+    pub finalized_head: BlockInfo,
+    /// The epoch of the finalized head
+    pub finalized_epoch: Epoch,
+    /// Sequence number of the finalized head in its epoch
+    pub finalized_seq: u64,
+    /// Where the derivation pipeline resumes reading L1 to derive the blocks past the safe head
+    pub checkpoint: PipelineCheckpoint,
+    /// The L2 block refs leading up to the safe head
+    pub l2_refs: Vec<(BlockInfo, Epoch)>,
+}
+
+/// The L1 position of the derivation pipeline at the safe head of a [Snapshot]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PipelineCheckpoint {
+    /// The L1 origin the channel bank is reset to, the epoch of the safe head
+    pub channel_bank_origin: Epoch,
+    /// The first L1 block whose batcher transactions are read again, a channel timeout
+    /// before the origin, so channels still open at the safe head are completed
+    pub l1_start_block: u64,
+}
+
+impl PipelineCheckpoint {
+    /// Returns the checkpoint of a pipeline reset to the given L1 origin
+    pub fn new(channel_bank_origin: Epoch, config: &Config) -> Self {
+        Self {
+            channel_bank_origin,
+            l1_start_block: get_l1_start_block(channel_bank_origin, &config.chain),
+        }
+    }
+}
+
+/// A version 1 or 2 snapshot, holding the finalized head only. The safe head of version 1
+/// snapshots is ignored, as their block refs only lead up to the finalized head.
+#[derive(Deserialize)]
+struct LegacySnapshot {
+    chain_id: u64,
+    finalized_head: BlockInfo,
+    finalized_epoch: Epoch,
+    finalized_seq: u64,
+    l2_refs: Vec<(BlockInfo, Epoch)>,
+}
+
+impl Snapshot {
+    /// Exports a snapshot of the node whose execution client serves the configured L2 RPC
+    pub async fn export(config: &Config) -> Result<Self> {
+        let provider = http::provider(&config.l2_rpc_url, http::L2_TIMEOUT)?;
+
+        let finalized = head_info(&provider, BlockNumber::Finalized, config).await?;
+        let safe = head_info(&provider, BlockNumber::Safe, config).await?;
+
+        let l2_refs = l2_refs(safe.l2_block_info.number, &provider, None, config)
+            .await
+            .into_values()
+            .collect();
+
+        Ok(Self {
+            version: SNAPSHOT_VERSION,
+            chain_id: config.chain.l2_chain_id,
+            safe_head: safe.l2_block_info,
+            safe_epoch: safe.l1_epoch,
+            safe_seq: safe.sequence_number,
+            finalized_head: finalized.l2_block_info,
+            finalized_epoch: finalized.l1_epoch,
+            finalized_seq: finalized.sequence_number,
+            checkpoint: PipelineCheckpoint::new(safe.l1_epoch, config),
+            l2_refs,
+        })
+    }
+
+    /// Imports the snapshot into the configured data directory. The node must be stopped.
+    pub fn import(&self, config: &Config) -> Result<()> {
+        self.validate(config)?;
+
+        let _data_dir = DataDir::open(config)?;
+        let path = state_path(config).ok_or(eyre::eyre!("a data directory is required"))?;
+        let mut store = KvStore::open(path)?;
+
+        for block_ref in &self.l2_refs {
+            store.put(
+                &l2_ref_key(block_ref.0.number),
+                &serde_json::to_vec(block_ref)?,
+            )?;
+        }
+
+        store.put(SNAPSHOT_KEY, &serde_json::to_vec(self)?)
+    }
+
+    /// Returns the snapshot imported into the configured data directory, if any
+    pub fn load(config: &Config) -> Result<Option<Self>> {
+        let Some(path) = state_path(config).filter(|path| path.exists()) else {
+            return Ok(None);
+        };
+
+        let store = KvStore::open(path)?;
+        let Some(snapshot) = store.get(SNAPSHOT_KEY)? else {
+            return Ok(None);
+        };
+
+        let snapshot = Self::decode(&snapshot, config)?;
+        snapshot.validate(config)?;
+
+        Ok(Some(snapshot))
+    }
+
+    /// Reads a snapshot file. Snapshots of older versions resume from their finalized head.
+    pub fn read(path: impl AsRef<Path>, config: &Config) -> Result<Self> {
+        Self::decode(&fs::read(path)?, config)
+    }
+
+    /// Decodes a snapshot of any supported version
+    fn decode(bytes: &[u8], config: &Config) -> Result<Self> {
+        let value: serde_json::Value = serde_json::from_slice(bytes)?;
+        let version = value["version"]
+            .as_u64()
+            .ok_or(eyre::eyre!("snapshot without version"))?;
+
+        match version {
+            1 | 2 => {
+                let legacy: LegacySnapshot = serde_json::from_value(value)?;
+                Ok(Self {
+                    version: SNAPSHOT_VERSION,
+                    chain_id: legacy.chain_id,
+                    safe_head: legacy.finalized_head,
+                    safe_epoch: legacy.finalized_epoch,
+                    safe_seq: legacy.finalized_seq,
+                    finalized_head: legacy.finalized_head,
+                    finalized_epoch: legacy.finalized_epoch,
+                    finalized_seq: legacy.finalized_seq,
+                    checkpoint: PipelineCheckpoint::new(legacy.finalized_epoch, config),
+                    l2_refs: legacy.l2_refs,
+                })
+            }
+            3 => Ok(serde_json::from_value(value)?),
+            version => eyre::bail!("unsupported snapshot version {}", version),
+        }
+    }
+
+    /// Writes the snapshot to a file
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<()> {
+        write_atomic(path.as_ref(), &serde_json::to_vec_pretty(self)?)
+    }
+
+    /// Returns the finalized head
+    pub fn finalized_head_info(&self) -> HeadInfo {
+        HeadInfo {
+            l2_block_info: self.finalized_head,
+            l1_epoch: self.finalized_epoch,
+            sequence_number: self.finalized_seq,
+        }
+    }
+
+    /// Returns the safe head to resume derivation from
+    pub fn safe_head_info(&self) -> HeadInfo {
+        HeadInfo {
+            l2_block_info: self.safe_head,
+            l1_epoch: self.safe_epoch,
+            sequence_number: self.safe_seq,
+        }
+    }
+
+    fn validate(&self, config: &Config) -> Result<()> {
+        if self.chain_id != config.chain.l2_chain_id {
+            eyre::bail!(
+                "snapshot is for chain {}, but chain {} is configured",
+                self.chain_id,
+                config.chain.l2_chain_id
+            );
+        }
+
+        let leads_to_head = self
+            .l2_refs
+            .last()
+            .is_some_and(|(block, _)| block.hash == self.safe_head.hash);
+
+        if !leads_to_head {
+            eyre::bail!("snapshot block refs do not lead up to the safe head");
+        }
+
+        if self.safe_head.number < self.finalized_head.number {
+            eyre::bail!("snapshot safe head is behind its finalized head");
+        }
+
+        if self.checkpoint.l1_start_block > self.checkpoint.channel_bank_origin.number {
+            eyre::bail!("snapshot pipeline checkpoint starts past its origin");
+        }
+
+        Ok(())
+    }
+}
+
+async fn head_info(
+    provider: &Provider<Http>,
+    block: BlockNumber,
+    config: &Config,
+) -> Result<HeadInfo> {
+    let block = provider
+        .get_block_with_txs(BlockId::Number(block))
+        .await?
+        .ok_or(eyre::eyre!("block {} not found", block))?;
+
+    HeadInfo::try_from_l2_block(config, block)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::ChainConfig;
+
+    use super::*;
+
+    #[test]
+    fn test_import() -> Result<()> {
+        let data_dir = std::env::temp_dir().join(format!("magi-snapshot-{}", std::process::id()));
+        let config = Config {
+            chain: ChainConfig::optimism(),
+            data_dir: Some(data_dir.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+
+        let finalized = BlockInfo {
+            number: 100,
+            hash: ethers::types::H256::repeat_byte(1),
+            ..Default::default()
+        };
+        let finalized_epoch = Epoch {
+            number: 10,
+            ..Default::default()
+        };
+        let safe = BlockInfo {
+            number: 101,
+            hash: ethers::types::H256::repeat_byte(2),
+            parent_hash: finalized.hash,
+            ..Default::default()
+        };
+        let safe_epoch = Epoch {
+            number: 400,
+            ..Default::default()
+        };
+
+        let snapshot = Snapshot {
+            version: SNAPSHOT_VERSION,
+            chain_id: config.chain.l2_chain_id,
+            safe_head: safe,
+            safe_epoch,
+            safe_seq: 1,
+            finalized_head: finalized,
+            finalized_epoch,
+            finalized_seq: 0,
+            checkpoint: PipelineCheckpoint::new(safe_epoch, &config),
+            l2_refs: vec![(finalized, finalized_epoch), (safe, safe_epoch)],
+        };
+        assert_eq!(snapshot.checkpoint.l1_start_block, 100);
+
+        let path = data_dir.join("snapshot.json");
+        fs::create_dir_all(&data_dir)?;
+        snapshot.write(&path)?;
+
+        let read = Snapshot::read(&path, &config)?;
+        read.import(&config)?;
+        assert_eq!(Snapshot::load(&config)?, Some(snapshot.clone()));
+        assert_eq!(read.safe_head_info().sequence_number, 1);
+
+        // version 1 and 2 snapshots resume from their finalized head, ignoring the safe
+        // head of version 1
+        let mut legacy = serde_json::to_value(&snapshot)?;
+        legacy["version"] = 2.into();
+        legacy["l2_refs"] = serde_json::to_value(vec![(finalized, finalized_epoch)])?;
+        for field in ["safe_seq", "checkpoint"] {
+            legacy.as_object_mut().unwrap().remove(field);
+        }
+        fs::write(&path, serde_json::to_vec(&legacy)?)?;
+        let read = Snapshot::read(&path, &config)?;
+        assert_eq!(read.safe_head, finalized);
+        assert_eq!(read.checkpoint.channel_bank_origin, finalized_epoch);
+        assert_eq!(read.checkpoint.l1_start_block, 0);
+        read.import(&config)?;
+
+        legacy["version"] = 1.into();
+        fs::write(&path, serde_json::to_vec(&legacy)?)?;
+        assert_eq!(Snapshot::read(&path, &config)?.safe_head, finalized);
+
+        legacy["version"] = 4.into();
+        fs::write(&path, serde_json::to_vec(&legacy)?)?;
+        assert!(Snapshot::read(&path, &config).is_err());
+
+        // block refs must lead up to the safe head
+        let mut invalid = snapshot.clone();
+        invalid.l2_refs.pop();
+        assert!(invalid.import(&config).is_err());
+
+        let other_chain = Config {
+            chain: ChainConfig::base(),
+            ..config
+        };
+        assert!(snapshot.import(&other_chain).is_err());
+
+        fs::remove_dir_all(&data_dir)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,136 @@
+use eyre::Result;
+
+use crate::{
+    config::Config,
+    engine::{ExecutionPayload, PayloadEnvelope},
+};
+
+use super::{unsafe_payloads_path, KvStore};
+
+/// Keeps the latest unsafe payloads applied by the driver in the data directory, so a
+/// restarted node restores its unsafe head without waiting for the next gossip block.
+///
+/// Payloads are keyed by block number. Applying a payload drops the payloads above it,
+/// which belong to a replaced chain, and the payloads older than the retention.
+#[derive(Debug)]
+pub struct UnsafePayloads {
+    /// The stored payloads, JSON encoded as [PayloadEnvelope]s to keep their parent beacon
+    /// block root
+    store: KvStore,
+    /// Number of payloads kept
+    retention: u64,
+}
+
+impl UnsafePayloads {
+    /// Opens the payload store if a data directory is configured and the retention is not 0
+    pub fn open(config: &Config) -> Result<Option<Self>> {
+        if config.unsafe_payload_retention == 0 {
+            return Ok(None);
+        }
+
+        let Some(path) = unsafe_payloads_path(config) else {
+            return Ok(None);
+        };
+
+        Ok(Some(Self {
+            store: KvStore::open(path)?,
+            retention: config.unsafe_payload_retention,
+        }))
+    }
+
+    /// Stores an applied payload, dropping the payloads it replaces or makes too old
+    pub fn insert(&mut self, payload: &ExecutionPayload) -> Result<()> {
+        let number = payload.block_number.as_u64();
+        self.store.put(
+            &number.to_be_bytes(),
+            &serde_json::to_vec(&PayloadEnvelope::new(payload.clone()))?,
+        )?;
+
+        let oldest = (number + 1).saturating_sub(self.retention);
+        let stale = self
+            .store
+            .keys(..oldest.to_be_bytes().to_vec())
+            .into_iter()
+            .chain(self.store.keys((number + 1).to_be_bytes().to_vec()..));
+
+        for key in stale.collect::<Vec<_>>() {
+            self.store.delete(&key)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the stored payloads above the given block, in order. Payloads which cannot be
+    /// decoded are skipped.
+    pub fn load_after(&self, number: u64) -> Result<Vec<ExecutionPayload>> {
+        let mut payloads = Vec::new();
+        for key in self.store.keys((number + 1).to_be_bytes().to_vec()..) {
+            let Some(value) = self.store.get(&key)? else {
+                continue;
+            };
+
+            match serde_json::from_slice::<PayloadEnvelope>(&value) {
+                Ok(envelope) => payloads.push(envelope.into_payload()),
+                Err(err) => tracing::warn!("could not decode stored unsafe payload: {}", err),
+            }
+        }
+
+        Ok(payloads)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::H256;
+
+    use super::*;
+
+    #[test]
+    fn test_unsafe_payloads() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("magi-unsafe-{}", std::process::id()));
+        let config = Config {
+            data_dir: Some(dir.to_string_lossy().to_string()),
+            unsafe_payload_retention: 3,
+            ..Default::default()
+        };
+
+        let payload = |number: u64, byte: u8| ExecutionPayload {
+            block_number: number.into(),
+            block_hash: H256::repeat_byte(byte),
+            parent_beacon_block_root: Some(H256::repeat_byte(byte)),
+            ..Default::default()
+        };
+
+        let mut payloads = UnsafePayloads::open(&config)?.unwrap();
+        for number in 1..=5 {
+            payloads.insert(&payload(number, 1))?;
+        }
+        drop(payloads);
+
+        // the payloads are kept across restarts, up to the retention
+        let mut payloads = UnsafePayloads::open(&config)?.unwrap();
+        let numbers = |payloads: &UnsafePayloads| -> Result<Vec<u64>> {
+            Ok(payloads
+                .load_after(0)?
+                .iter()
+                .map(|payload| payload.block_number.as_u64())
+                .collect())
+        };
+        assert_eq!(numbers(&payloads)?, vec![3, 4, 5]);
+        assert_eq!(payloads.load_after(4)?, vec![payload(5, 1)]);
+
+        // a payload replacing block 4 drops block 5
+        payloads.insert(&payload(4, 2))?;
+        assert_eq!(numbers(&payloads)?, vec![3, 4]);
+        assert_eq!(payloads.load_after(3)?, vec![payload(4, 2)]);
+
+        let disabled = Config {
+            unsafe_payload_retention: 0,
+            ..config
+        };
+        assert!(UnsafePayloads::open(&disabled)?.is_none());
+
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
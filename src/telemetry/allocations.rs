@@ -0,0 +1,80 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+
+/// Whether allocations are counted
+static ENABLED: AtomicBool = AtomicBool::new(false);
+/// Number of allocations since counting was enabled
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+/// Bytes allocated since counting was enabled
+static ALLOCATED_BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// A global allocator counting the allocations of the process while enabled with
+/// [enable]. Install it in a binary with `#[global_allocator]`; until counting is
+/// enabled it only adds a relaxed load to each allocation. The magi binary installs it
+/// with the `count-allocations` feature, so default builds do not pay for the load.
+#[derive(Debug)]
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        record(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        record(layout.size());
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        record(new_size.saturating_sub(layout.size()));
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+/// Counts an allocation of the given size, if enabled
+fn record(size: usize) {
+    if ENABLED.load(Ordering::Relaxed) {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(size as u64, Ordering::Relaxed);
+    }
+}
+
+/// Starts counting allocations. Only has an effect if the [CountingAllocator] is the
+/// global allocator.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// The allocations counted by the [CountingAllocator]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AllocationStats {
+    /// Number of allocations
+    pub allocations: u64,
+    /// Allocated bytes, counting the growth of reallocations
+    pub bytes: u64,
+}
+
+impl AllocationStats {
+    /// Returns the allocations counted so far
+    pub fn now() -> Self {
+        Self {
+            allocations: ALLOCATIONS.load(Ordering::Relaxed),
+            bytes: ALLOCATED_BYTES.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Returns the allocations counted since an earlier snapshot
+    pub fn since(&self, earlier: &Self) -> Self {
+        Self {
+            allocations: self.allocations.saturating_sub(earlier.allocations),
+            bytes: self.bytes.saturating_sub(earlier.bytes),
+        }
+    }
+}
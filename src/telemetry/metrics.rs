@@ -1,19 +1,125 @@
 use eyre::{Result, WrapErr};
 use lazy_static::lazy_static;
 use prometheus_exporter::{
-    prometheus::{register_int_gauge, IntGauge},
+    prometheus::{
+        register_histogram_vec, register_int_counter, register_int_counter_vec, register_int_gauge,
+        register_int_gauge_vec, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec,
+    },
     start,
 };
 
 lazy_static! {
-     /// Tracks the block number of the most recent finalized head.
-    pub static ref FINALIZED_HEAD: IntGauge =
-        register_int_gauge!("finalized_head", "finalized head number").unwrap();
-           /// Tracks the block number considered to be the safe head.
-    pub static ref SAFE_HEAD: IntGauge =
-        register_int_gauge!("safe_head", "safe head number").unwrap();
-           /// Monitors if the node is fully synced
-    pub static ref SYNCED: IntGauge = register_int_gauge!("synced", "synced flag").unwrap();
+     /// Tracks the block number of the most recent finalized head, labeled by L2 chain id.
+    pub static ref FINALIZED_HEAD: IntGaugeVec =
+        register_int_gauge_vec!("finalized_head", "finalized head number", &["chain"]).unwrap();
+           /// Tracks the block number considered to be the safe head, labeled by L2 chain id.
+    pub static ref SAFE_HEAD: IntGaugeVec =
+        register_int_gauge_vec!("safe_head", "safe head number", &["chain"]).unwrap();
+           /// Monitors if the node is fully synced, labeled by L2 chain id.
+    pub static ref SYNCED: IntGaugeVec =
+        register_int_gauge_vec!("synced", "synced flag", &["chain"]).unwrap();
+    /// Set if the L1 ProtocolVersions contract requires a newer protocol version
+    pub static ref PROTOCOL_VERSION_UPGRADE_REQUIRED: IntGauge = register_int_gauge!(
+        "protocol_version_upgrade_required",
+        "required protocol version is unsupported"
+    )
+    .unwrap();
+    /// Set if the L1 ProtocolVersions contract recommends a newer protocol version
+    pub static ref PROTOCOL_VERSION_UPGRADE_RECOMMENDED: IntGauge = register_int_gauge!(
+        "protocol_version_upgrade_recommended",
+        "recommended protocol version is unsupported"
+    )
+    .unwrap();
+    /// Index of the engine endpoint requests are sent to, 0 being the primary
+    pub static ref ACTIVE_ENGINE: IntGauge =
+        register_int_gauge!("active_engine", "active engine endpoint index").unwrap();
+    /// Engine API request latency in seconds, labeled by transport and method
+    pub static ref ENGINE_REQUEST_LATENCY: HistogramVec = register_histogram_vec!(
+        "engine_request_latency_seconds",
+        "engine api request latency",
+        &["transport", "method"]
+    )
+    .unwrap();
+    /// Counts the L1 reorgs detected by the chain watcher
+    pub static ref L1_REORGS: IntCounter =
+        register_int_counter!("l1_reorgs", "number of l1 reorgs").unwrap();
+    /// Counts the L1 requests rejected for exceeding the provider's rate limit
+    pub static ref L1_RATE_LIMITED: IntCounter =
+        register_int_counter!("l1_rate_limited", "number of rate limited l1 requests").unwrap();
+    /// Number of L1 epochs held in memory by the derivation state, labeled by L2 chain id
+    pub static ref STATE_L1_EPOCHS: IntGaugeVec =
+        register_int_gauge_vec!("state_l1_epochs", "l1 epochs in memory", &["chain"]).unwrap();
+    /// Number of L2 block refs held in memory by the derivation state, labeled by L2 chain id
+    pub static ref STATE_L2_REFS: IntGaugeVec =
+        register_int_gauge_vec!("state_l2_refs", "l2 block refs in memory", &["chain"]).unwrap();
+    /// Estimated memory held by the derivation state in bytes, labeled by L2 chain id
+    pub static ref STATE_MEMORY_BYTES: IntGaugeVec = register_int_gauge_vec!(
+        "state_memory_bytes",
+        "estimated derivation state memory usage",
+        &["chain"]
+    )
+    .unwrap();
+    /// Counts L1 cache hits, labeled by cache
+    pub static ref L1_CACHE_HITS: IntCounterVec =
+        register_int_counter_vec!("l1_cache_hits", "l1 cache hits", &["cache"]).unwrap();
+    /// Counts L1 cache misses, labeled by cache
+    pub static ref L1_CACHE_MISSES: IntCounterVec =
+        register_int_counter_vec!("l1_cache_misses", "l1 cache misses", &["cache"]).unwrap();
+    /// Number of fetched L1 blocks waiting for derivation, labeled by L2 chain id
+    pub static ref L1_PENDING_BLOCKS: IntGaugeVec = register_int_gauge_vec!(
+        "l1_pending_blocks",
+        "fetched l1 blocks waiting for derivation",
+        &["chain"]
+    )
+    .unwrap();
+    /// Counts the restarts of the L1 chain watcher task, labeled by L2 chain id and whether
+    /// it died or stalled
+    pub static ref L1_WATCHER_RESTARTS: IntCounterVec = register_int_counter_vec!(
+        "l1_watcher_restarts",
+        "number of l1 chain watcher restarts",
+        &["chain", "reason"]
+    )
+    .unwrap();
+    /// Counts the stalls reported by the watchdog, labeled by L2 chain id and the stalled
+    /// component
+    pub static ref WATCHDOG_STALLS: IntCounterVec = register_int_counter_vec!(
+        "watchdog_stalls",
+        "number of stalled driver components reported by the watchdog",
+        &["chain", "component"]
+    )
+    .unwrap();
+    /// Tracks the depth of the most recent L1 reorg
+    pub static ref L1_REORG_DEPTH: IntGauge =
+        register_int_gauge!("l1_reorg_depth", "depth of the latest l1 reorg").unwrap();
+    /// Number of connected p2p peers, labeled by L2 chain id
+    pub static ref P2P_PEERS: IntGaugeVec =
+        register_int_gauge_vec!("p2p_peers", "connected p2p peers", &["chain"]).unwrap();
+    /// Counts p2p peer bans, labeled by L2 chain id
+    pub static ref P2P_PEER_BANS: IntCounterVec =
+        register_int_counter_vec!("p2p_peer_bans", "number of banned p2p peers", &["chain"])
+            .unwrap();
+    /// Counts the deposit-only blocks derived after a sequencing window expired, labeled by L2 chain id
+    /// and whether batches were dropped as invalid meanwhile.
+    pub static ref DEPOSIT_ONLY_BLOCKS: IntCounterVec = register_int_counter_vec!(
+        "deposit_only_blocks",
+        "number of deposit-only blocks derived after the sequencing window expired",
+        &["chain", "reason"]
+    )
+    .unwrap();
+    /// Counts the errors of the derivation loop, labeled by L2 chain id and error code
+    pub static ref DERIVATION_ERRORS: IntCounterVec = register_int_counter_vec!(
+        "derivation_errors",
+        "number of derivation loop errors",
+        &["chain", "code"]
+    )
+    .unwrap();
+    /// Counts the batches dropped by derivation, labeled by L2 chain id and the violated rule.
+    pub static ref DROPPED_BATCHES: IntCounterVec = register_int_counter_vec!(
+        "dropped_batches",
+        "number of invalid batches dropped by derivation",
+        &["chain", "reason"]
+    )
+    .unwrap();
 }
 
 /// Starts the metrics server on port 9200
@@ -20,6 +20,9 @@ pub mod logging;
 /// Prometheus metrics
 pub mod metrics;
 
+/// Allocation counting for profiling
+pub mod allocations;
+
 // Re-export inner modules
 pub use logging::*;
 